@@ -0,0 +1,148 @@
+//! Build time of place orders txs on the gateway's hot path
+//!
+//! Covers a place orders request from its template lookup, or the tx builder on a template miss,
+//! to the signed tx encoded for sending, the RPC round trips are excluded. Run with `cargo bench --bench place_orders`.
+
+use std::{borrow::Cow, hint::black_box};
+
+use anchor_lang::InstructionData;
+use criterion::{criterion_group, criterion_main, Criterion};
+use drift_rs::{
+    constants::{ProgramData, PROGRAM_ID},
+    drift_idl,
+    types::{
+        accounts::{PerpMarket, SpotMarket, User},
+        MarketType, OrderParams, VersionedMessage,
+    },
+    Pubkey, TransactionBuilder,
+};
+use solana_sdk::{
+    compute_budget::ComputeBudgetInstruction,
+    hash::Hash,
+    instruction::{AccountMeta, Instruction},
+    message::Message,
+    signature::{Keypair, Signer},
+    transaction::VersionedTransaction,
+};
+
+#[allow(dead_code)]
+#[path = "../src/rpc.rs"]
+mod rpc;
+#[allow(dead_code)]
+#[path = "../src/templates.rs"]
+mod templates;
+
+use rpc::EncodedTx;
+use templates::{TemplateKey, TxTemplates};
+
+/// Orders of a typical quoting request
+fn orders() -> Vec<OrderParams> {
+    (0..4)
+        .map(|i| OrderParams {
+            market_index: i / 2,
+            market_type: MarketType::Perp,
+            base_asset_amount: 1_000_000_000,
+            price: 100_000_000 + i as u64,
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// A place orders message as built for `orders`, state/user/authority and market accounts
+fn place_orders_message(payer: &Pubkey, orders: &[OrderParams]) -> VersionedMessage {
+    let accounts = (0..8)
+        .map(|_| AccountMeta::new_readonly(Pubkey::new_unique(), false))
+        .collect();
+    VersionedMessage::Legacy(Message::new(
+        &[
+            ComputeBudgetInstruction::set_compute_unit_price(1_000),
+            Instruction {
+                program_id: PROGRAM_ID,
+                accounts,
+                data: drift_idl::instructions::PlaceOrders {
+                    params: orders.to_vec(),
+                }
+                .data(),
+            },
+        ],
+        Some(payer),
+    ))
+}
+
+/// Program data of the markets `orders` trade
+fn program_data() -> ProgramData {
+    let spot = vec![SpotMarket {
+        pubkey: Pubkey::new_unique(),
+        oracle: Pubkey::new_unique(),
+        ..Default::default()
+    }];
+    let perp = (0..2)
+        .map(|market_index| PerpMarket {
+            pubkey: Pubkey::new_unique(),
+            market_index,
+            amm: drift_rs::types::AMM {
+                oracle: Pubkey::new_unique(),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .collect();
+    ProgramData::new(spot, perp, &[])
+}
+
+fn place_orders(c: &mut Criterion) {
+    let keypair = Keypair::new();
+    let sub_account = Pubkey::new_unique();
+    let user = User::default();
+    let orders = orders();
+    let templates = TxTemplates::default();
+    templates.insert(
+        TemplateKey::new(sub_account, &user, &orders, None),
+        place_orders_message(&keypair.pubkey(), &orders),
+    );
+    let build = || {
+        let key = TemplateKey::new(sub_account, &user, &orders, None);
+        templates
+            .place_orders(&key, &orders, 5_000, None)
+            .expect("cached template")
+    };
+
+    c.bench_function("place_orders/build", |b| b.iter(|| black_box(build())));
+    c.bench_function("place_orders/end_to_end", |b| {
+        b.iter(|| {
+            let mut message = build();
+            message.set_recent_blockhash(Hash::new_unique());
+            let tx = VersionedTransaction::try_new(message, &[&keypair]).expect("signed");
+            black_box(EncodedTx::new(&tx))
+        })
+    });
+
+    let mut message = build();
+    message.set_recent_blockhash(Hash::new_unique());
+    let tx = VersionedTransaction::try_new(message, &[&keypair]).expect("signed");
+    // template miss, the default path. The account is copied per request as it's copied out of
+    // the account cache by the gateway
+    let program_data = program_data();
+    c.bench_function("place_orders/transaction_builder", |b| {
+        b.iter(|| {
+            black_box(
+                TransactionBuilder::new(
+                    &program_data,
+                    sub_account,
+                    Cow::Owned(user.clone()),
+                    false,
+                )
+                .with_priority_fee(5_000, None)
+                .place_orders(orders.clone())
+                .build(),
+            )
+        })
+    });
+
+    c.bench_function("place_orders/encode", |b| {
+        b.iter(|| black_box(EncodedTx::new(black_box(&tx))))
+    });
+}
+
+criterion_group!(benches, place_orders);
+criterion_main!(benches);
@@ -0,0 +1,13 @@
+fn main() {
+    // the gRPC API is generated from proto/gateway.proto, its messages convert to and from the
+    // JSON API types through serde
+    #[cfg(feature = "grpc")]
+    tonic_build::configure()
+        .build_client(false)
+        .type_attribute(
+            ".",
+            "#[derive(serde::Serialize, serde::Deserialize)] #[serde(rename_all = \"camelCase\", default)]",
+        )
+        .compile_protos(&["proto/gateway.proto"], &["proto"])
+        .expect("gateway.proto compiled");
+}
@@ -0,0 +1,44 @@
+//! Typed client for the drift gateway
+//!
+//! [`GatewayClient`] wraps the HTTP API, [`EventStream`] the Ws event API and resubscribes after
+//! disconnects.
+//!
+//! ```no_run
+//! use drift_gateway_client::{GatewayClient, Market, PlaceOrder};
+//! use rust_decimal::Decimal;
+//!
+//! # async fn run() -> drift_gateway_client::Result<()> {
+//! let client = GatewayClient::new("http://localhost:8080").with_sub_account(0);
+//! let order = PlaceOrder::limit(Market::perp(0), Decimal::new(1, 1), Decimal::from(100));
+//! let tx = client.place_orders(vec![order]).await?;
+//! println!("placed: {}", tx.tx);
+//! # Ok(())
+//! # }
+//! ```
+
+mod rest;
+mod ws;
+
+pub use drift_gateway_types::*;
+pub use rest::GatewayClient;
+pub use ws::{EventStream, StreamUpdate};
+
+/// Errors returned by the gateway client
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// the gateway rejected the request e.g. `400` bad request, `503` standby
+    #[error("gateway error {code}: {reason}")]
+    Api {
+        code: u32,
+        reason: String,
+        request_id: Option<String>,
+    },
+    #[error("http: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("ws: {0}")]
+    Ws(#[from] tokio_tungstenite::tungstenite::Error),
+    #[error("json: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,214 @@
+//! HTTP API client
+
+use reqwest::{Method, RequestBuilder, Response};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::json;
+
+use crate::{
+    CancelOrders, Collateral, Error, ErrorResponse, Leverage, MarginInfo, Market, ModifyOrder,
+    OrdersResponse, PlaceOrder, PollEventsResponse, PositionsResponse, Result, Snapshot,
+    TxEventsResponse, TxResponse, TxStatus,
+};
+
+/// Client of a gateway's HTTP API
+#[derive(Clone, Debug)]
+pub struct GatewayClient {
+    http: reqwest::Client,
+    /// e.g. `http://localhost:8080`
+    url: String,
+    /// sub-account of requests, the gateway's default if unset
+    sub_account_id: Option<u16>,
+}
+
+impl GatewayClient {
+    /// A client of the gateway at `url` e.g. `http://localhost:8080`
+    pub fn new(url: &str) -> Self {
+        Self::with_http_client(url, reqwest::Client::new())
+    }
+
+    /// A client sending requests with `http` e.g. to configure timeouts
+    pub fn with_http_client(url: &str, http: reqwest::Client) -> Self {
+        Self {
+            http,
+            url: url.trim_end_matches('/').to_string(),
+            sub_account_id: None,
+        }
+    }
+
+    /// Act for sub-account `id` instead of the gateway's default sub-account
+    pub fn with_sub_account(mut self, id: u16) -> Self {
+        self.sub_account_id = Some(id);
+        self
+    }
+
+    pub async fn get_orders(&self, market: Option<Market>) -> Result<OrdersResponse> {
+        self.send(self.request(Method::GET, "/v2/orders"), market)
+            .await
+    }
+
+    pub async fn get_positions(&self, market: Option<Market>) -> Result<PositionsResponse> {
+        self.send(self.request(Method::GET, "/v2/positions"), market)
+            .await
+    }
+
+    /// Return orders, positions, and margin as of one slot e.g. to resync after a Ws reconnect
+    pub async fn get_snapshot(&self) -> Result<Snapshot> {
+        self.send(self.request(Method::GET, "/v2/snapshot"), None::<()>)
+            .await
+    }
+
+    pub async fn get_margin_info(&self) -> Result<MarginInfo> {
+        self.send(self.request(Method::GET, "/v2/user/marginInfo"), None::<()>)
+            .await
+    }
+
+    pub async fn get_collateral(&self) -> Result<Collateral> {
+        self.send(self.request(Method::GET, "/v2/collateral"), None::<()>)
+            .await
+    }
+
+    pub async fn get_leverage(&self) -> Result<Leverage> {
+        self.send(self.request(Method::GET, "/v2/leverage"), None::<()>)
+            .await
+    }
+
+    pub async fn place_orders(&self, orders: Vec<PlaceOrder>) -> Result<TxResponse> {
+        self.send(
+            self.request(Method::POST, "/v2/orders"),
+            Some(json!({ "orders": orders })),
+        )
+        .await
+    }
+
+    pub async fn modify_orders(&self, orders: Vec<ModifyOrder>) -> Result<TxResponse> {
+        self.send(
+            self.request(Method::PATCH, "/v2/orders"),
+            Some(json!({ "orders": orders })),
+        )
+        .await
+    }
+
+    pub async fn cancel_orders(&self, cancel: CancelOrders) -> Result<TxResponse> {
+        self.send(self.request(Method::DELETE, "/v2/orders"), Some(cancel))
+            .await
+    }
+
+    /// Cancel, then place orders in one tx
+    pub async fn cancel_and_place_orders(
+        &self,
+        cancel: CancelOrders,
+        place: Vec<PlaceOrder>,
+    ) -> Result<TxResponse> {
+        self.send(
+            self.request(Method::POST, "/v2/orders/cancelAndPlace"),
+            Some(json!({
+                "cancel": cancel,
+                "modify": { "orders": [] },
+                "place": { "orders": place },
+            })),
+        )
+        .await
+    }
+
+    /// Return the events of the sub-account in tx `signature`
+    pub async fn get_tx_events(&self, signature: &str) -> Result<TxEventsResponse> {
+        self.send(
+            self.request(Method::GET, &format!("/v2/transactionEvent/{signature}")),
+            None::<()>,
+        )
+        .await
+    }
+
+    /// Return the confirmation status of tx `signature` sent via the gateway
+    pub async fn get_tx_status(&self, signature: &str) -> Result<TxStatus> {
+        self.send(
+            self.request(Method::GET, &format!("/v2/tx/{signature}")),
+            None::<()>,
+        )
+        .await
+    }
+
+    /// Long-poll journaled events after sequence number `since`
+    pub async fn poll_events(&self, since: u64, timeout_ms: u64) -> Result<PollEventsResponse> {
+        self.send(
+            self.request(Method::GET, "/v2/events/poll")
+                .query(&[("since", since), ("timeoutMs", timeout_ms)]),
+            None::<()>,
+        )
+        .await
+    }
+
+    /// Send a request to any gateway endpoint e.g. `(Method::GET, "/v2/fundingRate/0")`
+    pub async fn call<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<impl Serialize>,
+    ) -> Result<T> {
+        self.send(self.request(method, path), body).await
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let req = self.http.request(method, format!("{}{path}", self.url));
+        match self.sub_account_id {
+            Some(id) => req.query(&[("subAccountId", id)]),
+            None => req,
+        }
+    }
+
+    async fn send<T: DeserializeOwned>(
+        &self,
+        req: RequestBuilder,
+        body: Option<impl Serialize>,
+    ) -> Result<T> {
+        let req = match body {
+            Some(ref body) => req.json(body),
+            None => req,
+        };
+        parse_response(req.send().await?).await
+    }
+}
+
+/// Decode a response body, or its error body if the request failed
+async fn parse_response<T: DeserializeOwned>(res: Response) -> Result<T> {
+    let status = res.status();
+    let body = res.bytes().await?;
+    if status.is_success() {
+        return serde_json::from_slice(&body).map_err(Into::into);
+    }
+    Err(api_error(status.as_u16(), &body))
+}
+
+fn api_error(status: u16, body: &[u8]) -> Error {
+    match serde_json::from_slice::<ErrorResponse>(body) {
+        Ok(err) => Error::Api {
+            code: err.code,
+            reason: err.reason,
+            request_id: err.request_id,
+        },
+        Err(_) => Error::Api {
+            code: status as u32,
+            reason: String::from_utf8_lossy(body).into_owned(),
+            request_id: None,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn api_error_works() {
+        let err = api_error(
+            400,
+            br#"{"code": 6010, "reason": "order does not exist", "requestId": "abc-1"}"#,
+        );
+        assert!(matches!(
+            err,
+            Error::Api { code: 6010, ref request_id, .. } if request_id.as_deref() == Some("abc-1")
+        ));
+        let err = api_error(502, b"bad gateway");
+        assert!(matches!(err, Error::Api { code: 502, ref reason, .. } if reason == "bad gateway"));
+    }
+}
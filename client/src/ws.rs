@@ -0,0 +1,201 @@
+//! Ws event API client
+
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use log::{debug, warn};
+use serde_json::json;
+use tokio::{sync::mpsc, task::JoinHandle};
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+use crate::{Channel, Event, Market};
+
+/// Delay before the first reconnect, doubled on each failed attempt
+const MIN_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamUpdate {
+    Event(Box<Event>),
+    /// the connection dropped and was resubscribed, events in between may be missed e.g. resync
+    /// with `GatewayClient::get_snapshot`
+    Reconnected,
+}
+
+/// Events of a sub-account, resubscribed after disconnects until dropped
+pub struct EventStream {
+    rx: mpsc::Receiver<StreamUpdate>,
+    task: JoinHandle<()>,
+}
+
+impl EventStream {
+    /// Subscribe to events of `sub_account_id` at the gateway's Ws `url` e.g. `ws://localhost:1337`
+    ///
+    /// * `markets` - markets to stream on the markets channel
+    pub fn subscribe(url: &str, sub_account_id: u16, markets: Vec<Market>) -> Self {
+        Self::connect(
+            url,
+            json!({
+                "method": "subscribe",
+                "subAccountId": sub_account_id,
+                "markets": markets,
+            })
+            .to_string(),
+        )
+    }
+
+    /// Subscribe to only the events of `channel` and/or `market` of `sub_account_id`
+    ///
+    /// with `market` set, events not specific to a market (e.g. account updates) are not streamed
+    pub fn subscribe_filtered(
+        url: &str,
+        sub_account_id: u16,
+        channel: Option<Channel>,
+        market: Option<Market>,
+    ) -> Self {
+        Self::connect(
+            url,
+            json!({
+                "method": "subscribe",
+                "subAccountId": sub_account_id,
+                "markets": market.into_iter().collect::<Vec<_>>(),
+                "channel": channel,
+                "market": market,
+            })
+            .to_string(),
+        )
+    }
+
+    /// Subscribe to events of `sub_account_id` at a gateway requiring Ws auth, authenticating with
+    /// `api_key` on each connect
+    pub fn subscribe_with_api_key(
+        url: &str,
+        api_key: &str,
+        sub_account_id: u16,
+        markets: Vec<Market>,
+    ) -> Self {
+        Self::connect_with(
+            url,
+            vec![
+                json!({"method": "auth", "apiKey": api_key}).to_string(),
+                json!({
+                    "method": "subscribe",
+                    "subAccountId": sub_account_id,
+                    "markets": markets,
+                })
+                .to_string(),
+            ],
+        )
+    }
+
+    /// Subscribe to the fills of all users in `market`
+    pub fn subscribe_trades(url: &str, market: Market) -> Self {
+        Self::connect(
+            url,
+            json!({
+                "method": "subscribe",
+                "channel": Channel::Trades,
+                "market": market,
+            })
+            .to_string(),
+        )
+    }
+
+    /// Subscribe to gateway-level conditions, the active ones are streamed first
+    pub fn subscribe_status(url: &str) -> Self {
+        Self::connect(
+            url,
+            json!({
+                "method": "subscribe",
+                "channel": Channel::Status,
+            })
+            .to_string(),
+        )
+    }
+
+    fn connect(url: &str, request: String) -> Self {
+        Self::connect_with(url, vec![request])
+    }
+
+    /// Connect sending `requests` in order, reconnecting until dropped
+    fn connect_with(url: &str, requests: Vec<String>) -> Self {
+        let (tx, rx) = mpsc::channel(256);
+        let url = url.to_string();
+        let task = tokio::spawn(async move {
+            let mut delay = MIN_RECONNECT_DELAY;
+            let mut connected_before = false;
+            loop {
+                match connect_async(url.as_str()).await {
+                    Ok((mut ws, _)) => {
+                        let mut sent = Ok(());
+                        for request in &requests {
+                            sent = ws.send(Message::text(request.clone())).await;
+                            if sent.is_err() {
+                                break;
+                            }
+                        }
+                        if let Err(err) = sent {
+                            warn!("Ws subscribe failed: {err}");
+                        } else {
+                            delay = MIN_RECONNECT_DELAY;
+                            if connected_before && tx.send(StreamUpdate::Reconnected).await.is_err()
+                            {
+                                return;
+                            }
+                            connected_before = true;
+                            while let Some(Ok(msg)) = ws.next().await {
+                                let Message::Text(ref text) = msg else {
+                                    continue;
+                                };
+                                match serde_json::from_str::<Event>(text) {
+                                    Ok(event) => {
+                                        if tx
+                                            .send(StreamUpdate::Event(Box::new(event)))
+                                            .await
+                                            .is_err()
+                                        {
+                                            return;
+                                        }
+                                    }
+                                    // keeps the connection alive with `--ws-heartbeat-interval` set
+                                    Err(_) if is_heartbeat(text) => {
+                                        let response = json!({"method": "heartbeat"}).to_string();
+                                        if let Err(err) = ws.send(Message::text(response)).await {
+                                            warn!("Ws heartbeat failed: {err}");
+                                            break;
+                                        }
+                                    }
+                                    Err(err) => warn!("unexpected Ws message: {text}, {err}"),
+                                }
+                            }
+                        }
+                        debug!("Ws disconnected: {url}");
+                    }
+                    Err(err) => warn!("Ws connect failed: {url}, {err}"),
+                }
+                if tx.is_closed() {
+                    return;
+                }
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+            }
+        });
+
+        Self { rx, task }
+    }
+
+    /// Return the next update, None once the stream ended
+    pub async fn next(&mut self) -> Option<StreamUpdate> {
+        self.rx.recv().await
+    }
+}
+
+impl Drop for EventStream {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+fn is_heartbeat(text: &str) -> bool {
+    serde_json::from_str::<serde_json::Value>(text).is_ok_and(|msg| msg["channel"] == "heartbeat")
+}
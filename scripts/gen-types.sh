@@ -0,0 +1,26 @@
+#!/bin/bash
+# Generate TypeScript and Python models of the gateway API from the types the gateway serves
+#
+# requires: node (npx json-schema-to-typescript), python (pip install datamodel-code-generator)
+set -e
+
+OUT=${1:-generated}
+SCHEMA="$OUT/schema"
+
+mkdir -p "$SCHEMA"
+SCHEMA_DIR="$(cd "$SCHEMA" && pwd)" cargo test -q --features schema schema::api_schemas_export
+
+mkdir -p "$OUT/ts"
+for schema in "$SCHEMA"/*.json; do
+    name=$(basename "$schema" .json)
+    npx --yes json-schema-to-typescript --input "$schema" --output "$OUT/ts/$name.d.ts"
+done
+
+mkdir -p "$OUT/python"
+datamodel-codegen \
+    --input "$SCHEMA" \
+    --input-file-type jsonschema \
+    --output-model-type pydantic_v2.BaseModel \
+    --output "$OUT/python"
+
+echo "models written to $OUT/ts and $OUT/python"
@@ -0,0 +1,144 @@
+//! Auth of `/v2/admin` endpoints
+//!
+//! With `DRIFT_GATEWAY_ADMIN_KEY` set, admin requests must carry `Authorization: Bearer <key>`,
+//! without it only loopback clients are served. Admin endpoints send txs (bench), reload settings,
+//! and expose usage and account health, so they shouldn't be reachable by anyone able to reach the API.
+//!
+//! Auth wraps the admin scope rather than matching request paths, so it applies to every request
+//! routed there, including percent-encoded paths
+
+use std::{net::IpAddr, sync::Arc};
+
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    error::InternalError,
+    http::header::AUTHORIZATION,
+    middleware::Next,
+    Error, HttpResponse,
+};
+use serde_json::json;
+
+use crate::{request_id::RequestId, ws_auth::constant_time_eq};
+
+/// Authenticates admin requests by bearer token, or by a loopback peer if no key is set
+pub struct AdminAuth {
+    key: Option<String>,
+}
+
+impl AdminAuth {
+    pub fn new(key: Option<String>) -> Self {
+        Self { key }
+    }
+
+    /// Return true if admin requests are authenticated by key, not just by a loopback peer
+    pub fn has_key(&self) -> bool {
+        self.key.is_some()
+    }
+
+    /// Return true if an admin request is authorized
+    ///
+    /// * `authorization` - must carry the key, if set
+    /// * `peer` - must be a loopback address, if no key is set
+    pub fn verify(&self, authorization: Option<&str>, peer: Option<IpAddr>) -> bool {
+        match self.key {
+            Some(ref key) => authorization
+                .and_then(|auth| auth.strip_prefix("Bearer "))
+                .is_some_and(|token| constant_time_eq(token.as_bytes(), key.as_bytes())),
+            None => peer.is_some_and(|ip| ip.is_loopback()),
+        }
+    }
+}
+
+/// Refuse requests not authorized by `auth`, wraps the admin scope
+pub async fn authorize(
+    auth: Arc<AdminAuth>,
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let authorization = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+    if !auth.verify(authorization, req.peer_addr().map(|addr| addr.ip())) {
+        return Err(InternalError::from_response(
+            "unauthorized",
+            HttpResponse::Unauthorized().json(json!({
+                "code": 401,
+                "reason": "invalid admin key",
+                "requestId": RequestId::current(),
+            })),
+        )
+        .into());
+    }
+    next.call(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{
+        http::StatusCode,
+        middleware::from_fn,
+        test::{self, TestRequest},
+        web, App,
+    };
+
+    use super::*;
+
+    #[test]
+    fn admin_auth_works() {
+        let remote = Some(IpAddr::from([10, 0, 0, 2]));
+        let auth = AdminAuth::new(Some("secret".to_string()));
+        assert!(auth.verify(Some("Bearer secret"), remote));
+        assert!(!auth.verify(Some("Bearer secrets"), remote));
+        assert!(!auth.verify(Some("secret"), remote));
+        assert!(!auth.verify(None, remote));
+
+        // without a key only loopback clients are admitted
+        let auth = AdminAuth::new(None);
+        assert!(auth.verify(None, Some(IpAddr::from([127, 0, 0, 1]))));
+        assert!(!auth.verify(None, remote));
+        assert!(!auth.verify(None, None));
+    }
+
+    #[actix_web::test]
+    async fn admin_scope_refuses_encoded_paths() {
+        let auth = Arc::new(AdminAuth::new(Some("secret".to_string())));
+        let app = test::init_service(
+            App::new().service(
+                web::scope("/v2")
+                    .service(
+                        web::scope("/admin")
+                            .wrap(from_fn(move |req: ServiceRequest, next: Next<_>| {
+                                authorize(Arc::clone(&auth), req, next)
+                            }))
+                            .route("/bench", web::post().to(HttpResponse::Ok)),
+                    )
+                    .route("/orders", web::post().to(HttpResponse::Ok)),
+            ),
+        )
+        .await;
+
+        let status = |uri: &str, authorization: Option<&str>| {
+            let mut req = TestRequest::post().uri(uri);
+            if let Some(authorization) = authorization {
+                req = req.insert_header((AUTHORIZATION, authorization));
+            }
+            let res = test::try_call_service(&app, req.to_request());
+            async move {
+                match res.await {
+                    Ok(res) => res.status(),
+                    Err(err) => err.as_response_error().status_code(),
+                }
+            }
+        };
+        assert_eq!(status("/v2/orders", None).await, StatusCode::OK);
+        assert_eq!(
+            status("/v2/admin/bench", Some("Bearer secret")).await,
+            StatusCode::OK
+        );
+        for uri in ["/v2/admin/bench", "/v2/%61dmin/bench", "/v2/admin/%62ench"] {
+            assert_eq!(status(uri, None).await, StatusCode::UNAUTHORIZED, "{uri}");
+        }
+    }
+}
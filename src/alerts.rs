@@ -0,0 +1,271 @@
+//! Outbound alerting for critical gateway conditions
+//!
+//! Alerts are posted to configured sinks (generic webhook, Slack, Discord, Telegram).
+//! Each kind of alert is rate limited by a cooldown to avoid flooding the sinks.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use log::{info, warn};
+use rust_decimal::Decimal;
+use serde_json::json;
+
+use crate::LOG_TARGET;
+
+/// Min. time between alerts of the same kind
+const ALERT_COOLDOWN: Duration = Duration::from_secs(300);
+
+/// Message format of an alert sink
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SinkFormat {
+    /// `{"alert": "<kind>", "message": "<text>", "ts": <unix ts>}`
+    Webhook,
+    Slack,
+    Discord,
+    /// url should include the chat e.g. `https://api.telegram.org/bot<TOKEN>/sendMessage?chat_id=<ID>`
+    Telegram,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlertSink {
+    format: SinkFormat,
+    url: String,
+}
+
+impl AlertSink {
+    /// Parse a list of sinks from user command
+    ///
+    /// e.g. `slack=https://hooks.slack.com/..,webhook=https://example.com/alerts`
+    pub fn parse_list(sinks: &str) -> Result<Vec<Self>, String> {
+        sinks
+            .split(',')
+            .map(|sink| {
+                let (format, url) = sink
+                    .split_once('=')
+                    .ok_or(format!("expected <format>=<url>: {sink}"))?;
+                let format = match format {
+                    "webhook" => SinkFormat::Webhook,
+                    "slack" => SinkFormat::Slack,
+                    "discord" => SinkFormat::Discord,
+                    "telegram" => SinkFormat::Telegram,
+                    _ => return Err(format!("unknown alert sink: {format}")),
+                };
+                Ok(Self {
+                    format,
+                    url: url.to_string(),
+                })
+            })
+            .collect()
+    }
+
+    fn payload(&self, alert: &Alert) -> serde_json::Value {
+        let message = alert.to_string();
+        match self.format {
+            SinkFormat::Webhook => json!({
+                "alert": alert.kind(),
+                "message": message,
+                "ts": SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default(),
+            }),
+            SinkFormat::Slack => json!({ "text": message }),
+            SinkFormat::Discord => json!({ "content": message }),
+            SinkFormat::Telegram => json!({ "text": message }),
+        }
+    }
+}
+
+/// A critical gateway condition
+#[derive(Debug, Clone)]
+pub enum Alert {
+    /// sub-account margin health fell below the configured threshold
+    MarginHealth {
+        sub_account_id: u16,
+        health: Decimal,
+    },
+    /// sub-account is being liquidated
+    Liquidation { sub_account_id: u16 },
+    /// consecutive txs landed failed on-chain
+    TxFailureStreak { count: u32 },
+    /// an upstream subscription disconnected
+    SubscriptionOutage { subscription: String },
+    /// a market/oracle subscription stopped updating and was resubscribed
+    SubscriptionStalled { subscription: String, secs: u64 },
+    /// daily loss of a sub-account exceeded the limit, trading is halted
+    DailyLossLimit {
+        sub_account_id: u16,
+        loss: Decimal,
+        limit: Decimal,
+    },
+    /// drift oracle price diverged from the secondary price source
+    OracleDivergence {
+        market: String,
+        price: Decimal,
+        secondary_price: Decimal,
+        divergence: Decimal,
+    },
+}
+
+impl Alert {
+    fn kind(&self) -> &'static str {
+        match self {
+            Self::MarginHealth { .. } => "marginHealth",
+            Self::Liquidation { .. } => "liquidation",
+            Self::TxFailureStreak { .. } => "txFailureStreak",
+            Self::SubscriptionOutage { .. } => "subscriptionOutage",
+            Self::SubscriptionStalled { .. } => "subscriptionStalled",
+            Self::DailyLossLimit { .. } => "dailyLossLimit",
+            Self::OracleDivergence { .. } => "oracleDivergence",
+        }
+    }
+
+    /// Whether the alert is subject to the cooldown, liquidations are sent once as they start
+    fn has_cooldown(&self) -> bool {
+        !matches!(self, Self::Liquidation { .. })
+    }
+}
+
+impl std::fmt::Display for Alert {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MarginHealth {
+                sub_account_id,
+                health,
+            } => write!(
+                f,
+                "⚠️ drift gateway: sub-account {sub_account_id} margin health is {health}"
+            ),
+            Self::Liquidation { sub_account_id } => write!(
+                f,
+                "🚨 drift gateway: sub-account {sub_account_id} is being liquidated"
+            ),
+            Self::TxFailureStreak { count } => {
+                write!(f, "⚠️ drift gateway: {count} consecutive txs failed onchain")
+            }
+            Self::SubscriptionOutage { subscription } => {
+                write!(
+                    f,
+                    "⚠️ drift gateway: subscription disconnected: {subscription}"
+                )
+            }
+            Self::SubscriptionStalled { subscription, secs } => write!(
+                f,
+                "⚠️ drift gateway: subscription stalled for {secs}s, resubscribed: {subscription}"
+            ),
+            Self::DailyLossLimit {
+                sub_account_id,
+                loss,
+                limit,
+            } => write!(
+                f,
+                "🛑 drift gateway: daily loss {loss} of sub-account {sub_account_id} exceeded limit {limit}, trading halted"
+            ),
+            Self::OracleDivergence {
+                market,
+                price,
+                secondary_price,
+                divergence,
+            } => write!(
+                f,
+                "⚠️ drift gateway: {market} oracle price {price} diverged {divergence} from secondary price {secondary_price}"
+            ),
+        }
+    }
+}
+
+/// Sends alerts to the configured sinks
+#[derive(Default)]
+pub struct Alerter {
+    sinks: Vec<AlertSink>,
+    http: reqwest::Client,
+    /// last time an alert of each kind was sent
+    last_sent: Mutex<HashMap<&'static str, Instant>>,
+    /// alert after this many consecutive tx failures (0 = disabled)
+    tx_failure_threshold: u32,
+    tx_failures: AtomicU32,
+}
+
+impl Alerter {
+    pub fn new(sinks: Vec<AlertSink>, tx_failure_threshold: u32) -> Self {
+        info!(target: LOG_TARGET, "alerting to {} sink(s)", sinks.len());
+        Self {
+            sinks,
+            tx_failure_threshold,
+            ..Default::default()
+        }
+    }
+
+    /// Post `alert` to all sinks, unless an alert of the same kind was sent recently
+    pub fn send(&self, alert: Alert) {
+        warn!(target: LOG_TARGET, "{alert}");
+        if self.sinks.is_empty() {
+            return;
+        }
+        if alert.has_cooldown() {
+            let mut last_sent = self.last_sent.lock().expect("lock");
+            let now = Instant::now();
+            if last_sent
+                .get(alert.kind())
+                .is_some_and(|t| now.duration_since(*t) < ALERT_COOLDOWN)
+            {
+                return;
+            }
+            last_sent.insert(alert.kind(), now);
+        }
+
+        for sink in self.sinks.iter() {
+            let request = self.http.post(&sink.url).json(&sink.payload(&alert));
+            tokio::spawn(async move {
+                if let Err(err) = request.send().await.and_then(|r| r.error_for_status()) {
+                    warn!(target: LOG_TARGET, "failed sending alert: {err:?}");
+                }
+            });
+        }
+    }
+
+    /// Record the outcome of a landed tx, alerting on a streak of txs failing on-chain
+    ///
+    /// txs that don't land within their blockhash (e.g. congestion) aren't failures, as for the
+    /// status channel
+    pub fn record_landed_tx(&self, ok: bool) {
+        if self.tx_failure_threshold == 0 {
+            return;
+        }
+        if ok {
+            self.tx_failures.store(0, Ordering::Relaxed);
+            return;
+        }
+        let count = self.tx_failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if count % self.tx_failure_threshold == 0 {
+            self.send(Alert::TxFailureStreak { count });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_alert_sinks() {
+        let sinks = AlertSink::parse_list(
+            "slack=https://hooks.slack.com/services/x,telegram=https://api.telegram.org/botX/sendMessage?chat_id=1",
+        )
+        .unwrap();
+        assert_eq!(sinks.len(), 2);
+        assert_eq!(sinks[0].format, SinkFormat::Slack);
+        assert_eq!(sinks[0].url, "https://hooks.slack.com/services/x");
+        assert_eq!(sinks[1].format, SinkFormat::Telegram);
+        assert_eq!(
+            sinks[1].url,
+            "https://api.telegram.org/botX/sendMessage?chat_id=1"
+        );
+
+        assert!(AlertSink::parse_list("pager=https://example.com").is_err());
+        assert!(AlertSink::parse_list("https://example.com").is_err());
+    }
+}
@@ -0,0 +1,381 @@
+//! Archival of stored fill and order events to S3-compatible storage
+//!
+//! Events of the fills store are batched by period into Parquet files and uploaded to a bucket,
+//! feeding a data lake without extra glue services. Progress is kept in the store, each period is
+//! uploaded once it has ended and retried on the next check if the upload fails.
+
+use std::{io::Write, sync::Arc, time::Duration};
+
+use log::{info, warn};
+use parquet::{
+    basic::Compression,
+    data_type::{ByteArray, ByteArrayType, DataType, Int32Type, Int64Type},
+    errors::ParquetError,
+    file::{
+        properties::WriterProperties,
+        writer::{SerializedFileWriter, SerializedRowGroupWriter},
+    },
+    schema::parser::parse_message_type,
+};
+use reqwest::Url;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    controller::unix_timestamp,
+    fills::{FillStore, StoredEvent},
+    websocket::Channel,
+    ws_auth::{hmac_sha256, to_hex},
+    LOG_TARGET,
+};
+
+/// Interval between checks for ended periods
+const ARCHIVE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// Seconds after a period ends before it's archived, so late events are included
+const ARCHIVE_LAG: u64 = 60;
+
+/// Schema of archived events, `event` is the JSON of the event as served by the API
+const SCHEMA: &str = "
+message event {
+    required int64 ts;
+    required int32 sub_account_id;
+    required binary channel (UTF8);
+    required binary event (UTF8);
+}";
+
+/// Bucket objects are uploaded to, addressed path-style
+pub struct S3Target {
+    /// bucket url and key prefix
+    url: Url,
+    region: String,
+    access_key: String,
+    secret_key: String,
+    session_token: Option<String>,
+}
+
+impl S3Target {
+    /// * `url` - bucket url and optional key prefix
+    ///   e.g. `https://s3.us-east-1.amazonaws.com/<bucket>/<prefix>`
+    pub fn new(
+        url: &str,
+        region: String,
+        access_key: String,
+        secret_key: String,
+        session_token: Option<String>,
+    ) -> Result<Self, String> {
+        let url = Url::parse(url).map_err(|err| format!("invalid archive url: {err}"))?;
+        if url.host_str().is_none() || url.path().trim_matches('/').is_empty() {
+            return Err(format!(
+                "expected https://<host>/<bucket>[/<prefix>]: {url}"
+            ));
+        }
+        Ok(Self {
+            url,
+            region,
+            access_key,
+            secret_key,
+            session_token,
+        })
+    }
+
+    /// Return the url of object `name` and the headers of a PUT of `body` signed with AWS
+    /// signature v4 at unix timestamp `ts`
+    fn signed_put(&self, name: &str, body: &[u8], ts: u64) -> (Url, Vec<(&'static str, String)>) {
+        let mut url = self.url.clone();
+        url.set_path(&format!("{}/{name}", self.url.path().trim_end_matches('/')));
+        let host = match url.port() {
+            Some(port) => format!("{}:{port}", url.host_str().unwrap_or_default()),
+            None => url.host_str().unwrap_or_default().to_string(),
+        };
+        let amz_date = amz_date(ts);
+        let date = &amz_date[..8];
+        let payload_hash = to_hex(&Sha256::digest(body));
+
+        // sorted by name
+        let mut headers = vec![
+            ("host", host),
+            ("x-amz-content-sha256", payload_hash.clone()),
+            ("x-amz-date", amz_date.clone()),
+        ];
+        if let Some(ref token) = self.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        let signed_headers = headers
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(";");
+        let canonical_headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{name}:{value}\n"))
+            .collect();
+        let canonical_request = format!(
+            "PUT\n{}\n\n{canonical_headers}\n{signed_headers}\n{payload_hash}",
+            url.path()
+        );
+        let scope = format!("{date}/{}/s3/aws4_request", self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+            to_hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+        let signature = to_hex(&hmac_sha256(
+            &signing_key(&self.secret_key, date, &self.region, "s3"),
+            string_to_sign.as_bytes(),
+        ));
+
+        // the client sets the host header itself
+        headers.retain(|(name, _)| *name != "host");
+        headers.push((
+            "authorization",
+            format!(
+                "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+                self.access_key
+            ),
+        ));
+        (url, headers)
+    }
+}
+
+/// Archive events of `store` to `target` in the background, one Parquet file per `period`
+pub fn run(store: Arc<FillStore>, target: S3Target, period: Duration) {
+    let period = period.as_secs().max(1);
+    tokio::spawn(async move {
+        let http = reqwest::Client::new();
+        let mut interval = tokio::time::interval(ARCHIVE_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            let end = unix_timestamp().saturating_sub(ARCHIVE_LAG);
+            let Some(mut start) = store.archived_until().or_else(|| store.next_event_ts(0)) else {
+                continue;
+            };
+            loop {
+                let period_end = (start / period + 1) * period;
+                if period_end > end {
+                    break;
+                }
+                let events: Vec<(u64, StoredEvent)> =
+                    store.events_between(start, period_end).collect();
+                if events.is_empty() {
+                    // skip ahead to the period of the next event
+                    start = store
+                        .next_event_ts(period_end)
+                        .unwrap_or(end)
+                        .min(end)
+                        .max(period_end);
+                } else {
+                    let name = format!("events-{start}-{period_end}.parquet");
+                    if let Err(err) = upload(&http, &target, &name, &events).await {
+                        warn!(target: LOG_TARGET, "archive upload failed: {name}, {err}");
+                        break;
+                    }
+                    info!(target: LOG_TARGET, "archived {} events: {name}", events.len());
+                    start = period_end;
+                }
+                if let Err(err) = store.set_archived_until(start) {
+                    warn!(target: LOG_TARGET, "archive progress not persisted: {err:?}");
+                    break;
+                }
+            }
+        }
+    });
+}
+
+/// Upload `events` to `target` as the Parquet object `name`
+async fn upload(
+    http: &reqwest::Client,
+    target: &S3Target,
+    name: &str,
+    events: &[(u64, StoredEvent)],
+) -> Result<(), String> {
+    let body = encode_parquet(events).map_err(|err| err.to_string())?;
+    let (url, headers) = target.signed_put(name, &body, unix_timestamp());
+    let mut request = http.put(url).body(body);
+    for (name, value) in headers {
+        request = request.header(name, value);
+    }
+    request
+        .send()
+        .await
+        .and_then(|res| res.error_for_status())
+        .map(|_| ())
+        .map_err(|err| err.to_string())
+}
+
+/// Encode `events` and their timestamps as a Parquet file
+fn encode_parquet(events: &[(u64, StoredEvent)]) -> parquet::errors::Result<Vec<u8>> {
+    let ts: Vec<i64> = events.iter().map(|(ts, _)| *ts as i64).collect();
+    let sub_account_ids: Vec<i32> = events
+        .iter()
+        .map(|(_, e)| i32::from(e.sub_account_id))
+        .collect();
+    let channels: Vec<ByteArray> = events
+        .iter()
+        .map(|(_, e)| {
+            ByteArray::from(match e.channel {
+                Channel::Fills => "fills",
+                _ => "orders",
+            })
+        })
+        .collect();
+    let data = events
+        .iter()
+        .map(|(_, e)| serde_json::to_vec(&e.data).map(ByteArray::from))
+        .collect::<Result<Vec<ByteArray>, _>>()
+        .map_err(|err| ParquetError::General(err.to_string()))?;
+
+    let schema = Arc::new(parse_message_type(SCHEMA)?);
+    let props = Arc::new(
+        WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build(),
+    );
+    let mut file = Vec::new();
+    let mut writer = SerializedFileWriter::new(&mut file, schema, props)?;
+    let mut row_group = writer.next_row_group()?;
+    write_column::<Int64Type, _>(&mut row_group, &ts)?;
+    write_column::<Int32Type, _>(&mut row_group, &sub_account_ids)?;
+    write_column::<ByteArrayType, _>(&mut row_group, &channels)?;
+    write_column::<ByteArrayType, _>(&mut row_group, &data)?;
+    row_group.close()?;
+    writer.close()?;
+    Ok(file)
+}
+
+/// Write `values` to the next (required) column of `row_group`
+fn write_column<T: DataType, W: Write + Send>(
+    row_group: &mut SerializedRowGroupWriter<'_, W>,
+    values: &[T::T],
+) -> parquet::errors::Result<()> {
+    let mut column = row_group
+        .next_column()?
+        .ok_or_else(|| ParquetError::General("column missing from schema".into()))?;
+    column.typed::<T>().write_batch(values, None, None)?;
+    column.close()
+}
+
+/// Return the AWS signature v4 signing key
+fn signing_key(secret_key: &str, date: &str, region: &str, service: &str) -> [u8; 32] {
+    let key = hmac_sha256(format!("AWS4{secret_key}").as_bytes(), date.as_bytes());
+    let key = hmac_sha256(&key, region.as_bytes());
+    let key = hmac_sha256(&key, service.as_bytes());
+    hmac_sha256(&key, b"aws4_request")
+}
+
+/// Format unix timestamp `ts` as a basic ISO 8601 UTC time e.g. `20231114T221320Z`
+fn amz_date(ts: u64) -> String {
+    let (days, secs) = ((ts / 86_400) as i64, ts % 86_400);
+    // civil date from days since the epoch, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}{month:02}{day:02}T{:02}{:02}{:02}Z",
+        secs / 3_600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    use super::*;
+    use crate::websocket::AccountEvent;
+
+    #[test]
+    fn amz_date_works() {
+        assert_eq!(amz_date(0), "19700101T000000Z");
+        assert_eq!(amz_date(951_782_400), "20000229T000000Z");
+        assert_eq!(amz_date(1_700_000_000), "20231114T221320Z");
+    }
+
+    #[test]
+    fn signing_key_matches_aws_example() {
+        let key = signing_key(
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            "20120215",
+            "us-east-1",
+            "iam",
+        );
+        assert_eq!(
+            to_hex(&key),
+            "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d"
+        );
+    }
+
+    #[test]
+    fn signed_put_headers() {
+        let target = S3Target::new(
+            "http://localhost:9000/bucket/gateway/",
+            "us-east-1".into(),
+            "AKID".into(),
+            "secret".into(),
+            None,
+        )
+        .unwrap();
+        let (url, headers) = target.signed_put("events-0-3600.parquet", b"", 1_700_000_000);
+        assert_eq!(
+            url.as_str(),
+            "http://localhost:9000/bucket/gateway/events-0-3600.parquet"
+        );
+        let names: Vec<&str> = headers.iter().map(|(name, _)| *name).collect();
+        assert_eq!(
+            names,
+            ["x-amz-content-sha256", "x-amz-date", "authorization"]
+        );
+        assert!(headers[2].1.starts_with(
+            "AWS4-HMAC-SHA256 Credential=AKID/20231114/us-east-1/s3/aws4_request, SignedHeaders=host;x-amz-content-sha256;x-amz-date, Signature="
+        ));
+
+        assert!(S3Target::new(
+            "http://localhost:9000",
+            "".into(),
+            "".into(),
+            "".into(),
+            None
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn encode_parquet_works() {
+        let events: Vec<(u64, StoredEvent)> = (0..3)
+            .map(|i| {
+                (
+                    100 + i,
+                    StoredEvent {
+                        sub_account_id: 0,
+                        channel: Channel::Orders,
+                        data: AccountEvent::OrderCancel {
+                            order_id: i as u32,
+                            ts: 100 + i,
+                            signature: String::new(),
+                            tx_idx: 0,
+                        },
+                    },
+                )
+            })
+            .collect();
+        let file = encode_parquet(&events).unwrap();
+
+        let path = std::env::temp_dir().join(format!("gateway-archive-{}", std::process::id()));
+        std::fs::write(&path, file).unwrap();
+        let reader = SerializedFileReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+        assert_eq!(reader.metadata().file_metadata().num_rows(), 3);
+        assert_eq!(
+            reader
+                .metadata()
+                .file_metadata()
+                .schema_descr()
+                .num_columns(),
+            4
+        );
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,195 @@
+//! Backfill of user account changes missed during event subscription gaps
+//!
+//! Once the event subscription of a sub-account reconnects, the account is refetched and diffed
+//! against its state at disconnect, missed order and position changes are emitted as corrective
+//! events.
+
+use anchor_lang::AccountDeserialize;
+use drift_rs::{
+    types::{accounts::User, Order, OrderStatus, SdkResult},
+    DriftClient, Pubkey,
+};
+
+use crate::{
+    types::{get_market_decimals, Market, SpotPosition},
+    websocket::{AccountEvent, Channel, OrderWithDecimals},
+};
+
+/// Fetch the current account of `sub_account` from RPC, bypassing the account cache
+///
+/// Returns None if the account does not exist or is not a user account
+pub async fn fetch_user(client: &DriftClient, sub_account: &Pubkey) -> SdkResult<Option<User>> {
+    let data = client.rpc().get_account_data(sub_account).await?;
+    Ok(User::try_deserialize(&mut data.as_slice()).ok())
+}
+
+/// Return orders of `after` open and changed since `before`, and ids of orders no longer open
+fn order_changes(before: &User, after: &User) -> (Vec<Order>, Vec<u32>) {
+    let open = |user: &User| -> Vec<Order> {
+        user.orders
+            .iter()
+            .filter(|o| o.status == OrderStatus::Open)
+            .copied()
+            .collect()
+    };
+    let (before, after) = (open(before), open(after));
+    let changed = after
+        .iter()
+        .filter(|o| !before.contains(o))
+        .copied()
+        .collect();
+    let closed = before
+        .iter()
+        .filter(|o| !after.iter().any(|a| a.order_id == o.order_id))
+        .map(|o| o.order_id)
+        .collect();
+    (changed, closed)
+}
+
+/// Return open orders of `after` with a filled amount changed since `before`
+///
+/// orders new since `before` are returned if partially filled
+pub fn fill_progress(before: &User, after: &User) -> Vec<Order> {
+    after
+        .orders
+        .iter()
+        .filter(|o| o.status == OrderStatus::Open)
+        .filter(|o| {
+            let filled_before = before
+                .orders
+                .iter()
+                .find(|b| b.status == OrderStatus::Open && b.order_id == o.order_id)
+                .map_or(0, |b| b.base_asset_amount_filled);
+            o.base_asset_amount_filled != filled_before
+        })
+        .copied()
+        .collect()
+}
+
+/// Return corrective events for changes from `before` to `after` of an account
+///
+/// * `ts` - unix timestamp of the backfill
+pub async fn backfill_events(
+    client: &DriftClient,
+    before: &User,
+    after: &User,
+    ts: u64,
+) -> SdkResult<Vec<(Channel, AccountEvent)>> {
+    let program_data = client.program_data();
+    let (changed, closed) = order_changes(before, after);
+    let mut events: Vec<(Channel, AccountEvent)> = changed
+        .into_iter()
+        .map(|order| {
+            let decimals = get_market_decimals(
+                program_data,
+                Market::new(order.market_index, order.market_type),
+            );
+            (
+                Channel::Orders,
+                AccountEvent::OrderBackfill {
+                    order: OrderWithDecimals::from_order(order, decimals),
+                    ts,
+                },
+            )
+        })
+        .chain(
+            closed
+                .into_iter()
+                .map(|order_id| (Channel::Orders, AccountEvent::OrderClosed { order_id, ts })),
+        )
+        .collect();
+
+    let perp_changed = before.perp_positions != after.perp_positions;
+    let spot_changed = before.spot_positions != after.spot_positions;
+    if perp_changed || spot_changed {
+        let mut spot = Vec::new();
+        for position in after.spot_positions.iter().filter(|p| !p.is_available()) {
+            let spot_market = client
+                .get_spot_market_account(position.market_index)
+                .await?;
+            spot.push(SpotPosition::from_sdk_type(position, &spot_market));
+        }
+        events.push((
+            Channel::Account,
+            AccountEvent::AccountUpdate {
+                spot: spot_changed.then_some(spot),
+                perp: perp_changed.then(|| {
+                    after
+                        .perp_positions
+                        .iter()
+                        .filter(|p| !p.is_available())
+                        .map(|p| (*p).into())
+                        .collect()
+                }),
+                margin: None,
+                collateral: None,
+                leverage: None,
+                ts,
+            },
+        ));
+    }
+
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open_order(order_id: u32, filled: u64) -> Order {
+        Order {
+            order_id,
+            status: OrderStatus::Open,
+            base_asset_amount: 2_000,
+            base_asset_amount_filled: filled,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn order_changes_works() {
+        let mut before = User::default();
+        before.orders[0] = open_order(1, 0);
+        before.orders[1] = open_order(2, 0);
+        before.orders[2] = open_order(3, 0);
+
+        let mut after = User::default();
+        // 1 unchanged, 2 partially filled, 3 closed, 4 new
+        after.orders[0] = open_order(1, 0);
+        after.orders[1] = open_order(2, 1_000);
+        after.orders[3] = open_order(4, 0);
+
+        let (changed, closed) = order_changes(&before, &after);
+        assert_eq!(
+            changed.iter().map(|o| o.order_id).collect::<Vec<_>>(),
+            vec![2, 4]
+        );
+        assert_eq!(closed, vec![3]);
+
+        let (changed, closed) = order_changes(&after, &after);
+        assert!(changed.is_empty() && closed.is_empty());
+    }
+
+    #[test]
+    fn fill_progress_works() {
+        let mut before = User::default();
+        before.orders[0] = open_order(1, 0);
+        before.orders[1] = open_order(2, 500);
+
+        let mut after = User::default();
+        // 1 partially filled, 2 unchanged, 3 new and partially filled, 4 new
+        after.orders[0] = open_order(1, 1_000);
+        after.orders[1] = open_order(2, 500);
+        after.orders[2] = open_order(3, 200);
+        after.orders[3] = open_order(4, 0);
+
+        assert_eq!(
+            fill_progress(&before, &after)
+                .iter()
+                .map(|o| o.order_id)
+                .collect::<Vec<_>>(),
+            vec![1, 3]
+        );
+        assert!(fill_progress(&after, &after).is_empty());
+    }
+}
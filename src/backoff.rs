@@ -0,0 +1,83 @@
+//! Exponential backoff with jitter for retried subscriptions
+//!
+//! Each delay is drawn from the upper half of an exponentially growing window, so many streams
+//! dropped at once (e.g. on an RPC outage) don't retry in lockstep.
+
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    time::Duration,
+};
+
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    min: Duration,
+    max: Duration,
+    max_attempts: u32,
+    attempts: u32,
+}
+
+impl Backoff {
+    /// * `min` - window of the first retry
+    /// * `max` - max. window
+    /// * `max_attempts` - retries before giving up
+    pub fn new(min: Duration, max: Duration, max_attempts: u32) -> Self {
+        Self {
+            min,
+            max,
+            max_attempts,
+            attempts: 0,
+        }
+    }
+
+    /// Return the delay before the next retry, none once retries are exhausted
+    pub fn next_delay(&mut self) -> Option<Duration> {
+        if self.attempts >= self.max_attempts {
+            return None;
+        }
+        let window = self
+            .min
+            .saturating_mul(1 << self.attempts.min(16))
+            .min(self.max);
+        self.attempts += 1;
+        let half = window / 2;
+        Some(half + half.mul_f64(jitter()))
+    }
+
+    /// Retries made since the last reset
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// Start over e.g. once the retried stream is healthy again
+    pub fn reset(&mut self) {
+        self.attempts = 0;
+    }
+}
+
+/// Return a random fraction in [0, 1)
+fn jitter() -> f64 {
+    // std hashers are randomly keyed per instance
+    let random = RandomState::new().build_hasher().finish();
+    (random >> 11) as f64 / (1_u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delays_grow_with_jitter_until_exhausted() {
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(4), 4);
+        let windows = [1, 2, 4, 4].map(Duration::from_secs);
+        for window in windows {
+            let delay = backoff.next_delay().unwrap();
+            assert!(delay >= window / 2 && delay <= window, "{delay:?}");
+        }
+        assert_eq!(backoff.attempts(), 4);
+        assert!(backoff.next_delay().is_none());
+
+        backoff.reset();
+        assert!(backoff.next_delay().unwrap() <= Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,83 @@
+//! Chain clock estimate
+//!
+//! The program checks times e.g. order `maxTs` against the cluster's `Clock::unix_timestamp`, which
+//! runs up to tens of seconds apart from local time. The clock sysvar is fetched periodically and
+//! its offset from local time applied in between.
+
+use std::{
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use drift_rs::event_subscriber::RpcClient;
+use log::warn;
+use solana_sdk::{clock::Clock, sysvar};
+
+use crate::{controller::unix_timestamp, LOG_TARGET};
+
+const CLOCK_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Default, Debug)]
+pub struct ChainClock {
+    /// chain time minus local time, secs
+    offset: AtomicI64,
+}
+
+impl ChainClock {
+    /// Start tracking the chain clock via `rpc`
+    ///
+    /// until the first fetch succeeds chain time is local time
+    pub fn start(rpc: Arc<RpcClient>) -> Arc<Self> {
+        let chain_clock = Arc::new(Self::default());
+        tokio::spawn({
+            let chain_clock = Arc::clone(&chain_clock);
+            async move {
+                let mut interval = tokio::time::interval(CLOCK_REFRESH_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    let clock = rpc.get_account(&sysvar::clock::ID).await.map(|account| {
+                        bincode::deserialize::<Clock>(&account.data).map(|c| c.unix_timestamp)
+                    });
+                    match clock {
+                        Ok(Ok(chain_ts)) => chain_clock.update(chain_ts, unix_timestamp() as i64),
+                        Ok(Err(err)) => warn!(target: LOG_TARGET, "invalid clock sysvar: {err:?}"),
+                        Err(err) => warn!(target: LOG_TARGET, "clock fetch failed: {err:?}"),
+                    }
+                }
+            }
+        });
+        chain_clock
+    }
+
+    /// Return the chain's unix timestamp at local unix timestamp `now`
+    pub fn at(&self, now: i64) -> i64 {
+        now + self.offset.load(Ordering::Relaxed)
+    }
+
+    /// Return the chain's current unix timestamp
+    pub fn now(&self) -> i64 {
+        self.at(unix_timestamp() as i64)
+    }
+
+    fn update(&self, chain_ts: i64, now: i64) {
+        self.offset.store(chain_ts - now, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chain_clock_applies_offset() {
+        let chain_clock = ChainClock::default();
+        assert_eq!(chain_clock.at(1_000), 1_000);
+        chain_clock.update(990, 1_000);
+        assert_eq!(chain_clock.at(1_030), 1_020);
+        chain_clock.update(1_045, 1_030);
+        assert_eq!(chain_clock.at(1_031), 1_046);
+    }
+}
@@ -0,0 +1,92 @@
+//! Latest chain slot from a Ws slot subscription
+//!
+//! Used to tell how far cached account data lags the chain without an RPC round trip per read.
+//! The subscription is retried with backoff, while it is down (or silent) the slot is unknown.
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use drift_rs::event_subscriber::PubsubClient;
+use futures_util::StreamExt;
+use log::{debug, warn};
+
+use crate::{backoff::Backoff, controller::unix_timestamp, LOG_TARGET};
+
+/// Secs without a slot update after which the slot is unknown
+const MAX_SLOT_AGE: u64 = 10;
+
+const MIN_RESUBSCRIBE_DELAY: Duration = Duration::from_millis(500);
+const MAX_RESUBSCRIBE_DELAY: Duration = Duration::from_secs(30);
+
+#[derive(Default, Debug)]
+pub struct ChainSlot {
+    slot: AtomicU64,
+    /// unix timestamp of the latest slot update
+    updated_ts: AtomicU64,
+}
+
+impl ChainSlot {
+    /// Start tracking the chain slot via `ws`
+    pub fn start(ws: Arc<PubsubClient>) -> Arc<Self> {
+        let chain_slot = Arc::new(Self::default());
+        tokio::spawn({
+            let chain_slot = Arc::clone(&chain_slot);
+            async move {
+                let mut backoff =
+                    Backoff::new(MIN_RESUBSCRIBE_DELAY, MAX_RESUBSCRIBE_DELAY, u32::MAX);
+                loop {
+                    match ws.slot_subscribe().await {
+                        Ok((mut slots, unsubscribe)) => {
+                            debug!(target: LOG_TARGET, "slot subscription connected");
+                            backoff.reset();
+                            while let Some(info) = slots.next().await {
+                                chain_slot.update(info.slot, unix_timestamp());
+                            }
+                            unsubscribe().await;
+                            warn!(target: LOG_TARGET, "slot subscription ended");
+                        }
+                        Err(err) => {
+                            warn!(target: LOG_TARGET, "slot subscription failed: {err:?}");
+                        }
+                    }
+                    let delay = backoff.next_delay().unwrap_or(MAX_RESUBSCRIBE_DELAY);
+                    tokio::time::sleep(delay).await;
+                }
+            }
+        });
+        chain_slot
+    }
+
+    /// Return the latest chain slot at `now`, None if unknown or not updated recently
+    pub fn get(&self, now: u64) -> Option<u64> {
+        let updated_ts = self.updated_ts.load(Ordering::Acquire);
+        (updated_ts > 0 && now.saturating_sub(updated_ts) <= MAX_SLOT_AGE)
+            .then(|| self.slot.load(Ordering::Relaxed))
+    }
+
+    fn update(&self, slot: u64, now: u64) {
+        self.slot.fetch_max(slot, Ordering::Relaxed);
+        self.updated_ts.store(now, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chain_slot_expires() {
+        let chain_slot = ChainSlot::default();
+        assert_eq!(chain_slot.get(100), None);
+        chain_slot.update(1_000, 100);
+        // out of order updates don't move the slot back
+        chain_slot.update(999, 101);
+        assert_eq!(chain_slot.get(105), Some(1_000));
+        assert_eq!(chain_slot.get(101 + MAX_SLOT_AGE + 1), None);
+    }
+}
@@ -0,0 +1,68 @@
+//! Two-step confirmation of sensitive operations
+//!
+//! With confirmation enabled for an operation class (e.g. transfers), requests are held and
+//! answered with a one-time token instead of being sent. The operation runs once the token is
+//! confirmed via the admin API within the timeout, so a leaked trading key alone can't move funds.
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::ws_auth;
+
+/// Operations awaiting confirmation by one-time token
+pub struct Confirmations<T> {
+    timeout: Duration,
+    pending: Mutex<HashMap<String, (Instant, T)>>,
+}
+
+impl<T> Confirmations<T> {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            pending: Mutex::default(),
+        }
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Hold `operation` until confirmed, returning its token
+    pub fn request(&self, operation: T, now: Instant) -> String {
+        let token = ws_auth::challenge();
+        let mut pending = self.pending.lock().expect("lock");
+        pending.retain(|_, (requested, _)| now.duration_since(*requested) < self.timeout);
+        pending.insert(token.clone(), (now, operation));
+        token
+    }
+
+    /// Take the operation of `token`, None if unknown, expired, or taken already
+    pub fn confirm(&self, token: &str, now: Instant) -> Option<T> {
+        let (requested, operation) = self.pending.lock().expect("lock").remove(token)?;
+        (now.duration_since(requested) < self.timeout).then_some(operation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn confirmations_are_one_time() {
+        let confirmations = Confirmations::new(Duration::from_secs(30));
+        let now = Instant::now();
+        let token = confirmations.request(1, now);
+        assert_eq!(confirmations.confirm("unknown", now), None);
+        assert_eq!(confirmations.confirm(&token, now), Some(1));
+        assert_eq!(confirmations.confirm(&token, now), None);
+
+        let token = confirmations.request(2, now);
+        assert_eq!(
+            confirmations.confirm(&token, now + Duration::from_secs(30)),
+            None
+        );
+    }
+}
@@ -1,11 +1,17 @@
 use std::{
     borrow::Cow,
-    collections::HashSet,
+    collections::{HashMap, HashSet},
+    path::PathBuf,
     str::FromStr,
-    sync::Arc,
-    time::{Duration, SystemTime},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, RwLock,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
+use anchor_lang::AccountDeserialize;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use drift_rs::{
     constants::ProgramData,
     drift_idl::{self, types::MarginRequirementType},
@@ -18,39 +24,258 @@ use drift_rs::{
             calculate_margin_requirements,
         },
     },
-    priority_fee_subscriber::{PriorityFeeSubscriber, PriorityFeeSubscriberConfig},
     types::{
-        self, accounts::SpotMarket, MarketId, MarketType, ModifyOrderParams, OrderStatus,
+        self,
+        accounts::{PerpMarket, SpotMarket, User},
+        MarketId, MarketType, ModifyOrderParams, OrderParams, OrderStatus, PositionDirection,
         RpcSendTransactionConfig, SdkError, SdkResult, VersionedMessage,
     },
     utils::get_http_url,
     DriftClient, Pubkey, TransactionBuilder, Wallet,
 };
-use futures_util::{stream::FuturesUnordered, StreamExt};
+use futures_util::{stream::FuturesUnordered, FutureExt, StreamExt};
 use log::{debug, info, warn};
 use rust_decimal::Decimal;
 use solana_client::{client_error::ClientErrorKind, rpc_config::RpcTransactionConfig};
-use solana_sdk::signature::Signature;
-use solana_transaction_status::{option_serializer::OptionSerializer, UiTransactionEncoding};
+use solana_sdk::{
+    compute_budget::ComputeBudgetInstruction, hash::Hash, message::Message, signature::Signature,
+    transaction::VersionedTransaction,
+};
+use solana_transaction_status::{
+    option_serializer::OptionSerializer, TransactionStatus, UiTransactionEncoding,
+};
 use thiserror::Error;
 
 use crate::{
+    alerts::{Alert, Alerter},
+    chain_clock::ChainClock,
+    chain_slot::ChainSlot,
+    confirm::Confirmations,
+    dca::{Dca, DcaPlan},
+    dlob::{l2_levels, Dlob},
+    events::{EventJournal, JournalEntry},
+    execution::{execution_report, ExecutedFill, MAX_MARKOUT_DELAY},
+    fee_spend::{FeeSpendTracker, TxFees},
+    fills::{FillStore, FillsQuery},
+    guards::{
+        crossing_price, is_post_only, market_status_guard, oracle_peg, reduce_only_guard,
+        reprice_post_only, slippage_guard, ComplianceRules, CrossCheck, DailyLoss, DuplicateOrders,
+        KillSwitch, OrderFingerprint, RiskGroup, TradingWindow,
+    },
+    ha::Leadership,
+    health::{health_score, largest_risk, liquidation_distance},
+    history::{EquityHistory, EquitySnapshot, OracleHistory, OracleSample},
+    insurance::SocialLosses,
+    jito::{BundleStatus, Jito},
+    layout::{check_market_layout, IncompatibleMarkets},
+    log_limit::set_gateway_level,
+    market_data::{MarketDataSource, Polling, PubSub, MAX_MULTIPLE_ACCOUNTS},
+    nonce::{NonceLease, NoncePool},
+    oracle::{divergence, Hermes, SecondaryOracle},
+    order_status::{OrderStatus, PendingOrder},
+    priority_fees::PriorityFees,
+    reconcile::{reconcile_orders, reconcile_positions},
+    request_id::{inherit, RequestId},
+    rpc::{rate_limited_rpc_client, rpc_client, send_encoded_tx, EncodedTx, RpcHttpConfig},
+    scheduler::{Activation, Scheduler},
+    settings::RuntimeSettings,
+    signer::{LocalSigner, RemoteSigner, Signer},
+    slot_tag,
+    status::{market_subject, GatewayStatus, StatusCondition},
+    sub_accounts::{
+        encode_name, initialize_user_ixs, transfer_deposit_ix, user_stats_account, SubAccountNames,
+    },
+    subscriptions::{subscription_id, SubscriptionId, Subscriptions},
+    templates::{TemplateKey, TxTemplates},
+    throttle::OrderThrottle,
+    triggers::{Metric, Trigger, TriggerAction, Triggers},
+    twap::{Twap, TwapAlgo},
+    tx_status::{TxState, TxStatus, TxTracker},
     types::{
-        get_market_decimals, AllMarketsResponse, CancelAndPlaceRequest, CancelOrdersRequest,
-        GatewayWallet, GetOrdersRequest, GetOrdersResponse, GetPositionsRequest,
-        GetPositionsResponse, Market, MarketInfoResponse, ModifyOrdersRequest, Order, PerpPosition,
-        PerpPositionExtended, PlaceOrdersRequest, SolBalanceResponse, SpotPosition,
-        TxEventsResponse, TxResponse, UserCollateralResponse, UserLeverageResponse,
-        UserMarginResponse, WalletMode, PRICE_DECIMALS,
+        get_market_decimals, AllMarketsResponse, BenchRequest, BenchResponse,
+        CancelAndPlaceRequest, CancelOrdersRequest, Collateral, ConfirmRequest,
+        CreateSubAccountRequest, CreateSubAccountResponse, DcaPlansResponse, DcaRequest,
+        EquityHistoryRequest, EquityHistoryResponse, ExecutionReportRequest,
+        ExecutionReportResponse, FeeSpendRequest, FeeSpendResponse, FillsRequest, FillsResponse,
+        FundingRateResponse, GatewayWallet, GetOrdersRequest, GetOrdersResponse,
+        GetPositionsRequest, GetPositionsResponse, InsuranceResponse, LatencyStats, Leverage,
+        MarginInfo, MarginSnapshot, Market, MarketInfoResponse, MarketMarginResponse,
+        MarketOperations, ModifyOrdersRequest, OracleInfo, OraclePrice, Order, OrderBreach,
+        OrderbookRequest, OrderbookResponse, OrdersWhatIfResponse, PeggedOrder, PerpPosition,
+        PerpPositionExtended, PlaceOrder, PlaceOrdersRequest, PositionHealth, ReadinessResponse,
+        ReconcileRequest, ReconcileResponse, RepricedOrder, RpcStatsResponse,
+        ScheduleOrdersRequest, ScheduleOrdersResponse, ScheduledOrders, ScheduledOrdersResponse,
+        SnapshotResponse, SolBalanceResponse, SpotPosition, SubAccountInfo, SubAccountsResponse,
+        SubscriptionRequest, SubscriptionsResponse, TransferRequest, TransferResponse,
+        TriggerRequest, TriggersResponse, TwapAlgosResponse, TwapRequest, TxEventsResponse,
+        TxResponse, UsageRequest, UsageResponse, UserHealthResponse, WalletMode, WebhookResponse,
+        PRICE_DECIMALS, QUOTE_DECIMALS,
+    },
+    usage::{current_api_key, usage_authority, UsageTracker},
+    user_order_ids::UserOrderIds,
+    webhooks::{HeliusWebhook, WebhookTx},
+    websocket::{
+        camel_case, map_drift_event_for_account, AccountEvent, Channel, FUNDING_RATE_DECIMALS,
     },
-    websocket::map_drift_event_for_account,
-    Context, LOG_TARGET,
+    whatif::{apply_perp_fill, fill_price},
+    Context, AUDIT_LOG_TARGET, LOG_TARGET,
 };
 
 /// Default TTL in seconds of gateway tx retry
 /// afterwhich gateway will no longer resubmit or monitor the tx
 // ~10 slots
 const DEFAULT_TX_TTL: u16 = 4;
+/// Default resend timeout of durable nonce txs in seconds
+const DEFAULT_NONCE_TX_TTL: u16 = 30;
+/// Time to keep polling a confirmed tx for finalization
+const FINALIZE_TIMEOUT: Duration = Duration::from_secs(60);
+/// Max. time to poll a tx past its TTL until its blockhash expires
+const BLOCKHASH_EXPIRY_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Upper bound on the wait time of an events long poll
+const MAX_POLL_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Upper bound on txs sent by a single benchmark run
+const MAX_BENCH_ITERATIONS: u32 = 50;
+
+/// Time to wait for a benchmark tx to confirm
+const BENCH_CONFIRM_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Interval between margin health checks
+const MARGIN_HEALTH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// `User.status` flag set while the account is being liquidated
+const USER_STATUS_BEING_LIQUIDATED: u8 = 0b1;
+
+/// Interval between equity history snapshots
+const EQUITY_SNAPSHOT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Interval between oracle price samples of subscribed markets
+const ORACLE_SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Interval between daily loss checks
+const DAILY_LOSS_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Interval between removals of stored events past their retention
+const FILLS_PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Interval between trading window checks
+const TRADING_WINDOW_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Interval between social loss checks of perp markets
+const SOCIAL_LOSS_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Interval between writes of API usage rollups
+const USAGE_FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Interval between oracle cross-checks
+const ORACLE_CROSS_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+/// Max. secs since a secondary price was published to cross-check against it
+const MAX_SECONDARY_PRICE_AGE: i64 = 60;
+/// Max. slots an oracle price may lag the chain to cross-check it (~10s)
+const MAX_ORACLE_SLOT_LAG: u64 = 25;
+
+/// Interval between checks for due scheduled orders
+const SCHEDULER_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Min. interval between scheduler slot fetches via RPC, while the slot subscription is down
+const SCHEDULER_SLOT_FETCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Interval between tx status checks of a created sub-account
+const SUB_ACCOUNT_CONFIRM_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Max. wait for the tx creating a sub-account to confirm, about the blockhash lifetime
+const SUB_ACCOUNT_CONFIRM_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// Price levels per side of orderbook snapshots unless requested
+const DEFAULT_ORDERBOOK_DEPTH: usize = 20;
+
+/// Interval between checks for due DCA orders
+const DCA_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Interval between checks for due TWAP child orders
+const TWAP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Interval between trigger condition evaluations
+const TRIGGER_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Events returned by a fills query unless requested
+const DEFAULT_FILLS_LIMIT: usize = 100;
+
+/// Secs after a fill to mark it out against the oracle unless requested
+const DEFAULT_MARKOUT_HORIZON: u64 = 60;
+
+/// Max. wait for the blockhash in a readiness check
+const READINESS_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Max. slots a cached user account may lag the chain before it is checked via RPC (~10s)
+const MAX_ACCOUNT_SLOT_LAG: u64 = 25;
+
+/// Interval between subscription health samples
+const SUBSCRIPTION_HEALTH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Interval between RPC and market status checks of the Ws status channel
+const STATUS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// RPC slower to respond is reported as degraded
+const RPC_DEGRADED_LATENCY: Duration = Duration::from_secs(2);
+
+/// Upper bounds on the RPC dependent stages of a request
+#[derive(Clone, Copy, Debug)]
+pub struct Timeouts {
+    /// user account and balance fetches
+    pub account: Duration,
+    /// tx preflight simulation and submission to the primary RPC
+    pub send: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            account: Duration::from_secs(10),
+            send: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Run `fut`, failing with [`ControllerError::Timeout`] if `stage` takes longer than `timeout`
+async fn deadline<T>(
+    stage: &'static str,
+    timeout: Duration,
+    fut: impl std::future::Future<Output = GatewayResult<T>>,
+) -> GatewayResult<T> {
+    tokio::time::timeout(timeout, fut)
+        .await
+        .unwrap_or_else(|_| Err(ControllerError::Timeout(stage)))
+}
+
+/// Run `fut` like [`deadline`], failing with [`ControllerError::DeadlineExceeded`] if the request's
+/// `tx_deadline` passes first
+async fn within_tx_deadline<T>(
+    stage: &'static str,
+    timeout: Duration,
+    tx_deadline: Option<Instant>,
+    fut: impl std::future::Future<Output = GatewayResult<T>>,
+) -> GatewayResult<T> {
+    match tx_deadline {
+        Some(tx_deadline) if tx_deadline < Instant::now() + timeout => {
+            tokio::time::timeout_at(tx_deadline.into(), fut)
+                .await
+                .unwrap_or_else(|_| Err(ControllerError::DeadlineExceeded(stage)))
+        }
+        _ => deadline(stage, timeout, fut).await,
+    }
+}
+
+/// Return an error if the request's `tx_deadline` has passed by `stage`
+fn ensure_tx_deadline(stage: &'static str, tx_deadline: Option<Instant>) -> GatewayResult<()> {
+    match tx_deadline {
+        Some(tx_deadline) if tx_deadline <= Instant::now() => {
+            Err(ControllerError::DeadlineExceeded(stage))
+        }
+        _ => Ok(()),
+    }
+}
 
 pub type GatewayResult<T> = Result<T, ControllerError>;
 
@@ -64,21 +289,131 @@ pub enum ControllerError {
     TxFailed { reason: String, code: u32 },
     #[error("tx not found: {tx_sig}")]
     TxNotFound { tx_sig: String },
+    #[error("gateway is in standby mode")]
+    Standby,
+    #[error("trading halted: {0}")]
+    Halted(String),
+    #[error("{0}")]
+    RateLimited(String),
+    #[error("{0}")]
+    MarketPaused(String),
+    #[error("timed out: {0}")]
+    Timeout(&'static str),
+    #[error("deadline exceeded: {0}")]
+    DeadlineExceeded(&'static str),
+    #[error("bundle failed: {0}")]
+    Bundle(String),
+    #[error("signing failed: {0}")]
+    Signer(String),
 }
 
 #[derive(Clone)]
 pub struct AppState {
     pub wallet: Arc<GatewayWallet>,
     pub client: Arc<DriftClient>,
+    /// source of subscribed market and oracle accounts
+    market_data: Arc<dyn MarketDataSource>,
+    /// polls user accounts in polling-only mode, otherwise they're Ws subscribed
+    polled_accounts: Option<Arc<Polling>>,
+    /// signs txs, with the wallet key unless remote
+    tx_signer: Arc<dyn Signer>,
     /// Solana tx commitment level for preflight confirmation
     tx_commitment: CommitmentConfig,
     /// default sub_account_id to use if not provided
     default_subaccount_id: u16,
+    /// sub-accounts subscribed at startup or created since, the default first
+    sub_account_ids: Arc<RwLock<Vec<u16>>>,
+    /// true once events of the subscribed sub-accounts are journaled
+    events_subscribed: Arc<AtomicBool>,
     /// skip tx preflight on send or not (default: false)
     skip_tx_preflight: bool,
-    priority_fee_subscriber: Arc<PriorityFeeSubscriber>,
-    /// list of additional RPC endpoints for tx broadcast
-    extra_rpcs: Vec<Arc<RpcClient>>,
+    /// priority fees sampled per market
+    priority_fees: Arc<PriorityFees>,
+    /// RPC for tx submission and confirmation, separate from queries if configured
+    tx_rpc: Arc<RpcClient>,
+    /// list of additional RPC endpoints for tx broadcast, reloadable
+    extra_rpcs: Arc<RwLock<Vec<Arc<RpcClient>>>>,
+    /// active/standby leadership, only the leader may send txs
+    leadership: Arc<Leadership>,
+    /// prebuilt place orders txs
+    tx_templates: Arc<TxTemplates>,
+    /// recent events of subscribed sub-accounts
+    events: Arc<EventJournal>,
+    /// authenticates Helius webhooks, ingestion is disabled if unset
+    helius_webhook: Option<Arc<HeliusWebhook>>,
+    /// outbound alerts
+    alerter: Arc<Alerter>,
+    /// sampled equity of sub-accounts
+    equity_history: Arc<EquityHistory>,
+    /// sampled oracle prices of subscribed markets, to mark out fills against
+    oracle_history: Arc<OracleHistory>,
+    /// on-chain sub-account names
+    pub sub_account_names: Arc<SubAccountNames>,
+    /// sub-account names of additional delegator authorities
+    delegator_sub_accounts: Arc<HashMap<Pubkey, SubAccountNames>>,
+    /// aggregate exposure limits checked at order time
+    risk_groups: Arc<Vec<RiskGroup>>,
+    /// instrument restrictions by authority checked at order time
+    compliance_rules: Arc<ComplianceRules>,
+    /// halts order placement when a risk limit is breached
+    kill_switch: Arc<KillSwitch>,
+    /// allowed trading hours by sub-account id
+    trading_windows: Arc<HashMap<u16, TradingWindow>>,
+    /// per market order rate limits
+    order_throttle: Option<Arc<OrderThrottle>>,
+    /// recently placed orders, for duplicate detection
+    duplicate_orders: Option<Arc<DuplicateOrders>>,
+    /// transfers awaiting confirmation, if transfers require it
+    transfer_confirmations: Option<Arc<Confirmations<(Context, TransferRequest)>>>,
+    /// markets with account layouts incompatible with this build
+    pub incompatible_markets: Arc<IncompatibleMarkets>,
+    /// max. orders per request, unlimited if None
+    max_orders: Option<usize>,
+    /// handling of post-only orders crossing the DLOB, unchecked if None
+    cross_check: Option<CrossCheck>,
+    /// socialized losses observed by perp market
+    social_losses: Arc<SocialLosses>,
+    /// orders to place at a future time or slot
+    scheduled_orders: Arc<Scheduler<(Context, Vec<PlaceOrder>)>>,
+    /// recurring orders
+    dca: Arc<Dca>,
+    /// TWAP execution algos
+    twap: Arc<Twap>,
+    /// conditional order triggers
+    triggers: Arc<Triggers>,
+    /// pooled HTTP client shared by RPC clients
+    rpc_http: reqwest::Client,
+    timeouts: Timeouts,
+    /// resting orders of all users for orderbook snapshots
+    pub dlob: Arc<Dlob>,
+    /// active market, oracle, and account subscriptions
+    pub subscriptions: Arc<Subscriptions>,
+    /// latest chain slot, to check the lag of cached accounts
+    chain_slot: Arc<ChainSlot>,
+    /// chain time, to check times against as the program does
+    pub chain_clock: Arc<ChainClock>,
+    /// cached user accounts found current via RPC, by the cached slot and the slot of the check
+    verified_accounts: Arc<RwLock<HashMap<Pubkey, (u64, u64)>>>,
+    /// conditions streamed on the Ws status channel
+    pub status: Arc<GatewayStatus>,
+    /// persisted fill and order events, if enabled
+    fills: Option<Arc<FillStore>>,
+    /// submits txs as Jito bundles instead of via RPC, if set
+    jito: Option<Arc<Jito>>,
+    /// signs txs against durable nonces instead of recent blockhashes, if set
+    nonces: Option<Arc<NoncePool>>,
+    /// confirmation status of sent txs
+    txs: Arc<TxTracker>,
+    /// API usage by authority
+    pub usage: Arc<UsageTracker>,
+    /// tx fee spend by sub-account
+    fee_spend: Arc<FeeSpendTracker>,
+    /// user order ids of orders placed by sessions and algos
+    pub user_order_ids: Arc<UserOrderIds>,
+    /// runtime settings reloaded on SIGHUP, if set
+    settings_file: Option<PathBuf>,
+    /// markets subscribed by runtime settings, unsubscribed once left out. held while reloading
+    settings_markets: Arc<tokio::sync::Mutex<Vec<MarketId>>>,
 }
 
 impl AppState {
@@ -88,15 +423,60 @@ impl AppState {
     }
     /// Configured drift signing address
     pub fn signer(&self) -> Pubkey {
-        self.wallet.inner().signer()
+        self.tx_signer.pubkey()
     }
     pub fn default_sub_account(&self) -> Pubkey {
         self.wallet.inner().sub_account(self.default_subaccount_id)
     }
-    pub fn resolve_sub_account(&self, sub_account_id: Option<u16>) -> Pubkey {
+    /// Sub-accounts subscribed at startup or created since, the default first, their events are journaled
+    pub fn journaled_sub_accounts(&self) -> Vec<u16> {
+        self.sub_account_ids.read().expect("lock").clone()
+    }
+    /// Resolve the wallet acting for the authority of a request, the default authority if unset
+    pub fn resolve_wallet(&self, ctx: &Context) -> GatewayResult<&Wallet> {
+        let authority = ctx
+            .authority
+            .as_deref()
+            .map(|a| {
+                Pubkey::from_str(a)
+                    .map_err(|_| ControllerError::BadRequest(format!("invalid authority: {a}")))
+            })
+            .transpose()?;
         self.wallet
-            .inner()
-            .sub_account(sub_account_id.unwrap_or(self.default_subaccount_id))
+            .for_authority(authority.as_ref())
+            .ok_or_else(|| {
+                ControllerError::BadRequest(format!(
+                    "unknown authority: {}",
+                    ctx.authority.as_deref().unwrap_or_default()
+                ))
+            })
+    }
+    /// Sub-account names of `authority`
+    fn sub_account_names_of(&self, authority: &Pubkey) -> &SubAccountNames {
+        self.delegator_sub_accounts
+            .get(authority)
+            .unwrap_or(&self.sub_account_names)
+    }
+    /// Resolve the sub-account id of a request, by name if given
+    pub fn resolve_sub_account_id(&self, ctx: &Context) -> GatewayResult<u16> {
+        let sub_account_names = self.sub_account_names_of(self.resolve_wallet(ctx)?.authority());
+        match ctx.sub_account {
+            Some(ref name) => sub_account_names
+                .id(name)
+                .ok_or_else(|| ControllerError::BadRequest(format!("unknown sub-account: {name}"))),
+            None => match ctx.sub_account_id {
+                Some(id) if !sub_account_names.contains(id) => Err(ControllerError::BadRequest(
+                    format!("unknown sub-account id: {id}"),
+                )),
+                Some(id) => Ok(id),
+                None => Ok(self.default_subaccount_id),
+            },
+        }
+    }
+    pub fn resolve_sub_account(&self, ctx: &Context) -> GatewayResult<Pubkey> {
+        let wallet = self.resolve_wallet(ctx)?;
+        self.resolve_sub_account_id(ctx)
+            .map(|id| wallet.sub_account(id))
     }
 
     /// Initialize Gateway Drift client
@@ -116,312 +496,2679 @@ impl AppState {
         default_subaccount_id: Option<u16>,
         skip_tx_preflight: bool,
         extra_rpcs: Vec<&str>,
+        rpc_http: Option<RpcHttpConfig>,
     ) -> Self {
         let (state_commitment, tx_commitment) =
             commitment.unwrap_or((CommitmentConfig::confirmed(), CommitmentConfig::confirmed()));
+        let rpc_http_config = rpc_http.unwrap_or_default();
+        let rpc_http = rpc_http_config.http_client();
         let context = if devnet {
             types::Context::DevNet
         } else {
             types::Context::MainNet
         };
 
-        let rpc_client = RpcClient::new_with_commitment(endpoint.into(), state_commitment);
+        let rpc_client = match rpc_http_config.rate_limit {
+            Some(rate) => {
+                rate_limited_rpc_client(&rpc_http, endpoint.into(), state_commitment, rate)
+            }
+            None => rpc_client(&rpc_http, endpoint.into(), state_commitment),
+        };
         let client = DriftClient::new(context, rpc_client, wallet.inner().clone())
             .await
             .expect("ok");
 
-        let default_subaccount = wallet
-            .inner()
-            .sub_account(default_subaccount_id.unwrap_or(0));
-        if let Err(err) = client.subscribe_account(&default_subaccount).await {
-            log::error!(target: LOG_TARGET, "couldn't subscribe to user updates: {err:?}");
-        } else {
-            log::info!(target: LOG_TARGET, "subscribed to subaccount: {default_subaccount}");
-        }
-
-        let priority_fee_subscriber = PriorityFeeSubscriber::with_config(
-            RpcClient::new_with_commitment(endpoint.into(), state_commitment),
-            &[client
-                .program_data()
-                .perp_market_config_by_index(0)
-                .expect("market exists")
-                .pubkey],
-            PriorityFeeSubscriberConfig {
-                refresh_frequency: Some(Duration::from_millis(400 * 10)),
-                window: None,
-            },
-        );
+        let subscriptions = Subscriptions::default();
 
-        let priority_fee_subscriber = if wallet.is_emulating() {
-            Arc::new(priority_fee_subscriber)
-        } else {
+        let priority_fees = PriorityFees::new(
+            rpc_http.clone(),
+            endpoint,
+            state_commitment,
+            client.program_data(),
+            wallet.is_emulating(),
+        );
+        if !wallet.is_emulating() {
             client
                 .subscribe_blockhashes()
                 .await
                 .expect("blockhashes subscribed");
-            priority_fee_subscriber.subscribe()
-        };
+        }
 
+        let client = Arc::new(client);
         Self {
-            client: Arc::new(client),
+            chain_slot: ChainSlot::start(client.ws()),
+            chain_clock: ChainClock::start(client.rpc()),
+            verified_accounts: Arc::default(),
+            tx_rpc: client.rpc(),
+            market_data: Arc::new(PubSub::new(Arc::clone(&client))),
+            polled_accounts: None,
+            tx_signer: Arc::new(LocalSigner::new(wallet.inner().clone())),
+            client,
             tx_commitment,
             default_subaccount_id: default_subaccount_id.unwrap_or(0),
+            sub_account_ids: Arc::new(RwLock::new(vec![default_subaccount_id.unwrap_or(0)])),
+            events_subscribed: Arc::default(),
             skip_tx_preflight,
-            priority_fee_subscriber,
+            priority_fees: Arc::new(priority_fees),
             wallet: Arc::new(wallet),
-            extra_rpcs: extra_rpcs
-                .into_iter()
-                .map(|u| Arc::new(RpcClient::new(get_http_url(u).expect("valid RPC url"))))
-                .collect(),
+            extra_rpcs: Arc::new(RwLock::new(
+                extra_rpcs
+                    .into_iter()
+                    .map(|u| {
+                        Arc::new(rpc_client(
+                            &rpc_http,
+                            get_http_url(u).expect("valid RPC url"),
+                            CommitmentConfig::default(),
+                        ))
+                    })
+                    .collect(),
+            )),
+            leadership: Leadership::standalone(),
+            tx_templates: Arc::default(),
+            events: Arc::default(),
+            helius_webhook: None,
+            alerter: Arc::default(),
+            equity_history: Arc::default(),
+            oracle_history: Arc::default(),
+            sub_account_names: Arc::default(),
+            delegator_sub_accounts: Arc::new(
+                wallet
+                    .delegators()
+                    .map(|authority| (*authority, SubAccountNames::default()))
+                    .collect(),
+            ),
+            risk_groups: Arc::default(),
+            compliance_rules: Arc::default(),
+            kill_switch: Arc::default(),
+            trading_windows: Arc::default(),
+            order_throttle: None,
+            duplicate_orders: None,
+            transfer_confirmations: None,
+            incompatible_markets: Arc::default(),
+            max_orders: None,
+            cross_check: None,
+            social_losses: Arc::default(),
+            scheduled_orders: Arc::default(),
+            dca: Arc::default(),
+            twap: Arc::default(),
+            triggers: Arc::default(),
+            timeouts: Timeouts::default(),
+            rpc_http,
+            dlob: Arc::default(),
+            subscriptions: Arc::new(subscriptions),
+            status: Arc::default(),
+            fills: None,
+            jito: None,
+            nonces: None,
+            txs: Arc::default(),
+            usage: Arc::default(),
+            fee_spend: Arc::default(),
+            user_order_ids: Arc::default(),
+            settings_file: None,
+            settings_markets: Arc::default(),
         }
     }
 
-    /// Start market and oracle data subscriptions
-    ///
-    /// * configured_markets - list of static markets provided by user
-    ///
-    /// additional subscriptions will be included based on user's current positions (on default sub-account)
-    pub(crate) async fn subscribe_market_data(&self, configured_markets: &[MarketId]) {
-        let (spot, perps) = self
-            .client
-            .all_positions(&self.default_sub_account())
-            .await
-            .expect("loaded user positions");
+    /// Reject orders identical to one placed within `window`
+    pub fn with_duplicate_order_window(mut self, window: Duration) -> Self {
+        self.duplicate_orders = Some(Arc::new(DuplicateOrders::new(window)));
+        self
+    }
 
-        let mut user_markets: Vec<MarketId> = spot
-            .iter()
-            .map(|s| MarketId::spot(s.market_index))
-            .chain(perps.iter().map(|p| MarketId::perp(p.market_index)))
-            .collect();
-        user_markets.push(MarketId::QUOTE_SPOT); // usdc needed for most functions
-        user_markets.extend_from_slice(configured_markets);
+    /// Hold transfers until confirmed within `timeout`
+    pub fn with_transfer_confirmation(mut self, timeout: Duration) -> Self {
+        self.transfer_confirmations = Some(Arc::new(Confirmations::new(timeout)));
+        self
+    }
 
-        let init_rpc_throttle: u64 = std::env::var("INIT_RPC_THROTTLE")
-            .map(|s| s.parse().unwrap())
-            .unwrap_or(1);
+    /// Limit the rate of order messages per market
+    pub fn with_order_throttle(mut self, order_throttle: OrderThrottle) -> Self {
+        self.order_throttle = Some(Arc::new(order_throttle));
+        self
+    }
 
-        let markets = Vec::from_iter(HashSet::<MarketId>::from_iter(user_markets).into_iter());
-        info!(target: LOG_TARGET, "start market subscriptions: {markets:?}");
-        tokio::time::sleep(Duration::from_secs(init_rpc_throttle)).await;
-        self.client
-            .subscribe_oracles(&markets)
-            .await
-            .expect("oracles subscribed");
-        tokio::time::sleep(Duration::from_secs(init_rpc_throttle)).await;
-        self.client
-            .subscribe_markets(&markets)
-            .await
-            .expect("markets subscribed");
+    /// Refuse requests of more than `max_orders` orders, unlimited if None
+    pub fn with_max_orders(mut self, max_orders: Option<usize>) -> Self {
+        self.max_orders = max_orders;
+        self
     }
 
-    /// Return SOL balance of the tx signing account
-    pub async fn get_sol_balance(&self) -> GatewayResult<SolBalanceResponse> {
-        let balance = self
-            .client
-            .rpc()
-            .get_balance(&self.wallet.inner().signer())
-            .await
-            .map_err(|err| ControllerError::Sdk(err.into()))?;
-        Ok(SolBalanceResponse {
-            balance: Decimal::new(balance as i64, BASE_PRECISION.ilog10()).normalize(),
-        })
+    /// Check post-only orders against resting orders of the DLOB before sending
+    pub fn with_cross_check(mut self, cross_check: CrossCheck) -> Self {
+        self.cross_check = Some(cross_check);
+        self
     }
 
-    /// Cancel orders
-    ///
-    /// There are 4 intended scenarios for cancellation, in order of priority:
-    /// 1) "market" is set, cancel all orders in the market
-    /// 2) "user ids" are set, cancel all orders by user assigned id
-    /// 3) ids are given, cancel all orders by id (global, exchange assigned id)
-    /// 4) catch all. cancel all orders
-    pub async fn cancel_orders(
-        &self,
-        ctx: Context,
-        req: CancelOrdersRequest,
-    ) -> GatewayResult<TxResponse> {
-        let sub_account = self.resolve_sub_account(ctx.sub_account_id);
-        let account_data = self.client.get_user_account(&sub_account).await?;
-        let pf = self.get_priority_fee();
+    /// Subscribe `sub_account_ids` at startup in addition to the default sub-account
+    pub fn with_sub_accounts(mut self, sub_account_ids: &[u16]) -> Self {
+        let mut ids = self.sub_account_ids.write().expect("lock");
+        for id in sub_account_ids {
+            if !ids.contains(id) {
+                ids.push(*id);
+            }
+        }
+        drop(ids);
+        self
+    }
 
-        let priority_fee = ctx.cu_price.unwrap_or(pf);
-        debug!(target: LOG_TARGET, "priority_fee: {priority_fee:?}");
-        let builder = TransactionBuilder::new(
-            self.client.program_data(),
-            sub_account,
-            Cow::Owned(account_data),
-            self.wallet.is_delegated(),
-        )
-        .with_priority_fee(priority_fee, ctx.cu_limit);
-        let tx = build_cancel_ix(builder, req)?.build();
-        self.send_tx(tx, "cancel_orders", ctx.ttl).await
+    /// Reject order placement for sub-accounts outside of their trading window
+    pub fn with_trading_windows(mut self, trading_windows: HashMap<u16, TradingWindow>) -> Self {
+        self.trading_windows = Arc::new(trading_windows);
+        self
     }
 
-    /// Return position for market if given, otherwise return all positions
-    pub async fn get_positions(
-        &self,
-        ctx: Context,
-        req: Option<GetPositionsRequest>,
-    ) -> GatewayResult<GetPositionsResponse> {
-        let (all_spot, all_perp) = self
-            .client
-            .all_positions(&self.resolve_sub_account(ctx.sub_account_id))
-            .await?;
+    /// Enforce combined notional caps across groups of correlated markets
+    pub fn with_risk_groups(mut self, risk_groups: Vec<RiskGroup>) -> Self {
+        self.risk_groups = Arc::new(risk_groups);
+        self
+    }
 
-        // calculating spot token balance requires knowing the 'spot market account' data
-        let filtered_spot_positions: Vec<&drift_idl::types::SpotPosition> = all_spot
-            .iter()
-            .filter(|p| {
-                if let Some(GetPositionsRequest { ref market }) = req {
-                    p.market_index == market.market_index && MarketType::Spot == market.market_type
-                } else {
-                    true
-                }
-            })
-            .collect();
+    /// Enforce instrument restrictions on the orders of authorities
+    pub fn with_compliance_rules(mut self, compliance_rules: ComplianceRules) -> Self {
+        self.compliance_rules = Arc::new(compliance_rules);
+        self
+    }
 
-        let spot_market_futs = filtered_spot_positions
-            .iter()
-            .map(|x| self.client.get_spot_market_account(x.market_index));
+    /// Send alerts on critical conditions to the configured sinks
+    pub fn with_alerter(mut self, alerter: Alerter) -> Self {
+        self.alerter = Arc::new(alerter);
+        self
+    }
 
-        let spot_market_futs = FuturesUnordered::from_iter(spot_market_futs);
-        let spot_markets = spot_market_futs
-            .collect::<Vec<SdkResult<SpotMarket>>>()
-            .await;
+    /// Run with active/standby leader election, trading calls are rejected while on standby
+    pub fn with_leadership(mut self, leadership: Arc<Leadership>) -> Self {
+        self.leadership = leadership;
+        self
+    }
 
-        let filtered_spot_positions = filtered_spot_positions
-            .iter()
-            .zip(spot_markets.iter())
-            .map(|(position, market)| {
-                SpotPosition::from_sdk_type(position, market.as_ref().expect("spot market"))
-            })
-            .collect();
+    /// Run DCA plans loaded from (and persisted to) a state file
+    pub fn with_dca(mut self, dca: Dca) -> Self {
+        self.dca = Arc::new(dca);
+        self
+    }
 
-        Ok(GetPositionsResponse {
-            spot: filtered_spot_positions,
-            perp: all_perp
-                .into_iter()
-                .filter(|p| {
-                    if let Some(GetPositionsRequest { ref market }) = req {
-                        p.market_index == market.market_index
-                            && MarketType::Perp == market.market_type
-                    } else {
-                        true
-                    }
-                })
-                .map(Into::into)
-                .collect(),
-        })
+    /// Persist journaled fill and order events to `store`
+    pub fn with_fill_store(mut self, store: FillStore) -> Self {
+        let store = Arc::new(store);
+        self.events = Arc::new(EventJournal::with_store(Arc::clone(&store)));
+        self.usage = Arc::new(UsageTracker::with_store(
+            store.open_tree("usage").expect("usage tree"),
+        ));
+        self.fee_spend = Arc::new(FeeSpendTracker::with_store(
+            store.open_tree("fee_spend").expect("fee spend tree"),
+        ));
+        self.equity_history = Arc::new(EquityHistory::with_store(
+            store
+                .open_tree("equity_history")
+                .expect("equity history tree"),
+        ));
+        self.oracle_history = Arc::new(OracleHistory::with_store(
+            store
+                .open_tree("oracle_history")
+                .expect("oracle history tree"),
+        ));
+        self.fills = Some(store);
+        self
     }
 
-    pub async fn get_margin_info(&self, ctx: Context) -> GatewayResult<UserMarginResponse> {
-        let sub_account = self.resolve_sub_account(ctx.sub_account_id);
-        calculate_margin_requirements(
-            &self.client,
-            &self.client.get_user_account(&sub_account).await?,
-        )
-        .map(Into::into)
-        .map_err(ControllerError::Sdk)
+    /// Submit txs as Jito bundles with a tip, rather than via RPC
+    pub fn with_jito(mut self, jito: Jito) -> Self {
+        self.jito = Some(Arc::new(jito));
+        self
     }
 
-    pub async fn get_leverage(&self, ctx: Context) -> GatewayResult<UserLeverageResponse> {
-        let sub_account = self.resolve_sub_account(ctx.sub_account_id);
-        get_leverage(
-            &self.client,
-            &self.client.get_user_account(&sub_account).await?,
-        )
-        .map(Into::into)
-        .map_err(ControllerError::Sdk)
+    /// Sign txs against the durable nonces of `accounts`, their nonce authority must be the signer
+    ///
+    /// each in-flight tx holds one nonce account
+    pub fn with_nonce_accounts(mut self, accounts: Vec<Pubkey>) -> Self {
+        self.nonces = Some(Arc::new(NoncePool::new(accounts, self.signer())));
+        self
     }
 
-    pub async fn get_collateral(
-        &self,
-        ctx: Context,
-        margin_requirement_type: MarginRequirementType,
-    ) -> GatewayResult<UserCollateralResponse> {
-        let sub_account = self.resolve_sub_account(ctx.sub_account_id);
-        calculate_collateral(
-            &self.client,
-            &self.client.get_user_account(&sub_account).await?,
-            margin_requirement_type,
-        )
-        .map(Into::into)
-        .map_err(ControllerError::Sdk)
+    /// Reload runtime settings from the file at `path` on SIGHUP or request
+    pub fn with_settings_file(mut self, path: PathBuf) -> Self {
+        self.settings_file = Some(path);
+        self
     }
 
-    pub async fn get_position_extended(
-        &self,
-        ctx: Context,
-        market: Market,
-    ) -> GatewayResult<PerpPosition> {
-        let sub_account = self.resolve_sub_account(ctx.sub_account_id);
-        let user = self.client.get_user_account(&sub_account).await?;
+    /// Sign txs with the remote signing service at `url` holding the key of `pubkey`, instead of
+    /// the wallet key
+    pub fn with_remote_signer(mut self, url: &str, pubkey: Pubkey, token: Option<String>) -> Self {
+        self.tx_signer = Arc::new(RemoteSigner::new(
+            self.rpc_http.clone(),
+            url.to_string(),
+            pubkey,
+            token,
+        ));
+        self
+    }
 
-        let perp_position = user
-            .perp_positions
-            .iter()
-            .find(|p| p.market_index == market.market_index && !p.is_available());
+    /// Poll market and oracle accounts every `interval` instead of subscribing to them
+    pub fn with_polled_market_data(mut self, interval: Duration) -> Self {
+        self.market_data = Polling::start(Arc::clone(&self.client), interval);
+        self
+    }
 
-        if let Some(perp_position) = perp_position {
-            let calc = calculate_liquidation_price_and_unrealized_pnl(
-                &self.client,
-                &user,
-                market.market_index,
-            )
-            .await?;
-            let unsettled_pnl = Decimal::from_i128_with_scale(
-                perp_position
-                    .get_unrealized_pnl(calc.oracle_price)
-                    .unwrap_or_default(),
-                PRICE_DECIMALS,
-            );
+    /// Poll market, oracle, and user accounts every `interval`, subscribing to none via Ws
+    pub fn with_polling_only(mut self, interval: Duration) -> Self {
+        let polling = Polling::start(Arc::clone(&self.client), interval);
+        self.market_data = Arc::clone(&polling) as Arc<dyn MarketDataSource>;
+        self.polled_accounts = Some(polling);
+        self
+    }
 
-            let mut p: PerpPosition = (*perp_position).into();
-            p.set_extended_info(PerpPositionExtended {
-                liquidation_price: Decimal::new(calc.liquidation_price, PRICE_DECIMALS),
-                unrealized_pnl: Decimal::new(calc.unrealized_pnl as i64, PRICE_DECIMALS),
-                unsettled_pnl: unsettled_pnl.normalize(),
-                oracle_price: Decimal::new(calc.oracle_price, PRICE_DECIMALS),
-            });
+    /// Journal events of Helius webhooks carrying the auth header `auth`
+    pub fn with_helius_webhook(mut self, auth: String) -> Self {
+        self.helius_webhook = Some(Arc::new(HeliusWebhook::new(auth)));
+        self
+    }
 
-            Ok(p)
+    /// Return the fills store, None without one
+    pub fn fill_store(&self) -> Option<Arc<FillStore>> {
+        self.fills.clone()
+    }
+
+    /// Open the tree `name` of the fills store for other persisted state, None without a store
+    pub fn open_store_tree(&self, name: &str) -> Option<sled::Tree> {
+        self.fills
+            .as_ref()
+            .map(|store| store.open_tree(name).expect("store tree"))
+    }
+
+    /// Return the Helius webhook authenticator, None if ingestion is disabled
+    pub fn helius_webhook(&self) -> Option<&HeliusWebhook> {
+        self.helius_webhook.as_deref()
+    }
+
+    /// Bound RPC dependent request stages by `timeouts`
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// Use a dedicated RPC for tx submission and confirmation
+    ///
+    /// keeps latency critical sends from queueing behind queries on the primary RPC
+    ///
+    /// * `rate_limit` - max. requests per second of the tx lane, independent of the query limit
+    pub fn with_tx_rpc(mut self, endpoint: &str, rate_limit: Option<u32>) -> Self {
+        let url = get_http_url(endpoint).expect("valid RPC url");
+        self.tx_rpc = Arc::new(match rate_limit {
+            Some(rate) => rate_limited_rpc_client(&self.rpc_http, url, self.tx_commitment, rate),
+            None => rpc_client(&self.rpc_http, url, self.tx_commitment),
+        });
+        self
+    }
+
+    /// Return an error if this instance is not allowed to send txs
+    fn ensure_leader(&self) -> GatewayResult<()> {
+        if self.leadership.is_leader() {
+            Ok(())
         } else {
-            Err(ControllerError::BadRequest("no position".to_string()))
+            Err(ControllerError::Standby)
         }
     }
 
-    /// Return orders by market if given, otherwise return all orders
-    pub async fn get_orders(
+    /// Reject orders that would take a risk group's net notional over its cap
+    ///
+    /// notional of positions and orders is valued at the oracle price. Open orders count as if
+    /// filled on the side the new orders add to, so resting orders can't stack past the cap.
+    async fn check_exposure_limits(
         &self,
-        ctx: Context,
-        req: Option<GetOrdersRequest>,
-    ) -> GatewayResult<GetOrdersResponse> {
-        let sub_account = self.resolve_sub_account(ctx.sub_account_id);
-        let user = self.client.get_user_account(&sub_account).await?;
+        user: &User,
+        orders: &[OrderParams],
+    ) -> GatewayResult<()> {
+        for group in self.risk_groups.iter() {
+            let mut delta = Decimal::ZERO;
+            for order in orders {
+                let market = Market::new(order.market_index, order.market_type);
+                if !group.contains(market.as_market_id()) {
+                    continue;
+                }
+                let amount = Decimal::new(
+                    order.base_asset_amount as i64,
+                    get_market_decimals(self.client.program_data(), market),
+                );
+                let notional = amount * self.oracle_price(market.as_market_id()).await?;
+                match order.direction {
+                    PositionDirection::Long => delta += notional,
+                    PositionDirection::Short => delta -= notional,
+                }
+            }
+            if delta.is_zero() {
+                continue;
+            }
 
-        let orders: Vec<types::Order> = user
+            let mut current = Decimal::ZERO;
+            for market in group.markets.iter().copied() {
+                let amount = match market.kind() {
+                    MarketType::Perp => user
+                        .perp_positions
+                        .iter()
+                        .find(|p| p.market_index == market.index())
+                        .map(|p| Decimal::new(p.base_asset_amount, BASE_PRECISION.ilog10()))
+                        .unwrap_or_default(),
+                    MarketType::Spot => {
+                        match user
+                            .spot_positions
+                            .iter()
+                            .find(|p| !p.is_available() && p.market_index == market.index())
+                        {
+                            Some(position) => {
+                                let spot_market = self.spot_market_account(market.index()).await?;
+                                let amount = Decimal::from_i128_with_scale(
+                                    position.get_token_amount(&spot_market)? as i128,
+                                    spot_market.decimals,
+                                );
+                                if position.balance_type == Default::default() {
+                                    amount
+                                } else {
+                                    -amount
+                                }
+                            }
+                            None => Decimal::ZERO,
+                        }
+                    }
+                };
+                if !amount.is_zero() {
+                    current += amount * self.oracle_price(market).await?;
+                }
+            }
+
+            // notional of open orders that would add to the exposure when filled, by side
+            let (mut open_long, mut open_short) = (Decimal::ZERO, Decimal::ZERO);
+            for order in user.orders.iter().filter(|o| {
+                o.status == OrderStatus::Open
+                    && !o.reduce_only
+                    && group.contains(Market::new(o.market_index, o.market_type).as_market_id())
+            }) {
+                let market = Market::new(order.market_index, order.market_type);
+                let amount = Decimal::new(
+                    order
+                        .base_asset_amount
+                        .saturating_sub(order.base_asset_amount_filled) as i64,
+                    get_market_decimals(self.client.program_data(), market),
+                );
+                let notional = amount * self.oracle_price(market.as_market_id()).await?;
+                match order.direction {
+                    PositionDirection::Long => open_long += notional,
+                    PositionDirection::Short => open_short += notional,
+                }
+            }
+            if delta.is_sign_positive() {
+                group.check_exposure(current + open_long, delta)?;
+            } else {
+                group.check_exposure(current - open_short, delta)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reject orders to markets that are paused or otherwise not accepting them
+    ///
+    /// checked against cached market accounts, markets not cached are left to the program to check
+    ///
+    /// * `orders` - (market, reduce only) of each order
+    fn check_market_status(
+        &self,
+        orders: impl IntoIterator<Item = (Market, bool)>,
+    ) -> GatewayResult<()> {
+        for (market, reduce_only) in orders {
+            self.ensure_compatible_market(market)?;
+            let Some(operations) = self.market_operations(market) else {
+                debug!(target: LOG_TARGET, "market not cached, status unchecked: {market:?}");
+                continue;
+            };
+            if let Some(warning) = market_status_guard(market, &operations, reduce_only)? {
+                warn!(target: LOG_TARGET, "{warning}");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Refuse markets with account layouts incompatible with this build
+    fn ensure_compatible_market(&self, market: Market) -> GatewayResult<()> {
+        if self.incompatible_markets.contains(&market.as_market_id()) {
+            return Err(ControllerError::BadRequest(format!(
+                "{} account layout is incompatible with this gateway build",
+                market_subject(market)
+            )));
+        }
+        Ok(())
+    }
+
+    /// Return the operational state of a market from its cached account, None if not cached
+    fn market_operations(&self, market: Market) -> Option<MarketOperations> {
+        match market.market_type {
+            MarketType::Perp => self
+                .market_data
+                .perp_market(market.market_index)
+                .map(|(m, _)| MarketOperations::perp(&m)),
+            MarketType::Spot => self
+                .market_data
+                .spot_market(market.market_index)
+                .map(|(m, _)| MarketOperations::spot(&m)),
+        }
+    }
+
+    /// Bound fill prices of orders with a max. slippage by the current oracle price
+    ///
+    /// * `max_slippage_bps` - max. slippage of each order, if set
+    async fn apply_slippage_limits(
+        &self,
+        orders: &mut [OrderParams],
+        max_slippage_bps: &[Option<u32>],
+    ) -> GatewayResult<()> {
+        for (order, bps) in orders.iter_mut().zip(max_slippage_bps) {
+            let Some(bps) = bps else {
+                continue;
+            };
+            let market = Market::new(order.market_index, order.market_type).as_market_id();
+            let (oracle_price, slot) = self.oracle_price_and_slot(market).await?;
+            self.record_slot(SubscriptionId::Oracle(market), slot);
+            slippage_guard(order, *bps, oracle_price)?;
+        }
+        Ok(())
+    }
+
+    /// Peg orders to the current oracle price, returns the effective prices of pegged orders
+    ///
+    /// * `pegs` - oracle offset in bps of each order, if pegged
+    async fn apply_oracle_pegs(
+        &self,
+        orders: &mut [OrderParams],
+        pegs: &[Option<i32>],
+    ) -> GatewayResult<Vec<PeggedOrder>> {
+        let mut pegged = Vec::new();
+        for (order_index, (order, offset_bps)) in orders.iter_mut().zip(pegs).enumerate() {
+            let Some(offset_bps) = offset_bps else {
+                continue;
+            };
+            let tick_size = self
+                .order_tick_size(Market::new(order.market_index, order.market_type))
+                .await?;
+            let market = Market::new(order.market_index, order.market_type).as_market_id();
+            let (oracle_price, slot) = self.oracle_price_and_slot(market).await?;
+            self.record_slot(SubscriptionId::Oracle(market), slot);
+            let offset = oracle_peg(order, *offset_bps, oracle_price, tick_size)?;
+            pegged.push(PeggedOrder {
+                order_index,
+                oracle_price: Decimal::new(oracle_price, PRICE_DECIMALS).normalize(),
+                oracle_price_offset: Decimal::new(offset, PRICE_DECIMALS).normalize(),
+                price: Decimal::new(oracle_price + offset, PRICE_DECIMALS).normalize(),
+            });
+        }
+        Ok(pegged)
+    }
+
+    /// Reject or reprice post-only orders of `sub_account` that would cross resting orders, as
+    /// configured
+    ///
+    /// returns the repriced orders, none unless set to adjust
+    async fn apply_cross_check(
+        &self,
+        sub_account: &Pubkey,
+        orders: &mut [OrderParams],
+    ) -> GatewayResult<Vec<RepricedOrder>> {
+        let mut repriced = Vec::new();
+        let Some(cross_check) = self.cross_check else {
+            return Ok(repriced);
+        };
+        for (order_index, crossed, oracle_price) in
+            self.crossing_orders(sub_account, orders).await?
+        {
+            if cross_check == CrossCheck::Reject {
+                return Err(ControllerError::BadRequest(format!(
+                    "order {order_index} would cross the book at {}, post-only orders must rest",
+                    Decimal::new(crossed as i64, PRICE_DECIMALS).normalize()
+                )));
+            }
+            let order = &mut orders[order_index];
+            let requested_price = fill_price(order, oracle_price);
+            let tick_size = self
+                .order_tick_size(Market::new(order.market_index, order.market_type))
+                .await?;
+            let price = reprice_post_only(order, crossed, oracle_price, tick_size)?;
+            repriced.push(RepricedOrder {
+                order_index,
+                requested_price: Decimal::new(requested_price, PRICE_DECIMALS).normalize(),
+                price: Decimal::new(price as i64, PRICE_DECIMALS).normalize(),
+                crossed_price: Decimal::new(crossed as i64, PRICE_DECIMALS).normalize(),
+            });
+        }
+        Ok(repriced)
+    }
+
+    /// Return post-only orders of `sub_account` that would cross resting orders of the DLOB, by
+    /// index along with the price crossed and the oracle price they were checked at
+    ///
+    /// orders resting on `sub_account` itself are skipped, they may be cancelled by the same tx
+    async fn crossing_orders(
+        &self,
+        sub_account: &Pubkey,
+        orders: &[OrderParams],
+    ) -> GatewayResult<Vec<(usize, u64, i64)>> {
+        let mut crossing = Vec::new();
+        if !orders.iter().any(is_post_only) {
+            return Ok(crossing);
+        }
+        // awaits the initial load
+        deadline("orderbook load", self.timeouts.account, async {
+            Ok(self.dlob.orders(&self.client).await)
+        })
+        .await?;
+        let now = self.chain_clock.now();
+        for (order_index, order) in orders.iter().enumerate().filter(|(_, o)| is_post_only(o)) {
+            let market = Market::new(order.market_index, order.market_type);
+            let (oracle_price, slot) = self.oracle_price_and_slot(market.as_market_id()).await?;
+            self.record_slot(SubscriptionId::Oracle(market.as_market_id()), slot);
+            let best = self
+                .dlob
+                .best_prices_excluding(market, oracle_price, now, sub_account);
+            if let Some(crossed) = crossing_price(order, best, oracle_price) {
+                crossing.push((order_index, crossed, oracle_price));
+            }
+        }
+        Ok(crossing)
+    }
+
+    /// Return the order tick size of `market`
+    async fn order_tick_size(&self, market: Market) -> GatewayResult<u64> {
+        let tick_size = match market.market_type {
+            MarketType::Perp => {
+                self.perp_market_account(market.market_index)
+                    .await?
+                    .amm
+                    .order_tick_size
+            }
+            MarketType::Spot => {
+                self.spot_market_account(market.market_index)
+                    .await?
+                    .order_tick_size
+            }
+        };
+        Ok(tick_size)
+    }
+
+    async fn oracle_price(&self, market: MarketId) -> GatewayResult<Decimal> {
+        let (price, slot) = self.oracle_price_and_slot(market).await?;
+        self.record_slot(SubscriptionId::Oracle(market), slot);
+        Ok(Decimal::new(price, PRICE_DECIMALS))
+    }
+
+    /// Return the oracle price of `market` and its slot, fetched via RPC unless sourced
+    async fn oracle_price_and_slot(&self, market: MarketId) -> GatewayResult<(i64, u64)> {
+        if let Some(oracle) = self.market_data.oracle_price(market) {
+            return Ok(oracle);
+        }
+        let oracle = self.client.get_oracle_price_data_and_slot(market).await?;
+        Ok((oracle.data.price, oracle.slot))
+    }
+
+    /// Tag the current response with the `slot` of data read from subscription `id`
+    ///
+    /// the cache age is secs since the subscription last updated or was confirmed current, 0 for
+    /// data of unsubscribed accounts, i.e. fetched via RPC
+    fn record_slot(&self, id: SubscriptionId, slot: u64) {
+        let cache_age = self.subscriptions.age(&id, unix_timestamp());
+        slot_tag::record(slot, cache_age.unwrap_or_default());
+    }
+
+    /// Return a perp market account, from cache if subscribed
+    ///
+    /// refused if incompatible with this build, decoding it would panic
+    async fn perp_market_account(&self, market_index: u16) -> GatewayResult<PerpMarket> {
+        self.ensure_compatible_market(Market::perp(market_index))?;
+        match self.market_data.perp_market(market_index) {
+            Some((market, slot)) => {
+                self.record_slot(SubscriptionId::Market(MarketId::perp(market_index)), slot);
+                Ok(market)
+            }
+            None => Ok(self.client.get_perp_market_account(market_index).await?),
+        }
+    }
+
+    /// Return a spot market account, from cache if subscribed
+    ///
+    /// refused if incompatible with this build, decoding it would panic
+    async fn spot_market_account(&self, market_index: u16) -> GatewayResult<SpotMarket> {
+        self.ensure_compatible_market(Market::spot(market_index))?;
+        match self.market_data.spot_market(market_index) {
+            Some((market, slot)) => {
+                self.record_slot(SubscriptionId::Market(MarketId::spot(market_index)), slot);
+                Ok(market)
+            }
+            None => {
+                debug!(target: LOG_TARGET, "spot market not cached: {market_index}");
+                Ok(self.client.get_spot_market_account(market_index).await?)
+            }
+        }
+    }
+
+    /// Refuse orders breaking the compliance rule of the authority, given as (market, side, reduce-only)
+    ///
+    /// each decision is recorded in the audit log with the rule that matched
+    async fn check_compliance(
+        &self,
+        ctx: &Context,
+        orders: impl IntoIterator<Item = (MarketId, Option<PositionDirection>, bool)>,
+    ) -> GatewayResult<()> {
+        let authority = self.resolve_wallet(ctx)?.authority().to_string();
+        let Some((name, rule)) = self.compliance_rules.rule(&authority) else {
+            return Ok(());
+        };
+        for (order_index, (market, direction, reduce_only)) in orders.into_iter().enumerate() {
+            let expires_in = match rule.max_tenor {
+                Some(_) if market.kind() == MarketType::Perp => {
+                    let perp_market = self.perp_market_account(market.index()).await?;
+                    (perp_market.contract_type == types::ContractType::Prediction).then(|| {
+                        (perp_market.expiry_ts.max(0) as u64).saturating_sub(unix_timestamp())
+                    })
+                }
+                _ => None,
+            };
+            if let Err(reason) = rule.check(market, direction, reduce_only, expires_in) {
+                warn!(target: AUDIT_LOG_TARGET, "refused order {order_index} of {authority} on {market:?}, rule '{name}': {reason}");
+                return Err(ControllerError::BadRequest(format!(
+                    "order {order_index} refused by compliance rule '{name}': {reason}"
+                )));
+            }
+            info!(target: AUDIT_LOG_TARGET, "allowed order {order_index} of {authority} on {market:?}, rule '{name}'");
+        }
+        Ok(())
+    }
+
+    /// Return an error if the sub-account is outside of its trading window
+    fn ensure_trading_window(&self, ctx: &Context) -> GatewayResult<()> {
+        let sub_account_id = self.resolve_sub_account_id(ctx)?;
+        match self.trading_windows.get(&sub_account_id) {
+            Some(window) if !window.is_open(unix_timestamp()) => Err(ControllerError::Halted(
+                format!("outside trading window of sub-account {sub_account_id}"),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Refuse requests of more orders than the configured max., if any
+    fn check_order_count(&self, count: usize) -> GatewayResult<()> {
+        match self.max_orders {
+            Some(max_orders) if count > max_orders => Err(ControllerError::BadRequest(format!(
+                "too many orders: {count}, max. {max_orders}"
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Wait for (or fail on) per market order rate limits, if configured
+    async fn throttle_orders(
+        &self,
+        markets: impl IntoIterator<Item = Market>,
+    ) -> GatewayResult<()> {
+        match self.order_throttle {
+            Some(ref throttle) => {
+                throttle
+                    .acquire(markets.into_iter().map(Market::as_market_id))
+                    .await
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Reject orders duplicating a recent placement unless the request allows duplicates
+    ///
+    /// orders replacing those cancelled in the same tx (`replacing`) are recorded unchecked.
+    /// returns the recorded orders, to be forgotten if the tx fails to send
+    fn check_duplicate_orders(
+        &self,
+        ctx: &Context,
+        orders: &[OrderParams],
+        replacing: bool,
+    ) -> GatewayResult<Vec<OrderFingerprint>> {
+        let Some(ref duplicate_orders) = self.duplicate_orders else {
+            return Ok(vec![]);
+        };
+        let authority = *self.resolve_wallet(ctx)?.authority();
+        let sub_account_id = self.resolve_sub_account_id(ctx)?;
+        if ctx.allow_duplicate || replacing {
+            Ok(duplicate_orders.record(authority, sub_account_id, orders, Instant::now()))
+        } else {
+            duplicate_orders.check_and_record(authority, sub_account_id, orders, Instant::now())
+        }
+    }
+
+    /// Return the sub-account id of a request if the lifecycle of its orders is tracked
+    ///
+    /// orders are followed by their events, tracked for journaled sub-accounts of the gateway
+    /// authority only
+    fn tracked_sub_account_id(&self, ctx: &Context) -> GatewayResult<Option<u16>> {
+        let authority = self.resolve_wallet(ctx)?.authority();
+        let sub_account_id = self.resolve_sub_account_id(ctx)?;
+        Ok(
+            (authority == self.authority() && self.events.is_journaled(sub_account_id))
+                .then_some(sub_account_id),
+        )
+    }
+
+    /// Start tracking the lifecycle of orders about to be placed, returns their tracking ids
+    ///
+    /// orders of untracked sub-accounts get none
+    fn track_orders(&self, ctx: &Context, orders: &[OrderParams]) -> GatewayResult<Vec<u64>> {
+        // emulated wallets send no txs
+        if self.wallet.is_emulating() {
+            return Ok(vec![]);
+        }
+        let Some(sub_account_id) = self.tracked_sub_account_id(ctx)? else {
+            return Ok(vec![]);
+        };
+        let pending: Vec<PendingOrder> = orders
+            .iter()
+            .map(|o| {
+                let market = Market::new(o.market_index, o.market_type);
+                let decimals = get_market_decimals(self.client.program_data(), market);
+                PendingOrder {
+                    user_order_id: o.user_order_id,
+                    market,
+                    amount: Decimal::new(o.base_asset_amount as i64, decimals).normalize(),
+                }
+            })
+            .collect();
+        Ok(self
+            .events
+            .orders()
+            .submitted(sub_account_id, &pending, unix_timestamp()))
+    }
+
+    /// Send a tx placing orders recorded for duplicate detection, forgetting them on failure
+    ///
+    /// * `orders` - number of orders placed, accounted to the request's usage
+    /// * `tracked` - tracking ids of the orders, rejected if the tx fails
+    async fn send_orders_tx(
+        &self,
+        tx: VersionedMessage,
+        reason: &'static str,
+        ctx: &Context,
+        orders: usize,
+        recorded: Vec<OrderFingerprint>,
+        tracked: Vec<u64>,
+    ) -> GatewayResult<TxResponse> {
+        let res = self.send_tx(tx, reason, ctx).await;
+        match res {
+            Ok(ref tx) => {
+                self.events.orders().sent(&tracked, tx.signature());
+                self.usage.record_orders(
+                    &usage_authority(ctx.authority.as_deref(), self.authority()),
+                    current_api_key().as_deref(),
+                    orders as u64,
+                    unix_timestamp(),
+                );
+            }
+            Err(ref err) => {
+                if let Some(ref duplicate_orders) = self.duplicate_orders {
+                    duplicate_orders.forget(&recorded);
+                }
+                self.events
+                    .orders()
+                    .rejected(&tracked, &err.to_string(), unix_timestamp());
+            }
+        }
+        res
+    }
+
+    /// Return the user account from the subscription cache
+    ///
+    /// falls back to an RPC fetch if the account is not subscribed, or its cached slot lags the
+    /// chain by more than `MAX_ACCOUNT_SLOT_LAG` slots. Idle accounts found current via RPC are
+    /// served from cache again until they lag the check. The account is copied out of the cache
+    /// per request, the caches only return owned accounts.
+    async fn get_user_account(&self, sub_account: &Pubkey) -> GatewayResult<User> {
+        let Some((cached, slot)) = self.cached_user_account(sub_account) else {
+            debug!(target: LOG_TARGET, "user account not cached: {sub_account}");
+            return self
+                .fetch_user_account(sub_account)
+                .await
+                .map(|(_slot, user)| user);
+        };
+        let verified_slot = match self
+            .verified_accounts
+            .read()
+            .expect("lock")
+            .get(sub_account)
+        {
+            Some((cached_slot, verified_slot)) if *cached_slot == slot => *verified_slot,
+            _ => slot,
+        };
+        let lagging = self
+            .chain_slot
+            .get(unix_timestamp())
+            .is_some_and(|chain_slot| {
+                chain_slot.saturating_sub(verified_slot) > MAX_ACCOUNT_SLOT_LAG
+            });
+        if !lagging {
+            // idle accounts are current as of their last check
+            self.record_slot(SubscriptionId::Account(*sub_account), verified_slot);
+            return Ok(cached);
+        }
+
+        let (fetched_slot, user) = self.fetch_user_account(sub_account).await?;
+        if user == cached {
+            self.verified_accounts
+                .write()
+                .expect("lock")
+                .insert(*sub_account, (slot, fetched_slot));
+            self.subscriptions
+                .verified(&SubscriptionId::Account(*sub_account), unix_timestamp());
+        } else {
+            warn!(target: LOG_TARGET, "cached user account missed updates: {sub_account}, slot: {slot}");
+        }
+        Ok(user)
+    }
+
+    /// Return the latest chain slot, fetched via RPC unless the slot subscription is current
+    async fn chain_slot(&self) -> SdkResult<u64> {
+        match self.chain_slot.get(unix_timestamp()) {
+            Some(slot) => Ok(slot),
+            None => self.client.rpc().get_slot().await.map_err(SdkError::from),
+        }
+    }
+
+    /// Return the subscribed user account `sub_account` and its slot
+    fn cached_user_account(&self, sub_account: &Pubkey) -> Option<(User, u64)> {
+        match self.polled_accounts {
+            Some(ref polling) => polling.user_account(sub_account),
+            None => self
+                .client
+                .try_get_account_and_slot::<User>(sub_account)
+                .map(|user| (user.data, user.slot)),
+        }
+    }
+
+    /// Return the user order ids of open orders of `sub_account`, empty if not cached
+    pub fn open_user_order_ids(&self, sub_account: &Pubkey) -> Vec<u8> {
+        self.cached_user_account(sub_account)
+            .map(|(user, _)| {
+                user.orders
+                    .iter()
+                    .filter(|o| o.status == OrderStatus::Open)
+                    .map(|o| o.user_order_id)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Reserve the user order id of a DCA plan or TWAP algo of `ctx`, `requested` or any free
+    fn reserve_algo_user_order_id(
+        &self,
+        ctx: &Context,
+        requested: Option<u8>,
+    ) -> GatewayResult<u8> {
+        let sub_account = self.resolve_sub_account(ctx)?;
+        let open = self.open_user_order_ids(&sub_account);
+        match requested {
+            Some(0) => Err(ControllerError::BadRequest(
+                "userOrderId must be non-zero".to_string(),
+            )),
+            Some(id) if open.contains(&id) || !self.user_order_ids.claim(&sub_account, id) => Err(
+                ControllerError::BadRequest(format!("userOrderId {id} is in use")),
+            ),
+            Some(id) => Ok(id),
+            None => self
+                .user_order_ids
+                .reserve(&sub_account, &open)
+                .ok_or_else(|| {
+                    ControllerError::BadRequest(
+                        "no free userOrderId of the sub-account".to_string(),
+                    )
+                }),
+        }
+    }
+
+    /// Release the user order id of a done DCA plan or TWAP algo
+    fn release_algo_user_order_id(&self, authority: &Option<String>, sub_account_id: u16, id: u8) {
+        let ctx = Context {
+            authority: authority.clone(),
+            sub_account_id: Some(sub_account_id),
+            ..Default::default()
+        };
+        if let Ok(sub_account) = self.resolve_sub_account(&ctx) {
+            self.user_order_ids.release(&sub_account, id);
+        }
+    }
+
+    /// Subscribe updates of the user account `sub_account`
+    async fn subscribe_user_account(&self, sub_account: &Pubkey) -> SdkResult<()> {
+        match self.polled_accounts {
+            Some(ref polling) => polling.subscribe_account(sub_account).await,
+            None => self.client.subscribe_account(sub_account).await,
+        }
+    }
+
+    /// Stop updates of the user account `sub_account`
+    fn unsubscribe_user_account(&self, sub_account: &Pubkey) -> SdkResult<()> {
+        self.verified_accounts
+            .write()
+            .expect("lock")
+            .remove(sub_account);
+        match self.polled_accounts {
+            Some(ref polling) => {
+                polling.unsubscribe_account(sub_account);
+                Ok(())
+            }
+            None => self.client.unsubscribe_account(sub_account),
+        }
+    }
+
+    /// Fetch the user account via RPC, returns the user and slot of the fetch
+    async fn fetch_user_account(&self, sub_account: &Pubkey) -> GatewayResult<(u64, User)> {
+        let (slot, user) = deadline("account fetch", self.timeouts.account, async {
+            let rpc = self.client.rpc();
+            let res = rpc
+                .get_account_with_commitment(sub_account, rpc.commitment())
+                .await
+                .map_err(SdkError::from)?;
+            let user = res
+                .value
+                .and_then(|account| User::try_deserialize(&mut account.data.as_slice()).ok())
+                .ok_or_else(|| {
+                    ControllerError::BadRequest(format!("no user account: {sub_account}"))
+                })?;
+            Ok((res.context.slot, user))
+        })
+        .await?;
+        slot_tag::record(slot, 0);
+        Ok((slot, user))
+    }
+
+    /// Subscribe account updates of the default and configured sub-accounts
+    ///
+    /// called once the market data source is set, polling-only gateways subscribe none via Ws
+    pub(crate) async fn subscribe_sub_accounts(&self) {
+        for id in &self.journaled_sub_accounts() {
+            let sub_account = self.wallet.inner().sub_account(*id);
+            if let Err(err) = self.subscribe_user_account(&sub_account).await {
+                log::error!(target: LOG_TARGET, "couldn't subscribe to user updates: {id}, {err:?}");
+            } else {
+                log::info!(target: LOG_TARGET, "subscribed to subaccount: {sub_account} ({id})");
+                self.subscriptions
+                    .add([SubscriptionId::Account(sub_account)], unix_timestamp());
+            }
+        }
+    }
+
+    /// Start market and oracle data subscriptions
+    ///
+    /// * configured_markets - list of static markets provided by user
+    ///
+    /// additional subscriptions will be included based on current positions of the subscribed sub-accounts
+    pub(crate) async fn subscribe_market_data(&self, configured_markets: &[MarketId]) {
+        let mut user_markets = Vec::<MarketId>::new();
+        for id in &self.journaled_sub_accounts() {
+            let (spot, perps) = self
+                .client
+                .all_positions(&self.wallet.inner().sub_account(*id))
+                .await
+                .expect("loaded user positions");
+            user_markets.extend(
+                spot.iter()
+                    .map(|s| MarketId::spot(s.market_index))
+                    .chain(perps.iter().map(|p| MarketId::perp(p.market_index))),
+            );
+        }
+        user_markets.push(MarketId::QUOTE_SPOT); // usdc needed for most functions
+        user_markets.extend_from_slice(configured_markets);
+
+        let init_rpc_throttle: u64 = std::env::var("INIT_RPC_THROTTLE")
+            .map(|s| s.parse().unwrap())
+            .unwrap_or(1);
+
+        self.validate_market_layouts().await;
+        let markets: Vec<MarketId> = HashSet::<MarketId>::from_iter(user_markets)
+            .into_iter()
+            .filter(|m| !self.incompatible_markets.contains(m))
+            .collect();
+        info!(target: LOG_TARGET, "start market subscriptions: {markets:?}");
+        tokio::time::sleep(Duration::from_secs(init_rpc_throttle)).await;
+        self.market_data
+            .subscribe_oracles(&markets)
+            .await
+            .expect("oracles subscribed");
+        tokio::time::sleep(Duration::from_secs(init_rpc_throttle)).await;
+        self.market_data
+            .subscribe_markets(&markets)
+            .await
+            .expect("markets subscribed");
+        self.add_market_subscriptions(&markets);
+        self.priority_fees
+            .warm(self.client.program_data(), &markets);
+    }
+
+    /// Check the account layout of every market, flagging markets this build can't decode
+    ///
+    /// markets are assumed compatible if their accounts can't be fetched
+    async fn validate_market_layouts(&self) {
+        let program_data = self.client.program_data();
+        let markets: Vec<(MarketId, Pubkey)> = program_data
+            .perp_market_configs()
+            .iter()
+            .map(|m| (MarketId::perp(m.market_index), m.pubkey))
+            .chain(
+                program_data
+                    .spot_market_configs()
+                    .iter()
+                    .map(|m| (MarketId::spot(m.market_index), m.pubkey)),
+            )
+            .collect();
+        for chunk in markets.chunks(MAX_MULTIPLE_ACCOUNTS) {
+            let pubkeys: Vec<Pubkey> = chunk.iter().map(|(_, pubkey)| *pubkey).collect();
+            let accounts = match self.client.rpc().get_multiple_accounts(&pubkeys).await {
+                Ok(accounts) => accounts,
+                Err(err) => {
+                    warn!(target: LOG_TARGET, "couldn't validate market layouts: {err:?}");
+                    return;
+                }
+            };
+            for ((market, _), account) in chunk.iter().zip(accounts) {
+                let Some(account) = account else {
+                    continue;
+                };
+                if let Err(err) = check_market_layout(market.kind(), &account.data) {
+                    let subject = market_subject(Market::new(market.index(), market.kind()));
+                    warn!(target: LOG_TARGET, "skipping incompatible market {subject}: {err}");
+                    self.incompatible_markets.insert(*market);
+                    self.status.raise(
+                        StatusCondition::IncompatibleMarket,
+                        Some(subject),
+                        format!("account layout incompatible with this gateway build, {err}"),
+                    );
+                }
+            }
+        }
+    }
+
+    /// Fetch on-chain names of the sub-accounts of each authority
+    pub(crate) async fn load_sub_account_names(&self) {
+        let wallets = std::iter::once(self.wallet.inner()).chain(
+            self.wallet
+                .delegators()
+                .filter_map(|a| self.wallet.for_authority(Some(a))),
+        );
+        for wallet in wallets {
+            if let Err(err) = self
+                .sub_account_names_of(wallet.authority())
+                .refresh(&self.client, wallet)
+                .await
+            {
+                warn!(target: LOG_TARGET, "couldn't load sub-account names: {}, {err:?}", wallet.authority());
+            }
+        }
+    }
+
+    /// Return the authority's sub-accounts, refreshing cached names
+    pub async fn get_sub_accounts(&self, ctx: Context) -> GatewayResult<SubAccountsResponse> {
+        let wallet = self.resolve_wallet(&ctx)?;
+        let sub_account_names = self.sub_account_names_of(wallet.authority());
+        sub_account_names.refresh(&self.client, wallet).await?;
+        Ok(SubAccountsResponse {
+            sub_accounts: sub_account_names
+                .all()
+                .into_iter()
+                .map(|(sub_account_id, name)| SubAccountInfo {
+                    sub_account_id,
+                    name,
+                    address: wallet.sub_account(sub_account_id).to_string(),
+                })
+                .collect(),
+        })
+    }
+
+    /// Create a sub-account of the authority, with the next free id unless given
+    ///
+    /// the authority's user stats account is created along with its first sub-account
+    pub async fn create_sub_account(
+        &self,
+        ctx: Context,
+        req: CreateSubAccountRequest,
+    ) -> GatewayResult<CreateSubAccountResponse> {
+        self.ensure_leader()?;
+        if self.wallet.is_delegated() {
+            return Err(ControllerError::BadRequest(
+                "sub-accounts can only be created by the authority".to_owned(),
+            ));
+        }
+        let wallet = self.wallet.inner();
+        let authority = *wallet.authority();
+        let stats_exists = deadline("account fetch", self.timeouts.account, async {
+            let account = self
+                .client
+                .rpc()
+                .get_account_with_commitment(&user_stats_account(&authority), self.tx_commitment)
+                .await
+                .map_err(SdkError::from)?;
+            Ok(account.value.is_some())
+        })
+        .await?;
+        let next_id = if stats_exists {
+            self.client
+                .get_user_stats(&authority)
+                .await?
+                .number_of_sub_accounts_created
+        } else {
+            0
+        };
+
+        // the program only allows creating sub-accounts in order
+        let sub_account_id = req.sub_account_id.unwrap_or(next_id);
+        if sub_account_id != next_id {
+            return Err(ControllerError::BadRequest(format!(
+                "sub-accounts are created in order, next id is {next_id}"
+            )));
+        }
+        let name = req.name.unwrap_or_else(|| {
+            if sub_account_id == 0 {
+                "Main Account".to_string()
+            } else {
+                format!("Subaccount {sub_account_id}")
+            }
+        });
+        let name = encode_name(&name)
+            .ok_or_else(|| ControllerError::BadRequest("name exceeds 32 bytes".to_owned()))?;
+
+        let mut ixs = vec![ComputeBudgetInstruction::set_compute_unit_price(
+            ctx.cu_price.unwrap_or(self.get_priority_fee([])),
+        )];
+        if let Some(cu_limit) = ctx.cu_limit {
+            ixs.push(ComputeBudgetInstruction::set_compute_unit_limit(cu_limit));
+        }
+        ixs.extend(initialize_user_ixs(
+            &authority,
+            sub_account_id,
+            name,
+            !stats_exists,
+        ));
+        let tx = VersionedMessage::Legacy(Message::new(&ixs, Some(&authority)));
+
+        let tx = self.send_tx(tx, "create_sub_account", &ctx).await?;
+        // emulated txs are not sent
+        if let Ok(signature) = Signature::from_str(tx.signature()) {
+            self.add_created_sub_account(signature, sub_account_id, name);
+        }
+        Ok(CreateSubAccountResponse {
+            sub_account_id,
+            address: wallet.sub_account(sub_account_id).to_string(),
+            tx,
+        })
+    }
+
+    /// Once the tx creating `sub_account_id` confirms, accept requests for the sub-account, then
+    /// subscribe and journal it like the startup sub-accounts
+    fn add_created_sub_account(&self, signature: Signature, sub_account_id: u16, name: [u8; 32]) {
+        let state = self.clone();
+        tokio::spawn(async move {
+            let start = Instant::now();
+            let mut interval = tokio::time::interval(SUB_ACCOUNT_CONFIRM_INTERVAL);
+            loop {
+                interval.tick().await;
+                match state.txs.get(&signature).map(|tx| tx.state) {
+                    Some(TxState::Confirmed | TxState::Finalized) => break,
+                    // an expired tx may still land while its blockhash is valid
+                    Some(TxState::Submitted | TxState::Expired)
+                        if start.elapsed() < SUB_ACCOUNT_CONFIRM_TIMEOUT =>
+                    {
+                        continue
+                    }
+                    _ => {
+                        warn!(target: LOG_TARGET, "sub-account not created: {sub_account_id}, tx: {signature}");
+                        return;
+                    }
+                }
+            }
+            state
+                .sub_account_names
+                .insert(sub_account_id, decode_name(&name));
+            {
+                let mut ids = state.sub_account_ids.write().expect("lock");
+                if ids.contains(&sub_account_id) {
+                    return;
+                }
+                ids.push(sub_account_id);
+            }
+            let sub_account = state.wallet.inner().sub_account(sub_account_id);
+            if let Err(err) = state.subscribe_user_account(&sub_account).await {
+                log::error!(target: LOG_TARGET, "couldn't subscribe to user updates: {sub_account_id}, {err:?}");
+                return;
+            }
+            log::info!(target: LOG_TARGET, "subscribed to created subaccount: {sub_account} ({sub_account_id})");
+            state
+                .subscriptions
+                .add([SubscriptionId::Account(sub_account)], unix_timestamp());
+            if state.events_subscribed.load(Ordering::Relaxed) {
+                state.events.subscribe(
+                    Arc::clone(&state.client),
+                    Arc::clone(&state.alerter),
+                    sub_account_id,
+                    sub_account,
+                );
+            }
+        });
+    }
+
+    /// Move spot collateral between two sub-accounts of the authority
+    ///
+    /// with transfer confirmation enabled the transfer is held until confirmed
+    pub async fn transfer(
+        &self,
+        ctx: Context,
+        req: TransferRequest,
+    ) -> GatewayResult<TransferResponse> {
+        self.validate_transfer(&req)?;
+        match self.transfer_confirmations {
+            Some(ref confirmations) => {
+                info!(target: LOG_TARGET, "transfer awaiting confirmation: {req:?}");
+                Ok(TransferResponse::Pending {
+                    confirm_token: confirmations.request((ctx, req), Instant::now()),
+                    expires_in: confirmations.timeout().as_secs(),
+                })
+            }
+            None => self
+                .send_transfer(ctx, req)
+                .await
+                .map(TransferResponse::Sent),
+        }
+    }
+
+    /// Send a transfer held for confirmation by `token`
+    pub async fn confirm_transfer(&self, req: ConfirmRequest) -> GatewayResult<TxResponse> {
+        let (ctx, transfer) = self
+            .transfer_confirmations
+            .as_ref()
+            .and_then(|confirmations| confirmations.confirm(&req.token, Instant::now()))
+            .ok_or_else(|| {
+                ControllerError::BadRequest("unknown or expired confirmation token".to_owned())
+            })?;
+        info!(target: LOG_TARGET, "transfer confirmed: {transfer:?}");
+        // e.g. leadership may have changed while awaiting confirmation
+        self.validate_transfer(&transfer)?;
+        self.send_transfer(ctx, transfer).await
+    }
+
+    fn validate_transfer(&self, req: &TransferRequest) -> GatewayResult<()> {
+        self.ensure_leader()?;
+        if self.wallet.is_delegated() {
+            return Err(ControllerError::BadRequest(
+                "transfers can only be made by the authority".to_owned(),
+            ));
+        }
+        if req.from_sub_account_id == req.to_sub_account_id {
+            return Err(ControllerError::BadRequest(
+                "from and to sub-accounts must differ".to_owned(),
+            ));
+        }
+        if req.amount <= Decimal::ZERO {
+            return Err(ControllerError::BadRequest(
+                "amount must be positive".to_owned(),
+            ));
+        }
+        for id in [req.from_sub_account_id, req.to_sub_account_id] {
+            if !self.sub_account_names.contains(id) {
+                return Err(ControllerError::BadRequest(format!(
+                    "unknown sub-account id: {id}"
+                )));
+            }
+        }
+        if self
+            .client
+            .program_data()
+            .spot_market_config_by_index(req.market_index)
+            .is_none()
+        {
+            return Err(ControllerError::BadRequest(format!(
+                "unknown spot market: {}",
+                req.market_index
+            )));
+        }
+        Ok(())
+    }
+
+    async fn send_transfer(&self, ctx: Context, req: TransferRequest) -> GatewayResult<TxResponse> {
+        let program_data = self.client.program_data();
+        let decimals = program_data
+            .spot_market_config_by_index(req.market_index)
+            .map(|m| m.decimals)
+            .expect("validated");
+
+        let wallet = self.wallet.inner();
+        let from = wallet.sub_account(req.from_sub_account_id);
+        let to = wallet.sub_account(req.to_sub_account_id);
+        let from_user = self.get_user_account(&from).await?;
+        let transfer_ix = transfer_deposit_ix(
+            program_data,
+            wallet.authority(),
+            &from,
+            &from_user,
+            &to,
+            req.market_index,
+            req.token_amount(decimals),
+        )
+        .ok_or_else(|| ControllerError::BadRequest("unknown market of position".to_owned()))?;
+
+        let mut ixs = vec![ComputeBudgetInstruction::set_compute_unit_price(
+            ctx.cu_price.unwrap_or(self.get_priority_fee([])),
+        )];
+        if let Some(cu_limit) = ctx.cu_limit {
+            ixs.push(ComputeBudgetInstruction::set_compute_unit_limit(cu_limit));
+        }
+        ixs.push(transfer_ix);
+        let tx = VersionedMessage::Legacy(Message::new(&ixs, Some(wallet.authority())));
+
+        self.send_tx(tx, "transfer", &ctx).await
+    }
+
+    /// Start journaling events of the subscribed sub-accounts
+    pub(crate) fn subscribe_events(&self) {
+        self.events_subscribed.store(true, Ordering::Relaxed);
+        for id in &self.journaled_sub_accounts() {
+            self.events.subscribe(
+                Arc::clone(&self.client),
+                Arc::clone(&self.alerter),
+                *id,
+                self.wallet.inner().sub_account(*id),
+            );
+        }
+    }
+
+    /// Replicate the event journal of a primary gateway instead of subscribing to events
+    pub(crate) fn follow_events(&self, primary: &str) {
+        self.events.follow(primary, Arc::clone(&self.alerter));
+    }
+
+    /// Periodically snapshot equity, collateral and leverage of the given sub-accounts
+    pub(crate) fn record_equity_history(&self, sub_account_ids: Vec<u16>) {
+        let client = Arc::clone(&self.client);
+        let wallet = Arc::clone(&self.wallet);
+        let equity_history = Arc::clone(&self.equity_history);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(EQUITY_SNAPSHOT_INTERVAL);
+            loop {
+                interval.tick().await;
+                let ts = unix_timestamp();
+                for sub_account_id in sub_account_ids.iter().copied() {
+                    let sub_account = wallet.inner().sub_account(sub_account_id);
+                    match equity_snapshot(&client, &sub_account, ts).await {
+                        Ok(snapshot) => equity_history.record(sub_account_id, snapshot),
+                        Err(err) => {
+                            warn!(target: LOG_TARGET, "equity snapshot failed: {sub_account_id}, {err:?}");
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    /// Periodically sample oracle prices of subscribed markets, fills are marked out against them
+    pub(crate) fn record_oracle_history(&self) {
+        let market_data = Arc::clone(&self.market_data);
+        let subscriptions = Arc::clone(&self.subscriptions);
+        let oracle_history = Arc::clone(&self.oracle_history);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(ORACLE_SAMPLE_INTERVAL);
+            loop {
+                interval.tick().await;
+                let ts = unix_timestamp();
+                for id in subscriptions.ids() {
+                    let SubscriptionId::Oracle(market) = id else {
+                        continue;
+                    };
+                    if let Some((price, _)) = market_data.oracle_price(market) {
+                        oracle_history.record(
+                            (market.index(), market.kind() == MarketType::Perp),
+                            OracleSample {
+                                ts,
+                                price: Decimal::new(price, PRICE_DECIMALS),
+                            },
+                        );
+                    }
+                }
+            }
+        });
+    }
+
+    /// Periodically write changed usage and fee spend rollups to the store
+    pub(crate) fn persist_usage(&self) {
+        let usage = Arc::clone(&self.usage);
+        let fee_spend = Arc::clone(&self.fee_spend);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(USAGE_FLUSH_INTERVAL);
+            loop {
+                interval.tick().await;
+                usage.flush();
+                fee_spend.flush();
+            }
+        });
+    }
+
+    /// Periodically remove stored events older than `retention`
+    ///
+    /// with `archived` only events already archived are removed
+    pub(crate) fn prune_fills(&self, retention: Duration, archived: bool) {
+        let Some(store) = self.fill_store() else {
+            return;
+        };
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(FILLS_PRUNE_INTERVAL);
+            loop {
+                interval.tick().await;
+                let mut before = unix_timestamp().saturating_sub(retention.as_secs());
+                if archived {
+                    before = before.min(store.archived_until().unwrap_or_default());
+                }
+                match store.prune_before(before) {
+                    Ok(0) => (),
+                    Ok(removed) => {
+                        info!(target: LOG_TARGET, "removed {removed} stored events before {before}");
+                    }
+                    Err(err) => warn!(target: LOG_TARGET, "pruning stored events failed: {err:?}"),
+                }
+            }
+        });
+    }
+
+    /// Periodically record increases in the social loss of subscribed perp markets
+    pub(crate) fn monitor_social_losses(&self) {
+        let client = Arc::clone(&self.client);
+        let market_data = Arc::clone(&self.market_data);
+        let social_losses = Arc::clone(&self.social_losses);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SOCIAL_LOSS_INTERVAL);
+            loop {
+                interval.tick().await;
+                let ts = unix_timestamp();
+                for config in client.program_data().perp_market_configs() {
+                    // only subscribed markets, avoids an RPC request per market
+                    if let Some((perp, _)) = market_data.perp_market(config.market_index) {
+                        social_losses.observe(
+                            perp.market_index,
+                            Decimal::from_i128_with_scale(
+                                perp.amm.total_social_loss as i128,
+                                QUOTE_DECIMALS,
+                            ),
+                            ts,
+                        );
+                    }
+                }
+            }
+        });
+    }
+
+    /// Periodically sample the latest slot of each subscription
+    ///
+    /// accounts report the slot of their latest received update
+    ///
+    /// * `stall_timeout` - resubscribe subscriptions missing updates for this long, None to disable
+    pub(crate) fn monitor_subscriptions(&self, stall_timeout: Option<Duration>) {
+        let state = self.clone();
+        tokio::spawn(async move {
+            let client = &state.client;
+            let subscriptions = &state.subscriptions;
+            let mut interval = tokio::time::interval(SUBSCRIPTION_HEALTH_INTERVAL);
+            loop {
+                interval.tick().await;
+                let now = unix_timestamp();
+                for id in subscriptions.ids() {
+                    // cached data only, an RPC fallback would hide a stalled subscription
+                    let slot = match id {
+                        SubscriptionId::Market(m) => match m.kind() {
+                            MarketType::Perp => state
+                                .market_data
+                                .perp_market(m.index())
+                                .map(|(_, slot)| slot),
+                            MarketType::Spot => state
+                                .market_data
+                                .spot_market(m.index())
+                                .map(|(_, slot)| slot),
+                        },
+                        SubscriptionId::Oracle(m) => {
+                            state.market_data.oracle_price(m).map(|(_, slot)| slot)
+                        }
+                        SubscriptionId::Account(a) => {
+                            state.cached_user_account(&a).map(|(_, slot)| slot)
+                        }
+                    };
+                    if let Some(slot) = slot {
+                        subscriptions.observe(&id, slot, now);
+                    }
+                }
+
+                let Some(stall_timeout) = stall_timeout else {
+                    continue;
+                };
+                let chain_slot = match client.rpc().get_slot().await {
+                    Ok(slot) => slot,
+                    Err(err) => {
+                        warn!(target: LOG_TARGET, "stall check skipped, slot unavailable: {err:?}");
+                        continue;
+                    }
+                };
+                let mut stalled = Vec::new();
+                for (id, secs) in subscriptions.stalled(now, chain_slot, stall_timeout.as_secs()) {
+                    // quiet markets and accounts don't update, only missed changes are a stall
+                    match state.is_cached_current(id).await {
+                        Ok(true) => subscriptions.verified(&id, now),
+                        Ok(false) => stalled.push((id, secs)),
+                        Err(err) => {
+                            warn!(target: LOG_TARGET, "stall check skipped: {id:?}, {err:?}");
+                        }
+                    }
+                }
+                state.status.replace(
+                    StatusCondition::SubscriptionStalled,
+                    stalled
+                        .iter()
+                        .map(|(id, _)| (format!("{id:?}"), "no updates, resubscribed".to_owned()))
+                        .collect(),
+                );
+                for (id, secs) in stalled {
+                    warn!(target: LOG_TARGET, "subscription stalled for {secs}s, resubscribing: {id:?}");
+                    state.alerter.send(Alert::SubscriptionStalled {
+                        subscription: format!("{id:?}"),
+                        secs,
+                    });
+                    if let Err(err) = state.resubscribe_id(id).await {
+                        warn!(target: LOG_TARGET, "resubscribe failed: {id:?}, {err:?}");
+                    }
+                }
+            }
+        });
+    }
+
+    /// Return true if the cached data of a subscription matches its on-chain account
+    ///
+    /// oracles are expected to update continuously, they're never confirmed current
+    async fn is_cached_current(&self, id: SubscriptionId) -> GatewayResult<bool> {
+        let program_data = self.client.program_data();
+        let pubkey = match id {
+            SubscriptionId::Oracle(_) => return Ok(false),
+            SubscriptionId::Market(m) => match m.kind() {
+                MarketType::Perp => program_data
+                    .perp_market_configs()
+                    .iter()
+                    .find(|c| c.market_index == m.index())
+                    .map(|c| c.pubkey),
+                MarketType::Spot => program_data
+                    .spot_market_configs()
+                    .iter()
+                    .find(|c| c.market_index == m.index())
+                    .map(|c| c.pubkey),
+            },
+            SubscriptionId::Account(a) => Some(a),
+        };
+        let Some(pubkey) = pubkey else {
+            return Ok(false);
+        };
+        let account = deadline("account fetch", self.timeouts.account, async {
+            Ok(self
+                .client
+                .rpc()
+                .get_account(&pubkey)
+                .await
+                .map_err(SdkError::from)?)
+        })
+        .await?;
+        let data = &mut account.data.as_slice();
+        Ok(match id {
+            SubscriptionId::Market(m) if m.kind() == MarketType::Perp => {
+                let cached = self.market_data.perp_market(m.index()).map(|(m, _)| m);
+                PerpMarket::try_deserialize(data).ok() == cached
+            }
+            SubscriptionId::Market(m) => {
+                let cached = self.market_data.spot_market(m.index()).map(|(m, _)| m);
+                SpotMarket::try_deserialize(data).ok() == cached
+            }
+            _ => {
+                let cached = self.cached_user_account(&pubkey).map(|(u, _)| u);
+                User::try_deserialize(data).ok() == cached
+            }
+        })
+    }
+
+    /// Periodically check RPC health and the status of subscribed markets for the Ws status channel
+    pub(crate) fn monitor_status(&self) {
+        let state = self.clone();
+        tokio::spawn(async move {
+            let client = &state.client;
+            let mut interval = tokio::time::interval(STATUS_INTERVAL);
+            loop {
+                interval.tick().await;
+                match tokio::time::timeout(RPC_DEGRADED_LATENCY, client.rpc().get_slot()).await {
+                    Ok(Ok(_)) => state.status.clear(StatusCondition::RpcDegraded, None),
+                    Ok(Err(err)) => {
+                        debug!(target: LOG_TARGET, "status slot request failed: {err:?}");
+                        state.status.raise(
+                            StatusCondition::RpcDegraded,
+                            None,
+                            "slot request failed".to_owned(),
+                        );
+                    }
+                    Err(_) => state.status.raise(
+                        StatusCondition::RpcDegraded,
+                        None,
+                        format!(
+                            "slot request exceeded {}ms",
+                            RPC_DEGRADED_LATENCY.as_millis()
+                        ),
+                    ),
+                }
+
+                // cached accounts only, paused markets are reported as of their last update
+                let paused = state
+                    .subscriptions
+                    .ids()
+                    .into_iter()
+                    .filter_map(|id| {
+                        let SubscriptionId::Market(m) = id else {
+                            return None;
+                        };
+                        let market = Market::new(m.index(), m.kind());
+                        let operations = state.market_operations(market)?;
+                        match market_status_guard(market, &operations, false) {
+                            Err(ControllerError::MarketPaused(reason)) => {
+                                Some((market_subject(market), reason))
+                            }
+                            _ => None,
+                        }
+                    })
+                    .collect();
+                state.status.replace(StatusCondition::MarketPaused, paused);
+            }
+        });
+    }
+
+    /// Periodically compare drift oracle prices with secondary prices
+    ///
+    /// alerts when the relative divergence exceeds `threshold`. Stale prices of either source are
+    /// skipped, they'd alert on divergence the market doesn't trade at.
+    pub(crate) fn monitor_oracle_divergence(
+        &self,
+        hermes: Hermes,
+        oracles: Vec<SecondaryOracle>,
+        threshold: Decimal,
+    ) {
+        let state = self.clone();
+        tokio::spawn(async move {
+            let feed_ids: Vec<&str> = oracles.iter().map(|o| o.feed_id.as_str()).collect();
+            let mut interval = tokio::time::interval(ORACLE_CROSS_CHECK_INTERVAL);
+            loop {
+                interval.tick().await;
+                let secondary_prices = match hermes.prices(&feed_ids).await {
+                    Ok(prices) => prices,
+                    Err(err) => {
+                        warn!(target: LOG_TARGET, "secondary oracle fetch failed: {err}");
+                        continue;
+                    }
+                };
+                let chain_slot = match state.chain_slot().await {
+                    Ok(slot) => slot,
+                    Err(err) => {
+                        warn!(target: LOG_TARGET, "oracle cross-check skipped, slot unavailable: {err:?}");
+                        continue;
+                    }
+                };
+                let now = unix_timestamp() as i64;
+                for oracle in &oracles {
+                    let Some(secondary) = secondary_prices.get(&oracle.feed_id).copied() else {
+                        continue;
+                    };
+                    if secondary.is_stale(now, MAX_SECONDARY_PRICE_AGE) {
+                        warn!(target: LOG_TARGET, "secondary oracle stale: {}, published: {}", oracle.ticker, secondary.publish_time);
+                        continue;
+                    }
+                    let (price, slot) = match state.oracle_price_and_slot(oracle.market).await {
+                        Ok(oracle) => oracle,
+                        Err(err) => {
+                            warn!(target: LOG_TARGET, "oracle price fetch failed: {}, {err:?}", oracle.ticker);
+                            continue;
+                        }
+                    };
+                    if chain_slot.saturating_sub(slot) > MAX_ORACLE_SLOT_LAG {
+                        warn!(target: LOG_TARGET, "oracle stale: {}, slot: {slot}, chain slot: {chain_slot}", oracle.ticker);
+                        continue;
+                    }
+                    let price = Decimal::new(price, PRICE_DECIMALS);
+                    let divergence = divergence(price, secondary.price);
+                    if divergence > threshold {
+                        warn!(target: LOG_TARGET, "oracle divergence: {} {price} vs. {}", oracle.ticker, secondary.price);
+                        state.alerter.send(Alert::OracleDivergence {
+                            market: oracle.ticker.clone(),
+                            price: price.normalize(),
+                            secondary_price: secondary.price,
+                            divergence: divergence.round_dp(6).normalize(),
+                        });
+                    }
+                }
+            }
+        });
+    }
+
+    /// Return insurance fund and socialized loss info of a perp market
+    pub async fn get_insurance(&self, market_index: u16) -> GatewayResult<InsuranceResponse> {
+        let perp = self.perp_market_account(market_index).await?;
+        let quote = self
+            .spot_market_account(perp.quote_spot_market_index)
+            .await?;
+        let balance = self
+            .client
+            .rpc()
+            .get_token_account_balance(&quote.insurance_fund.vault)
+            .await
+            .map_err(|err| ControllerError::Sdk(err.into()))?;
+        let insurance_fund_balance = Decimal::from_i128_with_scale(
+            balance.amount.parse().unwrap_or_default(),
+            balance.decimals as u32,
+        );
+        let quote_amount = |amount: u64| Decimal::new(amount as i64, QUOTE_DECIMALS).normalize();
+
+        Ok(InsuranceResponse {
+            market_index,
+            insurance_fund_balance: insurance_fund_balance.normalize(),
+            max_insurance: quote_amount(perp.insurance_claim.quote_max_insurance),
+            settled_insurance: quote_amount(perp.insurance_claim.quote_settled_insurance),
+            total_social_loss: Decimal::from_i128_with_scale(
+                perp.amm.total_social_loss as i128,
+                QUOTE_DECIMALS,
+            )
+            .normalize(),
+            socialized_losses: self.social_losses.recent(market_index),
+        })
+    }
+
+    /// Return equity history of the sub-account at the given resolution
+    pub fn get_equity_history(
+        &self,
+        ctx: Context,
+        req: EquityHistoryRequest,
+    ) -> GatewayResult<EquityHistoryResponse> {
+        let sub_account_id = self.resolve_sub_account_id(&ctx)?;
+        Ok(EquityHistoryResponse {
+            snapshots: self.equity_history.query(sub_account_id, req.resolution),
+        })
+    }
+
+    /// Periodically check each sub-account's PnL loss since the start of the (UTC) day
+    ///
+    /// once a sub-account's loss exceeds `max_loss` open orders of every authority are cancelled
+    /// and the kill switch is tripped until all sub-accounts have rolled over to the next day
+    pub(crate) fn monitor_daily_loss(&self, max_loss: Decimal) {
+        let state = self.clone();
+        tokio::spawn(async move {
+            // day's starting PnL by sub-account, persisted so a restart doesn't reset the baseline
+            let store = state.open_store_tree("daily_loss");
+            let mut daily_losses: HashMap<u16, DailyLoss> = store
+                .iter()
+                .flat_map(|store| store.iter())
+                .filter_map(|entry| {
+                    let (key, value) = entry.ok()?;
+                    let sub_account_id = u16::from_be_bytes(key.as_ref().try_into().ok()?);
+                    Some((sub_account_id, serde_json::from_slice(&value).ok()?))
+                })
+                .collect();
+            // day the kill switch was tripped on
+            let mut tripped_day: Option<u64> = None;
+            let mut interval = tokio::time::interval(DAILY_LOSS_INTERVAL);
+            loop {
+                interval.tick().await;
+                let ts = unix_timestamp();
+                let sub_account_ids = state.journaled_sub_accounts();
+                for &sub_account_id in &sub_account_ids {
+                    let sub_account = state.wallet.inner().sub_account(sub_account_id);
+                    let pnl = match state.get_user_account(&sub_account).await {
+                        Ok(user) => sub_account_pnl(&state.client, &user).map_err(Into::into),
+                        Err(err) => Err(err),
+                    };
+                    let pnl = match pnl {
+                        Ok(pnl) => pnl,
+                        Err(err) => {
+                            warn!(target: LOG_TARGET, "daily loss check failed: {sub_account_id}, {err:?}");
+                            continue;
+                        }
+                    };
+                    let daily_loss = daily_losses.entry(sub_account_id).or_default();
+                    let new_day = daily_loss.is_new_day(ts);
+                    let loss = daily_loss.update(ts, pnl);
+                    if new_day {
+                        if let Some(store) = store.as_ref() {
+                            let value = serde_json::to_vec(daily_loss).expect("serializes");
+                            if let Err(err) = store.insert(sub_account_id.to_be_bytes(), value) {
+                                warn!(target: LOG_TARGET, "daily loss baseline not persisted: {err:?}");
+                            }
+                        }
+                    }
+                    if loss <= max_loss || state.kill_switch.is_tripped() {
+                        continue;
+                    }
+
+                    let reason = format!(
+                        "daily loss limit breached by sub-account {sub_account_id} ({loss} > {max_loss})"
+                    );
+                    tripped_day = Some(daily_loss.day());
+                    state.kill_switch.trip(reason.clone());
+                    state
+                        .status
+                        .raise(StatusCondition::KillSwitch, None, reason);
+                    state.alerter.send(Alert::DailyLossLimit {
+                        sub_account_id,
+                        loss,
+                        limit: max_loss,
+                    });
+                    state.cancel_all_authorities().await;
+                }
+
+                // reset once every sub-account has moved on from the day the switch tripped
+                if let Some(day) = tripped_day {
+                    let rolled_over = sub_account_ids.iter().all(|id| {
+                        daily_losses
+                            .get(id)
+                            .is_some_and(|daily_loss| daily_loss.day() > day)
+                    });
+                    if rolled_over {
+                        info!(target: LOG_TARGET, "new trading day, resetting kill switch");
+                        tripped_day = None;
+                        state.kill_switch.reset();
+                        state.status.clear(StatusCondition::KillSwitch, None);
+                    }
+                }
+            }
+        });
+    }
+
+    /// Cancel open orders of every sub-account of every configured authority
+    ///
+    /// the default authority's journaled sub-accounts, and the known sub-accounts of delegators
+    async fn cancel_all_authorities(&self) {
+        let mut targets: Vec<(Option<String>, u16)> = self
+            .journaled_sub_accounts()
+            .into_iter()
+            .map(|id| (None, id))
+            .collect();
+        for authority in self.wallet.delegators() {
+            let mut ids: Vec<u16> = self
+                .sub_account_names_of(authority)
+                .all()
+                .into_iter()
+                .map(|(id, _name)| id)
+                .collect();
+            if ids.is_empty() {
+                ids.push(self.default_subaccount_id);
+            }
+            targets.extend(ids.into_iter().map(|id| (Some(authority.to_string()), id)));
+        }
+        for (authority, id) in targets {
+            let ctx = Context {
+                authority: authority.clone(),
+                sub_account_id: Some(id),
+                ..Default::default()
+            };
+            if let Err(err) = self
+                .cancel_orders(ctx, CancelOrdersRequest::default())
+                .await
+            {
+                warn!(target: LOG_TARGET, "kill switch cancel orders failed: {authority:?}/{id}, {err:?}");
+            }
+        }
+    }
+
+    /// Cancel open orders of sub-accounts once their trading window closes
+    pub(crate) fn cancel_outside_trading_windows(&self) {
+        let state = self.clone();
+        tokio::spawn(async move {
+            let mut cancelled = HashSet::<u16>::new();
+            let mut interval = tokio::time::interval(TRADING_WINDOW_INTERVAL);
+            loop {
+                interval.tick().await;
+                let ts = unix_timestamp();
+                for (sub_account_id, window) in state.trading_windows.iter() {
+                    if window.is_open(ts) {
+                        cancelled.remove(sub_account_id);
+                        continue;
+                    }
+                    if cancelled.contains(sub_account_id) {
+                        continue;
+                    }
+                    info!(target: LOG_TARGET, "trading window closed, cancelling orders: {sub_account_id}");
+                    let ctx = Context {
+                        sub_account_id: Some(*sub_account_id),
+                        ..Default::default()
+                    };
+                    match state
+                        .cancel_orders(ctx, CancelOrdersRequest::default())
+                        .await
+                    {
+                        Ok(_) => {
+                            cancelled.insert(*sub_account_id);
+                        }
+                        Err(err) => {
+                            warn!(target: LOG_TARGET, "trading window cancel orders failed: {sub_account_id}, {err:?}");
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    /// Periodically check each journaled sub-account, alerting when margin health falls below
+    /// `threshold` or the account starts being liquidated
+    ///
+    /// health is the ratio of free to total maintenance collateral
+    pub(crate) fn monitor_margin_health(&self, threshold: Decimal) {
+        let state = self.clone();
+        tokio::spawn(async move {
+            // sub-accounts being liquidated as of the last check
+            let mut liquidating = HashSet::<u16>::new();
+            let mut interval = tokio::time::interval(MARGIN_HEALTH_INTERVAL);
+            loop {
+                interval.tick().await;
+                for sub_account_id in state.journaled_sub_accounts() {
+                    let sub_account = state.wallet.inner().sub_account(sub_account_id);
+                    let user = match state.get_user_account(&sub_account).await {
+                        Ok(user) => user,
+                        Err(err) => {
+                            warn!(target: LOG_TARGET, "margin monitor fetch failed: {sub_account_id}, {err:?}");
+                            continue;
+                        }
+                    };
+                    if user.status & USER_STATUS_BEING_LIQUIDATED != 0 {
+                        if liquidating.insert(sub_account_id) {
+                            state.alerter.send(Alert::Liquidation { sub_account_id });
+                        }
+                    } else {
+                        liquidating.remove(&sub_account_id);
+                    }
+                    match calculate_collateral(
+                        &state.client,
+                        &user,
+                        MarginRequirementType::Maintenance,
+                    ) {
+                        Ok(collateral) if collateral.total > 0 => {
+                            let health = Decimal::from_i128_with_scale(collateral.free, 0)
+                                / Decimal::from_i128_with_scale(collateral.total, 0);
+                            if health < threshold {
+                                state.alerter.send(Alert::MarginHealth {
+                                    sub_account_id,
+                                    health: health.round_dp(4),
+                                });
+                            }
+                        }
+                        Ok(_) => (),
+                        Err(err) => {
+                            warn!(target: LOG_TARGET, "margin monitor calculation failed: {sub_account_id}, {err:?}");
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    /// Return stored events of the journaled sub-accounts from unix timestamp `from`, oldest first
+    ///
+    /// catches up on events of previous runs of the gateway, empty without a fills store
+    pub(crate) fn stored_events_since(&self, from: u64) -> Vec<JournalEntry> {
+        let Some(ref store) = self.fills else {
+            return Vec::new();
+        };
+        let journaled = self.journaled_sub_accounts();
+        store
+            .events_since(from)
+            .filter(|e| journaled.contains(&e.sub_account_id))
+            .map(|e| JournalEntry {
+                seq: 0,
+                sub_account_id: e.sub_account_id,
+                channel: e.channel,
+                data: e.data,
+            })
+            .collect()
+    }
+
+    /// Return journaled events after `req.since`, waiting for new events if there are none
+    pub async fn poll_events(&self, req: PollEventsRequest) -> GatewayResult<PollEventsResponse> {
+        let timeout = Duration::from_millis(req.timeout_ms).min(MAX_POLL_TIMEOUT);
+        let epoch = self.events.epoch();
+        // `since` of a previous run of this gateway, its retained events are all new to the client
+        let since = match req.epoch {
+            Some(req_epoch) if req_epoch != epoch => 0,
+            _ => req.since,
+        };
+        let events = self.events.poll(since, timeout).await;
+        // a `since` ahead of the journal means the client saw a previous run of this gateway
+        let last_seq = events
+            .last()
+            .map(|e| e.seq)
+            .unwrap_or(since.min(self.events.last_seq()));
+
+        Ok(PollEventsResponse {
+            events,
+            last_seq,
+            epoch,
+        })
+    }
+
+    /// Return whether subscribed markets, oracles, user accounts, and the blockhash are cached
+    pub async fn readiness(&self) -> ReadinessResponse {
+        let client = &self.client;
+        let mut pending: Vec<String> = self
+            .subscriptions
+            .cold(|id| match *id {
+                SubscriptionId::Market(m) => match m.kind() {
+                    MarketType::Perp => self.market_data.perp_market(m.index()).is_some(),
+                    MarketType::Spot => self.market_data.spot_market(m.index()).is_some(),
+                },
+                SubscriptionId::Oracle(m) => self.market_data.oracle_price(m).is_some(),
+                SubscriptionId::Account(a) => self.cached_user_account(&a).is_some(),
+            })
+            .into_iter()
+            .map(|kind| format!("{}s", camel_case(&kind)))
+            .collect();
+        // emulated wallets send no txs
+        if !self.wallet.is_emulating() {
+            let blockhash =
+                tokio::time::timeout(READINESS_TIMEOUT, client.get_latest_blockhash()).await;
+            if !matches!(blockhash, Ok(Ok(_))) {
+                pending.push("blockhash".to_string());
+            }
+        }
+        ReadinessResponse {
+            ready: pending.is_empty(),
+            pending,
+        }
+    }
+
+    /// Return request stats of the RPC clients
+    pub fn get_rpc_stats(&self) -> RpcStatsResponse {
+        RpcStatsResponse {
+            primary: self.client.rpc().get_transport_stats().into(),
+            tx: self.tx_rpc.get_transport_stats().into(),
+            extra: self
+                .extra_rpcs
+                .read()
+                .expect("lock")
+                .iter()
+                .map(|rpc| rpc.get_transport_stats().into())
+                .collect(),
+        }
+    }
+
+    /// Return SOL balance of the tx signing account, the emulated authority in emulation mode
+    pub async fn get_sol_balance(&self) -> GatewayResult<SolBalanceResponse> {
+        // emulated wallets have a placeholder signer, the authority pays for its unsigned txs
+        let account = if self.wallet.is_emulating() {
+            *self.authority()
+        } else {
+            self.signer()
+        };
+        let balance = deadline("account fetch", self.timeouts.account, async {
+            self.client
+                .rpc()
+                .get_balance(&account)
+                .await
+                .map_err(|err| ControllerError::Sdk(err.into()))
+        })
+        .await?;
+        Ok(SolBalanceResponse {
+            balance: Decimal::new(balance as i64, BASE_PRECISION.ilog10()).normalize(),
+        })
+    }
+
+    /// Cancel orders
+    ///
+    /// There are 4 intended scenarios for cancellation, in order of priority:
+    /// 1) "market" is set, cancel all orders in the market
+    /// 2) "user ids" are set, cancel all orders by user assigned id
+    /// 3) ids are given, cancel all orders by id (global, exchange assigned id)
+    /// 4) catch all. cancel all orders
+    pub async fn cancel_orders(
+        &self,
+        ctx: Context,
+        req: CancelOrdersRequest,
+    ) -> GatewayResult<TxResponse> {
+        self.ensure_leader()?;
+        let sub_account = self.resolve_sub_account(&ctx)?;
+        let account_data = self.get_user_account(&sub_account).await?;
+        let pf = self.get_priority_fee(cancel_markets(&req, &account_data));
+
+        let priority_fee = ctx.cu_price.unwrap_or(pf);
+        debug!(target: LOG_TARGET, "priority_fee: {priority_fee:?}");
+        let builder = TransactionBuilder::new(
+            self.client.program_data(),
+            sub_account,
+            Cow::Owned(account_data),
+            self.wallet.is_delegated(),
+        )
+        .with_priority_fee(priority_fee, ctx.cu_limit);
+        let tx = build_cancel_ix(builder, req)?.build();
+        self.send_tx(tx, "cancel_orders", &ctx).await
+    }
+
+    /// Return spot market accounts in order of `market_indexes`
+    ///
+    /// subscribed markets are served from cache, others are fetched concurrently
+    async fn spot_market_accounts(
+        &self,
+        market_indexes: impl Iterator<Item = u16>,
+    ) -> GatewayResult<Vec<SpotMarket>> {
+        let futs = market_indexes.map(|market_index| self.spot_market_account(market_index));
+        futures_util::future::try_join_all(futs).await
+    }
+
+    /// Return position for market if given, otherwise return all positions
+    pub async fn get_positions(
+        &self,
+        ctx: Context,
+        req: Option<GetPositionsRequest>,
+    ) -> GatewayResult<GetPositionsResponse> {
+        let wallet = self.resolve_wallet(&ctx)?;
+        let sub_account_id = self.resolve_sub_account_id(&ctx)?;
+        let (all_spot, all_perp) = deadline("account fetch", self.timeouts.account, async {
+            self.client
+                .all_positions(&wallet.sub_account(sub_account_id))
+                .await
+                .map_err(Into::into)
+        })
+        .await?;
+
+        // calculating spot token balance requires knowing the 'spot market account' data
+        let filtered_spot_positions: Vec<&drift_idl::types::SpotPosition> = all_spot
+            .iter()
+            .filter(|p| {
+                if let Some(GetPositionsRequest { ref market }) = req {
+                    p.market_index == market.market_index && MarketType::Spot == market.market_type
+                } else {
+                    true
+                }
+            })
+            .collect();
+
+        let spot_markets = self
+            .spot_market_accounts(filtered_spot_positions.iter().map(|p| p.market_index))
+            .await?;
+
+        let filtered_spot_positions = filtered_spot_positions
+            .iter()
+            .zip(spot_markets.iter())
+            .map(|(position, market)| SpotPosition::from_sdk_type(position, market))
+            .collect();
+
+        Ok(GetPositionsResponse {
+            spot: filtered_spot_positions,
+            perp: all_perp
+                .into_iter()
+                .filter(|p| {
+                    if let Some(GetPositionsRequest { ref market }) = req {
+                        p.market_index == market.market_index
+                            && MarketType::Perp == market.market_type
+                    } else {
+                        true
+                    }
+                })
+                .map(Into::into)
+                .collect(),
+            sub_account_name: self
+                .sub_account_names_of(wallet.authority())
+                .name(sub_account_id),
+        })
+    }
+
+    pub async fn get_margin_info(&self, ctx: Context) -> GatewayResult<MarginInfo> {
+        let sub_account = self.resolve_sub_account(&ctx)?;
+        calculate_margin_requirements(&self.client, &self.get_user_account(&sub_account).await?)
+            .map(Into::into)
+            .map_err(ControllerError::Sdk)
+    }
+
+    pub async fn get_leverage(&self, ctx: Context) -> GatewayResult<Leverage> {
+        let sub_account = self.resolve_sub_account(&ctx)?;
+        get_leverage(&self.client, &self.get_user_account(&sub_account).await?)
+            .map(Into::into)
+            .map_err(ControllerError::Sdk)
+    }
+
+    pub async fn get_collateral(
+        &self,
+        ctx: Context,
+        margin_requirement_type: MarginRequirementType,
+    ) -> GatewayResult<Collateral> {
+        let sub_account = self.resolve_sub_account(&ctx)?;
+        calculate_collateral(
+            &self.client,
+            &self.get_user_account(&sub_account).await?,
+            margin_requirement_type,
+        )
+        .map(Into::into)
+        .map_err(ControllerError::Sdk)
+    }
+
+    /// Return margin of the sub-account before and after a batch of perp orders is filled,
+    /// along with any orders that would breach margin or exposure limits on their own
+    ///
+    /// orders are assumed filled at their limit price, or the oracle price (+ offset) if unset.
+    /// post-only orders are checked against the DLOB, as by the cross check
+    pub async fn what_if_orders(
+        &self,
+        ctx: Context,
+        req: PlaceOrdersRequest,
+    ) -> GatewayResult<OrdersWhatIfResponse> {
+        self.check_order_count(req.orders.len())?;
+        let sub_account = self.resolve_sub_account(&ctx)?;
+        let user = self.get_user_account(&sub_account).await?;
+        let orders: Vec<OrderParams> = req
             .orders
             .into_iter()
-            .filter(|o| o.status == OrderStatus::Open)
+            .map(|o| {
+                let base_decimals = get_market_decimals(self.client.program_data(), o.market);
+                o.to_order_params(base_decimals)
+            })
+            .collect();
+        if orders.iter().any(|o| o.market_type != MarketType::Perp) {
+            return Err(ControllerError::BadRequest(
+                "what-if supports perp orders only".to_string(),
+            ));
+        }
+
+        let mut post_trade = user.clone();
+        let mut breaches = Vec::new();
+        for (order_index, order) in orders.iter().enumerate() {
+            let (oracle_price, _slot) = self
+                .oracle_price_and_slot(MarketId::perp(order.market_index))
+                .await?;
+            let price = fill_price(order, oracle_price);
+            apply_perp_fill(&mut post_trade, order, price).map_err(ControllerError::BadRequest)?;
+
+            let reason = match self
+                .check_exposure_limits(&user, std::slice::from_ref(order))
+                .await
+            {
+                Err(ControllerError::Sdk(err)) => return Err(err.into()),
+                Err(err) => Some(err.to_string()),
+                Ok(()) => {
+                    let mut single = user.clone();
+                    apply_perp_fill(&mut single, order, price)
+                        .map_err(ControllerError::BadRequest)?;
+                    self.margin_snapshot(&single)?
+                        .is_breached()
+                        .then(|| "insufficient initial margin".to_string())
+                }
+            };
+            if let Some(reason) = reason {
+                breaches.push(OrderBreach {
+                    order_index,
+                    market: Market::perp(order.market_index),
+                    reason,
+                });
+            }
+        }
+
+        let crossing_orders: Vec<usize> = self
+            .crossing_orders(&sub_account, &orders)
+            .await?
+            .into_iter()
+            .map(|(order_index, _, _)| order_index)
+            .collect();
+
+        Ok(OrdersWhatIfResponse {
+            current: self.margin_snapshot(&user)?,
+            post_trade: self.margin_snapshot(&post_trade)?,
+            breaches,
+            would_cross: !crossing_orders.is_empty(),
+            crossing_orders,
+        })
+    }
+
+    fn margin_snapshot(&self, user: &User) -> GatewayResult<MarginSnapshot> {
+        Ok(MarginSnapshot {
+            margin: calculate_margin_requirements(&self.client, user)?.into(),
+            collateral: calculate_collateral(&self.client, user, MarginRequirementType::Initial)?
+                .into(),
+        })
+    }
+
+    /// Return a health summary of the sub-account with per position liquidation distance
+    pub async fn get_health(&self, ctx: Context) -> GatewayResult<UserHealthResponse> {
+        let sub_account = self.resolve_sub_account(&ctx)?;
+        let user = self.get_user_account(&sub_account).await?;
+        let margin: MarginInfo = calculate_margin_requirements(&self.client, &user)?.into();
+        let collateral: Collateral =
+            calculate_collateral(&self.client, &user, MarginRequirementType::Maintenance)?.into();
+
+        let mut positions = Vec::new();
+        for position in user.perp_positions.iter().filter(|p| !p.is_available()) {
+            let market = self.perp_market_account(position.market_index).await?;
+            let calc = calculate_liquidation_price_and_unrealized_pnl(
+                &self.client,
+                &user,
+                position.market_index,
+            )
+            .await?;
+            let amount = Decimal::new(position.base_asset_amount, BASE_PRECISION.ilog10());
+            let oracle_price = Decimal::new(calc.oracle_price, PRICE_DECIMALS);
+            let liquidation_price = Decimal::new(calc.liquidation_price, PRICE_DECIMALS);
+            let maintenance_requirement = amount.abs()
+                * oracle_price
+                * Decimal::new(market.margin_ratio_maintenance as i64, 4);
+            positions.push(PositionHealth {
+                market: Market::perp(position.market_index),
+                amount: amount.normalize(),
+                oracle_price: oracle_price.normalize(),
+                liquidation_price: (liquidation_price > Decimal::ZERO)
+                    .then(|| liquidation_price.normalize()),
+                liquidation_distance: liquidation_distance(oracle_price, liquidation_price),
+                maintenance_requirement: maintenance_requirement.round_dp(6).normalize(),
+            });
+        }
+
+        let margin_usage = if collateral.total > Decimal::ZERO {
+            (margin.maintenance / collateral.total).round_dp(4)
+        } else {
+            Decimal::ZERO
+        };
+        Ok(UserHealthResponse {
+            health: health_score(collateral.total, margin.maintenance),
+            margin_usage,
+            largest_risk: largest_risk(&positions).cloned(),
+            total_collateral: collateral.total,
+            maintenance_requirement: margin.maintenance,
+            positions,
+        })
+    }
+
+    pub async fn get_position_extended(
+        &self,
+        ctx: Context,
+        market: Market,
+    ) -> GatewayResult<PerpPosition> {
+        let sub_account = self.resolve_sub_account(&ctx)?;
+        let user = self.get_user_account(&sub_account).await?;
+
+        let perp_position = user
+            .perp_positions
+            .iter()
+            .find(|p| p.market_index == market.market_index && !p.is_available());
+
+        if let Some(perp_position) = perp_position {
+            let calc = calculate_liquidation_price_and_unrealized_pnl(
+                &self.client,
+                &user,
+                market.market_index,
+            )
+            .await?;
+            let unsettled_pnl = Decimal::from_i128_with_scale(
+                perp_position
+                    .get_unrealized_pnl(calc.oracle_price)
+                    .unwrap_or_default(),
+                PRICE_DECIMALS,
+            );
+
+            let mut p: PerpPosition = (*perp_position).into();
+            p.set_extended_info(PerpPositionExtended {
+                liquidation_price: Decimal::new(calc.liquidation_price, PRICE_DECIMALS),
+                unrealized_pnl: Decimal::new(calc.unrealized_pnl as i64, PRICE_DECIMALS),
+                unsettled_pnl: unsettled_pnl.normalize(),
+                oracle_price: Decimal::new(calc.oracle_price, PRICE_DECIMALS),
+            });
+
+            Ok(p)
+        } else {
+            Err(ControllerError::BadRequest("no position".to_string()))
+        }
+    }
+
+    /// Return orders by market if given, otherwise return all orders
+    pub async fn get_orders(
+        &self,
+        ctx: Context,
+        req: Option<GetOrdersRequest>,
+    ) -> GatewayResult<GetOrdersResponse> {
+        let sub_account = self.resolve_sub_account(&ctx)?;
+        let user = self.get_user_account(&sub_account).await?;
+
+        Ok(GetOrdersResponse {
+            orders: self.open_orders(&user, req.map(|r| r.market)),
+        })
+    }
+
+    /// Return open orders of `user`, optionally of `market` only
+    fn open_orders(&self, user: &User, market: Option<Market>) -> Vec<Order> {
+        user.orders
+            .iter()
+            .filter(|o| {
+                o.status == OrderStatus::Open
+                    && market.map_or(true, |m| {
+                        o.market_index == m.market_index && o.market_type == m.market_type
+                    })
+            })
+            .map(|o| {
+                let base_decimals = get_market_decimals(
+                    self.client.program_data(),
+                    Market::new(o.market_index, o.market_type),
+                );
+                Order::from_sdk_order(*o, base_decimals)
+            })
+            .collect()
+    }
+
+    /// Return persisted fills (or order events) of the sub-account
+    pub fn get_fills(&self, ctx: Context, req: FillsRequest) -> GatewayResult<FillsResponse> {
+        let Some(ref store) = self.fills else {
+            return Err(ControllerError::BadRequest(
+                "fill history disabled, see --fills-db".to_owned(),
+            ));
+        };
+        let market = match req.market {
+            Some(ref symbol) => {
+                let market = self.client.market_lookup(symbol).ok_or_else(|| {
+                    ControllerError::BadRequest(format!("unknown market: {symbol}"))
+                })?;
+                Some(Market::new(market.index(), market.kind()))
+            }
+            None => None,
+        };
+        let events = store.query(&FillsQuery {
+            sub_account_id: self.resolve_sub_account_id(&ctx)?,
+            channel: if req.orders {
+                Channel::Orders
+            } else {
+                Channel::Fills
+            },
+            market,
+            from: req.from.unwrap_or_default(),
+            to: req.to.unwrap_or(u64::MAX),
+            limit: req.limit.unwrap_or(DEFAULT_FILLS_LIMIT),
+        });
+        Ok(FillsResponse { events })
+    }
+
+    /// Return the execution quality of persisted fills of the sub-account by market
+    pub fn get_execution_report(
+        &self,
+        ctx: Context,
+        req: ExecutionReportRequest,
+    ) -> GatewayResult<ExecutionReportResponse> {
+        let Some(ref store) = self.fills else {
+            return Err(ControllerError::BadRequest(
+                "fill history disabled, see --fills-db".to_owned(),
+            ));
+        };
+        let market = match req.market {
+            Some(ref symbol) => {
+                let market = self.client.market_lookup(symbol).ok_or_else(|| {
+                    ControllerError::BadRequest(format!("unknown market: {symbol}"))
+                })?;
+                Some(Market::new(market.index(), market.kind()))
+            }
+            None => None,
+        };
+        let sub_account = self.resolve_sub_account(&ctx)?.to_string();
+        let to = req.to.unwrap_or_else(unix_timestamp);
+        let query = FillsQuery {
+            sub_account_id: self.resolve_sub_account_id(&ctx)?,
+            channel: Channel::Fills,
+            market,
+            from: req.from.unwrap_or(to.saturating_sub(7 * 86_400)),
+            to,
+            limit: usize::MAX,
+        };
+        let fills: Vec<ExecutedFill> = store
+            .events(&query)
+            .filter_map(|e| match e.data {
+                AccountEvent::Fill {
+                    side,
+                    amount,
+                    price,
+                    oracle_price,
+                    market_index,
+                    market_type,
+                    ts,
+                    maker,
+                    ..
+                } => Some(ExecutedFill {
+                    market: Market::new(market_index, market_type),
+                    ts,
+                    side,
+                    maker: maker.as_deref() == Some(sub_account.as_str()),
+                    price,
+                    oracle_price,
+                    amount,
+                }),
+                _ => None,
+            })
+            .collect();
+        let horizon = req.horizon.unwrap_or(DEFAULT_MARKOUT_HORIZON);
+        let mut oracle_history = HashMap::<(u16, bool), Vec<OracleSample>>::new();
+        for fill in &fills {
+            let key = (
+                fill.market.market_index,
+                fill.market.market_type == MarketType::Perp,
+            );
+            oracle_history.entry(key).or_insert_with(|| {
+                self.oracle_history.query(
+                    key,
+                    query.from + horizon,
+                    to + horizon + MAX_MARKOUT_DELAY,
+                )
+            });
+        }
+        Ok(ExecutionReportResponse {
+            markets: execution_report(&fills, &oracle_history, horizon),
+        })
+    }
+
+    /// Return the lifecycle status of order `order_id` of the sub-account
+    ///
+    /// the state is unknown if the sub-account's orders aren't tracked
+    pub fn get_order_status(&self, ctx: Context, order_id: u32) -> GatewayResult<OrderStatus> {
+        let Some(sub_account_id) = self.tracked_sub_account_id(&ctx)? else {
+            return Ok(OrderStatus::unknown(
+                self.resolve_sub_account_id(&ctx)?,
+                Some(order_id),
+                0,
+            ));
+        };
+        self.events
+            .orders()
+            .by_order_id(sub_account_id, order_id, unix_timestamp())
+            .ok_or_else(|| ControllerError::BadRequest(format!("order not tracked: {order_id}")))
+    }
+
+    /// Return the lifecycle status of the latest order with `user_order_id` of the sub-account
+    pub fn get_order_status_by_user_order_id(
+        &self,
+        ctx: Context,
+        user_order_id: u8,
+    ) -> GatewayResult<OrderStatus> {
+        let Some(sub_account_id) = self.tracked_sub_account_id(&ctx)? else {
+            return Ok(OrderStatus::unknown(
+                self.resolve_sub_account_id(&ctx)?,
+                None,
+                user_order_id,
+            ));
+        };
+        self.events
+            .orders()
+            .by_user_order_id(sub_account_id, user_order_id, unix_timestamp())
+            .ok_or_else(|| {
+                ControllerError::BadRequest(format!(
+                    "order not tracked: userOrderId {user_order_id}"
+                ))
+            })
+    }
+
+    /// Return orders, positions, and margin of the sub-account as of one account slot, along with
+    /// cached prices of subscribed oracles
+    pub async fn get_snapshot(&self, ctx: Context) -> GatewayResult<SnapshotResponse> {
+        let sub_account = self.resolve_sub_account(&ctx)?;
+        let (slot, user) = self.fetch_user_account(&sub_account).await?;
+
+        let active_spot: Vec<_> = user
+            .spot_positions
+            .iter()
+            .filter(|p| !p.is_available())
+            .collect();
+        let spot_markets = self
+            .spot_market_accounts(active_spot.iter().map(|p| p.market_index))
+            .await?;
+
+        let oracles = self
+            .subscriptions
+            .ids()
+            .into_iter()
+            .filter_map(|id| match id {
+                SubscriptionId::Oracle(m) => {
+                    self.market_data
+                        .oracle_price(m)
+                        .map(|(price, slot)| OraclePrice {
+                            market: Market::new(m.index(), m.kind()),
+                            price: Decimal::new(price, PRICE_DECIMALS).normalize(),
+                            slot,
+                        })
+                }
+                _ => None,
+            })
             .collect();
 
-        Ok(GetOrdersResponse {
-            orders: orders
+        Ok(SnapshotResponse {
+            slot,
+            orders: self.open_orders(&user, None),
+            spot: active_spot
                 .into_iter()
-                .filter(|o| {
-                    if let Some(GetOrdersRequest { ref market }) = req {
-                        o.market_index == market.market_index && o.market_type == market.market_type
-                    } else {
-                        true
-                    }
-                })
-                .map(|o| {
-                    let base_decimals = get_market_decimals(
-                        self.client.program_data(),
-                        Market::new(o.market_index, o.market_type),
-                    );
-                    Order::from_sdk_order(o, base_decimals)
-                })
+                .zip(spot_markets.iter())
+                .map(|(position, market)| SpotPosition::from_sdk_type(position, market))
                 .collect(),
+            perp: user
+                .perp_positions
+                .iter()
+                .filter(|p| !p.is_available())
+                .map(|p| (*p).into())
+                .collect(),
+            margin: calculate_margin_requirements(&self.client, &user)?.into(),
+            oracles,
+        })
+    }
+
+    /// Compare the client's believed open orders and positions with on-chain state
+    pub async fn reconcile(
+        &self,
+        ctx: Context,
+        req: ReconcileRequest,
+    ) -> GatewayResult<ReconcileResponse> {
+        let mut discrepancies = Vec::new();
+        if let Some(orders) = req.orders {
+            let actual = self.get_orders(ctx.clone(), None).await?;
+            discrepancies.extend(reconcile_orders(orders, actual.orders));
+        }
+        if let Some(positions) = req.positions {
+            let actual = self.get_positions(ctx, None).await?;
+            discrepancies.extend(reconcile_positions(positions, actual));
+        }
+
+        Ok(ReconcileResponse {
+            in_sync: discrepancies.is_empty(),
+            discrepancies,
         })
     }
 
@@ -429,9 +3176,26 @@ impl AppState {
         let spot = self.client.program_data().spot_market_configs();
         let perp = self.client.program_data().perp_market_configs();
 
+        // prefer live accounts for subscribed markets, operational state may have changed since startup
         AllMarketsResponse {
-            spot: spot.iter().map(|x| (*x).into()).collect(),
-            perp: perp.iter().map(|x| (*x).into()).collect(),
+            spot: spot
+                .iter()
+                .map(|x| {
+                    self.market_data
+                        .spot_market(x.market_index)
+                        .map_or(*x, |(market, _)| market)
+                        .into()
+                })
+                .collect(),
+            perp: perp
+                .iter()
+                .map(|x| {
+                    self.market_data
+                        .perp_market(x.market_index)
+                        .map_or(*x, |(market, _)| market)
+                        .into()
+                })
+                .collect(),
         }
     }
 
@@ -439,22 +3203,114 @@ impl AppState {
         &self,
         market_index: u16,
     ) -> GatewayResult<MarketInfoResponse> {
-        let perp = self.client.get_perp_market_account(market_index).await?;
+        let perp = self.perp_market_account(market_index).await?;
         let open_interest = (perp.get_open_interest() / BASE_PRECISION) as u64;
         let max_open_interest = (perp.amm.max_open_interest.as_u128() / BASE_PRECISION) as u64;
+        let oracle = self
+            .client
+            .get_oracle_price_data_and_slot(MarketId::perp(market_index))
+            .await?;
+        self.record_slot(
+            SubscriptionId::Oracle(MarketId::perp(market_index)),
+            oracle.slot,
+        );
 
         Ok(MarketInfoResponse {
             open_interest,
             max_open_interest,
+            operations: MarketOperations::perp(&perp),
+            oracle: OracleInfo {
+                source: camel_case(&perp.amm.oracle_source),
+                pubkey: perp.amm.oracle.to_string(),
+                price: Decimal::new(oracle.data.price, PRICE_DECIMALS).normalize(),
+                confidence: Decimal::new(oracle.data.confidence as i64, PRICE_DECIMALS).normalize(),
+            },
+        })
+    }
+
+    /// Return the last and predicted next funding rate of a perp market
+    pub async fn get_funding_rate(&self, market_index: u16) -> GatewayResult<FundingRateResponse> {
+        let perp = self.perp_market_account(market_index).await?;
+        Ok(FundingRateResponse::new(&perp))
+    }
+
+    /// Return an L2 snapshot of resting limit orders in the market by `symbol`
+    pub async fn get_orderbook(
+        &self,
+        symbol: &str,
+        req: OrderbookRequest,
+    ) -> GatewayResult<OrderbookResponse> {
+        let market = self
+            .client
+            .market_lookup(symbol)
+            .ok_or_else(|| ControllerError::BadRequest(format!("unknown market: {symbol}")))?;
+        self.ensure_compatible_market(Market::new(market.index(), market.kind()))?;
+        let (oracle_price, slot) = self.oracle_price_and_slot(market).await?;
+        self.record_slot(SubscriptionId::Oracle(market), slot);
+        let orders = deadline("orderbook load", self.timeouts.account, async {
+            Ok(self.dlob.orders(&self.client).await)
+        })
+        .await?;
+
+        let market = Market::new(market.index(), market.kind());
+        let (bids, asks) = l2_levels(
+            &orders,
+            market,
+            oracle_price,
+            self.chain_clock.now(),
+            get_market_decimals(self.client.program_data(), market),
+            req.depth.unwrap_or(DEFAULT_ORDERBOOK_DEPTH),
+        );
+        Ok(OrderbookResponse {
+            market,
+            oracle_price: Decimal::new(oracle_price, PRICE_DECIMALS),
+            bids,
+            asks,
         })
     }
 
+    /// Return margin configuration of the market given by symbol e.g. 'sol-perp', 'wbtc'
+    pub async fn get_market_margin(&self, symbol: &str) -> GatewayResult<MarketMarginResponse> {
+        let market = self
+            .client
+            .market_lookup(symbol)
+            .ok_or_else(|| ControllerError::BadRequest(format!("unknown market: {symbol}")))?;
+        let res = match market.kind() {
+            MarketType::Perp => {
+                MarketMarginResponse::perp(&self.perp_market_account(market.index()).await?)
+            }
+            MarketType::Spot => {
+                MarketMarginResponse::spot(&self.spot_market_account(market.index()).await?)
+            }
+        };
+        Ok(res)
+    }
+
     pub async fn cancel_and_place_orders(
         &self,
         ctx: Context,
         req: CancelAndPlaceRequest,
     ) -> GatewayResult<TxResponse> {
-        let orders = req
+        self.check_order_count(req.modify.orders.len() + req.place.orders.len())?;
+        self.ensure_leader()?;
+        self.kill_switch.check()?;
+        self.ensure_trading_window(&ctx)?;
+        self.throttle_orders(
+            req.modify
+                .orders
+                .iter()
+                .map(|o| o.market)
+                .chain(req.place.orders.iter().map(|o| o.market)),
+        )
+        .await?;
+        let max_slippage_bps: Vec<_> = req
+            .place
+            .orders
+            .iter()
+            .map(|o| o.max_slippage_bps)
+            .collect();
+        let pegs: Vec<_> = req.place.orders.iter().map(|o| o.peg_to_oracle).collect();
+        let mut orders: Vec<OrderParams> = req
             .place
             .orders
             .into_iter()
@@ -464,9 +3320,44 @@ impl AppState {
             })
             .collect();
 
-        let sub_account = self.resolve_sub_account(ctx.sub_account_id);
-        let account_data = self.client.get_user_account(&sub_account).await?;
-        let pf = self.get_priority_fee();
+        self.apply_slippage_limits(&mut orders, &max_slippage_bps)
+            .await?;
+        let pegged = self.apply_oracle_pegs(&mut orders, &pegs).await?;
+        let sub_account = self.resolve_sub_account(&ctx)?;
+        let repriced = self.apply_cross_check(&sub_account, &mut orders).await?;
+
+        let account_data = self.get_user_account(&sub_account).await?;
+        let adjustments = reduce_only_guard(&account_data.perp_positions, &mut orders)?;
+        self.check_market_status(
+            req.modify.orders.iter().map(|o| (o.market, true)).chain(
+                orders
+                    .iter()
+                    .map(|o| (Market::new(o.market_index, o.market_type), o.reduce_only)),
+            ),
+        )?;
+        self.check_exposure_limits(&account_data, &orders).await?;
+        self.check_compliance(
+            &ctx,
+            req.modify
+                .orders
+                .iter()
+                .map(|o| (o.market.as_market_id(), o.direction(), o.is_reduce_only()))
+                .chain(orders.iter().map(order_compliance)),
+        )
+        .await?;
+        // the tx cancels before placing, so identical orders are cancel-replaces
+        let recorded = self.check_duplicate_orders(&ctx, &orders, true)?;
+        let tracked = self.track_orders(&ctx, &orders)?;
+        let pf = self.get_priority_fee(
+            cancel_markets(&req.cancel, &account_data)
+                .into_iter()
+                .chain(req.modify.orders.iter().map(|o| o.market))
+                .chain(
+                    orders
+                        .iter()
+                        .map(|o| Market::new(o.market_index, o.market_type)),
+                ),
+        );
 
         let builder = TransactionBuilder::new(
             self.client.program_data(),
@@ -476,44 +3367,674 @@ impl AppState {
         )
         .with_priority_fee(ctx.cu_price.unwrap_or(pf), ctx.cu_limit);
 
-        let builder = build_cancel_ix(builder, req.cancel)?;
-        let tx = build_modify_ix(builder, req.modify, self.client.program_data())?
-            .place_orders(orders)
-            .build();
+        let order_count = orders.len();
+        let builder = build_cancel_ix(builder, req.cancel)?;
+        let tx = build_modify_ix(builder, req.modify, self.client.program_data())?
+            .place_orders(orders)
+            .build();
+
+        self.send_orders_tx(tx, "cancel_and_place", &ctx, order_count, recorded, tracked)
+            .await
+            .map(|res| {
+                res.with_adjustments(adjustments)
+                    .with_pegged(pegged)
+                    .with_repriced(repriced)
+            })
+    }
+
+    pub async fn place_orders(
+        &self,
+        ctx: Context,
+        req: PlaceOrdersRequest,
+    ) -> GatewayResult<TxResponse> {
+        self.place_orders_tracked(ctx, req)
+            .await
+            .map(|(res, _tracked)| res)
+    }
+
+    /// Place orders, returning the tracking ids of those with a user order id
+    async fn place_orders_tracked(
+        &self,
+        ctx: Context,
+        req: PlaceOrdersRequest,
+    ) -> GatewayResult<(TxResponse, Vec<u64>)> {
+        self.check_order_count(req.orders.len())?;
+        self.ensure_leader()?;
+        self.kill_switch.check()?;
+        self.ensure_trading_window(&ctx)?;
+        self.throttle_orders(req.orders.iter().map(|o| o.market))
+            .await?;
+        let sub_account = self.resolve_sub_account(&ctx)?;
+        let account_data = self.get_user_account(&sub_account).await?;
+        let pf = self.get_priority_fee(req.orders.iter().map(|o| o.market));
+        let priority_fee = ctx.cu_price.unwrap_or(pf);
+        debug!(target: LOG_TARGET, "priority fee: {priority_fee:?}");
+
+        let max_slippage_bps: Vec<_> = req.orders.iter().map(|o| o.max_slippage_bps).collect();
+        let pegs: Vec<_> = req.orders.iter().map(|o| o.peg_to_oracle).collect();
+        let mut orders: Vec<OrderParams> = req
+            .orders
+            .into_iter()
+            .map(|o| {
+                let base_decimals = get_market_decimals(self.client.program_data(), o.market);
+                o.to_order_params(base_decimals)
+            })
+            .collect();
+        self.apply_slippage_limits(&mut orders, &max_slippage_bps)
+            .await?;
+        let pegged = self.apply_oracle_pegs(&mut orders, &pegs).await?;
+        let repriced = self.apply_cross_check(&sub_account, &mut orders).await?;
+        let adjustments = reduce_only_guard(&account_data.perp_positions, &mut orders)?;
+        self.check_market_status(
+            orders
+                .iter()
+                .map(|o| (Market::new(o.market_index, o.market_type), o.reduce_only)),
+        )?;
+        self.check_exposure_limits(&account_data, &orders).await?;
+        self.check_compliance(&ctx, orders.iter().map(order_compliance))
+            .await?;
+        let recorded = self.check_duplicate_orders(&ctx, &orders, false)?;
+        let tracked = self.track_orders(&ctx, &orders)?;
+
+        let order_count = orders.len();
+        // only order params and compute budget differ between txs with the same accounts
+        let template_key = TemplateKey::new(sub_account, &account_data, &orders, ctx.cu_limit);
+        let tx =
+            match self
+                .tx_templates
+                .place_orders(&template_key, &orders, priority_fee, ctx.cu_limit)
+            {
+                Some(tx) => tx,
+                None => {
+                    let tx = TransactionBuilder::new(
+                        self.client.program_data(),
+                        sub_account,
+                        Cow::Owned(account_data),
+                        self.wallet.is_delegated(),
+                    )
+                    .with_priority_fee(priority_fee, ctx.cu_limit)
+                    .place_orders(orders)
+                    .build();
+                    self.tx_templates.insert(template_key, tx.clone());
+                    tx
+                }
+            };
+
+        let res = self
+            .send_orders_tx(
+                tx,
+                "place_orders",
+                &ctx,
+                order_count,
+                recorded,
+                tracked.clone(),
+            )
+            .await?;
+        Ok((
+            res.with_adjustments(adjustments)
+                .with_pegged(pegged)
+                .with_repriced(repriced),
+            tracked,
+        ))
+    }
+
+    /// Schedule orders to be placed at a timestamp or slot
+    pub fn schedule_orders(
+        &self,
+        ctx: Context,
+        req: ScheduleOrdersRequest,
+    ) -> GatewayResult<ScheduleOrdersResponse> {
+        let activation = match (req.activate_at, req.activate_slot) {
+            (Some(ts), None) => {
+                if ts <= unix_timestamp() {
+                    return Err(ControllerError::BadRequest(
+                        "activateAt is in the past".to_string(),
+                    ));
+                }
+                Activation::Ts(ts)
+            }
+            (None, Some(slot)) => Activation::Slot(slot),
+            _ => {
+                return Err(ControllerError::BadRequest(
+                    "expected one of activateAt or activateSlot".to_string(),
+                ))
+            }
+        };
+        if req.orders.is_empty() {
+            return Err(ControllerError::BadRequest("no orders".to_string()));
+        }
+        self.check_order_count(req.orders.len())?;
+        // pin the sub-account, names may be reassigned before activation
+        let ctx = Context {
+            sub_account_id: Some(self.resolve_sub_account_id(&ctx)?),
+            sub_account: None,
+            tx_deadline: None,
+            ..ctx
+        };
+        let id = self
+            .scheduled_orders
+            .add(activation, (ctx, req.orders))
+            .map_err(ControllerError::BadRequest)?;
+
+        Ok(ScheduleOrdersResponse { id })
+    }
+
+    /// Return pending scheduled orders
+    pub fn get_scheduled_orders(&self) -> ScheduledOrdersResponse {
+        ScheduledOrdersResponse {
+            scheduled: self
+                .scheduled_orders
+                .list()
+                .into_iter()
+                .map(|s| {
+                    let (ctx, orders) = s.payload;
+                    ScheduledOrders {
+                        id: s.id,
+                        activation: s.activation,
+                        sub_account_id: ctx.sub_account_id.unwrap_or(self.default_subaccount_id),
+                        orders,
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    /// Cancel a pending scheduled order
+    pub fn cancel_scheduled_orders(&self, id: u64) -> GatewayResult<ScheduleOrdersResponse> {
+        match self.scheduled_orders.cancel(id) {
+            Some(_) => Ok(ScheduleOrdersResponse { id }),
+            None => Err(ControllerError::BadRequest(format!(
+                "no scheduled orders: {id}"
+            ))),
+        }
+    }
+
+    /// Place scheduled orders as they become due
+    ///
+    /// orders are placed with the priority fee and blockhash at activation time
+    pub(crate) fn run_order_scheduler(&self) {
+        let state = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SCHEDULER_INTERVAL);
+            // the slot follows the slot subscription, while it's down the slot is fetched via RPC
+            // (and when)
+            let mut fetched_slot: Option<(Instant, u64)> = None;
+            loop {
+                interval.tick().await;
+                let slot = if state.scheduled_orders.has_slot_activations() {
+                    match state.chain_slot.get(unix_timestamp()) {
+                        Some(slot) => Some(slot),
+                        None => match fetched_slot {
+                            Some((at, slot)) if at.elapsed() < SCHEDULER_SLOT_FETCH_INTERVAL => {
+                                Some(slot)
+                            }
+                            _ => match state.client.rpc().get_slot().await {
+                                Ok(slot) => {
+                                    fetched_slot = Some((Instant::now(), slot));
+                                    Some(slot)
+                                }
+                                Err(err) => {
+                                    warn!(target: LOG_TARGET, "scheduler slot fetch failed: {err:?}");
+                                    None
+                                }
+                            },
+                        },
+                    }
+                } else {
+                    None
+                };
+                for scheduled in state.scheduled_orders.take_due(unix_timestamp(), slot) {
+                    let state = state.clone();
+                    tokio::spawn(async move {
+                        let (ctx, orders) = scheduled.payload;
+                        match state.place_orders(ctx, PlaceOrdersRequest { orders }).await {
+                            Ok(res) => {
+                                info!(target: LOG_TARGET, "placed scheduled orders: {}, {res:?}", scheduled.id);
+                            }
+                            Err(err) => {
+                                warn!(target: LOG_TARGET, "scheduled orders failed: {}, {err:?}", scheduled.id);
+                            }
+                        }
+                    });
+                }
+            }
+        });
+    }
+
+    /// Start a recurring order plan
+    pub fn create_dca(&self, ctx: Context, req: DcaRequest) -> GatewayResult<DcaPlan> {
+        if req.amount.is_zero() || req.interval == 0 || req.budget <= Decimal::ZERO {
+            return Err(ControllerError::BadRequest(
+                "amount, interval, and budget must be non-zero".to_string(),
+            ));
+        }
+        // spend is counted from order fills tracked by the sub-account's events
+        if self.wallet.is_emulating() {
+            return Err(ControllerError::BadRequest(
+                "DCA plans can't run in emulation mode".to_string(),
+            ));
+        }
+        let authority = self.resolve_wallet(&ctx)?.authority();
+        let Some(sub_account_id) = self.tracked_sub_account_id(&ctx)? else {
+            return Err(ControllerError::BadRequest(format!(
+                "sub-account {} is not journaled, DCA fills are tracked by its events",
+                self.resolve_sub_account_id(&ctx)?
+            )));
+        };
+        let ctx = Context {
+            authority: Some(authority.to_string()),
+            sub_account_id: Some(sub_account_id),
+            ..Default::default()
+        };
+        let user_order_id = self.reserve_algo_user_order_id(&ctx, req.user_order_id)?;
+        self.dca
+            .add(
+                req.market,
+                req.amount,
+                req.interval,
+                req.budget,
+                user_order_id,
+                ctx.authority.clone(),
+                sub_account_id,
+                req.start_at.unwrap_or_else(unix_timestamp),
+            )
+            .map_err(|err| {
+                self.release_algo_user_order_id(&ctx.authority, sub_account_id, user_order_id);
+                ControllerError::BadRequest(err)
+            })
+    }
+
+    pub fn get_dca_plans(&self) -> DcaPlansResponse {
+        DcaPlansResponse {
+            plans: self.dca.list(),
+        }
+    }
+
+    pub fn get_dca_plan(&self, id: u64) -> GatewayResult<DcaPlan> {
+        self.dca
+            .get(id)
+            .ok_or_else(|| ControllerError::BadRequest(format!("no DCA plan: {id}")))
+    }
+
+    /// Cancel a DCA plan, orders already placed are unaffected
+    pub fn cancel_dca(&self, id: u64) -> GatewayResult<DcaPlan> {
+        self.dca
+            .cancel(id)
+            .ok_or_else(|| ControllerError::BadRequest(format!("no DCA plan: {id}")))
+    }
+
+    /// Track order fills of DCA plans and place orders as they become due
+    pub(crate) fn run_dca(&self) {
+        let state = self.clone();
+        // restored plans keep their user order ids
+        for plan in self.dca.active() {
+            let ctx = Context {
+                authority: plan.authority.clone(),
+                sub_account_id: Some(plan.sub_account_id),
+                ..Default::default()
+            };
+            let claimed = self.resolve_sub_account(&ctx).is_ok_and(|sub_account| {
+                self.user_order_ids.claim(&sub_account, plan.user_order_id)
+            });
+            if !claimed {
+                warn!(target: LOG_TARGET, "DCA plan {} shares userOrderId {}", plan.id, plan.user_order_id);
+            }
+        }
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(DCA_INTERVAL);
+            loop {
+                interval.tick().await;
+                let now = unix_timestamp();
+                state.dca.update_fills(|tracking_id| {
+                    state
+                        .events
+                        .orders()
+                        .by_tracking_id(tracking_id, now)
+                        .map(|status| (status.filled, status.state.is_terminal()))
+                });
+                for plan in state.dca.take_done() {
+                    state.release_algo_user_order_id(
+                        &plan.authority,
+                        plan.sub_account_id,
+                        plan.user_order_id,
+                    );
+                }
+                for plan in state.dca.take_due(now) {
+                    let state = state.clone();
+                    tokio::spawn(async move {
+                        if let Err(err) = state.place_dca_order(&plan).await {
+                            warn!(target: LOG_TARGET, "DCA order failed: {}, {err:?}", plan.id);
+                            state.dca.record_error(plan.id, err.to_string());
+                        }
+                    });
+                }
+            }
+        });
+    }
+
+    async fn place_dca_order(&self, plan: &DcaPlan) -> GatewayResult<()> {
+        let price = self.oracle_price(plan.market.as_market_id()).await?;
+        let amount = plan.order_amount(price);
+        if amount.is_zero() {
+            // the rest of the budget is in open orders
+            if !plan.has_open_orders() {
+                self.dca.complete(plan.id);
+            }
+            return Ok(());
+        }
+        let ctx = Context {
+            authority: plan.authority.clone(),
+            sub_account_id: Some(plan.sub_account_id),
+            ..Default::default()
+        };
+        let mut order = PlaceOrder::market_order(plan.market, amount);
+        order.user_order_id = plan.user_order_id;
+        let (res, tracked) = self
+            .place_orders_tracked(
+                ctx,
+                PlaceOrdersRequest {
+                    orders: vec![order],
+                },
+            )
+            .await?;
+        info!(target: LOG_TARGET, "placed DCA order: {}, {amount} @ ~{price}, {res:?}", plan.id);
+        self.dca.record_order(plan.id, amount, price, &tracked);
+
+        Ok(())
+    }
+
+    /// Start a TWAP algo slicing a parent order into child orders over its duration
+    pub fn create_twap(&self, ctx: Context, req: TwapRequest) -> GatewayResult<TwapAlgo> {
+        if req.amount.is_zero() || req.interval == 0 || req.duration < req.interval {
+            return Err(ControllerError::BadRequest(
+                "amount and interval must be non-zero, duration at least one interval".to_string(),
+            ));
+        }
+        if let Some(participation) = req.max_participation {
+            if participation <= Decimal::ZERO || participation > Decimal::ONE {
+                return Err(ControllerError::BadRequest(format!(
+                    "maxParticipation must be in (0, 1]: {participation}"
+                )));
+            }
+            if req.market.market_type != MarketType::Perp {
+                return Err(ControllerError::BadRequest(
+                    "maxParticipation supports perp markets only".to_string(),
+                ));
+            }
+        }
+        // child order fills are tracked by the sub-account's events
+        if self.wallet.is_emulating() {
+            return Err(ControllerError::BadRequest(
+                "TWAP algos can't run in emulation mode".to_string(),
+            ));
+        }
+        let authority = self.resolve_wallet(&ctx)?.authority();
+        let Some(sub_account_id) = self.tracked_sub_account_id(&ctx)? else {
+            return Err(ControllerError::BadRequest(format!(
+                "sub-account {} is not journaled, TWAP fills are tracked by its events",
+                self.resolve_sub_account_id(&ctx)?
+            )));
+        };
+        let ctx = Context {
+            authority: Some(authority.to_string()),
+            sub_account_id: Some(sub_account_id),
+            ..Default::default()
+        };
+        let user_order_id = self.reserve_algo_user_order_id(&ctx, req.user_order_id)?;
+        self.twap
+            .add(
+                req.market,
+                req.amount,
+                req.duration,
+                req.interval,
+                req.max_participation,
+                req.max_slippage_bps,
+                user_order_id,
+                ctx.authority.clone(),
+                sub_account_id,
+                req.start_at.unwrap_or_else(unix_timestamp),
+            )
+            .map_err(|err| {
+                self.release_algo_user_order_id(&ctx.authority, sub_account_id, user_order_id);
+                ControllerError::BadRequest(err)
+            })
+    }
+
+    pub fn get_twap_algos(&self) -> TwapAlgosResponse {
+        TwapAlgosResponse {
+            algos: self.twap.list(),
+        }
+    }
+
+    pub fn get_twap_algo(&self, id: u64) -> GatewayResult<TwapAlgo> {
+        self.twap
+            .get(id)
+            .ok_or_else(|| ControllerError::BadRequest(format!("no TWAP algo: {id}")))
+    }
+
+    /// Cancel a TWAP algo, child orders already placed are unaffected
+    pub fn cancel_twap(&self, id: u64) -> GatewayResult<TwapAlgo> {
+        self.twap
+            .cancel(id)
+            .ok_or_else(|| ControllerError::BadRequest(format!("no TWAP algo: {id}")))
+    }
+
+    /// Track child order fills of TWAP algos and place child orders as they become due
+    pub(crate) fn run_twap(&self) {
+        let state = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(TWAP_INTERVAL);
+            loop {
+                interval.tick().await;
+                let now = unix_timestamp();
+                state.twap.update_fills(now, |tracking_id| {
+                    state
+                        .events
+                        .orders()
+                        .by_tracking_id(tracking_id, now)
+                        .map(|status| (status.filled, status.state.is_terminal()))
+                });
+                for algo in state.twap.take_done() {
+                    state.release_algo_user_order_id(
+                        &algo.authority,
+                        algo.sub_account_id,
+                        algo.user_order_id,
+                    );
+                }
+                for algo in state.twap.take_due(now) {
+                    let state = state.clone();
+                    tokio::spawn(async move {
+                        if let Err(err) = state.place_twap_order(&algo, now).await {
+                            warn!(target: LOG_TARGET, "TWAP order failed: {}, {err:?}", algo.id);
+                            state.twap.record_error(algo.id, err.to_string());
+                        }
+                        state.twap.sent(algo.id);
+                    });
+                }
+            }
+        });
+    }
+
+    async fn place_twap_order(&self, algo: &TwapAlgo, ts: u64) -> GatewayResult<()> {
+        let max_amount = match algo.max_participation {
+            Some(participation) => {
+                let market = self.perp_market_account(algo.market.market_index).await?;
+                let price = self.oracle_price(algo.market.as_market_id()).await?;
+                if price.is_zero() {
+                    return Err(ControllerError::BadRequest("zero oracle price".to_string()));
+                }
+                let volume = Decimal::new(market.amm.volume_24h as i64, QUOTE_DECIMALS);
+                Some(
+                    participation * volume * Decimal::from(algo.interval)
+                        / Decimal::from(86_400)
+                        / price,
+                )
+            }
+            None => None,
+        };
+        let amount = algo.child_amount(ts, max_amount);
+        if amount.is_zero() {
+            return Ok(());
+        }
+        // child orders of equal amounts are expected
+        let ctx = Context {
+            authority: algo.authority.clone(),
+            sub_account_id: Some(algo.sub_account_id),
+            allow_duplicate: true,
+            ..Default::default()
+        };
+        let mut order = PlaceOrder::market_order(algo.market, amount);
+        order.user_order_id = algo.user_order_id;
+        order.max_slippage_bps = algo.max_slippage_bps;
+        let (res, tracked) = self
+            .place_orders_tracked(
+                ctx,
+                PlaceOrdersRequest {
+                    orders: vec![order],
+                },
+            )
+            .await?;
+        info!(target: LOG_TARGET, "placed TWAP order: {}, {amount}, {res:?}", algo.id);
+        self.twap.record_order(algo.id, amount, &tracked);
+
+        Ok(())
+    }
+
+    /// Add a trigger that places or cancels orders once its condition is met
+    pub fn create_trigger(&self, ctx: Context, req: TriggerRequest) -> GatewayResult<Trigger> {
+        let condition = req.condition;
+        if matches!(
+            condition.metric,
+            Metric::FundingRate | Metric::UnrealizedPnl
+        ) && condition.market.market_type != MarketType::Perp
+        {
+            return Err(ControllerError::BadRequest(format!(
+                "{} requires a perp market",
+                camel_case(&condition.metric)
+            )));
+        }
+        if let TriggerAction::Place { ref orders } = req.action {
+            if orders.is_empty() {
+                return Err(ControllerError::BadRequest("no orders".to_string()));
+            }
+            self.check_order_count(orders.len())?;
+        }
+        let authority = self.resolve_wallet(&ctx)?.authority().to_string();
+        self.triggers
+            .add(
+                condition,
+                req.action,
+                Some(authority),
+                self.resolve_sub_account_id(&ctx)?,
+            )
+            .map_err(ControllerError::BadRequest)
+    }
+
+    pub fn get_triggers(&self) -> TriggersResponse {
+        TriggersResponse {
+            triggers: self.triggers.list(),
+        }
+    }
+
+    pub fn cancel_trigger(&self, id: u64) -> GatewayResult<Trigger> {
+        self.triggers
+            .cancel(id)
+            .ok_or_else(|| ControllerError::BadRequest(format!("no trigger: {id}")))
+    }
 
-        self.send_tx(tx, "cancel_and_place", ctx.ttl).await
+    /// Start the DLOB subscription ahead of the first order checked against it
+    pub(crate) fn load_dlob(&self) {
+        let (dlob, client) = (Arc::clone(&self.dlob), Arc::clone(&self.client));
+        tokio::spawn(async move {
+            let orders = dlob.orders(&client).await;
+            info!(target: LOG_TARGET, "dlob loaded: {} orders", orders.len());
+        });
     }
 
-    pub async fn place_orders(
-        &self,
-        ctx: Context,
-        req: PlaceOrdersRequest,
-    ) -> GatewayResult<TxResponse> {
-        let sub_account = self.resolve_sub_account(ctx.sub_account_id);
-        let account_data = self.client.get_user_account(&sub_account).await?;
-        let pf = self.get_priority_fee();
-        let priority_fee = ctx.cu_price.unwrap_or(pf);
-        debug!(target: LOG_TARGET, "priority fee: {priority_fee:?}");
+    /// Evaluate trigger conditions, running the actions of those met
+    pub(crate) fn monitor_triggers(&self) {
+        let state = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(TRIGGER_INTERVAL);
+            loop {
+                interval.tick().await;
+                for trigger in state.triggers.active() {
+                    let value = match state.trigger_metric(&trigger).await {
+                        Ok(value) => value,
+                        Err(err) => {
+                            debug!(target: LOG_TARGET, "trigger metric unavailable: {}, {err:?}", trigger.id);
+                            continue;
+                        }
+                    };
+                    if !trigger.condition.is_met(value)
+                        || !state.triggers.fire(trigger.id, value, unix_timestamp())
+                    {
+                        continue;
+                    }
+                    info!(target: LOG_TARGET, "trigger met: {}, {:?} = {value}", trigger.id, trigger.condition.metric);
+                    let state = state.clone();
+                    tokio::spawn(async move {
+                        let ctx = Context {
+                            authority: trigger.authority.clone(),
+                            sub_account_id: Some(trigger.sub_account_id),
+                            ..Default::default()
+                        };
+                        let res = match trigger.action {
+                            TriggerAction::Place { orders } => {
+                                state.place_orders(ctx, PlaceOrdersRequest { orders }).await
+                            }
+                            TriggerAction::Cancel(req) => state.cancel_orders(ctx, req).await,
+                        };
+                        if let Err(ref err) = res {
+                            warn!(target: LOG_TARGET, "trigger action failed: {}, {err:?}", trigger.id);
+                        }
+                        state.triggers.record_result(
+                            trigger.id,
+                            res.map(|r| r.signature().to_string())
+                                .map_err(|err| err.to_string()),
+                        );
+                    });
+                }
+            }
+        });
+    }
 
-        let orders = req
-            .orders
-            .into_iter()
-            .map(|o| {
-                let base_decimals = get_market_decimals(self.client.program_data(), o.market);
-                o.to_order_params(base_decimals)
-            })
-            .collect();
-        let tx = TransactionBuilder::new(
-            self.client.program_data(),
-            sub_account,
-            Cow::Owned(account_data),
-            self.wallet.is_delegated(),
-        )
-        .with_priority_fee(priority_fee, ctx.cu_limit)
-        .place_orders(orders)
-        .build();
+    /// Return the current value of a trigger's metric
+    async fn trigger_metric(&self, trigger: &Trigger) -> GatewayResult<Decimal> {
+        let market = trigger.condition.market;
+        let value = match trigger.condition.metric {
+            Metric::FundingRate => {
+                let perp = self.perp_market_account(market.market_index).await?;
+                Decimal::new(perp.amm.last_funding_rate, FUNDING_RATE_DECIMALS)
+            }
+            Metric::OraclePrice => self.oracle_price(market.as_market_id()).await?,
+            Metric::OracleConfidence => {
+                let oracle = self
+                    .client
+                    .get_oracle_price_data_and_slot(market.as_market_id())
+                    .await?;
+                Decimal::new(oracle.data.confidence as i64, PRICE_DECIMALS)
+            }
+            Metric::UnrealizedPnl => {
+                let ctx = Context {
+                    authority: trigger.authority.clone(),
+                    ..Default::default()
+                };
+                let sub_account = self
+                    .resolve_wallet(&ctx)?
+                    .sub_account(trigger.sub_account_id);
+                let user = self.get_user_account(&sub_account).await?;
+                let calc = calculate_liquidation_price_and_unrealized_pnl(
+                    &self.client,
+                    &user,
+                    market.market_index,
+                )
+                .await?;
+                Decimal::new(calc.unrealized_pnl as i64, PRICE_DECIMALS)
+            }
+        };
 
-        self.send_tx(tx, "place_orders", ctx.ttl).await
+        Ok(value)
     }
 
     pub async fn modify_orders(
@@ -521,9 +4042,24 @@ impl AppState {
         ctx: Context,
         req: ModifyOrdersRequest,
     ) -> GatewayResult<TxResponse> {
-        let sub_account = self.resolve_sub_account(ctx.sub_account_id);
-        let account_data = self.client.get_user_account(&sub_account).await?;
-        let pf = self.get_priority_fee();
+        self.check_order_count(req.orders.len())?;
+        self.ensure_leader()?;
+        self.kill_switch.check()?;
+        self.ensure_trading_window(&ctx)?;
+        self.throttle_orders(req.orders.iter().map(|o| o.market))
+            .await?;
+        // the reduce-only market check is left to the program, a modified order's side isn't known here
+        self.check_market_status(req.orders.iter().map(|o| (o.market, true)))?;
+        self.check_compliance(
+            &ctx,
+            req.orders
+                .iter()
+                .map(|o| (o.market.as_market_id(), o.direction(), o.is_reduce_only())),
+        )
+        .await?;
+        let sub_account = self.resolve_sub_account(&ctx)?;
+        let account_data = self.get_user_account(&sub_account).await?;
+        let pf = self.get_priority_fee(req.orders.iter().map(|o| o.market));
         let builder = TransactionBuilder::new(
             self.client.program_data(),
             sub_account,
@@ -532,7 +4068,98 @@ impl AppState {
         )
         .with_priority_fee(ctx.cu_price.unwrap_or(pf), ctx.cu_limit);
         let tx = build_modify_ix(builder, req, self.client.program_data())?.build();
-        self.send_tx(tx, "modify_orders", ctx.ttl).await
+        self.send_tx(tx, "modify_orders", &ctx).await
+    }
+
+    /// Return the confirmation status of a tx sent via the gateway
+    pub async fn get_tx_status(&self, signature: &str) -> GatewayResult<TxStatus> {
+        let tx_signature = Signature::from_str(signature)
+            .map_err(|_| ControllerError::BadRequest(format!("invalid signature: {signature}")))?;
+        let tx = self
+            .txs
+            .get(&tx_signature)
+            .ok_or_else(|| ControllerError::TxNotFound {
+                tx_sig: signature.to_owned(),
+            })?;
+        if tx.state != TxState::Expired {
+            return Ok(tx);
+        }
+        // an expired tx may have landed late e.g. its nonce couldn't be advanced
+        match signature_status(&self.tx_rpc, &tx_signature).await {
+            Some(status) => {
+                self.txs.landed(&tx_signature, &status, unix_timestamp());
+                Ok(self.txs.get(&tx_signature).unwrap_or(tx))
+            }
+            None => Ok(tx),
+        }
+    }
+
+    /// Journal the events of webhook delivered `txs` for the subscribed sub-accounts
+    ///
+    /// txs without logs are fetched, events journaled already e.g. via the event stream are ignored.
+    /// fetched txs that failed are skipped
+    pub async fn ingest_webhook_txs(&self, txs: Vec<WebhookTx>) -> GatewayResult<WebhookResponse> {
+        let mut decoded = 0;
+        for tx in txs {
+            let logs = match tx.logs {
+                Some(logs) => logs,
+                None => match self.fetch_tx_logs(&tx.signature).await {
+                    Ok(logs) => logs,
+                    Err(err) => {
+                        warn!(target: LOG_TARGET, "webhook tx fetch failed: {}, {err:?}", tx.signature);
+                        continue;
+                    }
+                },
+            };
+            for (tx_idx, log) in logs.iter().enumerate() {
+                let Some(event) = try_parse_log(log.as_str(), &tx.signature, tx_idx) else {
+                    continue;
+                };
+                for id in &self.journaled_sub_accounts() {
+                    self.events.push_event(
+                        &self.client,
+                        *id,
+                        self.wallet.inner().sub_account(*id),
+                        &event,
+                    );
+                }
+            }
+            decoded += 1;
+        }
+        Ok(WebhookResponse { txs: decoded })
+    }
+
+    /// Fetch the log messages of the tx `signature`, empty if it failed
+    async fn fetch_tx_logs(&self, signature: &str) -> GatewayResult<Vec<String>> {
+        let signature = Signature::from_str(signature)
+            .map_err(|_| ControllerError::BadRequest(format!("invalid signature: {signature}")))?;
+        let tx = deadline("tx fetch", self.timeouts.account, async {
+            self.client
+                .rpc()
+                .get_transaction_with_config(
+                    &signature,
+                    RpcTransactionConfig {
+                        encoding: Some(UiTransactionEncoding::Base64),
+                        commitment: Some(CommitmentConfig::confirmed()),
+                        max_supported_transaction_version: Some(0),
+                    },
+                )
+                .await
+                .map_err(|err| ControllerError::Sdk(err.into()))
+        })
+        .await?;
+        match tx.transaction.meta {
+            // logs of failed txs include events of the reverted instructions
+            Some(meta) if meta.err.is_some() => {
+                debug!(target: LOG_TARGET, "webhook tx failed: {signature}");
+                Ok(Vec::new())
+            }
+            Some(meta) => match meta.log_messages {
+                OptionSerializer::Some(logs) => Ok(logs),
+                _ => Ok(Vec::new()),
+            },
+            None => Ok(Vec::new()),
+        }
     }
 
     pub async fn get_tx_events_for_subaccount_id(
@@ -567,7 +4194,7 @@ impl AppState {
                 if let Some(meta) = tx.transaction.meta {
                     match meta.log_messages {
                         OptionSerializer::Some(logs) => {
-                            let sub_account = self.resolve_sub_account(ctx.sub_account_id);
+                            let sub_account = self.resolve_sub_account(&ctx)?;
                             for (tx_idx, log) in logs.iter().enumerate() {
                                 if let Some(evt) = try_parse_log(log.as_str(), tx_sig, tx_idx) {
                                     let (_, gw_event) = map_drift_event_for_account(
@@ -620,18 +4247,397 @@ impl AppState {
         }
     }
 
-    fn get_priority_fee(&self) -> u64 {
-        self.priority_fee_subscriber.priority_fee_nth(0.9)
+    /// Return daily API usage rollups by authority
+    pub fn get_usage(&self, req: UsageRequest) -> GatewayResult<UsageResponse> {
+        let to = req.to.unwrap_or_else(unix_timestamp);
+        let from = req.from.unwrap_or(to.saturating_sub(7 * 86_400));
+        Ok(UsageResponse {
+            usage: self
+                .usage
+                .query(from, to, req.authority.as_deref(), req.api_key.as_deref()),
+        })
+    }
+
+    /// Return daily tx fee spend rollups by sub-account
+    pub fn get_fee_spend(&self, req: FeeSpendRequest) -> GatewayResult<FeeSpendResponse> {
+        let to = req.to.unwrap_or_else(unix_timestamp);
+        let from = req.from.unwrap_or(to.saturating_sub(7 * 86_400));
+        Ok(FeeSpendResponse {
+            spend: self.fee_spend.query(from, to, req.sub_account.as_deref()),
+        })
+    }
+
+    /// Return active subscriptions and their health
+    pub fn get_subscriptions(&self) -> GatewayResult<SubscriptionsResponse> {
+        Ok(SubscriptionsResponse {
+            subscriptions: self.subscriptions.list(unix_timestamp()),
+        })
+    }
+
+    /// Resolve the registered subscription of a request
+    fn subscription_of(&self, req: &SubscriptionRequest) -> GatewayResult<SubscriptionId> {
+        let id = subscription_id(req.kind, req.market, req.account.as_deref())
+            .map_err(ControllerError::BadRequest)?;
+        if !self.subscriptions.contains(&id) {
+            return Err(ControllerError::BadRequest(format!(
+                "not subscribed: {id:?}"
+            )));
+        }
+        Ok(id)
+    }
+
+    /// Apply runtime `settings`, or those of the runtime config file if unset
+    ///
+    /// settings are checked and markets subscribed before any other setting is applied, markets
+    /// subscribed by previous settings and left out are unsubscribed
+    pub async fn reload_settings(
+        &self,
+        settings: Option<RuntimeSettings>,
+    ) -> GatewayResult<RuntimeSettings> {
+        let settings = match settings {
+            Some(settings) => settings,
+            None => {
+                let path = self.settings_file.as_ref().ok_or_else(|| {
+                    ControllerError::BadRequest(
+                        "no runtime config file, see --runtime-config".to_string(),
+                    )
+                })?;
+                RuntimeSettings::load(path).map_err(ControllerError::BadRequest)?
+            }
+        };
+        settings.validate().map_err(ControllerError::BadRequest)?;
+        let markets = settings
+            .markets
+            .as_deref()
+            .unwrap_or_default()
+            .iter()
+            .map(|ticker| {
+                self.client
+                    .market_lookup(ticker)
+                    .ok_or_else(|| ControllerError::BadRequest(format!("invalid market: {ticker}")))
+            })
+            .collect::<GatewayResult<Vec<MarketId>>>()?;
+        let extra_rpcs = settings
+            .extra_rpcs
+            .as_ref()
+            .map(|urls| {
+                urls.iter()
+                    .map(|url| {
+                        let url = get_http_url(url).map_err(|_| {
+                            ControllerError::BadRequest(format!("invalid RPC url: {url}"))
+                        })?;
+                        Ok(Arc::new(rpc_client(
+                            &self.rpc_http,
+                            url,
+                            CommitmentConfig::default(),
+                        )))
+                    })
+                    .collect::<GatewayResult<Vec<_>>>()
+            })
+            .transpose()?;
+        let level = settings
+            .log_level_filter()
+            .map_err(ControllerError::BadRequest)?;
+
+        let mut settings_markets = self.settings_markets.lock().await;
+        let subscribed = self.subscribe_markets(&markets).await?;
+        let (kept, removed): (Vec<MarketId>, Vec<MarketId>) = settings_markets
+            .iter()
+            .copied()
+            .partition(|m| settings.markets.is_none() || markets.contains(m));
+        if !removed.is_empty() {
+            info!(target: LOG_TARGET, "stop market subscriptions: {removed:?}");
+            self.market_data.unsubscribe_oracles(&removed).await?;
+            self.market_data.unsubscribe_markets(&removed).await?;
+            for m in &removed {
+                self.subscriptions.remove(&SubscriptionId::Market(*m));
+                self.subscriptions.remove(&SubscriptionId::Oracle(*m));
+            }
+        }
+        *settings_markets = kept.into_iter().chain(subscribed).collect();
+
+        if let Some(level) = level {
+            set_gateway_level(level);
+        }
+        if let Some(percentile) = settings.priority_fee_percentile {
+            self.priority_fees.set_percentile(percentile);
+        }
+        if let Some(max_fee) = settings.max_priority_fee {
+            self.priority_fees
+                .set_max_fee((max_fee > 0).then_some(max_fee));
+        }
+        if let Some(extra_rpcs) = extra_rpcs {
+            *self.extra_rpcs.write().expect("lock") = extra_rpcs;
+        }
+        info!(target: LOG_TARGET, "runtime settings applied: {settings:?}");
+        Ok(settings)
+    }
+
+    /// Reload the runtime config file on SIGHUP, instead of terminating
+    #[cfg(unix)]
+    pub(crate) fn reload_on_hangup(&self) {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut hangup = signal(SignalKind::hangup()).expect("SIGHUP handler installed");
+        let state = self.clone();
+        tokio::spawn(async move {
+            while hangup.recv().await.is_some() {
+                info!(target: LOG_TARGET, "SIGHUP, reloading runtime settings");
+                if let Err(err) = state.reload_settings(None).await {
+                    warn!(target: LOG_TARGET, "runtime settings reload failed: {err}");
+                }
+            }
+        });
+    }
+
+    /// Subscribe market and oracle data of `markets` not subscribed yet, returning those
+    async fn subscribe_markets(&self, markets: &[MarketId]) -> GatewayResult<Vec<MarketId>> {
+        let markets: Vec<MarketId> = markets
+            .iter()
+            .copied()
+            .filter(|m| {
+                !self.incompatible_markets.contains(m)
+                    && !self.subscriptions.contains(&SubscriptionId::Market(*m))
+            })
+            .collect();
+        if markets.is_empty() {
+            return Ok(markets);
+        }
+        info!(target: LOG_TARGET, "start market subscriptions: {markets:?}");
+        self.market_data.subscribe_oracles(&markets).await?;
+        self.market_data.subscribe_markets(&markets).await?;
+        self.add_market_subscriptions(&markets);
+        self.priority_fees
+            .warm(self.client.program_data(), &markets);
+        Ok(markets)
+    }
+
+    /// Register the market and oracle subscriptions of `markets`
+    fn add_market_subscriptions(&self, markets: &[MarketId]) {
+        self.subscriptions.add(
+            markets
+                .iter()
+                .flat_map(|m| [SubscriptionId::Market(*m), SubscriptionId::Oracle(*m)]),
+            unix_timestamp(),
+        );
+    }
+
+    /// Close a subscription and subscribe again, e.g. after it stalled
+    pub async fn resubscribe(
+        &self,
+        req: SubscriptionRequest,
+    ) -> GatewayResult<SubscriptionsResponse> {
+        let id = self.subscription_of(&req)?;
+        info!(target: LOG_TARGET, "resubscribing: {id:?}");
+        self.resubscribe_id(id).await?;
+        self.get_subscriptions()
+    }
+
+    async fn resubscribe_id(&self, id: SubscriptionId) -> GatewayResult<()> {
+        match id {
+            SubscriptionId::Market(m) => {
+                self.market_data.unsubscribe_markets(&[m]).await?;
+                self.market_data.subscribe_markets(&[m]).await?;
+            }
+            SubscriptionId::Oracle(m) => {
+                self.market_data.unsubscribe_oracles(&[m]).await?;
+                self.market_data.subscribe_oracles(&[m]).await?;
+            }
+            SubscriptionId::Account(a) => {
+                self.unsubscribe_user_account(&a)?;
+                self.subscribe_user_account(&a).await?;
+            }
+        }
+        self.subscriptions.resubscribed(id, unix_timestamp());
+        Ok(())
+    }
+
+    /// Close a subscription, its data is fetched via RPC afterwards
+    pub async fn unsubscribe(
+        &self,
+        req: SubscriptionRequest,
+    ) -> GatewayResult<SubscriptionsResponse> {
+        let id = self.subscription_of(&req)?;
+        info!(target: LOG_TARGET, "unsubscribing: {id:?}");
+        match id {
+            SubscriptionId::Market(m) => self.market_data.unsubscribe_markets(&[m]).await?,
+            SubscriptionId::Oracle(m) => self.market_data.unsubscribe_oracles(&[m]).await?,
+            SubscriptionId::Account(a) => self.unsubscribe_user_account(&a)?,
+        }
+        self.subscriptions.remove(&id);
+        self.get_subscriptions()
+    }
+
+    /// Measure tx build, sign, send, and confirmation latency against the configured RPCs
+    ///
+    /// Each iteration sends a real cancel tx for a non-existent order id, it lands onchain
+    /// and pays normal tx fees without affecting any orders
+    pub async fn bench(&self, ctx: Context, req: BenchRequest) -> GatewayResult<BenchResponse> {
+        self.ensure_leader()?;
+        if self.wallet.is_emulating() {
+            return Err(ControllerError::BadRequest(
+                "bench unavailable in emulation mode".to_string(),
+            ));
+        }
+        let iterations = req.iterations.clamp(1, MAX_BENCH_ITERATIONS);
+        let sub_account = self.resolve_sub_account(&ctx)?;
+        let tx_config = RpcSendTransactionConfig {
+            max_retries: Some(0),
+            preflight_commitment: Some(self.tx_commitment.commitment),
+            skip_preflight: self.skip_tx_preflight,
+            ..Default::default()
+        };
+
+        let mut build = Vec::with_capacity(iterations as usize);
+        let mut sign = Vec::with_capacity(iterations as usize);
+        let mut send = Vec::with_capacity(iterations as usize);
+        let mut confirm = Vec::with_capacity(iterations as usize);
+        let mut signatures = Vec::with_capacity(iterations as usize);
+
+        // fetched once up front so `build` times tx building alone
+        let account_data = self.get_user_account(&sub_account).await?;
+        for i in 0..iterations {
+            let t0 = Instant::now();
+            let tx = TransactionBuilder::new(
+                self.client.program_data(),
+                sub_account,
+                Cow::Borrowed(&account_data),
+                self.wallet.is_delegated(),
+            )
+            .with_priority_fee(
+                ctx.cu_price.unwrap_or(self.get_priority_fee([])),
+                ctx.cu_limit,
+            )
+            .cancel_orders_by_id(vec![u32::MAX])
+            .build();
+            build.push(t0.elapsed());
+
+            // signing only, the blockhash is served by its subscription
+            let recent_block_hash = self.client.get_latest_blockhash().await?;
+            let t0 = Instant::now();
+            let tx = self
+                .tx_signer
+                .sign(tx, recent_block_hash)
+                .await
+                .map_err(ControllerError::Signer)?;
+            sign.push(t0.elapsed());
+
+            let t0 = Instant::now();
+            let signature = self
+                .tx_rpc
+                .send_transaction_with_config(&tx, tx_config)
+                .await
+                .map_err(|err| handle_tx_err(err.into()))?;
+            send.push(t0.elapsed());
+
+            while t0.elapsed() < BENCH_CONFIRM_TIMEOUT {
+                if let Ok(Some(_)) = self.tx_rpc.get_signature_status(&signature).await {
+                    confirm.push(t0.elapsed());
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+            debug!(target: LOG_TARGET, "bench tx {i}: {signature}");
+            signatures.push(signature.to_string());
+        }
+
+        Ok(BenchResponse {
+            iterations,
+            confirmed: confirm.len() as u32,
+            build: LatencyStats::from_samples(&mut build),
+            sign: LatencyStats::from_samples(&mut sign),
+            send: LatencyStats::from_samples(&mut send),
+            confirm: LatencyStats::from_samples(&mut confirm),
+            signatures,
+        })
+    }
+
+    /// Return the priority fee for a tx referencing `markets`
+    fn get_priority_fee(&self, markets: impl IntoIterator<Item = Market>) -> u64 {
+        self.priority_fees
+            .priority_fee(self.client.program_data(), markets)
     }
 
     async fn send_tx(
         &self,
         tx: VersionedMessage,
         reason: &'static str,
-        ttl: Option<u16>,
+        ctx: &Context,
     ) -> GatewayResult<TxResponse> {
-        let recent_block_hash = self.client.get_latest_blockhash().await?;
-        let tx = self.wallet.inner().sign_tx(tx, recent_block_hash)?;
+        ensure_tx_deadline("tx build", ctx.tx_deadline)?;
+        // emulated txs are not sent, so hold no nonce
+        let nonce = match self.nonces {
+            Some(ref nonces) if !self.wallet.is_emulating() => {
+                Some(nonces.acquire().ok_or_else(|| {
+                    ControllerError::RateLimited("all nonce accounts in use".to_owned())
+                })?)
+            }
+            _ => None,
+        };
+        let (tx, recent_block_hash) = match nonce {
+            Some(ref lease) => {
+                let mut tx = tx;
+                lease.advance(&mut tx);
+                let nonce = within_tx_deadline(
+                    "nonce fetch",
+                    self.timeouts.send,
+                    ctx.tx_deadline,
+                    lease.fetch(&self.tx_rpc),
+                )
+                .await?;
+                (tx, nonce)
+            }
+            None => {
+                let recent_block_hash = within_tx_deadline(
+                    "blockhash fetch",
+                    self.timeouts.send,
+                    ctx.tx_deadline,
+                    async { self.client.get_latest_blockhash().await.map_err(Into::into) },
+                )
+                .await?;
+                (tx, recent_block_hash)
+            }
+        };
+        // durable nonce txs don't expire with the blockhash and may be resent for longer
+        let ttl = Duration::from_secs(match nonce {
+            Some(_) => ctx.ttl.unwrap_or(DEFAULT_NONCE_TX_TTL) as u64,
+            None => ctx.ttl.unwrap_or(DEFAULT_TX_TTL) as u64,
+        });
+        if self.wallet.is_emulating() {
+            let mut tx = tx;
+            tx.set_recent_blockhash(recent_block_hash);
+            debug!(target: LOG_TARGET, "built unsigned tx ({reason})");
+            return Ok(TxResponse::unsigned(BASE64.encode(tx.serialize())));
+        }
+        // the tip is paid by the order tx itself, so only if it lands
+        let tx = match self.jito {
+            Some(ref jito) => {
+                let mut tx = tx;
+                jito.add_tip(&mut tx, &self.signer());
+                tx
+            }
+            None => tx,
+        };
+        let tx = within_tx_deadline(
+            "tx sign",
+            self.timeouts.send,
+            ctx.tx_deadline,
+            self.tx_signer
+                .sign(tx, recent_block_hash)
+                .map(|tx| tx.map_err(ControllerError::Signer)),
+        )
+        .await?;
+        // fees are accounted to the sub-account of the request once the tx lands
+        let sub_account = self
+            .resolve_sub_account(ctx)
+            .map_or_else(|_| "unknown".to_owned(), |s| s.to_string());
+        // late orders are rejected rather than sent, once sent the tx can't be recalled
+        ensure_tx_deadline("tx sign", ctx.tx_deadline)?;
+        if let Some(ref jito) = self.jito {
+            return self
+                .send_bundle(jito, tx, reason, ttl, nonce, sub_account)
+                .await;
+        }
         let tx_config = RpcSendTransactionConfig {
             max_retries: Some(0),
             preflight_commitment: Some(self.tx_commitment.commitment),
@@ -639,42 +4645,62 @@ impl AppState {
             ..Default::default()
         };
 
+        // serialized once for all sends
+        let encoded_tx = EncodedTx::new(&tx);
+
         // submit to primary RPC first,
-        let sig = self
-            .client
-            .rpc()
-            .send_transaction_with_config(&tx, tx_config)
-            .await
-            .inspect(|s| {
-                debug!(target: LOG_TARGET, "sent tx ({reason}): {s}");
-            })
-            .map_err(|err| {
-                warn!(target: LOG_TARGET, "sending tx ({reason}) failed: {err:?}");
-                // tx has some program/logic error, retry won't fix
-                handle_tx_err(err.into())
-            })?;
+        let send = deadline("send", self.timeouts.send, async {
+            send_encoded_tx(&self.tx_rpc, &encoded_tx, tx_config)
+                .await
+                .inspect(|s| {
+                    debug!(target: LOG_TARGET, "sent tx ({reason}): {s}");
+                })
+                .map_err(|err| {
+                    warn!(target: LOG_TARGET, "sending tx ({reason}) failed: {err:?}");
+                    // tx has some program/logic error, retry won't fix
+                    handle_tx_err(err.into())
+                })
+        })
+        .await;
+        // a timed out send may still land, the tx is rebroadcast as usual and reported pending
+        // rather than failed so clients poll its status instead of resubmitting
+        let (sig, pending) = match send {
+            Ok(sig) => (sig, false),
+            Err(ControllerError::Timeout(stage)) => {
+                warn!(target: LOG_TARGET, "sending tx ({reason}) timed out ({stage}), rebroadcasting");
+                (tx.signatures[0], true)
+            }
+            Err(err) => return Err(err),
+        };
 
         // start a dedicated tx sending task
         // - tx is broadcast to all available RPCs
         // - retried at set intervals
         // - retried upto some given deadline
         // client should poll for the tx to confirm success
-        let primary_rpc = Arc::clone(&self.client).rpc();
+        let primary_rpc = Arc::clone(&self.tx_rpc);
         let tx_signature = sig;
-        let extra_rpcs = self.extra_rpcs.clone();
-        tokio::spawn(async move {
+        let extra_rpcs = self.extra_rpcs.read().expect("lock").clone();
+        let alerter = Arc::clone(&self.alerter);
+        let status = Arc::clone(&self.status);
+        let txs = Arc::clone(&self.txs);
+        let fee_spend = Arc::clone(&self.fee_spend);
+        let fees = TxFees::of(&tx.message);
+        let signer = Arc::clone(&self.tx_signer);
+        txs.submitted(tx_signature, RequestId::current(), unix_timestamp());
+        // rebroadcasts are logged with the id of the originating request
+        tokio::spawn(inherit(async move {
             let start = SystemTime::now();
-            let ttl = Duration::from_secs(ttl.unwrap_or(DEFAULT_TX_TTL) as u64);
-            let mut confirmed = false;
+            let mut landed = None;
             while SystemTime::now()
                 .duration_since(start)
                 .is_ok_and(|x| x < ttl)
             {
                 let mut futs = FuturesUnordered::new();
                 for rpc in extra_rpcs.iter() {
-                    futs.push(rpc.send_transaction_with_config(&tx, tx_config));
+                    futs.push(send_encoded_tx(rpc, &encoded_tx, tx_config));
                 }
-                futs.push(primary_rpc.send_transaction_with_config(&tx, tx_config));
+                futs.push(send_encoded_tx(&primary_rpc, &encoded_tx, tx_config));
 
                 while let Some(res) = futs.next().await {
                     match res {
@@ -689,21 +4715,341 @@ impl AppState {
 
                 tokio::time::sleep(Duration::from_millis(400)).await;
 
-                if let Ok(Some(Ok(()))) = primary_rpc.get_signature_status(&tx_signature).await {
-                    confirmed = true;
-                    info!(target: LOG_TARGET, "tx confirmed onchain: {tx_signature:?}");
-                    break;
+                let Some(status) = signature_status(&primary_rpc, &tx_signature).await else {
+                    continue;
+                };
+                match txs.landed(&tx_signature, &status, unix_timestamp()) {
+                    Some(state @ (TxState::Confirmed | TxState::Finalized)) => {
+                        info!(target: LOG_TARGET, "tx confirmed onchain: {tx_signature:?}");
+                        landed = Some(state);
+                        break;
+                    }
+                    // failed txs land too, resending can't change the result
+                    Some(TxState::Failed) => {
+                        let err = status.err;
+                        warn!(target: LOG_TARGET, "tx failed onchain: {tx_signature:?}, {err:?}");
+                        landed = Some(TxState::Failed);
+                        break;
+                    }
+                    _ => (),
                 }
             }
+            if landed.is_none() {
+                debug!(target: LOG_TARGET, "tx not confirmed within ttl, awaiting expiry: {tx_signature:?}");
+                landed = await_expiry(
+                    &primary_rpc,
+                    &txs,
+                    &tx_signature,
+                    &recent_block_hash,
+                    nonce,
+                    signer.as_ref(),
+                )
+                .await;
+            } else {
+                // the nonce account is released once the tx lands
+                drop(nonce);
+            }
+            if landed.is_some() {
+                record_fees(
+                    &primary_rpc,
+                    &fee_spend,
+                    &tx_signature,
+                    &sub_account,
+                    fees,
+                    0,
+                )
+                .await;
+            }
+            let confirmed = matches!(landed, Some(TxState::Confirmed | TxState::Finalized));
             if !confirmed {
                 warn!(target: LOG_TARGET, "tx was not confirmed: {tx_signature:?}");
             }
-        });
+            // txs that never land don't count, congestion alone doesn't raise a failure streak
+            if landed.is_some() {
+                alerter.record_landed_tx(confirmed);
+                status.record_landed_tx(confirmed);
+            }
+            if confirmed {
+                await_finalized(&primary_rpc, &txs, &tx_signature).await;
+            }
+        }));
+
+        let res = TxResponse::new(sig.to_string());
+        Ok(if pending { res.pending() } else { res })
+    }
+
+    /// Send a signed tx carrying a tip ix as a Jito bundle, polling the bundle status until it
+    /// lands or the tx TTL expires
+    ///
+    /// bundles that fail or are dropped are resent, a timed out send is reported pending like RPC
+    /// sends
+    async fn send_bundle(
+        &self,
+        jito: &Arc<Jito>,
+        tx: VersionedTransaction,
+        reason: &'static str,
+        ttl: Duration,
+        nonce: Option<NonceLease>,
+        sub_account: String,
+    ) -> GatewayResult<TxResponse> {
+        let sig = tx.signatures[0];
+        let fees = TxFees::of(&tx.message);
+        let send = deadline("send", self.timeouts.send, async {
+            jito.send_bundle(std::slice::from_ref(&tx))
+                .await
+                .inspect(|id| {
+                    debug!(target: LOG_TARGET, "sent bundle ({reason}): {id}, tx: {sig}");
+                })
+                .map_err(|err| {
+                    warn!(target: LOG_TARGET, "sending bundle ({reason}) failed: {err}");
+                    ControllerError::Bundle(err)
+                })
+        })
+        .await;
+        // a timed out send may still land, it is resent as usual and reported pending
+        let (mut bundle_id, pending) = match send {
+            Ok(id) => (Some(id), false),
+            Err(ControllerError::Timeout(stage)) => {
+                warn!(target: LOG_TARGET, "sending bundle ({reason}) timed out ({stage}), resending");
+                (None, true)
+            }
+            Err(err) => return Err(err),
+        };
+
+        let jito = Arc::clone(jito);
+        let alerter = Arc::clone(&self.alerter);
+        let status = Arc::clone(&self.status);
+        let rpc = Arc::clone(&self.tx_rpc);
+        let txs = Arc::clone(&self.txs);
+        let fee_spend = Arc::clone(&self.fee_spend);
+        let signer = Arc::clone(&self.tx_signer);
+        txs.submitted(sig, RequestId::current(), unix_timestamp());
+        tokio::spawn(inherit(async move {
+            let start = Instant::now();
+            let mut landed = false;
+            while start.elapsed() < ttl {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                let bundle_status = match bundle_id {
+                    Some(ref id) => jito.bundle_status(id).await,
+                    None => Ok(BundleStatus::Invalid),
+                };
+                match bundle_status {
+                    Ok(BundleStatus::Landed) => {
+                        landed = true;
+                        info!(target: LOG_TARGET, "bundle landed: {bundle_id:?}, tx: {sig}");
+                        break;
+                    }
+                    Ok(BundleStatus::Pending) => (),
+                    // not landed by the block engine, resent until the TTL
+                    Ok(BundleStatus::Failed | BundleStatus::Invalid) => {
+                        // the tx may have landed with an earlier bundle
+                        if let Some(tx_status) = signature_status(&rpc, &sig).await {
+                            if let Some(TxState::Confirmed | TxState::Finalized) =
+                                txs.landed(&sig, &tx_status, unix_timestamp())
+                            {
+                                landed = true;
+                                info!(target: LOG_TARGET, "bundle landed: tx: {sig}");
+                                break;
+                            }
+                        }
+                        match jito.send_bundle(std::slice::from_ref(&tx)).await {
+                            Ok(id) => {
+                                debug!(target: LOG_TARGET, "resent bundle ({reason}): {id}, tx: {sig}");
+                                bundle_id = Some(id);
+                            }
+                            Err(err) => {
+                                warn!(target: LOG_TARGET, "resending bundle ({reason}) failed: {err}");
+                            }
+                        }
+                    }
+                    Err(err) => {
+                        debug!(target: LOG_TARGET, "bundle status failed: {bundle_id:?}, {err}");
+                    }
+                }
+            }
+            let mut failed = false;
+            if landed {
+                // the nonce account is released once the tx lands
+                drop(nonce);
+                record_fees(&rpc, &fee_spend, &sig, &sub_account, fees, jito.tip()).await;
+            } else {
+                debug!(target: LOG_TARGET, "bundle not landed within ttl, awaiting expiry: {bundle_id:?}, tx: {sig}");
+                let blockhash = *tx.message.recent_blockhash();
+                let state =
+                    await_expiry(&rpc, &txs, &sig, &blockhash, nonce, signer.as_ref()).await;
+                if state.is_some() {
+                    record_fees(&rpc, &fee_spend, &sig, &sub_account, fees, jito.tip()).await;
+                }
+                landed = matches!(state, Some(TxState::Confirmed | TxState::Finalized));
+                failed = state == Some(TxState::Failed);
+            }
+            if !landed {
+                warn!(target: LOG_TARGET, "bundle did not land: {bundle_id:?}, tx: {sig}");
+            }
+            // bundles that never land don't count, like txs sent via RPC
+            if landed || failed {
+                alerter.record_landed_tx(landed);
+                status.record_landed_tx(landed);
+            }
+            if landed {
+                await_finalized(&rpc, &txs, &sig).await;
+            }
+        }));
+
+        let res = TxResponse::new(sig.to_string());
+        Ok(if pending { res.pending() } else { res })
+    }
+}
+
+/// Return the (market, side, reduce-only) of `order` for compliance checks
+fn order_compliance(order: &OrderParams) -> (MarketId, Option<PositionDirection>, bool) {
+    (
+        Market::new(order.market_index, order.market_type).as_market_id(),
+        Some(order.direction),
+        order.reduce_only,
+    )
+}
+
+/// Return the current unix timestamp in seconds
+pub(crate) fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
 
-        Ok(TxResponse::new(sig.to_string()))
+/// Return the RPC status of tx `signature`, None if unknown or the request failed
+async fn signature_status(rpc: &RpcClient, signature: &Signature) -> Option<TransactionStatus> {
+    rpc.get_signature_statuses(&[*signature])
+        .await
+        .ok()?
+        .value
+        .into_iter()
+        .next()
+        .flatten()
+}
+
+/// Record the fees charged to landed tx `signature` of `sub_account`, the `estimate` if its
+/// meta can't be fetched
+async fn record_fees(
+    rpc: &RpcClient,
+    fee_spend: &FeeSpendTracker,
+    signature: &Signature,
+    sub_account: &str,
+    estimate: TxFees,
+    tip: u64,
+) {
+    let config = RpcTransactionConfig {
+        encoding: Some(UiTransactionEncoding::Base64),
+        commitment: Some(CommitmentConfig::confirmed()),
+        max_supported_transaction_version: Some(0),
+    };
+    let fees = match rpc.get_transaction_with_config(signature, config).await {
+        Ok(tx) => match tx.transaction.meta {
+            Some(meta) => TxFees::charged(meta.fee, estimate),
+            None => estimate,
+        },
+        Err(err) => {
+            debug!(target: LOG_TARGET, "tx fetch failed, estimating fees: {signature}, {err}");
+            estimate
+        }
+    };
+    fee_spend.record(sub_account, fees, tip, unix_timestamp());
+}
+
+/// Poll the status of a tx not confirmed within its TTL until it lands or can no longer land,
+/// returns its landed state or `None` once it is marked expired
+///
+/// a tx can land until its blockhash expires, ~60-90s after it was fetched, and a durable nonce tx
+/// until its nonce is advanced
+async fn await_expiry(
+    rpc: &RpcClient,
+    txs: &TxTracker,
+    signature: &Signature,
+    recent_block_hash: &Hash,
+    nonce: Option<NonceLease>,
+    signer: &dyn Signer,
+) -> Option<TxState> {
+    let landed = |status: &TransactionStatus| {
+        txs.landed(signature, status, unix_timestamp())
+            .filter(|s| *s != TxState::Submitted)
+    };
+    match nonce {
+        Some(nonce) => nonce.retire(rpc, signer, *recent_block_hash).await,
+        None => {
+            let start = Instant::now();
+            while start.elapsed() < BLOCKHASH_EXPIRY_TIMEOUT {
+                tokio::time::sleep(Duration::from_secs(2)).await;
+                if let Some(state) = signature_status(rpc, signature)
+                    .await
+                    .and_then(|s| landed(&s))
+                {
+                    return Some(state);
+                }
+                if rpc
+                    .is_blockhash_valid(recent_block_hash, CommitmentConfig::processed())
+                    .await
+                    .is_ok_and(|valid| !valid)
+                {
+                    break;
+                }
+            }
+        }
+    }
+    // it may have landed before the blockhash (or nonce) moved on
+    if let Some(state) = signature_status(rpc, signature)
+        .await
+        .and_then(|s| landed(&s))
+    {
+        return Some(state);
+    }
+    txs.expired(signature, unix_timestamp());
+    None
+}
+
+/// Poll the status of a landed tx until it is finalized or [`FINALIZE_TIMEOUT`] elapses
+async fn await_finalized(rpc: &RpcClient, txs: &TxTracker, signature: &Signature) {
+    let start = Instant::now();
+    while start.elapsed() < FINALIZE_TIMEOUT {
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        if let Some(status) = signature_status(rpc, signature).await {
+            if txs
+                .landed(signature, &status, unix_timestamp())
+                .is_some_and(|s| s.is_terminal())
+            {
+                break;
+            }
+        }
     }
 }
 
+/// Sample equity, collateral and leverage of `sub_account`
+async fn equity_snapshot(
+    client: &DriftClient,
+    sub_account: &Pubkey,
+    ts: u64,
+) -> SdkResult<EquitySnapshot> {
+    let user = client.get_user_account(sub_account).await?;
+    let collateral: Collateral =
+        calculate_collateral(client, &user, MarginRequirementType::Maintenance)?.into();
+    let leverage: Leverage = get_leverage(client, &user)?.into();
+    Ok(EquitySnapshot {
+        ts,
+        equity: collateral.total,
+        free_collateral: collateral.free,
+        leverage: leverage.leverage,
+    })
+}
+
+/// Return the PnL of `user` in USD: its equity net of deposits and withdrawals
+fn sub_account_pnl(client: &DriftClient, user: &User) -> SdkResult<Decimal> {
+    let collateral: Collateral =
+        calculate_collateral(client, user, MarginRequirementType::Maintenance)?.into();
+    let net_deposits = Decimal::new(user.total_deposits as i64, QUOTE_DECIMALS)
+        - Decimal::new(user.total_withdraws as i64, QUOTE_DECIMALS);
+    Ok(collateral.total - net_deposits)
+}
+
 fn handle_tx_err(err: SdkError) -> ControllerError {
     if let Some(code) = err.to_anchor_error_code() {
         ControllerError::TxFailed {
@@ -715,6 +5061,23 @@ fn handle_tx_err(err: SdkError) -> ControllerError {
     }
 }
 
+/// Return the markets of orders cancelled by `req`
+fn cancel_markets(req: &CancelOrdersRequest, user: &User) -> Vec<Market> {
+    if let Some(market) = req.market {
+        return vec![market];
+    }
+    user.orders
+        .iter()
+        .filter(|o| o.status == OrderStatus::Open)
+        .filter(|o| match (&req.user_ids, &req.ids) {
+            (Some(user_ids), _) => user_ids.contains(&o.user_order_id),
+            (None, Some(ids)) => ids.contains(&o.order_id),
+            (None, None) => true,
+        })
+        .map(|o| Market::new(o.market_index, o.market_type))
+        .collect()
+}
+
 /// helper to transform CancelOrdersRequest into its drift program ix
 fn build_cancel_ix(
     builder: TransactionBuilder<'_>,
@@ -803,10 +5166,22 @@ pub fn create_wallet(
             }
         }
         (None, Some(emulate), None) => {
-            GatewayWallet::new(Wallet::read_only(emulate), WalletMode::Normal)
+            GatewayWallet::new(Wallet::read_only(emulate), WalletMode::Emulated)
         }
         _ => {
             panic!("expected 'DRIFT_GATEWAY_KEY' or --emulate <pubkey>");
         }
     }
 }
+
+/// Initialize a wallet for txs signed remotely by `signer`, acting for `delegate` if set
+pub fn create_remote_wallet(signer: Pubkey, delegate: Option<Pubkey>) -> GatewayWallet {
+    // the remote signer holds the key, the wallet only derives accounts
+    let mut wallet = Wallet::read_only(signer);
+    if let Some(authority) = delegate {
+        wallet.to_delegated(authority);
+        GatewayWallet::new(wallet, WalletMode::Delegated)
+    } else {
+        GatewayWallet::new(wallet, WalletMode::Normal)
+    }
+}
@@ -0,0 +1,424 @@
+//! Recurring (dollar cost average) orders
+//!
+//! Plans place a market order every `interval` seconds until their quote budget is spent.
+//! Spend is counted from the fills of placed orders, tracked like other orders placed via the
+//! gateway. Progress is optionally persisted to a JSON file so plans survive gateway restarts.
+
+use std::{collections::BTreeMap, path::PathBuf};
+
+use log::warn;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    registry::{Entry, Registry},
+    types::Market,
+    LOG_TARGET,
+};
+
+/// Max. active plans
+const MAX_ACTIVE_PLANS: usize = 100;
+/// User order id of orders of plans persisted without one
+const DEFAULT_USER_ORDER_ID: u8 = 254;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub enum DcaStatus {
+    Active,
+    Completed,
+    Cancelled,
+}
+
+/// An order placed by a plan
+#[derive(Debug, Clone)]
+struct DcaOrder {
+    /// order tracking id
+    tracking_id: u64,
+    /// base amount, unsigned
+    amount: Decimal,
+    /// oracle price when placed
+    price: Decimal,
+    /// base amount filled, unsigned
+    filled: Decimal,
+    /// false once the order is filled or closed
+    open: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct DcaPlan {
+    pub id: u64,
+    #[serde(flatten)]
+    pub market: Market,
+    /// base amount per order, negative to sell
+    pub amount: Decimal,
+    /// seconds between orders
+    pub interval: u64,
+    /// total quote notional to trade
+    pub budget: Decimal,
+    /// user order id of placed orders
+    #[serde(default = "default_user_order_id")]
+    pub user_order_id: u8,
+    /// authority the plan trades for, the default authority if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub authority: Option<String>,
+    pub sub_account_id: u16,
+    pub status: DcaStatus,
+    /// quote notional filled so far (at oracle price when placed)
+    pub spent: Decimal,
+    /// base amount placed so far
+    pub placed: Decimal,
+    /// base amount filled so far
+    #[serde(default)]
+    pub filled: Decimal,
+    pub orders: u32,
+    /// unix timestamp of the next order
+    pub next_ts: u64,
+    #[serde(default)]
+    pub last_error: Option<String>,
+    /// orders placed since the gateway started, tracking ids don't survive restarts
+    #[serde(skip)]
+    children: Vec<DcaOrder>,
+    /// true once the user order id is released
+    #[serde(skip)]
+    released: bool,
+}
+
+fn default_user_order_id() -> u8 {
+    DEFAULT_USER_ORDER_ID
+}
+
+impl DcaPlan {
+    /// Quote notional of open orders yet to fill
+    fn open_notional(&self) -> Decimal {
+        self.children
+            .iter()
+            .filter(|c| c.open)
+            .map(|c| (c.amount - c.filled).max(Decimal::ZERO) * c.price)
+            .sum()
+    }
+
+    /// Return true if any placed order is yet to fill or close
+    pub fn has_open_orders(&self) -> bool {
+        self.children.iter().any(|c| c.open)
+    }
+
+    /// Signed base amount of the next order at `price`, clipped to the budget not yet filled
+    /// or in open orders
+    pub fn order_amount(&self, price: Decimal) -> Decimal {
+        let remaining = (self.budget - self.spent - self.open_notional()).max(Decimal::ZERO);
+        if price.is_zero() {
+            return Decimal::ZERO;
+        }
+        let max_amount = remaining / price;
+        if self.amount.abs() <= max_amount {
+            self.amount
+        } else if self.amount.is_sign_negative() {
+            -max_amount
+        } else {
+            max_amount
+        }
+    }
+}
+
+impl Entry for DcaPlan {
+    fn id(&self) -> u64 {
+        self.id
+    }
+    fn is_active(&self) -> bool {
+        self.status == DcaStatus::Active
+    }
+    fn cancel(&mut self) {
+        self.status = DcaStatus::Cancelled;
+    }
+}
+
+/// DCA plans by id
+pub struct Dca {
+    plans: Registry<DcaPlan>,
+    /// file to persist plans to
+    path: Option<PathBuf>,
+}
+
+impl Default for Dca {
+    fn default() -> Self {
+        Self {
+            plans: Registry::new("DCA plans", MAX_ACTIVE_PLANS),
+            path: None,
+        }
+    }
+}
+
+impl Dca {
+    /// Load plans persisted at `path`, a missing file starts empty
+    pub fn load(path: PathBuf) -> std::io::Result<Self> {
+        let mut plans: Vec<DcaPlan> = match std::fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::default(),
+            Err(err) => return Err(err),
+        };
+        // user order ids of restored plans are reserved by active plans only
+        for plan in &mut plans {
+            plan.released = !plan.is_active();
+        }
+
+        Ok(Self {
+            plans: Registry::with_entries("DCA plans", MAX_ACTIVE_PLANS, plans),
+            path: Some(path),
+        })
+    }
+
+    /// Add a new plan, its first order is due at `start_ts`
+    ///
+    /// `user_order_id` is expected to be reserved for the plan
+    #[allow(clippy::too_many_arguments)]
+    pub fn add(
+        &self,
+        market: Market,
+        amount: Decimal,
+        interval: u64,
+        budget: Decimal,
+        user_order_id: u8,
+        authority: Option<String>,
+        sub_account_id: u16,
+        start_ts: u64,
+    ) -> Result<DcaPlan, String> {
+        let plan = self.plans.add(|id| DcaPlan {
+            id,
+            market,
+            amount,
+            interval,
+            budget,
+            user_order_id,
+            authority,
+            sub_account_id,
+            status: DcaStatus::Active,
+            spent: Decimal::ZERO,
+            placed: Decimal::ZERO,
+            filled: Decimal::ZERO,
+            orders: 0,
+            next_ts: start_ts,
+            last_error: None,
+            children: Vec::default(),
+            released: false,
+        })?;
+        self.persist(&self.plans.lock());
+
+        Ok(plan)
+    }
+
+    pub fn get(&self, id: u64) -> Option<DcaPlan> {
+        self.plans.get(id)
+    }
+
+    pub fn list(&self) -> Vec<DcaPlan> {
+        self.plans.list()
+    }
+
+    pub fn active(&self) -> Vec<DcaPlan> {
+        self.plans.active()
+    }
+
+    /// Cancel an active plan, returning it
+    pub fn cancel(&self, id: u64) -> Option<DcaPlan> {
+        let plan = self.plans.cancel(id)?;
+        self.persist(&self.plans.lock());
+
+        Some(plan)
+    }
+
+    /// Return active plans with an order due at `ts`
+    ///
+    /// their next order is pushed back one interval so in-flight orders are not repeated
+    pub fn take_due(&self, ts: u64) -> Vec<DcaPlan> {
+        let mut plans = self.plans.lock();
+        let due: Vec<DcaPlan> = plans
+            .values_mut()
+            .filter(|p| p.status == DcaStatus::Active && p.next_ts <= ts)
+            .map(|p| {
+                p.next_ts = ts.saturating_add(p.interval);
+                p.clone()
+            })
+            .collect();
+        if !due.is_empty() {
+            self.persist(&plans);
+        }
+
+        due
+    }
+
+    /// Record an order placed for plan `id` at oracle `price`
+    pub fn record_order(&self, id: u64, amount: Decimal, price: Decimal, tracking_ids: &[u64]) {
+        self.update(id, |plan| {
+            plan.placed += amount;
+            plan.orders += 1;
+            plan.last_error = None;
+            plan.children
+                .extend(tracking_ids.iter().map(|tracking_id| DcaOrder {
+                    tracking_id: *tracking_id,
+                    amount: amount.abs(),
+                    price,
+                    filled: Decimal::ZERO,
+                    open: true,
+                }));
+        });
+    }
+
+    /// Update fills of open orders from their tracked (filled, is closed) status
+    ///
+    /// plans are completed once fills reach their budget
+    pub fn update_fills(&self, status: impl Fn(u64) -> Option<(Decimal, bool)>) {
+        let mut plans = self.plans.lock();
+        let mut changed = false;
+        for plan in plans.values_mut().filter(|p| p.has_open_orders()) {
+            for child in plan.children.iter_mut().filter(|c| c.open) {
+                let (filled, open) = match status(child.tracking_id) {
+                    Some((filled, closed)) => (filled, !closed),
+                    // no longer tracked
+                    None => (child.filled, false),
+                };
+                let delta = filled - child.filled;
+                if delta > Decimal::ZERO {
+                    plan.filled += if plan.amount.is_sign_negative() {
+                        -delta
+                    } else {
+                        delta
+                    };
+                    plan.spent += delta * child.price;
+                    child.filled = filled;
+                    changed = true;
+                }
+                child.open = open;
+            }
+            plan.children.retain(|c| c.open);
+            if plan.spent >= plan.budget && plan.status == DcaStatus::Active {
+                plan.status = DcaStatus::Completed;
+            }
+        }
+        if changed {
+            self.persist(&plans);
+        }
+    }
+
+    /// Record a failed order for plan `id`, it is retried next interval
+    pub fn record_error(&self, id: u64, err: String) {
+        self.update(id, |plan| plan.last_error = Some(err));
+    }
+
+    /// Return plans done with their user order id, completed or cancelled without open orders,
+    /// each once
+    pub fn take_done(&self) -> Vec<DcaPlan> {
+        let mut plans = self.plans.lock();
+        plans
+            .values_mut()
+            .filter(|p| !p.is_active() && !p.released && !p.has_open_orders())
+            .map(|p| {
+                p.released = true;
+                p.clone()
+            })
+            .collect()
+    }
+
+    /// Mark plan `id` completed
+    pub fn complete(&self, id: u64) {
+        self.update(id, |plan| plan.status = DcaStatus::Completed);
+    }
+
+    fn update(&self, id: u64, f: impl FnOnce(&mut DcaPlan)) {
+        if self.plans.update(id, f).is_some() {
+            self.persist(&self.plans.lock());
+        }
+    }
+
+    fn persist(&self, plans: &BTreeMap<u64, DcaPlan>) {
+        let Some(ref path) = self.path else {
+            return;
+        };
+        let plans: Vec<&DcaPlan> = plans.values().collect();
+        // write then rename so a crash mid-write can't leave a truncated file
+        let tmp = path.with_extension("tmp");
+        let res = serde_json::to_vec_pretty(&plans)
+            .map_err(std::io::Error::from)
+            .and_then(|json| std::fs::write(&tmp, json))
+            .and_then(|_| std::fs::rename(&tmp, path));
+        if let Err(err) = res {
+            warn!(target: LOG_TARGET, "couldn't persist DCA plans: {err:?}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn order_amount_clips_to_budget() {
+        let dca = Dca::default();
+        let plan = dca
+            .add(
+                Market::spot(1),
+                Decimal::from(-2),
+                60,
+                Decimal::from(500),
+                1,
+                None,
+                0,
+                100,
+            )
+            .unwrap();
+        assert_eq!(plan.order_amount(Decimal::from(100)), Decimal::from(-2));
+
+        dca.record_order(plan.id, Decimal::from(-2), Decimal::from(100), &[1]);
+        dca.record_order(plan.id, Decimal::from(-2), Decimal::from(100), &[2]);
+        // open orders count against the budget until closed
+        let plan = dca.get(plan.id).unwrap();
+        assert_eq!(plan.spent, Decimal::ZERO);
+        assert_eq!(plan.order_amount(Decimal::from(100)), Decimal::from(-1));
+
+        // order 1 fills, order 2 is closed half filled
+        dca.update_fills(|tracking_id| match tracking_id {
+            1 => Some((Decimal::from(2), true)),
+            _ => Some((Decimal::ONE, true)),
+        });
+        let plan = dca.get(plan.id).unwrap();
+        assert_eq!(plan.spent, Decimal::from(300));
+        assert_eq!(plan.filled, Decimal::from(-3));
+        assert!(!plan.has_open_orders());
+        assert_eq!(plan.order_amount(Decimal::from(100)), Decimal::from(-2));
+
+        dca.record_order(plan.id, Decimal::from(-2), Decimal::from(100), &[3]);
+        assert!(dca.take_done().is_empty());
+        dca.update_fills(|_| Some((Decimal::from(2), true)));
+        assert_eq!(dca.get(plan.id).unwrap().status, DcaStatus::Completed);
+        assert_eq!(dca.take_done().len(), 1);
+        assert!(dca.take_done().is_empty());
+    }
+
+    #[test]
+    fn take_due_advances_schedule() {
+        let dca = Dca::default();
+        let plan = dca
+            .add(
+                Market::perp(0),
+                Decimal::ONE,
+                60,
+                Decimal::from(1_000),
+                1,
+                None,
+                0,
+                100,
+            )
+            .unwrap();
+
+        assert!(dca.take_due(99).is_empty());
+        assert_eq!(dca.take_due(100).len(), 1);
+        assert!(dca.take_due(159).is_empty());
+        assert_eq!(dca.get(plan.id).unwrap().next_ts, 160);
+
+        dca.cancel(plan.id);
+        assert!(dca.take_due(1_000).is_empty());
+        assert_eq!(dca.get(plan.id).unwrap().status, DcaStatus::Cancelled);
+    }
+}
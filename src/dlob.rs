@@ -0,0 +1,496 @@
+//! Best-effort L2 orderbook built from resting orders of on-chain user accounts
+//!
+//! User accounts are loaded once then kept current by a program account subscription, started on
+//! first use. Requests read the subscribed orders rather than scanning the program's accounts.
+//! One subscription is shared by all requests and Ws subscribers, and so are the L2 books built
+//! from it.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::Duration,
+};
+
+use anchor_lang::AccountDeserialize;
+use drift_rs::{
+    constants::PROGRAM_ID,
+    event_subscriber::RpcClient,
+    memcmp::{get_user_filter, get_user_with_order_filter},
+    types::{
+        accounts::User, MarketType, Order, OrderStatus, OrderType, PositionDirection, SdkResult,
+    },
+    DriftClient, Pubkey,
+};
+use futures_util::StreamExt;
+use log::{info, warn};
+use rust_decimal::Decimal;
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+use solana_sdk::account::Account;
+use tokio::sync::watch;
+
+use crate::{
+    backoff::Backoff,
+    types::{L2Level, Market, PRICE_DECIMALS},
+    LOG_TARGET,
+};
+
+const MIN_RESUBSCRIBE_DELAY: Duration = Duration::from_secs(1);
+const MAX_RESUBSCRIBE_DELAY: Duration = Duration::from_secs(30);
+
+/// Resting orders of all users, kept current by a program account subscription
+pub struct Dlob {
+    book: RwLock<Book>,
+    /// true once the initial load completed
+    loaded: watch::Sender<bool>,
+    started: AtomicBool,
+    /// latest L2 book by (market index, perp, depth)
+    l2_books: Mutex<HashMap<(u16, bool, usize), L2Book>>,
+}
+
+/// L2 book of a market, reused while its inputs are unchanged
+struct L2Book {
+    orders: Arc<Vec<Order>>,
+    oracle_price: i64,
+    now: i64,
+    levels: (Vec<L2Level>, Vec<L2Level>),
+}
+
+#[derive(Default)]
+struct Book {
+    /// resting orders by user account
+    users: HashMap<Pubkey, Vec<Order>>,
+    /// resting orders of all users, rebuilt on read after changes
+    snapshot: Option<Arc<Vec<Order>>>,
+}
+
+impl Default for Dlob {
+    fn default() -> Self {
+        Self {
+            book: RwLock::default(),
+            loaded: watch::Sender::new(false),
+            started: AtomicBool::default(),
+            l2_books: Mutex::default(),
+        }
+    }
+}
+
+impl Dlob {
+    /// Return resting orders of all users
+    ///
+    /// the subscription is started on first use, requests wait for its initial load
+    pub async fn orders(self: &Arc<Self>, client: &DriftClient) -> Arc<Vec<Order>> {
+        if !self.started.swap(true, Ordering::Relaxed) {
+            self.subscribe(client);
+        }
+        let mut loaded = self.loaded.subscribe();
+        let _ = loaded.wait_for(|loaded| *loaded).await;
+        self.snapshot()
+    }
+
+    /// Return the L2 book of `market` from `orders`, as [`l2_levels`]
+    ///
+    /// subscribers polling the same market share one book, built once per change of `orders`,
+    /// `oracle_price`, or `now`
+    pub fn l2_book(
+        &self,
+        orders: &Arc<Vec<Order>>,
+        market: Market,
+        oracle_price: i64,
+        now: i64,
+        base_decimals: u32,
+        depth: usize,
+    ) -> (Vec<L2Level>, Vec<L2Level>) {
+        let key = (
+            market.market_index,
+            market.market_type == MarketType::Perp,
+            depth,
+        );
+        let mut books = self.l2_books.lock().expect("lock");
+        if let Some(book) = books.get(&key).filter(|b| {
+            Arc::ptr_eq(&b.orders, orders) && b.oracle_price == oracle_price && b.now == now
+        }) {
+            return book.levels.clone();
+        }
+        let levels = l2_levels(orders, market, oracle_price, now, base_decimals, depth);
+        books.insert(
+            key,
+            L2Book {
+                orders: Arc::clone(orders),
+                oracle_price,
+                now,
+                levels: levels.clone(),
+            },
+        );
+        levels
+    }
+
+    /// Return the best bid and ask of `market` among resting orders of users other than
+    /// `exclude`, as [`best_prices`]
+    ///
+    /// orders are those loaded so far, see [`Dlob::orders`] to await the initial load
+    pub fn best_prices_excluding(
+        &self,
+        market: Market,
+        oracle_price: i64,
+        now: i64,
+        exclude: &Pubkey,
+    ) -> (Option<u64>, Option<u64>) {
+        let book = self.book.read().expect("lock");
+        best_prices(
+            book.users
+                .iter()
+                .filter(|(user, _)| *user != exclude)
+                .flat_map(|(_, orders)| orders),
+            market,
+            oracle_price,
+            now,
+        )
+    }
+
+    fn snapshot(&self) -> Arc<Vec<Order>> {
+        if let Some(ref snapshot) = self.book.read().expect("lock").snapshot {
+            return Arc::clone(snapshot);
+        }
+        let mut book = self.book.write().expect("lock");
+        let snapshot = Arc::new(book.users.values().flatten().copied().collect::<Vec<_>>());
+        book.snapshot = Some(Arc::clone(&snapshot));
+        snapshot
+    }
+
+    /// Replace all users' orders e.g. on (re)subscribing
+    fn load(&self, users: HashMap<Pubkey, Vec<Order>>) {
+        *self.book.write().expect("lock") = Book {
+            users,
+            snapshot: None,
+        };
+        self.loaded.send_replace(true);
+    }
+
+    /// Set the resting orders of `user`
+    fn update(&self, user: Pubkey, orders: Vec<Order>) {
+        let mut book = self.book.write().expect("lock");
+        if orders.is_empty() {
+            if book.users.remove(&user).is_none() {
+                return;
+            }
+        } else {
+            book.users.insert(user, orders);
+        }
+        book.snapshot = None;
+    }
+
+    /// Subscribe to user account changes, resubscribing with backoff until the process exits
+    fn subscribe(self: &Arc<Self>, client: &DriftClient) {
+        let dlob = Arc::clone(self);
+        let rpc = client.rpc();
+        let ws_client = client.ws();
+        tokio::spawn(async move {
+            // users without orders are included, so closing a user's last order is seen
+            let config = RpcProgramAccountsConfig {
+                filters: Some(vec![get_user_filter()]),
+                account_config: RpcAccountInfoConfig {
+                    encoding: Some(UiAccountEncoding::Base64Zstd),
+                    commitment: Some(rpc.commitment()),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let mut backoff = Backoff::new(MIN_RESUBSCRIBE_DELAY, MAX_RESUBSCRIBE_DELAY, u32::MAX);
+            loop {
+                match ws_client
+                    .program_subscribe(&PROGRAM_ID, Some(config.clone()))
+                    .await
+                {
+                    Ok((mut updates, unsubscribe)) => {
+                        // subscribed first so no change is missed between the load and the updates
+                        match load_resting_orders(&rpc).await {
+                            Ok(users) => {
+                                info!(target: LOG_TARGET, "orderbook loaded: {} users with orders", users.len());
+                                dlob.load(users);
+                                backoff.reset();
+                                while let Some(update) = updates.next().await {
+                                    let Ok(user) = Pubkey::from_str(&update.value.pubkey) else {
+                                        continue;
+                                    };
+                                    let orders = update
+                                        .value
+                                        .account
+                                        .decode::<Account>()
+                                        .and_then(|a| User::try_deserialize(&mut &a.data[..]).ok())
+                                        .map(|u| resting_orders(&u))
+                                        .unwrap_or_default();
+                                    dlob.update(user, orders);
+                                }
+                                warn!(target: LOG_TARGET, "orderbook subscription ended");
+                            }
+                            Err(err) => {
+                                warn!(target: LOG_TARGET, "failed loading orderbook: {err:?}");
+                            }
+                        }
+                        unsubscribe().await;
+                    }
+                    Err(err) => {
+                        warn!(target: LOG_TARGET, "failed subscribing orderbook: {err:?}");
+                    }
+                }
+                let delay = backoff.next_delay().unwrap_or(MAX_RESUBSCRIBE_DELAY);
+                tokio::time::sleep(delay).await;
+            }
+        });
+    }
+}
+
+/// Load resting orders of all users with open orders, by user account
+async fn load_resting_orders(rpc: &RpcClient) -> SdkResult<HashMap<Pubkey, Vec<Order>>> {
+    let accounts = rpc
+        .get_program_accounts_with_config(
+            &PROGRAM_ID,
+            RpcProgramAccountsConfig {
+                filters: Some(vec![get_user_filter(), get_user_with_order_filter()]),
+                account_config: RpcAccountInfoConfig {
+                    encoding: Some(UiAccountEncoding::Base64Zstd),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        )
+        .await?;
+
+    Ok(accounts
+        .into_iter()
+        .filter_map(|(pubkey, account)| {
+            let user = User::try_deserialize(&mut account.data.as_slice()).ok()?;
+            let orders = resting_orders(&user);
+            (!orders.is_empty()).then_some((pubkey, orders))
+        })
+        .collect())
+}
+
+/// Return the open limit orders of `user` with an unfilled amount
+fn resting_orders(user: &User) -> Vec<Order> {
+    user.orders
+        .iter()
+        .filter(|o| {
+            o.status == OrderStatus::Open
+                && o.order_type == OrderType::Limit
+                && o.base_asset_amount > o.base_asset_amount_filled
+        })
+        .copied()
+        .collect()
+}
+
+/// Aggregate resting orders of `market` into price levels, best first
+///
+/// * `oracle_price` - prices oracle offset orders
+/// * `now` - unix timestamp, expired orders are excluded
+/// * `depth` - max. levels per side
+pub fn l2_levels(
+    orders: &[Order],
+    market: Market,
+    oracle_price: i64,
+    now: i64,
+    base_decimals: u32,
+    depth: usize,
+) -> (Vec<L2Level>, Vec<L2Level>) {
+    let mut bids = BTreeMap::<u64, u64>::new();
+    let mut asks = BTreeMap::<u64, u64>::new();
+    for (o, price) in resting_prices(orders, market, oracle_price, now) {
+        let side = match o.direction {
+            PositionDirection::Long => &mut bids,
+            PositionDirection::Short => &mut asks,
+        };
+        *side.entry(price).or_default() += o.base_asset_amount - o.base_asset_amount_filled;
+    }
+
+    let level = |(price, size): (&u64, &u64)| L2Level {
+        price: Decimal::new(*price as i64, PRICE_DECIMALS).normalize(),
+        size: Decimal::new(*size as i64, base_decimals).normalize(),
+    };
+    (
+        bids.iter().rev().take(depth).map(level).collect(),
+        asks.iter().take(depth).map(level).collect(),
+    )
+}
+
+/// Return the best bid and ask price of `market` among resting orders, as [`l2_levels`]
+pub fn best_prices<'a>(
+    orders: impl IntoIterator<Item = &'a Order>,
+    market: Market,
+    oracle_price: i64,
+    now: i64,
+) -> (Option<u64>, Option<u64>) {
+    let (mut best_bid, mut best_ask) = (None, None);
+    for (o, price) in resting_prices(orders, market, oracle_price, now) {
+        match o.direction {
+            PositionDirection::Long => best_bid = best_bid.max(Some(price)),
+            PositionDirection::Short => best_ask = Some(best_ask.unwrap_or(u64::MAX).min(price)),
+        }
+    }
+    (best_bid, best_ask)
+}
+
+/// Unexpired orders of `market` with their limit price, oracle offset orders priced at `oracle_price`
+fn resting_prices<'a>(
+    orders: impl IntoIterator<Item = &'a Order>,
+    market: Market,
+    oracle_price: i64,
+    now: i64,
+) -> impl Iterator<Item = (&'a Order, u64)> {
+    orders
+        .into_iter()
+        .filter(move |o| {
+            o.market_index == market.market_index
+                && o.market_type == market.market_type
+                && (o.max_ts == 0 || o.max_ts > now)
+        })
+        .filter_map(move |o| {
+            let price = if o.oracle_price_offset != 0 {
+                oracle_price + o.oracle_price_offset as i64
+            } else {
+                o.price as i64
+            };
+            (price > 0).then_some((o, price as u64))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(direction: PositionDirection, price: u64, oracle_price_offset: i32) -> Order {
+        Order {
+            status: OrderStatus::Open,
+            order_type: OrderType::Limit,
+            market_type: MarketType::Perp,
+            market_index: 0,
+            direction,
+            price,
+            oracle_price_offset,
+            base_asset_amount: 2_000_000_000,
+            base_asset_amount_filled: 1_000_000_000,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn l2_levels_works() {
+        let orders = [
+            order(PositionDirection::Long, 99_000_000, 0),
+            order(PositionDirection::Long, 99_000_000, 0),
+            order(PositionDirection::Long, 0, -2_000_000),
+            order(PositionDirection::Short, 101_000_000, 0),
+            Order {
+                max_ts: 1,
+                ..order(PositionDirection::Short, 100_500_000, 0)
+            },
+            Order {
+                market_index: 1,
+                ..order(PositionDirection::Short, 100_500_000, 0)
+            },
+        ];
+        let (bids, asks) = l2_levels(&orders, Market::perp(0), 100_000_000, 2, 9, 10);
+
+        assert_eq!(bids.len(), 2);
+        assert_eq!(bids[0].price, Decimal::from(99));
+        assert_eq!(bids[0].size, Decimal::TWO);
+        assert_eq!(bids[1].price, Decimal::from(98));
+        assert_eq!(asks.len(), 1);
+        assert_eq!(asks[0].price, Decimal::from(101));
+        assert_eq!(asks[0].size, Decimal::ONE);
+
+        let (bids, _) = l2_levels(&orders, Market::perp(0), 100_000_000, 2, 9, 1);
+        assert_eq!(bids.len(), 1);
+    }
+
+    #[test]
+    fn best_prices_works() {
+        let orders = [
+            order(PositionDirection::Long, 99_000_000, 0),
+            order(PositionDirection::Long, 0, -500_000),
+            order(PositionDirection::Short, 101_000_000, 0),
+            order(PositionDirection::Short, 102_000_000, 0),
+            Order {
+                max_ts: 1,
+                ..order(PositionDirection::Short, 100_500_000, 0)
+            },
+        ];
+        assert_eq!(
+            best_prices(&orders, Market::perp(0), 100_000_000, 2),
+            (Some(99_500_000), Some(101_000_000))
+        );
+        assert_eq!(
+            best_prices(&orders, Market::perp(1), 100_000_000, 2),
+            (None, None)
+        );
+
+        // orders of the excluded user are skipped
+        let dlob = Dlob::default();
+        let (maker, other) = (Pubkey::new_unique(), Pubkey::new_unique());
+        dlob.load(HashMap::from([
+            (maker, vec![order(PositionDirection::Short, 100_500_000, 0)]),
+            (other, orders.to_vec()),
+        ]));
+        assert_eq!(
+            dlob.best_prices_excluding(Market::perp(0), 100_000_000, 2, &Pubkey::default()),
+            (Some(99_500_000), Some(100_500_000))
+        );
+        assert_eq!(
+            dlob.best_prices_excluding(Market::perp(0), 100_000_000, 2, &maker),
+            (Some(99_500_000), Some(101_000_000))
+        );
+    }
+
+    #[test]
+    fn dlob_updates_users() {
+        let dlob = Dlob::default();
+        let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        dlob.load(HashMap::from([(
+            a,
+            vec![order(PositionDirection::Long, 99_000_000, 0)],
+        )]));
+        assert_eq!(dlob.snapshot().len(), 1);
+
+        dlob.update(
+            b,
+            vec![
+                order(PositionDirection::Short, 101_000_000, 0),
+                order(PositionDirection::Short, 102_000_000, 0),
+            ],
+        );
+        assert_eq!(dlob.snapshot().len(), 3);
+
+        // the last order of a user closed
+        dlob.update(a, vec![]);
+        assert_eq!(dlob.snapshot().len(), 2);
+    }
+
+    #[test]
+    fn l2_book_shared_until_orders_change() {
+        let dlob = Dlob::default();
+        dlob.load(HashMap::from([(
+            Pubkey::new_unique(),
+            vec![order(PositionDirection::Long, 99_000_000, 0)],
+        )]));
+        let orders = dlob.snapshot();
+        let book = dlob.l2_book(&orders, Market::perp(0), 100_000_000, 2, 9, 10);
+        assert_eq!(book.0.len(), 1);
+        assert!(Arc::ptr_eq(&dlob.snapshot(), &orders));
+        assert_eq!(
+            dlob.l2_book(&orders, Market::perp(0), 100_000_000, 2, 9, 10),
+            book
+        );
+
+        dlob.update(
+            Pubkey::new_unique(),
+            vec![order(PositionDirection::Long, 98_000_000, 0)],
+        );
+        let orders = dlob.snapshot();
+        let book = dlob.l2_book(&orders, Market::perp(0), 100_000_000, 2, 9, 10);
+        assert_eq!(book.0.len(), 2);
+    }
+}
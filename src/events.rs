@@ -0,0 +1,493 @@
+//! In-memory journal of account events
+//!
+//! Events for subscribed sub-accounts are sequenced and retained in a bounded buffer
+//! so that HTTP clients can fetch them without a Ws connection. Sequence numbers restart with the
+//! journal, clients tell a restarted journal by its epoch.
+//! A follower gateway may instead replicate the journal of a primary gateway.
+//! Tx events delivered by several sources (e.g. the event stream and webhooks) are journaled once.
+
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, RwLock,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use drift_rs::{
+    event_subscriber::{DriftEvent, EventSubscriber},
+    types::accounts::User,
+    DriftClient, Pubkey,
+};
+use futures_util::StreamExt;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+
+use crate::{
+    alerts::{Alert, Alerter},
+    backfill::{backfill_events, fetch_user},
+    controller::unix_timestamp,
+    fills::FillStore,
+    order_status::OrderTracker,
+    websocket::{map_drift_event_for_account, AccountEvent, Channel},
+    LOG_TARGET,
+};
+
+/// Max. number of events retained by the journal
+const JOURNAL_CAPACITY: usize = 10_000;
+
+/// Long poll timeout used when following a primary gateway
+const FOLLOW_POLL_TIMEOUT_MS: u64 = 30_000;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct JournalEntry {
+    /// Sequence number of the event, strictly increasing
+    pub seq: u64,
+    pub sub_account_id: u16,
+    pub channel: Channel,
+    pub data: AccountEvent,
+}
+
+#[derive(Default)]
+struct JournalInner {
+    entries: VecDeque<JournalEntry>,
+    next_seq: u64,
+    /// keys of recently journaled tx events by sub-account, oldest first
+    seen: VecDeque<(u16, String)>,
+    seen_keys: HashSet<(u16, String)>,
+}
+
+impl JournalInner {
+    /// Record a tx event key, returns false if it was journaled already
+    fn insert_seen(&mut self, key: (u16, String)) -> bool {
+        if !self.seen_keys.insert(key.clone()) {
+            return false;
+        }
+        if self.seen.len() >= JOURNAL_CAPACITY {
+            if let Some(oldest) = self.seen.pop_front() {
+                self.seen_keys.remove(&oldest);
+            }
+        }
+        self.seen.push_back(key);
+        true
+    }
+}
+
+/// Events poll response of a primary gateway
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FollowResponse {
+    events: Vec<JournalEntry>,
+    last_seq: u64,
+    /// unset by primaries predating journal epochs
+    #[serde(default)]
+    epoch: Option<u64>,
+}
+
+/// Sequenced buffer of recent account events
+pub struct EventJournal {
+    /// identifies this run of the journal, unix timestamp in ms of its creation
+    epoch: u64,
+    inner: RwLock<JournalInner>,
+    notify: Notify,
+    /// persists fill and order events, if set
+    store: Option<Arc<FillStore>>,
+    /// lifecycle states of orders of the journaled sub-accounts
+    orders: OrderTracker,
+    /// sub-accounts whose event streams are journaled
+    subscribed: RwLock<HashSet<u16>>,
+    /// replicating the journal of a primary gateway
+    following: AtomicBool,
+}
+
+impl Default for EventJournal {
+    fn default() -> Self {
+        Self {
+            epoch: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or_default(),
+            inner: RwLock::default(),
+            notify: Notify::default(),
+            store: None,
+            orders: OrderTracker::default(),
+            subscribed: RwLock::default(),
+            following: AtomicBool::default(),
+        }
+    }
+}
+
+impl EventJournal {
+    /// A journal also persisting fill and order events to `store`
+    pub fn with_store(store: Arc<FillStore>) -> Self {
+        Self {
+            store: Some(store),
+            ..Default::default()
+        }
+    }
+
+    /// Journal events of `sub_account` until the process exits, resubscribing on disconnect
+    ///
+    /// changes missed while disconnected are backfilled on reconnect
+    pub fn subscribe(
+        self: &Arc<Self>,
+        client: Arc<DriftClient>,
+        alerter: Arc<Alerter>,
+        sub_account_id: u16,
+        sub_account: Pubkey,
+    ) {
+        self.subscribed
+            .write()
+            .expect("lock")
+            .insert(sub_account_id);
+        let journal = Arc::clone(self);
+        tokio::spawn(async move {
+            // account state when the event stream disconnected
+            let mut disconnected_user: Option<User> = None;
+            loop {
+                match EventSubscriber::subscribe(client.ws(), sub_account).await {
+                    Ok(mut event_stream) => {
+                        debug!(target: LOG_TARGET, "event journal connected: {sub_account_id}");
+                        if let Some(before) = disconnected_user.take() {
+                            journal
+                                .backfill(&client, sub_account_id, sub_account, &before)
+                                .await;
+                        }
+                        while let Some(ref event) = event_stream.next().await {
+                            journal.push_event(&client, sub_account_id, sub_account, event);
+                        }
+                        warn!(target: LOG_TARGET, "event journal stream finished: {sub_account_id}, resubscribing");
+                        disconnected_user = client.try_get_account::<User>(&sub_account).ok();
+                    }
+                    Err(err) => {
+                        warn!(target: LOG_TARGET, "event journal subscribe failed: {sub_account_id}, {err:?}");
+                    }
+                }
+                alerter.send(Alert::SubscriptionOutage {
+                    subscription: format!("events (sub-account {sub_account_id})"),
+                });
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        });
+    }
+
+    /// Replicate the journal of the primary gateway at `primary` until the process exits
+    ///
+    /// events are re-sequenced by this journal
+    pub fn follow(self: &Arc<Self>, primary: &str, alerter: Arc<Alerter>) {
+        self.following.store(true, Ordering::Relaxed);
+        let journal = Arc::clone(self);
+        let url = format!("{}/v2/events/poll", primary.trim_end_matches('/'));
+        tokio::spawn(async move {
+            let client = reqwest::Client::new();
+            // sequence number and epoch of the primary's journal
+            let mut cursor = 0;
+            let mut epoch = None;
+            loop {
+                let res = client
+                    .get(&url)
+                    .query(&[("since", cursor), ("timeoutMs", FOLLOW_POLL_TIMEOUT_MS)])
+                    .send()
+                    .await
+                    .and_then(|res| res.error_for_status());
+                let res = match res {
+                    Ok(res) => res.json::<FollowResponse>().await,
+                    Err(err) => Err(err),
+                };
+                match res {
+                    Ok(res) => {
+                        let restarted = match (epoch, res.epoch) {
+                            (Some(epoch), Some(primary_epoch)) => epoch != primary_epoch,
+                            _ => res.last_seq < cursor,
+                        };
+                        epoch = res.epoch;
+                        if restarted {
+                            // primary restarted, its retained events are all new to us
+                            warn!(target: LOG_TARGET, "primary gateway journal reset, resyncing");
+                            cursor = 0;
+                            continue;
+                        }
+                        cursor = res.last_seq;
+                        for entry in res.events {
+                            journal.push(entry.sub_account_id, entry.channel, entry.data);
+                        }
+                    }
+                    Err(err) => {
+                        warn!(target: LOG_TARGET, "following primary gateway failed: {err:?}");
+                        alerter.send(Alert::SubscriptionOutage {
+                            subscription: format!("primary gateway events ({url})"),
+                        });
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                    }
+                }
+            }
+        });
+    }
+
+    /// Journal corrective events for changes of `sub_account` since `before`
+    ///
+    /// the account cache is resubscribed if it drifted from the fetched account
+    async fn backfill(
+        &self,
+        client: &DriftClient,
+        sub_account_id: u16,
+        sub_account: Pubkey,
+        before: &User,
+    ) {
+        let after = match fetch_user(client, &sub_account).await {
+            Ok(Some(user)) => user,
+            Ok(None) => {
+                warn!(target: LOG_TARGET, "backfill skipped, not a user account: {sub_account}");
+                return;
+            }
+            Err(err) => {
+                warn!(target: LOG_TARGET, "backfill fetch failed: {sub_account_id}, {err:?}");
+                return;
+            }
+        };
+        match backfill_events(client, before, &after, unix_timestamp()).await {
+            Ok(events) => {
+                if !events.is_empty() {
+                    info!(target: LOG_TARGET, "backfilled {} events: {sub_account_id}", events.len());
+                }
+                for (channel, data) in events {
+                    self.push(sub_account_id, channel, data);
+                }
+            }
+            Err(err) => {
+                warn!(target: LOG_TARGET, "backfill failed: {sub_account_id}, {err:?}");
+            }
+        }
+
+        if client.try_get_account::<User>(&sub_account).ok().as_ref() != Some(&after) {
+            info!(target: LOG_TARGET, "account cache drifted, resubscribing: {sub_account}");
+            let resubscribed = match client.unsubscribe_account(&sub_account) {
+                Ok(()) => client.subscribe_account(&sub_account).await,
+                Err(err) => Err(err),
+            };
+            if let Err(err) = resubscribed {
+                warn!(target: LOG_TARGET, "account resubscribe failed: {sub_account}, {err:?}");
+            }
+        }
+    }
+
+    /// Journal the drift `event` if it concerns `sub_account`
+    pub(crate) fn push_event(
+        &self,
+        client: &DriftClient,
+        sub_account_id: u16,
+        sub_account: Pubkey,
+        event: &DriftEvent,
+    ) {
+        let (channel, data) =
+            map_drift_event_for_account(client.program_data(), event, sub_account);
+        if let Some(data) = data {
+            self.push(sub_account_id, channel, data);
+        }
+    }
+
+    /// Append an event to the journal and wake any pollers
+    ///
+    /// tx events journaled already are ignored
+    pub(crate) fn push(&self, sub_account_id: u16, channel: Channel, data: AccountEvent) {
+        if let Some(key) = tx_event_key(&data) {
+            if !self
+                .inner
+                .write()
+                .expect("lock")
+                .insert_seen((sub_account_id, key))
+            {
+                debug!(target: LOG_TARGET, "duplicate event ignored: {sub_account_id}, {data:?}");
+                return;
+            }
+        }
+        if let Some(ref store) = self.store {
+            store.record(sub_account_id, channel, &data);
+        }
+        self.orders
+            .on_event(sub_account_id, &data, unix_timestamp());
+        {
+            let mut inner = self.inner.write().expect("lock");
+            inner.next_seq += 1;
+            let seq = inner.next_seq;
+            if inner.entries.len() >= JOURNAL_CAPACITY {
+                inner.entries.pop_front();
+            }
+            inner.entries.push_back(JournalEntry {
+                seq,
+                sub_account_id,
+                channel,
+                data,
+            });
+        }
+        self.notify.notify_waiters();
+    }
+
+    /// Return the lifecycle tracker of journaled orders
+    pub fn orders(&self) -> &OrderTracker {
+        &self.orders
+    }
+
+    /// Return true if events of the gateway authority's sub-account `sub_account_id` are journaled
+    ///
+    /// a follower journals the sub-accounts of its primary
+    pub fn is_journaled(&self, sub_account_id: u16) -> bool {
+        self.following.load(Ordering::Relaxed)
+            || self
+                .subscribed
+                .read()
+                .expect("lock")
+                .contains(&sub_account_id)
+    }
+
+    /// Return the epoch of the journal, changes when the gateway restarts
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// Return the latest sequence number
+    pub fn last_seq(&self) -> u64 {
+        self.inner.read().expect("lock").next_seq
+    }
+
+    /// Return all retained events with sequence number greater than `seq`
+    pub(crate) fn since(&self, seq: u64) -> Vec<JournalEntry> {
+        let inner = self.inner.read().expect("lock");
+        let start = inner.entries.partition_point(|e| e.seq <= seq);
+        inner.entries.range(start..).cloned().collect()
+    }
+
+    /// Return events after `seq`, waiting up to `timeout` for new events if there are none
+    pub(crate) async fn poll(&self, seq: u64, timeout: Duration) -> Vec<JournalEntry> {
+        // register interest before checking to avoid missing a push in between
+        let notified = self.notify.notified();
+        let events = self.since(seq);
+        if !events.is_empty() {
+            return events;
+        }
+        let _ = tokio::time::timeout(timeout, notified).await;
+        self.since(seq)
+    }
+}
+
+/// Return the key of a tx event, identical across event sources, None for other events
+pub(crate) fn tx_event_key(data: &AccountEvent) -> Option<String> {
+    let (signature, key) = match data {
+        AccountEvent::Fill {
+            signature, tx_idx, ..
+        }
+        | AccountEvent::OrderCreate {
+            signature, tx_idx, ..
+        }
+        | AccountEvent::OrderCancel {
+            signature, tx_idx, ..
+        }
+        | AccountEvent::Trade {
+            signature, tx_idx, ..
+        }
+        | AccountEvent::FundingPayment {
+            signature, tx_idx, ..
+        } => (signature, tx_idx.to_string()),
+        AccountEvent::OrderCancelMissing {
+            signature,
+            order_id,
+            ..
+        } => (signature, format!("cancelMissing:{order_id}")),
+        AccountEvent::OrderExpire {
+            signature,
+            order_id,
+            ..
+        } => (signature, format!("expire:{order_id}")),
+        _ => return None,
+    };
+    (!signature.is_empty()).then(|| format!("{signature}:{key}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::Decimal;
+
+    use super::*;
+
+    fn funding(ts: u64) -> AccountEvent {
+        AccountEvent::FundingPayment {
+            amount: Decimal::ONE,
+            market_index: 0,
+            ts,
+            signature: String::new(),
+            tx_idx: 0,
+        }
+    }
+
+    #[test]
+    fn journal_since() {
+        let journal = EventJournal::default();
+        for ts in 0..3 {
+            journal.push(0, Channel::Funding, funding(ts));
+        }
+        assert_eq!(journal.last_seq(), 3);
+        assert_eq!(journal.since(0).len(), 3);
+        let events = journal.since(2);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].seq, 3);
+        assert!(journal.since(3).is_empty());
+    }
+
+    #[test]
+    fn journal_ignores_duplicate_tx_events() {
+        let journal = EventJournal::default();
+        let event = |signature: &str, tx_idx| AccountEvent::FundingPayment {
+            amount: Decimal::ONE,
+            market_index: 0,
+            ts: 1,
+            signature: signature.into(),
+            tx_idx,
+        };
+        journal.push(0, Channel::Funding, event("sig", 0));
+        journal.push(0, Channel::Funding, event("sig", 0));
+        journal.push(0, Channel::Funding, event("sig", 1));
+        journal.push(1, Channel::Funding, event("sig", 0));
+        assert_eq!(journal.last_seq(), 3);
+    }
+
+    #[test]
+    fn journal_entry_roundtrip() {
+        let journal = EventJournal::default();
+        journal.push(2, Channel::Funding, funding(7));
+        let json = serde_json::to_string(&journal.since(0)).unwrap();
+
+        let follower = EventJournal::default();
+        for entry in serde_json::from_str::<Vec<JournalEntry>>(&json).unwrap() {
+            follower.push(entry.sub_account_id, entry.channel, entry.data);
+        }
+        let events = follower.since(0);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].sub_account_id, 2);
+        assert!(matches!(
+            events[0].data,
+            AccountEvent::FundingPayment { ts: 7, .. }
+        ));
+    }
+
+    #[actix_web::test]
+    async fn journal_poll_wakes_on_push() {
+        let journal = Arc::new(EventJournal::default());
+        let poller = tokio::spawn({
+            let journal = Arc::clone(&journal);
+            async move { journal.poll(0, Duration::from_secs(5)).await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        journal.push(1, Channel::Funding, funding(1));
+
+        let events = poller.await.unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].sub_account_id, 1);
+
+        // times out with no new events
+        assert!(journal.poll(1, Duration::from_millis(10)).await.is_empty());
+    }
+}
@@ -0,0 +1,213 @@
+//! Execution quality of journaled fills
+//!
+//! Fills are compared against the oracle price at fill time (spread capture) and the sampled
+//! oracle price `horizon` secs later (markout). Amounts are weighted by the quote notional of each
+//! fill.
+
+use std::collections::{BTreeMap, HashMap};
+
+use drift_rs::types::MarketType;
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::{history::OracleSample, types::Market, websocket::Side};
+
+/// Max. secs between the horizon and the oracle sample a fill is marked out against
+pub const MAX_MARKOUT_DELAY: u64 = 30;
+
+/// Fill of the reported sub-account
+#[derive(Debug, Clone)]
+pub struct ExecutedFill {
+    pub market: Market,
+    pub ts: u64,
+    pub side: Side,
+    pub maker: bool,
+    pub price: Decimal,
+    pub oracle_price: Decimal,
+    pub amount: Decimal,
+}
+
+/// Execution quality of fills as maker or taker, bps are notional weighted
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct RoleExecution {
+    pub fills: u64,
+    /// quote notional
+    pub volume: Decimal,
+    /// fill price improvement on the oracle price, negative if filled worse than the oracle
+    pub spread_capture_bps: Decimal,
+    /// oracle move against the fill after the horizon, negative if it moved in favor
+    pub adverse_selection_bps: Decimal,
+    /// fills with an oracle sample to mark out against, others don't count to adverse selection
+    pub markout_fills: u64,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct MarketExecution {
+    #[serde(flatten)]
+    pub market: Market,
+    /// maker share of volume
+    pub maker_ratio: Decimal,
+    pub maker: RoleExecution,
+    pub taker: RoleExecution,
+}
+
+#[derive(Default)]
+struct RoleSums {
+    fills: u64,
+    volume: Decimal,
+    /// notional x edge
+    edge: Decimal,
+    markout_fills: u64,
+    markout_volume: Decimal,
+    /// notional x markout
+    markout: Decimal,
+}
+
+impl RoleSums {
+    fn finish(self) -> RoleExecution {
+        let bps = |sum: Decimal, volume: Decimal| {
+            if volume.is_zero() {
+                Decimal::ZERO
+            } else {
+                (sum / volume * Decimal::from(10_000))
+                    .round_dp(2)
+                    .normalize()
+            }
+        };
+        RoleExecution {
+            fills: self.fills,
+            volume: self.volume.normalize(),
+            spread_capture_bps: bps(self.edge, self.volume),
+            adverse_selection_bps: -bps(self.markout, self.markout_volume),
+            markout_fills: self.markout_fills,
+        }
+    }
+}
+
+/// Return the execution quality of `fills` (oldest first) by market
+///
+/// * `oracle_history` - oracle samples by market (oldest first), keyed by index and true if perp
+/// * `horizon` - secs after a fill to mark it out against the oracle price
+pub fn execution_report(
+    fills: &[ExecutedFill],
+    oracle_history: &HashMap<(u16, bool), Vec<OracleSample>>,
+    horizon: u64,
+) -> Vec<MarketExecution> {
+    let mut markets = BTreeMap::<(u16, bool), (Market, RoleSums, RoleSums)>::new();
+    // next oracle sample per market, fills are in time order so samples are only scanned forward
+    let mut cursors = HashMap::<(u16, bool), usize>::new();
+    for fill in fills {
+        if fill.oracle_price.is_zero() {
+            continue;
+        }
+        let key = (
+            fill.market.market_index,
+            fill.market.market_type == MarketType::Perp,
+        );
+        let (_, maker, taker) = markets
+            .entry(key)
+            .or_insert_with(|| (fill.market, RoleSums::default(), RoleSums::default()));
+        let sums = if fill.maker { maker } else { taker };
+        let direction = match fill.side {
+            Side::Buy => Decimal::ONE,
+            Side::Sell => Decimal::NEGATIVE_ONE,
+        };
+        let notional = fill.amount * fill.price;
+        sums.fills += 1;
+        sums.volume += notional;
+        sums.edge += notional * direction * (fill.oracle_price - fill.price) / fill.oracle_price;
+
+        let markout_ts = fill.ts + horizon;
+        let samples = oracle_history.get(&key).map_or(&[][..], Vec::as_slice);
+        let cursor = cursors.entry(key).or_default();
+        while samples.get(*cursor).is_some_and(|s| s.ts < markout_ts) {
+            *cursor += 1;
+        }
+        let later = samples
+            .get(*cursor)
+            .filter(|s| s.ts <= markout_ts + MAX_MARKOUT_DELAY && !s.price.is_zero());
+        if let Some(later) = later {
+            sums.markout_fills += 1;
+            sums.markout_volume += notional;
+            sums.markout +=
+                notional * direction * (later.price - fill.oracle_price) / fill.oracle_price;
+        }
+    }
+    markets
+        .into_values()
+        .map(|(market, maker, taker)| {
+            let total = maker.volume + taker.volume;
+            MarketExecution {
+                market,
+                maker_ratio: if total.is_zero() {
+                    Decimal::ZERO
+                } else {
+                    (maker.volume / total).round_dp(4).normalize()
+                },
+                maker: maker.finish(),
+                taker: taker.finish(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fill(ts: u64, side: Side, maker: bool, price: i64, oracle_price: i64) -> ExecutedFill {
+        ExecutedFill {
+            market: Market::perp(0),
+            ts,
+            side,
+            maker,
+            price: Decimal::from(price),
+            oracle_price: Decimal::from(oracle_price),
+            amount: Decimal::ONE,
+        }
+    }
+
+    #[test]
+    fn spread_capture_and_adverse_selection() {
+        let fills = [
+            // maker bought 1 below the oracle, the oracle then dropped 2
+            fill(0, Side::Buy, true, 99, 100),
+            // taker sold 1 below the oracle
+            fill(10, Side::Sell, false, 99, 100),
+            // maker sold 1 above the oracle
+            fill(60, Side::Sell, true, 99, 98),
+        ];
+        let sample = |ts: u64, price: i64| OracleSample {
+            ts,
+            price: Decimal::from(price),
+        };
+        let oracle_history = HashMap::from([(
+            (0, true),
+            vec![
+                sample(0, 100),
+                sample(30, 99),
+                sample(65, 98),
+                sample(75, 98),
+            ],
+        )]);
+        let report = execution_report(&fills, &oracle_history, 60);
+        assert_eq!(report.len(), 1);
+        let market = &report[0];
+        assert_eq!(market.maker.fills, 2);
+        assert_eq!(market.taker.fills, 1);
+        assert_eq!(market.maker_ratio, Decimal::new(6667, 4));
+        // (100bps x 99 + 102.04bps x 99) / 198
+        assert_eq!(market.maker.spread_capture_bps, Decimal::new(10102, 2));
+        // the last fill has no sample after its horizon
+        assert_eq!(market.maker.markout_fills, 1);
+        assert_eq!(market.maker.adverse_selection_bps, Decimal::from(200));
+        assert_eq!(market.taker.spread_capture_bps, Decimal::from(-100));
+        // sold at an oracle of 100, marked out at 98
+        assert_eq!(market.taker.markout_fills, 1);
+        assert_eq!(market.taker.adverse_selection_bps, Decimal::from(-200));
+    }
+}
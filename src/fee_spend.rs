@@ -0,0 +1,223 @@
+//! Tx fee spend accounting by sub-account
+//!
+//! Fees charged to txs landed on-chain (incl. failed txs, which pay fees too) and Jito tips of
+//! landed bundles are summed per sub-account and UTC day. With `--fills-db` set, daily rollups are written
+//! to the embedded db so they survive restarts.
+
+use serde::{Deserialize, Serialize};
+use solana_sdk::{compute_budget, message::VersionedMessage};
+
+use crate::rollups::{day_of, DailyRollups, Rollup};
+
+/// Fee per tx signature in lamports
+const LAMPORTS_PER_SIGNATURE: u64 = 5_000;
+
+/// Compute unit limit per ix of txs not setting a limit
+const DEFAULT_IX_COMPUTE_UNITS: u64 = 200_000;
+
+/// Max. compute unit limit of a tx
+const MAX_COMPUTE_UNITS: u64 = 1_400_000;
+
+/// Fee spend of a sub-account over one UTC day, amounts in lamports
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct DailyFeeSpend {
+    /// start of the day, unix secs
+    pub day: u64,
+    pub sub_account: String,
+    /// txs landed
+    pub txs: u64,
+    /// signature fees
+    pub base_fees: u64,
+    /// fees charged above the signature fees
+    pub priority_fees: u64,
+    /// Jito bundle tips
+    pub tips: u64,
+}
+
+/// Fees of a tx in lamports
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct TxFees {
+    pub base: u64,
+    pub priority: u64,
+}
+
+impl TxFees {
+    /// Return the fees estimated for landing `message`
+    pub fn of(message: &VersionedMessage) -> Self {
+        let account_keys = message.static_account_keys();
+        let mut cu_limit = None;
+        let mut cu_price = 0_u64;
+        let mut ixs = 0_u64;
+        for ix in message.instructions() {
+            if account_keys.get(ix.program_id_index as usize) != Some(&compute_budget::id()) {
+                ixs += 1;
+                continue;
+            }
+            // first byte is the ComputeBudgetInstruction variant, followed by its LE value
+            match ix.data.split_first() {
+                Some((2, value)) if value.len() >= 4 => {
+                    cu_limit = Some(u32::from_le_bytes(value[..4].try_into().unwrap()) as u64);
+                }
+                Some((3, value)) if value.len() >= 8 => {
+                    cu_price = u64::from_le_bytes(value[..8].try_into().unwrap());
+                }
+                _ => (),
+            }
+        }
+        let cu_limit = cu_limit
+            .unwrap_or(ixs * DEFAULT_IX_COMPUTE_UNITS)
+            .min(MAX_COMPUTE_UNITS);
+        Self {
+            base: message.header().num_required_signatures as u64 * LAMPORTS_PER_SIGNATURE,
+            // compute unit price is in micro-lamports, rounded up
+            priority: (cu_price as u128 * cu_limit as u128).div_ceil(1_000_000) as u64,
+        }
+    }
+
+    /// Return the `fee` charged to a landed tx, split as `estimate`d
+    pub fn charged(fee: u64, estimate: Self) -> Self {
+        let base = estimate.base.min(fee);
+        Self {
+            base,
+            priority: fee - base,
+        }
+    }
+}
+
+impl Rollup for DailyFeeSpend {
+    type Key = (u64, String);
+    const NAME: &'static str = "fee spend";
+
+    fn day(&self) -> u64 {
+        self.day
+    }
+
+    fn key(&self) -> Self::Key {
+        (self.day, self.sub_account.clone())
+    }
+
+    fn store_key(&self) -> Vec<u8> {
+        [&self.day.to_be_bytes()[..], self.sub_account.as_bytes()].concat()
+    }
+}
+
+/// Sums tx fee spend per sub-account and day
+#[derive(Default)]
+pub struct FeeSpendTracker {
+    rollups: DailyRollups<DailyFeeSpend>,
+}
+
+impl FeeSpendTracker {
+    /// Persist rollups to `store`, continuing from those already stored
+    pub fn with_store(store: sled::Tree) -> Self {
+        Self {
+            rollups: DailyRollups::with_store(store),
+        }
+    }
+
+    /// Record a tx of `sub_account` landed at `now`, with the Jito `tip` of its bundle, if any
+    pub fn record(&self, sub_account: &str, fees: TxFees, tip: u64, now: u64) {
+        let day = day_of(now);
+        let init = || DailyFeeSpend {
+            day,
+            sub_account: sub_account.to_owned(),
+            ..Default::default()
+        };
+        self.rollups
+            .update((day, sub_account.to_owned()), init, |spend| {
+                spend.txs += 1;
+                spend.base_fees += fees.base;
+                spend.priority_fees += fees.priority;
+                spend.tips += tip;
+            });
+    }
+
+    /// Write rollups changed since the last flush to the store, if any
+    pub fn flush(&self) {
+        self.rollups.flush();
+    }
+
+    /// Return daily fee spend between unix timestamps `from` and `to` (inclusive), oldest first
+    pub fn query(&self, from: u64, to: u64, sub_account: Option<&str>) -> Vec<DailyFeeSpend> {
+        self.rollups.query(from, to, |s| {
+            sub_account.map_or(true, |a| s.sub_account == a)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::{
+        compute_budget::ComputeBudgetInstruction, instruction::Instruction, message::Message,
+        pubkey::Pubkey,
+    };
+
+    use super::*;
+    use crate::rollups::SECS_PER_DAY;
+
+    #[test]
+    fn tx_fees_from_compute_budget() {
+        let payer = Pubkey::new_unique();
+        let ix = Instruction::new_with_bytes(Pubkey::new_unique(), &[], vec![]);
+        let message =
+            |ixs: &[Instruction]| VersionedMessage::Legacy(Message::new(ixs, Some(&payer)));
+
+        let fees = TxFees::of(&message(&[
+            ComputeBudgetInstruction::set_compute_unit_price(1_500),
+            ComputeBudgetInstruction::set_compute_unit_limit(300_001),
+            ix.clone(),
+        ]));
+        assert_eq!(
+            fees,
+            TxFees {
+                base: 5_000,
+                priority: 451,
+            }
+        );
+        // default limit per ix without a limit ix
+        let fees = TxFees::of(&message(&[
+            ComputeBudgetInstruction::set_compute_unit_price(1_000),
+            ix.clone(),
+            ix.clone(),
+        ]));
+        assert_eq!(fees.priority, 400);
+
+        // charged fees replace the estimate
+        assert_eq!(
+            TxFees::charged(12_000, TxFees::of(&message(&[ix]))),
+            TxFees {
+                base: 5_000,
+                priority: 7_000,
+            }
+        );
+    }
+
+    #[test]
+    fn spend_rolls_up_daily() {
+        let tracker = FeeSpendTracker::default();
+        let day = 20_000 * SECS_PER_DAY;
+        let fees = TxFees {
+            base: 5_000,
+            priority: 100,
+        };
+        tracker.record("sub-a", fees, 0, day + 10);
+        tracker.record("sub-a", fees, 1_000, day + 20);
+        tracker.record("sub-b", fees, 0, day + 30);
+        tracker.record("sub-a", fees, 0, day + SECS_PER_DAY);
+
+        assert_eq!(
+            tracker.query(day, day, Some("sub-a")),
+            vec![DailyFeeSpend {
+                day,
+                sub_account: "sub-a".into(),
+                txs: 2,
+                base_fees: 10_000,
+                priority_fees: 200,
+                tips: 1_000,
+            }]
+        );
+        assert_eq!(tracker.query(day, day + SECS_PER_DAY, None).len(), 3);
+    }
+}
@@ -0,0 +1,402 @@
+//! Persistent history of fill and order events
+//!
+//! Events journaled for subscribed sub-accounts are also written to an embedded store, keyed by
+//! event time, so fills remain queryable across client disconnects and gateway restarts. An index
+//! keyed by sub-account, channel, and time serves queries without scanning other accounts' events.
+
+use std::{
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+use sled::{transaction::TransactionError, IVec, Transactional};
+
+use crate::{
+    controller::unix_timestamp,
+    types::Market,
+    websocket::{AccountEvent, Channel},
+    LOG_TARGET,
+};
+
+/// Max. events returned by a query
+pub const MAX_QUERY_LIMIT: usize = 1_000;
+
+/// Tree of the (sub-account, channel, time) index
+const INDEX_TREE: &str = "fills_index";
+/// Tree of archival progress
+const ARCHIVE_TREE: &str = "fills_archive";
+/// Key of the end of the archived time range
+const ARCHIVED_UNTIL_KEY: &[u8] = b"archived_until";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct StoredEvent {
+    pub sub_account_id: u16,
+    pub channel: Channel,
+    pub data: AccountEvent,
+}
+
+/// Filter of stored events, bounds are unix timestamps (inclusive)
+#[derive(Debug, Clone)]
+pub(crate) struct FillsQuery {
+    pub sub_account_id: u16,
+    pub channel: Channel,
+    /// only events of this market, events without a market (e.g. cancels) are excluded
+    pub market: Option<Market>,
+    pub from: u64,
+    pub to: u64,
+    pub limit: usize,
+}
+
+/// Embedded store of fill and order events
+pub struct FillStore {
+    db: sled::Db,
+    /// keys of stored events by sub-account, channel, and time
+    index: sled::Tree,
+    /// archival progress
+    archive: sled::Tree,
+    /// disambiguates events with the same timestamp
+    next_seq: AtomicU64,
+}
+
+impl FillStore {
+    /// Open the store at `path`, creating it if missing
+    pub fn open(path: impl AsRef<Path>) -> sled::Result<Self> {
+        Self::from_db(sled::open(path)?)
+    }
+
+    /// Open a separate tree of the store's db e.g. for other persisted state
+    pub fn open_tree(&self, name: &str) -> sled::Result<sled::Tree> {
+        self.db.open_tree(name)
+    }
+
+    fn from_db(db: sled::Db) -> sled::Result<Self> {
+        // continue after the latest stored sequence number
+        let next_seq = db
+            .last()
+            .ok()
+            .flatten()
+            .and_then(|(key, _)| key.get(8..16)?.try_into().ok())
+            .map(|seq| u64::from_be_bytes(seq) + 1)
+            .unwrap_or_default();
+        let index = db.open_tree(INDEX_TREE)?;
+        // index events stored before the index existed
+        if index.is_empty() && !db.is_empty() {
+            for entry in db.iter() {
+                let (key, value) = entry?;
+                if let Ok(event) = serde_json::from_slice::<StoredEvent>(&value) {
+                    index.insert(
+                        index_key(event.sub_account_id, event.channel, &key),
+                        IVec::default(),
+                    )?;
+                }
+            }
+        }
+        Ok(Self {
+            archive: db.open_tree(ARCHIVE_TREE)?,
+            db,
+            index,
+            next_seq: AtomicU64::new(next_seq),
+        })
+    }
+
+    /// Store an event if it is a fill or order event
+    pub(crate) fn record(&self, sub_account_id: u16, channel: Channel, data: &AccountEvent) {
+        if !matches!(channel, Channel::Fills | Channel::Orders) {
+            return;
+        }
+        let ts = event_ts(data).unwrap_or_else(unix_timestamp);
+        let seq = self.next_seq.fetch_add(1, Ordering::Relaxed);
+        let mut key = [0_u8; 16];
+        key[..8].copy_from_slice(&ts.to_be_bytes());
+        key[8..].copy_from_slice(&seq.to_be_bytes());
+
+        let event = StoredEvent {
+            sub_account_id,
+            channel,
+            data: data.clone(),
+        };
+        let index_key = index_key(sub_account_id, channel, &key);
+        let res = serde_json::to_vec(&event)
+            .map_err(|err| err.to_string())
+            .and_then(|value| {
+                (&*self.db, &self.index)
+                    .transaction(|(db, index)| {
+                        db.insert(&key[..], value.as_slice())?;
+                        index.insert(index_key.as_slice(), IVec::default())?;
+                        Ok(())
+                    })
+                    .map_err(|err: TransactionError| err.to_string())
+            });
+        if let Err(err) = res {
+            warn!(target: LOG_TARGET, "couldn't store event: {err}");
+        }
+    }
+
+    /// Return stored events matching `query`, oldest first
+    pub(crate) fn query(&self, query: &FillsQuery) -> Vec<StoredEvent> {
+        self.events(query)
+            .take(query.limit.min(MAX_QUERY_LIMIT))
+            .collect()
+    }
+
+    /// Iterate stored events of all sub-accounts from unix timestamp `from`, oldest first
+    pub(crate) fn events_since(&self, from: u64) -> impl Iterator<Item = StoredEvent> + '_ {
+        self.db
+            .range(from.to_be_bytes()..)
+            .values()
+            .filter_map(|value| serde_json::from_slice::<StoredEvent>(&value.ok()?).ok())
+    }
+
+    /// Iterate stored events of all sub-accounts and their timestamps from unix timestamp `from`
+    /// until `to` (exclusive), oldest first
+    pub(crate) fn events_between(
+        &self,
+        from: u64,
+        to: u64,
+    ) -> impl Iterator<Item = (u64, StoredEvent)> + '_ {
+        self.db
+            .range(from.to_be_bytes()..to.to_be_bytes())
+            .filter_map(|entry| {
+                let (key, value) = entry.ok()?;
+                let ts = u64::from_be_bytes(key.get(..8)?.try_into().ok()?);
+                Some((ts, serde_json::from_slice::<StoredEvent>(&value).ok()?))
+            })
+    }
+
+    /// Return the unix timestamp of the oldest event stored from `from`
+    pub(crate) fn next_event_ts(&self, from: u64) -> Option<u64> {
+        let (key, _) = self.db.range(from.to_be_bytes()..).next()?.ok()?;
+        Some(u64::from_be_bytes(key.get(..8)?.try_into().ok()?))
+    }
+
+    /// Return the end of the archived time range, None if nothing was archived
+    pub(crate) fn archived_until(&self) -> Option<u64> {
+        let value = self.archive.get(ARCHIVED_UNTIL_KEY).ok()??;
+        Some(u64::from_be_bytes(value.as_ref().try_into().ok()?))
+    }
+
+    /// Record that events before unix timestamp `ts` are archived
+    pub(crate) fn set_archived_until(&self, ts: u64) -> sled::Result<()> {
+        self.archive
+            .insert(ARCHIVED_UNTIL_KEY, &ts.to_be_bytes()[..])
+            .map(|_| ())
+    }
+
+    /// Remove events before unix timestamp `ts`, returns the number removed
+    pub(crate) fn prune_before(&self, ts: u64) -> sled::Result<usize> {
+        let mut removed = 0;
+        for entry in self.db.range(..ts.to_be_bytes()) {
+            let (key, value) = entry?;
+            if let Ok(event) = serde_json::from_slice::<StoredEvent>(&value) {
+                self.index
+                    .remove(index_key(event.sub_account_id, event.channel, &key))?;
+            }
+            self.db.remove(key)?;
+            removed += 1;
+        }
+        Ok(removed)
+    }
+
+    /// Iterate all stored events matching `query` oldest first, ignoring its limit
+    pub(crate) fn events<'a>(
+        &'a self,
+        query: &'a FillsQuery,
+    ) -> impl Iterator<Item = StoredEvent> + 'a {
+        let from = index_key(
+            query.sub_account_id,
+            query.channel,
+            &[query.from.to_be_bytes(), [0; 8]].concat(),
+        );
+        let to = index_key(
+            query.sub_account_id,
+            query.channel,
+            &[query.to.to_be_bytes(), [u8::MAX; 8]].concat(),
+        );
+        self.index
+            .range(from..=to)
+            .keys()
+            .filter_map(|key| {
+                let value = self.db.get(key.ok()?.get(3..)?).ok()??;
+                serde_json::from_slice::<StoredEvent>(&value).ok()
+            })
+            .filter(|e| {
+                query
+                    .market
+                    .map_or(true, |m| event_market(&e.data) == Some(m.as_market_id()))
+            })
+    }
+}
+
+/// Return the index key of the event stored at `key`
+fn index_key(sub_account_id: u16, channel: Channel, key: &[u8]) -> Vec<u8> {
+    let channel = match channel {
+        Channel::Fills => 0,
+        _ => 1,
+    };
+    [&sub_account_id.to_be_bytes()[..], &[channel], key].concat()
+}
+
+/// Return the unix timestamp of an event, if it has one
+pub(crate) fn event_ts(event: &AccountEvent) -> Option<u64> {
+    match event {
+        AccountEvent::Fill { ts, .. }
+        | AccountEvent::OrderCreate { ts, .. }
+        | AccountEvent::OrderCancel { ts, .. }
+        | AccountEvent::OrderExpire { ts, .. }
+        | AccountEvent::OrderBackfill { ts, .. }
+        | AccountEvent::OrderClosed { ts, .. } => Some(*ts),
+        _ => None,
+    }
+}
+
+/// Return the market of an event, if it has one
+fn event_market(event: &AccountEvent) -> Option<drift_rs::types::MarketId> {
+    match event {
+        AccountEvent::Fill {
+            market_index,
+            market_type,
+            ..
+        } => Some(Market::new(*market_index, *market_type).as_market_id()),
+        AccountEvent::OrderCreate { order, .. } | AccountEvent::OrderBackfill { order, .. } => {
+            Some(Market::new(order.market_index, order.market_type).as_market_id())
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cancel(order_id: u32, ts: u64) -> AccountEvent {
+        AccountEvent::OrderCancel {
+            order_id,
+            ts,
+            signature: String::new(),
+            tx_idx: 0,
+        }
+    }
+
+    #[test]
+    fn fill_store_works() {
+        let store =
+            FillStore::from_db(sled::Config::new().temporary(true).open().unwrap()).unwrap();
+        store.record(0, Channel::Orders, &cancel(1, 100));
+        store.record(0, Channel::Orders, &cancel(2, 100));
+        store.record(1, Channel::Orders, &cancel(3, 150));
+        store.record(0, Channel::Orders, &cancel(4, 200));
+        // not stored
+        store.record(
+            0,
+            Channel::Funding,
+            &AccountEvent::FundingPayment {
+                amount: Default::default(),
+                market_index: 0,
+                ts: 150,
+                signature: String::new(),
+                tx_idx: 0,
+            },
+        );
+
+        let query = FillsQuery {
+            sub_account_id: 0,
+            channel: Channel::Orders,
+            market: None,
+            from: 100,
+            to: 150,
+            limit: 10,
+        };
+        let order_ids = |events: Vec<StoredEvent>| -> Vec<u32> {
+            events
+                .into_iter()
+                .filter_map(|e| match e.data {
+                    AccountEvent::OrderCancel { order_id, .. } => Some(order_id),
+                    _ => None,
+                })
+                .collect()
+        };
+        assert_eq!(order_ids(store.query(&query)), vec![1, 2]);
+        assert_eq!(
+            order_ids(store.query(&FillsQuery {
+                to: 200,
+                limit: 2,
+                ..query.clone()
+            })),
+            vec![1, 2]
+        );
+        assert_eq!(
+            order_ids(store.query(&FillsQuery {
+                to: 200,
+                ..query.clone()
+            })),
+            vec![1, 2, 4]
+        );
+        assert!(store
+            .query(&FillsQuery {
+                market: Some(Market::perp(0)),
+                ..query
+            })
+            .is_empty());
+
+        // all sub-accounts, in the order stored
+        assert_eq!(order_ids(store.events_since(150).collect()), vec![3, 4]);
+        assert_eq!(
+            order_ids(store.events_between(100, 200).map(|(_ts, e)| e).collect()),
+            vec![1, 2, 3]
+        );
+        assert_eq!(store.next_event_ts(101), Some(150));
+        assert_eq!(store.next_event_ts(201), None);
+    }
+
+    #[test]
+    fn fill_store_prunes_old_events() {
+        let store =
+            FillStore::from_db(sled::Config::new().temporary(true).open().unwrap()).unwrap();
+        store.record(0, Channel::Orders, &cancel(1, 100));
+        store.record(0, Channel::Orders, &cancel(2, 200));
+        assert_eq!(store.archived_until(), None);
+        store.set_archived_until(150).unwrap();
+        assert_eq!(store.archived_until(), Some(150));
+
+        assert_eq!(store.prune_before(150).unwrap(), 1);
+        let events = store.query(&FillsQuery {
+            sub_account_id: 0,
+            channel: Channel::Orders,
+            market: None,
+            from: 0,
+            to: 300,
+            limit: 10,
+        });
+        assert_eq!(events.len(), 1);
+        assert_eq!(store.index.len(), 1);
+    }
+
+    #[test]
+    fn fill_store_indexes_existing_events() {
+        // events stored before the index existed
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let event = StoredEvent {
+            sub_account_id: 2,
+            channel: Channel::Orders,
+            data: cancel(7, 100),
+        };
+        let key = [100_u64.to_be_bytes(), 0_u64.to_be_bytes()].concat();
+        db.insert(key, serde_json::to_vec(&event).unwrap()).unwrap();
+
+        let store = FillStore::from_db(db).unwrap();
+        store.record(2, Channel::Orders, &cancel(8, 100));
+        let events = store.query(&FillsQuery {
+            sub_account_id: 2,
+            channel: Channel::Orders,
+            market: None,
+            from: 0,
+            to: 200,
+            limit: 10,
+        });
+        assert_eq!(events.len(), 2);
+    }
+}
@@ -0,0 +1,1584 @@
+//! FIX 4.4 order-entry and drop copy sessions
+//!
+//! OMS/EMS systems connect over TCP as the session initiator. `NewOrderSingle` and
+//! `OrderCancelRequest` are translated to controller calls, `ExecutionReport`s are sent from
+//! journaled order and fill events of the session's orders.
+//!
+//! Drop copy sessions enter no orders, they're sent an `ExecutionReport` for every fill of the
+//! journaled sub-accounts e.g. for a risk system.
+//!
+//! Sessions are persisted in a [`SessionStore`]: sequence numbers, sent messages, and the open
+//! orders of order-entry sessions carry over to the next logon, so events missed while
+//! disconnected are reported on logon and resend requests are answered with the stored messages.
+//! Events of a previous run of the gateway are caught up from the fills store, those while the
+//! gateway was down aren't observed.
+
+use std::{
+    collections::HashSet,
+    io,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use drift_rs::{types::MarketType, Pubkey};
+use log::{debug, info, warn};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+        TcpListener, TcpStream,
+    },
+};
+
+use crate::{
+    controller::{unix_timestamp, AppState},
+    events::{tx_event_key, JournalEntry},
+    fills::event_ts,
+    net,
+    order_status::{OrderState, OrderStatus},
+    types::{
+        decode_name, CancelOrdersRequest, Market, PlaceOrder, PlaceOrdersRequest, PollEventsRequest,
+    },
+    websocket::{AccountEvent, Side},
+    ws_auth::constant_time_eq,
+    Context, LOG_TARGET,
+};
+
+const SOH: u8 = 0x01;
+const BEGIN_STRING: &str = "FIX.4.4";
+/// Max. size of an inbound message
+const MAX_MESSAGE_LEN: usize = 16 * 1024;
+/// Time allowed for the initiator to logon after connecting
+const LOGON_TIMEOUT: Duration = Duration::from_secs(10);
+/// Long-poll timeout of the journal while waiting for events
+const EVENTS_POLL_TIMEOUT_MS: u64 = 30_000;
+/// Max. messages stored per session for resends
+const MAX_STORED_MESSAGES: u64 = 10_000;
+
+/// FIX field tags
+mod tag {
+    pub const ACCOUNT: u32 = 1;
+    pub const AVG_PX: u32 = 6;
+    pub const BEGIN_SEQ_NO: u32 = 7;
+    pub const BEGIN_STRING: u32 = 8;
+    pub const BODY_LENGTH: u32 = 9;
+    pub const CL_ORD_ID: u32 = 11;
+    pub const COMMISSION: u32 = 12;
+    pub const COMM_TYPE: u32 = 13;
+    pub const CUM_QTY: u32 = 14;
+    pub const EXEC_ID: u32 = 17;
+    pub const EXEC_INST: u32 = 18;
+    pub const LAST_PX: u32 = 31;
+    pub const LAST_QTY: u32 = 32;
+    pub const MSG_SEQ_NUM: u32 = 34;
+    pub const MSG_TYPE: u32 = 35;
+    pub const NEW_SEQ_NO: u32 = 36;
+    pub const ORDER_ID: u32 = 37;
+    pub const ORDER_QTY: u32 = 38;
+    pub const ORD_STATUS: u32 = 39;
+    pub const ORD_TYPE: u32 = 40;
+    pub const ORIG_CL_ORD_ID: u32 = 41;
+    pub const POSS_DUP_FLAG: u32 = 43;
+    pub const PRICE: u32 = 44;
+    pub const REF_SEQ_NUM: u32 = 45;
+    pub const SENDER_COMP_ID: u32 = 49;
+    pub const SENDING_TIME: u32 = 52;
+    pub const SIDE: u32 = 54;
+    pub const SYMBOL: u32 = 55;
+    pub const TARGET_COMP_ID: u32 = 56;
+    pub const TEXT: u32 = 58;
+    pub const TIME_IN_FORCE: u32 = 59;
+    pub const TRANSACT_TIME: u32 = 60;
+    pub const ENCRYPT_METHOD: u32 = 98;
+    pub const CXL_REJ_REASON: u32 = 102;
+    pub const HEART_BT_INT: u32 = 108;
+    pub const TEST_REQ_ID: u32 = 112;
+    pub const ORIG_SENDING_TIME: u32 = 122;
+    pub const GAP_FILL_FLAG: u32 = 123;
+    pub const EXPIRE_TIME: u32 = 126;
+    pub const RESET_SEQ_NUM_FLAG: u32 = 141;
+    pub const EXEC_TYPE: u32 = 150;
+    pub const LEAVES_QTY: u32 = 151;
+    pub const SESSION_REJECT_REASON: u32 = 373;
+    pub const CXL_REJ_RESPONSE_TO: u32 = 434;
+    pub const PASSWORD: u32 = 554;
+}
+
+/// FIX server settings
+#[derive(Debug, Clone, Default)]
+pub struct FixConfig {
+    /// CompID of the gateway, the TargetCompID of initiators
+    pub comp_id: String,
+    /// SenderCompIDs allowed to logon, any if empty
+    pub sender_comp_ids: HashSet<String>,
+    /// SenderCompIDs of drop copy sessions, allowed to logon in addition
+    pub drop_copy_comp_ids: HashSet<String>,
+    /// Password required on logon, if any
+    pub password: Option<String>,
+    /// persists sessions, if set
+    pub store: Option<Arc<SessionStore>>,
+}
+
+/// Persisted state of a session
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct StoredSession {
+    /// next outbound MsgSeqNum
+    out_seq: u64,
+    /// journal sequence number of the last reported event
+    since: u64,
+    /// journal epoch `since` refers to
+    epoch: u64,
+    /// unix timestamp of the last reported event
+    last_ts: u64,
+    /// tx event keys of the reported events at `last_ts`
+    last_keys: Vec<(u16, String)>,
+}
+
+/// Persists the sequence numbers, sent messages, and open orders of sessions
+pub struct SessionStore {
+    tree: sled::Tree,
+}
+
+impl SessionStore {
+    pub fn new(tree: sled::Tree) -> Self {
+        Self { tree }
+    }
+
+    fn load(&self, counterparty: &str) -> sled::Result<Option<StoredSession>> {
+        let state = self.tree.get(key(counterparty, b"s"))?;
+        Ok(state.and_then(|state| serde_json::from_slice(&state).ok()))
+    }
+
+    fn save(&self, counterparty: &str, session: &StoredSession) -> sled::Result<()> {
+        let state = serde_json::to_vec(session).expect("session serializes");
+        self.tree.insert(key(counterparty, b"s"), state)?;
+        Ok(())
+    }
+
+    /// Forget the sequence numbers and messages of `counterparty`, its orders are kept
+    fn reset(&self, counterparty: &str) -> sled::Result<()> {
+        self.tree.remove(key(counterparty, b"s"))?;
+        for entry in self.tree.scan_prefix(key(counterparty, b"r")) {
+            self.tree.remove(entry?.0)?;
+        }
+        Ok(())
+    }
+
+    fn load_orders(&self, counterparty: &str) -> sled::Result<Vec<FixOrder>> {
+        let orders = self.tree.get(key(counterparty, b"o"))?;
+        Ok(orders
+            .and_then(|orders| serde_json::from_slice(&orders).ok())
+            .unwrap_or_default())
+    }
+
+    fn save_orders(&self, counterparty: &str, orders: &[FixOrder]) -> sled::Result<()> {
+        let orders = serde_json::to_vec(orders).expect("orders serialize");
+        self.tree.insert(key(counterparty, b"o"), orders)?;
+        Ok(())
+    }
+
+    /// Store an encoded message sent with `seq`, dropping the oldest beyond the max.
+    fn store_message(&self, counterparty: &str, seq: u64, message: &[u8]) -> sled::Result<()> {
+        self.tree.insert(message_key(counterparty, seq), message)?;
+        if seq > MAX_STORED_MESSAGES {
+            self.tree
+                .remove(message_key(counterparty, seq - MAX_STORED_MESSAGES))?;
+        }
+        Ok(())
+    }
+
+    /// Return the stored messages sent with sequence numbers in `begin..end`
+    fn messages(
+        &self,
+        counterparty: &str,
+        begin: u64,
+        end: u64,
+    ) -> sled::Result<Vec<(u64, Vec<u8>)>> {
+        let prefix_len = key(counterparty, b"r").len();
+        self.tree
+            .range(message_key(counterparty, begin)..message_key(counterparty, end))
+            .map(|entry| {
+                let (key, message) = entry?;
+                let seq = u64::from_be_bytes(key[prefix_len..].try_into().expect("message key"));
+                Ok((seq, message.to_vec()))
+            })
+            .collect()
+    }
+}
+
+/// Return the store key of `counterparty` with `suffix`
+fn key(counterparty: &str, suffix: &[u8]) -> Vec<u8> {
+    let mut key = counterparty.as_bytes().to_vec();
+    // CompIDs have no NUL, so one counterparty's keys aren't a prefix of another's
+    key.push(0);
+    key.extend_from_slice(suffix);
+    key
+}
+
+fn message_key(counterparty: &str, seq: u64) -> Vec<u8> {
+    let mut key = key(counterparty, b"r");
+    key.extend_from_slice(&seq.to_be_bytes());
+    key
+}
+
+/// A FIX message, fields in order excluding the standard header and trailer
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    fields: Vec<(u32, String)>,
+}
+
+impl Message {
+    pub fn new(msg_type: &str) -> Self {
+        Self {
+            fields: vec![(tag::MSG_TYPE, msg_type.to_string())],
+        }
+    }
+
+    pub fn with(mut self, tag: u32, value: impl ToString) -> Self {
+        self.fields.push((tag, value.to_string()));
+        self
+    }
+
+    /// Return the value of the first `tag` field, if any
+    pub fn get(&self, tag: u32) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(t, _)| *t == tag)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn msg_type(&self) -> &str {
+        self.get(tag::MSG_TYPE).unwrap_or_default()
+    }
+
+    fn seq_num(&self) -> Option<u64> {
+        self.get(tag::MSG_SEQ_NUM).and_then(|s| s.parse().ok())
+    }
+
+    /// Encode with the standard header and trailer
+    pub fn encode(&self, sender: &str, target: &str, seq: u64, sending_time: &str) -> Vec<u8> {
+        let mut body = String::new();
+        push_field(&mut body, tag::MSG_TYPE, self.msg_type());
+        push_field(&mut body, tag::SENDER_COMP_ID, sender);
+        push_field(&mut body, tag::TARGET_COMP_ID, target);
+        push_field(&mut body, tag::MSG_SEQ_NUM, &seq.to_string());
+        push_field(&mut body, tag::SENDING_TIME, sending_time);
+        for (tag, value) in self.fields.iter().filter(|(t, _)| *t != tag::MSG_TYPE) {
+            push_field(&mut body, *tag, value);
+        }
+
+        let mut message = String::new();
+        push_field(&mut message, tag::BEGIN_STRING, BEGIN_STRING);
+        push_field(&mut message, tag::BODY_LENGTH, &body.len().to_string());
+        message.push_str(&body);
+        let mut message = message.into_bytes();
+        let checksum = checksum(&message);
+        message.extend_from_slice(format!("10={checksum:03}\x01").as_bytes());
+        message
+    }
+
+    /// Parse the first message of `buf`, removing it from the buffer
+    ///
+    /// Returns none if the message is incomplete
+    pub fn decode(buf: &mut Vec<u8>) -> Result<Option<Self>, String> {
+        let soh = |from: usize| buf[from..].iter().position(|b| *b == SOH).map(|i| from + i);
+        let (Some(begin_end), Some(length_end)) = (soh(0), soh(0).and_then(|i| soh(i + 1))) else {
+            return if buf.len() > MAX_MESSAGE_LEN {
+                Err("message too long".into())
+            } else {
+                Ok(None)
+            };
+        };
+        if &buf[..begin_end] != format!("8={BEGIN_STRING}").as_bytes() {
+            return Err(format!("expected BeginString {BEGIN_STRING}"));
+        }
+        let body_length: usize = std::str::from_utf8(&buf[begin_end + 1..length_end])
+            .ok()
+            .and_then(|f| f.strip_prefix("9="))
+            .and_then(|len| len.parse().ok())
+            .filter(|len| *len <= MAX_MESSAGE_LEN)
+            .ok_or("invalid BodyLength")?;
+        let trailer = length_end + 1 + body_length;
+        // 10=nnn<SOH>
+        if buf.len() < trailer + 7 {
+            return Ok(None);
+        }
+        let expected = format!("10={:03}\x01", checksum(&buf[..trailer]));
+        if &buf[trailer..trailer + 7] != expected.as_bytes() {
+            return Err("invalid CheckSum".into());
+        }
+
+        let body =
+            std::str::from_utf8(&buf[length_end + 1..trailer]).map_err(|_| "invalid UTF-8")?;
+        let mut fields = Vec::new();
+        for field in body.split_terminator(SOH as char) {
+            let (tag, value) = field
+                .split_once('=')
+                .and_then(|(t, v)| Some((t.parse().ok()?, v.to_string())))
+                .ok_or(format!("invalid field: {field}"))?;
+            fields.push((tag, value));
+        }
+        buf.drain(..trailer + 7);
+
+        let message = Self { fields };
+        if message.get(tag::MSG_TYPE).is_none() {
+            return Err("missing MsgType".into());
+        }
+        Ok(Some(message))
+    }
+}
+
+fn push_field(message: &mut String, tag: u32, value: &str) {
+    message.push_str(&tag.to_string());
+    message.push('=');
+    message.push_str(value);
+    message.push(SOH as char);
+}
+
+fn checksum(bytes: &[u8]) -> u32 {
+    bytes.iter().map(|b| *b as u32).sum::<u32>() % 256
+}
+
+/// Format unix timestamp `ms` as a FIX UTCTimestamp e.g. `20241017-08:01:00.000`
+pub fn utc_timestamp(ms: u64) -> String {
+    let secs = ms / 1_000;
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    let time = secs % 86_400;
+    format!(
+        "{year:04}{month:02}{day:02}-{:02}:{:02}:{:02}.{:03}",
+        time / 3_600,
+        time % 3_600 / 60,
+        time % 60,
+        ms % 1_000
+    )
+}
+
+/// Parse a FIX UTCTimestamp as unix timestamp (seconds), millis are truncated
+pub fn parse_utc_timestamp(value: &str) -> Option<i64> {
+    let (date, time) = value.split_once('-')?;
+    let time = time.split('.').next()?;
+    let num = |s: &str| s.parse::<i64>().ok();
+    if date.len() != 8 || time.len() != 8 {
+        return None;
+    }
+    let (year, month, day) = (num(&date[..4])?, num(&date[4..6])?, num(&date[6..])?);
+    let mut hms = time.split(':').map(num);
+    let (h, m, s) = (hms.next()??, hms.next()??, hms.next()??);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || h > 23 || m > 59 || s > 60 {
+        return None;
+    }
+    Some(days_from_civil(year, month, day) * 86_400 + h * 3_600 + m * 60 + s)
+}
+
+/// Return the (year, month, day) of `days` since the unix epoch
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    (yoe + era * 400 + i64::from(month <= 2), month, day)
+}
+
+/// Return the days since the unix epoch of a date
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Translate a `NewOrderSingle` to an order with `user_order_id`
+///
+/// * `market_lookup` - resolve the market of a symbol e.g. `SOL-PERP`
+pub fn place_order(
+    message: &Message,
+    user_order_id: u8,
+    market_lookup: impl Fn(&str) -> Option<Market>,
+) -> Result<PlaceOrder, String> {
+    let required = |tag: u32, name: &str| message.get(tag).ok_or(format!("missing {name}"));
+    let symbol = required(tag::SYMBOL, "Symbol")?;
+    let market = market_lookup(symbol).ok_or(format!("unknown symbol: {symbol}"))?;
+    let quantity = required(tag::ORDER_QTY, "OrderQty")?
+        .parse::<Decimal>()
+        .ok()
+        .filter(|q| q.is_sign_positive() && !q.is_zero())
+        .ok_or("invalid OrderQty")?;
+    let amount = match required(tag::SIDE, "Side")? {
+        "1" => quantity,
+        "2" => -quantity,
+        side => return Err(format!("unsupported Side: {side}")),
+    };
+    let (order_type, price) = match required(tag::ORD_TYPE, "OrdType")? {
+        "1" => ("market", None),
+        "2" => (
+            "limit",
+            Some(
+                required(tag::PRICE, "Price")?
+                    .parse::<Decimal>()
+                    .map_err(|_| "invalid Price")?,
+            ),
+        ),
+        ord_type => return Err(format!("unsupported OrdType: {ord_type}")),
+    };
+    let max_ts = match message.get(tag::TIME_IN_FORCE).unwrap_or("0") {
+        // Day and GTC rest until cancelled, market orders are immediate
+        "0" | "1" => None,
+        "3" if order_type == "market" => None,
+        "6" => Some(
+            parse_utc_timestamp(required(tag::EXPIRE_TIME, "ExpireTime")?)
+                .ok_or("invalid ExpireTime")?,
+        ),
+        tif => return Err(format!("unsupported TimeInForce: {tif}")),
+    };
+    let exec_inst = message.get(tag::EXEC_INST).unwrap_or_default();
+
+    let mut order = json!({
+        "amount": amount,
+        "price": price.unwrap_or_default(),
+        "userOrderId": user_order_id,
+        "orderType": order_type,
+        // participate don't initiate
+        "postOnly": exec_inst.contains('6'),
+        // do not increase
+        "reduceOnly": exec_inst.contains('E'),
+        "maxTs": max_ts,
+    });
+    // market fields are flattened into the order
+    if let (Some(order), Ok(Value::Object(market))) =
+        (order.as_object_mut(), serde_json::to_value(market))
+    {
+        order.extend(market);
+    }
+    serde_json::from_value(order).map_err(|err| err.to_string())
+}
+
+/// An order entered on the session
+#[derive(Debug, Serialize, Deserialize)]
+struct FixOrder {
+    cl_ord_id: String,
+    /// ClOrdID of a pending cancel request
+    cancel_cl_ord_id: Option<String>,
+    symbol: String,
+    side: String,
+    sub_account_id: u16,
+    sub_account: Pubkey,
+    /// reserved gateway-wide until the order is done
+    user_order_id: u8,
+    /// set once the order is created onchain
+    order_id: Option<u32>,
+    quantity: Decimal,
+    filled: Decimal,
+    /// sum of fill amount x price
+    filled_notional: Decimal,
+}
+
+impl FixOrder {
+    fn avg_price(&self) -> Decimal {
+        self.filled_notional
+            .checked_div(self.filled)
+            .unwrap_or_default()
+            .normalize()
+    }
+}
+
+/// Start accepting FIX sessions on `listen_address`
+pub async fn start_fix_server(
+    listener: std::net::TcpListener,
+    controller: AppState,
+    config: FixConfig,
+) {
+    let listener = TcpListener::from_std(listener).expect("FIX listener");
+    info!(
+        target: LOG_TARGET,
+        "FIX server listening at: {}, CompID: {}",
+        listener.local_addr().expect("bound"),
+        config.comp_id
+    );
+    let config = Arc::new(config);
+    // SenderCompIDs logged on, one session each
+    let logged_on = Arc::new(Mutex::new(HashSet::<String>::new()));
+    tokio::spawn(async move {
+        while let Ok((stream, peer)) = listener.accept().await {
+            let controller = controller.clone();
+            let config = Arc::clone(&config);
+            let logged_on = Arc::clone(&logged_on);
+            tokio::spawn(async move {
+                if let Err(err) = run_session(stream, controller, &config, &logged_on).await {
+                    debug!(target: LOG_TARGET, "FIX session {} ended: {err}", net::peer(peer));
+                }
+            });
+        }
+    });
+}
+
+async fn run_session(
+    stream: TcpStream,
+    controller: AppState,
+    config: &FixConfig,
+    logged_on: &Mutex<HashSet<String>>,
+) -> io::Result<()> {
+    let (mut reader, writer) = stream.into_split();
+    let mut buf = Vec::new();
+    let logon = tokio::time::timeout(LOGON_TIMEOUT, read_message(&mut reader, &mut buf))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "no logon"))??;
+
+    let counterparty = logon
+        .get(tag::SENDER_COMP_ID)
+        .unwrap_or_default()
+        .to_string();
+    let mut session = Session {
+        controller,
+        comp_id: config.comp_id.clone(),
+        counterparty: counterparty.clone(),
+        out_seq: 1,
+        in_seq: logon.seq_num().unwrap_or(1) + 1,
+        orders: Vec::new(),
+        exec_ids: 0,
+        drop_copy: config.drop_copy_comp_ids.contains(&counterparty),
+        store: None,
+        since: None,
+        epoch: None,
+        last_ts: unix_timestamp(),
+        last_keys: Vec::new(),
+        writer,
+    };
+    let heartbeat_secs = match check_logon(&logon, config) {
+        Ok(heartbeat_secs) if logged_on.lock().expect("lock").insert(counterparty.clone()) => {
+            heartbeat_secs
+        }
+        Ok(_) => return session.logout("already logged on").await,
+        Err(reason) => return session.logout(&reason).await,
+    };
+    info!(target: LOG_TARGET, "FIX session logged on: {counterparty}");
+
+    let mut reply = Message::new("A")
+        .with(tag::ENCRYPT_METHOD, 0)
+        .with(tag::HEART_BT_INT, heartbeat_secs);
+    if logon.get(tag::RESET_SEQ_NUM_FLAG) == Some("Y") {
+        reply = reply.with(tag::RESET_SEQ_NUM_FLAG, "Y");
+    }
+    let result = async {
+        if let Some(ref store) = config.store {
+            session.restore(store, &logon)?;
+        }
+        session.send(reply).await?;
+        session
+            .run(reader, buf, Duration::from_secs(heartbeat_secs))
+            .await
+    }
+    .await;
+    logged_on.lock().expect("lock").remove(&counterparty);
+    info!(target: LOG_TARGET, "FIX session logged out: {counterparty}");
+    result
+}
+
+/// Validate a logon, returning the heartbeat interval
+fn check_logon(logon: &Message, config: &FixConfig) -> Result<u64, String> {
+    if logon.msg_type() != "A" {
+        return Err("expected Logon".into());
+    }
+    if logon.get(tag::TARGET_COMP_ID) != Some(config.comp_id.as_str()) {
+        return Err(format!("TargetCompID must be {}", config.comp_id));
+    }
+    let sender = logon.get(tag::SENDER_COMP_ID).unwrap_or_default();
+    if sender.is_empty()
+        || (!config.sender_comp_ids.is_empty()
+            && !config.sender_comp_ids.contains(sender)
+            && !config.drop_copy_comp_ids.contains(sender))
+    {
+        return Err(format!("unknown SenderCompID: {sender}"));
+    }
+    if config.password.as_deref().is_some_and(|password| {
+        !constant_time_eq(
+            logon.get(tag::PASSWORD).unwrap_or_default().as_bytes(),
+            password.as_bytes(),
+        )
+    }) {
+        return Err("invalid Password".into());
+    }
+    logon
+        .get(tag::HEART_BT_INT)
+        .and_then(|s| s.parse().ok())
+        .filter(|secs| *secs > 0)
+        .ok_or("invalid HeartBtInt".into())
+}
+
+/// Read the next message, cancel safe
+async fn read_message(reader: &mut OwnedReadHalf, buf: &mut Vec<u8>) -> io::Result<Message> {
+    let mut chunk = [0_u8; 4096];
+    loop {
+        if let Some(message) =
+            Message::decode(buf).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?
+        {
+            return Ok(message);
+        }
+        match reader.read(&mut chunk).await? {
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            n => buf.extend_from_slice(&chunk[..n]),
+        }
+    }
+}
+
+struct Session {
+    controller: AppState,
+    comp_id: String,
+    /// SenderCompID of the initiator
+    counterparty: String,
+    /// next outbound MsgSeqNum
+    out_seq: u64,
+    /// next expected inbound MsgSeqNum
+    in_seq: u64,
+    /// open orders entered on the session
+    orders: Vec<FixOrder>,
+    exec_ids: u64,
+    /// reports fills of all journaled sub-accounts, enters no orders
+    drop_copy: bool,
+    /// persists the session, if set
+    store: Option<Arc<SessionStore>>,
+    /// journal sequence number of the last handled event, None until known
+    since: Option<u64>,
+    /// journal epoch `since` refers to, None until known
+    epoch: Option<u64>,
+    /// unix timestamp of the last handled event, events since are caught up from the fills store
+    /// if the journal restarts
+    last_ts: u64,
+    /// tx event keys of the handled events at `last_ts`
+    last_keys: Vec<(u16, String)>,
+    writer: OwnedWriteHalf,
+}
+
+impl Session {
+    async fn run(
+        &mut self,
+        mut reader: OwnedReadHalf,
+        mut buf: Vec<u8>,
+        heartbeat: Duration,
+    ) -> io::Result<()> {
+        let controller = self.controller.clone();
+        // events after logon only
+        let (mut since, epoch) = controller
+            .poll_events(PollEventsRequest {
+                since: u64::MAX,
+                timeout_ms: 0,
+                epoch: None,
+            })
+            .await
+            .map(|polled| (polled.last_seq, Some(polled.epoch)))
+            .unwrap_or_default();
+        // persisted sessions resume after their last reported event
+        if let Some(resumed) = self.since {
+            if epoch.is_some() && epoch != self.epoch {
+                // the gateway restarted, events of its previous run are caught up from the fills
+                // store and all events of the new journal are new
+                self.catch_up().await?;
+                since = 0;
+            } else {
+                since = since.min(resumed);
+            }
+        }
+        self.since = Some(since);
+        self.epoch = epoch.or(self.epoch);
+        self.save()?;
+        let mut heartbeats = tokio::time::interval(heartbeat);
+        heartbeats.tick().await;
+        let mut last_received = Instant::now();
+
+        loop {
+            tokio::select! {
+                message = read_message(&mut reader, &mut buf) => {
+                    let message = match message {
+                        Ok(message) => message,
+                        Err(err) if err.kind() == io::ErrorKind::InvalidData => {
+                            return self.logout(&err.to_string()).await;
+                        }
+                        Err(err) => return Err(err),
+                    };
+                    last_received = Instant::now();
+                    if !self.handle(message).await? {
+                        return Ok(());
+                    }
+                }
+                polled = controller.poll_events(PollEventsRequest {
+                    since,
+                    timeout_ms: EVENTS_POLL_TIMEOUT_MS,
+                    epoch: None,
+                }) => {
+                    if let Ok(polled) = polled {
+                        since = polled.last_seq;
+                        for entry in polled.events {
+                            self.handle_event(entry).await?;
+                        }
+                        self.since = Some(since);
+                        self.save()?;
+                    }
+                }
+                _ = heartbeats.tick() => {
+                    // the initiator's heartbeats are overdue
+                    if last_received.elapsed() > heartbeat * 2 {
+                        return self.logout("heartbeat timeout").await;
+                    }
+                    self.send(Message::new("0")).await?;
+                }
+            }
+        }
+    }
+
+    /// Handle a message, returning false once the session ends
+    async fn handle(&mut self, message: Message) -> io::Result<bool> {
+        debug!(target: LOG_TARGET, "FIX {}: {message:?}", self.counterparty);
+        if message.get(tag::SENDER_COMP_ID) != Some(self.counterparty.as_str())
+            || message.get(tag::TARGET_COMP_ID) != Some(self.comp_id.as_str())
+        {
+            self.reject(&message, 9, "CompID problem").await?;
+            self.logout("CompID problem").await?;
+            return Ok(false);
+        }
+        let Some(seq) = message.seq_num() else {
+            self.logout("missing MsgSeqNum").await?;
+            return Ok(false);
+        };
+        if seq < self.in_seq {
+            if message.get(tag::POSS_DUP_FLAG) == Some("Y") {
+                return Ok(true);
+            }
+            self.logout(&format!("MsgSeqNum too low, expected {}", self.in_seq))
+                .await?;
+            return Ok(false);
+        }
+        if seq > self.in_seq {
+            // inbound messages aren't stored, orders missed aren't requested again
+            warn!(
+                target: LOG_TARGET,
+                "FIX {}: MsgSeqNum gap, expected {} got {seq}", self.counterparty, self.in_seq
+            );
+        }
+        self.in_seq = seq + 1;
+
+        match message.msg_type() {
+            // heartbeat
+            "0" => (),
+            // test request
+            "1" => {
+                let mut heartbeat = Message::new("0");
+                if let Some(id) = message.get(tag::TEST_REQ_ID) {
+                    heartbeat = heartbeat.with(tag::TEST_REQ_ID, id);
+                }
+                self.send(heartbeat).await?;
+            }
+            // resend request
+            "2" => {
+                let begin = message
+                    .get(tag::BEGIN_SEQ_NO)
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(1);
+                self.resend(begin).await?;
+            }
+            // sequence reset
+            "4" => {
+                if let Some(next) = message.get(tag::NEW_SEQ_NO).and_then(|s| s.parse().ok()) {
+                    self.in_seq = next;
+                }
+            }
+            // logout
+            "5" => {
+                self.send(Message::new("5")).await?;
+                return Ok(false);
+            }
+            "D" | "F" if self.drop_copy => {
+                self.reject(&message, 11, "drop copy session, orders aren't accepted")
+                    .await?
+            }
+            "D" => self.new_order(&message).await?,
+            "F" => self.cancel_order(&message).await?,
+            _ => self.reject(&message, 11, "unsupported MsgType").await?,
+        }
+        Ok(true)
+    }
+
+    async fn new_order(&mut self, message: &Message) -> io::Result<()> {
+        let cl_ord_id = message.get(tag::CL_ORD_ID).unwrap_or_default().to_string();
+        let mut order = FixOrder {
+            cl_ord_id: cl_ord_id.clone(),
+            cancel_cl_ord_id: None,
+            symbol: message.get(tag::SYMBOL).unwrap_or_default().to_string(),
+            side: message.get(tag::SIDE).unwrap_or_default().to_string(),
+            sub_account_id: 0,
+            sub_account: Pubkey::default(),
+            user_order_id: 0,
+            order_id: None,
+            quantity: message
+                .get(tag::ORDER_QTY)
+                .and_then(|q| q.parse().ok())
+                .unwrap_or_default(),
+            filled: Decimal::ZERO,
+            filled_notional: Decimal::ZERO,
+        };
+        if cl_ord_id.is_empty() {
+            return self.order_rejected(&order, "missing ClOrdID").await;
+        }
+        if self.orders.iter().any(|o| o.cl_ord_id == cl_ord_id) {
+            return self.order_rejected(&order, "duplicate ClOrdID").await;
+        }
+
+        let ctx = account_context(message.get(tag::ACCOUNT));
+        let sub_account_id = match self.controller.resolve_sub_account_id(&ctx) {
+            Ok(id) if self.controller.journaled_sub_accounts().contains(&id) => id,
+            Ok(id) => {
+                let reason = format!("sub-account {id} is not subscribed, see --sub-accounts");
+                return self.order_rejected(&order, &reason).await;
+            }
+            Err(err) => return self.order_rejected(&order, &err.to_string()).await,
+        };
+        let sub_account = match self.controller.resolve_sub_account(&ctx) {
+            Ok(sub_account) => sub_account,
+            Err(err) => return self.order_rejected(&order, &err.to_string()).await,
+        };
+        let open = self.controller.open_user_order_ids(&sub_account);
+        let Some(user_order_id) = self.controller.user_order_ids.reserve(&sub_account, &open)
+        else {
+            return self.order_rejected(&order, "too many open orders").await;
+        };
+        order.sub_account_id = sub_account_id;
+        order.sub_account = sub_account;
+        order.user_order_id = user_order_id;
+
+        let client = Arc::clone(&self.controller.client);
+        let place = place_order(message, user_order_id, |symbol| {
+            client
+                .market_lookup(symbol)
+                .map(|m| Market::new(m.index(), m.kind()))
+        });
+        let result = match place {
+            Ok(place) => self
+                .controller
+                .place_orders(
+                    ctx,
+                    PlaceOrdersRequest {
+                        orders: vec![place],
+                    },
+                )
+                .await
+                .map_err(|err| err.to_string()),
+            Err(reason) => Err(reason),
+        };
+        match result {
+            Ok(tx) => {
+                let exec_id = self.next_exec_id();
+                let report =
+                    execution_report(&order, exec_id, "A", "A").with(tag::TEXT, tx.signature());
+                self.orders.push(order);
+                self.send(report).await
+            }
+            Err(reason) => {
+                self.controller
+                    .user_order_ids
+                    .release(&sub_account, user_order_id);
+                self.order_rejected(&order, &reason).await
+            }
+        }
+    }
+
+    async fn cancel_order(&mut self, message: &Message) -> io::Result<()> {
+        let cl_ord_id = message.get(tag::CL_ORD_ID).unwrap_or_default().to_string();
+        let orig_cl_ord_id = message.get(tag::ORIG_CL_ORD_ID).unwrap_or_default();
+        let Some(idx) = self
+            .orders
+            .iter()
+            .position(|o| o.cl_ord_id == orig_cl_ord_id)
+        else {
+            let reject = cancel_reject(&cl_ord_id, orig_cl_ord_id, None, 1, "unknown order");
+            return self.send(reject).await;
+        };
+
+        let order = &self.orders[idx];
+        let ctx = Context {
+            sub_account_id: Some(order.sub_account_id),
+            ..Default::default()
+        };
+        let req = match order.order_id {
+            Some(order_id) => CancelOrdersRequest {
+                ids: Some(vec![order_id]),
+                ..Default::default()
+            },
+            None => CancelOrdersRequest {
+                user_ids: Some(vec![order.user_order_id]),
+                ..Default::default()
+            },
+        };
+        match self.controller.cancel_orders(ctx, req).await {
+            Ok(_) => {
+                self.orders[idx].cancel_cl_ord_id = Some(cl_ord_id);
+                let exec_id = self.next_exec_id();
+                let report = execution_report(&self.orders[idx], exec_id, "6", "6");
+                self.send(report).await
+            }
+            Err(err) => {
+                let order_id = self.orders[idx].order_id;
+                let reject =
+                    cancel_reject(&cl_ord_id, orig_cl_ord_id, order_id, 99, &err.to_string());
+                self.send(reject).await
+            }
+        }
+    }
+
+    /// Send execution reports of an event of the session's orders
+    /// Report the events stored since the last handled event, except those still journaled
+    async fn catch_up(&mut self) -> io::Result<()> {
+        let journaled: HashSet<(u16, String)> = self
+            .controller
+            .poll_events(PollEventsRequest {
+                since: 0,
+                timeout_ms: 0,
+                epoch: None,
+            })
+            .await
+            .map(|polled| polled.events)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|e| Some((e.sub_account_id, tx_event_key(&e.data)?)))
+            .collect();
+        let restarted_ts = self.epoch.unwrap_or_default() / 1_000;
+        let (last_ts, last_keys) = (self.last_ts, self.last_keys.clone());
+        let events: Vec<_> = self
+            .controller
+            .stored_events_since(last_ts)
+            .into_iter()
+            .filter(|e| {
+                let ts = event_ts(&e.data).unwrap_or_default();
+                match tx_event_key(&e.data).map(|key| (e.sub_account_id, key)) {
+                    Some(key) => {
+                        !journaled.contains(&key) && !(ts == last_ts && last_keys.contains(&key))
+                    }
+                    // can't tell events without a key apart, those of the previous run only
+                    None => ts > last_ts && ts < restarted_ts,
+                }
+            })
+            .collect();
+        info!(
+            target: LOG_TARGET,
+            "FIX {}: journal restarted, catching up {} stored events", self.counterparty, events.len()
+        );
+        for entry in events {
+            self.handle_event(entry).await?;
+        }
+        Ok(())
+    }
+
+    /// Handle a journaled event, tracking the last handled for catching up
+    async fn handle_event(&mut self, entry: JournalEntry) -> io::Result<()> {
+        if let Some(ts) = event_ts(&entry.data) {
+            if ts > self.last_ts {
+                self.last_ts = ts;
+                self.last_keys.clear();
+            }
+            if ts == self.last_ts {
+                if let Some(key) = tx_event_key(&entry.data) {
+                    self.last_keys.push((entry.sub_account_id, key));
+                }
+            }
+        }
+        self.on_event(entry).await
+    }
+
+    async fn on_event(&mut self, entry: JournalEntry) -> io::Result<()> {
+        if self.drop_copy {
+            return self.on_drop_copy_event(entry).await;
+        }
+        let sub_account_id = entry.sub_account_id;
+        let find = |orders: &[FixOrder], order_id: u32| {
+            orders
+                .iter()
+                .position(|o| o.sub_account_id == sub_account_id && o.order_id == Some(order_id))
+        };
+        match entry.data {
+            AccountEvent::OrderCreate { order, .. } => {
+                let Some(idx) = self.orders.iter().position(|o| {
+                    o.sub_account_id == sub_account_id
+                        && o.order_id.is_none()
+                        && o.user_order_id == order.user_order_id
+                }) else {
+                    return Ok(());
+                };
+                let fix_order = &mut self.orders[idx];
+                fix_order.order_id = Some(order.order_id);
+                // the gateway may have adjusted the amount e.g. to the market's step size
+                fix_order.quantity = order.amount.abs();
+                let exec_id = self.next_exec_id();
+                let report = execution_report(&self.orders[idx], exec_id, "0", "0");
+                self.send(report).await
+            }
+            AccountEvent::Fill {
+                order_id,
+                amount,
+                price,
+                signature,
+                tx_idx,
+                ..
+            } => {
+                let Some(idx) = find(&self.orders, order_id) else {
+                    return Ok(());
+                };
+                let order = &mut self.orders[idx];
+                order.filled += amount.abs();
+                order.filled_notional += amount.abs() * price;
+                let status = if order.filled >= order.quantity {
+                    "2"
+                } else {
+                    "1"
+                };
+                // unique per fill, the same if the fill is reported again
+                let exec_id = format!("{signature}:{tx_idx}");
+                let report = execution_report(&self.orders[idx], exec_id, "F", status)
+                    .with(tag::LAST_QTY, amount.abs().normalize())
+                    .with(tag::LAST_PX, price.normalize());
+                if status == "2" {
+                    self.remove_order(idx);
+                }
+                self.send(report).await
+            }
+            AccountEvent::OrderCancel { order_id, .. } => {
+                let Some(idx) = find(&self.orders, order_id) else {
+                    return Ok(());
+                };
+                let order = self.remove_order(idx);
+                let exec_id = self.next_exec_id();
+                let report = execution_report(&order, exec_id, "4", "4");
+                self.send(report).await
+            }
+            AccountEvent::OrderExpire { order_id, .. }
+            | AccountEvent::OrderExpired { order_id, .. } => {
+                let Some(idx) = find(&self.orders, order_id) else {
+                    return Ok(());
+                };
+                let order = self.remove_order(idx);
+                let exec_id = self.next_exec_id();
+                let report = execution_report(&order, exec_id, "C", "C");
+                self.send(report).await
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Send an execution report of a fill of any journaled sub-account
+    async fn on_drop_copy_event(&mut self, entry: JournalEntry) -> io::Result<()> {
+        let AccountEvent::Fill {
+            order_id,
+            market_index,
+            market_type,
+            ..
+        } = &entry.data
+        else {
+            return Ok(());
+        };
+        let (order_id, market_index) = (*order_id, *market_index);
+        let ctx = Context {
+            sub_account_id: Some(entry.sub_account_id),
+            ..Default::default()
+        };
+        let order = self.controller.get_order_status(ctx, order_id).ok();
+        let program_data = self.controller.client.program_data();
+        let symbol = match market_type {
+            MarketType::Perp => program_data
+                .perp_market_config_by_index(market_index)
+                .map(|m| decode_name(&m.name)),
+            MarketType::Spot => program_data
+                .spot_market_config_by_index(market_index)
+                .map(|m| decode_name(&m.name)),
+        }
+        .unwrap_or_default();
+        match drop_copy_report(entry.sub_account_id, &entry.data, &symbol, order.as_ref()) {
+            Some(report) => self.send(report).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Resume the persisted state of the session, sequence numbers start over if the initiator
+    /// resets
+    ///
+    /// open orders are kept across resets, their user order ids are reserved again
+    fn restore(&mut self, store: &Arc<SessionStore>, logon: &Message) -> io::Result<()> {
+        if logon.get(tag::RESET_SEQ_NUM_FLAG) == Some("Y") {
+            store.reset(&self.counterparty).map_err(io::Error::other)?;
+        } else if let Some(stored) = store.load(&self.counterparty).map_err(io::Error::other)? {
+            info!(
+                target: LOG_TARGET,
+                "FIX {} resumed: MsgSeqNum {}", self.counterparty, stored.out_seq
+            );
+            self.out_seq = stored.out_seq;
+            self.since = Some(stored.since);
+            self.epoch = Some(stored.epoch);
+            self.last_ts = stored.last_ts;
+            self.last_keys = stored.last_keys;
+        }
+        if !self.drop_copy {
+            self.orders = store
+                .load_orders(&self.counterparty)
+                .map_err(io::Error::other)?;
+            for order in &self.orders {
+                // still reserved if the gateway didn't restart
+                self.controller
+                    .user_order_ids
+                    .claim(&order.sub_account, order.user_order_id);
+            }
+        }
+        self.store = Some(Arc::clone(store));
+        Ok(())
+    }
+
+    /// Persist the session's sequence numbers and open orders, if stored
+    fn save(&self) -> io::Result<()> {
+        let (Some(store), Some(since), Some(epoch)) = (&self.store, self.since, self.epoch) else {
+            return Ok(());
+        };
+        let session = StoredSession {
+            out_seq: self.out_seq,
+            since,
+            epoch,
+            last_ts: self.last_ts,
+            last_keys: self.last_keys.clone(),
+        };
+        store
+            .save(&self.counterparty, &session)
+            .map_err(io::Error::other)?;
+        if !self.drop_copy {
+            store
+                .save_orders(&self.counterparty, &self.orders)
+                .map_err(io::Error::other)?;
+        }
+        Ok(())
+    }
+
+    /// Remove the done order at `idx`, releasing its user order id
+    fn remove_order(&mut self, idx: usize) -> FixOrder {
+        let order = self.orders.remove(idx);
+        self.controller
+            .user_order_ids
+            .release(&order.sub_account, order.user_order_id);
+        order
+    }
+
+    /// Answer a resend request from `begin`, stored messages are resent and session messages gap
+    /// filled
+    async fn resend(&mut self, begin: u64) -> io::Result<()> {
+        let messages = match self.store {
+            Some(ref store) => store
+                .messages(&self.counterparty, begin, self.out_seq)
+                .map_err(io::Error::other)?,
+            None => Vec::new(),
+        };
+        let mut next = begin;
+        for (seq, message) in messages {
+            let Ok(Some(message)) = Message::decode(&mut message.clone()) else {
+                continue;
+            };
+            if seq > next {
+                self.gap_fill(next, seq).await?;
+            }
+            self.write(&possible_duplicate(&message), seq).await?;
+            next = seq + 1;
+        }
+        if next == begin || next < self.out_seq {
+            self.gap_fill(next, self.out_seq).await?;
+        }
+        Ok(())
+    }
+
+    /// Skip the initiator from `seq` to `new_seq`
+    async fn gap_fill(&mut self, seq: u64, new_seq: u64) -> io::Result<()> {
+        let gap_fill = Message::new("4")
+            .with(tag::POSS_DUP_FLAG, "Y")
+            .with(tag::GAP_FILL_FLAG, "Y")
+            .with(tag::NEW_SEQ_NO, new_seq);
+        self.write(&gap_fill, seq).await
+    }
+
+    fn next_exec_id(&mut self) -> String {
+        self.exec_ids += 1;
+        format!("{}-{}", now_ms(), self.exec_ids)
+    }
+
+    async fn order_rejected(&mut self, order: &FixOrder, reason: &str) -> io::Result<()> {
+        let exec_id = self.next_exec_id();
+        let report = execution_report(order, exec_id, "8", "8").with(tag::TEXT, reason);
+        self.send(report).await
+    }
+
+    /// Send a session level reject of `message`
+    async fn reject(&mut self, message: &Message, reason: u32, text: &str) -> io::Result<()> {
+        let reject = Message::new("3")
+            .with(tag::REF_SEQ_NUM, message.seq_num().unwrap_or_default())
+            .with(tag::SESSION_REJECT_REASON, reason)
+            .with(tag::TEXT, text);
+        self.send(reject).await
+    }
+
+    async fn logout(&mut self, reason: &str) -> io::Result<()> {
+        warn!(target: LOG_TARGET, "FIX {} logout: {reason}", self.counterparty);
+        self.send(Message::new("5").with(tag::TEXT, reason)).await
+    }
+
+    async fn send(&mut self, message: Message) -> io::Result<()> {
+        let bytes = self.encode(&message, self.out_seq);
+        // stored ahead of sending, the initiator may request a resend of a message it missed.
+        // session messages are gap filled instead
+        if let Some(ref store) = self.store {
+            if !matches!(message.msg_type(), "0" | "1" | "2" | "3" | "4" | "5" | "A") {
+                store
+                    .store_message(&self.counterparty, self.out_seq, &bytes)
+                    .map_err(io::Error::other)?;
+            }
+        }
+        self.out_seq += 1;
+        self.save()?;
+        self.writer.write_all(&bytes).await
+    }
+
+    async fn write(&mut self, message: &Message, seq: u64) -> io::Result<()> {
+        let bytes = self.encode(message, seq);
+        self.writer.write_all(&bytes).await
+    }
+
+    fn encode(&self, message: &Message, seq: u64) -> Vec<u8> {
+        message.encode(
+            &self.comp_id,
+            &self.counterparty,
+            seq,
+            &utc_timestamp(now_ms()),
+        )
+    }
+}
+
+/// Return the context of a FIX Account, the sub-account id or name
+fn account_context(account: Option<&str>) -> Context {
+    match account {
+        Some(account) => match account.parse() {
+            Ok(id) => Context {
+                sub_account_id: Some(id),
+                ..Default::default()
+            },
+            Err(_) => Context {
+                sub_account: Some(account.to_string()),
+                ..Default::default()
+            },
+        },
+        None => Context::default(),
+    }
+}
+
+fn execution_report(order: &FixOrder, exec_id: String, exec_type: &str, status: &str) -> Message {
+    let leaves = if matches!(status, "2" | "4" | "8" | "C") {
+        Decimal::ZERO
+    } else {
+        (order.quantity - order.filled).max(Decimal::ZERO)
+    };
+    let report = Message::new("8")
+        .with(
+            tag::ORDER_ID,
+            order.order_id.map_or("NONE".into(), |id| id.to_string()),
+        )
+        .with(tag::EXEC_ID, exec_id);
+    // cancels report the ClOrdID of the cancel request
+    let report = match order.cancel_cl_ord_id {
+        Some(ref cancel_cl_ord_id) if matches!(exec_type, "4" | "6") => report
+            .with(tag::CL_ORD_ID, cancel_cl_ord_id)
+            .with(tag::ORIG_CL_ORD_ID, &order.cl_ord_id),
+        _ => report.with(tag::CL_ORD_ID, &order.cl_ord_id),
+    };
+    report
+        .with(tag::EXEC_TYPE, exec_type)
+        .with(tag::ORD_STATUS, status)
+        .with(tag::SYMBOL, &order.symbol)
+        .with(tag::SIDE, &order.side)
+        .with(tag::ORDER_QTY, order.quantity.normalize())
+        .with(tag::LEAVES_QTY, leaves.normalize())
+        .with(tag::CUM_QTY, order.filled.normalize())
+        .with(tag::AVG_PX, order.avg_price())
+        .with(tag::TRANSACT_TIME, utc_timestamp(now_ms()))
+}
+
+/// Return the drop copy execution report of a fill `event` of `sub_account_id`
+///
+/// * `order` - status of the filled order, if tracked
+fn drop_copy_report(
+    sub_account_id: u16,
+    event: &AccountEvent,
+    symbol: &str,
+    order: Option<&OrderStatus>,
+) -> Option<Message> {
+    let AccountEvent::Fill {
+        side,
+        fee,
+        amount,
+        price,
+        order_id,
+        ts,
+        tx_idx,
+        signature,
+        ..
+    } = event
+    else {
+        return None;
+    };
+    let last_qty = amount.abs();
+    let (status, quantity, filled) = match order {
+        Some(order) => (
+            if order.state == OrderState::Filled {
+                "2"
+            } else {
+                "1"
+            },
+            order.amount,
+            order.filled,
+        ),
+        None => ("1", None, last_qty),
+    };
+    let leaves = quantity.map_or(Decimal::ZERO, |q| (q - filled).max(Decimal::ZERO));
+    let report = Message::new("8")
+        .with(tag::ORDER_ID, order_id)
+        // unique per fill, the same as reported by order-entry sessions
+        .with(tag::EXEC_ID, format!("{signature}:{tx_idx}"))
+        .with(tag::EXEC_TYPE, "F")
+        .with(tag::ORD_STATUS, status)
+        .with(tag::ACCOUNT, sub_account_id)
+        .with(tag::SYMBOL, symbol)
+        .with(
+            tag::SIDE,
+            match side {
+                Side::Buy => "1",
+                Side::Sell => "2",
+            },
+        );
+    let report = match quantity {
+        Some(quantity) => report.with(tag::ORDER_QTY, quantity.normalize()),
+        None => report,
+    };
+    Some(
+        report
+            .with(tag::LAST_QTY, last_qty.normalize())
+            .with(tag::LAST_PX, price.normalize())
+            .with(tag::LEAVES_QTY, leaves.normalize())
+            .with(tag::CUM_QTY, filled.normalize())
+            // the average price of earlier fills isn't tracked
+            .with(tag::AVG_PX, 0)
+            .with(tag::COMMISSION, fee.normalize())
+            // absolute
+            .with(tag::COMM_TYPE, 3)
+            .with(tag::TRANSACT_TIME, utc_timestamp(*ts * 1_000)),
+    )
+}
+
+/// Return a sent `message` to resend, flagged as a possible duplicate
+fn possible_duplicate(message: &Message) -> Message {
+    let header = [
+        tag::SENDER_COMP_ID,
+        tag::TARGET_COMP_ID,
+        tag::MSG_SEQ_NUM,
+        tag::SENDING_TIME,
+    ];
+    let resent = Message {
+        fields: message
+            .fields
+            .iter()
+            .filter(|(tag, _)| !header.contains(tag))
+            .cloned()
+            .collect(),
+    }
+    .with(tag::POSS_DUP_FLAG, "Y");
+    match message.get(tag::SENDING_TIME) {
+        Some(sending_time) => resent.with(tag::ORIG_SENDING_TIME, sending_time),
+        None => resent,
+    }
+}
+
+fn cancel_reject(
+    cl_ord_id: &str,
+    orig_cl_ord_id: &str,
+    order_id: Option<u32>,
+    reason: u32,
+    text: &str,
+) -> Message {
+    Message::new("9")
+        .with(
+            tag::ORDER_ID,
+            order_id.map_or("NONE".into(), |id| id.to_string()),
+        )
+        .with(tag::CL_ORD_ID, cl_ord_id)
+        .with(tag::ORIG_CL_ORD_ID, orig_cl_ord_id)
+        // the order is unaffected
+        .with(tag::ORD_STATUS, "0")
+        .with(tag::CXL_REJ_RESPONSE_TO, 1)
+        .with(tag::CXL_REJ_REASON, reason)
+        .with(tag::TEXT, text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_round_trip() {
+        let message = Message::new("D")
+            .with(tag::CL_ORD_ID, "order-1")
+            .with(tag::SYMBOL, "SOL-PERP");
+        let mut buf = message.encode("GATEWAY", "CLIENT", 2, "20241017-08:01:00.000");
+        let encoded = String::from_utf8(buf.clone()).unwrap().replace('\x01', "|");
+        assert_eq!(
+            encoded,
+            "8=FIX.4.4|9=79|35=D|49=GATEWAY|56=CLIENT|34=2|52=20241017-08:01:00.000|11=order-1|55=SOL-PERP|10=192|"
+        );
+
+        // partial messages wait for more bytes
+        let mut partial = buf[..20].to_vec();
+        assert_eq!(Message::decode(&mut partial), Ok(None));
+
+        buf.extend_from_slice(b"8=FIX.4.4");
+        let decoded = Message::decode(&mut buf).unwrap().unwrap();
+        assert_eq!(decoded.msg_type(), "D");
+        assert_eq!(decoded.get(tag::SYMBOL), Some("SOL-PERP"));
+        assert_eq!(decoded.seq_num(), Some(2));
+        assert_eq!(buf, b"8=FIX.4.4");
+
+        let mut corrupted = message.encode("GATEWAY", "CLIENT", 2, "20241017-08:01:00.000");
+        corrupted[30] = b'X';
+        assert!(Message::decode(&mut corrupted).is_err());
+    }
+
+    #[test]
+    fn drop_copy_reports_resent() {
+        let fill = AccountEvent::Fill {
+            side: Side::Sell,
+            fee: Decimal::new(5, 2),
+            amount: Decimal::new(-15, 1),
+            price: Decimal::new(10125, 2),
+            oracle_price: Decimal::new(101, 0),
+            order_id: 42,
+            market_index: 0,
+            market_type: MarketType::Perp,
+            ts: 1_729_152_060,
+            tx_idx: 3,
+            signature: "sig".into(),
+            maker: None,
+            maker_order_id: None,
+            maker_fee: None,
+            taker: None,
+            taker_order_id: None,
+            taker_fee: None,
+        };
+        let report = drop_copy_report(2, &fill, "SOL-PERP", None).unwrap();
+        assert_eq!(report.get(tag::EXEC_ID), Some("sig:3"));
+        assert_eq!(report.get(tag::ACCOUNT), Some("2"));
+        assert_eq!(report.get(tag::SIDE), Some("2"));
+        assert_eq!(report.get(tag::LAST_QTY), Some("1.5"));
+        assert_eq!(report.get(tag::LAST_PX), Some("101.25"));
+        assert_eq!(
+            report.get(tag::TRANSACT_TIME),
+            Some("20241017-08:01:00.000")
+        );
+
+        let store = SessionStore::new(
+            sled::Config::new()
+                .temporary(true)
+                .open()
+                .unwrap()
+                .open_tree("fix")
+                .unwrap(),
+        );
+        let sent = report.encode("GATEWAY", "RISK", 7, "20241017-08:01:00.000");
+        store.store_message("RISK", 7, &sent).unwrap();
+        store.store_message("RISK-2", 8, &sent).unwrap();
+        let stored = StoredSession {
+            out_seq: 8,
+            since: 12,
+            epoch: 1_729_152_000_000,
+            last_ts: 1_729_152_060,
+            last_keys: vec![(2, "sig:3".into())],
+        };
+        store.save("RISK", &stored).unwrap();
+        assert_eq!(store.load("RISK").unwrap(), Some(stored));
+        let reports = store.messages("RISK", 1, 8).unwrap();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].0, 7);
+
+        let resent =
+            possible_duplicate(&Message::decode(&mut reports[0].1.clone()).unwrap().unwrap());
+        let resent =
+            Message::decode(&mut resent.encode("GATEWAY", "RISK", 7, "20241017-09:00:00.000"))
+                .unwrap()
+                .unwrap();
+        assert_eq!(resent.get(tag::POSS_DUP_FLAG), Some("Y"));
+        assert_eq!(
+            resent.get(tag::ORIG_SENDING_TIME),
+            Some("20241017-08:01:00.000")
+        );
+        assert_eq!(resent.get(tag::SENDING_TIME), Some("20241017-09:00:00.000"));
+        assert_eq!(resent.get(tag::EXEC_ID), Some("sig:3"));
+
+        store.reset("RISK").unwrap();
+        assert_eq!(store.load("RISK").unwrap(), None);
+        assert!(store.messages("RISK", 1, 8).unwrap().is_empty());
+        assert_eq!(store.messages("RISK-2", 1, 9).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn utc_timestamps() {
+        assert_eq!(utc_timestamp(0), "19700101-00:00:00.000");
+        assert_eq!(utc_timestamp(1_729_152_060_123), "20241017-08:01:00.123");
+        assert_eq!(
+            parse_utc_timestamp("20241017-08:01:00.123"),
+            Some(1_729_152_060)
+        );
+        assert_eq!(
+            parse_utc_timestamp("20240229-23:59:59"),
+            Some(1_709_251_199)
+        );
+        assert_eq!(parse_utc_timestamp("2024-10-17"), None);
+    }
+
+    #[test]
+    fn new_order_single_to_order() {
+        let lookup = |symbol: &str| (symbol == "SOL-PERP").then(|| Market::perp(0));
+        let message = Message::new("D")
+            .with(tag::CL_ORD_ID, "order-1")
+            .with(tag::SYMBOL, "SOL-PERP")
+            .with(tag::SIDE, "2")
+            .with(tag::ORDER_QTY, "1.5")
+            .with(tag::ORD_TYPE, "2")
+            .with(tag::PRICE, "101.25")
+            .with(tag::EXEC_INST, "6");
+        let order = serde_json::to_value(place_order(&message, 7, lookup).unwrap()).unwrap();
+        assert_eq!(order["amount"], "-1.5");
+        assert_eq!(order["price"], "101.25");
+        assert_eq!(order["orderType"], "limit");
+        assert_eq!(order["userOrderId"], 7);
+        assert_eq!(order["postOnly"], true);
+        assert_eq!(order["reduceOnly"], false);
+
+        let unknown = Message::new("D").with(tag::SYMBOL, "BTC-PERP");
+        assert_eq!(
+            place_order(&unknown, 7, lookup).unwrap_err(),
+            "unknown symbol: BTC-PERP"
+        );
+        let ioc_limit = message.with(tag::TIME_IN_FORCE, "3");
+        assert_eq!(
+            place_order(&ioc_limit, 7, lookup).unwrap_err(),
+            "unsupported TimeInForce: 3"
+        );
+    }
+}
@@ -0,0 +1,441 @@
+//! gRPC API alongside HTTP
+//!
+//! Messages of `proto/gateway.proto` mirror the JSON API, requests and responses are converted
+//! through their JSON form so both APIs share the controller and its validation.
+//!
+//! Like REST listeners, the server is limited to some capabilities, and requests are accounted to
+//! their authority and API key.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    time::{Duration, Instant},
+};
+
+use drift_rs::Pubkey;
+use futures_util::{stream, Stream, StreamExt};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use tonic::{
+    metadata::MetadataMap,
+    transport::{server::TcpIncoming, Server},
+    Request, Response, Status,
+};
+
+use crate::{
+    controller::{unix_timestamp, AppState, ControllerError},
+    events::JournalEntry,
+    listeners::Capabilities,
+    request_id::{RequestId, REQUEST_ID_HEADER},
+    types::{
+        CancelOrdersRequest, GetOrdersRequest, GetPositionsRequest, Market, ModifyOrdersRequest,
+        PlaceOrdersRequest, PollEventsRequest,
+    },
+    usage::{api_key_scope, usage_api_key, usage_authority, API_KEY_HEADER},
+    websocket::Channel,
+    Context,
+};
+
+pub mod proto {
+    tonic::include_proto!("drift.gateway.v1");
+}
+
+use proto::gateway_server::{Gateway, GatewayServer};
+
+/// Long-poll timeout of the journal while streaming events
+const EVENTS_POLL_TIMEOUT_MS: u64 = 30_000;
+
+type EventStream = Pin<Box<dyn Stream<Item = Result<proto::Event, Status>> + Send>>;
+
+/// Serve the gRPC API on `listener`
+///
+/// * `capabilities` - requests allowed, `admin` has no effect
+/// * `max_message_size` - max. request message size in bytes, as the HTTP body size limit
+pub async fn serve(
+    listener: std::net::TcpListener,
+    controller: AppState,
+    capabilities: Capabilities,
+    max_message_size: usize,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let listener = tokio::net::TcpListener::from_std(listener)?;
+    let incoming = TcpIncoming::from_listener(listener, true, None)?;
+    let default_authority = *controller.authority();
+    let service = GatewayService {
+        controller,
+        capabilities,
+        default_authority,
+    };
+    Server::builder()
+        .add_service(GatewayServer::new(service).max_decoding_message_size(max_message_size))
+        .serve_with_incoming(incoming)
+        .await?;
+    Ok(())
+}
+
+struct GatewayService {
+    controller: AppState,
+    capabilities: Capabilities,
+    default_authority: Pubkey,
+}
+
+/// Metadata of a request, read before its message is taken
+struct Call {
+    request_id: RequestId,
+    api_key: Option<String>,
+}
+
+impl Call {
+    fn of<T>(req: &Request<T>) -> Self {
+        let metadata = req.metadata();
+        Self {
+            request_id: request_id(metadata),
+            api_key: usage_api_key(metadata.get(API_KEY_HEADER).map(|value| value.as_bytes())),
+        }
+    }
+}
+
+impl GatewayService {
+    /// Refuse requests needing a capability the server lacks, `trade` if the request changes state
+    fn check(&self, trade: bool) -> Result<(), Status> {
+        let missing = if trade {
+            (!self.capabilities.trade).then_some("trade")
+        } else {
+            (!self.capabilities.read).then_some("read")
+        };
+        match missing {
+            Some(capability) => Err(Status::permission_denied(format!(
+                "listener lacks capability: {capability}"
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Run `call` if allowed, accounting its usage to the `authority` acted for and API key
+    async fn handle<T: Serialize, U: DeserializeOwned + prost::Message>(
+        &self,
+        meta: Call,
+        trade: bool,
+        authority: Option<&str>,
+        call: impl Future<Output = Result<T, ControllerError>>,
+    ) -> Result<Response<U>, Status> {
+        if let Err(mut status) = self.check(trade) {
+            tag(&meta.request_id, status.metadata_mut());
+            return Err(status);
+        }
+        let authority = usage_authority(authority, &self.default_authority);
+        let result = handle(meta.request_id, api_key_scope(meta.api_key.clone(), call)).await;
+        let egress_bytes = result
+            .as_ref()
+            .map_or(0, |res: &Response<U>| res.get_ref().encoded_len());
+        self.controller.usage.record_request(
+            &authority,
+            meta.api_key.as_deref(),
+            egress_bytes as u64,
+            unix_timestamp(),
+        );
+        result
+    }
+}
+
+#[tonic::async_trait]
+impl Gateway for GatewayService {
+    type SubscribeEventsStream = EventStream;
+
+    async fn get_markets(
+        &self,
+        req: Request<proto::GetMarketsRequest>,
+    ) -> Result<Response<proto::GetMarketsResponse>, Status> {
+        self.handle(Call::of(&req), false, None, async {
+            Ok(self.controller.get_markets())
+        })
+        .await
+    }
+
+    async fn get_positions(
+        &self,
+        req: Request<proto::GetPositionsRequest>,
+    ) -> Result<Response<proto::GetPositionsResponse>, Status> {
+        let call = Call::of(&req);
+        let req = req.into_inner();
+        let authority = req.context.as_ref().and_then(|ctx| ctx.authority.clone());
+        self.handle(call, false, authority.as_deref(), async {
+            let ctx = context(req.context)?;
+            let market: Option<Market> = req.market.map(convert_request).transpose()?;
+            self.controller
+                .get_positions(ctx, market.map(|market| GetPositionsRequest { market }))
+                .await
+        })
+        .await
+    }
+
+    async fn get_orders(
+        &self,
+        req: Request<proto::GetOrdersRequest>,
+    ) -> Result<Response<proto::GetOrdersResponse>, Status> {
+        let call = Call::of(&req);
+        let req = req.into_inner();
+        let authority = req.context.as_ref().and_then(|ctx| ctx.authority.clone());
+        self.handle(call, false, authority.as_deref(), async {
+            let ctx = context(req.context)?;
+            let market: Option<Market> = req.market.map(convert_request).transpose()?;
+            self.controller
+                .get_orders(ctx, market.map(|market| GetOrdersRequest { market }))
+                .await
+        })
+        .await
+    }
+
+    async fn place_orders(
+        &self,
+        req: Request<proto::PlaceOrdersRequest>,
+    ) -> Result<Response<proto::TxResponse>, Status> {
+        let call = Call::of(&req);
+        let req = req.into_inner();
+        let authority = req.context.as_ref().and_then(|ctx| ctx.authority.clone());
+        self.handle(call, true, authority.as_deref(), async {
+            let ctx = context(req.context)?;
+            let orders = convert_request(req.orders)?;
+            self.controller
+                .place_orders(ctx, PlaceOrdersRequest { orders })
+                .await
+        })
+        .await
+    }
+
+    async fn modify_orders(
+        &self,
+        req: Request<proto::ModifyOrdersRequest>,
+    ) -> Result<Response<proto::TxResponse>, Status> {
+        let call = Call::of(&req);
+        let req = req.into_inner();
+        let authority = req.context.as_ref().and_then(|ctx| ctx.authority.clone());
+        self.handle(call, true, authority.as_deref(), async {
+            let ctx = context(req.context)?;
+            let orders = convert_request(req.orders)?;
+            self.controller
+                .modify_orders(ctx, ModifyOrdersRequest { orders })
+                .await
+        })
+        .await
+    }
+
+    async fn cancel_orders(
+        &self,
+        req: Request<proto::CancelOrdersRequest>,
+    ) -> Result<Response<proto::TxResponse>, Status> {
+        let call = Call::of(&req);
+        let req = req.into_inner();
+        let authority = req.context.as_ref().and_then(|ctx| ctx.authority.clone());
+        self.handle(call, true, authority.as_deref(), async {
+            let ctx = context(req.context)?;
+            // repeated fields can't be unset, empty ids mean none given
+            let cancel = CancelOrdersRequest {
+                market: req.market.map(convert_request).transpose()?,
+                ids: (!req.ids.is_empty()).then_some(req.ids),
+                user_ids: (!req.user_ids.is_empty())
+                    .then(|| convert_request(req.user_ids))
+                    .transpose()?,
+            };
+            self.controller.cancel_orders(ctx, cancel).await
+        })
+        .await
+    }
+
+    async fn subscribe_events(
+        &self,
+        req: Request<proto::SubscribeEventsRequest>,
+    ) -> Result<Response<Self::SubscribeEventsStream>, Status> {
+        self.check(false)?;
+        let api_key = usage_api_key(
+            req.metadata()
+                .get(API_KEY_HEADER)
+                .map(|value| value.as_bytes()),
+        );
+        self.controller.usage.record_request(
+            &self.default_authority.to_string(),
+            api_key.as_deref(),
+            0,
+            unix_timestamp(),
+        );
+        let req = req.into_inner();
+        let channels: Vec<Channel> = convert_request(req.channels).map_err(to_status)?;
+        let sub_account_id = req.sub_account_id;
+        let controller = self.controller.clone();
+
+        // poll the journal from `since` until an error ends the stream
+        let events = stream::unfold(Some(req.since), move |since| {
+            let controller = controller.clone();
+            let channels = channels.clone();
+            async move {
+                let poll = PollEventsRequest {
+                    since: since?,
+                    timeout_ms: EVENTS_POLL_TIMEOUT_MS,
+                    epoch: None,
+                };
+                let (events, next) = match controller.poll_events(poll).await {
+                    Ok(polled) => (
+                        polled
+                            .events
+                            .into_iter()
+                            .filter(|e| {
+                                sub_account_id.map_or(true, |id| id == u32::from(e.sub_account_id))
+                                    && (channels.is_empty() || channels.contains(&e.channel))
+                            })
+                            .map(to_event)
+                            .collect(),
+                        Some(polled.last_seq),
+                    ),
+                    Err(err) => (vec![Err(to_status(err))], None),
+                };
+                Some((stream::iter(events), next))
+            }
+        })
+        .flatten();
+
+        Ok(Response::new(Box::pin(events)))
+    }
+}
+
+/// Return the id of a request, from its `x-request-id` metadata if valid
+fn request_id(metadata: &MetadataMap) -> RequestId {
+    RequestId::from_client(
+        metadata
+            .get(REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok()),
+    )
+}
+
+/// Run `call` in the scope of `request_id`, converting its result to the response message
+///
+/// the request id is returned in the response or error metadata
+async fn handle<T: Serialize, U: DeserializeOwned>(
+    request_id: RequestId,
+    call: impl Future<Output = Result<T, ControllerError>>,
+) -> Result<Response<U>, Status> {
+    let result = request_id.clone().scope(call).await.map_err(to_status);
+    match result
+        .and_then(|payload| convert(payload).map_err(|err| Status::internal(err.to_string())))
+    {
+        Ok(payload) => {
+            let mut response = Response::new(payload);
+            tag(&request_id, response.metadata_mut());
+            Ok(response)
+        }
+        Err(mut status) => {
+            tag(&request_id, status.metadata_mut());
+            Err(status)
+        }
+    }
+}
+
+/// Return `request_id` in response or error `metadata`
+fn tag(request_id: &RequestId, metadata: &mut MetadataMap) {
+    if let Ok(value) = request_id.as_str().parse() {
+        metadata.insert(REQUEST_ID_HEADER, value);
+    }
+}
+
+/// Return the request context of `ctx`, the default if unset
+fn context(ctx: Option<proto::Context>) -> Result<Context, ControllerError> {
+    let ctx = ctx.unwrap_or_default();
+    let deadline_ms = ctx.deadline_ms;
+    let mut context: Context = convert_request(ctx)?;
+    context.tx_deadline = deadline_ms.map(|ms| Instant::now() + Duration::from_millis(ms));
+    Ok(context)
+}
+
+/// Convert a request message to its JSON API type
+fn convert_request<T: Serialize, U: DeserializeOwned>(value: T) -> Result<U, ControllerError> {
+    convert(value).map_err(|err| ControllerError::BadRequest(err.to_string()))
+}
+
+/// Convert between a message and its JSON API type
+fn convert<T: Serialize, U: DeserializeOwned>(value: T) -> Result<U, serde_json::Error> {
+    let mut value = serde_json::to_value(value)?;
+    strip_nulls(&mut value);
+    serde_json::from_value(value)
+}
+
+/// Remove unset fields, JSON API types default missing fields but may refuse nulls
+fn strip_nulls(value: &mut Value) {
+    match value {
+        Value::Object(fields) => {
+            fields.retain(|_, v| !v.is_null());
+            fields.values_mut().for_each(strip_nulls);
+        }
+        Value::Array(items) => items.iter_mut().for_each(strip_nulls),
+        _ => (),
+    }
+}
+
+fn to_event(entry: JournalEntry) -> Result<proto::Event, Status> {
+    let channel = match serde_json::to_value(entry.channel) {
+        Ok(Value::String(channel)) => channel,
+        _ => return Err(Status::internal("invalid event channel")),
+    };
+    Ok(proto::Event {
+        seq: entry.seq,
+        sub_account_id: entry.sub_account_id.into(),
+        channel,
+        data: serde_json::to_string(&entry.data)
+            .map_err(|err| Status::internal(err.to_string()))?,
+    })
+}
+
+/// Map controller errors to status codes, as `handle_result` does for HTTP
+fn to_status(err: ControllerError) -> Status {
+    let message = err.to_string();
+    match err {
+        ControllerError::BadRequest(_) | ControllerError::TxFailed { .. } => {
+            Status::invalid_argument(message)
+        }
+        ControllerError::TxNotFound { .. } => Status::not_found(message),
+        ControllerError::MarketPaused(_) => Status::failed_precondition(message),
+        ControllerError::RateLimited(_) => Status::resource_exhausted(message),
+        ControllerError::Timeout(_) | ControllerError::DeadlineExceeded(_) => {
+            Status::deadline_exceeded(message)
+        }
+        ControllerError::Standby
+        | ControllerError::Halted(_)
+        | ControllerError::Bundle(_)
+        | ControllerError::Signer(_) => Status::unavailable(message),
+        ControllerError::Sdk(_) => Status::internal(message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_convert_to_api_types() {
+        let order = proto::PlaceOrder {
+            market_index: 1,
+            market_type: "perp".into(),
+            amount: "-0.5".into(),
+            price: Some("101.25".into()),
+            ..Default::default()
+        };
+        let orders: Vec<crate::types::PlaceOrder> = convert_request(vec![order]).unwrap();
+        assert_eq!(orders[0].market.market_index, 1);
+        assert_eq!(
+            orders[0].market.market_type,
+            drift_rs::types::MarketType::Perp
+        );
+
+        let ctx = context(Some(proto::Context {
+            sub_account_id: Some(2),
+            deadline_ms: Some(500),
+            ..Default::default()
+        }))
+        .unwrap();
+        assert_eq!(ctx.sub_account_id, Some(2));
+        assert!(ctx.tx_deadline.is_some());
+
+        let market = proto::Market {
+            market_index: 0,
+            market_type: "swap".into(),
+        };
+        assert!(convert_request::<_, Market>(market).is_err());
+    }
+}
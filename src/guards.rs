@@ -0,0 +1,1257 @@
+//! Pre-trade order checks
+//!
+//! Catch orders the program would reject before a tx is sent, saving the fee and round trip,
+//! and enforce the gateway's own risk limits.
+
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+    sync::{Mutex, RwLock},
+    time::{Duration, Instant},
+};
+
+use drift_rs::{
+    drift_idl::types::PerpPosition,
+    math::constants::BASE_PRECISION,
+    types::{
+        MarketId, MarketStatus, MarketType, OrderParams, OrderType, PositionDirection,
+        PostOnlyParam,
+    },
+    Pubkey,
+};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    controller::{ControllerError, GatewayResult},
+    types::{Market, MarketOperations, OrderAdjustment},
+    whatif::fill_price,
+};
+
+/// Validate reduce-only perp orders against the user's positions
+///
+/// Orders exceeding the position size are clamped to it, the adjustments are returned.
+/// The position is reduced cumulatively across the batch, an order is clamped to the size left
+/// by the reduce-only orders before it. Orders that could only increase (or open) a position, or
+/// with no size left to reduce, are rejected.
+pub fn reduce_only_guard(
+    perp_positions: &[PerpPosition],
+    orders: &mut [OrderParams],
+) -> GatewayResult<Vec<OrderAdjustment>> {
+    let base_decimals = BASE_PRECISION.ilog10();
+    let mut adjustments = Vec::new();
+    // position size left to reduce by market
+    let mut remaining = HashMap::<u16, u64>::new();
+    for (order_index, order) in orders.iter_mut().enumerate() {
+        if !order.reduce_only || order.market_type != MarketType::Perp {
+            continue;
+        }
+        let position = perp_positions
+            .iter()
+            .find(|p| p.market_index == order.market_index)
+            .map(|p| p.base_asset_amount)
+            .unwrap_or_default();
+        let reduces = match order.direction {
+            PositionDirection::Long => position < 0,
+            PositionDirection::Short => position > 0,
+        };
+        if !reduces {
+            return Err(ControllerError::BadRequest(format!(
+                "reduce-only order {order_index} would increase position in perp market {}",
+                order.market_index
+            )));
+        }
+        let max_amount = remaining
+            .entry(order.market_index)
+            .or_insert(position.unsigned_abs());
+        if *max_amount == 0 {
+            return Err(ControllerError::BadRequest(format!(
+                "reduce-only order {order_index} exceeds the position left by prior orders in perp market {}",
+                order.market_index
+            )));
+        }
+        if order.base_asset_amount > *max_amount {
+            adjustments.push(OrderAdjustment {
+                order_index,
+                market_index: order.market_index,
+                requested_amount: Decimal::new(order.base_asset_amount as i64, base_decimals),
+                amount: Decimal::new(*max_amount as i64, base_decimals),
+                reason: "reduceOnly".into(),
+            });
+            order.base_asset_amount = *max_amount;
+        }
+        *max_amount -= order.base_asset_amount;
+    }
+
+    Ok(adjustments)
+}
+
+/// Reject orders to a market that would not accept or fill them
+///
+/// returns a warning for markets where fills are degraded but possible
+pub fn market_status_guard(
+    market: Market,
+    operations: &MarketOperations,
+    reduce_only: bool,
+) -> GatewayResult<Option<String>> {
+    let market_type = match market.market_type {
+        MarketType::Perp => "perp",
+        MarketType::Spot => "spot",
+    };
+    let paused = |reason: &str| {
+        Err(ControllerError::MarketPaused(format!(
+            "{market_type} market {} is {reason}",
+            market.market_index
+        )))
+    };
+    if operations.fills_paused() {
+        return paused("fill paused");
+    }
+    match operations.status {
+        MarketStatus::Initialized => paused("not active"),
+        MarketStatus::FillPaused => paused("fill paused"),
+        MarketStatus::Settlement => paused("in settlement"),
+        MarketStatus::Delisted => paused("delisted"),
+        MarketStatus::ReduceOnly if !reduce_only => paused("reduce only"),
+        MarketStatus::AmmPaused => Ok(Some(format!(
+            "{market_type} market {} amm fills are paused",
+            market.market_index
+        ))),
+        _ => Ok(None),
+    }
+}
+
+/// Bound the fill price of a market or oracle order to `max_slippage_bps` from `oracle_price`
+///
+/// the limit price (oracle offset for oracle orders) and auction prices are clamped to the bound,
+/// the program ends auctions at the limit price
+pub fn slippage_guard(
+    order: &mut OrderParams,
+    max_slippage_bps: u32,
+    oracle_price: i64,
+) -> GatewayResult<()> {
+    if max_slippage_bps >= 10_000 {
+        return Err(ControllerError::BadRequest(
+            "maxSlippageBps must be less than 10000".into(),
+        ));
+    }
+    let slippage = (oracle_price as i128 * max_slippage_bps as i128 / 10_000) as i64;
+    let is_long = order.direction == PositionDirection::Long;
+    // the better of `price` and `bound` for the taker, i.e. the tighter limit
+    let clamp = |price: i64, bound: i64| {
+        if is_long {
+            price.min(bound)
+        } else {
+            price.max(bound)
+        }
+    };
+    let bound = match order.order_type {
+        OrderType::Market => {
+            let bound = if is_long {
+                oracle_price + slippage
+            } else {
+                oracle_price - slippage
+            };
+            order.price = match order.price {
+                0 => bound as u64,
+                price => clamp(price as i64, bound) as u64,
+            };
+            bound
+        }
+        // oracle order prices are offsets from the oracle price
+        OrderType::Oracle => {
+            let bound = if is_long { slippage } else { -slippage };
+            let offset = match order.oracle_price_offset {
+                Some(offset) => clamp(offset as i64, bound),
+                None => bound,
+            };
+            order.oracle_price_offset = Some(i32::try_from(offset).map_err(|_| {
+                ControllerError::BadRequest(
+                    "maxSlippageBps exceeds the max. oracle price offset, set a tighter bound"
+                        .into(),
+                )
+            })?);
+            bound
+        }
+        _ => {
+            return Err(ControllerError::BadRequest(
+                "maxSlippageBps is only supported on market and oracle orders".into(),
+            ))
+        }
+    };
+    order.auction_start_price = order.auction_start_price.map(|p| clamp(p, bound));
+    order.auction_end_price = order.auction_end_price.map(|p| clamp(p, bound));
+
+    Ok(())
+}
+
+/// Peg an order to `offset_bps` from `oracle_price` as an oracle offset limit order
+///
+/// the offset is rounded to `tick_size`, down for bids and up for asks i.e. never more aggressive
+/// than requested. Returns the offset.
+pub fn oracle_peg(
+    order: &mut OrderParams,
+    offset_bps: i32,
+    oracle_price: i64,
+    tick_size: u64,
+) -> GatewayResult<i64> {
+    if order.oracle_price_offset.is_some() {
+        return Err(ControllerError::BadRequest(
+            "pegToOracle and oraclePriceOffset are exclusive".into(),
+        ));
+    }
+    if order.order_type != OrderType::Limit {
+        return Err(ControllerError::BadRequest(
+            "pegToOracle is only supported on limit orders".into(),
+        ));
+    }
+    if offset_bps.unsigned_abs() >= 10_000 {
+        return Err(ControllerError::BadRequest(
+            "pegToOracle must be within (-10000, 10000) bps".into(),
+        ));
+    }
+    let offset = (oracle_price as i128 * offset_bps as i128 / 10_000) as i64;
+    let tick_size = tick_size.max(1) as i64;
+    let offset = if order.direction == PositionDirection::Long {
+        offset.div_euclid(tick_size) * tick_size
+    } else {
+        -((-offset).div_euclid(tick_size) * tick_size)
+    };
+    let oracle_price_offset = i32::try_from(offset).map_err(|_| {
+        ControllerError::BadRequest(
+            "pegToOracle exceeds the max. oracle price offset, set a smaller offset".into(),
+        )
+    })?;
+    order.price = 0;
+    order.oracle_price_offset = Some(oracle_price_offset);
+
+    Ok(offset)
+}
+
+/// Handling of post-only orders that would cross resting orders
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CrossCheck {
+    Reject,
+    /// reprice one tick behind the best opposite price
+    Adjust,
+}
+
+impl FromStr for CrossCheck {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reject" => Ok(Self::Reject),
+            "adjust" => Ok(Self::Adjust),
+            _ => Err(format!("unknown cross check mode: {s}")),
+        }
+    }
+}
+
+/// True for limit orders the program rejects (or skips) if they would take liquidity
+///
+/// 'slide' orders are repriced by the program itself
+pub fn is_post_only(order: &OrderParams) -> bool {
+    order.order_type == OrderType::Limit
+        && matches!(
+            order.post_only,
+            PostOnlyParam::MustPostOnly | PostOnlyParam::TryPostOnly
+        )
+}
+
+/// Return the best opposite price a post-only `order` would cross at, None if it would rest
+///
+/// * `best_prices` - best bid and ask of resting orders in the order's market
+/// * `oracle_price` - prices oracle offset orders
+pub fn crossing_price(
+    order: &OrderParams,
+    best_prices: (Option<u64>, Option<u64>),
+    oracle_price: i64,
+) -> Option<u64> {
+    if !is_post_only(order) {
+        return None;
+    }
+    let price = fill_price(order, oracle_price);
+    match order.direction {
+        PositionDirection::Long => best_prices.1.filter(|ask| price >= *ask as i64),
+        PositionDirection::Short => best_prices.0.filter(|bid| price <= *bid as i64),
+    }
+}
+
+/// Reprice a post-only `order` crossing at `crossed` to the first `tick_size` behind it
+///
+/// oracle offset orders keep their peg with the offset moved. Returns the new price.
+pub fn reprice_post_only(
+    order: &mut OrderParams,
+    crossed: u64,
+    oracle_price: i64,
+    tick_size: u64,
+) -> GatewayResult<u64> {
+    let tick_size = tick_size.max(1);
+    let price = match order.direction {
+        PositionDirection::Long => (crossed.saturating_sub(1) / tick_size) * tick_size,
+        PositionDirection::Short => (crossed / tick_size + 1) * tick_size,
+    };
+    if price == 0 {
+        return Err(ControllerError::BadRequest(
+            "post-only bid can't rest below the best ask".into(),
+        ));
+    }
+    match order.oracle_price_offset {
+        Some(_) if order.price == 0 => {
+            let offset = i32::try_from(price as i64 - oracle_price).map_err(|_| {
+                ControllerError::BadRequest(
+                    "repriced order exceeds the max. oracle price offset".into(),
+                )
+            })?;
+            order.oracle_price_offset = Some(offset);
+        }
+        _ => order.price = price,
+    }
+
+    Ok(price)
+}
+
+/// Markets whose exposure is capped in aggregate e.g. SOL-PERP + SOL + JitoSOL
+#[derive(Debug, Clone, PartialEq)]
+pub struct RiskGroup {
+    pub name: String,
+    pub markets: Vec<MarketId>,
+    /// max. absolute net notional (USD) across the group's markets
+    pub max_notional: Decimal,
+}
+
+impl RiskGroup {
+    /// Parse risk groups from user command
+    ///
+    /// e.g. `sol=sol-perp,sol,jitosol:50000;btc=btc-perp,wbtc:20000`
+    pub fn parse_list(
+        groups: &str,
+        market_lookup: impl Fn(&str) -> Option<MarketId>,
+    ) -> Result<Vec<Self>, String> {
+        groups
+            .split(';')
+            .map(|group| {
+                let (name, rest) = group
+                    .split_once('=')
+                    .ok_or(format!("expected <name>=<markets>:<max notional>: {group}"))?;
+                let (markets, max_notional) = rest
+                    .rsplit_once(':')
+                    .ok_or(format!("expected <name>=<markets>:<max notional>: {group}"))?;
+                let markets = markets
+                    .split(',')
+                    .map(|ticker| market_lookup(ticker).ok_or(format!("invalid market: {ticker}")))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let max_notional = max_notional
+                    .parse()
+                    .map_err(|_| format!("invalid max notional: {max_notional}"))?;
+                Ok(Self {
+                    name: name.to_string(),
+                    markets,
+                    max_notional,
+                })
+            })
+            .collect()
+    }
+
+    pub fn contains(&self, market: MarketId) -> bool {
+        self.markets.contains(&market)
+    }
+
+    /// Check the group's net notional after adding `delta` to `current` stays within its cap
+    ///
+    /// changes that reduce the exposure are always allowed
+    pub fn check_exposure(&self, current: Decimal, delta: Decimal) -> GatewayResult<()> {
+        let next = current + delta;
+        if next.abs() > self.max_notional && next.abs() > current.abs() {
+            return Err(ControllerError::BadRequest(format!(
+                "orders exceed exposure limit of risk group '{}': {} > {}",
+                self.name,
+                next.abs().round_dp(2),
+                self.max_notional
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Instrument restrictions of an authority's orders
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ComplianceRule {
+    /// markets orders may be placed on, any if unset
+    pub markets: Option<Vec<MarketId>>,
+    /// refuse short orders, unless reduce-only
+    pub long_only: bool,
+    /// max. time to expiry (s) of prediction markets
+    pub max_tenor: Option<u64>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct ComplianceRuleConfig {
+    #[serde(default)]
+    markets: Option<Vec<String>>,
+    #[serde(default)]
+    long_only: bool,
+    #[serde(default)]
+    max_tenor_days: Option<u64>,
+}
+
+impl ComplianceRule {
+    /// Check an order against the rule, returning the constraint it breaks
+    ///
+    /// `expires_in` is the time to expiry (s) of prediction markets
+    pub fn check(
+        &self,
+        market: MarketId,
+        direction: Option<PositionDirection>,
+        reduce_only: bool,
+        expires_in: Option<u64>,
+    ) -> Result<(), String> {
+        if let Some(ref markets) = self.markets {
+            if !markets.contains(&market) {
+                return Err("markets: market not allowed".to_string());
+            }
+        }
+        if self.long_only && direction == Some(PositionDirection::Short) && !reduce_only {
+            return Err("longOnly: short orders must be reduce-only".to_string());
+        }
+        if let (Some(max_tenor), Some(expires_in)) = (self.max_tenor, expires_in) {
+            if expires_in > max_tenor {
+                return Err(format!(
+                    "maxTenorDays: market expires in {} days",
+                    expires_in.div_ceil(86_400)
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Compliance rules by authority
+#[derive(Debug, Default)]
+pub struct ComplianceRules {
+    rules: HashMap<String, ComplianceRule>,
+}
+
+impl ComplianceRules {
+    /// Parse rules from JSON, keyed by authority pubkey or `default` for other authorities
+    ///
+    /// e.g. `{"default":{"markets":["sol-perp"],"longOnly":true,"maxTenorDays":30}}`
+    pub fn parse(
+        json: &str,
+        market_lookup: impl Fn(&str) -> Option<MarketId>,
+    ) -> Result<Self, String> {
+        let configs: HashMap<String, ComplianceRuleConfig> =
+            serde_json::from_str(json).map_err(|err| format!("invalid compliance rules: {err}"))?;
+        let mut rules = HashMap::with_capacity(configs.len());
+        for (authority, config) in configs {
+            let markets = config
+                .markets
+                .map(|markets| {
+                    markets
+                        .iter()
+                        .map(|ticker| {
+                            market_lookup(ticker).ok_or(format!("invalid market: {ticker}"))
+                        })
+                        .collect::<Result<Vec<_>, _>>()
+                })
+                .transpose()?;
+            rules.insert(
+                authority,
+                ComplianceRule {
+                    markets,
+                    long_only: config.long_only,
+                    max_tenor: config.max_tenor_days.map(|days| days * 86_400),
+                },
+            );
+        }
+        Ok(Self { rules })
+    }
+
+    /// Return the rule applying to `authority` and its name, if any
+    pub fn rule(&self, authority: &str) -> Option<(&str, &ComplianceRule)> {
+        self.rules
+            .get_key_value(authority)
+            .or_else(|| self.rules.get_key_value("default"))
+            .map(|(name, rule)| (name.as_str(), rule))
+    }
+}
+
+/// Halts order placement once tripped by a risk limit
+#[derive(Default)]
+pub struct KillSwitch {
+    /// reason the switch was tripped, if tripped
+    reason: RwLock<Option<String>>,
+}
+
+impl KillSwitch {
+    pub fn trip(&self, reason: String) {
+        *self.reason.write().expect("lock") = Some(reason);
+    }
+    pub fn reset(&self) {
+        *self.reason.write().expect("lock") = None;
+    }
+    pub fn is_tripped(&self) -> bool {
+        self.reason.read().expect("lock").is_some()
+    }
+    /// Return an error if the switch is tripped
+    pub fn check(&self) -> GatewayResult<()> {
+        match self.reason.read().expect("lock").as_ref() {
+            Some(reason) => Err(ControllerError::Halted(reason.clone())),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Tracks loss of PnL since the start of the (UTC) day
+///
+/// PnL is equity net of deposits and withdrawals, so realized and unrealized PnL count towards the
+/// day's loss while moving funds in or out doesn't
+#[derive(Default, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DailyLoss {
+    day: u64,
+    start_pnl: Decimal,
+}
+
+impl DailyLoss {
+    /// Update with the PnL at unix timestamp `ts`, returning the day's loss so far
+    ///
+    /// the first update of each day sets its starting PnL
+    pub fn update(&mut self, ts: u64, pnl: Decimal) -> Decimal {
+        let day = ts / (24 * 60 * 60);
+        if day != self.day {
+            self.day = day;
+            self.start_pnl = pnl;
+        }
+        (self.start_pnl - pnl).max(Decimal::ZERO)
+    }
+
+    /// Whether `ts` falls on a later day than the last update
+    pub fn is_new_day(&self, ts: u64) -> bool {
+        ts / (24 * 60 * 60) != self.day
+    }
+
+    /// Day of the last update, days since the unix epoch
+    pub fn day(&self) -> u64 {
+        self.day
+    }
+}
+
+/// Weekly schedule during which order placement is allowed
+///
+/// times are in a fixed UTC offset, not a timezone: windows don't follow daylight saving changes
+/// and must be reconfigured when a desk's local offset changes
+#[derive(Debug, Clone, PartialEq)]
+pub struct TradingWindow {
+    /// allowed days, Monday first
+    days: [bool; 7],
+    /// window open, minutes from midnight
+    start: u32,
+    /// window close, minutes from midnight (before `start` if the window spans midnight)
+    end: u32,
+    /// offset of the window's timezone from UTC in minutes
+    utc_offset: i32,
+}
+
+impl TradingWindow {
+    const DAYS: [&'static str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+    /// Parse a trading window e.g. `mon-fri 08:00-17:00 +08:00`
+    ///
+    /// days are a range, a comma separated list or `daily`, the UTC offset defaults to `+00:00`
+    pub fn parse(window: &str) -> Result<Self, String> {
+        let mut parts = window.split_whitespace();
+        let (Some(days), Some(hours)) = (parts.next(), parts.next()) else {
+            return Err(format!(
+                "expected <days> <HH:MM>-<HH:MM> [<utc offset>]: {window}"
+            ));
+        };
+        let utc_offset = match parts.next() {
+            Some(offset) => {
+                let (sign, hhmm) = match (offset.strip_prefix('+'), offset.strip_prefix('-')) {
+                    (Some(hhmm), _) => (1, hhmm),
+                    (_, Some(hhmm)) => (-1, hhmm),
+                    _ => return Err(format!("invalid utc offset: {offset}")),
+                };
+                sign * parse_hhmm(hhmm)? as i32
+            }
+            None => 0,
+        };
+        if let Some(extra) = parts.next() {
+            return Err(format!(
+                "unexpected '{extra}' after the utc offset, timezone names are not supported: {window}"
+            ));
+        }
+
+        let day_index = |day: &str| {
+            Self::DAYS
+                .iter()
+                .position(|d| *d == day)
+                .ok_or(format!("invalid day: {day}"))
+        };
+        let mut allowed = [false; 7];
+        if days == "daily" {
+            allowed = [true; 7];
+        } else if let Some((first, last)) = days.split_once('-') {
+            let (first, last) = (day_index(first)?, day_index(last)?);
+            for (idx, allowed) in allowed.iter_mut().enumerate() {
+                *allowed = if first <= last {
+                    (first..=last).contains(&idx)
+                } else {
+                    idx >= first || idx <= last
+                };
+            }
+        } else {
+            for day in days.split(',') {
+                allowed[day_index(day)?] = true;
+            }
+        }
+
+        let (start, end) = hours
+            .split_once('-')
+            .ok_or(format!("invalid hours: {hours}"))?;
+        Ok(Self {
+            days: allowed,
+            start: parse_hhmm(start)?,
+            end: parse_hhmm(end)?,
+            utc_offset,
+        })
+    }
+
+    /// Whether the window is open at unix timestamp `ts`
+    pub fn is_open(&self, ts: u64) -> bool {
+        let local_minutes = (ts / 60) as i64 + self.utc_offset as i64;
+        let minute_of_day = local_minutes.rem_euclid(24 * 60) as u32;
+        let day = local_minutes.div_euclid(24 * 60);
+        // 1970-01-01 was a Thursday
+        let weekday = |day: i64| (day + 3).rem_euclid(7) as usize;
+
+        if self.start <= self.end {
+            self.days[weekday(day)] && (self.start..self.end).contains(&minute_of_day)
+        } else if minute_of_day >= self.start {
+            // window spans midnight, opened today
+            self.days[weekday(day)]
+        } else {
+            // window spans midnight, opened yesterday
+            minute_of_day < self.end && self.days[weekday(day - 1)]
+        }
+    }
+}
+
+fn parse_hhmm(hhmm: &str) -> Result<u32, String> {
+    let (h, m) = hhmm
+        .split_once(':')
+        .ok_or(format!("expected HH:MM: {hhmm}"))?;
+    match (h.parse::<u32>(), m.parse::<u32>()) {
+        (Ok(h), Ok(m)) if h <= 24 && m < 60 && h * 60 + m <= 24 * 60 => Ok(h * 60 + m),
+        _ => Err(format!("expected HH:MM: {hhmm}")),
+    }
+}
+
+/// Identity of an order for duplicate detection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct OrderFingerprint {
+    authority: Pubkey,
+    sub_account_id: u16,
+    market_index: u16,
+    is_perp: bool,
+    is_long: bool,
+    price: u64,
+    base_asset_amount: u64,
+    oracle_price_offset: Option<i32>,
+    order_type: u8,
+    reduce_only: bool,
+    post_only: bool,
+    trigger_price: Option<u64>,
+}
+
+impl OrderFingerprint {
+    fn new(authority: Pubkey, sub_account_id: u16, order: &OrderParams) -> Self {
+        Self {
+            authority,
+            sub_account_id,
+            market_index: order.market_index,
+            is_perp: order.market_type == MarketType::Perp,
+            is_long: order.direction == PositionDirection::Long,
+            price: order.price,
+            base_asset_amount: order.base_asset_amount,
+            oracle_price_offset: order.oracle_price_offset,
+            order_type: order.order_type as u8,
+            reduce_only: order.reduce_only,
+            post_only: order.post_only != PostOnlyParam::None,
+            trigger_price: order.trigger_price,
+        }
+    }
+}
+
+/// Rejects orders identical to one placed within a recent window
+pub struct DuplicateOrders {
+    window: Duration,
+    /// last placement time of recent orders
+    seen: Mutex<HashMap<OrderFingerprint, Instant>>,
+}
+
+impl DuplicateOrders {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            seen: Mutex::default(),
+        }
+    }
+
+    /// Return an error if any order duplicates another of the batch or one placed within the
+    /// window, otherwise record them
+    pub fn check_and_record(
+        &self,
+        authority: Pubkey,
+        sub_account_id: u16,
+        orders: &[OrderParams],
+        now: Instant,
+    ) -> GatewayResult<Vec<OrderFingerprint>> {
+        let mut batch = HashSet::with_capacity(orders.len());
+        for (order_index, order) in orders.iter().enumerate() {
+            if !batch.insert(OrderFingerprint::new(authority, sub_account_id, order)) {
+                return Err(ControllerError::BadRequest(format!(
+                    "order {order_index} duplicates an earlier order of the request, set allowDuplicate=true to place it"
+                )));
+            }
+        }
+        // held until recorded, so concurrent requests can't both pass the check
+        let mut seen = self.seen.lock().expect("lock");
+        seen.retain(|_, placed| now.duration_since(*placed) < self.window);
+        for (order_index, order) in orders.iter().enumerate() {
+            if seen.contains_key(&OrderFingerprint::new(authority, sub_account_id, order)) {
+                return Err(ControllerError::BadRequest(format!(
+                    "order {order_index} duplicates an order placed within the last {}ms, set allowDuplicate=true to place it",
+                    self.window.as_millis()
+                )));
+            }
+        }
+        Ok(Self::insert(
+            &mut seen,
+            authority,
+            sub_account_id,
+            orders,
+            now,
+        ))
+    }
+
+    /// Record orders without checking them e.g. those replacing cancelled orders
+    pub fn record(
+        &self,
+        authority: Pubkey,
+        sub_account_id: u16,
+        orders: &[OrderParams],
+        now: Instant,
+    ) -> Vec<OrderFingerprint> {
+        let mut seen = self.seen.lock().expect("lock");
+        Self::insert(&mut seen, authority, sub_account_id, orders, now)
+    }
+
+    /// Record orders in the held `seen` map, returns their fingerprints
+    fn insert(
+        seen: &mut HashMap<OrderFingerprint, Instant>,
+        authority: Pubkey,
+        sub_account_id: u16,
+        orders: &[OrderParams],
+        now: Instant,
+    ) -> Vec<OrderFingerprint> {
+        let fingerprints: Vec<OrderFingerprint> = orders
+            .iter()
+            .map(|o| OrderFingerprint::new(authority, sub_account_id, o))
+            .collect();
+        seen.extend(fingerprints.iter().map(|f| (*f, now)));
+        fingerprints
+    }
+
+    /// Forget recorded orders e.g. after they failed to send
+    pub fn forget(&self, fingerprints: &[OrderFingerprint]) {
+        let mut seen = self.seen.lock().expect("lock");
+        for fingerprint in fingerprints {
+            seen.remove(fingerprint);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn market_status_guard_rejects_paused() {
+        let operations = |status, paused_operations| MarketOperations {
+            status,
+            paused_operations,
+        };
+        let sol = Market::perp(0);
+
+        assert!(matches!(
+            market_status_guard(sol, &operations(MarketStatus::Active, vec![]), false),
+            Ok(None)
+        ));
+        assert!(matches!(
+            market_status_guard(sol, &operations(MarketStatus::Active, vec!["fill"]), false),
+            Err(ControllerError::MarketPaused(_))
+        ));
+        assert!(matches!(
+            market_status_guard(sol, &operations(MarketStatus::Settlement, vec![]), true),
+            Err(ControllerError::MarketPaused(_))
+        ));
+        // reduce only markets accept reduce only orders
+        assert!(matches!(
+            market_status_guard(sol, &operations(MarketStatus::ReduceOnly, vec![]), false),
+            Err(ControllerError::MarketPaused(_))
+        ));
+        assert!(matches!(
+            market_status_guard(sol, &operations(MarketStatus::ReduceOnly, vec![]), true),
+            Ok(None)
+        ));
+        assert!(matches!(
+            market_status_guard(sol, &operations(MarketStatus::AmmPaused, vec![]), false),
+            Ok(Some(_))
+        ));
+    }
+
+    #[test]
+    fn slippage_guard_bounds_fill_price() {
+        // $100 oracle price
+        const ORACLE: i64 = 100_000_000;
+        let order = |order_type, direction, price| OrderParams {
+            order_type,
+            direction,
+            price,
+            ..Default::default()
+        };
+
+        // 50bps above the oracle price for buys
+        let mut buy = order(OrderType::Market, PositionDirection::Long, 0);
+        slippage_guard(&mut buy, 50, ORACLE).unwrap();
+        assert_eq!(buy.price, 100_500_000);
+        // tighter limits are kept
+        let mut buy = order(OrderType::Market, PositionDirection::Long, 100_200_000);
+        slippage_guard(&mut buy, 50, ORACLE).unwrap();
+        assert_eq!(buy.price, 100_200_000);
+        // 50bps below for sells
+        let mut sell = order(OrderType::Market, PositionDirection::Short, 99_000_000);
+        sell.auction_end_price = Some(98_000_000);
+        slippage_guard(&mut sell, 50, ORACLE).unwrap();
+        assert_eq!(sell.price, 99_500_000);
+        assert_eq!(sell.auction_end_price, Some(99_500_000));
+
+        let mut sell = order(OrderType::Oracle, PositionDirection::Short, 0);
+        slippage_guard(&mut sell, 100, ORACLE).unwrap();
+        assert_eq!(sell.oracle_price_offset, Some(-1_000_000));
+
+        let mut limit = order(OrderType::Limit, PositionDirection::Long, ORACLE as u64);
+        assert!(slippage_guard(&mut limit, 50, ORACLE).is_err());
+        let mut buy = order(OrderType::Market, PositionDirection::Long, 0);
+        assert!(slippage_guard(&mut buy, 10_000, ORACLE).is_err());
+
+        // $100k oracle price, a 3% offset exceeds i32
+        let mut buy = order(OrderType::Oracle, PositionDirection::Long, 0);
+        assert!(slippage_guard(&mut buy, 300, 100_000_000_000).is_err());
+        let mut buy = order(OrderType::Oracle, PositionDirection::Long, 0);
+        slippage_guard(&mut buy, 100, 100_000_000_000).unwrap();
+        assert_eq!(buy.oracle_price_offset, Some(1_000_000_000));
+    }
+
+    #[test]
+    fn crossing_post_only_orders_reprice() {
+        // $100 oracle price, $0.01 ticks, book is $99.50 / $100.50
+        const ORACLE: i64 = 100_000_000;
+        const TICK: u64 = 10_000;
+        const BOOK: (Option<u64>, Option<u64>) = (Some(99_500_000), Some(100_500_000));
+        let order = |direction, price| OrderParams {
+            direction,
+            order_type: OrderType::Limit,
+            post_only: PostOnlyParam::MustPostOnly,
+            price,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            crossing_price(&order(PositionDirection::Long, 100_000_000), BOOK, ORACLE),
+            None
+        );
+        assert_eq!(
+            crossing_price(&order(PositionDirection::Long, 100_500_000), BOOK, ORACLE),
+            Some(100_500_000)
+        );
+        assert_eq!(
+            crossing_price(&order(PositionDirection::Short, 99_000_000), BOOK, ORACLE),
+            Some(99_500_000)
+        );
+        assert_eq!(
+            crossing_price(
+                &order(PositionDirection::Short, 99_000_000),
+                (None, None),
+                ORACLE
+            ),
+            None
+        );
+        // takers may cross
+        let taker = OrderParams {
+            post_only: PostOnlyParam::None,
+            ..order(PositionDirection::Long, 101_000_000)
+        };
+        assert_eq!(crossing_price(&taker, BOOK, ORACLE), None);
+
+        let mut bid = order(PositionDirection::Long, 101_000_000);
+        assert_eq!(
+            reprice_post_only(&mut bid, 100_500_000, ORACLE, TICK).unwrap(),
+            100_490_000
+        );
+        assert_eq!(bid.price, 100_490_000);
+        let mut ask = order(PositionDirection::Short, 99_000_000);
+        assert_eq!(
+            reprice_post_only(&mut ask, 99_500_000, ORACLE, TICK).unwrap(),
+            99_510_000
+        );
+        // oracle offset orders move their offset
+        let mut pegged = OrderParams {
+            oracle_price_offset: Some(1_000_000),
+            ..order(PositionDirection::Long, 0)
+        };
+        assert_eq!(crossing_price(&pegged, BOOK, ORACLE), Some(100_500_000));
+        reprice_post_only(&mut pegged, 100_500_000, ORACLE, TICK).unwrap();
+        assert_eq!(pegged.price, 0);
+        assert_eq!(pegged.oracle_price_offset, Some(490_000));
+    }
+
+    #[test]
+    fn oracle_peg_rounds_to_tick() {
+        // $100 oracle price, $0.01 ticks
+        const ORACLE: i64 = 100_000_000;
+        const TICK: u64 = 10_000;
+        let order = |direction| OrderParams {
+            direction,
+            order_type: OrderType::Limit,
+            price: 99_000_000,
+            ..Default::default()
+        };
+
+        // 2.5bps below is $0.025, bids round down
+        let mut bid = order(PositionDirection::Long);
+        assert_eq!(oracle_peg(&mut bid, -5, ORACLE / 2, TICK).unwrap(), -30_000);
+        assert_eq!(bid.order_type, OrderType::Limit);
+        assert_eq!(bid.price, 0);
+        assert_eq!(bid.oracle_price_offset, Some(-30_000));
+        // asks round up
+        let mut ask = order(PositionDirection::Short);
+        assert_eq!(oracle_peg(&mut ask, 5, ORACLE / 2, TICK).unwrap(), 30_000);
+        let mut ask = order(PositionDirection::Short);
+        assert_eq!(oracle_peg(&mut ask, 10, ORACLE, TICK).unwrap(), 100_000);
+
+        let mut pegged = order(PositionDirection::Long);
+        pegged.oracle_price_offset = Some(-10_000);
+        assert!(oracle_peg(&mut pegged, -5, ORACLE, TICK).is_err());
+        let mut trigger = order(PositionDirection::Long);
+        trigger.order_type = OrderType::TriggerLimit;
+        assert!(oracle_peg(&mut trigger, -5, ORACLE, TICK).is_err());
+        // market orders aren't made resting limit orders
+        let mut market = order(PositionDirection::Long);
+        market.order_type = OrderType::Market;
+        assert!(oracle_peg(&mut market, -5, ORACLE, TICK).is_err());
+        assert_eq!(market.order_type, OrderType::Market);
+        // offsets beyond i32 at $100k
+        let mut bid = order(PositionDirection::Long);
+        assert!(oracle_peg(&mut bid, -300, 100_000_000_000, TICK).is_err());
+        assert_eq!(bid.oracle_price_offset, None);
+        assert_eq!(
+            oracle_peg(&mut bid, -100, 100_000_000_000, TICK).unwrap(),
+            -1_000_000_000
+        );
+    }
+
+    #[test]
+    fn duplicate_orders_within_window() {
+        let duplicates = DuplicateOrders::new(Duration::from_millis(500));
+        let now = Instant::now();
+        let order = |price| OrderParams {
+            market_index: 1,
+            price,
+            base_asset_amount: 1_000,
+            ..Default::default()
+        };
+
+        let authority = Pubkey::new_unique();
+        let delegator = Pubkey::new_unique();
+
+        assert!(duplicates
+            .check_and_record(authority, 0, &[order(100)], now)
+            .is_ok());
+        assert!(duplicates
+            .check_and_record(authority, 0, &[order(100)], now)
+            .is_err());
+        // different price, sub-account or authority
+        assert!(duplicates
+            .check_and_record(authority, 0, &[order(101)], now)
+            .is_ok());
+        assert!(duplicates
+            .check_and_record(authority, 1, &[order(100)], now)
+            .is_ok());
+        assert!(duplicates
+            .check_and_record(delegator, 0, &[order(100)], now)
+            .is_ok());
+        let post_only = OrderParams {
+            post_only: PostOnlyParam::MustPostOnly,
+            ..order(100)
+        };
+        assert!(duplicates
+            .check_and_record(authority, 0, &[post_only], now)
+            .is_ok());
+        // duplicates within the batch, none recorded
+        assert!(duplicates
+            .check_and_record(authority, 0, &[order(103), order(103)], now)
+            .is_err());
+        assert!(duplicates
+            .check_and_record(authority, 0, &[order(103)], now)
+            .is_ok());
+        // replacements are recorded unchecked
+        duplicates.record(authority, 0, &[order(102)], now);
+        duplicates.record(authority, 0, &[order(102)], now);
+        assert!(duplicates
+            .check_and_record(authority, 0, &[order(102)], now)
+            .is_err());
+        // outside the window
+        let later = now + Duration::from_millis(500);
+        let recorded = duplicates
+            .check_and_record(authority, 0, &[order(100)], later)
+            .unwrap();
+
+        duplicates.forget(&recorded);
+        assert!(duplicates
+            .check_and_record(authority, 0, &[order(100)], later)
+            .is_ok());
+    }
+
+    #[test]
+    fn duplicate_orders_checked_atomically() {
+        let duplicates = DuplicateOrders::new(Duration::from_secs(60));
+        let authority = Pubkey::new_unique();
+        let now = Instant::now();
+        // identical requests racing on two workers, exactly one is placed
+        for price in 1..=100 {
+            let order = OrderParams {
+                price,
+                base_asset_amount: 1_000,
+                ..Default::default()
+            };
+            let barrier = std::sync::Barrier::new(2);
+            let check = || {
+                barrier.wait();
+                duplicates
+                    .check_and_record(authority, 0, std::slice::from_ref(&order), now)
+                    .is_ok()
+            };
+            let placed = std::thread::scope(|s| {
+                let workers = [s.spawn(check), s.spawn(check)];
+                workers
+                    .into_iter()
+                    .map(|w| w.join().expect("worker"))
+                    .filter(|placed| *placed)
+                    .count()
+            });
+            assert_eq!(placed, 1, "price {price}");
+        }
+    }
+
+    #[test]
+    fn trading_window_is_open() {
+        // 2024-01-08 00:00 UTC, a Monday
+        const MONDAY: u64 = 1_704_672_000;
+        const HOUR: u64 = 60 * 60;
+
+        let window = TradingWindow::parse("mon-fri 08:00-17:00").unwrap();
+        assert!(!window.is_open(MONDAY + 7 * HOUR));
+        assert!(window.is_open(MONDAY + 8 * HOUR));
+        assert!(!window.is_open(MONDAY + 17 * HOUR));
+        assert!(!window.is_open(MONDAY + 5 * 24 * HOUR + 9 * HOUR)); // saturday
+
+        // 08:00 +08:00 is 00:00 UTC
+        let window = TradingWindow::parse("mon-fri 08:00-17:00 +08:00").unwrap();
+        assert!(window.is_open(MONDAY));
+        assert!(!window.is_open(MONDAY + 9 * HOUR));
+
+        // spans midnight, opens friday evening
+        let window = TradingWindow::parse("fri 22:00-02:00").unwrap();
+        assert!(window.is_open(MONDAY + 4 * 24 * HOUR + 23 * HOUR));
+        assert!(window.is_open(MONDAY + 5 * 24 * HOUR + HOUR));
+        assert!(!window.is_open(MONDAY + HOUR));
+
+        assert!(TradingWindow::parse("mon-fri").is_err());
+        assert!(TradingWindow::parse("mon-fri 08:00-17:00 ö08:00").is_err());
+        assert!(TradingWindow::parse("mon-fri 08:00-17:00 Europe/London").is_err());
+        assert!(TradingWindow::parse("mon-fri 08:00-17:00 +01:00 BST").is_err());
+        assert!(TradingWindow::parse("mon-fry 08:00-17:00").is_err());
+        assert!(TradingWindow::parse("daily 08:00-25:00").is_err());
+    }
+
+    #[test]
+    fn daily_loss_resets_each_day() {
+        const DAY: u64 = 24 * 60 * 60;
+        let mut daily_loss = DailyLoss::default();
+        assert_eq!(daily_loss.update(DAY, Decimal::from(1_000)), Decimal::ZERO);
+        assert_eq!(
+            daily_loss.update(DAY + 60, Decimal::from(900)),
+            Decimal::from(100)
+        );
+        assert_eq!(
+            daily_loss.update(DAY + 120, Decimal::from(1_100)),
+            Decimal::ZERO
+        );
+        assert!(daily_loss.is_new_day(2 * DAY));
+        assert_eq!(daily_loss.day(), 1);
+        assert_eq!(
+            daily_loss.update(2 * DAY, Decimal::from(800)),
+            Decimal::ZERO
+        );
+        assert_eq!(daily_loss.day(), 2);
+        assert_eq!(
+            daily_loss.update(2 * DAY + 60, Decimal::from(700)),
+            Decimal::from(100)
+        );
+    }
+
+    #[test]
+    fn daily_loss_round_trips() {
+        let mut daily_loss = DailyLoss::default();
+        daily_loss.update(24 * 60 * 60, Decimal::new(-1_005, 1));
+        let stored = serde_json::to_vec(&daily_loss).unwrap();
+        let mut restored: DailyLoss = serde_json::from_slice(&stored).unwrap();
+        assert_eq!(restored, daily_loss);
+        assert_eq!(
+            restored.update(24 * 60 * 60 + 60, Decimal::from(-200)),
+            Decimal::new(995, 1)
+        );
+    }
+
+    #[test]
+    fn parse_risk_groups() {
+        let lookup = |ticker: &str| match ticker {
+            "sol-perp" => Some(MarketId::perp(0)),
+            "sol" => Some(MarketId::spot(1)),
+            "jitosol" => Some(MarketId::spot(6)),
+            _ => None,
+        };
+        let groups = RiskGroup::parse_list("sol=sol-perp,sol,jitosol:50000", lookup).unwrap();
+        assert_eq!(
+            groups,
+            vec![RiskGroup {
+                name: "sol".into(),
+                markets: vec![MarketId::perp(0), MarketId::spot(1), MarketId::spot(6)],
+                max_notional: Decimal::from(50_000),
+            }]
+        );
+        assert!(RiskGroup::parse_list("sol=sol-perp,doge:1000", lookup).is_err());
+        assert!(RiskGroup::parse_list("sol=sol-perp", lookup).is_err());
+    }
+
+    #[test]
+    fn risk_group_check_exposure() {
+        let group = RiskGroup {
+            name: "sol".into(),
+            markets: vec![],
+            max_notional: Decimal::from(1_000),
+        };
+        assert!(group
+            .check_exposure(Decimal::from(500), Decimal::from(400))
+            .is_ok());
+        assert!(group
+            .check_exposure(Decimal::from(500), Decimal::from(600))
+            .is_err());
+        assert!(group
+            .check_exposure(Decimal::from(-500), Decimal::from(-600))
+            .is_err());
+        // already over the cap but reducing
+        assert!(group
+            .check_exposure(Decimal::from(1_500), Decimal::from(-200))
+            .is_ok());
+    }
+
+    #[test]
+    fn reduce_only_guard_clamps_and_rejects() {
+        let positions = [PerpPosition {
+            market_index: 1,
+            base_asset_amount: 2 * BASE_PRECISION as i64,
+            ..Default::default()
+        }];
+        let order = |direction, base_asset_amount| OrderParams {
+            market_index: 1,
+            market_type: MarketType::Perp,
+            direction,
+            base_asset_amount,
+            reduce_only: true,
+            ..Default::default()
+        };
+
+        // within position size
+        let mut orders = [order(PositionDirection::Short, BASE_PRECISION as u64)];
+        assert!(reduce_only_guard(&positions, &mut orders)
+            .unwrap()
+            .is_empty());
+
+        // would flip the position
+        let mut orders = [order(PositionDirection::Short, 3 * BASE_PRECISION as u64)];
+        let adjustments = reduce_only_guard(&positions, &mut orders).unwrap();
+        assert_eq!(orders[0].base_asset_amount, 2 * BASE_PRECISION as u64);
+        assert_eq!(adjustments[0].requested_amount, Decimal::from(3));
+        assert_eq!(adjustments[0].amount, Decimal::from(2));
+
+        // would increase the position
+        let mut orders = [order(PositionDirection::Long, BASE_PRECISION as u64)];
+        assert!(reduce_only_guard(&positions, &mut orders).is_err());
+
+        // reduced cumulatively across the batch
+        let mut orders = [
+            order(PositionDirection::Short, BASE_PRECISION as u64),
+            order(PositionDirection::Short, 3 * BASE_PRECISION as u64 / 2),
+        ];
+        let adjustments = reduce_only_guard(&positions, &mut orders).unwrap();
+        assert_eq!(orders[1].base_asset_amount, BASE_PRECISION as u64);
+        assert_eq!(adjustments[0].order_index, 1);
+        let mut orders = [
+            order(PositionDirection::Short, 2 * BASE_PRECISION as u64),
+            order(PositionDirection::Short, BASE_PRECISION as u64),
+        ];
+        assert!(reduce_only_guard(&positions, &mut orders).is_err());
+    }
+
+    #[test]
+    fn compliance_rules() {
+        let lookup = |ticker: &str| match ticker {
+            "sol-perp" => Some(MarketId::perp(0)),
+            "trump-win-2028-bet" => Some(MarketId::perp(36)),
+            _ => None,
+        };
+        let rules = ComplianceRules::parse(
+            r#"{"default":{"markets":["sol-perp","trump-win-2028-bet"],"longOnly":true,"maxTenorDays":30},"auth1":{}}"#,
+            lookup,
+        )
+        .unwrap();
+        let (name, rule) = rules.rule("auth2").unwrap();
+        assert_eq!(name, "default");
+        let long = Some(PositionDirection::Long);
+        let short = Some(PositionDirection::Short);
+        assert!(rule.check(MarketId::perp(0), long, false, None).is_ok());
+        assert!(rule.check(MarketId::spot(1), long, false, None).is_err());
+        assert!(rule.check(MarketId::perp(0), short, false, None).is_err());
+        assert!(rule.check(MarketId::perp(0), short, true, None).is_ok());
+        assert!(rule.check(MarketId::perp(0), None, false, None).is_ok());
+        assert!(rule
+            .check(MarketId::perp(36), long, false, Some(30 * 86_400))
+            .is_ok());
+        assert!(rule
+            .check(MarketId::perp(36), long, false, Some(31 * 86_400))
+            .is_err());
+
+        let (name, rule) = rules.rule("auth1").unwrap();
+        assert_eq!(name, "auth1");
+        assert!(rule.check(MarketId::spot(1), short, false, None).is_ok());
+
+        assert!(ComplianceRules::parse(r#"{"default":{"markets":["doge"]}}"#, lookup).is_err());
+        assert!(ComplianceRules::parse(r#"{"default":{"shortOnly":true}}"#, lookup).is_err());
+        assert!(ComplianceRules::default().rule("auth1").is_none());
+    }
+}
@@ -0,0 +1,240 @@
+//! Active/standby leader election
+//!
+//! Gateway instances sharing a lease file elect a single leader that is allowed to trade.
+//! Standby instances keep their subscriptions warm and take over once the leader's lease expires.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use fs2::FileExt;
+use log::{info, warn};
+
+use crate::LOG_TARGET;
+
+/// Tracks whether this gateway instance currently holds the trading lease
+pub struct Leadership {
+    is_leader: AtomicBool,
+    /// lease timeout, None when running without HA
+    lease_timeout: Option<Duration>,
+    /// start of the last successful lease renewal
+    renewed_at: Mutex<Option<Instant>>,
+}
+
+impl Leadership {
+    /// Leadership for a gateway running without HA, it is always the leader
+    pub fn standalone() -> Arc<Self> {
+        Arc::new(Self {
+            is_leader: AtomicBool::new(true),
+            lease_timeout: None,
+            renewed_at: Mutex::default(),
+        })
+    }
+
+    /// True if this instance may send transactions
+    ///
+    /// a leader steps down once its last renewal is older than the lease timeout, a standby
+    /// may have taken over the lease while renewals stalled
+    pub fn is_leader(&self) -> bool {
+        if !self.is_leader.load(Ordering::Relaxed) {
+            return false;
+        }
+        match self.lease_timeout {
+            Some(lease_timeout) => self
+                .renewed_at
+                .lock()
+                .expect("lock")
+                .is_some_and(|renewed_at| renewed_at.elapsed() < lease_timeout),
+            None => true,
+        }
+    }
+
+    /// Start contending for the lease at `path`
+    ///
+    /// The lease is renewed every `lease_timeout / 3`, a standby takes over
+    /// once the current holder has not renewed for `lease_timeout`.
+    /// `lease_timeout` must be non-zero
+    pub fn elect(path: PathBuf, lease_timeout: Duration) -> Arc<Self> {
+        let leadership = Arc::new(Self {
+            is_leader: AtomicBool::new(false),
+            lease_timeout: Some(lease_timeout),
+            renewed_at: Mutex::default(),
+        });
+        let instance_id = format!("{}-{}", std::process::id(), now_millis());
+        info!(target: LOG_TARGET, "HA instance id: {instance_id}, lease: {path:?}");
+
+        tokio::spawn({
+            let leadership = Arc::clone(&leadership);
+            async move {
+                let renew_interval = lease_timeout / 3;
+                loop {
+                    // the lease is written no earlier than this, so it expires no earlier either
+                    let attempted_at = Instant::now();
+                    let held = tokio::task::spawn_blocking({
+                        let path = path.clone();
+                        let instance_id = instance_id.clone();
+                        move || try_acquire(&path, &instance_id, lease_timeout)
+                    })
+                    .await
+                    .map_err(std::io::Error::other)
+                    .and_then(|res| res)
+                    .unwrap_or_else(|err| {
+                        warn!(target: LOG_TARGET, "HA lease error: {err:?}");
+                        false
+                    });
+                    if held {
+                        *leadership.renewed_at.lock().expect("lock") = Some(attempted_at);
+                    }
+                    let was_leader = leadership.is_leader.swap(held, Ordering::Relaxed);
+                    if held && !was_leader {
+                        info!(target: LOG_TARGET, "acquired leadership, trading enabled 👑");
+                    } else if !held && was_leader {
+                        warn!(target: LOG_TARGET, "lost leadership, entering standby");
+                    }
+                    tokio::time::sleep(renew_interval).await;
+                }
+            }
+        });
+
+        leadership
+    }
+}
+
+/// Try to acquire or renew the lease, returns true if this instance holds it
+///
+/// the lease file is read and written under an exclusive lock, so only one instance can take
+/// over an expired lease
+fn try_acquire(
+    path: &PathBuf,
+    instance_id: &str,
+    lease_timeout: Duration,
+) -> std::io::Result<bool> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)?;
+    file.lock_exclusive()?;
+    let res = renew_locked(&mut file, instance_id, lease_timeout);
+    let _ = FileExt::unlock(&file);
+    res
+}
+
+/// Renew the lease in the locked lease `file` unless another instance holds it
+fn renew_locked(
+    file: &mut File,
+    instance_id: &str,
+    lease_timeout: Duration,
+) -> std::io::Result<bool> {
+    let now = now_millis();
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)?;
+    if let Some((holder, ts)) = parse_lease(&contents) {
+        let expired = now.saturating_sub(ts) > lease_timeout.as_millis() as u64;
+        if holder != instance_id && !expired {
+            return Ok(false);
+        }
+    }
+
+    file.set_len(0)?;
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(format!("{instance_id} {now}").as_bytes())?;
+    file.sync_data()?;
+
+    Ok(true)
+}
+
+/// Parse lease file contents `<instance id> <unix ts millis>`
+fn parse_lease(contents: &str) -> Option<(&str, u64)> {
+    let (holder, ts) = contents.trim().split_once(' ')?;
+    Some((holder, ts.parse().ok()?))
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_lease_works() {
+        assert_eq!(
+            parse_lease("1234-1700000000000 1700000001000\n"),
+            Some(("1234-1700000000000", 1_700_000_001_000))
+        );
+        assert_eq!(parse_lease(""), None);
+        assert_eq!(parse_lease("1234-1700000000000"), None);
+        assert_eq!(parse_lease("1234 not-a-ts"), None);
+    }
+
+    /// Open a lease file named `name` holding `contents`
+    fn lease_file(name: &str, contents: &str) -> (PathBuf, File) {
+        let path = std::env::temp_dir().join(format!("gateway-ha-{}-{name}", std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .unwrap();
+        (path, file)
+    }
+
+    #[test]
+    fn renew_locked_refuses_live_lease() {
+        let lease = format!("other {}", now_millis());
+        let (path, mut file) = lease_file("live", &lease);
+
+        assert!(!renew_locked(&mut file, "me", Duration::from_secs(10)).unwrap());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), lease);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn renew_locked_takes_over_expired_lease() {
+        let expired = now_millis() - 20_000;
+        let (path, mut file) = lease_file("expired", &format!("other {expired}"));
+
+        assert!(renew_locked(&mut file, "me", Duration::from_secs(10)).unwrap());
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let (holder, ts) = parse_lease(&contents).unwrap();
+        assert_eq!(holder, "me");
+        assert!(ts > expired);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn renew_locked_renews_own_lease() {
+        let (path, mut file) = lease_file("own", &format!("me {}", now_millis()));
+
+        assert!(renew_locked(&mut file, "me", Duration::from_secs(10)).unwrap());
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn leader_steps_down_on_stale_renewal() {
+        let lease_timeout = Duration::from_secs(10);
+        let leadership = Leadership {
+            is_leader: AtomicBool::new(true),
+            lease_timeout: Some(lease_timeout),
+            renewed_at: Mutex::new(Some(Instant::now())),
+        };
+        assert!(leadership.is_leader());
+
+        *leadership.renewed_at.lock().unwrap() = Instant::now().checked_sub(lease_timeout);
+        assert!(!leadership.is_leader());
+        assert!(Leadership::standalone().is_leader());
+    }
+}
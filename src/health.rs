@@ -0,0 +1,105 @@
+//! Account health summary
+//!
+//! Condenses margin requirements, collateral and liquidation prices into a single score
+//! with per position risk.
+
+use rust_decimal::Decimal;
+
+use crate::types::PositionHealth;
+
+/// Health score in [0, 100], 0 at the point of liquidation
+///
+/// * `total_collateral` - total maintenance collateral
+/// * `maintenance_requirement` - maintenance margin requirement
+pub fn health_score(total_collateral: Decimal, maintenance_requirement: Decimal) -> Decimal {
+    if maintenance_requirement.is_zero() {
+        return Decimal::ONE_HUNDRED;
+    }
+    if total_collateral <= Decimal::ZERO {
+        return Decimal::ZERO;
+    }
+    let usage = maintenance_requirement / total_collateral;
+    ((Decimal::ONE - usage).max(Decimal::ZERO) * Decimal::ONE_HUNDRED).round_dp(2)
+}
+
+/// Relative price move until liquidation, None if the position has no liquidation price
+pub fn liquidation_distance(oracle_price: Decimal, liquidation_price: Decimal) -> Option<Decimal> {
+    if liquidation_price <= Decimal::ZERO || oracle_price.is_zero() {
+        return None;
+    }
+    Some(
+        ((oracle_price - liquidation_price) / oracle_price)
+            .abs()
+            .round_dp(4),
+    )
+}
+
+/// Return the position contributing the most to the maintenance requirement
+pub fn largest_risk(positions: &[PositionHealth]) -> Option<&PositionHealth> {
+    positions
+        .iter()
+        .filter(|p| !p.maintenance_requirement.is_zero())
+        .max_by_key(|p| p.maintenance_requirement)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Market;
+
+    #[test]
+    fn health_score_works() {
+        assert_eq!(
+            health_score(Decimal::from(100), Decimal::ZERO),
+            Decimal::ONE_HUNDRED
+        );
+        assert_eq!(
+            health_score(Decimal::from(100), Decimal::from(25)),
+            Decimal::from(75)
+        );
+        assert_eq!(
+            health_score(Decimal::from(100), Decimal::from(150)),
+            Decimal::ZERO
+        );
+        assert_eq!(health_score(Decimal::ZERO, Decimal::ONE), Decimal::ZERO);
+    }
+
+    #[test]
+    fn liquidation_distance_works() {
+        assert_eq!(
+            liquidation_distance(Decimal::from(100), Decimal::from(80)),
+            Some(Decimal::new(2, 1))
+        );
+        // short
+        assert_eq!(
+            liquidation_distance(Decimal::from(100), Decimal::from(150)),
+            Some(Decimal::new(5, 1))
+        );
+        assert_eq!(
+            liquidation_distance(Decimal::from(100), Decimal::from(-1)),
+            None
+        );
+    }
+
+    #[test]
+    fn largest_risk_works() {
+        let position = |market_index, maintenance_requirement| PositionHealth {
+            market: Market::perp(market_index),
+            amount: Decimal::ONE,
+            oracle_price: Decimal::ONE,
+            liquidation_price: None,
+            liquidation_distance: None,
+            maintenance_requirement,
+        };
+        let positions = vec![
+            position(0, Decimal::from(5)),
+            position(1, Decimal::from(20)),
+            position(2, Decimal::ZERO),
+        ];
+        assert_eq!(
+            largest_risk(&positions).map(|p| p.market.market_index),
+            Some(1)
+        );
+        assert!(largest_risk(&positions[2..]).is_none());
+    }
+}
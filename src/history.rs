@@ -0,0 +1,323 @@
+//! History of account equity snapshots and oracle prices
+//!
+//! Sub-accounts are sampled at a fixed interval and retained in a bounded buffer,
+//! queries downsample the buffer to the requested resolution. With a store, snapshots are also
+//! persisted so the history survives restarts.
+//!
+//! Oracle prices of subscribed markets are sampled the same way, to mark out fills against.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::RwLock,
+};
+
+use log::warn;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::LOG_TARGET;
+
+/// Max. snapshots retained per sub-account (7 days @ 1m)
+const HISTORY_CAPACITY: usize = 7 * 24 * 60;
+
+/// Max. oracle samples retained per market (7 days @ 5s)
+const ORACLE_HISTORY_CAPACITY: usize = 7 * 24 * 60 * 12;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct EquitySnapshot {
+    /// unix timestamp (seconds)
+    pub ts: u64,
+    /// total maintenance collateral
+    pub equity: Decimal,
+    pub free_collateral: Decimal,
+    pub leverage: Decimal,
+}
+
+/// Sampling resolution of an equity history query
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum Resolution {
+    #[default]
+    #[serde(rename = "1m")]
+    OneMinute,
+    #[serde(rename = "5m")]
+    FiveMinutes,
+    #[serde(rename = "15m")]
+    FifteenMinutes,
+    #[serde(rename = "1h")]
+    OneHour,
+    #[serde(rename = "4h")]
+    FourHours,
+    #[serde(rename = "1d")]
+    OneDay,
+}
+
+impl Resolution {
+    /// Bucket size in seconds
+    pub fn seconds(self) -> u64 {
+        match self {
+            Self::OneMinute => 60,
+            Self::FiveMinutes => 5 * 60,
+            Self::FifteenMinutes => 15 * 60,
+            Self::OneHour => 60 * 60,
+            Self::FourHours => 4 * 60 * 60,
+            Self::OneDay => 24 * 60 * 60,
+        }
+    }
+}
+
+/// Bounded equity snapshots per sub-account
+#[derive(Default)]
+pub struct EquityHistory {
+    snapshots: RwLock<HashMap<u16, VecDeque<EquitySnapshot>>>,
+    store: Option<sled::Tree>,
+}
+
+/// Store key of a snapshot, ordered by sub-account then time
+fn store_key(sub_account_id: u16, ts: u64) -> [u8; 10] {
+    let mut key = [0; 10];
+    key[..2].copy_from_slice(&sub_account_id.to_be_bytes());
+    key[2..].copy_from_slice(&ts.to_be_bytes());
+    key
+}
+
+impl EquityHistory {
+    /// Persist snapshots to `store`, continuing from those already stored
+    pub fn with_store(store: sled::Tree) -> Self {
+        let mut snapshots = HashMap::<u16, VecDeque<EquitySnapshot>>::new();
+        for (key, value) in store.iter().filter_map(Result::ok) {
+            let Some(sub_account_id) = key.get(..2).and_then(|id| id.try_into().ok()) else {
+                continue;
+            };
+            if let Ok(snapshot) = serde_json::from_slice(&value) {
+                snapshots
+                    .entry(u16::from_be_bytes(sub_account_id))
+                    .or_default()
+                    .push_back(snapshot);
+            }
+        }
+        Self {
+            snapshots: RwLock::new(snapshots),
+            store: Some(store),
+        }
+    }
+
+    pub fn record(&self, sub_account_id: u16, snapshot: EquitySnapshot) {
+        let mut snapshots = self.snapshots.write().expect("lock");
+        let history = snapshots.entry(sub_account_id).or_default();
+        let evicted = if history.len() >= HISTORY_CAPACITY {
+            history.pop_front()
+        } else {
+            None
+        };
+        if let Some(ref store) = self.store {
+            let res = serde_json::to_vec(&snapshot)
+                .map_err(|err| err.to_string())
+                .and_then(|value| {
+                    store
+                        .insert(store_key(sub_account_id, snapshot.ts), value)
+                        .map_err(|err| err.to_string())
+                })
+                .and_then(|_| match evicted {
+                    Some(evicted) => store
+                        .remove(store_key(sub_account_id, evicted.ts))
+                        .map(|_| ())
+                        .map_err(|err| err.to_string()),
+                    None => Ok(()),
+                });
+            if let Err(err) = res {
+                warn!(target: LOG_TARGET, "couldn't store equity snapshot: {err}");
+            }
+        }
+        history.push_back(snapshot);
+    }
+
+    /// Return snapshots of `sub_account_id`, keeping the last snapshot of each `resolution` bucket
+    pub fn query(&self, sub_account_id: u16, resolution: Resolution) -> Vec<EquitySnapshot> {
+        let snapshots = self.snapshots.read().expect("lock");
+        let Some(history) = snapshots.get(&sub_account_id) else {
+            return vec![];
+        };
+        let bucket = resolution.seconds();
+        let mut result = Vec::<EquitySnapshot>::with_capacity(history.len());
+        for snapshot in history {
+            match result.last_mut() {
+                Some(last) if last.ts / bucket == snapshot.ts / bucket => {
+                    *last = snapshot.clone();
+                }
+                _ => result.push(snapshot.clone()),
+            }
+        }
+        result
+    }
+}
+
+/// Oracle price of a market at a point in time
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct OracleSample {
+    /// unix timestamp (seconds)
+    pub ts: u64,
+    pub price: Decimal,
+}
+
+/// Bounded oracle price samples per market, keyed by market index and true if perp
+#[derive(Default)]
+pub struct OracleHistory {
+    samples: RwLock<HashMap<(u16, bool), VecDeque<OracleSample>>>,
+    store: Option<sled::Tree>,
+}
+
+/// Store key of an oracle sample, ordered by market then time
+fn oracle_store_key((market_index, perp): (u16, bool), ts: u64) -> [u8; 11] {
+    let mut key = [0; 11];
+    key[..2].copy_from_slice(&market_index.to_be_bytes());
+    key[2] = perp as u8;
+    key[3..].copy_from_slice(&ts.to_be_bytes());
+    key
+}
+
+impl OracleHistory {
+    /// Persist samples to `store`, continuing from those already stored
+    pub fn with_store(store: sled::Tree) -> Self {
+        let mut samples = HashMap::<(u16, bool), VecDeque<OracleSample>>::new();
+        for (key, value) in store.iter().filter_map(Result::ok) {
+            let Some(market_index) = key.get(..2).and_then(|idx| idx.try_into().ok()) else {
+                continue;
+            };
+            if let Ok(sample) = serde_json::from_slice(&value) {
+                samples
+                    .entry((u16::from_be_bytes(market_index), key[2] == 1))
+                    .or_default()
+                    .push_back(sample);
+            }
+        }
+        Self {
+            samples: RwLock::new(samples),
+            store: Some(store),
+        }
+    }
+
+    pub fn record(&self, market: (u16, bool), sample: OracleSample) {
+        let mut samples = self.samples.write().expect("lock");
+        let history = samples.entry(market).or_default();
+        let evicted = if history.len() >= ORACLE_HISTORY_CAPACITY {
+            history.pop_front()
+        } else {
+            None
+        };
+        if let Some(ref store) = self.store {
+            let res = serde_json::to_vec(&sample)
+                .map_err(|err| err.to_string())
+                .and_then(|value| {
+                    store
+                        .insert(oracle_store_key(market, sample.ts), value)
+                        .map_err(|err| err.to_string())
+                })
+                .and_then(|_| match evicted {
+                    Some(evicted) => store
+                        .remove(oracle_store_key(market, evicted.ts))
+                        .map(|_| ())
+                        .map_err(|err| err.to_string()),
+                    None => Ok(()),
+                });
+            if let Err(err) = res {
+                warn!(target: LOG_TARGET, "couldn't store oracle sample: {err}");
+            }
+        }
+        history.push_back(sample);
+    }
+
+    /// Return samples of `market` between unix timestamps `from` and `to` (inclusive), oldest first
+    pub fn query(&self, market: (u16, bool), from: u64, to: u64) -> Vec<OracleSample> {
+        let samples = self.samples.read().expect("lock");
+        let Some(history) = samples.get(&market) else {
+            return vec![];
+        };
+        let start = history.partition_point(|s| s.ts < from);
+        history
+            .range(start..)
+            .take_while(|s| s.ts <= to)
+            .copied()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(ts: u64) -> EquitySnapshot {
+        EquitySnapshot {
+            ts,
+            equity: Decimal::from(ts),
+            free_collateral: Decimal::ZERO,
+            leverage: Decimal::ZERO,
+        }
+    }
+
+    #[test]
+    fn equity_history_downsamples() {
+        let history = EquityHistory::default();
+        for ts in (0..600).step_by(60) {
+            history.record(0, snapshot(ts));
+        }
+
+        assert_eq!(history.query(0, Resolution::OneMinute).len(), 10);
+        let five_minutes = history.query(0, Resolution::FiveMinutes);
+        assert_eq!(
+            five_minutes.iter().map(|s| s.ts).collect::<Vec<_>>(),
+            vec![240, 540]
+        );
+        assert!(history.query(1, Resolution::OneMinute).is_empty());
+    }
+
+    #[test]
+    fn equity_history_persists() {
+        let tree = sled::Config::new()
+            .temporary(true)
+            .open()
+            .unwrap()
+            .open_tree("equity_history")
+            .unwrap();
+        let history = EquityHistory::with_store(tree.clone());
+        history.record(0, snapshot(60));
+        history.record(1, snapshot(60));
+        history.record(0, snapshot(120));
+
+        let restored = EquityHistory::with_store(tree);
+        assert_eq!(
+            restored.query(0, Resolution::OneMinute),
+            vec![snapshot(60), snapshot(120)]
+        );
+        assert_eq!(restored.query(1, Resolution::OneMinute), vec![snapshot(60)]);
+    }
+
+    #[test]
+    fn oracle_history_persists() {
+        let tree = sled::Config::new()
+            .temporary(true)
+            .open()
+            .unwrap()
+            .open_tree("oracle_history")
+            .unwrap();
+        let history = OracleHistory::with_store(tree.clone());
+        let sample = |ts: u64| OracleSample {
+            ts,
+            price: Decimal::from(ts),
+        };
+        for ts in (0..60).step_by(5) {
+            history.record((0, true), sample(ts));
+        }
+        history.record((0, false), sample(10));
+
+        let restored = OracleHistory::with_store(tree);
+        assert_eq!(
+            restored.query((0, true), 12, 20),
+            vec![sample(15), sample(20)]
+        );
+        assert_eq!(restored.query((0, false), 0, 60), vec![sample(10)]);
+        assert!(restored.query((1, true), 0, 60).is_empty());
+    }
+}
@@ -0,0 +1,97 @@
+//! Socialized loss tracking
+//!
+//! The program only keeps a cumulative social loss per perp market,
+//! increases observed while the gateway runs are recorded as loss events.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::RwLock,
+};
+
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+/// Max. socialized loss events retained per market
+const MAX_EVENTS: usize = 100;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct SocializedLoss {
+    /// unix timestamp (seconds) the loss was observed
+    pub ts: u64,
+    /// quote amount socialized
+    pub amount: Decimal,
+}
+
+/// Socialized loss events by perp market index
+#[derive(Default)]
+pub struct SocialLosses {
+    /// market index => (last observed total social loss, recent events)
+    markets: RwLock<HashMap<u16, (Decimal, VecDeque<SocializedLoss>)>>,
+}
+
+impl SocialLosses {
+    /// Observe a market's cumulative social loss, recording any increase since the last observation
+    pub fn observe(&self, market_index: u16, total_social_loss: Decimal, ts: u64) {
+        let mut markets = self.markets.write().expect("lock");
+        match markets.get_mut(&market_index) {
+            Some((last_total, events)) => {
+                if total_social_loss > *last_total {
+                    if events.len() == MAX_EVENTS {
+                        events.pop_front();
+                    }
+                    events.push_back(SocializedLoss {
+                        ts,
+                        amount: total_social_loss - *last_total,
+                    });
+                }
+                *last_total = total_social_loss;
+            }
+            None => {
+                markets.insert(market_index, (total_social_loss, VecDeque::new()));
+            }
+        }
+    }
+
+    /// Return recent socialized loss events of the market, newest first
+    pub fn recent(&self, market_index: u16) -> Vec<SocializedLoss> {
+        self.markets
+            .read()
+            .expect("lock")
+            .get(&market_index)
+            .map(|(_, events)| events.iter().rev().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn observe_records_increases() {
+        let losses = SocialLosses::default();
+        // first observation is the baseline
+        losses.observe(0, Decimal::from(100), 1);
+        assert!(losses.recent(0).is_empty());
+
+        losses.observe(0, Decimal::from(100), 2);
+        losses.observe(0, Decimal::from(150), 3);
+        losses.observe(0, Decimal::from(175), 4);
+        assert_eq!(
+            losses.recent(0),
+            vec![
+                SocializedLoss {
+                    ts: 4,
+                    amount: Decimal::from(25)
+                },
+                SocializedLoss {
+                    ts: 3,
+                    amount: Decimal::from(50)
+                },
+            ]
+        );
+        assert!(losses.recent(1).is_empty());
+    }
+}
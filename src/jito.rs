@@ -0,0 +1,218 @@
+//! Tx submission as Jito bundles
+//!
+//! The order tx carries a tip ix to a Jito tip account and is sent as a bundle to a Jito block
+//! engine, the tip is only paid if the tx lands. Bundles that fail or are dropped are resent until
+//! the tx TTL, like txs sent via RPC.
+
+use std::{
+    str::FromStr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use drift_rs::{types::VersionedMessage, Pubkey};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use solana_sdk::{instruction::Instruction, system_instruction, transaction::VersionedTransaction};
+
+use crate::message::insert_ix;
+
+/// Default tip per bundle in lamports
+pub const DEFAULT_JITO_TIP: u64 = 10_000;
+
+/// Jito tip accounts, tips are spread across them to reduce write lock contention
+const TIP_ACCOUNTS: [&str; 8] = [
+    "96gYZGLnJYVFmbjzopPSU6QiEV5fGqZNyN9nmNhvrZU5",
+    "HFqU5x63VTqvQss8hp11i4wVV8bD44PvwucfZ2bU7gRe",
+    "Cw8CFyM9FkoMi7K7Crf6HNQqf4uEMzpKw6QNghXLvLkY",
+    "ADaUMid9yfUytqMBgopwjb2DTLSokTSzL1zt6iGPaS49",
+    "DfXygSm4jCyNCybVYYK6DwvWqjKee8pbDmJGcLWNDXjh",
+    "ADuUkR4vqLUMWXxW9gh6D6L8pMSawimctcNZ5pGwDcEt",
+    "DttWaMuVvTiduZRnguLF7jNxTgiMBZ1hyAumKUiL2KRL",
+    "3AVi9Tg9Uo68tJfuvoKvqKNWKkC5wPdSSdeBnizKZ6jT",
+];
+
+/// Status of an in-flight bundle
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum BundleStatus {
+    /// not found, or dropped by the block engine
+    Invalid,
+    Pending,
+    /// all regions failed to land the bundle
+    Failed,
+    Landed,
+}
+
+#[derive(Deserialize)]
+struct RpcResponse<T> {
+    result: Option<T>,
+    error: Option<Value>,
+}
+
+#[derive(Deserialize)]
+struct InflightStatuses {
+    value: Vec<InflightStatus>,
+}
+
+#[derive(Deserialize)]
+struct InflightStatus {
+    status: BundleStatus,
+}
+
+/// Jito block engine client
+pub struct Jito {
+    http: reqwest::Client,
+    /// e.g. `https://mainnet.block-engine.jito.wtf`
+    url: String,
+    /// tip per bundle in lamports
+    tip: u64,
+    next_tip_account: AtomicUsize,
+}
+
+impl Jito {
+    pub fn new(url: &str, tip: u64) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            url: url.trim_end_matches('/').to_string(),
+            tip,
+            next_tip_account: AtomicUsize::default(),
+        }
+    }
+
+    /// Return the tip per bundle in lamports
+    pub fn tip(&self) -> u64 {
+        self.tip
+    }
+
+    /// Return an ix transferring the tip from `payer` to a Jito tip account
+    pub fn tip_ix(&self, payer: &Pubkey) -> Instruction {
+        let idx = self.next_tip_account.fetch_add(1, Ordering::Relaxed) % TIP_ACCOUNTS.len();
+        let tip_account = Pubkey::from_str(TIP_ACCOUNTS[idx]).expect("valid pubkey");
+        system_instruction::transfer(payer, &tip_account, self.tip)
+    }
+
+    /// Append the tip ix paid by `payer` to `message`, as its last ix
+    pub fn add_tip(&self, message: &mut VersionedMessage, payer: &Pubkey) {
+        let ix = self.tip_ix(payer);
+        insert_ix(message, message.instructions().len(), &ix);
+    }
+
+    /// Send signed `txs` as a bundle, returns the bundle id
+    pub async fn send_bundle(&self, txs: &[VersionedTransaction]) -> Result<String, String> {
+        let txs = txs
+            .iter()
+            .map(|tx| {
+                bincode::serialize(tx)
+                    .map(|tx| BASE64.encode(tx))
+                    .map_err(|err| err.to_string())
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        self.call(
+            "/api/v1/bundles",
+            "sendBundle",
+            json!([txs, { "encoding": "base64" }]),
+        )
+        .await
+    }
+
+    /// Return the status of a bundle sent within the last 5 mins
+    pub async fn bundle_status(&self, bundle_id: &str) -> Result<BundleStatus, String> {
+        let statuses: InflightStatuses = self
+            .call(
+                "/api/v1/getInflightBundleStatuses",
+                "getInflightBundleStatuses",
+                json!([[bundle_id]]),
+            )
+            .await?;
+        Ok(statuses
+            .value
+            .first()
+            .map_or(BundleStatus::Invalid, |s| s.status))
+    }
+
+    async fn call<T: for<'de> Deserialize<'de>>(
+        &self,
+        path: &str,
+        method: &str,
+        params: Value,
+    ) -> Result<T, String> {
+        let res = self
+            .http
+            .post(format!("{}{path}", self.url))
+            .json(&json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": method,
+                "params": params,
+            }))
+            .send()
+            .await
+            .map_err(|err| err.to_string())?;
+        let body = res.text().await.map_err(|err| err.to_string())?;
+        parse_response(&body)
+    }
+}
+
+fn parse_response<T: for<'de> Deserialize<'de>>(body: &str) -> Result<T, String> {
+    let res: RpcResponse<T> = serde_json::from_str(body).map_err(|err| err.to_string())?;
+    match (res.result, res.error) {
+        (Some(result), None) => Ok(result),
+        (_, Some(err)) => Err(err.to_string()),
+        (None, None) => Err(format!("empty response: {body}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_response_works() {
+        let id: String =
+            parse_response(r#"{"jsonrpc":"2.0","result":"2id3YC2jK9G5Wo2","id":1}"#).unwrap();
+        assert_eq!(id, "2id3YC2jK9G5Wo2");
+
+        let statuses: InflightStatuses = parse_response(
+            r#"{"jsonrpc":"2.0","result":{"context":{"slot":280999028},"value":[{"bundle_id":"b1","status":"Landed","landed_slot":280999027}]},"id":1}"#,
+        )
+        .unwrap();
+        assert_eq!(statuses.value[0].status, BundleStatus::Landed);
+
+        let err = parse_response::<String>(
+            r#"{"jsonrpc":"2.0","error":{"code":-32602,"message":"bundle contains an already processed transaction"},"id":1}"#,
+        )
+        .unwrap_err();
+        assert!(err.contains("already processed"));
+    }
+
+    #[test]
+    fn tip_accounts_rotate() {
+        let jito = Jito::new("http://localhost", 1_000);
+        let payer = Pubkey::new_unique();
+        let first = jito.tip_ix(&payer);
+        let second = jito.tip_ix(&payer);
+        assert_ne!(first.accounts[1].pubkey, second.accounts[1].pubkey);
+        assert_eq!(first.accounts[0].pubkey, payer);
+    }
+
+    #[test]
+    fn tip_is_last_ix() {
+        let jito = Jito::new("http://localhost", 1_000);
+        let payer = Pubkey::new_unique();
+        let order_ix = Instruction::new_with_bytes(Pubkey::new_unique(), &[1], vec![]);
+        let mut message =
+            VersionedMessage::Legacy(solana_sdk::message::Message::new(&[order_ix], Some(&payer)));
+        jito.add_tip(&mut message, &payer);
+
+        let keys = message.static_account_keys();
+        let tip = message.instructions().last().unwrap();
+        assert_eq!(message.instructions().len(), 2);
+        assert_eq!(
+            keys[tip.program_id_index as usize],
+            solana_sdk::system_program::id()
+        );
+        assert_eq!(keys[tip.accounts[0] as usize], payer);
+        assert!(TIP_ACCOUNTS.contains(&keys[tip.accounts[1] as usize].to_string().as_str()));
+        assert_eq!(keys[0], payer);
+    }
+}
@@ -0,0 +1,70 @@
+//! On-chain account layout validation
+//!
+//! Market accounts are decoded zero-copy and must be exactly the size of this build's structs,
+//! a program upgrade changing a layout (e.g. on devnet ahead of mainnet) would otherwise panic the
+//! decoding of its accounts. Markets are checked on startup, incompatible markets are skipped
+//! from subscriptions and requests reading their accounts refused while the rest keep trading.
+
+use std::{collections::HashSet, mem::size_of, sync::RwLock};
+
+use drift_rs::types::{
+    accounts::{PerpMarket, SpotMarket},
+    MarketId, MarketType,
+};
+
+/// Anchor account discriminator
+const DISCRIMINATOR_SIZE: usize = 8;
+
+/// Return the expected size of market accounts of `kind`
+pub fn market_account_size(kind: MarketType) -> usize {
+    DISCRIMINATOR_SIZE
+        + match kind {
+            MarketType::Perp => size_of::<PerpMarket>(),
+            MarketType::Spot => size_of::<SpotMarket>(),
+        }
+}
+
+/// Check the data of a market account of `kind` decodes with this build's layout
+pub fn check_market_layout(kind: MarketType, data: &[u8]) -> Result<(), String> {
+    let expected = market_account_size(kind);
+    if data.len() != expected {
+        return Err(format!(
+            "account size {} != expected {expected}",
+            data.len()
+        ));
+    }
+    Ok(())
+}
+
+/// Markets with accounts incompatible with this build
+#[derive(Default)]
+pub struct IncompatibleMarkets(RwLock<HashSet<MarketId>>);
+
+impl IncompatibleMarkets {
+    pub fn insert(&self, market: MarketId) {
+        self.0.write().expect("lock").insert(market);
+    }
+
+    pub fn contains(&self, market: &MarketId) -> bool {
+        self.0.read().expect("lock").contains(market)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn market_layouts() {
+        let perp = vec![0_u8; market_account_size(MarketType::Perp)];
+        assert!(check_market_layout(MarketType::Perp, &perp).is_ok());
+        assert!(check_market_layout(MarketType::Perp, &perp[..perp.len() - 8]).is_err());
+        assert!(check_market_layout(MarketType::Spot, &perp).is_err());
+        assert!(check_market_layout(MarketType::Spot, &[]).is_err());
+
+        let incompatible = IncompatibleMarkets::default();
+        incompatible.insert(MarketId::perp(1));
+        assert!(incompatible.contains(&MarketId::perp(1)));
+        assert!(!incompatible.contains(&MarketId::spot(1)));
+    }
+}
@@ -0,0 +1,177 @@
+//! Request body size and JSON depth limits
+//!
+//! Bodies are buffered up to the size limit before reaching handlers, larger bodies are refused
+//! with 413 and bodies nesting JSON arrays or objects deeper than the depth limit with 400.
+//! Webhooks deliver batches of txs so they're allowed larger bodies.
+
+use actix_web::{
+    body::MessageBody,
+    dev::{Payload, ServiceRequest, ServiceResponse},
+    error::InternalError,
+    http::header::CONTENT_LENGTH,
+    middleware::Next,
+    web::BytesMut,
+    Error, HttpResponse,
+};
+use futures_util::StreamExt;
+use serde_json::json;
+
+use crate::request_id::RequestId;
+
+/// Default max. request body size in KB
+pub const DEFAULT_MAX_BODY_KB: usize = 256;
+/// Default max. nesting depth of JSON request bodies
+pub const DEFAULT_MAX_JSON_DEPTH: usize = 32;
+/// Min. body size of webhook requests in KB, Helius batches txs into one request
+pub const MIN_WEBHOOK_BODY_KB: usize = 8 * 1024;
+/// Path prefix of webhook requests
+const WEBHOOKS_PATH: &str = "/v2/webhooks/";
+
+#[derive(Clone, Copy, Debug)]
+pub struct RequestLimits {
+    /// max. body size in bytes
+    pub max_body_size: usize,
+    /// max. body size of webhook requests in bytes
+    pub max_webhook_body_size: usize,
+    /// max. nesting depth of JSON arrays and objects
+    pub max_json_depth: usize,
+}
+
+impl RequestLimits {
+    pub fn new(max_body_size: usize, max_json_depth: usize) -> Self {
+        Self {
+            max_body_size,
+            max_webhook_body_size: max_body_size.max(MIN_WEBHOOK_BODY_KB * 1024),
+            max_json_depth,
+        }
+    }
+
+    /// Return the max. body size of requests to `path`
+    pub fn max_body_size_of(&self, path: &str) -> usize {
+        if path.starts_with(WEBHOOKS_PATH) {
+            self.max_webhook_body_size
+        } else {
+            self.max_body_size
+        }
+    }
+}
+
+/// Refuse requests exceeding `limits`, otherwise pass on their buffered body
+pub async fn limit_request(
+    limits: RequestLimits,
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let max_body_size = limits.max_body_size_of(req.path());
+    let content_length = req
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok());
+    if content_length.is_some_and(|len| len > max_body_size) {
+        return Err(too_large(max_body_size));
+    }
+
+    // chunked bodies have no length upfront
+    let mut body = BytesMut::new();
+    let mut payload = req.take_payload();
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk?;
+        if body.len() + chunk.len() > max_body_size {
+            return Err(too_large(max_body_size));
+        }
+        body.extend_from_slice(&chunk);
+    }
+
+    let depth = json_depth(&body);
+    if depth > limits.max_json_depth {
+        return Err(InternalError::from_response(
+            "too deep",
+            HttpResponse::BadRequest().json(json!({
+                "code": 400,
+                "reason": format!(
+                    "JSON nested too deep: {depth}, max. {}",
+                    limits.max_json_depth
+                ),
+                "requestId": RequestId::current(),
+            })),
+        )
+        .into());
+    }
+
+    req.set_payload(Payload::from(body.freeze()));
+    next.call(req).await
+}
+
+fn too_large(max_body_size: usize) -> Error {
+    InternalError::from_response(
+        "too large",
+        HttpResponse::PayloadTooLarge().json(json!({
+            "code": 413,
+            "reason": format!("request body too large, max. {max_body_size} bytes"),
+            "requestId": RequestId::current(),
+        })),
+    )
+    .into()
+}
+
+/// Return the max. nesting depth of arrays and objects in JSON `body`
+///
+/// brackets within strings are ignored, malformed JSON is left for handlers to refuse
+pub fn json_depth(body: &[u8]) -> usize {
+    let mut depth = 0_usize;
+    let mut max_depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for b in body {
+        if in_string {
+            match b {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => (),
+            }
+            continue;
+        }
+        match b {
+            b'"' => in_string = true,
+            b'[' | b'{' => {
+                depth += 1;
+                max_depth = max_depth.max(depth);
+            }
+            b']' | b'}' => depth = depth.saturating_sub(1),
+            _ => (),
+        }
+    }
+    max_depth
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_depth_ignores_strings() {
+        assert_eq!(json_depth(b""), 0);
+        assert_eq!(json_depth(b"1"), 0);
+        assert_eq!(json_depth(br#"{"orders":[{"amount":1}]}"#), 3);
+        assert_eq!(json_depth(br#"{"a":"[[[{{","b":"\"[["}"#), 1);
+        assert_eq!(json_depth(&[b'['; 1000]), 1000);
+    }
+
+    #[test]
+    fn webhooks_allowed_larger_bodies() {
+        let limits = RequestLimits::new(256 * 1024, 32);
+        assert_eq!(limits.max_body_size_of("/v2/orders"), 256 * 1024);
+        assert_eq!(
+            limits.max_body_size_of("/v2/webhooks/helius"),
+            MIN_WEBHOOK_BODY_KB * 1024
+        );
+        // never below the limit of other requests
+        let limits = RequestLimits::new(16 * 1024 * 1024, 32);
+        assert_eq!(
+            limits.max_body_size_of("/v2/webhooks/helius"),
+            16 * 1024 * 1024
+        );
+    }
+}
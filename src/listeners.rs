@@ -0,0 +1,264 @@
+//! REST listeners with capability sets
+//!
+//! The API may be bound on several addresses, each limited to some capabilities e.g. full access
+//! on localhost and read-only on the LAN. Requests a listener lacks the capability for are refused.
+//!
+//! Checks wrap the scopes requests are routed to, so they apply to percent-encoded paths as routed.
+
+use std::{
+    collections::HashMap,
+    net::{SocketAddr, ToSocketAddrs},
+    sync::Arc,
+};
+
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    error::InternalError,
+    http::Method,
+    middleware::Next,
+    Error, HttpResponse,
+};
+use serde_json::json;
+
+use crate::request_id::RequestId;
+
+/// Requests allowed on a listener
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Capabilities {
+    /// queries e.g. positions, orders, market data
+    pub read: bool,
+    /// placing, modifying, and cancelling orders, transfers, and other state changes
+    pub trade: bool,
+    /// `/v2/admin` endpoints
+    pub admin: bool,
+}
+
+impl Capabilities {
+    pub const FULL: Self = Self {
+        read: true,
+        trade: true,
+        admin: true,
+    };
+
+    /// Parse capabilities joined by `+` e.g. `read+trade`, or `full`
+    pub fn parse(capabilities: &str) -> Result<Self, String> {
+        let mut parsed = Self::default();
+        for capability in capabilities.split('+') {
+            match capability.trim() {
+                "read" => parsed.read = true,
+                "trade" => parsed.trade = true,
+                "admin" => parsed.admin = true,
+                "full" => parsed = Self::FULL,
+                other => return Err(format!("unknown capability: {other}")),
+            }
+        }
+        Ok(parsed)
+    }
+
+    /// Return the capability missing for a request to `scope`, if any
+    ///
+    /// * `path` - percent-decoded path, as routed
+    pub fn missing(&self, scope: Scope, method: &Method, path: &str) -> Option<&'static str> {
+        if scope == Scope::Admin {
+            return (!self.admin).then_some("admin");
+        }
+        // simulated orders change nothing
+        if matches!(*method, Method::GET | Method::HEAD) || path == "/v2/orders/whatIf" {
+            return (!self.read).then_some("read");
+        }
+        (!self.trade).then_some("trade")
+    }
+}
+
+/// API scopes a capability check wraps, probes are served on any listener
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scope {
+    /// `/v2` endpoints other than admin
+    Api,
+    /// `/v2/admin` endpoints
+    Admin,
+}
+
+/// Refuse requests to `scope` the listener they arrived on lacks the capability for
+pub async fn require_capabilities(
+    listeners: Arc<Listeners>,
+    scope: Scope,
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let missing = listeners
+        .capabilities(&req.app_config().local_addr())
+        .missing(scope, req.method(), req.match_info().as_str());
+    if let Some(missing) = missing {
+        return Err(InternalError::from_response(
+            "forbidden",
+            HttpResponse::Forbidden().json(json!({
+                "code": 403,
+                "reason": format!("listener lacks capability: {missing}"),
+                "requestId": RequestId::current(),
+            })),
+        )
+        .into());
+    }
+    next.call(req).await
+}
+
+/// Capabilities by bound address
+#[derive(Debug, Clone, Default)]
+pub struct Listeners(HashMap<SocketAddr, Capabilities>);
+
+impl Listeners {
+    /// Parse a list of listeners from user command
+    ///
+    /// e.g. `127.0.0.1:8080=full,192.168.1.10:8080=read`
+    pub fn parse_list(listeners: &str) -> Result<Self, String> {
+        let mut parsed = HashMap::new();
+        for listener in listeners.split(',') {
+            let (addr, capabilities) = listener
+                .split_once('=')
+                .ok_or(format!("expected <host>:<port>=<capabilities>: {listener}"))?;
+            let capabilities = Capabilities::parse(capabilities)?;
+            let addrs = addr
+                .to_socket_addrs()
+                .map_err(|err| format!("invalid listener address: {addr}, {err}"))?;
+            for addr in addrs {
+                if parsed.insert(addr, capabilities).is_some() {
+                    return Err(format!("duplicate listener: {addr}"));
+                }
+            }
+        }
+        Ok(Self(parsed))
+    }
+
+    /// True if no listeners are configured
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Addresses to bind
+    pub fn addrs(&self) -> impl Iterator<Item = &SocketAddr> {
+        self.0.keys()
+    }
+
+    /// Return the capabilities of the listener bound to `addr`, full if not configured
+    pub fn capabilities(&self, addr: &SocketAddr) -> Capabilities {
+        self.0.get(addr).copied().unwrap_or(Capabilities::FULL)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{
+        http::StatusCode,
+        middleware::from_fn,
+        test::{self, TestRequest},
+        web, App,
+    };
+
+    use super::*;
+
+    #[test]
+    fn parse_listeners() {
+        let listeners =
+            Listeners::parse_list("127.0.0.1:8080=full,0.0.0.0:8081=read+trade").unwrap();
+        assert_eq!(listeners.addrs().count(), 2);
+        assert_eq!(
+            listeners.capabilities(&"127.0.0.1:8080".parse().unwrap()),
+            Capabilities::FULL
+        );
+        assert_eq!(
+            listeners.capabilities(&"0.0.0.0:8081".parse().unwrap()),
+            Capabilities {
+                read: true,
+                trade: true,
+                admin: false,
+            }
+        );
+
+        assert!(Listeners::parse_list("127.0.0.1:8080").is_err());
+        assert!(Listeners::parse_list("127.0.0.1:8080=write").is_err());
+        assert!(Listeners::parse_list("127.0.0.1:8080=read,127.0.0.1:8080=full").is_err());
+    }
+
+    #[actix_web::test]
+    async fn capabilities_of_requests() {
+        let read = Capabilities::parse("read").unwrap();
+        assert_eq!(
+            read.missing(Scope::Api, &Method::GET, "/v2/positions"),
+            None
+        );
+        assert_eq!(
+            read.missing(Scope::Api, &Method::POST, "/v2/orders/whatIf"),
+            None
+        );
+        assert_eq!(
+            read.missing(Scope::Api, &Method::POST, "/v2/orders"),
+            Some("trade")
+        );
+        assert_eq!(
+            read.missing(Scope::Api, &Method::DELETE, "/v2/orders"),
+            Some("trade")
+        );
+        assert_eq!(
+            read.missing(Scope::Admin, &Method::GET, "/v2/admin/usage"),
+            Some("admin")
+        );
+
+        let trade = Capabilities::parse("trade").unwrap();
+        assert_eq!(
+            trade.missing(Scope::Api, &Method::GET, "/v2/orders"),
+            Some("read")
+        );
+        assert_eq!(
+            trade.missing(Scope::Api, &Method::PATCH, "/v2/orders"),
+            None
+        );
+        assert_eq!(
+            Capabilities::FULL.missing(Scope::Admin, &Method::POST, "/v2/admin/bench"),
+            None
+        );
+
+        // checked on the scope requests are routed to, percent-encoded or not
+        let listeners = Arc::new(Listeners::parse_list("127.0.0.1:8080=read+trade").unwrap());
+        let app = test::init_service(
+            App::new().service(
+                web::scope("/v2")
+                    .service(
+                        web::scope("/admin")
+                            .wrap(from_fn({
+                                let listeners = Arc::clone(&listeners);
+                                move |req: ServiceRequest, next: Next<_>| {
+                                    let listeners = Arc::clone(&listeners);
+                                    require_capabilities(listeners, Scope::Admin, req, next)
+                                }
+                            }))
+                            .route("/bench", web::post().to(HttpResponse::Ok)),
+                    )
+                    .service(
+                        web::scope("")
+                            .wrap(from_fn(move |req: ServiceRequest, next: Next<_>| {
+                                let listeners = Arc::clone(&listeners);
+                                require_capabilities(listeners, Scope::Api, req, next)
+                            }))
+                            .route("/orders", web::post().to(HttpResponse::Ok))
+                            .route("/orders/whatIf", web::post().to(HttpResponse::Ok)),
+                    ),
+            ),
+        )
+        .await;
+        for (uri, status) in [
+            ("/v2/orders", StatusCode::OK),
+            ("/v2/orders/%77hatIf", StatusCode::OK),
+            ("/v2/admin/bench", StatusCode::FORBIDDEN),
+            ("/v2/%61dmin/bench", StatusCode::FORBIDDEN),
+        ] {
+            let res = test::try_call_service(&app, TestRequest::post().uri(uri).to_request()).await;
+            let res_status = match res {
+                Ok(res) => res.status(),
+                Err(err) => err.as_response_error().status_code(),
+            };
+            assert_eq!(res_status, status, "{uri}");
+        }
+    }
+}
@@ -0,0 +1,143 @@
+//! Log file output with size and time based rotation
+//!
+//! The active file is rotated to `<path>.1` (previous `<path>.1` to `<path>.2` and so on) once it
+//! exceeds the max. size or age, files beyond the retention count are deleted.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+/// Log file rotation options
+#[derive(Debug, Clone)]
+pub struct Rotation {
+    /// max. bytes of the active file
+    pub max_size: u64,
+    /// max. age of the active file, None to rotate by size only
+    pub max_age: Option<Duration>,
+    /// number of rotated files kept
+    pub keep: usize,
+}
+
+/// Log file writer rotating per [`Rotation`]
+pub struct RotatingFile {
+    path: PathBuf,
+    rotation: Rotation,
+    file: File,
+    /// bytes written to the active file
+    size: u64,
+    opened: Instant,
+}
+
+impl RotatingFile {
+    /// Open the log file at `path` for appending
+    pub fn open(path: PathBuf, rotation: Rotation) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path,
+            rotation,
+            file,
+            size,
+            opened: Instant::now(),
+        })
+    }
+
+    /// Path of the `n`th rotated file
+    fn rotated_path(&self, n: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{n}"));
+        path.into()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.rotation.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            let _ = fs::remove_file(self.rotated_path(self.rotation.keep));
+            for n in (1..self.rotation.keep).rev() {
+                let from = self.rotated_path(n);
+                if from.exists() {
+                    fs::rename(from, self.rotated_path(n + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated_path(1))?;
+        }
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+        self.opened = Instant::now();
+        Ok(())
+    }
+
+    fn should_rotate(&self, len: usize) -> bool {
+        self.size > 0
+            && (self.size + len as u64 > self.rotation.max_size
+                || self
+                    .rotation
+                    .max_age
+                    .is_some_and(|max_age| self.opened.elapsed() >= max_age))
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.should_rotate(buf.len()) {
+            if let Err(err) = self.rotate() {
+                // keep logging to the current file rather than losing lines
+                eprintln!("log file rotation failed: {err:?}");
+            }
+        }
+        let n = self.file.write(buf)?;
+        self.size += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotating_file_works() {
+        let dir = std::env::temp_dir().join(format!("gateway-log-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("gateway.log");
+        let mut file = RotatingFile::open(
+            path.clone(),
+            Rotation {
+                max_size: 10,
+                max_age: None,
+                keep: 2,
+            },
+        )
+        .unwrap();
+
+        for line in ["aaaaaaaa\n", "bbbbbbbb\n", "cccccccc\n", "dddddddd\n"] {
+            file.write_all(line.as_bytes()).unwrap();
+        }
+        file.flush().unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "dddddddd\n");
+        assert_eq!(
+            fs::read_to_string(dir.join("gateway.log.1")).unwrap(),
+            "cccccccc\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("gateway.log.2")).unwrap(),
+            "bbbbbbbb\n"
+        );
+        assert!(!dir.join("gateway.log.3").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
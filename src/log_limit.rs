@@ -0,0 +1,288 @@
+//! Rate limit of repeated identical warning/error log lines
+//!
+//! Each distinct line may be logged up to `limit` times per `interval`, further repeats are
+//! suppressed and summarized once the interval has passed, e.g. during reconnect storms of an
+//! upstream outage. Audit logs are exempt, and logged regardless of the gateway level.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+use crate::{AUDIT_LOG_TARGET, LOG_TARGET};
+
+/// Max. distinct lines tracked, expired entries are dropped beyond this
+const MAX_TRACKED: usize = 1_024;
+
+/// Level of gateway logs, reloadable at runtime
+static GATEWAY_LEVEL: AtomicUsize = AtomicUsize::new(LevelFilter::Info as usize);
+/// Max. level of other logs
+static OTHER_LEVEL: AtomicUsize = AtomicUsize::new(LevelFilter::Off as usize);
+
+/// Return the level of gateway logs
+fn gateway_level() -> LevelFilter {
+    level_filter(GATEWAY_LEVEL.load(Ordering::Relaxed))
+}
+
+/// Set the level of gateway logs
+pub fn set_gateway_level(level: LevelFilter) {
+    GATEWAY_LEVEL.store(level as usize, Ordering::Relaxed);
+    // audit logs are info
+    log::set_max_level(
+        level
+            .max(level_filter(OTHER_LEVEL.load(Ordering::Relaxed)))
+            .max(LevelFilter::Info),
+    );
+}
+
+fn level_filter(level: usize) -> LevelFilter {
+    LevelFilter::iter().nth(level).unwrap_or(LevelFilter::Trace)
+}
+
+/// Whether the level of `metadata` is enabled, gateway logs are filtered by the gateway level
+fn level_enabled(metadata: &Metadata) -> bool {
+    metadata.target() == AUDIT_LOG_TARGET
+        || !metadata.target().starts_with(LOG_TARGET)
+        || metadata.level() <= gateway_level()
+}
+
+/// Outcome of a log line
+#[derive(Debug, PartialEq)]
+enum Admit {
+    Log,
+    /// log, after summarizing repeats suppressed in the previous interval
+    LogWithSummary(u32),
+    Suppress,
+}
+
+struct Window {
+    start: Instant,
+    count: u32,
+    suppressed: u32,
+}
+
+struct LogLimiter {
+    limit: u32,
+    interval: Duration,
+    windows: Mutex<HashMap<(Level, String, String), Window>>,
+}
+
+/// Repeats of a line suppressed in an expired interval
+#[derive(Debug, PartialEq)]
+struct Suppressed {
+    level: Level,
+    target: String,
+    message: String,
+    count: u32,
+}
+
+impl LogLimiter {
+    /// Admit a line, also returning suppressed repeats of expired lines dropped to bound tracking
+    fn admit(
+        &self,
+        level: Level,
+        target: &str,
+        message: &str,
+        now: Instant,
+    ) -> (Admit, Vec<Suppressed>) {
+        let mut windows = self.windows.lock().expect("lock");
+        let mut pruned = Vec::new();
+        if windows.len() >= MAX_TRACKED {
+            let interval = self.interval;
+            windows.retain(|(level, target, message), w| {
+                let expired = now.saturating_duration_since(w.start) >= interval;
+                if expired && w.suppressed > 0 {
+                    pruned.push(Suppressed {
+                        level: *level,
+                        target: target.clone(),
+                        message: message.clone(),
+                        count: w.suppressed,
+                    });
+                }
+                !expired
+            });
+        }
+        let window = windows
+            .entry((level, target.to_string(), message.to_string()))
+            .or_insert(Window {
+                start: now,
+                count: 0,
+                suppressed: 0,
+            });
+
+        let mut summary = 0;
+        if now.saturating_duration_since(window.start) >= self.interval {
+            summary = window.suppressed;
+            *window = Window {
+                start: now,
+                count: 0,
+                suppressed: 0,
+            };
+        }
+        let admit = if window.count < self.limit {
+            window.count += 1;
+            if summary > 0 {
+                Admit::LogWithSummary(summary)
+            } else {
+                Admit::Log
+            }
+        } else {
+            window.suppressed += 1;
+            Admit::Suppress
+        };
+
+        (admit, pruned)
+    }
+}
+
+/// Logger suppressing repeated identical warnings/errors of the inner logger
+pub struct RateLimitedLogger {
+    inner: env_logger::Logger,
+    /// None if unlimited
+    limiter: Option<LogLimiter>,
+}
+
+impl RateLimitedLogger {
+    /// `inner` should admit all gateway logs, they're filtered by the reloadable gateway level
+    ///
+    /// * `limit` - max. identical lines per `interval`, 0 disables the limit
+    pub fn new(inner: env_logger::Logger, limit: u32, interval: Duration) -> Self {
+        Self {
+            inner,
+            limiter: (limit > 0).then(|| LogLimiter {
+                limit,
+                interval,
+                windows: Mutex::default(),
+            }),
+        }
+    }
+
+    /// Install as the global logger
+    ///
+    /// * `gateway_level` - level of gateway logs
+    /// * `other_level` - max. level of other logs
+    pub fn init(self, gateway_level: LevelFilter, other_level: LevelFilter) {
+        OTHER_LEVEL.store(other_level as usize, Ordering::Relaxed);
+        set_gateway_level(gateway_level);
+        log::set_boxed_logger(Box::new(self)).expect("logger initialized once");
+    }
+
+    fn log_summary(&self, level: Level, target: &str, suppressed: u32, message: &str) {
+        self.inner.log(
+            &Record::builder()
+                .level(level)
+                .target(target)
+                .args(format_args!(
+                    "suppressed {suppressed} repeats of: {message}"
+                ))
+                .build(),
+        );
+    }
+}
+
+impl Log for RateLimitedLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        level_enabled(metadata) && self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !level_enabled(record.metadata()) {
+            return;
+        }
+        let limiter = match self.limiter {
+            Some(ref limiter)
+                if record.level() <= Level::Warn
+                    && record.target() != AUDIT_LOG_TARGET
+                    && self.inner.matches(record) =>
+            {
+                limiter
+            }
+            _ => return self.inner.log(record),
+        };
+        let message = record.args().to_string();
+        let (admit, pruned) =
+            limiter.admit(record.level(), record.target(), &message, Instant::now());
+        for suppressed in pruned {
+            self.log_summary(
+                suppressed.level,
+                &suppressed.target,
+                suppressed.count,
+                &suppressed.message,
+            );
+        }
+        match admit {
+            Admit::Log => self.inner.log(record),
+            Admit::LogWithSummary(suppressed) => {
+                self.log_summary(record.level(), record.target(), suppressed, &message);
+                self.inner.log(record);
+            }
+            Admit::Suppress => (),
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_limiter_works() {
+        let limiter = LogLimiter {
+            limit: 2,
+            interval: Duration::from_secs(60),
+            windows: Mutex::default(),
+        };
+        let t0 = Instant::now();
+        let admit =
+            |message: &str, now: Instant| limiter.admit(Level::Warn, "gateway", message, now).0;
+
+        assert_eq!(admit("stream finished", t0), Admit::Log);
+        assert_eq!(admit("stream finished", t0), Admit::Log);
+        assert_eq!(admit("stream finished", t0), Admit::Suppress);
+        assert_eq!(admit("stream finished", t0), Admit::Suppress);
+        // distinct lines are limited separately
+        assert_eq!(admit("other", t0), Admit::Log);
+
+        let t1 = t0 + Duration::from_secs(60);
+        assert_eq!(admit("stream finished", t1), Admit::LogWithSummary(2));
+        assert_eq!(admit("stream finished", t1), Admit::Log);
+    }
+
+    #[test]
+    fn log_limiter_summarizes_pruned_lines() {
+        let limiter = LogLimiter {
+            limit: 1,
+            interval: Duration::from_secs(60),
+            windows: Mutex::default(),
+        };
+        let t0 = Instant::now();
+        limiter.admit(Level::Warn, "gateway", "stream finished", t0);
+        limiter.admit(Level::Warn, "gateway", "stream finished", t0);
+        for i in 1..MAX_TRACKED {
+            limiter.admit(Level::Warn, "gateway", &i.to_string(), t0);
+        }
+
+        let t1 = t0 + Duration::from_secs(60);
+        let (admit, pruned) = limiter.admit(Level::Warn, "gateway", "other", t1);
+        assert_eq!(admit, Admit::Log);
+        assert_eq!(
+            pruned,
+            vec![Suppressed {
+                level: Level::Warn,
+                target: "gateway".to_string(),
+                message: "stream finished".to_string(),
+                count: 1,
+            }]
+        );
+    }
+}
@@ -1,11 +1,19 @@
-use std::{borrow::Borrow, str::FromStr, sync::Arc, time::Duration};
+use std::{
+    borrow::Borrow,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use actix_web::{
-    delete, get,
-    middleware::Logger,
+    body::{BodySize, MessageBody},
+    delete,
+    dev::{Service, ServiceRequest},
+    get,
+    middleware::{self, Logger, Next},
     patch, post,
     web::{self, Json},
-    App, Either, HttpResponse, HttpServer, Responder,
+    App, Either, HttpRequest, HttpResponse, HttpServer, Responder,
 };
 use argh::FromArgs;
 use drift_rs::{
@@ -13,26 +21,106 @@ use drift_rs::{
     Pubkey,
 };
 use log::{debug, info, warn};
+use rust_decimal::Decimal;
 use serde_json::json;
 
 use crate::{
-    controller::{create_wallet, AppState, ControllerError},
+    controller::{create_remote_wallet, create_wallet, unix_timestamp, AppState, ControllerError},
+    log_limit::RateLimitedLogger,
+    request_id::{format_log, RequestId, REQUEST_ID_HEADER},
+    slot_tag::SlotTag,
     types::{
-        CancelAndPlaceRequest, CancelOrdersRequest, Market, ModifyOrdersRequest, PlaceOrdersRequest,
+        BenchRequest, CancelAndPlaceRequest, CancelOrdersRequest, ConfirmRequest,
+        CreateSubAccountRequest, DcaRequest, EquityHistoryRequest, ExecutionReportRequest,
+        FeeSpendRequest, FillsRequest, Market, ModifyOrdersRequest, OrderbookRequest,
+        PlaceOrdersRequest, PollEventsRequest, ReconcileRequest, ScheduleOrdersRequest,
+        SubscriptionRequest, TransferRequest, TriggerRequest, TwapRequest, UsageRequest,
     },
+    usage::{api_key_scope, usage_api_key, usage_authority, API_KEY_HEADER},
 };
 
+mod admin_auth;
+mod alerts;
+#[cfg(feature = "archive")]
+mod archive;
+mod backfill;
+mod backoff;
+mod chain_clock;
+mod chain_slot;
+mod confirm;
 mod controller;
+mod dca;
+mod dlob;
+mod events;
+mod execution;
+mod fee_spend;
+mod fills;
+mod fix;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod guards;
+mod ha;
+mod health;
+mod history;
+mod insurance;
+mod jito;
+mod layout;
+mod limits;
+mod listeners;
+mod log_file;
+mod log_limit;
+mod market_data;
+mod message;
+mod net;
+mod nonce;
+mod oracle;
+mod order_status;
+mod priority_fees;
+mod program_events;
+mod reconcile;
+mod registry;
+mod request_id;
+mod rollups;
+mod rpc;
+mod runtime;
+mod scheduler;
+#[cfg(all(test, feature = "schema"))]
+mod schema;
+mod settings;
+mod signer;
+mod slot_tag;
+mod status;
+mod sub_accounts;
+mod subscriptions;
+mod templates;
+mod throttle;
+mod triggers;
+mod twap;
+mod tx_status;
 mod types;
+mod usage;
+mod user_order_ids;
+mod webhooks;
 mod websocket;
+mod whatif;
+mod ws_auth;
+mod ws_queue;
 
 pub const LOG_TARGET: &str = "gateway";
+/// Log target of compliance decisions, always logged and not rate limited
+pub const AUDIT_LOG_TARGET: &str = "gateway::audit";
 
 /// Request context
-#[derive(serde::Deserialize, Default)]
+#[derive(serde::Deserialize, Default, Clone)]
 struct Context {
     #[serde(default, rename = "subAccountId")]
     pub sub_account_id: Option<u16>,
+    /// sub-account name, takes precedence over `sub_account_id`
+    #[serde(default, rename = "subAccount")]
+    pub sub_account: Option<String>,
+    /// delegator authority to act for (delegated mode), the default authority if unset
+    #[serde(default, rename = "authority")]
+    pub authority: Option<String>,
     #[serde(default, rename = "computeUnitLimit")]
     pub cu_limit: Option<u32>,
     #[serde(default, rename = "computeUnitPrice")]
@@ -40,6 +128,28 @@ struct Context {
     /// Tx retry TTL
     #[serde(default, rename = "ttl")]
     pub ttl: Option<u16>,
+    /// skip duplicate order detection
+    #[serde(default, rename = "allowDuplicate")]
+    pub allow_duplicate: bool,
+    /// latest time the tx may be sent, from the `X-Deadline-Ms` header
+    #[serde(skip)]
+    pub tx_deadline: Option<Instant>,
+}
+
+/// Header with the latency budget of order txs in milliseconds
+const DEADLINE_HEADER: &str = "X-Deadline-Ms";
+
+/// Set the tx deadline of `ctx` from the request's latency budget header, if any
+fn with_tx_deadline(mut ctx: Context, req: &HttpRequest) -> Result<Context, ControllerError> {
+    if let Some(value) = req.headers().get(DEADLINE_HEADER) {
+        let budget_ms: u64 = value
+            .to_str()
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .ok_or_else(|| ControllerError::BadRequest(format!("invalid {DEADLINE_HEADER}")))?;
+        ctx.tx_deadline = Some(Instant::now() + Duration::from_millis(budget_ms));
+    }
+    Ok(ctx)
 }
 
 #[get("/markets")]
@@ -53,6 +163,33 @@ async fn get_market_info(controller: web::Data<AppState>, path: web::Path<u16>)
     handle_result(controller.get_perp_market_info(*path).await)
 }
 
+#[get("/fundingRate/{index}")]
+async fn get_funding_rate(controller: web::Data<AppState>, path: web::Path<u16>) -> impl Responder {
+    handle_result(controller.get_funding_rate(*path).await)
+}
+
+#[get("/marketMargin/{symbol}")]
+async fn get_market_margin(
+    controller: web::Data<AppState>,
+    path: web::Path<String>,
+) -> impl Responder {
+    handle_result(controller.get_market_margin(&path).await)
+}
+
+#[get("/orderbook/{symbol}")]
+async fn get_orderbook(
+    controller: web::Data<AppState>,
+    path: web::Path<String>,
+    req: web::Query<OrderbookRequest>,
+) -> impl Responder {
+    handle_result(controller.get_orderbook(&path, req.0).await)
+}
+
+#[get("/insurance/{index}")]
+async fn get_insurance(controller: web::Data<AppState>, path: web::Path<u16>) -> impl Responder {
+    handle_result(controller.get_insurance(*path).await)
+}
+
 #[get("/orders")]
 async fn get_orders(
     controller: web::Data<AppState>,
@@ -75,26 +212,164 @@ async fn create_orders(
     controller: web::Data<AppState>,
     body: web::Bytes,
     ctx: web::Query<Context>,
+    http_req: HttpRequest,
+) -> impl Responder {
+    let ctx = match with_tx_deadline(ctx.0, &http_req) {
+        Ok(ctx) => ctx,
+        Err(err) => return handle_result(Err(err)),
+    };
+    match serde_json::from_slice::<'_, PlaceOrdersRequest>(body.as_ref()) {
+        Ok(req) => {
+            debug!(target: LOG_TARGET, "request: {req:?}");
+            handle_result(controller.place_orders(ctx, req).await)
+        }
+        Err(err) => handle_deser_error(err),
+    }
+}
+
+#[post("/orders/whatIf")]
+async fn what_if_orders(
+    controller: web::Data<AppState>,
+    body: web::Bytes,
+    ctx: web::Query<Context>,
 ) -> impl Responder {
     match serde_json::from_slice::<'_, PlaceOrdersRequest>(body.as_ref()) {
         Ok(req) => {
             debug!(target: LOG_TARGET, "request: {req:?}");
-            handle_result(controller.place_orders(ctx.0, req).await)
+            handle_result(controller.what_if_orders(ctx.0, req).await)
+        }
+        Err(err) => handle_deser_error(err),
+    }
+}
+
+#[post("/orders/scheduled")]
+async fn schedule_orders(
+    controller: web::Data<AppState>,
+    body: web::Bytes,
+    ctx: web::Query<Context>,
+) -> impl Responder {
+    match serde_json::from_slice::<'_, ScheduleOrdersRequest>(body.as_ref()) {
+        Ok(req) => {
+            debug!(target: LOG_TARGET, "request: {req:?}");
+            handle_result(controller.schedule_orders(ctx.0, req))
         }
         Err(err) => handle_deser_error(err),
     }
 }
 
+#[get("/orders/scheduled")]
+async fn get_scheduled_orders(controller: web::Data<AppState>) -> impl Responder {
+    Json(controller.get_scheduled_orders())
+}
+
+#[delete("/orders/scheduled/{id}")]
+async fn cancel_scheduled_orders(
+    controller: web::Data<AppState>,
+    path: web::Path<u64>,
+) -> impl Responder {
+    handle_result(controller.cancel_scheduled_orders(*path))
+}
+
+#[post("/dca")]
+async fn create_dca(
+    controller: web::Data<AppState>,
+    body: web::Bytes,
+    ctx: web::Query<Context>,
+) -> impl Responder {
+    match serde_json::from_slice::<'_, DcaRequest>(body.as_ref()) {
+        Ok(req) => {
+            debug!(target: LOG_TARGET, "request: {req:?}");
+            handle_result(controller.create_dca(ctx.0, req))
+        }
+        Err(err) => handle_deser_error(err),
+    }
+}
+
+#[get("/dca")]
+async fn get_dca_plans(controller: web::Data<AppState>) -> impl Responder {
+    Json(controller.get_dca_plans())
+}
+
+#[get("/dca/{id}")]
+async fn get_dca_plan(controller: web::Data<AppState>, path: web::Path<u64>) -> impl Responder {
+    handle_result(controller.get_dca_plan(*path))
+}
+
+#[delete("/dca/{id}")]
+async fn cancel_dca(controller: web::Data<AppState>, path: web::Path<u64>) -> impl Responder {
+    handle_result(controller.cancel_dca(*path))
+}
+
+#[post("/algo/twap")]
+async fn create_twap(
+    controller: web::Data<AppState>,
+    body: web::Bytes,
+    ctx: web::Query<Context>,
+) -> impl Responder {
+    match serde_json::from_slice::<'_, TwapRequest>(body.as_ref()) {
+        Ok(req) => {
+            debug!(target: LOG_TARGET, "request: {req:?}");
+            handle_result(controller.create_twap(ctx.0, req))
+        }
+        Err(err) => handle_deser_error(err),
+    }
+}
+
+#[get("/algo/twap")]
+async fn get_twap_algos(controller: web::Data<AppState>) -> impl Responder {
+    Json(controller.get_twap_algos())
+}
+
+#[get("/algo/twap/{id}")]
+async fn get_twap_algo(controller: web::Data<AppState>, path: web::Path<u64>) -> impl Responder {
+    handle_result(controller.get_twap_algo(*path))
+}
+
+#[delete("/algo/twap/{id}")]
+async fn cancel_twap(controller: web::Data<AppState>, path: web::Path<u64>) -> impl Responder {
+    handle_result(controller.cancel_twap(*path))
+}
+
+#[post("/triggers")]
+async fn create_trigger(
+    controller: web::Data<AppState>,
+    body: web::Bytes,
+    ctx: web::Query<Context>,
+) -> impl Responder {
+    match serde_json::from_slice::<'_, TriggerRequest>(body.as_ref()) {
+        Ok(req) => {
+            debug!(target: LOG_TARGET, "request: {req:?}");
+            handle_result(controller.create_trigger(ctx.0, req))
+        }
+        Err(err) => handle_deser_error(err),
+    }
+}
+
+#[get("/triggers")]
+async fn get_triggers(controller: web::Data<AppState>) -> impl Responder {
+    Json(controller.get_triggers())
+}
+
+#[delete("/triggers/{id}")]
+async fn cancel_trigger(controller: web::Data<AppState>, path: web::Path<u64>) -> impl Responder {
+    handle_result(controller.cancel_trigger(*path))
+}
+
 #[patch("/orders")]
 async fn modify_orders(
     controller: web::Data<AppState>,
     body: web::Bytes,
     ctx: web::Query<Context>,
+    http_req: HttpRequest,
 ) -> impl Responder {
+    let ctx = match with_tx_deadline(ctx.0, &http_req) {
+        Ok(ctx) => ctx,
+        Err(err) => return handle_result(Err(err)),
+    };
     match serde_json::from_slice::<'_, ModifyOrdersRequest>(body.as_ref()) {
         Ok(req) => {
             debug!(target: LOG_TARGET, "request: {req:?}");
-            handle_result(controller.modify_orders(ctx.0, req).await)
+            handle_result(controller.modify_orders(ctx, req).await)
         }
         Err(err) => handle_deser_error(err),
     }
@@ -105,7 +380,12 @@ async fn cancel_orders(
     controller: web::Data<AppState>,
     body: web::Bytes,
     ctx: web::Query<Context>,
+    http_req: HttpRequest,
 ) -> impl Responder {
+    let ctx = match with_tx_deadline(ctx.0, &http_req) {
+        Ok(ctx) => ctx,
+        Err(err) => return handle_result(Err(err)),
+    };
     let mut req = CancelOrdersRequest::default();
     // handle the body manually to allow empty payload `Json` requires some body is set
     if !body.is_empty() {
@@ -115,7 +395,7 @@ async fn cancel_orders(
         }
     };
     debug!(target: LOG_TARGET, "request: {req:?}");
-    handle_result(controller.cancel_orders(ctx.0, req).await)
+    handle_result(controller.cancel_orders(ctx, req).await)
 }
 
 #[post("/orders/cancelAndPlace")]
@@ -123,11 +403,16 @@ async fn cancel_and_place_orders(
     controller: web::Data<AppState>,
     body: web::Bytes,
     ctx: web::Query<Context>,
+    http_req: HttpRequest,
 ) -> impl Responder {
+    let ctx = match with_tx_deadline(ctx.0, &http_req) {
+        Ok(ctx) => ctx,
+        Err(err) => return handle_result(Err(err)),
+    };
     match serde_json::from_slice::<'_, CancelAndPlaceRequest>(body.as_ref()) {
         Ok(req) => {
             debug!(target: LOG_TARGET, "request: {req:?}");
-            handle_result(controller.cancel_and_place_orders(ctx.0, req).await)
+            handle_result(controller.cancel_and_place_orders(ctx, req).await)
         }
         Err(err) => handle_deser_error(err),
     }
@@ -184,6 +469,11 @@ async fn get_tx_events(
     )
 }
 
+#[get("/snapshot")]
+async fn get_snapshot(controller: web::Data<AppState>, ctx: web::Query<Context>) -> impl Responder {
+    handle_result(controller.get_snapshot(ctx.0).await)
+}
+
 #[get("/user/marginInfo")]
 async fn get_margin_info(
     controller: web::Data<AppState>,
@@ -192,6 +482,11 @@ async fn get_margin_info(
     handle_result(controller.get_margin_info(ctx.0).await)
 }
 
+#[get("/user/health")]
+async fn get_health(controller: web::Data<AppState>, ctx: web::Query<Context>) -> impl Responder {
+    handle_result(controller.get_health(ctx.0).await)
+}
+
 #[get("/leverage")]
 async fn get_leverage(controller: web::Data<AppState>, ctx: web::Query<Context>) -> impl Responder {
     handle_result(controller.get_leverage(ctx.0).await)
@@ -209,32 +504,391 @@ async fn get_collateral(
     )
 }
 
+#[post("/reconcile")]
+async fn reconcile(
+    controller: web::Data<AppState>,
+    body: web::Bytes,
+    ctx: web::Query<Context>,
+) -> impl Responder {
+    match serde_json::from_slice::<'_, ReconcileRequest>(body.as_ref()) {
+        Ok(req) => {
+            debug!(target: LOG_TARGET, "request: {req:?}");
+            handle_result(controller.reconcile(ctx.0, req).await)
+        }
+        Err(err) => handle_deser_error(err),
+    }
+}
+
+#[get("/subAccounts")]
+async fn get_sub_accounts(
+    controller: web::Data<AppState>,
+    ctx: web::Query<Context>,
+) -> impl Responder {
+    handle_result(controller.get_sub_accounts(ctx.0).await)
+}
+
+#[post("/subAccount")]
+async fn create_sub_account(
+    controller: web::Data<AppState>,
+    body: web::Bytes,
+    ctx: web::Query<Context>,
+) -> impl Responder {
+    let mut req = CreateSubAccountRequest::default();
+    // an empty body creates the next sub-account with a default name
+    if !body.is_empty() {
+        match serde_json::from_slice(body.as_ref()) {
+            Ok(deser) => req = deser,
+            Err(err) => return handle_deser_error(err),
+        }
+    };
+    debug!(target: LOG_TARGET, "request: {req:?}");
+    handle_result(controller.create_sub_account(ctx.0, req).await)
+}
+
+#[get("/livez")]
+async fn livez() -> impl Responder {
+    HttpResponse::Ok().json(json!({ "status": "ok" }))
+}
+
+#[get("/readyz")]
+async fn readyz(controller: web::Data<AppState>) -> impl Responder {
+    let readiness = controller.readiness().await;
+    if readiness.ready {
+        HttpResponse::Ok().json(readiness)
+    } else {
+        HttpResponse::ServiceUnavailable().json(readiness)
+    }
+}
+
+#[get("/rpcStats")]
+async fn get_rpc_stats(controller: web::Data<AppState>) -> impl Responder {
+    Json(controller.get_rpc_stats())
+}
+
+#[get("/events/poll")]
+async fn poll_events(
+    controller: web::Data<AppState>,
+    req: web::Query<PollEventsRequest>,
+) -> impl Responder {
+    handle_result(controller.poll_events(req.0).await)
+}
+
+#[get("/fills")]
+async fn get_fills(
+    controller: web::Data<AppState>,
+    ctx: web::Query<Context>,
+    req: web::Query<FillsRequest>,
+) -> impl Responder {
+    handle_result(controller.get_fills(ctx.0, req.0))
+}
+
+#[get("/report/execution")]
+async fn get_execution_report(
+    controller: web::Data<AppState>,
+    ctx: web::Query<Context>,
+    req: web::Query<ExecutionReportRequest>,
+) -> impl Responder {
+    handle_result(controller.get_execution_report(ctx.0, req.0))
+}
+
+#[get("/orderStatus/{order_id}")]
+async fn get_order_status(
+    controller: web::Data<AppState>,
+    path: web::Path<u32>,
+    ctx: web::Query<Context>,
+) -> impl Responder {
+    handle_result(controller.get_order_status(ctx.0, path.into_inner()))
+}
+
+#[get("/orderStatus/client/{user_order_id}")]
+async fn get_order_status_by_user_order_id(
+    controller: web::Data<AppState>,
+    path: web::Path<u8>,
+    ctx: web::Query<Context>,
+) -> impl Responder {
+    handle_result(controller.get_order_status_by_user_order_id(ctx.0, path.into_inner()))
+}
+
+#[get("/tx/{signature}")]
+async fn get_tx_status(controller: web::Data<AppState>, path: web::Path<String>) -> impl Responder {
+    handle_result(controller.get_tx_status(&path.into_inner()).await)
+}
+
+#[get("/history/equity")]
+async fn get_equity_history(
+    controller: web::Data<AppState>,
+    ctx: web::Query<Context>,
+    req: web::Query<EquityHistoryRequest>,
+) -> impl Responder {
+    handle_result(controller.get_equity_history(ctx.0, req.0))
+}
+
+#[post("/bench")]
+async fn bench(
+    controller: web::Data<AppState>,
+    body: web::Bytes,
+    ctx: web::Query<Context>,
+) -> impl Responder {
+    let mut req = BenchRequest::default();
+    // handle the body manually to allow empty payload `Json` requires some body is set
+    if !body.is_empty() {
+        match serde_json::from_slice(body.as_ref()) {
+            Ok(deser) => req = deser,
+            Err(err) => return handle_deser_error(err),
+        }
+    };
+    handle_result(controller.bench(ctx.0, req).await)
+}
+
+#[post("/transfer")]
+async fn transfer(
+    controller: web::Data<AppState>,
+    body: web::Bytes,
+    ctx: web::Query<Context>,
+) -> impl Responder {
+    match serde_json::from_slice::<'_, TransferRequest>(body.as_ref()) {
+        Ok(req) => {
+            debug!(target: LOG_TARGET, "request: {req:?}");
+            handle_result(controller.transfer(ctx.0, req).await)
+        }
+        Err(err) => handle_deser_error(err),
+    }
+}
+
+#[post("/confirm")]
+async fn confirm(controller: web::Data<AppState>, body: web::Bytes) -> impl Responder {
+    match serde_json::from_slice::<'_, ConfirmRequest>(body.as_ref()) {
+        Ok(req) => handle_result(controller.confirm_transfer(req).await),
+        Err(err) => handle_deser_error(err),
+    }
+}
+
+#[get("/subscriptions")]
+async fn get_subscriptions(controller: web::Data<AppState>) -> impl Responder {
+    handle_result(controller.get_subscriptions())
+}
+
+#[get("/usage")]
+async fn get_usage(
+    controller: web::Data<AppState>,
+    req: web::Query<UsageRequest>,
+) -> impl Responder {
+    handle_result(controller.get_usage(req.into_inner()))
+}
+
+#[get("/fees")]
+async fn get_fee_spend(
+    controller: web::Data<AppState>,
+    req: web::Query<FeeSpendRequest>,
+) -> impl Responder {
+    handle_result(controller.get_fee_spend(req.into_inner()))
+}
+
+#[post("/webhooks/helius")]
+async fn helius_webhook(
+    controller: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Bytes,
+) -> impl Responder {
+    let Some(webhook) = controller.helius_webhook() else {
+        return Either::Left(HttpResponse::NotFound().json(json!(
+            {
+                "code": 404,
+                "reason": "webhook ingestion disabled",
+                "requestId": RequestId::current(),
+            }
+        )));
+    };
+    let authorization = req
+        .headers()
+        .get(actix_web::http::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+    if !webhook.verify(authorization) {
+        return Either::Left(HttpResponse::Unauthorized().json(json!(
+            {
+                "code": 401,
+                "reason": "invalid webhook auth header",
+                "requestId": RequestId::current(),
+            }
+        )));
+    }
+    match webhooks::parse_helius(body.as_ref()) {
+        Ok(txs) => handle_result(controller.ingest_webhook_txs(txs).await),
+        Err(reason) => handle_result(Err(ControllerError::BadRequest(reason))),
+    }
+}
+
+#[post("/reload")]
+async fn reload_settings(
+    controller: web::Data<AppState>,
+    admin_auth: web::Data<admin_auth::AdminAuth>,
+    body: web::Bytes,
+) -> impl Responder {
+    let mut settings = None;
+    if !body.is_empty() {
+        // settings may redirect txs to other RPCs, loopback clients aren't trusted to set them
+        if !admin_auth.has_key() {
+            return Either::Left(HttpResponse::Forbidden().json(json!({
+                "code": 403,
+                "reason": "settings in the body require DRIFT_GATEWAY_ADMIN_KEY",
+                "requestId": RequestId::current(),
+            })));
+        }
+        match serde_json::from_slice(body.as_ref()) {
+            Ok(deser) => settings = Some(deser),
+            Err(err) => return handle_deser_error(err),
+        }
+    };
+
+    handle_result(controller.reload_settings(settings).await)
+}
+
+#[post("/subscriptions/resubscribe")]
+async fn resubscribe(controller: web::Data<AppState>, body: web::Bytes) -> impl Responder {
+    match serde_json::from_slice::<'_, SubscriptionRequest>(body.as_ref()) {
+        Ok(req) => {
+            debug!(target: LOG_TARGET, "request: {req:?}");
+            handle_result(controller.resubscribe(req).await)
+        }
+        Err(err) => handle_deser_error(err),
+    }
+}
+
+#[delete("/subscriptions")]
+async fn unsubscribe(controller: web::Data<AppState>, body: web::Bytes) -> impl Responder {
+    match serde_json::from_slice::<'_, SubscriptionRequest>(body.as_ref()) {
+        Ok(req) => {
+            debug!(target: LOG_TARGET, "request: {req:?}");
+            handle_result(controller.unsubscribe(req).await)
+        }
+        Err(err) => handle_deser_error(err),
+    }
+}
+
+/// Startup error of an invalid option
+fn invalid_config(reason: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, reason)
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     let config: GatewayConfig = argh::from_env();
 
     let mut logger = env_logger::Builder::from_default_env();
-    if config.verbose {
-        logger
-            .filter_module(LOG_TARGET, log::LevelFilter::Debug)
-            .filter_module("rpc", log::LevelFilter::Debug)
+    let mut other_level = env_logger::Builder::from_default_env().build().filter();
+    // gateway logs are filtered by their reloadable level instead
+    logger.filter_module(LOG_TARGET, log::LevelFilter::Trace);
+    let gateway_level = if config.verbose {
+        logger.filter_module("rpc", log::LevelFilter::Debug);
+        other_level = other_level.max(log::LevelFilter::Debug);
+        log::LevelFilter::Debug
     } else {
-        logger.filter_module(LOG_TARGET, log::LevelFilter::Info)
+        log::LevelFilter::Info
+    };
+    logger.format(format_log);
+    if let Some(ref path) = config.log_file {
+        let file = log_file::RotatingFile::open(
+            path.into(),
+            log_file::Rotation {
+                max_size: config.log_file_max_size * 1024 * 1024,
+                max_age: (config.log_file_max_age > 0)
+                    .then(|| Duration::from_secs(config.log_file_max_age * 60 * 60)),
+                keep: config.log_file_keep,
+            },
+        )
+        .expect("log file opened");
+        logger.target(env_logger::Target::Pipe(Box::new(file)));
+    }
+    RateLimitedLogger::new(
+        logger.build(),
+        config.log_rate_limit,
+        Duration::from_secs(config.log_rate_interval),
+    )
+    .init(gateway_level, other_level);
+
+    let cpu_affinity = config
+        .cpu_affinity
+        .as_deref()
+        .map(|cores| Arc::new(runtime::CpuAffinity::parse(cores).expect("valid --cpu-affinity")));
+    if let Some(ref affinity) = cpu_affinity {
+        info!(target: LOG_TARGET, "pinning threads to cores: {affinity:?}");
+        // background tasks run on the main thread
+        affinity.pin_current_thread();
     }
-    .init();
 
     let secret_key = std::env::var("DRIFT_GATEWAY_KEY");
-    let delegate = config
+    let delegates: Vec<Pubkey> = config
         .delegate
-        .map(|ref x| Pubkey::from_str(x).expect("valid pubkey"));
+        .as_deref()
+        .map(|x| {
+            x.split(",")
+                .map(|x| Pubkey::from_str(x).expect("valid pubkey"))
+                .collect()
+        })
+        .unwrap_or_default();
     let emulate = config
         .emulate
         .map(|ref x| Pubkey::from_str(x).expect("valid pubkey"));
-    let wallet = create_wallet(secret_key.ok(), emulate, delegate);
+    let remote_signer = config.remote_signer.as_ref().map(|url| {
+        let pubkey = config
+            .remote_signer_pubkey
+            .as_deref()
+            .map(|x| Pubkey::from_str(x).expect("valid pubkey"))
+            .expect("--remote-signer requires --remote-signer-pubkey");
+        (url, pubkey)
+    });
+    let wallet = match remote_signer {
+        Some((_, pubkey)) => {
+            assert!(
+                secret_key.is_err() && emulate.is_none(),
+                "--remote-signer excludes 'DRIFT_GATEWAY_KEY' and --emulate"
+            );
+            create_remote_wallet(pubkey, delegates.first().copied())
+        }
+        None => create_wallet(secret_key.ok(), emulate, delegates.first().copied()),
+    }
+    .with_delegators(&delegates);
     let state_commitment = CommitmentConfig::from_str(&config.commitment)
         .expect("one of: processed | confirmed | finalized");
     let tx_commitment = CommitmentConfig::from_str(&config.tx_commitment)
         .expect("one of: processed | confirmed | finalized");
+    if config.rpc_rate_limit == Some(0) || config.tx_rpc_rate_limit == Some(0) {
+        return Err(invalid_config("RPC rate limits must be positive"));
+    }
+    if config.market_data_interval == 0 {
+        return Err(invalid_config("--market-data-interval must be positive"));
+    }
+    if config.archive_url.is_some() && cfg!(not(feature = "archive")) {
+        return Err(invalid_config(
+            "--archive-url requires a build with the 'archive' feature",
+        ));
+    }
+    if (config.archive_url.is_some() || config.fills_retention_days.is_some())
+        && config.fills_db.is_none()
+    {
+        return Err(invalid_config(
+            "--archive-url and --fills-retention-days require --fills-db",
+        ));
+    }
+    if config.grpc_port.is_some() && cfg!(not(feature = "grpc")) {
+        return Err(invalid_config(
+            "--grpc-port requires a build with the 'grpc' feature",
+        ));
+    }
+    let grpc_capabilities = listeners::Capabilities::parse(&config.grpc_capabilities)
+        .map_err(|err| invalid_config(&format!("invalid --grpc-capabilities: {err}")))?;
+    if config.transfer_confirm_timeout.is_some()
+        && std::env::var("DRIFT_GATEWAY_ADMIN_KEY").map_or(true, |key| key.is_empty())
+    {
+        return Err(invalid_config(
+            "--transfer-confirm-timeout requires DRIFT_GATEWAY_ADMIN_KEY",
+        ));
+    }
+    if config.transfer_confirm_timeout == Some(0) {
+        return Err(invalid_config(
+            "--transfer-confirm-timeout must be positive",
+        ));
+    }
     let extra_rpcs = config.extra_rpcs.as_ref();
     let state = AppState::new(
         &config.rpc_host,
@@ -246,23 +900,314 @@ async fn main() -> std::io::Result<()> {
         extra_rpcs
             .map(|s| s.split(",").collect())
             .unwrap_or_default(),
+        Some(rpc::RpcHttpConfig {
+            pool_max_idle_per_host: config.rpc_pool_size,
+            tcp_keepalive: (config.rpc_tcp_keepalive > 0)
+                .then(|| Duration::from_secs(config.rpc_tcp_keepalive)),
+            http2_only: config.rpc_http2,
+            rate_limit: config.rpc_rate_limit,
+        }),
     )
     .await;
+    let state = match remote_signer {
+        Some((url, pubkey)) => {
+            info!(target: LOG_TARGET, "signing txs with remote signer: {url} ({pubkey})");
+            state.with_remote_signer(
+                url,
+                pubkey,
+                std::env::var("DRIFT_GATEWAY_SIGNER_TOKEN")
+                    .ok()
+                    .filter(|token| !token.is_empty()),
+            )
+        }
+        None => state,
+    };
+    let state = state.with_timeouts(controller::Timeouts {
+        account: Duration::from_millis(config.account_timeout),
+        send: Duration::from_millis(config.send_timeout),
+    });
+    let state = match config.ha_lock_file {
+        Some(ref path) => {
+            info!(target: LOG_TARGET, "running in active/standby mode");
+            state.with_leadership(ha::Leadership::elect(
+                path.into(),
+                Duration::from_secs(config.ha_lease_timeout),
+            ))
+        }
+        None => state,
+    };
+    let state = match (config.tx_rpc.as_ref(), config.tx_rpc_rate_limit) {
+        (None, None) => state,
+        (tx_rpc, rate_limit) => {
+            // a rate limited tx lane is separate from the query lane even to the same RPC
+            let tx_rpc = tx_rpc.unwrap_or(&config.rpc_host);
+            info!(target: LOG_TARGET, "using dedicated tx RPC: {tx_rpc}, rate limit: {rate_limit:?}");
+            state.with_tx_rpc(tx_rpc, rate_limit)
+        }
+    };
+    let state = match config.jito_url {
+        Some(ref url) => {
+            info!(target: LOG_TARGET, "sending txs as Jito bundles: {url}, tip: {} lamports", config.jito_tip);
+            state.with_jito(jito::Jito::new(url, config.jito_tip))
+        }
+        None => state,
+    };
+    let state = match config.nonce_account {
+        Some(ref accounts) => {
+            let accounts: Vec<Pubkey> = accounts
+                .split(",")
+                .map(|s| Pubkey::from_str(s).expect("valid nonce account"))
+                .collect();
+            info!(target: LOG_TARGET, "signing txs with durable nonces: {accounts:?}");
+            state.with_nonce_accounts(accounts)
+        }
+        None => state,
+    };
+    let state = match config.risk_groups {
+        Some(ref groups) => {
+            let risk_groups =
+                guards::RiskGroup::parse_list(groups, |ticker| state.client.market_lookup(ticker))
+                    .expect("valid risk groups");
+            info!(target: LOG_TARGET, "enforcing risk groups: {risk_groups:?}");
+            state.with_risk_groups(risk_groups)
+        }
+        None => state,
+    };
+    let state = match config.compliance_rules {
+        Some(ref path) => {
+            let json = std::fs::read_to_string(path).expect("readable compliance rules");
+            let rules =
+                guards::ComplianceRules::parse(&json, |ticker| state.client.market_lookup(ticker))
+                    .expect("valid compliance rules");
+            info!(target: LOG_TARGET, "enforcing compliance rules: {rules:?}");
+            state.with_compliance_rules(rules)
+        }
+        None => state,
+    };
+    let state = match config.sub_accounts {
+        Some(ref sub_accounts) => {
+            let sub_account_ids: Vec<u16> = sub_accounts
+                .split(",")
+                .map(|s| s.parse().expect("valid sub-account id"))
+                .collect();
+            state.with_sub_accounts(&sub_account_ids)
+        }
+        None => state,
+    };
+    let state = match config.trading_windows {
+        Some(ref windows) => {
+            let trading_windows = parse_trading_windows(windows).expect("valid trading windows");
+            info!(target: LOG_TARGET, "trading windows: {trading_windows:?}");
+            state.with_trading_windows(trading_windows)
+        }
+        None => state,
+    };
+    let state = match config.market_order_rate {
+        Some(rate) => {
+            info!(target: LOG_TARGET, "order rate limit: {rate}/s per market ({:?})", config.market_order_throttle);
+            state.with_order_throttle(throttle::OrderThrottle::new(
+                rate,
+                config.market_order_throttle,
+            ))
+        }
+        None => state,
+    };
+    let state = match config.duplicate_order_window {
+        Some(window) => {
+            info!(target: LOG_TARGET, "rejecting duplicate orders within {window}ms");
+            state.with_duplicate_order_window(Duration::from_millis(window))
+        }
+        None => state,
+    };
+    let state = match config.post_only_cross_check {
+        Some(cross_check) => {
+            info!(target: LOG_TARGET, "checking post-only orders against the dlob ({cross_check:?})");
+            state.with_cross_check(cross_check)
+        }
+        None => state,
+    };
+    let state = match config.transfer_confirm_timeout {
+        Some(timeout) => {
+            info!(target: LOG_TARGET, "transfers await confirmation for up to {timeout}s");
+            state.with_transfer_confirmation(Duration::from_secs(timeout))
+        }
+        None => state,
+    };
+    let state = state.with_max_orders(config.max_orders_per_request);
+    let state = match config.market_data {
+        _ if config.polling_only => {
+            info!(target: LOG_TARGET, "polling-only mode, polling accounts every {}s", config.market_data_interval);
+            state.with_polling_only(Duration::from_secs(config.market_data_interval))
+        }
+        market_data::MarketDataMode::PubSub => state,
+        market_data::MarketDataMode::Polling => {
+            info!(target: LOG_TARGET, "polling market data every {}s", config.market_data_interval);
+            state.with_polled_market_data(Duration::from_secs(config.market_data_interval))
+        }
+    };
+    let state = match config.runtime_config {
+        Some(ref path) => state.with_settings_file(path.into()),
+        None => state,
+    };
+    let state = match std::env::var("DRIFT_GATEWAY_HELIUS_AUTH")
+        .ok()
+        .filter(|auth| !auth.is_empty())
+    {
+        Some(auth) => {
+            info!(target: LOG_TARGET, "ingesting Helius webhooks at /v2/webhooks/helius");
+            state.with_helius_webhook(auth)
+        }
+        None => state,
+    };
+    let state = match config.dca_state {
+        Some(ref path) => {
+            let dca = dca::Dca::load(path.into()).expect("valid DCA state file");
+            info!(target: LOG_TARGET, "DCA state: {path}");
+            state.with_dca(dca)
+        }
+        None => state,
+    };
+    let state = match config.fills_db {
+        Some(ref path) => {
+            let store = fills::FillStore::open(path).expect("valid fills db");
+            info!(target: LOG_TARGET, "fill history: {path}");
+            state.with_fill_store(store)
+        }
+        None => state,
+    };
+    let state = match config.alert_sinks {
+        Some(ref sinks) => state.with_alerter(alerts::Alerter::new(
+            alerts::AlertSink::parse_list(sinks).expect("valid alert sinks"),
+            config.alert_tx_failures,
+        )),
+        None => state,
+    };
 
     // start market+oracle subs
     let mut markets = Vec::<MarketId>::default();
     if let Some(ref user_markets) = config.markets {
         markets.extend(parse_markets(&state.client, user_markets).expect("valid markets"));
     };
+    state.subscribe_sub_accounts().await;
     state.subscribe_market_data(&markets).await;
     info!(target: LOG_TARGET, "subscribed to market data updates 🛜");
+    if config.runtime_config.is_some() {
+        state
+            .reload_settings(None)
+            .await
+            .expect("valid runtime config");
+    }
+    #[cfg(unix)]
+    state.reload_on_hangup();
+    match config.follow {
+        Some(ref primary) => {
+            info!(target: LOG_TARGET, "following event journal of: {primary}");
+            state.follow_events(primary);
+        }
+        // event streams are Ws log subscriptions
+        None if config.polling_only => {
+            warn!(target: LOG_TARGET, "event journal disabled in polling-only mode");
+        }
+        None => state.subscribe_events(),
+    }
+    state.load_sub_account_names().await;
+    if let Some(ref sub_accounts) = config.equity_history {
+        let sub_account_ids: Vec<u16> = sub_accounts
+            .split(",")
+            .map(|s| s.parse().expect("valid sub-account id"))
+            .collect();
+        info!(target: LOG_TARGET, "recording equity history: {sub_account_ids:?}");
+        state.record_equity_history(sub_account_ids);
+    }
+    if config.trading_windows_cancel {
+        state.cancel_outside_trading_windows();
+    }
+    if let Some(max_loss) = config.max_daily_loss {
+        info!(target: LOG_TARGET, "max daily loss: {max_loss}");
+        state.monitor_daily_loss(max_loss);
+    }
+    state.monitor_social_losses();
+    state.persist_usage();
+    if config.fills_db.is_some() {
+        state.record_oracle_history();
+    }
+    if let Some(ref url) = config.archive_url {
+        #[cfg(feature = "archive")]
+        {
+            let target = archive::S3Target::new(
+                url,
+                config.archive_region.clone(),
+                std::env::var("AWS_ACCESS_KEY_ID").unwrap_or_default(),
+                std::env::var("AWS_SECRET_ACCESS_KEY").unwrap_or_default(),
+                std::env::var("AWS_SESSION_TOKEN")
+                    .ok()
+                    .filter(|token| !token.is_empty()),
+            )
+            .map_err(|err| invalid_config(&err))?;
+            info!(target: LOG_TARGET, "archiving fills to: {url}");
+            archive::run(
+                state.fill_store().expect("fills db"),
+                target,
+                Duration::from_secs(config.archive_period),
+            );
+        }
+        #[cfg(not(feature = "archive"))]
+        let _ = url;
+    }
+    if let Some(days) = config.fills_retention_days {
+        state.prune_fills(
+            Duration::from_secs(days * 24 * 60 * 60),
+            config.archive_url.is_some(),
+        );
+    }
+    state.monitor_subscriptions(config.subscription_stall_timeout.map(Duration::from_secs));
+    state.monitor_status();
+    state.run_order_scheduler();
+    state.run_dca();
+    state.run_twap();
+    state.monitor_triggers();
+    if config.post_only_cross_check.is_some() {
+        state.load_dlob();
+    }
+    if let Some(ref oracles) = config.oracle_cross_check {
+        let oracles = oracle::SecondaryOracle::parse_list(oracles, |ticker| {
+            state.client.market_lookup(ticker)
+        })
+        .expect("valid secondary oracles");
+        info!(target: LOG_TARGET, "cross-checking oracles: {oracles:?}");
+        state.monitor_oracle_divergence(
+            oracle::Hermes::new(&config.oracle_cross_check_url),
+            oracles,
+            config.oracle_max_divergence,
+        );
+    }
+    if let Some(threshold) = config.alert_margin_health {
+        info!(target: LOG_TARGET, "alerting on margin health below {threshold}");
+        state.monitor_margin_health(threshold);
+    }
 
-    info!(
-        target: LOG_TARGET,
-        "🏛️ gateway listening at http://{}:{}", config.host, config.port
-    );
+    let listeners = match config.listeners {
+        Some(ref listeners) => {
+            listeners::Listeners::parse_list(listeners).expect("valid listeners")
+        }
+        None => listeners::Listeners::default(),
+    };
+    if listeners.is_empty() {
+        info!(
+            target: LOG_TARGET,
+            "🏛️ gateway listening at http://{}",
+            net::host_port(&config.host, config.port)
+        );
+    }
+    for addr in listeners.addrs() {
+        info!(
+            target: LOG_TARGET,
+            "🏛️ gateway listening at http://{addr} ({:?})",
+            listeners.capabilities(addr)
+        );
+    }
 
-    if delegate.is_some() {
+    if !delegates.is_empty() {
         info!(
             target: LOG_TARGET,
             "🪪 authority: {:?}, default sub-account: {:?}, 🔑 delegate: {:?}",
@@ -270,6 +1215,9 @@ async fn main() -> std::io::Result<()> {
             state.default_sub_account(),
             state.signer(),
         );
+        for authority in state.wallet.delegators() {
+            info!(target: LOG_TARGET, "🪪 delegator: {authority:?}");
+        }
     } else {
         info!(
             target: LOG_TARGET,
@@ -278,45 +1226,307 @@ async fn main() -> std::io::Result<()> {
             state.default_sub_account()
         );
         if emulate.is_some() {
-            warn!("using emulation mode, tx signing unavailable");
+            warn!("using emulation mode, txs are built but not signed or sent");
         }
     }
 
-    let client = Box::leak(Box::new(Arc::clone(state.client.borrow())));
-    websocket::start_ws_server(
-        format!("{}:{}", &config.host, config.ws_port).as_str(),
-        client.ws(),
-        state.wallet.inner().clone(),
-        client.program_data(),
-    )
-    .await;
+    if config.polling_only {
+        // Ws event streams are log subscriptions of the RPC
+        warn!(target: LOG_TARGET, "Ws event server disabled in polling-only mode");
+    } else {
+        let client = Box::leak(Box::new(Arc::clone(state.client.borrow())));
+        let ws_server = websocket::start_ws_server(
+            net::bind_host(&config.host, config.ws_port)?,
+            client,
+            client.ws(),
+            state.wallet.inner().clone(),
+            client.program_data(),
+            Arc::clone(&state.sub_account_names),
+            Arc::clone(&state.subscriptions),
+            Arc::clone(&state.incompatible_markets),
+            Arc::clone(&state.dlob),
+            Arc::clone(&state.chain_clock),
+            config.order_expiry_warning,
+            (config.ws_heartbeat_interval > 0).then(|| websocket::WsHeartbeat {
+                interval: Duration::from_secs(config.ws_heartbeat_interval),
+                timeout: Duration::from_secs(config.ws_heartbeat_timeout),
+            }),
+            std::env::var("DRIFT_GATEWAY_WS_AUTH_KEY")
+                .ok()
+                .filter(|key| !key.is_empty())
+                .map(|key| Arc::new(ws_auth::WsAuth::new(key))),
+            Arc::clone(&state.status),
+        );
+        if config.ws_threads > 0 {
+            // lives as long as the process
+            let ws_runtime = Box::leak(Box::new(runtime::dedicated_runtime(
+                "gateway-ws",
+                config.ws_threads,
+                cpu_affinity.clone(),
+            )?));
+            info!(target: LOG_TARGET, "Ws server runtime: {} threads", config.ws_threads);
+            ws_runtime.spawn(ws_server);
+        } else {
+            ws_server.await;
+        }
+    }
 
-    HttpServer::new(move || {
-        App::new()
-            .wrap(Logger::new("%a | %s | %r | (%Dms)").log_target(LOG_TARGET))
-            .app_data(web::Data::new(state.clone()))
-            .service(
-                web::scope("/v2")
-                    .service(get_markets)
-                    .service(get_positions)
-                    .service(get_orders)
-                    .service(create_orders)
-                    .service(cancel_orders)
-                    .service(modify_orders)
-                    .service(cancel_and_place_orders)
-                    .service(get_sol_balance)
-                    .service(get_positions_extended)
-                    .service(get_tx_events)
-                    .service(get_market_info)
-                    .service(get_margin_info)
-                    .service(get_leverage)
-                    .service(get_collateral),
-            )
+    if let Some(grpc_port) = config.grpc_port {
+        #[cfg(feature = "grpc")]
+        {
+            let listener = net::bind_host(&config.host, grpc_port)?;
+            info!(
+                target: LOG_TARGET,
+                "🏛️ gateway gRPC listening at http://{}",
+                listener.local_addr()?
+            );
+            let state = state.clone();
+            let max_message_size = config.max_body_size * 1024;
+            tokio::spawn(async move {
+                if let Err(err) =
+                    grpc::serve(listener, state, grpc_capabilities, max_message_size).await
+                {
+                    log::error!(target: LOG_TARGET, "gRPC server failed: {err:?}");
+                }
+            });
+        }
+        // refused on startup without the feature
+        #[cfg(not(feature = "grpc"))]
+        let _ = (grpc_port, grpc_capabilities);
+    }
+
+    if let Some(fix_port) = config.fix_port {
+        let comp_ids = |ids: Option<&str>| {
+            ids.map(|ids| ids.split(',').map(|id| id.trim().to_string()).collect())
+                .unwrap_or_default()
+        };
+        fix::start_fix_server(
+            net::bind_host(&config.host, fix_port)?,
+            state.clone(),
+            fix::FixConfig {
+                comp_id: config.fix_comp_id.clone(),
+                sender_comp_ids: comp_ids(config.fix_sender_comp_ids.as_deref()),
+                drop_copy_comp_ids: comp_ids(config.fix_drop_copy_comp_ids.as_deref()),
+                password: std::env::var("DRIFT_GATEWAY_FIX_PASSWORD")
+                    .ok()
+                    .filter(|password| !password.is_empty()),
+                // sessions persist across gateway restarts with a fills db only
+                store: Some(Arc::new(fix::SessionStore::new(
+                    state.open_store_tree("fix_sessions").unwrap_or_else(|| {
+                        sled::Config::new()
+                            .temporary(true)
+                            .open()
+                            .and_then(|db| db.open_tree("fix_sessions"))
+                            .expect("temporary store")
+                    }),
+                ))),
+            },
+        )
+        .await;
+    }
+
+    let usage = Arc::clone(&state.usage);
+    let default_authority = *state.authority();
+    let request_limits =
+        limits::RequestLimits::new(config.max_body_size * 1024, config.max_json_depth);
+    let listeners = Arc::new(listeners);
+    let admin_key = std::env::var("DRIFT_GATEWAY_ADMIN_KEY")
+        .ok()
+        .filter(|key| !key.is_empty());
+    if admin_key.is_none() {
+        warn!(target: LOG_TARGET, "admin endpoints are served to loopback clients only, set DRIFT_GATEWAY_ADMIN_KEY");
+    }
+    let admin_auth = Arc::new(admin_auth::AdminAuth::new(admin_key));
+    let server = HttpServer::new({
+        let listeners = Arc::clone(&listeners);
+        let cpu_affinity = cpu_affinity.clone();
+        // called on each worker thread
+        move || {
+            if let Some(ref affinity) = cpu_affinity {
+                affinity.pin_current_thread();
+            }
+            let usage = Arc::clone(&usage);
+            let listeners = Arc::clone(&listeners);
+            let admin_auth = Arc::clone(&admin_auth);
+            let admin_data = web::Data::from(Arc::clone(&admin_auth));
+            App::new()
+                // oversized and deeply nested request bodies are refused
+                .wrap(middleware::from_fn(
+                    move |req: ServiceRequest, next: Next<_>| {
+                        limits::limit_request(request_limits, req, next)
+                    },
+                ))
+                .wrap_fn(|req, srv| {
+                    let request_id = RequestId::from_header(req.headers().get(REQUEST_ID_HEADER));
+                    let res = srv.call(req);
+                    let res = request_id.clone().scope(SlotTag::scope(async move {
+                        let res = res.await;
+                        (res, SlotTag::current())
+                    }));
+                    async move {
+                        let (res, slot_tag) = res.await;
+                        let mut res = res?;
+                        request_id.tag(res.headers_mut());
+                        SlotTag::tag(slot_tag, res.headers_mut());
+                        Ok(res)
+                    }
+                })
+                // usage is accounted to the authority acted for and the API key, if any
+                .wrap_fn(move |req, srv| {
+                    let authority = web::Query::<Context>::from_query(req.query_string())
+                        .ok()
+                        .and_then(|ctx| ctx.into_inner().authority);
+                    let authority = usage_authority(authority.as_deref(), &default_authority);
+                    let api_key =
+                        usage_api_key(req.headers().get(API_KEY_HEADER).map(|v| v.as_bytes()));
+                    let usage = Arc::clone(&usage);
+                    let res = api_key_scope(api_key.clone(), srv.call(req));
+                    async move {
+                        let res = res.await?;
+                        let egress_bytes = match res.response().body().size() {
+                            BodySize::Sized(size) => size,
+                            _ => 0,
+                        };
+                        usage.record_request(
+                            &authority,
+                            api_key.as_deref(),
+                            egress_bytes,
+                            unix_timestamp(),
+                        );
+                        Ok(res)
+                    }
+                })
+                .wrap(
+                    Logger::new("%{peer}xi | %s | %r | (%Dms) | %{x-request-id}o")
+                        .custom_request_replace("peer", |req| {
+                            // as `%a`, IPv4 peers of dual-stack listeners without the v6 mapping
+                            req.connection_info()
+                                .realip_remote_addr()
+                                .map(net::peer_ip)
+                                .unwrap_or_else(|| "-".into())
+                        })
+                        .log_target(LOG_TARGET),
+                )
+                .app_data(web::Data::new(state.clone()))
+                .app_data(admin_data)
+                .app_data(web::PayloadConfig::new(
+                    request_limits.max_webhook_body_size,
+                ))
+                .service(livez)
+                .service(readyz)
+                .service(
+                    web::scope("/v2")
+                        .service(
+                            web::scope("/admin")
+                                // admin requests without the admin key, or from remote clients if
+                                // unset, are refused
+                                .wrap(middleware::from_fn(
+                                    move |req: ServiceRequest, next: Next<_>| {
+                                        admin_auth::authorize(Arc::clone(&admin_auth), req, next)
+                                    },
+                                ))
+                                // requests the listener lacks the capability for are refused
+                                .wrap(middleware::from_fn({
+                                    let listeners = Arc::clone(&listeners);
+                                    move |req: ServiceRequest, next: Next<_>| {
+                                        listeners::require_capabilities(
+                                            Arc::clone(&listeners),
+                                            listeners::Scope::Admin,
+                                            req,
+                                            next,
+                                        )
+                                    }
+                                }))
+                                .service(bench)
+                                .service(confirm)
+                                .service(get_subscriptions)
+                                .service(get_usage)
+                                .service(get_fee_spend)
+                                .service(reload_settings)
+                                .service(resubscribe)
+                                .service(unsubscribe),
+                        )
+                        .service(
+                            web::scope("")
+                                // requests the listener lacks the capability for are refused
+                                .wrap(middleware::from_fn(
+                                    move |req: ServiceRequest, next: Next<_>| {
+                                        listeners::require_capabilities(
+                                            Arc::clone(&listeners),
+                                            listeners::Scope::Api,
+                                            req,
+                                            next,
+                                        )
+                                    },
+                                ))
+                                .service(get_markets)
+                                .service(get_positions)
+                                .service(get_snapshot)
+                                .service(get_orders)
+                                .service(create_orders)
+                                .service(what_if_orders)
+                                .service(schedule_orders)
+                                .service(get_scheduled_orders)
+                                .service(cancel_scheduled_orders)
+                                .service(create_dca)
+                                .service(get_dca_plans)
+                                .service(get_dca_plan)
+                                .service(cancel_dca)
+                                .service(create_twap)
+                                .service(get_twap_algos)
+                                .service(get_twap_algo)
+                                .service(cancel_twap)
+                                .service(create_trigger)
+                                .service(get_triggers)
+                                .service(cancel_trigger)
+                                .service(cancel_orders)
+                                .service(modify_orders)
+                                .service(cancel_and_place_orders)
+                                .service(get_sol_balance)
+                                .service(get_positions_extended)
+                                .service(get_tx_events)
+                                .service(get_market_info)
+                                .service(get_margin_info)
+                                .service(get_health)
+                                .service(get_leverage)
+                                .service(get_collateral)
+                                .service(get_sub_accounts)
+                                .service(create_sub_account)
+                                .service(transfer)
+                                .service(get_insurance)
+                                .service(get_market_margin)
+                                .service(get_funding_rate)
+                                .service(get_orderbook)
+                                .service(reconcile)
+                                .service(poll_events)
+                                .service(get_fills)
+                                .service(get_execution_report)
+                                .service(get_order_status)
+                                .service(get_order_status_by_user_order_id)
+                                .service(get_tx_status)
+                                .service(get_rpc_stats)
+                                .service(get_equity_history)
+                                .service(helius_webhook),
+                        ),
+                )
+        }
     })
-    .keep_alive(Duration::from_secs(config.keep_alive_timeout as u64))
-    .bind((config.host, config.port))?
-    .run()
-    .await
+    .keep_alive(Duration::from_secs(config.keep_alive_timeout as u64));
+    let server = match config.http_workers {
+        Some(workers) => server.workers(workers),
+        None => server,
+    };
+    // bound here rather than by actix so `::` is dual-stack on every OS
+    let server = if listeners.is_empty() {
+        net::resolve(&config.host, config.port)?
+            .into_iter()
+            .try_fold(server, |server, addr| server.listen(net::bind(addr)?))?
+    } else {
+        listeners
+            .addrs()
+            .try_fold(server, |server, addr| server.listen(net::bind(*addr)?))?
+    };
+    server.run().await
 }
 
 fn handle_result<T: std::fmt::Debug>(
@@ -330,6 +1540,7 @@ fn handle_result<T: std::fmt::Debug>(
                 {
                     "code": 500,
                     "reason": err.to_string(),
+                    "requestId": RequestId::current(),
                 }
             )))
         }
@@ -338,6 +1549,7 @@ fn handle_result<T: std::fmt::Debug>(
                 {
                     "code": code,
                     "reason": reason,
+                    "requestId": RequestId::current(),
                 }
             )))
         }
@@ -346,6 +1558,7 @@ fn handle_result<T: std::fmt::Debug>(
                 {
                     "code": 400,
                     "reason": reason,
+                    "requestId": RequestId::current(),
                 }
             )))
         }
@@ -354,6 +1567,61 @@ fn handle_result<T: std::fmt::Debug>(
                 {
                     "code": 404,
                     "reason": format!("tx not found: {}", tx_sig),
+                    "requestId": RequestId::current(),
+                }
+            )))
+        }
+        Err(ControllerError::MarketPaused(reason)) => {
+            Either::Left(HttpResponse::Conflict().json(json!(
+                {
+                    "code": 409,
+                    "reason": reason,
+                    "requestId": RequestId::current(),
+                }
+            )))
+        }
+        Err(ControllerError::RateLimited(reason)) => {
+            Either::Left(HttpResponse::TooManyRequests().json(json!(
+                {
+                    "code": 429,
+                    "reason": reason,
+                    "requestId": RequestId::current(),
+                }
+            )))
+        }
+        Err(err @ (ControllerError::Timeout(_) | ControllerError::DeadlineExceeded(_))) => {
+            Either::Left(HttpResponse::GatewayTimeout().json(json!(
+                {
+                    "code": 504,
+                    "reason": err.to_string(),
+                    "requestId": RequestId::current(),
+                }
+            )))
+        }
+        Err(ControllerError::Bundle(reason)) => {
+            Either::Left(HttpResponse::BadGateway().json(json!(
+                {
+                    "code": 502,
+                    "reason": reason,
+                    "requestId": RequestId::current(),
+                }
+            )))
+        }
+        Err(err @ ControllerError::Signer(_)) => {
+            Either::Left(HttpResponse::BadGateway().json(json!(
+                {
+                    "code": 502,
+                    "reason": err.to_string(),
+                    "requestId": RequestId::current(),
+                }
+            )))
+        }
+        Err(err @ (ControllerError::Standby | ControllerError::Halted(_))) => {
+            Either::Left(HttpResponse::ServiceUnavailable().json(json!(
+                {
+                    "code": 503,
+                    "reason": err.to_string(),
+                    "requestId": RequestId::current(),
                 }
             )))
         }
@@ -365,6 +1633,7 @@ fn handle_deser_error<T>(err: serde_json::Error) -> Either<HttpResponse, Json<T>
         {
             "code": 400,
             "reason": err.to_string(),
+            "requestId": RequestId::current(),
         }
     )))
 }
@@ -383,20 +1652,54 @@ struct GatewayConfig {
     /// run in devnet mode
     #[argh(switch)]
     dev: bool,
-    /// gateway host address
+    /// gateway host address, IPv4 or IPv6 e.g. '::1', '::' listens on IPv4 and IPv6
     #[argh(option, default = "String::from(\"127.0.0.1\")")]
     host: String,
     /// gateway port
     #[argh(option, default = "8080")]
     port: u16,
+    /// comma separated REST listeners as <host>:<port>=<capabilities>, replacing --host/--port.
+    /// capabilities are read, trade, admin joined by '+', or full
+    /// e.g. '127.0.0.1:8080=full,0.0.0.0:8081=read'
+    #[argh(option)]
+    listeners: Option<String>,
     /// gateway Ws port
     #[argh(option, default = "1337")]
     ws_port: u16,
+    /// serve the gRPC API on this port of --host, requires a build with the 'grpc' feature
+    #[argh(option)]
+    grpc_port: Option<u16>,
+    /// capabilities of the gRPC API as read, trade joined by '+', or full (default: read)
+    #[argh(option, default = "String::from(\"read\")")]
+    grpc_capabilities: String,
+    /// accept FIX 4.4 order-entry sessions on this port of --host
+    #[argh(option)]
+    fix_port: Option<u16>,
+    /// CompID of the gateway's FIX sessions, the TargetCompID of initiators
+    #[argh(option, default = "String::from(\"DRIFT-GATEWAY\")")]
+    fix_comp_id: String,
+    /// comma separated SenderCompIDs allowed to logon to FIX sessions, any if unset
+    #[argh(option)]
+    fix_sender_comp_ids: Option<String>,
+    /// comma separated SenderCompIDs of FIX drop copy sessions, sent execution reports of all
+    /// fills and persisted with --fills-db
+    #[argh(option)]
+    fix_drop_copy_comp_ids: Option<String>,
     /// http keep-alive timeout in seconds
     #[argh(option, default = "3600")]
     keep_alive_timeout: u32,
+    /// max. request body size in KB, larger requests are refused with 413 (default: 256)
+    #[argh(option, default = "limits::DEFAULT_MAX_BODY_KB")]
+    max_body_size: usize,
+    /// max. nesting depth of JSON arrays and objects in request bodies (default: 32)
+    #[argh(option, default = "limits::DEFAULT_MAX_JSON_DEPTH")]
+    max_json_depth: usize,
+    /// max. orders per place, modify, whatIf, schedule, or trigger request (default: unlimited)
+    #[argh(option)]
+    max_orders_per_request: Option<usize>,
     /// use delegated signing mode
-    /// provide the delegator's pubkey (i.e the main account)
+    /// provide the delegator's pubkey (i.e the main account), or a comma separated list to act
+    /// for multiple delegators, the first is the default authority
     /// 'DRIFT_GATEWAY_KEY' should be set to the delegate's private key
     #[argh(option)]
     delegate: Option<String>,
@@ -418,9 +1721,206 @@ struct GatewayConfig {
     /// extra solana RPC urls for improved Tx broadcast
     #[argh(option)]
     extra_rpcs: Option<String>,
+    /// solana RPC url dedicated to tx submission and confirmation (default: rpc_host)
+    /// queries continue to use rpc_host
+    #[argh(option)]
+    tx_rpc: Option<String>,
     /// enable debug logging
     #[argh(switch)]
     verbose: bool,
+    /// run in active/standby mode, gateways sharing this lease file elect a single trading leader
+    #[argh(option)]
+    ha_lock_file: Option<String>,
+    /// seconds before an unrenewed HA lease may be taken over by a standby (default: 10)
+    #[argh(option, default = "10", from_str_fn(parse_positive))]
+    ha_lease_timeout: u64,
+    /// comma separated alert sinks as <format>=<url>, format is one of: webhook, slack, discord, telegram
+    #[argh(option)]
+    alert_sinks: Option<String>,
+    /// alert when free/total maintenance collateral of a journaled sub-account falls below this ratio e.g. 0.2
+    #[argh(option)]
+    alert_margin_health: Option<Decimal>,
+    /// alert after this many consecutive txs failing onchain, 0 to disable (default: 5)
+    #[argh(option, default = "5")]
+    alert_tx_failures: u32,
+    /// comma separated sub-account ids to record equity history for e.g. '0,1'
+    #[argh(option)]
+    equity_history: Option<String>,
+    /// combined notional caps (USD) across correlated markets as <name>=<markets>:<max notional>
+    /// separated by ';' e.g. 'sol=sol-perp,sol,jitosol:50000;btc=btc-perp,wbtc:20000'
+    #[argh(option)]
+    risk_groups: Option<String>,
+    /// path of a JSON file of instrument restrictions by authority (markets, longOnly,
+    /// maxTenorDays), enforced at order time
+    #[argh(option)]
+    compliance_rules: Option<String>,
+    /// halt trading and cancel open orders when a sub-account's PnL drops more than this (USD)
+    /// since the start of the UTC day
+    #[argh(option)]
+    max_daily_loss: Option<Decimal>,
+    /// allowed trading hours per sub-account as <id>=<days> <HH:MM>-<HH:MM> [<utc offset>]
+    /// separated by ';' e.g. '0=mon-fri 08:00-17:00 +08:00;1=daily 00:00-23:00', one window per
+    /// sub-account. the offset is fixed and does not follow daylight saving
+    #[argh(option)]
+    trading_windows: Option<String>,
+    /// cancel open orders of sub-accounts when their trading window closes
+    #[argh(switch)]
+    trading_windows_cancel: bool,
+    /// max. order messages (place/modify) per second per market
+    #[argh(option)]
+    market_order_rate: Option<u32>,
+    /// behaviour of orders over the market rate limit: 'queue' (wait up to 1s) or 'reject' (default: queue)
+    #[argh(option, default = "throttle::ThrottleMode::Queue")]
+    market_order_throttle: throttle::ThrottleMode,
+    /// reject orders identical (market, side, price, size, sub-account) to one placed within this
+    /// many milliseconds, unless the request sets 'allowDuplicate=true'
+    #[argh(option)]
+    duplicate_order_window: Option<u64>,
+    /// check post-only orders against resting orders before sending: 'reject' orders that would
+    /// cross or 'adjust' them one tick behind the best opposite price
+    #[argh(option)]
+    post_only_cross_check: Option<guards::CrossCheck>,
+    /// hold transfers until confirmed via 'POST /v2/admin/confirm' within this many seconds,
+    /// requires DRIFT_GATEWAY_ADMIN_KEY
+    #[argh(option)]
+    transfer_confirm_timeout: Option<u64>,
+    /// seconds before an order's maxTs to emit the Ws 'orderExpiring' event, 0 to disable (default: 5)
+    #[argh(option, default = "5")]
+    order_expiry_warning: u64,
+    /// seconds between Ws heartbeats, connections not responding are closed, 0 to disable (default: 0)
+    #[argh(option, default = "0")]
+    ws_heartbeat_interval: u64,
+    /// seconds to await a response to a Ws heartbeat before closing the connection (default: 10)
+    #[argh(option, default = "10")]
+    ws_heartbeat_timeout: u64,
+    /// cross-check oracle prices against Pyth price feeds as <market>=<feed id>
+    /// separated by ',' e.g. 'sol-perp=0xef0d8b..,btc-perp=0xe62df6..'
+    #[argh(option)]
+    oracle_cross_check: Option<String>,
+    /// Pyth Hermes URL for oracle cross-checks (default: https://hermes.pyth.network)
+    #[argh(option, default = "oracle::DEFAULT_HERMES_URL.to_string()")]
+    oracle_cross_check_url: String,
+    /// alert when an oracle price diverges from its cross-check price by more than this ratio (default: 0.01)
+    #[argh(option, default = "Decimal::new(1, 2)")]
+    oracle_max_divergence: Decimal,
+    /// persist DCA plans and their progress to this file, plans are resumed on restart
+    #[argh(option)]
+    dca_state: Option<String>,
+    /// max. milliseconds to wait on the RPC for account and balance fetches (default: 10000)
+    #[argh(option, default = "10_000")]
+    account_timeout: u64,
+    /// max. milliseconds to wait on the RPC for tx preflight and submission (default: 10000)
+    #[argh(option, default = "10_000")]
+    send_timeout: u64,
+    /// max. idle RPC connections kept open per host (default: 64)
+    #[argh(option, default = "64")]
+    rpc_pool_size: usize,
+    /// TCP keepalive interval in seconds of RPC connections, 0 to disable (default: 30)
+    #[argh(option, default = "30")]
+    rpc_tcp_keepalive: u64,
+    /// use HTTP/2 without negotiation for RPC requests, the RPC must support it
+    #[argh(switch)]
+    rpc_http2: bool,
+    /// max. requests per second to rpc_host for queries, unlimited if unset
+    #[argh(option)]
+    rpc_rate_limit: Option<u32>,
+    /// max. requests per second of tx submission and confirmation, limited apart from queries
+    /// (default: unlimited)
+    #[argh(option)]
+    tx_rpc_rate_limit: Option<u32>,
+    /// replicate the event journal of the primary gateway at this URL (e.g. 'http://primary:8080')
+    /// instead of subscribing to account events
+    #[argh(option)]
+    follow: Option<String>,
+    /// max. identical warning/error log lines per `log-rate-interval`, repeats are suppressed
+    /// and summarized (default: 0, unlimited)
+    #[argh(option, default = "0")]
+    log_rate_limit: u32,
+    /// interval in seconds of `log-rate-limit` (default: 60)
+    #[argh(option, default = "60")]
+    log_rate_interval: u64,
+    /// write logs to this file instead of stderr, rotated by size and age
+    #[argh(option)]
+    log_file: Option<String>,
+    /// max. size in MB of the log file before it is rotated (default: 100)
+    #[argh(option, default = "100")]
+    log_file_max_size: u64,
+    /// max. age in hours of the log file before it is rotated, 0 to rotate by size only
+    /// (default: 24)
+    #[argh(option, default = "24")]
+    log_file_max_age: u64,
+    /// number of rotated log files kept (default: 7)
+    #[argh(option, default = "7")]
+    log_file_keep: usize,
+    /// resubscribe market, oracle, and account subscriptions missing updates for this many seconds
+    /// while the chain progresses, disabled if unset
+    #[argh(option)]
+    subscription_stall_timeout: Option<u64>,
+    /// comma separated sub-account ids to subscribe at startup in addition to the default e.g.
+    /// '0,1,2', their events are journaled and positions' markets subscribed
+    #[argh(option)]
+    sub_accounts: Option<String>,
+    /// persist fill and order events to an embedded db at this path, queryable via `/v2/fills`
+    #[argh(option)]
+    fills_db: Option<String>,
+    /// upload events of --fills-db as Parquet files to this S3-compatible bucket url e.g.
+    /// 'https://s3.us-east-1.amazonaws.com/<bucket>/<prefix>', requires a build with the
+    /// 'archive' feature
+    #[argh(option)]
+    archive_url: Option<String>,
+    /// region of the --archive-url bucket (default: us-east-1)
+    #[argh(option, default = "String::from(\"us-east-1\")")]
+    archive_region: String,
+    /// seconds of events per archived file (default: 3600)
+    #[argh(option, default = "3600", from_str_fn(parse_positive))]
+    archive_period: u64,
+    /// remove events of --fills-db older than this many days, once archived with --archive-url
+    #[argh(option, from_str_fn(parse_positive))]
+    fills_retention_days: Option<u64>,
+    /// submit txs as bundles to the Jito block engine at this URL
+    /// (e.g. 'https://mainnet.block-engine.jito.wtf') instead of via RPC
+    #[argh(option)]
+    jito_url: Option<String>,
+    /// tip per Jito bundle in lamports (default: 10000)
+    #[argh(option, default = "jito::DEFAULT_JITO_TIP")]
+    jito_tip: u64,
+    /// sign txs against the durable nonce of these comma separated nonce accounts instead of
+    /// recent blockhashes, the signer must be their nonce authority
+    #[argh(option)]
+    nonce_account: Option<String>,
+    /// source of market data, 'pubsub' Ws subscriptions or 'polling' for RPCs with few or no Ws
+    /// subscriptions e.g. free tiers (default: pubsub)
+    #[argh(option, default = "market_data::MarketDataMode::PubSub")]
+    market_data: market_data::MarketDataMode,
+    /// interval in seconds of polling market data, positive (default: 5)
+    #[argh(option, default = "5")]
+    market_data_interval: u64,
+    /// sign txs with the remote signing service at this URL instead of 'DRIFT_GATEWAY_KEY'
+    #[argh(option)]
+    remote_signer: Option<String>,
+    /// pubkey of the remote signer's key, the delegate with --delegate
+    #[argh(option)]
+    remote_signer_pubkey: Option<String>,
+    /// JSON file of runtime settings (markets, extra RPCs, priority fees, log level), reloaded on
+    /// SIGHUP or `POST /v2/admin/reload` without a restart
+    #[argh(option)]
+    runtime_config: Option<String>,
+    /// subscribe to nothing via Ws, market, oracle and sub-account accounts are polled every
+    /// --market-data-interval instead. For rate limited RPCs (e.g. free tiers), disables the Ws
+    /// event server and event journal
+    #[argh(switch)]
+    polling_only: bool,
+    /// HTTP API worker threads (default: number of physical cores)
+    #[argh(option)]
+    http_workers: Option<usize>,
+    /// run the Ws event server on a runtime of its own with this many threads, 0 to share the
+    /// main thread (default: 0)
+    #[argh(option, default = "0")]
+    ws_threads: usize,
+    /// pin gateway threads round-robin to these cores e.g. '0-3' or '2,3', the main thread
+    /// first, then Ws server and HTTP worker threads
+    #[argh(option)]
+    cpu_affinity: Option<String>,
 }
 
 /// Parse raw markets list from user command
@@ -439,6 +1939,38 @@ fn parse_markets(client: &drift_rs::DriftClient, markets: &str) -> Result<Vec<Ma
     Ok(configured_markets)
 }
 
+/// Parse a positive integer e.g. a number of seconds, refusing zero
+fn parse_positive(value: &str) -> Result<u64, String> {
+    match value.parse() {
+        Ok(0) => Err("expected a positive number".into()),
+        Ok(value) => Ok(value),
+        Err(_) => Err(format!("invalid number: {value}")),
+    }
+}
+
+/// Parse trading windows by sub-account id from user command
+fn parse_trading_windows(
+    windows: &str,
+) -> Result<std::collections::HashMap<u16, guards::TradingWindow>, String> {
+    let mut parsed = std::collections::HashMap::new();
+    for w in windows.split(";") {
+        let (id, window) = w
+            .split_once("=")
+            .ok_or(format!("expected <id>=<window>: {w}"))?;
+        let id: u16 = id
+            .trim()
+            .parse()
+            .map_err(|_| format!("invalid sub-account id: {id}"))?;
+        if parsed
+            .insert(id, guards::TradingWindow::parse(window)?)
+            .is_some()
+        {
+            return Err(format!("duplicate trading window of sub-account: {id}"));
+        }
+    }
+    Ok(parsed)
+}
+
 #[cfg(test)]
 mod tests {
     use actix_web::{http::Method, test, App};
@@ -460,7 +1992,36 @@ mod tests {
         };
         let rpc_endpoint = std::env::var("TEST_RPC_ENDPOINT")
             .unwrap_or_else(|_| "https://api.devnet.solana.com".to_string());
-        AppState::new(&rpc_endpoint, true, wallet, None, None, false, vec![]).await
+        let state =
+            AppState::new(&rpc_endpoint, true, wallet, None, None, false, vec![], None).await;
+        state.subscribe_sub_accounts().await;
+        state
+    }
+
+    #[actix_web::test]
+    async fn error_body_parses_as_client_type() {
+        let result = RequestId::from_client(Some("req-1"))
+            .scope(async {
+                handle_result::<()>(Err(ControllerError::BadRequest("bad amount".into())))
+            })
+            .await;
+        let Either::Left(resp) = result else {
+            panic!("error response");
+        };
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        let error: drift_gateway_types::ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(error.code, 400);
+        assert_eq!(error.reason, "bad amount");
+        assert_eq!(error.request_id.as_deref(), Some("req-1"));
+    }
+
+    #[test]
+    fn parse_trading_windows_works() {
+        let windows =
+            parse_trading_windows("0=mon-fri 08:00-12:00 +01:00;1=daily 00:00-23:00").unwrap();
+        assert_eq!(windows.len(), 2);
+        assert!(parse_trading_windows("0=mon-fri 08:00-12:00;0=mon-fri 13:00-17:00").is_err());
+        assert!(parse_trading_windows("0=mon-fri 08:00-12:00;x=daily 00:00-23:00").is_err());
     }
 
     // likely safe to ignore during development, mainy regression tests for CI
@@ -481,7 +2042,9 @@ mod tests {
 
         let rpc_endpoint = std::env::var("TEST_RPC_ENDPOINT")
             .unwrap_or_else(|_| "https://api.devnet.solana.com".to_string());
-        let state = AppState::new(&rpc_endpoint, true, wallet, None, None, false, vec![]).await;
+        let state =
+            AppState::new(&rpc_endpoint, true, wallet, None, None, false, vec![], None).await;
+        state.subscribe_sub_accounts().await;
 
         let app = test::init_service(
             App::new()
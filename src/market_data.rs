@@ -0,0 +1,424 @@
+//! Sources of market and oracle account data
+//!
+//! Subscribed markets are sourced by a [`MarketDataSource`], selected on startup:
+//! - [`PubSub`]: Ws account subscriptions of the RPC, the default
+//! - [`Polling`]: market and oracle accounts fetched in batches on an interval. Slower to reflect
+//!   changes, but keeps the gateway functional on RPC tiers without (or with few) Ws subscriptions.
+//!   Also polls user accounts in polling-only mode
+//!
+//! Other backends (e.g. Geyser) implement the trait.
+
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use anchor_lang::AccountDeserialize;
+use drift_rs::{
+    ffi::get_oracle_price,
+    types::{
+        accounts::{PerpMarket, SpotMarket, User},
+        MarketId, MarketType, OracleSource, SdkResult,
+    },
+    DriftClient, Pubkey,
+};
+use futures_util::future::{BoxFuture, FutureExt};
+use log::{debug, warn};
+use solana_sdk::account::Account;
+
+use crate::{layout::check_market_layout, LOG_TARGET};
+
+/// Max. accounts per `getMultipleAccounts` request
+pub const MAX_MULTIPLE_ACCOUNTS: usize = 100;
+
+/// Selects the market data source
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MarketDataMode {
+    PubSub,
+    Polling,
+}
+
+impl FromStr for MarketDataMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pubsub" => Ok(Self::PubSub),
+            "polling" => Ok(Self::Polling),
+            other => Err(format!("unknown market data mode: {other}")),
+        }
+    }
+}
+
+/// Source of the accounts of subscribed markets and oracles
+///
+/// accounts not sourced are fetched via RPC on use
+pub trait MarketDataSource: Send + Sync {
+    /// Start sourcing the accounts of `markets`
+    fn subscribe_markets<'a>(&'a self, markets: &'a [MarketId]) -> BoxFuture<'a, SdkResult<()>>;
+    /// Stop sourcing the accounts of `markets`
+    fn unsubscribe_markets<'a>(&'a self, markets: &'a [MarketId]) -> BoxFuture<'a, SdkResult<()>>;
+    /// Start sourcing the oracles of `markets`
+    fn subscribe_oracles<'a>(&'a self, markets: &'a [MarketId]) -> BoxFuture<'a, SdkResult<()>>;
+    /// Stop sourcing the oracles of `markets`
+    fn unsubscribe_oracles<'a>(&'a self, markets: &'a [MarketId]) -> BoxFuture<'a, SdkResult<()>>;
+    /// Return the sourced oracle price of `market` and its slot
+    fn oracle_price(&self, market: MarketId) -> Option<(i64, u64)>;
+    /// Return a sourced perp market account and its slot
+    fn perp_market(&self, market_index: u16) -> Option<(PerpMarket, u64)>;
+    /// Return a sourced spot market account and its slot
+    fn spot_market(&self, market_index: u16) -> Option<(SpotMarket, u64)>;
+}
+
+/// Ws account subscriptions of the drift client
+pub struct PubSub {
+    client: Arc<DriftClient>,
+}
+
+impl PubSub {
+    pub fn new(client: Arc<DriftClient>) -> Self {
+        Self { client }
+    }
+}
+
+impl MarketDataSource for PubSub {
+    fn subscribe_markets<'a>(&'a self, markets: &'a [MarketId]) -> BoxFuture<'a, SdkResult<()>> {
+        self.client.subscribe_markets(markets).boxed()
+    }
+
+    fn unsubscribe_markets<'a>(&'a self, markets: &'a [MarketId]) -> BoxFuture<'a, SdkResult<()>> {
+        self.client.unsubscribe_markets(markets).boxed()
+    }
+
+    fn subscribe_oracles<'a>(&'a self, markets: &'a [MarketId]) -> BoxFuture<'a, SdkResult<()>> {
+        self.client.subscribe_oracles(markets).boxed()
+    }
+
+    fn unsubscribe_oracles<'a>(&'a self, markets: &'a [MarketId]) -> BoxFuture<'a, SdkResult<()>> {
+        self.client.unsubscribe_oracles(markets).boxed()
+    }
+
+    fn oracle_price(&self, market: MarketId) -> Option<(i64, u64)> {
+        self.client
+            .try_get_oracle_price_data_and_slot(market)
+            .map(|o| (o.data.price, o.slot))
+    }
+
+    fn perp_market(&self, market_index: u16) -> Option<(PerpMarket, u64)> {
+        self.client
+            .try_get_perp_market_account_and_slot(market_index)
+            .map(|m| (m.data, m.slot))
+    }
+
+    fn spot_market(&self, market_index: u16) -> Option<(SpotMarket, u64)> {
+        self.client
+            .try_get_spot_market_account_and_slot(market_index)
+            .map(|m| (m.data, m.slot))
+    }
+}
+
+/// A polled market account
+#[derive(Clone, Copy)]
+enum PolledMarket {
+    /// not fetched yet
+    Pending,
+    Perp(PerpMarket, u64),
+    Spot(SpotMarket, u64),
+}
+
+/// Market, oracle, and user accounts polled in batches via RPC
+pub struct Polling {
+    client: Arc<DriftClient>,
+    markets: RwLock<HashMap<MarketId, PolledMarket>>,
+    /// oracle prices and slots by market, None until fetched
+    oracles: RwLock<HashMap<MarketId, Option<(i64, u64)>>>,
+    /// user accounts and slots, None until fetched
+    accounts: RwLock<HashMap<Pubkey, Option<(User, u64)>>>,
+}
+
+impl Polling {
+    /// Start polling subscribed accounts every `interval`
+    pub fn start(client: Arc<DriftClient>, interval: Duration) -> Arc<Self> {
+        let polling = Arc::new(Self {
+            client,
+            markets: RwLock::default(),
+            oracles: RwLock::default(),
+            accounts: RwLock::default(),
+        });
+        tokio::spawn({
+            let polling = Arc::clone(&polling);
+            async move {
+                let mut interval = tokio::time::interval(interval);
+                loop {
+                    interval.tick().await;
+                    let markets: Vec<MarketId> = polling
+                        .markets
+                        .read()
+                        .expect("lock")
+                        .keys()
+                        .copied()
+                        .collect();
+                    if let Err(err) = polling.poll_markets(&markets).await {
+                        warn!(target: LOG_TARGET, "polling market accounts failed: {err:?}");
+                    }
+                    let oracles: Vec<MarketId> = polling
+                        .oracles
+                        .read()
+                        .expect("lock")
+                        .keys()
+                        .copied()
+                        .collect();
+                    if let Err(err) = polling.poll_oracles(&oracles).await {
+                        warn!(target: LOG_TARGET, "polling oracle accounts failed: {err:?}");
+                    }
+                    let accounts: Vec<Pubkey> = polling
+                        .accounts
+                        .read()
+                        .expect("lock")
+                        .keys()
+                        .copied()
+                        .collect();
+                    if let Err(err) = polling.poll_accounts(&accounts).await {
+                        warn!(target: LOG_TARGET, "polling user accounts failed: {err:?}");
+                    }
+                }
+            }
+        });
+        polling
+    }
+
+    /// Poll the user account `pubkey` from the next interval on
+    fn add_account(&self, pubkey: Pubkey) {
+        self.accounts
+            .write()
+            .expect("lock")
+            .entry(pubkey)
+            .or_default();
+    }
+
+    /// Poll the user account `pubkey`, available once subscribed
+    pub async fn subscribe_account(&self, pubkey: &Pubkey) -> SdkResult<()> {
+        self.add_account(*pubkey);
+        self.poll_accounts(&[*pubkey]).await
+    }
+
+    /// Stop polling the user account `pubkey`
+    pub fn unsubscribe_account(&self, pubkey: &Pubkey) {
+        self.accounts.write().expect("lock").remove(pubkey);
+    }
+
+    /// Return the polled user account `pubkey` and its slot
+    pub fn user_account(&self, pubkey: &Pubkey) -> Option<(User, u64)> {
+        *self.accounts.read().expect("lock").get(pubkey)?
+    }
+
+    /// Fetch `pubkeys` in batches, returns each account with the slot it was fetched at
+    async fn fetch(&self, pubkeys: &[Pubkey]) -> SdkResult<Vec<(Option<Account>, u64)>> {
+        let rpc = self.client.rpc();
+        let mut accounts = Vec::with_capacity(pubkeys.len());
+        for chunk in pubkeys.chunks(MAX_MULTIPLE_ACCOUNTS) {
+            let response = rpc
+                .get_multiple_accounts_with_commitment(chunk, rpc.commitment())
+                .await?;
+            let slot = response.context.slot;
+            accounts.extend(response.value.into_iter().map(|account| (account, slot)));
+        }
+        Ok(accounts)
+    }
+
+    /// Fetch the oracle accounts of `markets`
+    async fn poll_oracles(&self, markets: &[MarketId]) -> SdkResult<()> {
+        let program_data = self.client.program_data();
+        let oracles: Vec<(MarketId, Pubkey, OracleSource)> = markets
+            .iter()
+            .filter_map(|m| {
+                let (oracle, source) = match m.kind() {
+                    MarketType::Perp => program_data
+                        .perp_market_config_by_index(m.index())
+                        .map(|c| (c.amm.oracle, c.amm.oracle_source)),
+                    MarketType::Spot => program_data
+                        .spot_market_config_by_index(m.index())
+                        .map(|c| (c.oracle, c.oracle_source)),
+                }?;
+                Some((*m, oracle, source))
+            })
+            .collect();
+        let pubkeys: Vec<Pubkey> = oracles.iter().map(|(_, pubkey, _)| *pubkey).collect();
+        let accounts = self.fetch(&pubkeys).await?;
+        let mut polled = self.oracles.write().expect("lock");
+        for ((market, pubkey, source), (account, slot)) in oracles.into_iter().zip(accounts) {
+            let Some(account) = account else {
+                continue;
+            };
+            // unsubscribed while fetching
+            let Some(entry) = polled.get_mut(&market) else {
+                continue;
+            };
+            match get_oracle_price(source, &mut (pubkey, account), slot) {
+                Ok(oracle) => *entry = Some((oracle.price, slot)),
+                Err(err) => {
+                    warn!(target: LOG_TARGET, "invalid oracle account: {market:?}, {err:?}");
+                }
+            }
+        }
+        debug!(target: LOG_TARGET, "polled {} oracle accounts", pubkeys.len());
+        Ok(())
+    }
+
+    /// Fetch the user accounts `pubkeys`
+    async fn poll_accounts(&self, pubkeys: &[Pubkey]) -> SdkResult<()> {
+        let accounts = self.fetch(pubkeys).await?;
+        let mut polled = self.accounts.write().expect("lock");
+        for (pubkey, (account, slot)) in pubkeys.iter().zip(accounts) {
+            let Some(account) = account else {
+                continue;
+            };
+            let Some(entry) = polled.get_mut(pubkey) else {
+                continue;
+            };
+            if let Ok(user) = User::try_deserialize(&mut account.data.as_slice()) {
+                *entry = Some((user, slot));
+            }
+        }
+        Ok(())
+    }
+
+    /// Fetch the accounts of `markets`
+    async fn poll_markets(&self, markets: &[MarketId]) -> SdkResult<()> {
+        let program_data = self.client.program_data();
+        let markets: Vec<(MarketId, Pubkey)> = markets
+            .iter()
+            .filter_map(|m| {
+                let pubkey = match m.kind() {
+                    MarketType::Perp => program_data
+                        .perp_market_config_by_index(m.index())
+                        .map(|c| c.pubkey),
+                    MarketType::Spot => program_data
+                        .spot_market_config_by_index(m.index())
+                        .map(|c| c.pubkey),
+                }?;
+                Some((*m, pubkey))
+            })
+            .collect();
+        let pubkeys: Vec<Pubkey> = markets.iter().map(|(_, pubkey)| *pubkey).collect();
+        let accounts = self.fetch(&pubkeys).await?;
+        let mut polled = self.markets.write().expect("lock");
+        for ((market, _), (account, slot)) in markets.iter().zip(accounts) {
+            let Some(account) = account else {
+                continue;
+            };
+            // unsubscribed while fetching
+            let Some(entry) = polled.get_mut(market) else {
+                continue;
+            };
+            if let Some(account) = decode(*market, &account.data, slot) {
+                *entry = account;
+            }
+        }
+        debug!(target: LOG_TARGET, "polled {} market accounts", markets.len());
+        Ok(())
+    }
+}
+
+/// Decode a market account of `market` fetched at `slot`, None if incompatible
+fn decode(market: MarketId, data: &[u8], slot: u64) -> Option<PolledMarket> {
+    check_market_layout(market.kind(), data).ok()?;
+    let mut data = data;
+    match market.kind() {
+        MarketType::Perp => PerpMarket::try_deserialize(&mut data)
+            .ok()
+            .map(|m| PolledMarket::Perp(m, slot)),
+        MarketType::Spot => SpotMarket::try_deserialize(&mut data)
+            .ok()
+            .map(|m| PolledMarket::Spot(m, slot)),
+    }
+}
+
+impl MarketDataSource for Polling {
+    fn subscribe_markets<'a>(&'a self, markets: &'a [MarketId]) -> BoxFuture<'a, SdkResult<()>> {
+        async move {
+            {
+                let mut polled = self.markets.write().expect("lock");
+                for market in markets {
+                    polled.entry(*market).or_insert(PolledMarket::Pending);
+                }
+            }
+            // available once subscribed, as Ws subscriptions are
+            self.poll_markets(markets).await
+        }
+        .boxed()
+    }
+
+    fn unsubscribe_markets<'a>(&'a self, markets: &'a [MarketId]) -> BoxFuture<'a, SdkResult<()>> {
+        let mut polled = self.markets.write().expect("lock");
+        for market in markets {
+            polled.remove(market);
+        }
+        async { Ok(()) }.boxed()
+    }
+
+    fn subscribe_oracles<'a>(&'a self, markets: &'a [MarketId]) -> BoxFuture<'a, SdkResult<()>> {
+        async move {
+            {
+                let mut polled = self.oracles.write().expect("lock");
+                for market in markets {
+                    polled.entry(*market).or_default();
+                }
+            }
+            self.poll_oracles(markets).await
+        }
+        .boxed()
+    }
+
+    fn unsubscribe_oracles<'a>(&'a self, markets: &'a [MarketId]) -> BoxFuture<'a, SdkResult<()>> {
+        let mut polled = self.oracles.write().expect("lock");
+        for market in markets {
+            polled.remove(market);
+        }
+        async { Ok(()) }.boxed()
+    }
+
+    fn oracle_price(&self, market: MarketId) -> Option<(i64, u64)> {
+        *self.oracles.read().expect("lock").get(&market)?
+    }
+
+    fn perp_market(&self, market_index: u16) -> Option<(PerpMarket, u64)> {
+        match self
+            .markets
+            .read()
+            .expect("lock")
+            .get(&MarketId::perp(market_index))?
+        {
+            PolledMarket::Perp(market, slot) => Some((*market, *slot)),
+            _ => None,
+        }
+    }
+
+    fn spot_market(&self, market_index: u16) -> Option<(SpotMarket, u64)> {
+        match self
+            .markets
+            .read()
+            .expect("lock")
+            .get(&MarketId::spot(market_index))?
+        {
+            PolledMarket::Spot(market, slot) => Some((*market, *slot)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn market_data_modes() {
+        assert_eq!("pubsub".parse(), Ok(MarketDataMode::PubSub));
+        assert_eq!("polling".parse(), Ok(MarketDataMode::Polling));
+        assert!("geyser".parse::<MarketDataMode>().is_err());
+
+        assert!(decode(MarketId::perp(0), &[0; 16], 1).is_none());
+    }
+}
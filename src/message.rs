@@ -0,0 +1,155 @@
+//! Edits of compiled tx messages
+//!
+//! Order txs are built as compiled messages, ixs added by the gateway after building (nonce
+//! advances, Jito tips) are compiled into them here.
+
+use drift_rs::{types::VersionedMessage, Pubkey};
+use solana_sdk::instruction::{CompiledInstruction, Instruction};
+
+/// Return (key, is signer, is writable) of the static account keys of a message
+fn static_keys(message: &VersionedMessage) -> Vec<(Pubkey, bool, bool)> {
+    let header = message.header();
+    let keys = message.static_account_keys();
+    let num_signed = header.num_required_signatures as usize;
+    let writable_signed = num_signed - header.num_readonly_signed_accounts as usize;
+    let writable_unsigned = keys.len() - header.num_readonly_unsigned_accounts as usize;
+    keys.iter()
+        .enumerate()
+        .map(|(i, key)| {
+            let is_signer = i < num_signed;
+            let is_writable = if is_signer {
+                i < writable_signed
+            } else {
+                i < writable_unsigned
+            };
+            (*key, is_signer, is_writable)
+        })
+        .collect()
+}
+
+/// Insert `ix` at `index` of the ixs of a compiled message, adding any accounts it is missing
+///
+/// static keys are reordered by signer/writable role as the message format requires, the fee
+/// payer stays first. Keys loaded from lookup tables follow the static keys and are shifted.
+pub fn insert_ix(message: &mut VersionedMessage, index: usize, ix: &Instruction) {
+    let mut keys = static_keys(message);
+    let num_static = keys.len();
+    let metas = ix
+        .accounts
+        .iter()
+        .map(|a| (a.pubkey, a.is_signer, a.is_writable))
+        .chain([(ix.program_id, false, false)]);
+    for (key, is_signer, is_writable) in metas {
+        match keys.iter_mut().find(|k| k.0 == key) {
+            Some(k) => {
+                k.1 |= is_signer;
+                k.2 |= is_writable;
+            }
+            None => keys.push((key, is_signer, is_writable)),
+        }
+    }
+    // writable signers, readonly signers, writable, readonly
+    let rank = |(_, is_signer, is_writable): &(Pubkey, bool, bool)| match (*is_signer, *is_writable)
+    {
+        (true, true) => 0,
+        (true, false) => 1,
+        (false, true) => 2,
+        (false, false) => 3,
+    };
+    let mut order: Vec<usize> = (0..keys.len()).collect();
+    order.sort_by_key(|i| rank(&keys[*i]));
+    let mut new_index = vec![0_u8; keys.len()];
+    for (new, old) in order.iter().enumerate() {
+        new_index[*old] = new as u8;
+    }
+    let added = (keys.len() - num_static) as u8;
+    let remap = |i: u8| {
+        if (i as usize) < num_static {
+            new_index[i as usize]
+        } else {
+            i + added
+        }
+    };
+    let index_of = |key: &Pubkey| {
+        let old = keys.iter().position(|k| k.0 == *key).expect("key added");
+        new_index[old]
+    };
+    let compiled = CompiledInstruction {
+        program_id_index: index_of(&ix.program_id),
+        accounts: ix.accounts.iter().map(|a| index_of(&a.pubkey)).collect(),
+        data: ix.data.clone(),
+    };
+
+    let (header, account_keys, instructions) = match message {
+        VersionedMessage::Legacy(ref mut m) => {
+            (&mut m.header, &mut m.account_keys, &mut m.instructions)
+        }
+        VersionedMessage::V0(ref mut m) => {
+            (&mut m.header, &mut m.account_keys, &mut m.instructions)
+        }
+    };
+    for ix in instructions.iter_mut() {
+        ix.program_id_index = remap(ix.program_id_index);
+        ix.accounts.iter_mut().for_each(|a| *a = remap(*a));
+    }
+    instructions.insert(index, compiled);
+    let count = |r| keys.iter().filter(|k| rank(k) == r).count() as u8;
+    header.num_required_signatures = count(0) + count(1);
+    header.num_readonly_signed_accounts = count(1);
+    header.num_readonly_unsigned_accounts = count(3);
+    *account_keys = order.into_iter().map(|i| keys[i].0).collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::{instruction::AccountMeta, message::Message, system_instruction};
+
+    use super::*;
+
+    /// Return (program id, accounts, data) of each ix, with accounts as (key, signer, writable)
+    fn decompile(message: &VersionedMessage) -> Vec<(Pubkey, Vec<(Pubkey, bool, bool)>, Vec<u8>)> {
+        let keys = static_keys(message);
+        message
+            .instructions()
+            .iter()
+            .map(|ix| {
+                (
+                    keys[ix.program_id_index as usize].0,
+                    ix.accounts.iter().map(|a| keys[*a as usize]).collect(),
+                    ix.data.clone(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn insert_ix_works() {
+        let payer = Pubkey::new_unique();
+        let nonce = Pubkey::new_unique();
+        let ix = Instruction::new_with_bytes(
+            Pubkey::new_unique(),
+            &[1, 2, 3],
+            vec![
+                AccountMeta::new_readonly(payer, true),
+                AccountMeta::new(Pubkey::new_unique(), false),
+                AccountMeta::new_readonly(Pubkey::new_unique(), false),
+            ],
+        );
+        let advance = system_instruction::advance_nonce_account(&nonce, &payer);
+
+        let mut message = VersionedMessage::Legacy(Message::new(&[ix.clone()], Some(&payer)));
+        insert_ix(&mut message, 0, &advance);
+        let expected =
+            VersionedMessage::Legacy(Message::new(&[advance.clone(), ix.clone()], Some(&payer)));
+
+        assert_eq!(decompile(&message), decompile(&expected));
+        assert_eq!(message.header(), expected.header());
+        assert_eq!(message.static_account_keys()[0], payer);
+
+        let tip = system_instruction::transfer(&payer, &Pubkey::new_unique(), 1_000);
+        insert_ix(&mut message, 2, &tip);
+        let expected = VersionedMessage::Legacy(Message::new(&[advance, ix, tip], Some(&payer)));
+        assert_eq!(decompile(&message), decompile(&expected));
+        assert_eq!(message.header(), expected.header());
+    }
+}
@@ -0,0 +1,115 @@
+//! Listener addresses for IPv4, IPv6, and dual-stack hosts
+//!
+//! Hosts are IPv4 or IPv6 literals (optionally bracketed e.g. `[::1]`) or names. Binding the IPv6
+//! unspecified address `::` accepts IPv4 connections too, regardless of the OS default.
+
+use std::{
+    io,
+    net::{IpAddr, SocketAddr, TcpListener, ToSocketAddrs},
+};
+
+use socket2::{Domain, Protocol, Socket, Type};
+
+/// Pending connections queued by a listener
+const LISTEN_BACKLOG: i32 = 1024;
+
+/// Return `host` without brackets e.g. `[::1]` as `::1`
+pub fn unbracket(host: &str) -> &str {
+    host.strip_prefix('[')
+        .and_then(|h| h.strip_suffix(']'))
+        .unwrap_or(host)
+}
+
+/// Format `host` and `port` as an address e.g. `[::1]:8080`, bracketing IPv6 literals
+pub fn host_port(host: &str, port: u16) -> String {
+    let host = unbracket(host);
+    if host.contains(':') {
+        format!("[{host}]:{port}")
+    } else {
+        format!("{host}:{port}")
+    }
+}
+
+/// Resolve `host` and `port` to socket addresses
+pub fn resolve(host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+    (unbracket(host), port)
+        .to_socket_addrs()
+        .map(|addrs| addrs.collect())
+}
+
+/// Bind a non-blocking TCP listener on `addr`, dual-stack if it's `::`
+pub fn bind(addr: SocketAddr) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    if addr.is_ipv6() && addr.ip().is_unspecified() {
+        socket.set_only_v6(false)?;
+    }
+    // as std, allow rebinding while old connections linger in TIME_WAIT
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(LISTEN_BACKLOG)?;
+    socket.set_nonblocking(true)?;
+    Ok(socket.into())
+}
+
+/// Bind a listener on the first address of `host` and `port` that's available
+pub fn bind_host(host: &str, port: u16) -> io::Result<TcpListener> {
+    let mut last_err = io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("no addresses: {}", host_port(host, port)),
+    );
+    for addr in resolve(host, port)? {
+        match bind(addr) {
+            Ok(listener) => return Ok(listener),
+            Err(err) => last_err = err,
+        }
+    }
+    Err(last_err)
+}
+
+/// Return the address of a peer, IPv4 peers of dual-stack listeners as IPv4
+pub fn peer(addr: SocketAddr) -> SocketAddr {
+    SocketAddr::new(addr.ip().to_canonical(), addr.port())
+}
+
+/// Return an IP of request logs, IPv4-mapped IPv6 as IPv4, others unchanged
+pub fn peer_ip(ip: &str) -> String {
+    match unbracket(ip).parse::<IpAddr>() {
+        Ok(ip) => ip.to_canonical().to_string(),
+        Err(_) => ip.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ipv6_hosts() {
+        assert_eq!(host_port("127.0.0.1", 8080), "127.0.0.1:8080");
+        assert_eq!(host_port("::1", 8080), "[::1]:8080");
+        assert_eq!(host_port("[::1]", 8080), "[::1]:8080");
+        assert_eq!(
+            resolve("[::1]", 1337).unwrap(),
+            vec!["[::1]:1337".parse::<SocketAddr>().unwrap()]
+        );
+
+        assert_eq!(
+            peer("[::ffff:192.168.1.5]:4000".parse().unwrap()),
+            "192.168.1.5:4000".parse::<SocketAddr>().unwrap()
+        );
+        assert_eq!(peer_ip("::ffff:10.0.0.1"), "10.0.0.1");
+        assert_eq!(peer_ip("2001:db8::1"), "2001:db8::1");
+        assert_eq!(peer_ip("unknown"), "unknown");
+    }
+
+    #[test]
+    fn dual_stack_listener() {
+        // hosts without IPv6 can't bind `::`
+        let Ok(listener) = bind("[::]:0".parse().unwrap()) else {
+            return;
+        };
+        let port = listener.local_addr().unwrap().port();
+        assert!(std::net::TcpStream::connect(("127.0.0.1", port)).is_ok());
+    }
+}
@@ -0,0 +1,181 @@
+//! Durable nonce tx signing
+//!
+//! Txs are signed against the nonce stored in a nonce account rather than a recent blockhash, so
+//! they remain valid until the nonce is advanced and can be rebroadcast for longer than a
+//! blockhash lives. Each tx advances the nonce of its account in its first ix, so at most one tx
+//! per nonce can land. Accounts are leased to one tx at a time from a pool.
+//! A tx given up before it confirmed could still land later, so its nonce is advanced before the
+//! account is leased again.
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use drift_rs::{
+    event_subscriber::RpcClient,
+    types::{SdkError, VersionedMessage},
+    Pubkey,
+};
+use log::{debug, warn};
+use solana_sdk::{
+    hash::Hash,
+    instruction::Instruction,
+    message::Message,
+    nonce::state::{State, Versions},
+    system_instruction,
+};
+
+use crate::{
+    controller::{ControllerError, GatewayResult},
+    message::insert_ix,
+    signer::Signer,
+    LOG_TARGET,
+};
+
+/// Interval between nonce checks of a retired lease
+const RETIRE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Checks of a retired lease before its account is dropped from the pool
+const MAX_RETIRE_ATTEMPTS: u32 = 15;
+
+/// Pool of nonce accounts of the signer
+pub struct NoncePool {
+    /// nonce authority, the tx signer
+    authority: Pubkey,
+    free: Mutex<VecDeque<Pubkey>>,
+}
+
+impl NoncePool {
+    pub fn new(accounts: Vec<Pubkey>, authority: Pubkey) -> Self {
+        Self {
+            authority,
+            free: Mutex::new(accounts.into()),
+        }
+    }
+
+    /// Lease a nonce account until the lease is dropped, None if all accounts are leased
+    pub fn acquire(self: &Arc<Self>) -> Option<NonceLease> {
+        let account = self.free.lock().expect("lock").pop_front()?;
+        Some(NonceLease {
+            account,
+            pool: Arc::clone(self),
+            discarded: false,
+        })
+    }
+}
+
+/// A nonce account leased to one tx, returned to the pool on drop
+pub struct NonceLease {
+    account: Pubkey,
+    pool: Arc<NoncePool>,
+    /// the nonce couldn't be advanced, the account is not returned
+    discarded: bool,
+}
+
+impl NonceLease {
+    /// Fetch the current nonce of the account
+    pub async fn fetch(&self, rpc: &RpcClient) -> GatewayResult<Hash> {
+        let account = rpc
+            .get_account(&self.account)
+            .await
+            .map_err(SdkError::from)?;
+        let invalid = |reason| ControllerError::BadRequest(format!("{reason}: {}", self.account));
+        let versions: Versions =
+            bincode::deserialize(&account.data).map_err(|_| invalid("not a nonce account"))?;
+        match versions.state() {
+            State::Initialized(data) => Ok(data.blockhash()),
+            State::Uninitialized => Err(invalid("nonce account uninitialized")),
+        }
+    }
+
+    /// Prepend the ix advancing the nonce to `message`, it must be the first ix of the tx
+    pub fn advance(&self, message: &mut VersionedMessage) {
+        insert_ix(message, 0, &self.advance_ix());
+    }
+
+    fn advance_ix(&self) -> Instruction {
+        system_instruction::advance_nonce_account(&self.account, &self.pool.authority)
+    }
+
+    /// Return the lease to the pool once the nonce moved past `used`, the nonce of a tx given up
+    /// before it confirmed
+    ///
+    /// while the nonce is unchanged the tx could still land, it's invalidated by advancing the
+    /// nonce with a tx signed against a recent blockhash. The account is dropped from the pool if
+    /// the nonce doesn't move.
+    pub async fn retire(mut self, rpc: &RpcClient, signer: &dyn Signer, used: Hash) {
+        for _ in 0..MAX_RETIRE_ATTEMPTS {
+            match self.fetch(rpc).await {
+                Ok(nonce) if nonce != used => return,
+                Ok(_) => {
+                    if let Err(err) = self.send_advance(rpc, signer).await {
+                        warn!(target: LOG_TARGET, "nonce advance failed: {}, {err}", self.account);
+                    }
+                }
+                Err(err) => {
+                    warn!(target: LOG_TARGET, "nonce fetch failed: {}, {err:?}", self.account);
+                }
+            }
+            tokio::time::sleep(RETIRE_INTERVAL).await;
+        }
+        warn!(target: LOG_TARGET, "nonce not advanced, account dropped from the pool: {}", self.account);
+        self.discarded = true;
+    }
+
+    /// Send a tx advancing the nonce, signed against a recent blockhash
+    async fn send_advance(&self, rpc: &RpcClient, signer: &dyn Signer) -> Result<(), String> {
+        let payer = signer.pubkey();
+        let message = VersionedMessage::Legacy(Message::new(&[self.advance_ix()], Some(&payer)));
+        let blockhash = rpc
+            .get_latest_blockhash()
+            .await
+            .map_err(|err| err.to_string())?;
+        let tx = signer.sign(message, blockhash).await?;
+        let signature = rpc
+            .send_transaction(&tx)
+            .await
+            .map_err(|err| err.to_string())?;
+        debug!(target: LOG_TARGET, "sent nonce advance: {}, {signature}", self.account);
+        Ok(())
+    }
+}
+
+impl Drop for NonceLease {
+    fn drop(&mut self) {
+        if !self.discarded {
+            self.pool.free.lock().expect("lock").push_back(self.account);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leases_are_exclusive() {
+        let accounts = vec![Pubkey::new_unique(), Pubkey::new_unique()];
+        let pool = Arc::new(NoncePool::new(accounts.clone(), Pubkey::new_unique()));
+        let first = pool.acquire().unwrap();
+        let second = pool.acquire().unwrap();
+        assert!(pool.acquire().is_none());
+        assert_ne!(first.account, second.account);
+
+        drop(first);
+        assert_eq!(pool.acquire().unwrap().account, accounts[0]);
+    }
+
+    #[test]
+    fn discarded_lease_not_returned() {
+        let pool = Arc::new(NoncePool::new(
+            vec![Pubkey::new_unique()],
+            Pubkey::new_unique(),
+        ));
+        let mut lease = pool.acquire().unwrap();
+        lease.discarded = true;
+        drop(lease);
+        assert!(pool.acquire().is_none());
+    }
+}
@@ -0,0 +1,211 @@
+//! Cross-check of drift oracle prices against a secondary price source
+//!
+//! Secondary prices are fetched from Pyth Hermes by price feed id.
+
+use std::collections::HashMap;
+
+use drift_rs::types::MarketId;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+/// Default Pyth Hermes endpoint
+pub const DEFAULT_HERMES_URL: &str = "https://hermes.pyth.network";
+
+/// A market's secondary price feed
+#[derive(Debug, Clone, PartialEq)]
+pub struct SecondaryOracle {
+    pub ticker: String,
+    pub market: MarketId,
+    /// hex encoded Pyth price feed id
+    pub feed_id: String,
+}
+
+impl SecondaryOracle {
+    /// Parse a list of secondary oracles from user command
+    ///
+    /// e.g. `sol-perp=ef0d8b6f..,btc-perp=e62df6c8..`
+    pub fn parse_list(
+        oracles: &str,
+        market_lookup: impl Fn(&str) -> Option<MarketId>,
+    ) -> Result<Vec<Self>, String> {
+        oracles
+            .split(',')
+            .map(|oracle| {
+                let (ticker, feed_id) = oracle
+                    .split_once('=')
+                    .ok_or(format!("expected <market>=<feed id>: {oracle}"))?;
+                let market = market_lookup(ticker).ok_or(format!("invalid market: {ticker}"))?;
+                let feed_id = feed_id.trim_start_matches("0x").to_ascii_lowercase();
+                if feed_id.len() != 64 || !feed_id.chars().all(|c| c.is_ascii_hexdigit()) {
+                    return Err(format!("invalid feed id: {feed_id}"));
+                }
+                Ok(Self {
+                    ticker: ticker.to_string(),
+                    market,
+                    feed_id,
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Deserialize)]
+struct HermesResponse {
+    parsed: Vec<HermesPriceUpdate>,
+}
+
+#[derive(Deserialize)]
+struct HermesPriceUpdate {
+    id: String,
+    price: HermesPrice,
+}
+
+#[derive(Deserialize)]
+struct HermesPrice {
+    price: String,
+    expo: i32,
+    publish_time: i64,
+}
+
+/// A secondary price and its publish time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SecondaryPrice {
+    pub price: Decimal,
+    /// unix timestamp
+    pub publish_time: i64,
+}
+
+impl SecondaryPrice {
+    /// Whether the price was published more than `max_age` secs before `now`
+    pub fn is_stale(&self, now: i64, max_age: i64) -> bool {
+        now.saturating_sub(self.publish_time) > max_age
+    }
+}
+
+/// Pyth Hermes price client
+pub struct Hermes {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl Hermes {
+    pub fn new(url: &str) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Fetch latest prices by feed id
+    pub async fn prices(
+        &self,
+        feed_ids: &[&str],
+    ) -> Result<HashMap<String, SecondaryPrice>, String> {
+        let query: Vec<(&str, &str)> = feed_ids.iter().map(|id| ("ids[]", *id)).collect();
+        let res = self
+            .client
+            .get(format!("{}/v2/updates/price/latest", self.url))
+            .query(&query)
+            .send()
+            .await
+            .and_then(|res| res.error_for_status())
+            .map_err(|err| err.to_string())?;
+        let body = res.text().await.map_err(|err| err.to_string())?;
+        parse_hermes_prices(&body)
+    }
+}
+
+fn parse_hermes_prices(body: &str) -> Result<HashMap<String, SecondaryPrice>, String> {
+    let res: HermesResponse = serde_json::from_str(body).map_err(|err| err.to_string())?;
+    res.parsed
+        .into_iter()
+        .map(|update| {
+            let HermesPrice {
+                price: ref raw,
+                expo,
+                publish_time,
+            } = update.price;
+            let invalid = || format!("invalid price: {raw}e{expo}");
+            let mantissa: i64 = raw.parse().map_err(|_| invalid())?;
+            let price = if expo < 0 {
+                Decimal::try_new(mantissa, expo.unsigned_abs()).map_err(|_| invalid())?
+            } else {
+                10_i64
+                    .checked_pow(expo.unsigned_abs())
+                    .and_then(|scale| Decimal::from(mantissa).checked_mul(Decimal::from(scale)))
+                    .ok_or_else(invalid)?
+            };
+            Ok((
+                update.id.to_ascii_lowercase(),
+                SecondaryPrice {
+                    price: price.normalize(),
+                    publish_time,
+                },
+            ))
+        })
+        .collect()
+}
+
+/// Relative divergence of `price` from `reference`
+pub fn divergence(price: Decimal, reference: Decimal) -> Decimal {
+    if reference.is_zero() {
+        return Decimal::ZERO;
+    }
+    ((price - reference) / reference).abs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOL_FEED: &str = "ef0d8b6fda2ceba41da15d4095d1da392a0d2f8ed0c6c7bc0f4cfac8c280b56d";
+
+    #[test]
+    fn parse_secondary_oracles() {
+        let lookup = |ticker: &str| (ticker == "sol-perp").then_some(MarketId::perp(0));
+        let oracles =
+            SecondaryOracle::parse_list(&format!("sol-perp=0x{SOL_FEED}"), lookup).expect("parses");
+        assert_eq!(oracles[0].market, MarketId::perp(0));
+        assert_eq!(oracles[0].feed_id, SOL_FEED);
+
+        assert!(SecondaryOracle::parse_list(&format!("btc-perp={SOL_FEED}"), lookup).is_err());
+        assert!(SecondaryOracle::parse_list("sol-perp=1234", lookup).is_err());
+    }
+
+    #[test]
+    fn parse_hermes_response() {
+        let body = format!(
+            r#"{{"binary":{{"encoding":"hex","data":[]}},"parsed":[{{"id":"{SOL_FEED}","price":{{"price":"14523000000","conf":"7000000","expo":-8,"publish_time":1704777451}}}}]}}"#
+        );
+        let prices = parse_hermes_prices(&body).expect("parses");
+        assert_eq!(prices[SOL_FEED].price, Decimal::new(14523, 2));
+        assert_eq!(prices[SOL_FEED].publish_time, 1704777451);
+        assert!(!prices[SOL_FEED].is_stale(1704777461, 30));
+        assert!(prices[SOL_FEED].is_stale(1704777491, 30));
+
+        // positive exponents scale up
+        let body = format!(
+            r#"{{"parsed":[{{"id":"{SOL_FEED}","price":{{"price":"145","conf":"1","expo":2,"publish_time":1704777451}}}}]}}"#
+        );
+        let prices = parse_hermes_prices(&body).expect("parses");
+        assert_eq!(prices[SOL_FEED].price, Decimal::from(14_500));
+
+        let body = format!(
+            r#"{{"parsed":[{{"id":"{SOL_FEED}","price":{{"price":"145","conf":"1","expo":40,"publish_time":1704777451}}}}]}}"#
+        );
+        assert!(parse_hermes_prices(&body).is_err());
+    }
+
+    #[test]
+    fn divergence_is_relative() {
+        assert_eq!(
+            divergence(Decimal::from(101), Decimal::from(100)),
+            Decimal::new(1, 2)
+        );
+        assert_eq!(
+            divergence(Decimal::from(99), Decimal::from(100)),
+            Decimal::new(1, 2)
+        );
+        assert_eq!(divergence(Decimal::ONE, Decimal::ZERO), Decimal::ZERO);
+    }
+}
@@ -0,0 +1,632 @@
+//! Order lifecycle tracking
+//!
+//! Orders of journaled sub-accounts are tracked from tx submission if placed via the gateway,
+//! otherwise from their creation event. Submitted orders are matched to their creation event by
+//! user order id, or by placing tx for orders without one. Account events advance an order through
+//! its states until a terminal state (filled, cancelled, expired, rejected, or closed).
+//! Orders of sub-accounts whose events aren't journaled can't be followed, their state is unknown.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::RwLock,
+};
+
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::{
+    types::Market,
+    websocket::{AccountEvent, OrderWithDecimals},
+};
+
+/// Max. orders tracked, the oldest are forgotten first
+const MAX_TRACKED_ORDERS: usize = 10_000;
+
+/// Submitted orders without a creation event for this many secs are considered rejected
+const SUBMITTED_TIMEOUT_SECS: u64 = 60;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub enum OrderState {
+    /// placing tx sent, order not yet seen on-chain
+    Submitted,
+    Accepted,
+    PartiallyFilled,
+    Filled,
+    Cancelled,
+    Expired,
+    /// placing tx failed or the order never appeared on-chain
+    Rejected,
+    /// no longer open, closed while the event subscription was down (cause unknown)
+    Closed,
+    /// not tracked, events of the sub-account aren't journaled
+    Unknown,
+}
+
+impl OrderState {
+    pub fn is_terminal(&self) -> bool {
+        !matches!(
+            self,
+            Self::Submitted | Self::Accepted | Self::PartiallyFilled
+        )
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct StateChange {
+    pub state: OrderState,
+    pub ts: u64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct OrderStatus {
+    pub sub_account_id: u16,
+    /// None until the order is accepted on-chain
+    pub order_id: Option<u32>,
+    /// 0 if unset
+    pub user_order_id: u8,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    pub market: Option<Market>,
+    pub state: OrderState,
+    /// base amount, unsigned
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amount: Option<Decimal>,
+    pub filled: Decimal,
+    /// signature of the placing tx, if placed via this gateway
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tx: Option<String>,
+    /// why the order was rejected
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// state changes, oldest first
+    pub history: Vec<StateChange>,
+}
+
+impl OrderStatus {
+    /// Status of an order of a sub-account whose events aren't journaled
+    pub fn unknown(sub_account_id: u16, order_id: Option<u32>, user_order_id: u8) -> Self {
+        Self {
+            sub_account_id,
+            order_id,
+            user_order_id,
+            market: None,
+            state: OrderState::Unknown,
+            amount: None,
+            filled: Decimal::ZERO,
+            tx: None,
+            reason: None,
+            history: vec![],
+        }
+    }
+
+    fn transition(&mut self, state: OrderState, ts: u64) {
+        if self.state != state {
+            self.state = state;
+            self.history.push(StateChange { state, ts });
+        }
+    }
+
+    /// Set fill progress, advancing the state of open orders
+    fn set_filled(&mut self, filled: Decimal, ts: u64) {
+        self.filled = filled;
+        if self.state.is_terminal() {
+            return;
+        }
+        match self.amount {
+            Some(amount) if filled >= amount => self.transition(OrderState::Filled, ts),
+            _ if filled > Decimal::ZERO => self.transition(OrderState::PartiallyFilled, ts),
+            _ => self.transition(OrderState::Accepted, ts),
+        }
+    }
+
+    fn close(&mut self, state: OrderState, ts: u64) {
+        if !self.state.is_terminal() {
+            self.transition(state, ts);
+        }
+    }
+}
+
+/// An order about to be placed via the gateway
+#[derive(Debug, Clone)]
+pub struct PendingOrder {
+    pub user_order_id: u8,
+    pub market: Market,
+    /// base amount, unsigned
+    pub amount: Decimal,
+}
+
+/// Id of a tracked order, in order of tracking
+type TrackingId = u64;
+
+#[derive(Default)]
+struct TrackerInner {
+    orders: HashMap<TrackingId, OrderStatus>,
+    by_order_id: HashMap<(u16, u32), TrackingId>,
+    /// latest order of each user order id
+    by_user_order_id: HashMap<(u16, u8), TrackingId>,
+    tracked: VecDeque<TrackingId>,
+    /// tracking ids of orders awaiting acceptance, with their submit time
+    submitted: Vec<(TrackingId, u64)>,
+    next_id: TrackingId,
+}
+
+impl TrackerInner {
+    fn track(&mut self, status: OrderStatus) -> TrackingId {
+        if self.tracked.len() >= MAX_TRACKED_ORDERS {
+            if let Some(oldest) = self.tracked.pop_front() {
+                if let Some(old) = self.orders.remove(&oldest) {
+                    if let Some(order_id) = old.order_id {
+                        self.by_order_id.remove(&(old.sub_account_id, order_id));
+                    }
+                    let key = (old.sub_account_id, old.user_order_id);
+                    if self.by_user_order_id.get(&key) == Some(&oldest) {
+                        self.by_user_order_id.remove(&key);
+                    }
+                }
+            }
+        }
+        self.next_id += 1;
+        let id = self.next_id;
+        if status.user_order_id != 0 {
+            self.by_user_order_id
+                .insert((status.sub_account_id, status.user_order_id), id);
+        }
+        if let Some(order_id) = status.order_id {
+            self.by_order_id
+                .insert((status.sub_account_id, order_id), id);
+        }
+        self.orders.insert(id, status);
+        self.tracked.push_back(id);
+        id
+    }
+
+    /// Return the tracked order of an on-chain order, tracking it if new
+    ///
+    /// * `signature` - the order's placing tx, if known
+    fn accept(
+        &mut self,
+        sub_account_id: u16,
+        order: &OrderWithDecimals,
+        signature: Option<&str>,
+        ts: u64,
+    ) -> TrackingId {
+        if let Some(id) = self.by_order_id.get(&(sub_account_id, order.order_id)) {
+            return *id;
+        }
+        // match the earliest submitted order with the same user order id, and placing tx if sent
+        let submitted = self.submitted.iter().position(|(id, _)| {
+            self.orders.get(id).is_some_and(|o| {
+                o.sub_account_id == sub_account_id
+                    && o.user_order_id == order.user_order_id
+                    && match (o.tx.as_deref(), signature) {
+                        (Some(tx), Some(signature)) => tx == signature,
+                        // orders without a user order id are told apart by their tx only
+                        _ => o.user_order_id != 0,
+                    }
+            })
+        });
+        match submitted {
+            Some(idx) => {
+                let (id, _) = self.submitted.remove(idx);
+                self.by_order_id
+                    .insert((sub_account_id, order.order_id), id);
+                let status = self.orders.get_mut(&id).expect("tracked");
+                status.order_id = Some(order.order_id);
+                status.transition(OrderState::Accepted, ts);
+                id
+            }
+            None => self.track(OrderStatus {
+                sub_account_id,
+                order_id: Some(order.order_id),
+                user_order_id: order.user_order_id,
+                market: Some(Market::new(order.market_index, order.market_type)),
+                state: OrderState::Accepted,
+                amount: Some(order.amount),
+                filled: Decimal::ZERO,
+                tx: signature.map(str::to_string),
+                reason: None,
+                history: vec![StateChange {
+                    state: OrderState::Accepted,
+                    ts,
+                }],
+            }),
+        }
+    }
+
+    /// Merge an order tracked from its creation event into the submitted order `id` placed by
+    /// the same tx, for events seen before the tx was recorded as sent
+    fn claim_accepted(&mut self, id: TrackingId) {
+        let Some(status) = self.orders.get(&id) else {
+            return;
+        };
+        let (sub_account_id, user_order_id) = (status.sub_account_id, status.user_order_id);
+        let Some(tx) = status.tx.clone() else {
+            return;
+        };
+        let accepted = self.tracked.iter().copied().find(|other| {
+            *other != id
+                && self.orders.get(other).is_some_and(|o| {
+                    o.sub_account_id == sub_account_id
+                        && o.user_order_id == user_order_id
+                        && o.tx.as_deref() == Some(tx.as_str())
+                        && o.history.first().map(|c| c.state) == Some(OrderState::Accepted)
+                })
+        });
+        let Some(accepted) = accepted else {
+            return;
+        };
+        let other = self.orders.remove(&accepted).expect("tracked");
+        self.tracked.retain(|t| *t != accepted);
+        if user_order_id != 0 {
+            self.by_user_order_id
+                .insert((sub_account_id, user_order_id), id);
+        }
+        if let Some(order_id) = other.order_id {
+            self.by_order_id.insert((sub_account_id, order_id), id);
+        }
+        self.submitted.retain(|(s, _)| *s != id);
+        let status = self.orders.get_mut(&id).expect("tracked");
+        status.order_id = other.order_id;
+        status.amount = other.amount.or(status.amount);
+        status.filled = other.filled;
+        status.state = other.state;
+        status.history.extend(other.history);
+    }
+
+    fn by_order_id_mut(&mut self, sub_account_id: u16, order_id: u32) -> Option<&mut OrderStatus> {
+        let id = self.by_order_id.get(&(sub_account_id, order_id))?;
+        self.orders.get_mut(id)
+    }
+}
+
+/// Lifecycle states of recent orders
+#[derive(Default)]
+pub struct OrderTracker {
+    inner: RwLock<TrackerInner>,
+}
+
+impl OrderTracker {
+    /// Track orders about to be placed, returns their tracking ids
+    ///
+    /// orders without a user order id are matched to their creation event once their tx is sent
+    pub fn submitted(&self, sub_account_id: u16, orders: &[PendingOrder], now: u64) -> Vec<u64> {
+        let mut inner = self.inner.write().expect("lock");
+        orders
+            .iter()
+            .map(|o| {
+                let id = inner.track(OrderStatus {
+                    sub_account_id,
+                    order_id: None,
+                    user_order_id: o.user_order_id,
+                    market: Some(o.market),
+                    state: OrderState::Submitted,
+                    amount: Some(o.amount),
+                    filled: Decimal::ZERO,
+                    tx: None,
+                    reason: None,
+                    history: vec![StateChange {
+                        state: OrderState::Submitted,
+                        ts: now,
+                    }],
+                });
+                inner.submitted.push((id, now));
+                id
+            })
+            .collect()
+    }
+
+    /// Record the placing tx of submitted orders
+    pub fn sent(&self, ids: &[u64], signature: &str) {
+        let mut inner = self.inner.write().expect("lock");
+        for id in ids {
+            if let Some(status) = inner.orders.get_mut(id) {
+                status.tx = Some(signature.to_string());
+            }
+            inner.claim_accepted(*id);
+        }
+    }
+
+    /// Reject submitted orders e.g. the placing tx failed
+    pub fn rejected(&self, ids: &[u64], reason: &str, now: u64) {
+        let mut inner = self.inner.write().expect("lock");
+        inner.submitted.retain(|(id, _)| !ids.contains(id));
+        for id in ids {
+            if let Some(status) = inner.orders.get_mut(id) {
+                status.reason = Some(reason.to_string());
+                status.close(OrderState::Rejected, now);
+            }
+        }
+    }
+
+    /// Advance tracked orders by an account event of `sub_account_id`
+    pub(crate) fn on_event(&self, sub_account_id: u16, event: &AccountEvent, now: u64) {
+        let mut inner = self.inner.write().expect("lock");
+        match event {
+            AccountEvent::OrderCreate {
+                order,
+                ts,
+                signature,
+                ..
+            } => {
+                let signature = (!signature.is_empty()).then_some(signature.as_str());
+                inner.accept(sub_account_id, order, signature, *ts);
+            }
+            AccountEvent::OrderBackfill { order, ts } => {
+                let id = inner.accept(sub_account_id, order, None, *ts);
+                if let Some(status) = inner.orders.get_mut(&id) {
+                    status.set_filled(order.filled, *ts);
+                }
+            }
+            AccountEvent::Fill {
+                order_id,
+                amount,
+                ts,
+                ..
+            } => {
+                if let Some(status) = inner.by_order_id_mut(sub_account_id, *order_id) {
+                    let filled = status.filled + amount;
+                    status.set_filled(filled, *ts);
+                }
+            }
+            AccountEvent::OrderCancel { order_id, ts, .. } => {
+                if let Some(status) = inner.by_order_id_mut(sub_account_id, *order_id) {
+                    status.close(OrderState::Cancelled, *ts);
+                }
+            }
+            AccountEvent::OrderExpire { order_id, ts, .. }
+            | AccountEvent::OrderExpired { order_id, ts, .. } => {
+                if let Some(status) = inner.by_order_id_mut(sub_account_id, *order_id) {
+                    status.close(OrderState::Expired, *ts);
+                }
+            }
+            AccountEvent::OrderClosed { order_id, ts } => {
+                if let Some(status) = inner.by_order_id_mut(sub_account_id, *order_id) {
+                    status.close(OrderState::Closed, *ts);
+                }
+            }
+            _ => (),
+        }
+        Self::expire_submitted(&mut inner, now);
+    }
+
+    /// Reject submitted orders not accepted in time
+    fn expire_submitted(inner: &mut TrackerInner, now: u64) {
+        let (expired, pending): (Vec<_>, Vec<_>) = inner
+            .submitted
+            .drain(..)
+            .partition(|(_, ts)| now.saturating_sub(*ts) >= SUBMITTED_TIMEOUT_SECS);
+        inner.submitted = pending;
+        for (id, _) in expired {
+            if let Some(status) = inner.orders.get_mut(&id) {
+                status.reason = Some(format!("not accepted within {SUBMITTED_TIMEOUT_SECS}s"));
+                status.close(OrderState::Rejected, now);
+            }
+        }
+    }
+
+    /// Return the status of order `order_id`
+    pub fn by_order_id(&self, sub_account_id: u16, order_id: u32, now: u64) -> Option<OrderStatus> {
+        let mut inner = self.inner.write().expect("lock");
+        Self::expire_submitted(&mut inner, now);
+        let id = inner.by_order_id.get(&(sub_account_id, order_id))?;
+        inner.orders.get(id).cloned()
+    }
+
+    /// Return the status of the order with tracking id `id`
+    pub fn by_tracking_id(&self, id: u64, now: u64) -> Option<OrderStatus> {
+        let mut inner = self.inner.write().expect("lock");
+        Self::expire_submitted(&mut inner, now);
+        inner.orders.get(&id).cloned()
+    }
+
+    /// Return the status of the latest order with `user_order_id`
+    pub fn by_user_order_id(
+        &self,
+        sub_account_id: u16,
+        user_order_id: u8,
+        now: u64,
+    ) -> Option<OrderStatus> {
+        let mut inner = self.inner.write().expect("lock");
+        Self::expire_submitted(&mut inner, now);
+        let id = inner
+            .by_user_order_id
+            .get(&(sub_account_id, user_order_id))?;
+        inner.orders.get(id).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use drift_rs::types::Order;
+
+    use super::*;
+
+    fn created(order_id: u32, user_order_id: u8, amount: u64, ts: u64) -> AccountEvent {
+        created_by("", order_id, user_order_id, amount, ts)
+    }
+
+    fn created_by(
+        signature: &str,
+        order_id: u32,
+        user_order_id: u8,
+        amount: u64,
+        ts: u64,
+    ) -> AccountEvent {
+        AccountEvent::OrderCreate {
+            order: OrderWithDecimals::from_order(
+                Order {
+                    order_id,
+                    user_order_id,
+                    base_asset_amount: amount,
+                    ..Default::default()
+                },
+                0,
+            ),
+            ts,
+            signature: signature.to_string(),
+            tx_idx: 0,
+        }
+    }
+
+    fn fill(order_id: u32, amount: i64, ts: u64) -> AccountEvent {
+        AccountEvent::Fill {
+            side: crate::websocket::Side::Buy,
+            fee: Decimal::ZERO,
+            amount: Decimal::from(amount),
+            price: Decimal::ONE,
+            oracle_price: Decimal::ONE,
+            order_id,
+            market_index: 0,
+            market_type: drift_rs::types::MarketType::Perp,
+            ts,
+            tx_idx: 0,
+            signature: String::new(),
+            maker: None,
+            maker_order_id: None,
+            maker_fee: None,
+            taker: None,
+            taker_order_id: None,
+            taker_fee: None,
+        }
+    }
+
+    #[test]
+    fn order_lifecycle() {
+        let tracker = OrderTracker::default();
+        let ids = tracker.submitted(
+            0,
+            &[
+                PendingOrder {
+                    user_order_id: 5,
+                    market: Market::perp(0),
+                    amount: Decimal::from(10),
+                },
+                PendingOrder {
+                    user_order_id: 0,
+                    market: Market::perp(0),
+                    amount: Decimal::from(1),
+                },
+            ],
+            100,
+        );
+        assert_eq!(ids.len(), 2);
+        tracker.sent(&ids, "sig");
+        let status = tracker.by_user_order_id(0, 5, 100).unwrap();
+        assert_eq!(status.state, OrderState::Submitted);
+        assert_eq!(status.tx.as_deref(), Some("sig"));
+
+        tracker.on_event(0, &created(42, 5, 10, 101), 101);
+        tracker.on_event(0, &fill(42, 4, 102), 102);
+        let status = tracker.by_order_id(0, 42, 102).unwrap();
+        assert_eq!(status.state, OrderState::PartiallyFilled);
+        assert_eq!(status.user_order_id, 5);
+        tracker.on_event(0, &fill(42, 6, 103), 103);
+        // terminal states are final
+        tracker.on_event(
+            0,
+            &AccountEvent::OrderCancel {
+                order_id: 42,
+                ts: 104,
+                signature: String::new(),
+                tx_idx: 0,
+            },
+            104,
+        );
+        let status = tracker.by_order_id(0, 42, 104).unwrap();
+        assert_eq!(status.state, OrderState::Filled);
+        assert_eq!(
+            status.history.iter().map(|c| c.state).collect::<Vec<_>>(),
+            vec![
+                OrderState::Submitted,
+                OrderState::Accepted,
+                OrderState::PartiallyFilled,
+                OrderState::Filled
+            ]
+        );
+
+        // orders placed elsewhere are tracked from creation
+        tracker.on_event(1, &created(7, 0, 1, 105), 105);
+        assert_eq!(
+            tracker.by_order_id(1, 7, 105).unwrap().state,
+            OrderState::Accepted
+        );
+        assert!(tracker.by_order_id(0, 7, 105).is_none());
+    }
+
+    #[test]
+    fn order_without_user_order_id_matched_by_tx() {
+        let tracker = OrderTracker::default();
+        let pending = [PendingOrder {
+            user_order_id: 0,
+            market: Market::perp(0),
+            amount: Decimal::from(2),
+        }];
+
+        // created after the tx is sent
+        let ids = tracker.submitted(0, &pending, 100);
+        tracker.sent(&ids, "sig-1");
+        tracker.on_event(0, &created_by("sig-2", 8, 0, 2, 101), 101);
+        assert_eq!(
+            tracker.by_tracking_id(ids[0], 101).unwrap().state,
+            OrderState::Submitted
+        );
+        tracker.on_event(0, &created_by("sig-1", 9, 0, 2, 101), 101);
+        let status = tracker.by_tracking_id(ids[0], 101).unwrap();
+        assert_eq!(status.state, OrderState::Accepted);
+        assert_eq!(status.order_id, Some(9));
+
+        // created before the tx is recorded as sent
+        let ids = tracker.submitted(0, &pending, 110);
+        tracker.on_event(0, &created_by("sig-3", 10, 0, 2, 111), 111);
+        tracker.on_event(0, &fill(10, 2, 112), 112);
+        tracker.sent(&ids, "sig-3");
+        let status = tracker.by_tracking_id(ids[0], 112).unwrap();
+        assert_eq!(status.state, OrderState::Filled);
+        assert_eq!(status.order_id, Some(10));
+        assert_eq!(
+            status.history.iter().map(|c| c.state).collect::<Vec<_>>(),
+            vec![
+                OrderState::Submitted,
+                OrderState::Accepted,
+                OrderState::Filled
+            ]
+        );
+        assert_eq!(tracker.by_order_id(0, 10, 112).unwrap().tx, status.tx);
+        // not rejected once claimed
+        assert_eq!(
+            tracker.by_tracking_id(ids[0], 200).unwrap().state,
+            OrderState::Filled
+        );
+    }
+
+    #[test]
+    fn order_rejected() {
+        let tracker = OrderTracker::default();
+        let pending = [PendingOrder {
+            user_order_id: 1,
+            market: Market::spot(1),
+            amount: Decimal::ONE,
+        }];
+        let ids = tracker.submitted(0, &pending, 100);
+        tracker.rejected(&ids, "insufficient collateral", 100);
+        let status = tracker.by_user_order_id(0, 1, 100).unwrap();
+        assert_eq!(status.state, OrderState::Rejected);
+        assert_eq!(status.reason.as_deref(), Some("insufficient collateral"));
+
+        // never accepted
+        tracker.submitted(0, &pending, 200);
+        assert_eq!(
+            tracker.by_user_order_id(0, 1, 230).unwrap().state,
+            OrderState::Submitted
+        );
+        assert_eq!(
+            tracker.by_user_order_id(0, 1, 260).unwrap().state,
+            OrderState::Rejected
+        );
+    }
+}
@@ -0,0 +1,224 @@
+//! Priority fees sampled per market
+//!
+//! Fees are sampled against the writable market account of each market a tx references,
+//! so congestion in busy markets doesn't inflate fees in quiet ones. Up to
+//! [`MAX_SAMPLED_MARKETS`] markets are sampled, txs of other markets pay the default market's fee.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use drift_rs::{
+    constants::ProgramData,
+    event_subscriber::CommitmentConfig,
+    priority_fee_subscriber::{PriorityFeeSubscriber, PriorityFeeSubscriberConfig},
+    types::{MarketId, MarketType},
+    Pubkey,
+};
+use log::debug;
+
+use crate::{rpc::rpc_client, types::Market, LOG_TARGET};
+
+/// Percentile of sampled fees to pay, unless set otherwise
+const DEFAULT_FEE_PERCENTILE: f32 = 0.9;
+/// How often fee samples are refreshed
+const REFRESH_FREQUENCY: Duration = Duration::from_millis(400 * 10);
+/// Age before a market's samples are used, until then the default market's fee applies
+const WARMUP: Duration = Duration::from_millis(400 * 10 * 2);
+/// Max. markets sampled (each polls the RPC), others pay the default market's fee
+const MAX_SAMPLED_MARKETS: usize = 32;
+
+pub struct PriorityFees {
+    rpc_http: reqwest::Client,
+    endpoint: String,
+    commitment: CommitmentConfig,
+    /// subscribers are not started in emulation mode, only the default market is sampled
+    emulating: bool,
+    /// samples of the default market (SOL-PERP), used for txs without a known market
+    default: Arc<PriorityFeeSubscriber>,
+    /// samples per market and when they started
+    markets: RwLock<HashMap<MarketId, (Instant, Arc<PriorityFeeSubscriber>)>>,
+    settings: RwLock<FeeSettings>,
+}
+
+/// Priority fee settings, reloadable at runtime
+#[derive(Debug, Clone, Copy)]
+struct FeeSettings {
+    /// percentile of sampled fees to pay
+    percentile: f32,
+    /// max. fee to pay, if any
+    max_fee: Option<u64>,
+}
+
+impl PriorityFees {
+    pub fn new(
+        rpc_http: reqwest::Client,
+        endpoint: &str,
+        commitment: CommitmentConfig,
+        program_data: &ProgramData,
+        emulating: bool,
+    ) -> Self {
+        let default_market = MarketId::perp(0);
+        let default = subscriber(
+            &rpc_http,
+            endpoint,
+            commitment,
+            market_account(program_data, default_market).expect("market exists"),
+            emulating,
+        );
+        Self {
+            markets: RwLock::new(HashMap::from([(
+                default_market,
+                (Instant::now(), Arc::clone(&default)),
+            )])),
+            rpc_http,
+            endpoint: endpoint.to_string(),
+            commitment,
+            emulating,
+            default,
+            settings: RwLock::new(FeeSettings {
+                percentile: DEFAULT_FEE_PERCENTILE,
+                max_fee: None,
+            }),
+        }
+    }
+
+    /// Pay the `percentile` of sampled fees
+    pub fn set_percentile(&self, percentile: f32) {
+        self.settings.write().unwrap().percentile = percentile;
+    }
+
+    /// Pay at most `max_fee`, unlimited if unset
+    pub fn set_max_fee(&self, max_fee: Option<u64>) {
+        self.settings.write().unwrap().max_fee = max_fee;
+    }
+
+    /// Start sampling fees of `markets` ahead of their first tx
+    pub fn warm(&self, program_data: &ProgramData, markets: &[MarketId]) {
+        if self.emulating {
+            return;
+        }
+        let now = Instant::now();
+        for market in markets {
+            self.market_fee(program_data, *market, now);
+        }
+    }
+
+    /// Return the priority fee for a tx referencing `markets`
+    ///
+    /// i.e. the highest fee among the markets, or the default market's fee if none are given
+    pub fn priority_fee(
+        &self,
+        program_data: &ProgramData,
+        markets: impl IntoIterator<Item = Market>,
+    ) -> u64 {
+        let now = Instant::now();
+        let fee = markets
+            .into_iter()
+            .map(|m| self.market_fee(program_data, m.as_market_id(), now))
+            .max()
+            .unwrap_or_else(|| self.default_fee());
+        match self.settings.read().unwrap().max_fee {
+            Some(max_fee) => fee.min(max_fee),
+            None => fee,
+        }
+    }
+
+    fn percentile(&self) -> f32 {
+        self.settings.read().unwrap().percentile
+    }
+
+    fn default_fee(&self) -> u64 {
+        self.default.priority_fee_nth(self.percentile())
+    }
+
+    /// Return the fee of `market`, subscribing to its samples on first use
+    fn market_fee(&self, program_data: &ProgramData, market: MarketId, now: Instant) -> u64 {
+        if self.emulating {
+            return self.default_fee();
+        }
+        if let Some((started, subscriber)) = self.markets.read().unwrap().get(&market) {
+            return if is_warm(*started, now) {
+                subscriber.priority_fee_nth(self.percentile())
+            } else {
+                self.default_fee()
+            };
+        }
+
+        let Some(account) = market_account(program_data, market) else {
+            return self.default_fee();
+        };
+        let mut markets = self.markets.write().unwrap();
+        if markets.len() >= MAX_SAMPLED_MARKETS {
+            debug!(target: LOG_TARGET, "priority fee samplers at capacity, default fee: {market:?}");
+            return self.default_fee();
+        }
+        if !markets.contains_key(&market) {
+            debug!(target: LOG_TARGET, "sampling priority fees: {market:?}");
+            let subscriber = subscriber(
+                &self.rpc_http,
+                &self.endpoint,
+                self.commitment,
+                account,
+                self.emulating,
+            );
+            markets.insert(market, (now, subscriber));
+        }
+        self.default_fee()
+    }
+}
+
+/// Return a fee subscriber sampling txs writing to `account`, started unless `emulating`
+fn subscriber(
+    rpc_http: &reqwest::Client,
+    endpoint: &str,
+    commitment: CommitmentConfig,
+    account: Pubkey,
+    emulating: bool,
+) -> Arc<PriorityFeeSubscriber> {
+    let subscriber = PriorityFeeSubscriber::with_config(
+        rpc_client(rpc_http, endpoint.into(), commitment),
+        &[account],
+        PriorityFeeSubscriberConfig {
+            refresh_frequency: Some(REFRESH_FREQUENCY),
+            window: None,
+        },
+    );
+    if emulating {
+        Arc::new(subscriber)
+    } else {
+        subscriber.subscribe()
+    }
+}
+
+/// Return the writable market account of `market`
+fn market_account(program_data: &ProgramData, market: MarketId) -> Option<Pubkey> {
+    match market.kind() {
+        MarketType::Perp => program_data
+            .perp_market_config_by_index(market.index())
+            .map(|m| m.pubkey),
+        MarketType::Spot => program_data
+            .spot_market_config_by_index(market.index())
+            .map(|m| m.pubkey),
+    }
+}
+
+/// Whether samples started at `started` cover enough of the fee window to use
+fn is_warm(started: Instant, now: Instant) -> bool {
+    now.saturating_duration_since(started) >= WARMUP
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_warm_works() {
+        let started = Instant::now();
+        assert!(!is_warm(started, started));
+        assert!(!is_warm(started, started + WARMUP / 2));
+        assert!(is_warm(started, started + WARMUP));
+    }
+}
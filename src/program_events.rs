@@ -0,0 +1,98 @@
+//! Events of all users of the drift program, from one log subscription shared by all subscribers
+//!
+//! Every fill mentions the program, so public trades are read from a program-wide subscription.
+//! It's started by the first subscriber and closed once the last one is gone.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use drift_rs::{
+    constants::PROGRAM_ID,
+    event_subscriber::{DriftEvent, EventSubscriber, PubsubClient},
+};
+use futures_util::StreamExt;
+use log::{info, warn};
+use tokio::sync::broadcast;
+
+use crate::{backoff::Backoff, LOG_TARGET};
+
+const MIN_RESUBSCRIBE_DELAY: Duration = Duration::from_secs(1);
+const MAX_RESUBSCRIBE_DELAY: Duration = Duration::from_secs(30);
+
+/// Events buffered per subscriber, slower subscribers miss events
+const EVENTS_CAPACITY: usize = 1_024;
+
+/// Program events fanned out to subscribers
+pub struct ProgramEvents {
+    ws_client: Arc<PubsubClient>,
+    events: broadcast::Sender<Arc<DriftEvent>>,
+    /// true while the program subscription is running
+    running: Mutex<bool>,
+}
+
+impl ProgramEvents {
+    pub fn new(ws_client: Arc<PubsubClient>) -> Self {
+        Self {
+            ws_client,
+            events: broadcast::Sender::new(EVENTS_CAPACITY),
+            running: Mutex::new(false),
+        }
+    }
+
+    /// Subscribe to program events, starting the program subscription if not running
+    pub fn subscribe(self: &Arc<Self>) -> broadcast::Receiver<Arc<DriftEvent>> {
+        let mut running = self.running.lock().expect("lock");
+        let events = self.events.subscribe();
+        if !*running {
+            *running = true;
+            self.start();
+        }
+        events
+    }
+
+    /// Return true if the subscription should stop, all subscribers are gone
+    fn stop_if_unused(&self) -> bool {
+        let mut running = self.running.lock().expect("lock");
+        if self.events.receiver_count() == 0 {
+            *running = false;
+        }
+        !*running
+    }
+
+    /// Forward program events to subscribers, resubscribing with backoff until none are left
+    fn start(self: &Arc<Self>) {
+        let program_events = Arc::clone(self);
+        tokio::spawn(async move {
+            info!(target: LOG_TARGET, "subscribing to program events");
+            let mut backoff = Backoff::new(MIN_RESUBSCRIBE_DELAY, MAX_RESUBSCRIBE_DELAY, u32::MAX);
+            loop {
+                match EventSubscriber::subscribe(Arc::clone(&program_events.ws_client), PROGRAM_ID)
+                    .await
+                {
+                    Ok(mut stream) => {
+                        backoff.reset();
+                        while let Some(event) = stream.next().await {
+                            if program_events.events.send(Arc::new(event)).is_err()
+                                && program_events.stop_if_unused()
+                            {
+                                info!(target: LOG_TARGET, "unsubscribed from program events");
+                                return;
+                            }
+                        }
+                        warn!(target: LOG_TARGET, "program event stream ended");
+                    }
+                    Err(err) => {
+                        warn!(target: LOG_TARGET, "failed subscribing program events: {err:?}");
+                    }
+                }
+                if program_events.stop_if_unused() {
+                    return;
+                }
+                let delay = backoff.next_delay().unwrap_or(MAX_RESUBSCRIBE_DELAY);
+                tokio::time::sleep(delay).await;
+            }
+        });
+    }
+}
@@ -0,0 +1,222 @@
+//! Reconcile client order/position state with on-chain state
+
+use std::collections::BTreeMap;
+
+use drift_rs::types::MarketType;
+use rust_decimal::Decimal;
+
+use crate::types::{
+    Discrepancy, GetPositionsResponse, Market, Order, ReconcileOrder, ReconcilePosition,
+};
+
+/// Return differences between the client's believed open orders and on-chain open orders
+pub fn reconcile_orders(expected: Vec<ReconcileOrder>, actual: Vec<Order>) -> Vec<Discrepancy> {
+    let mut unmatched: Vec<Option<Order>> = actual.into_iter().map(Some).collect();
+    let mut discrepancies = Vec::new();
+
+    for order in expected {
+        let matched = unmatched.iter_mut().find(|o| {
+            o.as_ref()
+                .is_some_and(|o| match (order.order_id, order.user_order_id) {
+                    (Some(order_id), _) => o.order_id == order_id,
+                    (None, Some(user_order_id)) => {
+                        user_order_id != 0
+                            && o.user_order_id == user_order_id
+                            && o.market_index == order.market.market_index
+                            && o.market_type == order.market.market_type
+                    }
+                    (None, None) => false,
+                })
+        });
+        let Some(onchain) = matched.and_then(Option::take) else {
+            discrepancies.push(Discrepancy::MissingOrder {
+                market: order.market,
+                order_id: order.order_id,
+                user_order_id: order.user_order_id,
+            });
+            continue;
+        };
+
+        for (field, expected, actual) in [
+            ("amount", order.amount, onchain.amount),
+            ("price", order.price, onchain.price),
+            ("filled", order.filled, onchain.filled),
+        ] {
+            if let Some(expected) = expected {
+                if expected != actual {
+                    discrepancies.push(Discrepancy::OrderMismatch {
+                        order_id: onchain.order_id,
+                        field,
+                        expected,
+                        actual,
+                    });
+                }
+            }
+        }
+    }
+
+    discrepancies.extend(
+        unmatched
+            .into_iter()
+            .flatten()
+            .map(|order| Discrepancy::UnknownOrder { order }),
+    );
+
+    discrepancies
+}
+
+/// Return differences between the client's believed positions and on-chain positions
+///
+/// markets missing from either side are treated as a zero position
+pub fn reconcile_positions(
+    expected: Vec<ReconcilePosition>,
+    actual: GetPositionsResponse,
+) -> Vec<Discrepancy> {
+    // (market index, is perp) => (expected, actual)
+    let mut positions = BTreeMap::<(u16, bool), (Decimal, Decimal)>::new();
+    for p in expected {
+        let is_perp = p.market.market_type == MarketType::Perp;
+        positions
+            .entry((p.market.market_index, is_perp))
+            .or_default()
+            .0 += p.amount;
+    }
+    for p in actual.perp {
+        positions.entry((p.market_index, true)).or_default().1 += p.amount;
+    }
+    for p in actual.spot {
+        let amount = if p.balance_type == "borrow" {
+            -p.amount
+        } else {
+            p.amount
+        };
+        positions.entry((p.market_index, false)).or_default().1 += amount;
+    }
+
+    positions
+        .into_iter()
+        .filter(|(_, (expected, actual))| expected != actual)
+        .map(
+            |((market_index, is_perp), (expected, actual))| Discrepancy::PositionMismatch {
+                market: if is_perp {
+                    Market::perp(market_index)
+                } else {
+                    Market::spot(market_index)
+                },
+                expected: expected.normalize(),
+                actual: actual.normalize(),
+            },
+        )
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use drift_rs::types::{self as sdk_types, OrderStatus};
+
+    use super::*;
+
+    fn onchain_order(order_id: u32, user_order_id: u8) -> Order {
+        Order::from_sdk_order(
+            sdk_types::Order {
+                order_id,
+                user_order_id,
+                market_index: 0,
+                market_type: MarketType::Perp,
+                base_asset_amount: 1_000_000_000,
+                price: 100_000_000,
+                status: OrderStatus::Open,
+                ..Default::default()
+            },
+            9,
+        )
+    }
+
+    fn client_order(order_id: Option<u32>, user_order_id: Option<u8>) -> ReconcileOrder {
+        ReconcileOrder {
+            market: Market::perp(0),
+            order_id,
+            user_order_id,
+            amount: Some(Decimal::ONE),
+            price: Some(Decimal::from(100)),
+            filled: None,
+        }
+    }
+
+    #[test]
+    fn reconcile_orders_works() {
+        let discrepancies = reconcile_orders(
+            vec![
+                // matches
+                client_order(Some(1), None),
+                // matches by user order id, price differs
+                ReconcileOrder {
+                    price: Some(Decimal::from(101)),
+                    ..client_order(None, Some(5))
+                },
+                // not open on-chain
+                client_order(Some(3), None),
+            ],
+            vec![
+                onchain_order(1, 0),
+                onchain_order(2, 5),
+                onchain_order(4, 0),
+            ],
+        );
+
+        assert_eq!(discrepancies.len(), 3);
+        assert!(matches!(
+            discrepancies[0],
+            Discrepancy::OrderMismatch {
+                order_id: 2,
+                field: "price",
+                ..
+            }
+        ));
+        assert!(matches!(
+            discrepancies[1],
+            Discrepancy::MissingOrder {
+                order_id: Some(3),
+                ..
+            }
+        ));
+        assert!(matches!(
+            discrepancies[2],
+            Discrepancy::UnknownOrder { ref order } if order.order_id == 4
+        ));
+    }
+
+    #[test]
+    fn reconcile_positions_works() {
+        let actual = GetPositionsResponse {
+            spot: vec![],
+            perp: vec![sdk_types::PerpPosition {
+                market_index: 0,
+                base_asset_amount: 2_000_000_000,
+                ..Default::default()
+            }
+            .into()],
+            sub_account_name: None,
+        };
+        let discrepancies = reconcile_positions(
+            vec![
+                ReconcilePosition {
+                    market: Market::perp(0),
+                    amount: Decimal::ONE,
+                },
+                ReconcilePosition {
+                    market: Market::spot(1),
+                    amount: Decimal::ZERO,
+                },
+            ],
+            actual,
+        );
+
+        assert_eq!(discrepancies.len(), 1);
+        assert!(matches!(
+            discrepancies[0],
+            Discrepancy::PositionMismatch { expected, actual, .. }
+                if expected == Decimal::ONE && actual == Decimal::TWO
+        ));
+    }
+}
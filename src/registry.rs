@@ -0,0 +1,142 @@
+//! Entries of background order features (DCA plans, triggers, TWAP algos) by id
+
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, MutexGuard,
+    },
+};
+
+/// An entry of a [`Registry`]
+pub trait Entry: Clone {
+    fn id(&self) -> u64;
+    /// True until the entry is completed or cancelled
+    fn is_active(&self) -> bool;
+    /// Cancel the (active) entry
+    fn cancel(&mut self);
+}
+
+/// Entries by id, with a cap on active entries
+pub struct Registry<T> {
+    /// name of entries in errors e.g. "DCA plans"
+    name: &'static str,
+    max_active: usize,
+    next_id: AtomicU64,
+    entries: Mutex<BTreeMap<u64, T>>,
+}
+
+impl<T: Entry> Registry<T> {
+    pub fn new(name: &'static str, max_active: usize) -> Self {
+        Self::with_entries(name, max_active, Vec::default())
+    }
+
+    /// Registry of restored `entries`, new ids continue after theirs
+    pub fn with_entries(name: &'static str, max_active: usize, entries: Vec<T>) -> Self {
+        let next_id = entries.iter().map(|e| e.id()).max().unwrap_or_default() + 1;
+        Self {
+            name,
+            max_active,
+            next_id: AtomicU64::new(next_id),
+            entries: Mutex::new(entries.into_iter().map(|e| (e.id(), e)).collect()),
+        }
+    }
+
+    /// Add the entry built from a new id, unless too many are active
+    pub fn add(&self, build: impl FnOnce(u64) -> T) -> Result<T, String> {
+        let mut entries = self.lock();
+        if entries.values().filter(|e| e.is_active()).count() >= self.max_active {
+            return Err(format!(
+                "too many active {} (max. {})",
+                self.name, self.max_active
+            ));
+        }
+        let entry = build(self.next_id.fetch_add(1, Ordering::Relaxed));
+        entries.insert(entry.id(), entry.clone());
+
+        Ok(entry)
+    }
+
+    pub fn get(&self, id: u64) -> Option<T> {
+        self.lock().get(&id).cloned()
+    }
+
+    pub fn list(&self) -> Vec<T> {
+        self.lock().values().cloned().collect()
+    }
+
+    pub fn active(&self) -> Vec<T> {
+        self.lock()
+            .values()
+            .filter(|e| e.is_active())
+            .cloned()
+            .collect()
+    }
+
+    /// Cancel an active entry, returning it
+    pub fn cancel(&self, id: u64) -> Option<T> {
+        self.update(id, |entry| {
+            if entry.is_active() {
+                entry.cancel();
+            }
+            entry.clone()
+        })
+    }
+
+    /// Apply `f` to entry `id`, None if unknown
+    pub fn update<R>(&self, id: u64, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+        self.lock().get_mut(&id).map(f)
+    }
+
+    /// Lock all entries e.g. to update them in one pass
+    pub fn lock(&self) -> MutexGuard<'_, BTreeMap<u64, T>> {
+        self.entries.lock().expect("lock")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone)]
+    struct Job {
+        id: u64,
+        active: bool,
+    }
+
+    impl Entry for Job {
+        fn id(&self) -> u64 {
+            self.id
+        }
+        fn is_active(&self) -> bool {
+            self.active
+        }
+        fn cancel(&mut self) {
+            self.active = false;
+        }
+    }
+
+    #[test]
+    fn registry_caps_active_entries() {
+        let registry = Registry::with_entries(
+            "jobs",
+            2,
+            vec![Job {
+                id: 5,
+                active: true,
+            }],
+        );
+        let job = registry.add(|id| Job { id, active: true }).unwrap();
+        assert_eq!(job.id, 6);
+        assert_eq!(
+            registry.add(|id| Job { id, active: true }).err().as_deref(),
+            Some("too many active jobs (max. 2)")
+        );
+
+        assert!(registry.cancel(5).is_some_and(|job| !job.active));
+        assert!(registry.cancel(1).is_none());
+        assert_eq!(registry.active().len(), 1);
+        assert_eq!(registry.add(|id| Job { id, active: true }).unwrap().id, 7);
+        assert_eq!(registry.list().len(), 3);
+    }
+}
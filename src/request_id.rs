@@ -0,0 +1,156 @@
+//! Request ids correlating the responses, log lines, and txs of one HTTP/Ws request
+//!
+//! The id of the request being handled is kept task-local, log lines written within the request
+//! (and tasks spawned with [`inherit`]) are tagged with it.
+
+use std::{
+    future::Future,
+    io::Write,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+use env_logger::fmt::Formatter;
+use log::Record;
+use serde::Serialize;
+use tokio::task::futures::TaskLocalFuture;
+
+/// Header carrying the request id, a valid client provided id is used as is
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Max. length of client provided ids
+const MAX_CLIENT_ID_LEN: usize = 64;
+
+tokio::task_local! {
+    static REQUEST_ID: RequestId;
+}
+
+/// Sequence of generated ids
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct RequestId(String);
+
+impl RequestId {
+    /// Generate an id, unique across gateway restarts
+    pub fn generate() -> Self {
+        static STARTED: std::sync::OnceLock<u64> = std::sync::OnceLock::new();
+        let started = STARTED.get_or_init(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default()
+        });
+        Self(format!(
+            "{started:x}-{:06}",
+            NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        ))
+    }
+
+    /// Return the client provided id of the `X-Request-Id` header, or a generated one if missing/invalid
+    pub fn from_header(value: Option<&HeaderValue>) -> Self {
+        Self::from_client(value.and_then(|v| v.to_str().ok()))
+    }
+
+    /// Return the client provided id e.g. `requestId` of a Ws request, or a generated one if missing/invalid
+    pub fn from_client(value: Option<&str>) -> Self {
+        value
+            .filter(|v| {
+                !v.is_empty()
+                    && v.len() <= MAX_CLIENT_ID_LEN
+                    && v.chars().all(|c| c.is_ascii_graphic())
+            })
+            .map(|v| Self(v.to_string()))
+            .unwrap_or_else(Self::generate)
+    }
+
+    /// Return the id of the request being handled by the current task, if any
+    pub fn current() -> Option<Self> {
+        REQUEST_ID.try_with(Clone::clone).ok()
+    }
+
+    /// Run `fut` as part of this request
+    pub fn scope<F: Future>(self, fut: F) -> TaskLocalFuture<RequestId, F> {
+        REQUEST_ID.scope(self, fut)
+    }
+
+    /// Set the request id header of a response
+    pub fn tag(&self, headers: &mut HeaderMap) {
+        if let Ok(value) = HeaderValue::from_str(&self.0) {
+            headers.insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for RequestId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Run `fut` as part of the current request, if any, e.g. to tag logs of a spawned task
+pub fn inherit<F: Future>(fut: F) -> impl Future<Output = F::Output> {
+    let request_id = RequestId::current();
+    async move {
+        match request_id {
+            Some(request_id) => request_id.scope(fut).await,
+            None => fut.await,
+        }
+    }
+}
+
+/// Log format, the default env_logger format with the request id if any
+pub fn format_log(buf: &mut Formatter, record: &Record) -> std::io::Result<()> {
+    let ts = buf.timestamp();
+    match RequestId::current() {
+        Some(request_id) => writeln!(
+            buf,
+            "[{ts} {:<5} {} {request_id}] {}",
+            record.level(),
+            record.target(),
+            record.args()
+        ),
+        None => writeln!(
+            buf,
+            "[{ts} {:<5} {}] {}",
+            record.level(),
+            record.target(),
+            record.args()
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_header_works() {
+        let id = RequestId::from_header(Some(&HeaderValue::from_static("my-order-1")));
+        assert_eq!(id.as_str(), "my-order-1");
+
+        let generated = RequestId::from_header(Some(&HeaderValue::from_static("has space")));
+        assert_ne!(generated.as_str(), "has space");
+        assert_ne!(RequestId::from_header(None), RequestId::from_header(None));
+        assert_eq!(RequestId::from_client(Some("sub-1")).as_str(), "sub-1");
+        assert_ne!(RequestId::from_client(Some("")).as_str(), "");
+    }
+
+    #[actix_web::test]
+    async fn scope_works() {
+        assert!(RequestId::current().is_none());
+        let id = RequestId::generate();
+        let inherited = id
+            .clone()
+            .scope(async { tokio::spawn(inherit(async { RequestId::current() })).await })
+            .await
+            .unwrap();
+        assert_eq!(inherited, Some(id));
+    }
+}
@@ -0,0 +1,127 @@
+//! Daily rollups kept in memory and optionally persisted to the embedded db
+//!
+//! Shared by API usage and fee spend accounting. Changed rollups are written on `flush`, and
+//! restored from the store on startup.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    hash::Hash,
+    sync::Mutex,
+};
+
+use log::warn;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::LOG_TARGET;
+
+pub const SECS_PER_DAY: u64 = 86_400;
+
+/// Return the start of the UTC day of unix timestamp `ts`
+pub fn day_of(ts: u64) -> u64 {
+    ts - ts % SECS_PER_DAY
+}
+
+/// Totals of one UTC day, keyed by the day and what they're accounted to
+pub trait Rollup: Serialize + DeserializeOwned + Clone {
+    type Key: Ord + Hash + Clone;
+    /// name of the rollups in logs
+    const NAME: &'static str;
+
+    /// start of the day, unix secs
+    fn day(&self) -> u64;
+    fn key(&self) -> Self::Key;
+    /// key of the rollup in the store
+    fn store_key(&self) -> Vec<u8>;
+}
+
+struct RollupsInner<T: Rollup> {
+    days: BTreeMap<T::Key, T>,
+    /// rollups changed since the last flush
+    dirty: HashSet<T::Key>,
+}
+
+/// Daily rollups by key, ordered by day first
+pub struct DailyRollups<T: Rollup> {
+    inner: Mutex<RollupsInner<T>>,
+    store: Option<sled::Tree>,
+}
+
+impl<T: Rollup> Default for DailyRollups<T> {
+    fn default() -> Self {
+        Self {
+            inner: Mutex::new(RollupsInner {
+                days: BTreeMap::new(),
+                dirty: HashSet::new(),
+            }),
+            store: None,
+        }
+    }
+}
+
+impl<T: Rollup> DailyRollups<T> {
+    /// Persist rollups to `store`, continuing from those already stored
+    pub fn with_store(store: sled::Tree) -> Self {
+        let days = store
+            .iter()
+            .values()
+            .filter_map(|value| serde_json::from_slice::<T>(&value.ok()?).ok())
+            .map(|rollup| (rollup.key(), rollup))
+            .collect();
+        Self {
+            inner: Mutex::new(RollupsInner {
+                days,
+                dirty: HashSet::new(),
+            }),
+            store: Some(store),
+        }
+    }
+
+    /// Apply `f` to the rollup of `key`, created by `init` if new
+    pub fn update(&self, key: T::Key, init: impl FnOnce() -> T, f: impl FnOnce(&mut T)) {
+        let mut inner = self.inner.lock().expect("lock");
+        f(inner.days.entry(key.clone()).or_insert_with(init));
+        inner.dirty.insert(key);
+    }
+
+    /// Write rollups changed since the last flush to the store, if any
+    pub fn flush(&self) {
+        let Some(ref store) = self.store else {
+            return;
+        };
+        let changed: Vec<T> = {
+            let mut inner = self.inner.lock().expect("lock");
+            let dirty = std::mem::take(&mut inner.dirty);
+            dirty
+                .iter()
+                .filter_map(|key| inner.days.get(key).cloned())
+                .collect()
+        };
+        for rollup in changed {
+            let res = serde_json::to_vec(&rollup)
+                .map_err(|err| err.to_string())
+                .and_then(|value| {
+                    store
+                        .insert(rollup.store_key(), value)
+                        .map(|_| ())
+                        .map_err(|err| err.to_string())
+                });
+            if let Err(err) = res {
+                warn!(target: LOG_TARGET, "couldn't store {}: {err}", T::NAME);
+            }
+        }
+    }
+
+    /// Return rollups between unix timestamps `from` and `to` (inclusive) accepted by `filter`,
+    /// oldest first
+    pub fn query(&self, from: u64, to: u64, filter: impl Fn(&T) -> bool) -> Vec<T> {
+        let from = day_of(from);
+        self.inner
+            .lock()
+            .expect("lock")
+            .days
+            .values()
+            .filter(|r| r.day() >= from && r.day() <= to && filter(r))
+            .cloned()
+            .collect()
+    }
+}
@@ -0,0 +1,240 @@
+//! Pooled HTTP transport shared by the gateway's solana RPC clients
+//!
+//! Signed txs are serialized once and the encoding reused for every RPC they are (re)sent to.
+//! RPC clients may be rate limited, each client (e.g. the query and tx lanes) by its own limit.
+
+use std::{
+    cell::RefCell,
+    str::FromStr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use drift_rs::{
+    event_subscriber::{CommitmentConfig, RpcClient},
+    types::RpcSendTransactionConfig,
+};
+use serde_json::json;
+use solana_client::{
+    client_error::{ClientErrorKind, Result as ClientResult},
+    http_sender::HttpSender,
+    rpc_client::RpcClientConfig,
+    rpc_request::RpcRequest,
+    rpc_sender::{RpcSender, RpcTransportStats},
+};
+use solana_sdk::{
+    packet::PACKET_DATA_SIZE, signature::Signature, transaction::VersionedTransaction,
+};
+use solana_transaction_status::UiTransactionEncoding;
+
+/// Request timeout of RPC HTTP calls, same as the solana client default
+const RPC_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// RPC HTTP client options
+#[derive(Debug, Clone)]
+pub struct RpcHttpConfig {
+    /// max. idle connections kept open per RPC host
+    pub pool_max_idle_per_host: usize,
+    /// TCP keepalive interval of pooled connections
+    pub tcp_keepalive: Option<Duration>,
+    /// use HTTP/2 without negotiation, the RPC must support it
+    pub http2_only: bool,
+    /// max. requests per second to the primary RPC, unlimited if unset
+    pub rate_limit: Option<u32>,
+}
+
+impl Default for RpcHttpConfig {
+    fn default() -> Self {
+        Self {
+            pool_max_idle_per_host: 64,
+            tcp_keepalive: Some(Duration::from_secs(30)),
+            http2_only: false,
+            rate_limit: None,
+        }
+    }
+}
+
+impl RpcHttpConfig {
+    /// Build a pooled HTTP client, clones share the connection pool
+    pub fn http_client(&self) -> reqwest::Client {
+        let builder = reqwest::Client::builder()
+            .timeout(RPC_REQUEST_TIMEOUT)
+            .pool_max_idle_per_host(self.pool_max_idle_per_host)
+            .tcp_keepalive(self.tcp_keepalive)
+            .tcp_nodelay(true);
+        let builder = if self.http2_only {
+            builder.http2_prior_knowledge()
+        } else {
+            builder
+        };
+        builder.build().expect("valid RPC http client")
+    }
+}
+
+thread_local! {
+    /// tx serialization buffer, reused across txs
+    static TX_BUFFER: RefCell<Vec<u8>> = RefCell::new(Vec::with_capacity(PACKET_DATA_SIZE));
+}
+
+/// A signed tx in its wire encoding
+pub struct EncodedTx {
+    signature: Signature,
+    /// base64 serialized tx
+    encoded: String,
+}
+
+impl EncodedTx {
+    pub fn new(tx: &VersionedTransaction) -> Self {
+        let encoded = TX_BUFFER.with_borrow_mut(|buf| {
+            buf.clear();
+            bincode::serialize_into(&mut *buf, tx).expect("serializable tx");
+            BASE64.encode(buf.as_slice())
+        });
+        Self {
+            signature: tx.signatures.first().copied().unwrap_or_default(),
+            encoded,
+        }
+    }
+
+    pub fn signature(&self) -> &Signature {
+        &self.signature
+    }
+}
+
+/// Send an encoded tx via `rpc`, as `RpcClient::send_transaction_with_config`
+pub async fn send_encoded_tx(
+    rpc: &RpcClient,
+    tx: &EncodedTx,
+    config: RpcSendTransactionConfig,
+) -> ClientResult<Signature> {
+    let config = RpcSendTransactionConfig {
+        encoding: Some(UiTransactionEncoding::Base64),
+        preflight_commitment: Some(
+            config
+                .preflight_commitment
+                .unwrap_or(rpc.commitment().commitment),
+        ),
+        ..config
+    };
+    let signature: String = rpc
+        .send(RpcRequest::SendTransaction, json!([tx.encoded, config]))
+        .await?;
+    let signature = Signature::from_str(&signature)
+        .map_err(|err| ClientErrorKind::Custom(format!("invalid signature from RPC: {err}")))?;
+    // the RPC may have decoded a different tx
+    if signature != tx.signature {
+        return Err(ClientErrorKind::Custom(format!(
+            "RPC returned signature {signature} != {}",
+            tx.signature
+        ))
+        .into());
+    }
+    Ok(signature)
+}
+
+/// Return an RPC client for `url` using the pooled `http_client`
+pub fn rpc_client(
+    http_client: &reqwest::Client,
+    url: String,
+    commitment: CommitmentConfig,
+) -> RpcClient {
+    RpcClient::new_sender(
+        HttpSender::new_with_client(url, http_client.clone()),
+        RpcClientConfig::with_commitment(commitment),
+    )
+}
+
+/// Return an RPC client for `url` using the pooled `http_client`, sending up to `rate` requests per second
+///
+/// requests over the rate wait their turn, bursts of up to a second's worth are sent immediately
+pub fn rate_limited_rpc_client(
+    http_client: &reqwest::Client,
+    url: String,
+    commitment: CommitmentConfig,
+    rate: u32,
+) -> RpcClient {
+    RpcClient::new_sender(
+        RateLimitedSender {
+            inner: HttpSender::new_with_client(url, http_client.clone()),
+            limit: RateLimit::new(rate),
+        },
+        RpcClientConfig::with_commitment(commitment),
+    )
+}
+
+/// Request rate limit (GCRA)
+struct RateLimit {
+    /// time between requests at the sustained rate
+    interval: Duration,
+    /// burst allowance
+    burst: Duration,
+    /// theoretical arrival time of the next request
+    tat: Mutex<Option<Instant>>,
+}
+
+impl RateLimit {
+    fn new(rate: u32) -> Self {
+        let interval = Duration::from_secs(1) / rate.max(1);
+        Self {
+            interval,
+            burst: Duration::from_secs(1).saturating_sub(interval),
+            tat: Mutex::default(),
+        }
+    }
+
+    /// Reserve a request at `now`, returning the time to wait before sending it
+    fn reserve(&self, now: Instant) -> Duration {
+        let mut tat = self.tat.lock().expect("lock");
+        let next = tat.map_or(now, |tat| tat.max(now));
+        *tat = Some(next + self.interval);
+        next.saturating_duration_since(now + self.burst)
+    }
+}
+
+/// HTTP transport with a rate limit of its own
+struct RateLimitedSender {
+    inner: HttpSender,
+    limit: RateLimit,
+}
+
+#[async_trait]
+impl RpcSender for RateLimitedSender {
+    async fn send(
+        &self,
+        request: RpcRequest,
+        params: serde_json::Value,
+    ) -> ClientResult<serde_json::Value> {
+        let delay = self.limit.reserve(Instant::now());
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+        self.inner.send(request, params).await
+    }
+
+    fn get_transport_stats(&self) -> RpcTransportStats {
+        self.inner.get_transport_stats()
+    }
+
+    fn url(&self) -> String {
+        self.inner.url()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_limit_allows_bursts() {
+        let limit = RateLimit::new(4);
+        let now = Instant::now();
+        for _ in 0..4 {
+            assert!(limit.reserve(now).is_zero());
+        }
+        assert_eq!(limit.reserve(now), Duration::from_millis(250));
+        // recovers at the sustained rate
+        assert!(limit.reserve(now + Duration::from_millis(500)).is_zero());
+    }
+}
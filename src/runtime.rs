@@ -0,0 +1,101 @@
+//! Runtime threads and CPU pinning
+//!
+//! Background tasks run on the main thread and HTTP requests on `--http-workers` threads. With
+//! `--ws-threads` the Ws event server gets a runtime of its own, so event fan-out to many clients
+//! can't delay order requests. With `--cpu-affinity` the gateway's threads are pinned round-robin
+//! to the given cores, leaving the others to e.g. a co-located trading bot.
+
+use std::{
+    cell::Cell,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use core_affinity::CoreId;
+use log::{debug, warn};
+use tokio::runtime::Runtime;
+
+use crate::LOG_TARGET;
+
+thread_local! {
+    /// whether the thread is pinned already
+    static PINNED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Cores to pin threads to
+#[derive(Debug)]
+pub struct CpuAffinity {
+    cores: Vec<usize>,
+    next: AtomicUsize,
+}
+
+impl CpuAffinity {
+    /// Parse a list of cores and core ranges e.g. `0-3,6`
+    pub fn parse(cores: &str) -> Result<Self, String> {
+        let mut ids = Vec::new();
+        for part in cores.split(',') {
+            let (first, last) = part.split_once('-').unwrap_or((part, part));
+            match (first.trim().parse::<usize>(), last.trim().parse::<usize>()) {
+                (Ok(first), Ok(last)) if first <= last => ids.extend(first..=last),
+                _ => return Err(format!("invalid cores: {part}")),
+            }
+        }
+        Ok(Self {
+            cores: ids,
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    /// Pin the calling thread to the next core, threads pinned already are unchanged
+    pub fn pin_current_thread(&self) {
+        if PINNED.get() {
+            return;
+        }
+        let core = self.cores[self.next.fetch_add(1, Ordering::Relaxed) % self.cores.len()];
+        let thread = std::thread::current();
+        let name = thread.name().unwrap_or("unnamed");
+        if core_affinity::set_for_current(CoreId { id: core }) {
+            debug!(target: LOG_TARGET, "pinned thread {name} to core {core}");
+            PINNED.set(true);
+        } else {
+            warn!(target: LOG_TARGET, "couldn't pin thread {name} to core {core}");
+        }
+    }
+}
+
+/// Build a runtime of `threads` worker threads named `name`, pinned if `affinity` is set
+pub fn dedicated_runtime(
+    name: &str,
+    threads: usize,
+    affinity: Option<Arc<CpuAffinity>>,
+) -> std::io::Result<Runtime> {
+    let mut builder = tokio::runtime::Builder::new_multi_thread();
+    builder
+        .worker_threads(threads)
+        .thread_name(name)
+        .enable_all();
+    if let Some(affinity) = affinity {
+        builder.on_thread_start(move || affinity.pin_current_thread());
+    }
+    builder.build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_cpu_affinity() {
+        assert_eq!(
+            CpuAffinity::parse("0-3,6").unwrap().cores,
+            vec![0, 1, 2, 3, 6]
+        );
+        assert_eq!(CpuAffinity::parse("2").unwrap().cores, vec![2]);
+        assert!(CpuAffinity::parse("3-1").is_err());
+        assert!(CpuAffinity::parse("0,").is_err());
+        assert!(CpuAffinity::parse("").is_err());
+        assert!(CpuAffinity::parse("a-b").is_err());
+    }
+}
@@ -0,0 +1,138 @@
+//! Deferred execution of requests at a timestamp or slot
+
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+use serde::Serialize;
+
+/// Max. pending entries
+const MAX_PENDING: usize = 1_000;
+
+/// When a scheduled entry becomes due
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum Activation {
+    /// unix timestamp (seconds)
+    #[serde(rename = "activateAt")]
+    Ts(u64),
+    #[serde(rename = "activateSlot")]
+    Slot(u64),
+}
+
+impl Activation {
+    fn is_due(&self, ts: u64, slot: Option<u64>) -> bool {
+        match self {
+            Self::Ts(at) => ts >= *at,
+            Self::Slot(at) => slot.is_some_and(|slot| slot >= *at),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Scheduled<T> {
+    pub id: u64,
+    pub activation: Activation,
+    pub payload: T,
+}
+
+/// Pending entries ordered by id
+pub struct Scheduler<T> {
+    next_id: AtomicU64,
+    pending: Mutex<BTreeMap<u64, Scheduled<T>>>,
+}
+
+impl<T> Default for Scheduler<T> {
+    fn default() -> Self {
+        Self {
+            next_id: AtomicU64::new(1),
+            pending: Mutex::default(),
+        }
+    }
+}
+
+impl<T: Clone> Scheduler<T> {
+    /// Add an entry, returning its id
+    pub fn add(&self, activation: Activation, payload: T) -> Result<u64, String> {
+        let mut pending = self.pending.lock().expect("lock");
+        if pending.len() >= MAX_PENDING {
+            return Err(format!("too many scheduled entries (max. {MAX_PENDING})"));
+        }
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        pending.insert(
+            id,
+            Scheduled {
+                id,
+                activation,
+                payload,
+            },
+        );
+        Ok(id)
+    }
+
+    /// Remove a pending entry
+    pub fn cancel(&self, id: u64) -> Option<Scheduled<T>> {
+        self.pending.lock().expect("lock").remove(&id)
+    }
+
+    /// Return all pending entries
+    pub fn list(&self) -> Vec<Scheduled<T>> {
+        self.pending
+            .lock()
+            .expect("lock")
+            .values()
+            .cloned()
+            .collect()
+    }
+
+    /// True if any pending entry activates by slot
+    pub fn has_slot_activations(&self) -> bool {
+        self.pending
+            .lock()
+            .expect("lock")
+            .values()
+            .any(|s| matches!(s.activation, Activation::Slot(_)))
+    }
+
+    /// Remove and return entries due at `ts` (unix seconds) or `slot`
+    pub fn take_due(&self, ts: u64, slot: Option<u64>) -> Vec<Scheduled<T>> {
+        let mut pending = self.pending.lock().expect("lock");
+        let due: Vec<u64> = pending
+            .values()
+            .filter(|s| s.activation.is_due(ts, slot))
+            .map(|s| s.id)
+            .collect();
+        due.iter().filter_map(|id| pending.remove(id)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scheduler_take_due() {
+        let scheduler = Scheduler::<&str>::default();
+        let a = scheduler.add(Activation::Ts(100), "a").unwrap();
+        let b = scheduler.add(Activation::Slot(5_000), "b").unwrap();
+        let c = scheduler.add(Activation::Ts(200), "c").unwrap();
+        assert!(scheduler.has_slot_activations());
+
+        assert!(scheduler.take_due(99, None).is_empty());
+        let due = scheduler.take_due(100, Some(4_999));
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].id, a);
+
+        let due = scheduler.take_due(150, Some(5_000));
+        assert_eq!(due[0].id, b);
+        assert!(!scheduler.has_slot_activations());
+
+        assert_eq!(scheduler.cancel(c).map(|s| s.payload), Some("c"));
+        assert!(scheduler.take_due(300, None).is_empty());
+        assert!(scheduler.cancel(c).is_none());
+    }
+}
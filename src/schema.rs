@@ -0,0 +1,161 @@
+//! JSON schemas of the gateway API, generated from the types the gateway (de)serializes
+//!
+//! `SCHEMA_DIR=<dir> cargo test --features schema schema::` writes one file per type, see
+//! `scripts/gen-types.sh`.
+
+use std::collections::HashSet;
+
+use drift_gateway_types::ErrorResponse;
+use schemars::{schema::RootSchema, schema_for};
+
+use crate::{
+    dca::DcaPlan,
+    order_status::OrderStatus,
+    settings::RuntimeSettings,
+    triggers::Trigger,
+    twap::TwapAlgo,
+    tx_status::TxStatus,
+    types::{
+        AllMarketsResponse, BenchRequest, BenchResponse, CancelAndPlaceRequest,
+        CancelOrdersRequest, Collateral, ConfirmRequest, CreateSubAccountRequest,
+        CreateSubAccountResponse, DcaPlansResponse, DcaRequest, EquityHistoryRequest,
+        EquityHistoryResponse, ExecutionReportRequest, ExecutionReportResponse, FeeSpendRequest,
+        FeeSpendResponse, FillsRequest, FillsResponse, FundingRateResponse, GetOrdersRequest,
+        GetOrdersResponse, GetPositionsRequest, GetPositionsResponse, InsuranceResponse, Leverage,
+        MarginInfo, MarginSnapshot, MarketInfoResponse, MarketMarginResponse, ModifyOrdersRequest,
+        OrderbookRequest, OrderbookResponse, OrdersWhatIfResponse, PerpPosition,
+        PlaceOrdersRequest, PollEventsRequest, PollEventsResponse, ReadinessResponse,
+        ReconcileRequest, ReconcileResponse, RpcStatsResponse, ScheduleOrdersRequest,
+        ScheduleOrdersResponse, ScheduledOrdersResponse, SnapshotResponse, SolBalanceResponse,
+        SubAccountsResponse, SubscriptionRequest, SubscriptionsResponse, TransferRequest,
+        TransferResponse, TriggerRequest, TriggersResponse, TwapAlgosResponse, TwapRequest,
+        TxEventsResponse, TxResponse, UsageRequest, UsageResponse, UserHealthResponse,
+        WebhookResponse,
+    },
+    websocket::{AccountEvent, WsEvent, WsRequest},
+};
+
+/// Schemas of every request, response, and event type of the HTTP and Ws APIs by name
+fn api_schemas() -> Vec<(&'static str, RootSchema)> {
+    vec![
+        // HTTP requests, bodies and query strings
+        ("PlaceOrdersRequest", schema_for!(PlaceOrdersRequest)),
+        ("ModifyOrdersRequest", schema_for!(ModifyOrdersRequest)),
+        ("CancelOrdersRequest", schema_for!(CancelOrdersRequest)),
+        ("CancelAndPlaceRequest", schema_for!(CancelAndPlaceRequest)),
+        ("ScheduleOrdersRequest", schema_for!(ScheduleOrdersRequest)),
+        ("DcaRequest", schema_for!(DcaRequest)),
+        ("TwapRequest", schema_for!(TwapRequest)),
+        ("TriggerRequest", schema_for!(TriggerRequest)),
+        ("GetOrdersRequest", schema_for!(GetOrdersRequest)),
+        ("GetPositionsRequest", schema_for!(GetPositionsRequest)),
+        ("FillsRequest", schema_for!(FillsRequest)),
+        (
+            "ExecutionReportRequest",
+            schema_for!(ExecutionReportRequest),
+        ),
+        ("PollEventsRequest", schema_for!(PollEventsRequest)),
+        ("ReconcileRequest", schema_for!(ReconcileRequest)),
+        (
+            "CreateSubAccountRequest",
+            schema_for!(CreateSubAccountRequest),
+        ),
+        ("TransferRequest", schema_for!(TransferRequest)),
+        ("ConfirmRequest", schema_for!(ConfirmRequest)),
+        ("EquityHistoryRequest", schema_for!(EquityHistoryRequest)),
+        ("OrderbookRequest", schema_for!(OrderbookRequest)),
+        ("SubscriptionRequest", schema_for!(SubscriptionRequest)),
+        ("UsageRequest", schema_for!(UsageRequest)),
+        ("FeeSpendRequest", schema_for!(FeeSpendRequest)),
+        ("BenchRequest", schema_for!(BenchRequest)),
+        ("RuntimeSettings", schema_for!(RuntimeSettings)),
+        // HTTP responses
+        ("TxResponse", schema_for!(TxResponse)),
+        ("TxStatus", schema_for!(TxStatus)),
+        ("TxEventsResponse", schema_for!(TxEventsResponse)),
+        ("TransferResponse", schema_for!(TransferResponse)),
+        ("OrderStatus", schema_for!(OrderStatus)),
+        ("GetOrdersResponse", schema_for!(GetOrdersResponse)),
+        ("GetPositionsResponse", schema_for!(GetPositionsResponse)),
+        ("PerpPosition", schema_for!(PerpPosition)),
+        ("SnapshotResponse", schema_for!(SnapshotResponse)),
+        ("MarginInfo", schema_for!(MarginInfo)),
+        ("Collateral", schema_for!(Collateral)),
+        ("Leverage", schema_for!(Leverage)),
+        ("MarginSnapshot", schema_for!(MarginSnapshot)),
+        ("OrdersWhatIfResponse", schema_for!(OrdersWhatIfResponse)),
+        ("UserHealthResponse", schema_for!(UserHealthResponse)),
+        ("SolBalanceResponse", schema_for!(SolBalanceResponse)),
+        ("AllMarketsResponse", schema_for!(AllMarketsResponse)),
+        ("MarketInfoResponse", schema_for!(MarketInfoResponse)),
+        ("MarketMarginResponse", schema_for!(MarketMarginResponse)),
+        ("FundingRateResponse", schema_for!(FundingRateResponse)),
+        ("InsuranceResponse", schema_for!(InsuranceResponse)),
+        ("OrderbookResponse", schema_for!(OrderbookResponse)),
+        ("FillsResponse", schema_for!(FillsResponse)),
+        (
+            "ExecutionReportResponse",
+            schema_for!(ExecutionReportResponse),
+        ),
+        ("PollEventsResponse", schema_for!(PollEventsResponse)),
+        ("ReconcileResponse", schema_for!(ReconcileResponse)),
+        (
+            "ScheduleOrdersResponse",
+            schema_for!(ScheduleOrdersResponse),
+        ),
+        (
+            "ScheduledOrdersResponse",
+            schema_for!(ScheduledOrdersResponse),
+        ),
+        ("DcaPlan", schema_for!(DcaPlan)),
+        ("DcaPlansResponse", schema_for!(DcaPlansResponse)),
+        ("TwapAlgo", schema_for!(TwapAlgo)),
+        ("TwapAlgosResponse", schema_for!(TwapAlgosResponse)),
+        ("Trigger", schema_for!(Trigger)),
+        ("TriggersResponse", schema_for!(TriggersResponse)),
+        ("SubAccountsResponse", schema_for!(SubAccountsResponse)),
+        (
+            "CreateSubAccountResponse",
+            schema_for!(CreateSubAccountResponse),
+        ),
+        ("EquityHistoryResponse", schema_for!(EquityHistoryResponse)),
+        ("SubscriptionsResponse", schema_for!(SubscriptionsResponse)),
+        ("UsageResponse", schema_for!(UsageResponse)),
+        ("FeeSpendResponse", schema_for!(FeeSpendResponse)),
+        ("WebhookResponse", schema_for!(WebhookResponse)),
+        ("ReadinessResponse", schema_for!(ReadinessResponse)),
+        ("RpcStatsResponse", schema_for!(RpcStatsResponse)),
+        ("BenchResponse", schema_for!(BenchResponse)),
+        ("ErrorResponse", schema_for!(ErrorResponse)),
+        // Ws messages
+        ("WsRequest", schema_for!(WsRequest)),
+        ("WsEvent", schema_for!(WsEvent<'static, AccountEvent>)),
+    ]
+}
+
+#[test]
+fn api_schemas_export() {
+    let schemas = api_schemas();
+    let names: HashSet<&str> = schemas.iter().map(|(name, _)| *name).collect();
+    assert_eq!(names.len(), schemas.len(), "schema names are unique");
+    for (name, schema) in &schemas {
+        assert!(
+            schema.schema.object.is_some() || schema.schema.subschemas.is_some(),
+            "{name} has a schema"
+        );
+    }
+
+    let Some(dir) = std::env::var_os("SCHEMA_DIR") else {
+        return;
+    };
+    let dir = std::path::PathBuf::from(dir);
+    std::fs::create_dir_all(&dir).expect("created schema dir");
+    for (name, schema) in schemas {
+        let path = dir.join(format!("{name}.json"));
+        std::fs::write(
+            &path,
+            serde_json::to_string_pretty(&schema).expect("serializes"),
+        )
+        .expect("schema written");
+    }
+}
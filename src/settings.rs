@@ -0,0 +1,96 @@
+//! Runtime settings reloadable without a restart
+//!
+//! Settings are read from the `--runtime-config` JSON file on startup, and again on SIGHUP or
+//! `POST /v2/admin/reload`. Unset fields keep their current value, so a reload changes only what
+//! the file sets while subscriptions and Ws event streams carry on.
+
+use std::path::Path;
+
+use log::LevelFilter;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct RuntimeSettings {
+    /// markets to subscribe in addition to those subscribed e.g. `["sol-perp", "wbtc"]`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub markets: Option<Vec<String>>,
+    /// additional RPC endpoints for tx submission, replacing the current ones
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extra_rpcs: Option<Vec<String>>,
+    /// percentile of sampled priority fees to pay, in (0, 1]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority_fee_percentile: Option<f32>,
+    /// max. priority fee in micro-lamports per CU, 0 for no max.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_priority_fee: Option<u64>,
+    /// level of gateway logs, one of: off, error, warn, info, debug, trace
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_level: Option<String>,
+}
+
+impl RuntimeSettings {
+    /// Read settings from the JSON file at `path`
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let data = std::fs::read(path)
+            .map_err(|err| format!("couldn't read {}: {err}", path.display()))?;
+        let settings: Self = serde_json::from_slice(&data)
+            .map_err(|err| format!("invalid runtime config {}: {err}", path.display()))?;
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    /// Check settings apply before any of them is
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(percentile) = self.priority_fee_percentile {
+            if !(percentile > 0.0 && percentile <= 1.0) {
+                return Err(format!(
+                    "priorityFeePercentile must be in (0, 1]: {percentile}"
+                ));
+            }
+        }
+        self.log_level_filter()?;
+        Ok(())
+    }
+
+    /// Return the log level, if set
+    pub fn log_level_filter(&self) -> Result<Option<LevelFilter>, String> {
+        self.log_level
+            .as_deref()
+            .map(|level| {
+                level
+                    .parse()
+                    .map_err(|_| format!("invalid logLevel: {level}"))
+            })
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runtime_settings() {
+        let settings: RuntimeSettings = serde_json::from_str(
+            r#"{"markets":["sol-perp"],"priorityFeePercentile":0.75,"logLevel":"debug"}"#,
+        )
+        .unwrap();
+        assert!(settings.validate().is_ok());
+        assert_eq!(settings.extra_rpcs, None);
+        assert_eq!(settings.log_level_filter(), Ok(Some(LevelFilter::Debug)));
+
+        let invalid = RuntimeSettings {
+            priority_fee_percentile: Some(1.5),
+            ..Default::default()
+        };
+        assert!(invalid.validate().is_err());
+        let invalid = RuntimeSettings {
+            log_level: Some("loud".into()),
+            ..Default::default()
+        };
+        assert!(invalid.validate().is_err());
+        assert!(serde_json::from_str::<RuntimeSettings>(r#"{"market":["sol-perp"]}"#).is_err());
+    }
+}
@@ -0,0 +1,171 @@
+//! Tx signers
+//!
+//! Txs are signed by the key of `DRIFT_GATEWAY_KEY`, or by a remote signing service so the key
+//! needn't be held by the gateway. The remote signer is sent each tx message and returns its
+//! signature, which is verified before the tx is sent.
+//!
+//! ```text
+//! POST <url>
+//! { "pubkey": "<signer pubkey>", "message": "<base64 serialized tx message>" }
+//! => { "signature": "<base58 signature>" }
+//! ```
+
+use std::{str::FromStr, time::Duration};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use drift_rs::{types::VersionedMessage, Pubkey, Wallet};
+use futures_util::future::{BoxFuture, FutureExt};
+use serde::{Deserialize, Serialize};
+use solana_sdk::{hash::Hash, signature::Signature, transaction::VersionedTransaction};
+
+/// Max. time to wait on the remote signer
+const REMOTE_SIGNER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Signs tx messages
+pub trait Signer: Send + Sync {
+    /// Return the signing key
+    fn pubkey(&self) -> Pubkey;
+    /// Sign `message` with `recent_block_hash`
+    fn sign(
+        &self,
+        message: VersionedMessage,
+        recent_block_hash: Hash,
+    ) -> BoxFuture<'_, Result<VersionedTransaction, String>>;
+}
+
+/// Signs with the key of the wallet
+pub struct LocalSigner {
+    wallet: Wallet,
+}
+
+impl LocalSigner {
+    pub fn new(wallet: Wallet) -> Self {
+        Self { wallet }
+    }
+}
+
+impl Signer for LocalSigner {
+    fn pubkey(&self) -> Pubkey {
+        self.wallet.signer()
+    }
+
+    fn sign(
+        &self,
+        message: VersionedMessage,
+        recent_block_hash: Hash,
+    ) -> BoxFuture<'_, Result<VersionedTransaction, String>> {
+        let tx = self
+            .wallet
+            .sign_tx(message, recent_block_hash)
+            .map_err(|err| err.to_string());
+        async move { tx }.boxed()
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SignRequest {
+    pubkey: String,
+    message: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SignResponse {
+    signature: String,
+}
+
+/// Signs via a remote signing service
+pub struct RemoteSigner {
+    http: reqwest::Client,
+    url: String,
+    pubkey: Pubkey,
+    /// bearer token of requests, if set
+    token: Option<String>,
+}
+
+impl RemoteSigner {
+    pub fn new(http: reqwest::Client, url: String, pubkey: Pubkey, token: Option<String>) -> Self {
+        Self {
+            http,
+            url,
+            pubkey,
+            token,
+        }
+    }
+}
+
+impl Signer for RemoteSigner {
+    fn pubkey(&self) -> Pubkey {
+        self.pubkey
+    }
+
+    fn sign(
+        &self,
+        mut message: VersionedMessage,
+        recent_block_hash: Hash,
+    ) -> BoxFuture<'_, Result<VersionedTransaction, String>> {
+        async move {
+            message.set_recent_blockhash(recent_block_hash);
+            if message.header().num_required_signatures != 1 {
+                return Err("remote signer signs single signer txs only".to_string());
+            }
+            let data = message.serialize();
+            let mut req = self
+                .http
+                .post(&self.url)
+                .timeout(REMOTE_SIGNER_TIMEOUT)
+                .json(&SignRequest {
+                    pubkey: self.pubkey.to_string(),
+                    message: BASE64.encode(&data),
+                });
+            if let Some(ref token) = self.token {
+                req = req.bearer_auth(token);
+            }
+            let res = req
+                .send()
+                .await
+                .and_then(|res| res.error_for_status())
+                .map_err(|err| format!("remote signer: {err}"))?
+                .json::<SignResponse>()
+                .await
+                .map_err(|err| format!("remote signer response: {err}"))?;
+            let signature = verify(&self.pubkey, &data, &res.signature)?;
+            Ok(VersionedTransaction {
+                signatures: vec![signature],
+                message,
+            })
+        }
+        .boxed()
+    }
+}
+
+/// Parse the `signature` of `data`, refused unless made by `pubkey`
+fn verify(pubkey: &Pubkey, data: &[u8], signature: &str) -> Result<Signature, String> {
+    let signature = Signature::from_str(signature)
+        .map_err(|_| format!("remote signer returned an invalid signature: {signature}"))?;
+    if !signature.verify(pubkey.as_ref(), data) {
+        return Err(format!(
+            "remote signer returned a signature not made by {pubkey}"
+        ));
+    }
+    Ok(signature)
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::signature::{Keypair, Signer as _};
+
+    use super::*;
+
+    #[test]
+    fn remote_signatures_are_verified() {
+        let keypair = Keypair::new();
+        let data = b"tx message";
+        let signature = keypair.sign_message(data).to_string();
+        assert!(verify(&keypair.pubkey(), data, &signature).is_ok());
+        assert!(verify(&keypair.pubkey(), b"other message", &signature).is_err());
+        assert!(verify(&Keypair::new().pubkey(), data, &signature).is_err());
+        assert!(verify(&keypair.pubkey(), data, "not a signature").is_err());
+    }
+}
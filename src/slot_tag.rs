@@ -0,0 +1,101 @@
+//! Slot tags of HTTP responses
+//!
+//! Reads of cached or RPC fetched state within a request record the slot the data is as of, the
+//! response is tagged with the oldest slot (and largest cache age) read, so clients can order
+//! updates and detect stale data.
+
+use std::{future::Future, sync::Mutex};
+
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+use tokio::task::futures::TaskLocalFuture;
+
+/// Header carrying the slot the response data is as of
+pub const SLOT_HEADER: &str = "x-slot";
+/// Header carrying the secs since the cached response data last changed, 0 if fetched via RPC
+pub const CACHE_AGE_HEADER: &str = "x-cache-age";
+
+tokio::task_local! {
+    static SLOT_TAG: SlotTag;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Tag {
+    slot: u64,
+    cache_age: u64,
+}
+
+/// Slot of the data read by the current request
+#[derive(Default, Debug)]
+pub struct SlotTag(Mutex<Option<Tag>>);
+
+impl SlotTag {
+    /// Run `fut` recording the slots of data it reads
+    pub fn scope<F: Future>(fut: F) -> TaskLocalFuture<SlotTag, F> {
+        SLOT_TAG.scope(SlotTag::default(), fut)
+    }
+
+    /// Return the slot and cache age recorded by the current task, if any
+    pub fn current() -> Option<(u64, u64)> {
+        SLOT_TAG
+            .try_with(|tag| tag.0.lock().expect("lock").map(|t| (t.slot, t.cache_age)))
+            .ok()
+            .flatten()
+    }
+
+    /// Set the slot headers of a response, if any slot was recorded
+    pub fn tag(slot_and_age: Option<(u64, u64)>, headers: &mut HeaderMap) {
+        let Some((slot, cache_age)) = slot_and_age else {
+            return;
+        };
+        headers.insert(
+            HeaderName::from_static(SLOT_HEADER),
+            HeaderValue::from(slot),
+        );
+        headers.insert(
+            HeaderName::from_static(CACHE_AGE_HEADER),
+            HeaderValue::from(cache_age),
+        );
+    }
+}
+
+/// Record a read of data as of `slot`, last changed `cache_age` secs ago
+///
+/// noop outside of a request
+pub fn record(slot: u64, cache_age: u64) {
+    let _ = SLOT_TAG.try_with(|tag| {
+        let mut tag = tag.0.lock().expect("lock");
+        *tag = Some(match *tag {
+            Some(t) => Tag {
+                slot: t.slot.min(slot),
+                cache_age: t.cache_age.max(cache_age),
+            },
+            None => Tag { slot, cache_age },
+        });
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_web::test]
+    async fn slot_tag_works() {
+        record(1, 1);
+        assert!(SlotTag::current().is_none());
+
+        let tag = SlotTag::scope(async {
+            assert!(SlotTag::current().is_none());
+            record(120, 0);
+            record(100, 3);
+            record(130, 1);
+            SlotTag::current()
+        })
+        .await;
+        assert_eq!(tag, Some((100, 3)));
+
+        let mut headers = HeaderMap::new();
+        SlotTag::tag(tag, &mut headers);
+        assert_eq!(headers.get(SLOT_HEADER).unwrap(), "100");
+        assert_eq!(headers.get(CACHE_AGE_HEADER).unwrap(), "3");
+    }
+}
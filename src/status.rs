@@ -0,0 +1,217 @@
+//! Gateway-level conditions affecting trading
+//!
+//! Monitors raise and clear conditions (kill switch tripped, RPC degraded, subscriptions stalled,
+//! markets paused, tx failure streaks), changes are broadcast to the Ws status channel so clients
+//! have one place to decide whether to keep quoting.
+
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex,
+    },
+};
+
+use drift_rs::types::MarketType;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+use crate::{controller::unix_timestamp, types::Market};
+
+/// Changes buffered per status subscriber
+const STATUS_CHANNEL_CAPACITY: usize = 256;
+
+/// Consecutive txs failing on-chain raising a tx failure streak
+pub const TX_FAILURE_STREAK: u32 = 3;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub enum StatusCondition {
+    /// order placement is halted
+    KillSwitch,
+    /// the RPC is failing or slow to respond
+    RpcDegraded,
+    /// a subscription stopped updating
+    SubscriptionStalled,
+    /// a subscribed market isn't accepting or filling orders
+    MarketPaused,
+    /// a market's account layout doesn't match this build, it's skipped
+    IncompatibleMarket,
+    /// consecutive txs landed failed on-chain
+    TxFailureStreak,
+    /// the account event stream of a Ws subscription is down, sent to that subscription only
+    EventStream,
+}
+
+/// A condition raised or cleared
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct StatusUpdate {
+    pub condition: StatusCondition,
+    /// the market or subscription affected, unset for gateway-wide conditions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+    /// true while the condition holds
+    pub active: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    pub ts: u64,
+}
+
+/// Return the subject of conditions of `market` e.g. `perp-0`
+pub fn market_subject(market: Market) -> String {
+    match market.market_type {
+        MarketType::Perp => format!("perp-{}", market.market_index),
+        MarketType::Spot => format!("spot-{}", market.market_index),
+    }
+}
+
+type ConditionKey = (StatusCondition, Option<String>);
+
+/// Active conditions of the gateway
+pub struct GatewayStatus {
+    active: Mutex<BTreeMap<ConditionKey, StatusUpdate>>,
+    updates: broadcast::Sender<StatusUpdate>,
+    tx_failures: AtomicU32,
+}
+
+impl Default for GatewayStatus {
+    fn default() -> Self {
+        Self {
+            active: Mutex::default(),
+            updates: broadcast::channel(STATUS_CHANNEL_CAPACITY).0,
+            tx_failures: AtomicU32::default(),
+        }
+    }
+}
+
+impl GatewayStatus {
+    /// Raise `condition` of `subject`, updating its detail if already active
+    pub fn raise(&self, condition: StatusCondition, subject: Option<String>, detail: String) {
+        let mut active = self.active.lock().expect("lock");
+        let key = (condition, subject);
+        if active
+            .get(&key)
+            .is_some_and(|u| u.detail.as_ref() == Some(&detail))
+        {
+            return;
+        }
+        let update = StatusUpdate {
+            condition,
+            subject: key.1.clone(),
+            active: true,
+            detail: Some(detail),
+            ts: unix_timestamp(),
+        };
+        active.insert(key, update.clone());
+        // no receivers is fine
+        let _ = self.updates.send(update);
+    }
+
+    /// Clear `condition` of `subject`, if active
+    pub fn clear(&self, condition: StatusCondition, subject: Option<String>) {
+        let mut active = self.active.lock().expect("lock");
+        if active.remove(&(condition, subject.clone())).is_some() {
+            let _ = self.updates.send(StatusUpdate {
+                condition,
+                subject,
+                active: false,
+                detail: None,
+                ts: unix_timestamp(),
+            });
+        }
+    }
+
+    /// Set the subjects of `condition` to exactly `raised` as (subject, detail), clearing others
+    pub fn replace(&self, condition: StatusCondition, raised: Vec<(String, String)>) {
+        let cleared: Vec<Option<String>> = self
+            .active
+            .lock()
+            .expect("lock")
+            .keys()
+            .filter(|(c, subject)| {
+                *c == condition
+                    && !raised
+                        .iter()
+                        .any(|(s, _)| subject.as_deref() == Some(s.as_str()))
+            })
+            .map(|(_, subject)| subject.clone())
+            .collect();
+        for subject in cleared {
+            self.clear(condition, subject);
+        }
+        for (subject, detail) in raised {
+            self.raise(condition, Some(subject), detail);
+        }
+    }
+
+    /// Record the outcome of a landed tx, raising a streak of txs failing on-chain
+    ///
+    /// txs that don't land within their blockhash (e.g. congestion) aren't failures
+    pub fn record_landed_tx(&self, ok: bool) {
+        if ok {
+            if self.tx_failures.swap(0, Ordering::Relaxed) >= TX_FAILURE_STREAK {
+                self.clear(StatusCondition::TxFailureStreak, None);
+            }
+            return;
+        }
+        if self.tx_failures.fetch_add(1, Ordering::Relaxed) + 1 == TX_FAILURE_STREAK {
+            self.raise(
+                StatusCondition::TxFailureStreak,
+                None,
+                format!("{TX_FAILURE_STREAK} consecutive txs failed onchain"),
+            );
+        }
+    }
+
+    /// Return the active conditions and a receiver of changes after them
+    pub fn subscribe(&self) -> (Vec<StatusUpdate>, broadcast::Receiver<StatusUpdate>) {
+        // changes are sent under the lock, none fall between the two
+        let active = self.active.lock().expect("lock");
+        (active.values().cloned().collect(), self.updates.subscribe())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conditions_raise_and_clear() {
+        let status = GatewayStatus::default();
+        let (active, mut updates) = status.subscribe();
+        assert!(active.is_empty());
+
+        status.replace(
+            StatusCondition::MarketPaused,
+            vec![("perp-0".into(), "fill paused".into())],
+        );
+        // unchanged conditions aren't broadcast again
+        status.raise(
+            StatusCondition::MarketPaused,
+            Some("perp-0".into()),
+            "fill paused".into(),
+        );
+        for _ in 0..TX_FAILURE_STREAK {
+            status.record_landed_tx(false);
+        }
+        status.replace(StatusCondition::MarketPaused, vec![]);
+        status.record_landed_tx(true);
+
+        let changes: Vec<_> = std::iter::from_fn(|| updates.try_recv().ok())
+            .map(|u| (u.condition, u.subject, u.active))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                (StatusCondition::MarketPaused, Some("perp-0".into()), true),
+                (StatusCondition::TxFailureStreak, None, true),
+                (StatusCondition::MarketPaused, Some("perp-0".into()), false),
+                (StatusCondition::TxFailureStreak, None, false),
+            ]
+        );
+        assert!(status.subscribe().0.is_empty());
+    }
+}
@@ -0,0 +1,274 @@
+//! Cache of on-chain sub-account names
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::RwLock,
+};
+
+use anchor_lang::InstructionData;
+use drift_rs::{
+    constants::{ProgramData, PROGRAM_ID},
+    drift_idl,
+    types::{accounts::User, SdkResult},
+    DriftClient, Pubkey, Wallet,
+};
+use log::debug;
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction},
+    system_program, sysvar,
+};
+
+use crate::{types::decode_name, LOG_TARGET};
+
+/// Sub-account names of the gateway authority
+#[derive(Default)]
+pub struct SubAccountNames {
+    names: RwLock<BTreeMap<u16, String>>,
+}
+
+impl SubAccountNames {
+    /// Fetch names of all sub-accounts created by the wallet's authority, replacing the cache
+    pub async fn refresh(&self, client: &DriftClient, wallet: &Wallet) -> SdkResult<()> {
+        let user_stats = client.get_user_stats(wallet.authority()).await?;
+        let mut names = BTreeMap::new();
+        for sub_account_id in 0..user_stats.number_of_sub_accounts_created {
+            // deleted sub-accounts no longer exist
+            match client
+                .get_user_account(&wallet.sub_account(sub_account_id))
+                .await
+            {
+                Ok(user) => {
+                    names.insert(sub_account_id, decode_name(&user.name));
+                }
+                Err(err) => {
+                    debug!(target: LOG_TARGET, "skipping sub-account name: {sub_account_id}, {err:?}");
+                }
+            }
+        }
+        *self.names.write().expect("lock") = names;
+        Ok(())
+    }
+
+    /// Add the name of a created sub-account
+    pub fn insert(&self, sub_account_id: u16, name: String) {
+        self.names
+            .write()
+            .expect("lock")
+            .insert(sub_account_id, name);
+    }
+
+    /// Return the name of `sub_account_id`
+    pub fn name(&self, sub_account_id: u16) -> Option<String> {
+        self.names
+            .read()
+            .expect("lock")
+            .get(&sub_account_id)
+            .cloned()
+    }
+
+    /// True if `sub_account_id` exists, always true before names are loaded
+    pub fn contains(&self, sub_account_id: u16) -> bool {
+        let names = self.names.read().expect("lock");
+        names.is_empty() || names.contains_key(&sub_account_id)
+    }
+
+    /// Return the id of the sub-account named `name`
+    pub fn id(&self, name: &str) -> Option<u16> {
+        self.names
+            .read()
+            .expect("lock")
+            .iter()
+            .find_map(|(id, n)| (n == name).then_some(*id))
+    }
+
+    /// Return all (id, name) pairs ordered by id
+    pub fn all(&self) -> Vec<(u16, String)> {
+        self.names
+            .read()
+            .expect("lock")
+            .iter()
+            .map(|(id, name)| (*id, name.clone()))
+            .collect()
+    }
+}
+
+/// Encode an account name, None if longer than 32 bytes
+pub fn encode_name(name: &str) -> Option<[u8; 32]> {
+    let mut encoded = [b' '; 32];
+    encoded
+        .get_mut(..name.len())?
+        .copy_from_slice(name.as_bytes());
+    Some(encoded)
+}
+
+/// Return the user stats account of `authority`
+pub fn user_stats_account(authority: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"user_stats", authority.as_ref()], &PROGRAM_ID).0
+}
+
+/// Return instructions creating sub-account `sub_account_id` of `authority`
+///
+/// * `init_stats` - also create the authority's user stats account, required before its first sub-account
+pub fn initialize_user_ixs(
+    authority: &Pubkey,
+    sub_account_id: u16,
+    name: [u8; 32],
+    init_stats: bool,
+) -> Vec<Instruction> {
+    let (user, _) = Pubkey::find_program_address(
+        &[b"user", authority.as_ref(), &sub_account_id.to_le_bytes()],
+        &PROGRAM_ID,
+    );
+    let user_stats = user_stats_account(authority);
+    let (state, _) = Pubkey::find_program_address(&[b"drift_state"], &PROGRAM_ID);
+    let common_accounts = [
+        AccountMeta::new(user_stats, false),
+        AccountMeta::new(state, false),
+        AccountMeta::new_readonly(*authority, true),
+        // payer
+        AccountMeta::new(*authority, true),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+
+    let mut ixs = Vec::with_capacity(2);
+    if init_stats {
+        ixs.push(Instruction {
+            program_id: PROGRAM_ID,
+            accounts: common_accounts.to_vec(),
+            data: drift_idl::instructions::InitializeUserStats {}.data(),
+        });
+    }
+    ixs.push(Instruction {
+        program_id: PROGRAM_ID,
+        accounts: std::iter::once(AccountMeta::new(user, false))
+            .chain(common_accounts)
+            .collect(),
+        data: drift_idl::instructions::InitializeUser {
+            sub_account_id,
+            name,
+        }
+        .data(),
+    });
+    ixs
+}
+
+/// Return an instruction moving `amount` (token precision) of spot market `market_index` collateral
+/// between two sub-accounts of `authority`
+///
+/// * `from_user` - account of the `from` sub-account, its markets are required for the margin check
+///
+/// Returns None if a market is unknown
+pub fn transfer_deposit_ix(
+    program_data: &ProgramData,
+    authority: &Pubkey,
+    from: &Pubkey,
+    from_user: &User,
+    to: &Pubkey,
+    market_index: u16,
+    amount: u64,
+) -> Option<Instruction> {
+    let spot_market = program_data.spot_market_config_by_index(market_index)?;
+    let (state, _) = Pubkey::find_program_address(&[b"drift_state"], &PROGRAM_ID);
+
+    // quote market is required to margin perp positions
+    let spot_indexes: BTreeSet<u16> = from_user
+        .spot_positions
+        .iter()
+        .filter(|p| !p.is_available())
+        .map(|p| p.market_index)
+        .chain([0, market_index])
+        .collect();
+    let perp_indexes: BTreeSet<u16> = from_user
+        .perp_positions
+        .iter()
+        .filter(|p| !p.is_available())
+        .map(|p| p.market_index)
+        .collect();
+
+    let mut oracles = BTreeSet::new();
+    let mut spot_markets = Vec::with_capacity(spot_indexes.len());
+    for index in spot_indexes {
+        let spot = program_data.spot_market_config_by_index(index)?;
+        oracles.insert(spot.oracle);
+        spot_markets.push(if index == market_index {
+            AccountMeta::new(spot.pubkey, false)
+        } else {
+            AccountMeta::new_readonly(spot.pubkey, false)
+        });
+    }
+    let mut perp_markets = Vec::with_capacity(perp_indexes.len());
+    for index in perp_indexes {
+        let perp = program_data.perp_market_config_by_index(index)?;
+        oracles.insert(perp.amm.oracle);
+        perp_markets.push(AccountMeta::new_readonly(perp.pubkey, false));
+    }
+
+    // remaining accounts are ordered oracles, spot markets, perp markets
+    let accounts = [
+        AccountMeta::new(*from, false),
+        AccountMeta::new(*to, false),
+        AccountMeta::new(user_stats_account(authority), false),
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new_readonly(state, false),
+        AccountMeta::new_readonly(spot_market.vault, false),
+    ]
+    .into_iter()
+    .chain(
+        oracles
+            .into_iter()
+            .map(|o| AccountMeta::new_readonly(o, false)),
+    )
+    .chain(spot_markets)
+    .chain(perp_markets)
+    .collect();
+
+    Some(Instruction {
+        program_id: PROGRAM_ID,
+        accounts,
+        data: drift_idl::instructions::TransferDeposit {
+            market_index,
+            amount,
+        }
+        .data(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_name_works() {
+        let name = encode_name("mm-sol").unwrap();
+        assert_eq!(decode_name(&name), "mm-sol");
+        assert!(encode_name(&"x".repeat(33)).is_none());
+    }
+
+    #[test]
+    fn initialize_user_ixs_works() {
+        let authority = Pubkey::new_unique();
+        let wallet = Wallet::read_only(authority);
+        let ixs = initialize_user_ixs(&authority, 0, encode_name("main").unwrap(), true);
+        assert_eq!(ixs.len(), 2);
+        assert_eq!(ixs[1].accounts[0].pubkey, wallet.sub_account(0));
+        assert_eq!(ixs[0].accounts[0].pubkey, ixs[1].accounts[1].pubkey);
+
+        let ixs = initialize_user_ixs(&authority, 3, encode_name("mm").unwrap(), false);
+        assert_eq!(ixs.len(), 1);
+        assert_eq!(ixs[0].accounts[0].pubkey, wallet.sub_account(3));
+    }
+
+    #[test]
+    fn contains_unloaded_is_permissive() {
+        let names = SubAccountNames::default();
+        assert!(names.contains(7));
+        names
+            .names
+            .write()
+            .unwrap()
+            .extend([(0, "main".to_string()), (2, "mm-sol".to_string())]);
+        assert!(names.contains(2));
+        assert!(!names.contains(1));
+    }
+}
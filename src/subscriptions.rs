@@ -0,0 +1,331 @@
+//! Registry of market, oracle, and account subscriptions and their health
+//!
+//! Subscriptions are sampled periodically, the update time of a subscription advances when its
+//! latest slot changes. A subscription is a stall candidate once it has not updated for a timeout
+//! while the chain kept producing slots. Markets and accounts only update when they change, so
+//! candidates whose data is confirmed current on-chain are quiet rather than stalled.
+
+use std::{collections::HashMap, sync::RwLock};
+
+use drift_rs::{types::MarketId, Pubkey};
+use serde::{Deserialize, Serialize};
+
+use crate::types::{Market, SubscriptionInfo};
+
+/// Min. slots the chain is expected to produce per second (~400ms slots)
+const MIN_SLOTS_PER_SEC: u64 = 2;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub enum SubscriptionKind {
+    Market,
+    Oracle,
+    Account,
+}
+
+/// Identifies a subscription
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SubscriptionId {
+    Market(MarketId),
+    Oracle(MarketId),
+    Account(Pubkey),
+}
+
+impl SubscriptionId {
+    pub fn kind(&self) -> SubscriptionKind {
+        match self {
+            Self::Market(_) => SubscriptionKind::Market,
+            Self::Oracle(_) => SubscriptionKind::Oracle,
+            Self::Account(_) => SubscriptionKind::Account,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+struct Health {
+    subscribed_ts: u64,
+    last_slot: Option<u64>,
+    /// when `last_slot` last changed
+    last_update_ts: Option<u64>,
+    /// when the data was last confirmed current on-chain
+    verified_ts: Option<u64>,
+    /// times resubscribed
+    resubscribes: u32,
+}
+
+impl Health {
+    fn secs_since_update(&self, now: u64) -> u64 {
+        now.saturating_sub(self.last_update_ts.unwrap_or(self.subscribed_ts))
+    }
+
+    /// secs since the data was last updated or confirmed current
+    fn secs_since_verified(&self, now: u64) -> u64 {
+        self.secs_since_update(now)
+            .min(now.saturating_sub(self.verified_ts.unwrap_or_default()))
+    }
+}
+
+#[derive(Default)]
+pub struct Subscriptions {
+    entries: RwLock<HashMap<SubscriptionId, Health>>,
+}
+
+impl Subscriptions {
+    /// Register new subscriptions, resetting the health of existing ones
+    pub fn add(&self, ids: impl IntoIterator<Item = SubscriptionId>, now: u64) {
+        let mut entries = self.entries.write().expect("lock");
+        for id in ids {
+            entries.insert(
+                id,
+                Health {
+                    subscribed_ts: now,
+                    ..Default::default()
+                },
+            );
+        }
+    }
+
+    /// Reset the health of a resubscribed subscription
+    pub fn resubscribed(&self, id: SubscriptionId, now: u64) {
+        let mut entries = self.entries.write().expect("lock");
+        let resubscribes = entries.get(&id).map(|h| h.resubscribes).unwrap_or_default() + 1;
+        entries.insert(
+            id,
+            Health {
+                subscribed_ts: now,
+                resubscribes,
+                ..Default::default()
+            },
+        );
+    }
+
+    /// Remove a subscription, returns false if it was not registered
+    pub fn remove(&self, id: &SubscriptionId) -> bool {
+        self.entries.write().expect("lock").remove(id).is_some()
+    }
+
+    pub fn contains(&self, id: &SubscriptionId) -> bool {
+        self.entries.read().expect("lock").contains_key(id)
+    }
+
+    /// Return secs since a subscription last updated or was confirmed current, None if not registered
+    pub fn age(&self, id: &SubscriptionId, now: u64) -> Option<u64> {
+        self.entries
+            .read()
+            .expect("lock")
+            .get(id)
+            .map(|health| health.secs_since_verified(now))
+    }
+
+    /// Return the kinds of subscriptions with data not yet cached, i.e. not `is_warm`
+    pub fn cold(&self, is_warm: impl Fn(&SubscriptionId) -> bool) -> Vec<SubscriptionKind> {
+        let mut kinds: Vec<SubscriptionKind> = Vec::new();
+        for id in self.entries.read().expect("lock").keys() {
+            if !kinds.contains(&id.kind()) && !is_warm(id) {
+                kinds.push(id.kind());
+            }
+        }
+        kinds.sort_by_key(|k| *k as u8);
+        kinds
+    }
+
+    /// Return all registered subscriptions
+    pub fn ids(&self) -> Vec<SubscriptionId> {
+        self.entries.read().expect("lock").keys().copied().collect()
+    }
+
+    /// Record the latest `slot` of a subscription observed at `now`
+    pub fn observe(&self, id: &SubscriptionId, slot: u64, now: u64) {
+        if let Some(health) = self.entries.write().expect("lock").get_mut(id) {
+            if health.last_slot != Some(slot) {
+                health.last_slot = Some(slot);
+                health.last_update_ts = Some(now);
+            }
+        }
+    }
+
+    /// Record that the data of a subscription was confirmed current on-chain at `now`
+    pub fn verified(&self, id: &SubscriptionId, now: u64) {
+        if let Some(health) = self.entries.write().expect("lock").get_mut(id) {
+            health.verified_ts = Some(now);
+        }
+    }
+
+    /// Return subscriptions without updates or confirmations for `timeout` secs, with their secs since update
+    ///
+    /// * `chain_slot` - current slot, a subscription is only stalled if the chain progressed past it
+    ///
+    /// quiet markets and accounts are candidates too, see [`Self::verified`]
+    pub fn stalled(&self, now: u64, chain_slot: u64, timeout: u64) -> Vec<(SubscriptionId, u64)> {
+        self.entries
+            .read()
+            .expect("lock")
+            .iter()
+            .filter_map(|(id, health)| {
+                let secs = health.secs_since_update(now);
+                let chain_progressed = health.last_slot.map_or(true, |slot| {
+                    chain_slot.saturating_sub(slot) >= timeout * MIN_SLOTS_PER_SEC
+                });
+                (health.secs_since_verified(now) >= timeout && chain_progressed)
+                    .then_some((*id, secs))
+            })
+            .collect()
+    }
+
+    /// Return subscriptions with their health at `now`, stalest first
+    pub fn list(&self, now: u64) -> Vec<SubscriptionInfo> {
+        let mut subscriptions: Vec<SubscriptionInfo> = self
+            .entries
+            .read()
+            .expect("lock")
+            .iter()
+            .map(|(id, health)| {
+                let (market, account) = match id {
+                    SubscriptionId::Market(m) | SubscriptionId::Oracle(m) => {
+                        (Some(Market::new(m.index(), m.kind())), None)
+                    }
+                    SubscriptionId::Account(a) => (None, Some(a.to_string())),
+                };
+                SubscriptionInfo {
+                    kind: id.kind(),
+                    market,
+                    account,
+                    subscribed_ts: health.subscribed_ts,
+                    last_slot: health.last_slot,
+                    last_update_ts: health.last_update_ts,
+                    secs_since_update: health.secs_since_update(now),
+                    resubscribes: health.resubscribes,
+                }
+            })
+            .collect();
+        subscriptions.sort_by(|a, b| b.secs_since_update.cmp(&a.secs_since_update));
+        subscriptions
+    }
+}
+
+/// Return the subscription of `kind` identified by `market` or `account`
+pub fn subscription_id(
+    kind: SubscriptionKind,
+    market: Option<Market>,
+    account: Option<&str>,
+) -> Result<SubscriptionId, String> {
+    let market_id = || {
+        market
+            .map(Market::as_market_id)
+            .ok_or_else(|| "market required".to_string())
+    };
+    match kind {
+        SubscriptionKind::Market => market_id().map(SubscriptionId::Market),
+        SubscriptionKind::Oracle => market_id().map(SubscriptionId::Oracle),
+        SubscriptionKind::Account => account
+            .ok_or_else(|| "account required".to_string())?
+            .parse()
+            .map(SubscriptionId::Account)
+            .map_err(|_| "invalid account".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subscriptions_health() {
+        let subscriptions = Subscriptions::default();
+        let market = SubscriptionId::Market(MarketId::perp(0));
+        let oracle = SubscriptionId::Oracle(MarketId::perp(0));
+        subscriptions.add([market, oracle], 100);
+
+        subscriptions.observe(&market, 5, 110);
+        subscriptions.observe(&oracle, 7, 110);
+        // slot unchanged, not an update
+        subscriptions.observe(&market, 5, 150);
+        subscriptions.observe(&oracle, 8, 150);
+
+        let list = subscriptions.list(160);
+        assert_eq!(list.len(), 2);
+        assert_eq!(subscriptions.age(&market, 160), Some(50));
+        assert_eq!(
+            subscriptions.age(&SubscriptionId::Market(MarketId::spot(0)), 160),
+            None
+        );
+        assert_eq!(list[0].kind, SubscriptionKind::Market);
+        assert_eq!(list[0].last_slot, Some(5));
+        assert_eq!(list[0].secs_since_update, 50);
+        assert_eq!(list[1].secs_since_update, 10);
+
+        // resubscribing resets health
+        subscriptions.resubscribed(market, 160);
+        let list = subscriptions.list(160);
+        assert_eq!(list[1].last_slot, None);
+        assert_eq!(list[1].resubscribes, 1);
+        assert!(subscriptions.remove(&market));
+        assert!(!subscriptions.contains(&market));
+    }
+
+    #[test]
+    fn stalled_works() {
+        let subscriptions = Subscriptions::default();
+        let market = SubscriptionId::Market(MarketId::perp(0));
+        let oracle = SubscriptionId::Oracle(MarketId::perp(0));
+        let account = SubscriptionId::Account(Pubkey::new_unique());
+        subscriptions.add([market, oracle, account], 100);
+        subscriptions.observe(&market, 1_000, 100);
+        subscriptions.observe(&oracle, 1_000, 100);
+        subscriptions.observe(&oracle, 1_100, 140);
+        subscriptions.observe(&account, 1_110, 150);
+
+        // chain stalled too
+        assert!(subscriptions.stalled(160, 1_020, 30).is_empty());
+        assert_eq!(subscriptions.stalled(160, 1_120, 30), vec![(market, 60)]);
+        assert!(subscriptions.stalled(160, 1_120, 90).is_empty());
+
+        // a quiet market confirmed current on-chain is not stalled
+        subscriptions.verified(&market, 160);
+        assert!(subscriptions.stalled(170, 1_140, 30).is_empty());
+        subscriptions.observe(&oracle, 1_280, 195);
+        subscriptions.observe(&account, 1_280, 195);
+        assert_eq!(subscriptions.stalled(200, 1_300, 30), vec![(market, 100)]);
+        assert_eq!(subscriptions.age(&market, 200), Some(40));
+    }
+
+    #[test]
+    fn cold_works() {
+        let subscriptions = Subscriptions::default();
+        let account = SubscriptionId::Account(Pubkey::new_unique());
+        subscriptions.add(
+            [
+                SubscriptionId::Market(MarketId::perp(0)),
+                SubscriptionId::Market(MarketId::perp(1)),
+                SubscriptionId::Oracle(MarketId::perp(0)),
+                account,
+            ],
+            100,
+        );
+        assert!(subscriptions.cold(|_| true).is_empty());
+        assert_eq!(
+            subscriptions.cold(|id| *id != SubscriptionId::Market(MarketId::perp(1))),
+            vec![SubscriptionKind::Market]
+        );
+        assert_eq!(
+            subscriptions.cold(|id| matches!(id, SubscriptionId::Oracle(_))),
+            vec![SubscriptionKind::Market, SubscriptionKind::Account]
+        );
+    }
+
+    #[test]
+    fn subscription_id_works() {
+        let account = Pubkey::new_unique();
+        assert_eq!(
+            subscription_id(SubscriptionKind::Oracle, Some(Market::spot(1)), None),
+            Ok(SubscriptionId::Oracle(MarketId::spot(1)))
+        );
+        assert_eq!(
+            subscription_id(SubscriptionKind::Account, None, Some(&account.to_string())),
+            Ok(SubscriptionId::Account(account))
+        );
+        assert!(subscription_id(SubscriptionKind::Market, None, None).is_err());
+        assert!(subscription_id(SubscriptionKind::Account, None, Some("x")).is_err());
+    }
+}
@@ -0,0 +1,210 @@
+//! Prebuilt order transaction templates
+//!
+//! Building a place orders tx resolves account metas for every market the user touches.
+//! These only change when the user's open markets change, so a built message is cached per
+//! (sub-account, markets) and later requests only patch the ix data of the cached message.
+
+use std::{collections::HashMap, sync::RwLock};
+
+use anchor_lang::InstructionData;
+use drift_rs::{
+    constants::PROGRAM_ID,
+    drift_idl,
+    types::{accounts::User, MarketType, OrderParams, VersionedMessage},
+    Pubkey,
+};
+use solana_sdk::compute_budget::{self, ComputeBudgetInstruction};
+
+/// Upper bound on cached templates, the cache is reset once exceeded
+const MAX_TEMPLATES: usize = 256;
+
+/// Identifies the accounts referenced by a place orders tx
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct TemplateKey {
+    sub_account: Pubkey,
+    /// (market index, is perp) of user positions and new orders, sorted
+    markets: Vec<(u16, bool)>,
+    /// whether the tx sets an explicit CU limit
+    cu_limit: bool,
+}
+
+impl TemplateKey {
+    pub fn new(
+        sub_account: Pubkey,
+        user: &User,
+        orders: &[OrderParams],
+        cu_limit: Option<u32>,
+    ) -> Self {
+        let mut markets: Vec<(u16, bool)> = user
+            .perp_positions
+            .iter()
+            .filter(|p| !p.is_available())
+            .map(|p| (p.market_index, true))
+            .chain(
+                user.spot_positions
+                    .iter()
+                    .filter(|p| !p.is_available())
+                    .map(|p| (p.market_index, false)),
+            )
+            .chain(
+                orders
+                    .iter()
+                    .map(|o| (o.market_index, o.market_type == MarketType::Perp)),
+            )
+            .collect();
+        markets.sort_unstable();
+        markets.dedup();
+
+        Self {
+            sub_account,
+            markets,
+            cu_limit: cu_limit.is_some(),
+        }
+    }
+}
+
+/// Cache of place orders tx templates
+#[derive(Default)]
+pub struct TxTemplates {
+    templates: RwLock<HashMap<TemplateKey, VersionedMessage>>,
+}
+
+impl TxTemplates {
+    /// Return a cached tx for `key` patched with the given orders and compute budget
+    pub fn place_orders(
+        &self,
+        key: &TemplateKey,
+        orders: &[OrderParams],
+        priority_fee: u64,
+        cu_limit: Option<u32>,
+    ) -> Option<VersionedMessage> {
+        let template = self.templates.read().expect("lock").get(key).cloned()?;
+        patch_place_orders(template, orders, priority_fee, cu_limit)
+    }
+
+    /// Store a freshly built place orders tx as the template for `key`
+    pub fn insert(&self, key: TemplateKey, tx: VersionedMessage) {
+        let mut templates = self.templates.write().expect("lock");
+        if templates.len() >= MAX_TEMPLATES {
+            templates.clear();
+        }
+        templates.insert(key, tx);
+    }
+}
+
+/// Replace order params and compute budget of a place orders tx
+fn patch_place_orders(
+    mut tx: VersionedMessage,
+    orders: &[OrderParams],
+    priority_fee: u64,
+    cu_limit: Option<u32>,
+) -> Option<VersionedMessage> {
+    let (account_keys, instructions) = match tx {
+        VersionedMessage::Legacy(ref mut m) => (&m.account_keys, &mut m.instructions),
+        VersionedMessage::V0(ref mut m) => (&m.account_keys, &mut m.instructions),
+    };
+
+    let (mut patched_orders, mut patched_price, mut patched_limit) = (false, false, false);
+    for ix in instructions.iter_mut() {
+        let program_id = account_keys.get(ix.program_id_index as usize)?;
+        if *program_id == compute_budget::id() {
+            // first byte is the ComputeBudgetInstruction variant
+            match ix.data.first() {
+                Some(2) => {
+                    ix.data = ComputeBudgetInstruction::set_compute_unit_limit(cu_limit?).data;
+                    patched_limit = true;
+                }
+                Some(3) => {
+                    ix.data = ComputeBudgetInstruction::set_compute_unit_price(priority_fee).data;
+                    patched_price = true;
+                }
+                _ => (),
+            }
+        } else if *program_id == PROGRAM_ID {
+            // a template holds exactly one drift ix
+            if patched_orders {
+                return None;
+            }
+            ix.data = drift_idl::instructions::PlaceOrders {
+                params: orders.to_vec(),
+            }
+            .data();
+            patched_orders = true;
+        }
+    }
+
+    // a compute budget ix missing from the template can't be patched in, the tx is rebuilt
+    if (priority_fee > 0 && !patched_price) || (cu_limit.is_some() && !patched_limit) {
+        return None;
+    }
+
+    patched_orders.then_some(tx)
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::{instruction::Instruction, message::Message};
+
+    use super::*;
+
+    #[test]
+    fn patch_place_orders_works() {
+        let payer = Pubkey::new_unique();
+        let order = |base_asset_amount| OrderParams {
+            base_asset_amount,
+            ..Default::default()
+        };
+        let tx = VersionedMessage::Legacy(Message::new(
+            &[
+                ComputeBudgetInstruction::set_compute_unit_price(1),
+                ComputeBudgetInstruction::set_compute_unit_limit(200_000),
+                Instruction {
+                    program_id: PROGRAM_ID,
+                    accounts: vec![],
+                    data: drift_idl::instructions::PlaceOrders {
+                        params: vec![order(1)],
+                    }
+                    .data(),
+                },
+            ],
+            Some(&payer),
+        ));
+
+        let patched = patch_place_orders(tx.clone(), &[order(2), order(3)], 500, Some(300_000))
+            .expect("patched");
+        let instructions = patched.instructions();
+        assert_eq!(
+            instructions[0].data,
+            ComputeBudgetInstruction::set_compute_unit_price(500).data
+        );
+        assert_eq!(
+            instructions[1].data,
+            ComputeBudgetInstruction::set_compute_unit_limit(300_000).data
+        );
+        assert_eq!(
+            instructions[2].data,
+            drift_idl::instructions::PlaceOrders {
+                params: vec![order(2), order(3)]
+            }
+            .data()
+        );
+
+        // template has a CU limit ix but the request did not set one
+        assert!(patch_place_orders(tx, &[order(2)], 500, None).is_none());
+
+        // template without a CU price ix, the request's priority fee must not be dropped
+        let tx = VersionedMessage::Legacy(Message::new(
+            &[Instruction {
+                program_id: PROGRAM_ID,
+                accounts: vec![],
+                data: drift_idl::instructions::PlaceOrders {
+                    params: vec![order(1)],
+                }
+                .data(),
+            }],
+            Some(&payer),
+        ));
+        assert!(patch_place_orders(tx.clone(), &[order(2)], 500, None).is_none());
+        assert!(patch_place_orders(tx, &[order(2)], 0, None).is_some());
+    }
+}
@@ -0,0 +1,156 @@
+//! Per market order rate limits
+//!
+//! Each market allows up to `rate` order messages per second (GCRA), bursts up to the same size.
+//! Requests over the limit are queued until the market allows them or rejected outright.
+
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use drift_rs::types::{MarketId, MarketType};
+
+use crate::controller::{ControllerError, GatewayResult};
+
+/// Max. time a request may be queued for
+const MAX_QUEUE_WAIT: Duration = Duration::from_secs(1);
+
+/// Behaviour of requests over the limit
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ThrottleMode {
+    /// wait until allowed (up to `MAX_QUEUE_WAIT`)
+    Queue,
+    Reject,
+}
+
+impl FromStr for ThrottleMode {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "queue" => Ok(Self::Queue),
+            "reject" => Ok(Self::Reject),
+            _ => Err(format!("unknown throttle mode: {s}")),
+        }
+    }
+}
+
+pub struct OrderThrottle {
+    /// time between order messages at the sustained rate
+    interval: Duration,
+    /// burst allowance
+    window: Duration,
+    mode: ThrottleMode,
+    /// theoretical arrival time of the next message per market
+    tat: Mutex<HashMap<MarketId, Instant>>,
+}
+
+impl OrderThrottle {
+    /// * `rate` - max. order messages per second per market
+    pub fn new(rate: u32, mode: ThrottleMode) -> Self {
+        Self {
+            interval: Duration::from_secs(1) / rate.max(1),
+            window: Duration::from_secs(1),
+            mode,
+            tat: Mutex::default(),
+        }
+    }
+
+    /// Acquire permits for order messages in the given markets, one per entry
+    ///
+    /// waits if queueing is enabled, otherwise returns an error if any market is over its limit
+    pub async fn acquire(&self, markets: impl IntoIterator<Item = MarketId>) -> GatewayResult<()> {
+        let delay = self.reserve(markets, Instant::now())?;
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+        Ok(())
+    }
+
+    /// Reserve permits at `now`, returning the time to wait before they are valid
+    ///
+    /// nothing is reserved if any market is over its limit
+    fn reserve(
+        &self,
+        markets: impl IntoIterator<Item = MarketId>,
+        now: Instant,
+    ) -> GatewayResult<Duration> {
+        let mut counts = HashMap::<MarketId, u32>::new();
+        for market in markets {
+            *counts.entry(market).or_default() += 1;
+        }
+
+        let mut tat = self.tat.lock().expect("lock");
+        let mut next = Vec::with_capacity(counts.len());
+        let mut delay = Duration::ZERO;
+        for (market, count) in counts {
+            let market_tat = tat.get(&market).copied().unwrap_or(now).max(now);
+            let market_next = market_tat + self.interval * count;
+            let market_delay = market_next.saturating_duration_since(now + self.window);
+            let allowed = match self.mode {
+                ThrottleMode::Reject => market_delay.is_zero(),
+                ThrottleMode::Queue => market_delay <= MAX_QUEUE_WAIT,
+            };
+            if !allowed {
+                let market_type = match market.kind() {
+                    MarketType::Perp => "perp",
+                    MarketType::Spot => "spot",
+                };
+                return Err(ControllerError::RateLimited(format!(
+                    "order rate limit exceeded for {market_type} market {}",
+                    market.index()
+                )));
+            }
+            delay = delay.max(market_delay);
+            next.push((market, market_next));
+        }
+        tat.extend(next);
+
+        Ok(delay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn throttle_reject() {
+        let throttle = OrderThrottle::new(2, ThrottleMode::Reject);
+        let now = Instant::now();
+        let sol = MarketId::perp(0);
+        let btc = MarketId::perp(1);
+
+        assert!(throttle.reserve([sol, sol], now).is_ok());
+        assert!(throttle.reserve([sol], now).is_err());
+        // other markets are unaffected
+        assert!(throttle.reserve([btc], now).is_ok());
+        // failed requests reserve nothing
+        assert!(throttle.reserve([btc, sol], now).is_err());
+        assert!(throttle.reserve([btc], now).is_ok());
+        // a permit frees up every 500ms
+        assert!(throttle
+            .reserve([sol], now + Duration::from_millis(500))
+            .is_ok());
+    }
+
+    #[test]
+    fn throttle_queue() {
+        let throttle = OrderThrottle::new(2, ThrottleMode::Queue);
+        let now = Instant::now();
+        let sol = MarketId::perp(0);
+
+        assert_eq!(throttle.reserve([sol, sol], now).unwrap(), Duration::ZERO);
+        assert_eq!(
+            throttle.reserve([sol], now).unwrap(),
+            Duration::from_millis(500)
+        );
+        assert_eq!(
+            throttle.reserve([sol], now).unwrap(),
+            Duration::from_millis(1_000)
+        );
+        // would wait longer than allowed
+        assert!(throttle.reserve([sol], now).is_err());
+    }
+}
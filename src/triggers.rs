@@ -0,0 +1,216 @@
+//! Gateway evaluated trigger conditions
+//!
+//! A trigger watches a market metric and places or cancels predefined orders once its condition is met.
+//! Triggers are one-shot, they are evaluated against the gateway's market and account subscriptions.
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    registry::{Entry, Registry},
+    types::{CancelOrdersRequest, Market, PlaceOrder},
+};
+
+/// Max. active triggers
+const MAX_ACTIVE_TRIGGERS: usize = 100;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub enum Metric {
+    /// last funding rate of a perp market
+    FundingRate,
+    OraclePrice,
+    /// oracle confidence interval (quote)
+    OracleConfidence,
+    /// unrealized PnL (quote) of a perp position
+    UnrealizedPnl,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub enum Comparison {
+    Above,
+    Below,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct Condition {
+    pub metric: Metric,
+    #[serde(flatten)]
+    pub market: Market,
+    pub op: Comparison,
+    pub threshold: Decimal,
+}
+
+impl Condition {
+    pub fn is_met(&self, value: Decimal) -> bool {
+        match self.op {
+            Comparison::Above => value > self.threshold,
+            Comparison::Below => value < self.threshold,
+        }
+    }
+}
+
+/// Orders to place or cancel once a trigger condition is met
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum TriggerAction {
+    Place { orders: Vec<PlaceOrder> },
+    Cancel(CancelOrdersRequest),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub enum TriggerStatus {
+    Active,
+    Triggered,
+    Cancelled,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct Trigger {
+    pub id: u64,
+    pub condition: Condition,
+    pub action: TriggerAction,
+    /// authority the action trades for, the default authority if unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub authority: Option<String>,
+    pub sub_account_id: u16,
+    pub status: TriggerStatus,
+    /// metric value that met the condition
+    pub triggered_value: Option<Decimal>,
+    pub triggered_ts: Option<u64>,
+    /// signature of the action tx
+    pub tx: Option<String>,
+    pub error: Option<String>,
+}
+
+impl Entry for Trigger {
+    fn id(&self) -> u64 {
+        self.id
+    }
+    fn is_active(&self) -> bool {
+        self.status == TriggerStatus::Active
+    }
+    fn cancel(&mut self) {
+        self.status = TriggerStatus::Cancelled;
+    }
+}
+
+/// Triggers by id
+pub struct Triggers {
+    triggers: Registry<Trigger>,
+}
+
+impl Default for Triggers {
+    fn default() -> Self {
+        Self {
+            triggers: Registry::new("triggers", MAX_ACTIVE_TRIGGERS),
+        }
+    }
+}
+
+impl Triggers {
+    pub fn add(
+        &self,
+        condition: Condition,
+        action: TriggerAction,
+        authority: Option<String>,
+        sub_account_id: u16,
+    ) -> Result<Trigger, String> {
+        self.triggers.add(|id| Trigger {
+            id,
+            condition,
+            action,
+            authority,
+            sub_account_id,
+            status: TriggerStatus::Active,
+            triggered_value: None,
+            triggered_ts: None,
+            tx: None,
+            error: None,
+        })
+    }
+
+    pub fn list(&self) -> Vec<Trigger> {
+        self.triggers.list()
+    }
+
+    pub fn active(&self) -> Vec<Trigger> {
+        self.triggers.active()
+    }
+
+    /// Cancel an active trigger, returning it
+    pub fn cancel(&self, id: u64) -> Option<Trigger> {
+        self.triggers.cancel(id)
+    }
+
+    /// Mark trigger `id` as triggered, returns false if it was no longer active
+    pub fn fire(&self, id: u64, value: Decimal, ts: u64) -> bool {
+        self.triggers
+            .update(id, |trigger| {
+                if !trigger.is_active() {
+                    return false;
+                }
+                trigger.status = TriggerStatus::Triggered;
+                trigger.triggered_value = Some(value);
+                trigger.triggered_ts = Some(ts);
+                true
+            })
+            .unwrap_or_default()
+    }
+
+    /// Record the outcome of trigger `id`'s action
+    pub fn record_result(&self, id: u64, result: Result<String, String>) {
+        self.triggers.update(id, |trigger| match result {
+            Ok(tx) => trigger.tx = Some(tx),
+            Err(err) => trigger.error = Some(err),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trigger_fires_once() {
+        let condition: Condition = serde_json::from_str(
+            r#"{"metric":"fundingRate","marketIndex":0,"marketType":"perp","op":"above","threshold":"0.001"}"#,
+        )
+        .unwrap();
+        assert!(!condition.is_met(Decimal::new(1, 3)));
+        assert!(condition.is_met(Decimal::new(2, 3)));
+
+        let action: TriggerAction =
+            serde_json::from_str(r#"{"type":"cancel","marketIndex":0,"marketType":"perp"}"#)
+                .unwrap();
+        assert!(matches!(
+            action,
+            TriggerAction::Cancel(CancelOrdersRequest {
+                market: Some(_),
+                ..
+            })
+        ));
+
+        let triggers = Triggers::default();
+        let trigger = triggers.add(condition, action, None, 0).unwrap();
+        assert_eq!(triggers.active().len(), 1);
+        assert!(triggers.fire(trigger.id, Decimal::new(2, 3), 100));
+        assert!(!triggers.fire(trigger.id, Decimal::new(2, 3), 101));
+        assert!(triggers.active().is_empty());
+
+        triggers.record_result(trigger.id, Ok("sig".to_string()));
+        let trigger = &triggers.list()[0];
+        assert_eq!(trigger.status, TriggerStatus::Triggered);
+        assert_eq!(trigger.tx.as_deref(), Some("sig"));
+    }
+}
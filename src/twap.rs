@@ -0,0 +1,427 @@
+//! TWAP execution algos
+//!
+//! An algo slices a parent order into market orders placed every `interval` seconds until its
+//! end time. Each child order trades an even share of the amount still unfilled, optionally
+//! capped to a share of the market's traded volume. Child orders are tracked like other orders
+//! placed via the gateway, their fills arriving with the sub-account's events.
+
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::{
+    registry::{Entry, Registry},
+    types::Market,
+};
+
+/// Max. active algos
+const MAX_ACTIVE_ALGOS: usize = 100;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub enum TwapStatus {
+    Active,
+    Completed,
+    Cancelled,
+}
+
+/// A child order of an algo
+#[derive(Debug, Clone)]
+struct TwapChild {
+    /// order tracking id
+    tracking_id: u64,
+    /// base amount, negative to sell
+    amount: Decimal,
+    /// base amount filled, unsigned
+    filled: Decimal,
+    /// false once the order is filled or closed
+    open: bool,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct TwapAlgo {
+    pub id: u64,
+    #[serde(flatten)]
+    pub market: Market,
+    /// base amount of the parent order, negative to sell
+    pub amount: Decimal,
+    /// seconds between child orders
+    pub interval: u64,
+    /// max. share of the market's volume traded per interval, if set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_participation: Option<Decimal>,
+    /// max. fill price deviation of child orders from the oracle price in bps, if set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_slippage_bps: Option<u32>,
+    /// user order id of child orders
+    pub user_order_id: u8,
+    /// authority the algo trades for, the default authority if unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub authority: Option<String>,
+    pub sub_account_id: u16,
+    pub status: TwapStatus,
+    /// base amount of child orders placed so far
+    pub placed: Decimal,
+    /// base amount of child orders filled so far
+    pub filled: Decimal,
+    pub orders: u32,
+    pub start_ts: u64,
+    pub end_ts: u64,
+    /// unix timestamp of the next child order
+    pub next_ts: u64,
+    pub last_error: Option<String>,
+    #[serde(skip)]
+    children: Vec<TwapChild>,
+    /// true while a child order is being sent
+    #[serde(skip)]
+    in_flight: bool,
+    /// true once the user order id is released
+    #[serde(skip)]
+    released: bool,
+}
+
+impl TwapAlgo {
+    /// Signed base amount of open child orders yet to fill
+    fn open_amount(&self) -> Decimal {
+        self.children
+            .iter()
+            .filter(|c| c.open)
+            .map(|c| {
+                let unfilled = (c.amount.abs() - c.filled).max(Decimal::ZERO);
+                if c.amount.is_sign_negative() {
+                    -unfilled
+                } else {
+                    unfilled
+                }
+            })
+            .sum()
+    }
+
+    /// Signed base amount of the child order due at `ts`
+    ///
+    /// the amount not filled or in open orders is split evenly across the remaining intervals,
+    /// capped to `max_amount` (unsigned) if set
+    pub fn child_amount(&self, ts: u64, max_amount: Option<Decimal>) -> Decimal {
+        let remaining = self.amount - self.filled - self.open_amount();
+        if remaining.is_zero() || remaining.is_sign_negative() != self.amount.is_sign_negative() {
+            return Decimal::ZERO;
+        }
+        let intervals = self
+            .end_ts
+            .saturating_sub(ts)
+            .div_ceil(self.interval)
+            .max(1);
+        let amount = remaining / Decimal::from(intervals);
+        match max_amount {
+            Some(max_amount) if amount.abs() > max_amount => {
+                if amount.is_sign_negative() {
+                    -max_amount
+                } else {
+                    max_amount
+                }
+            }
+            _ => amount,
+        }
+    }
+}
+
+impl Entry for TwapAlgo {
+    fn id(&self) -> u64 {
+        self.id
+    }
+    fn is_active(&self) -> bool {
+        self.status == TwapStatus::Active
+    }
+    fn cancel(&mut self) {
+        self.status = TwapStatus::Cancelled;
+    }
+}
+
+/// TWAP algos by id
+pub struct Twap {
+    algos: Registry<TwapAlgo>,
+}
+
+impl Default for Twap {
+    fn default() -> Self {
+        Self {
+            algos: Registry::new("TWAP algos", MAX_ACTIVE_ALGOS),
+        }
+    }
+}
+
+impl Twap {
+    /// Add a new algo placing child orders from `start_ts` until `start_ts + duration`
+    ///
+    /// `user_order_id` is expected to be reserved for the algo
+    #[allow(clippy::too_many_arguments)]
+    pub fn add(
+        &self,
+        market: Market,
+        amount: Decimal,
+        duration: u64,
+        interval: u64,
+        max_participation: Option<Decimal>,
+        max_slippage_bps: Option<u32>,
+        user_order_id: u8,
+        authority: Option<String>,
+        sub_account_id: u16,
+        start_ts: u64,
+    ) -> Result<TwapAlgo, String> {
+        let end_ts = start_ts
+            .checked_add(duration)
+            .ok_or_else(|| format!("startAt + duration out of range: {start_ts} + {duration}"))?;
+        self.algos.add(|id| TwapAlgo {
+            id,
+            market,
+            amount,
+            interval,
+            max_participation,
+            max_slippage_bps,
+            user_order_id,
+            authority,
+            sub_account_id,
+            status: TwapStatus::Active,
+            placed: Decimal::ZERO,
+            filled: Decimal::ZERO,
+            orders: 0,
+            start_ts,
+            end_ts,
+            next_ts: start_ts,
+            last_error: None,
+            children: Vec::default(),
+            in_flight: false,
+            released: false,
+        })
+    }
+
+    pub fn get(&self, id: u64) -> Option<TwapAlgo> {
+        self.algos.get(id)
+    }
+
+    pub fn list(&self) -> Vec<TwapAlgo> {
+        self.algos.list()
+    }
+
+    /// Cancel an active algo, returning it
+    pub fn cancel(&self, id: u64) -> Option<TwapAlgo> {
+        self.algos.cancel(id)
+    }
+
+    /// Update fills of open child orders from their tracked (filled, is closed) status
+    ///
+    /// algos are completed once filled, or past their end time without open child orders
+    pub fn update_fills(&self, ts: u64, status: impl Fn(u64) -> Option<(Decimal, bool)>) {
+        let mut algos = self.algos.lock();
+        for algo in algos.values_mut() {
+            if algo.children.iter().all(|c| !c.open) && algo.status != TwapStatus::Active {
+                continue;
+            }
+            for child in algo.children.iter_mut().filter(|c| c.open) {
+                match status(child.tracking_id) {
+                    Some((filled, closed)) => {
+                        child.filled = filled;
+                        child.open = !closed;
+                    }
+                    // no longer tracked
+                    None => child.open = false,
+                }
+            }
+            let filled: Decimal = algo.children.iter().map(|c| c.filled).sum();
+            algo.filled = if algo.amount.is_sign_negative() {
+                -filled
+            } else {
+                filled
+            };
+            let done = algo.filled.abs() >= algo.amount.abs()
+                || (ts >= algo.end_ts && !algo.in_flight && algo.children.iter().all(|c| !c.open));
+            if done && algo.status == TwapStatus::Active {
+                algo.status = TwapStatus::Completed;
+            }
+        }
+    }
+
+    /// Return active algos with a child order due at `ts`
+    ///
+    /// their child order is in flight until `sent`, meanwhile they're not due again so the child
+    /// amount counts all child orders placed
+    pub fn take_due(&self, ts: u64) -> Vec<TwapAlgo> {
+        let mut algos = self.algos.lock();
+        algos
+            .values_mut()
+            .filter(|a| {
+                a.status == TwapStatus::Active && !a.in_flight && a.next_ts <= ts && ts < a.end_ts
+            })
+            .map(|a| {
+                a.next_ts = ts.saturating_add(a.interval);
+                a.in_flight = true;
+                a.clone()
+            })
+            .collect()
+    }
+
+    /// Mark the child order of algo `id` sent, or failed
+    pub fn sent(&self, id: u64) {
+        self.update(id, |algo| algo.in_flight = false);
+    }
+
+    /// Return algos done with their user order id, completed or cancelled without open child
+    /// orders, each once
+    pub fn take_done(&self) -> Vec<TwapAlgo> {
+        let mut algos = self.algos.lock();
+        algos
+            .values_mut()
+            .filter(|a| {
+                a.status != TwapStatus::Active
+                    && !a.released
+                    && !a.in_flight
+                    && a.children.iter().all(|c| !c.open)
+            })
+            .map(|a| {
+                a.released = true;
+                a.clone()
+            })
+            .collect()
+    }
+
+    /// Record a child order of algo `id` with its tracking ids
+    pub fn record_order(&self, id: u64, amount: Decimal, tracking_ids: &[u64]) {
+        self.update(id, |algo| {
+            algo.placed += amount;
+            algo.orders += 1;
+            algo.last_error = None;
+            algo.children
+                .extend(tracking_ids.iter().map(|tracking_id| TwapChild {
+                    tracking_id: *tracking_id,
+                    amount,
+                    filled: Decimal::ZERO,
+                    open: true,
+                }));
+        });
+    }
+
+    /// Record a failed child order of algo `id`, it is retried next interval
+    pub fn record_error(&self, id: u64, err: String) {
+        self.update(id, |algo| algo.last_error = Some(err));
+    }
+
+    fn update(&self, id: u64, f: impl FnOnce(&mut TwapAlgo)) {
+        self.algos.update(id, f);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn child_orders_slice_unfilled_amount() {
+        let twap = Twap::default();
+        let algo = twap
+            .add(
+                Market::perp(0),
+                Decimal::from(-10),
+                100,
+                10,
+                None,
+                None,
+                200,
+                None,
+                0,
+                1_000,
+            )
+            .unwrap();
+        assert_eq!(algo.child_amount(1_000, None), Decimal::from(-1));
+        assert_eq!(
+            algo.child_amount(1_000, Some(Decimal::new(5, 1))),
+            Decimal::new(-5, 1)
+        );
+
+        assert_eq!(twap.take_due(1_000).len(), 1);
+        assert!(twap.take_due(1_009).is_empty());
+        // not due again while the child order is in flight
+        assert!(twap.take_due(1_010).is_empty());
+        twap.record_order(algo.id, Decimal::from(-1), &[7]);
+        twap.sent(algo.id);
+
+        // the open child order counts towards the amount
+        let algo = twap.get(algo.id).unwrap();
+        assert_eq!(algo.child_amount(1_010, None), Decimal::from(-1));
+
+        // half filled then expired, the unfilled half is spread over the remaining intervals
+        twap.update_fills(1_010, |_| Some((Decimal::new(5, 1), true)));
+        let algo = twap.get(algo.id).unwrap();
+        assert_eq!(algo.filled, Decimal::new(-5, 1));
+        assert_eq!(algo.status, TwapStatus::Active);
+        assert_eq!(algo.child_amount(1_090, None), Decimal::new(-95, 1));
+
+        // past the end time without open orders
+        assert!(twap.take_due(1_100).is_empty());
+        twap.update_fills(1_100, |_| None);
+        assert_eq!(twap.get(algo.id).unwrap().status, TwapStatus::Completed);
+        assert_eq!(twap.take_done().len(), 1);
+        assert!(twap.take_done().is_empty());
+    }
+
+    #[test]
+    fn filled_algo_completes() {
+        let twap = Twap::default();
+        let algo = twap
+            .add(
+                Market::spot(1),
+                Decimal::from(2),
+                60,
+                30,
+                None,
+                None,
+                200,
+                None,
+                0,
+                0,
+            )
+            .unwrap();
+        twap.record_order(algo.id, Decimal::from(2), &[1]);
+        twap.update_fills(10, |_| Some((Decimal::from(2), false)));
+        // done with its user order id once the child order closes
+        assert!(twap.take_done().is_empty());
+        twap.update_fills(10, |_| Some((Decimal::from(2), true)));
+        let algo = twap.get(algo.id).unwrap();
+        assert_eq!(algo.status, TwapStatus::Completed);
+        assert_eq!(algo.child_amount(30, None), Decimal::ZERO);
+
+        let algo = twap
+            .add(
+                Market::spot(1),
+                Decimal::from(2),
+                60,
+                30,
+                None,
+                None,
+                200,
+                None,
+                0,
+                0,
+            )
+            .unwrap();
+        twap.cancel(algo.id);
+        assert!(twap.take_due(0).is_empty());
+        assert_eq!(twap.get(algo.id).unwrap().status, TwapStatus::Cancelled);
+        assert_eq!(twap.take_done().len(), 2);
+
+        let err = twap.add(
+            Market::spot(1),
+            Decimal::from(2),
+            u64::MAX,
+            30,
+            None,
+            None,
+            200,
+            None,
+            0,
+            1,
+        );
+        assert!(err.is_err());
+    }
+}
@@ -0,0 +1,208 @@
+//! Confirmation status of txs sent via the gateway
+//!
+//! Sent txs are tracked by their rebroadcast task from submission until they are finalized, fail
+//! on-chain, or expire once they can no longer land i.e. their blockhash expired or nonce advanced.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::RwLock,
+};
+
+pub use drift_gateway_types::{TxState, TxStatus};
+use solana_sdk::{
+    instruction::InstructionError, signature::Signature, transaction::TransactionError,
+};
+use solana_transaction_status::{TransactionConfirmationStatus, TransactionStatus};
+
+use crate::request_id::RequestId;
+
+/// Max. txs tracked, the oldest are forgotten first
+const MAX_TRACKED_TXS: usize = 10_000;
+
+/// Move `tx` to `state` at `ts`, confirmations only advance
+///
+/// an expired tx that is seen landed later takes its landed state
+fn transition(tx: &mut TxStatus, state: TxState, ts: u64) {
+    let rank = |s: TxState| match s {
+        TxState::Submitted => 0,
+        TxState::Confirmed => 1,
+        _ => 2,
+    };
+    let landed_late = tx.state == TxState::Expired
+        && matches!(
+            state,
+            TxState::Confirmed | TxState::Finalized | TxState::Failed
+        );
+    if landed_late || (!tx.state.is_terminal() && rank(state) > rank(tx.state)) {
+        tx.state = state;
+        tx.updated_ts = ts;
+    }
+}
+
+#[derive(Default)]
+struct TrackerInner {
+    txs: HashMap<Signature, TxStatus>,
+    tracked: VecDeque<Signature>,
+}
+
+/// Tracks the confirmation status of sent txs
+#[derive(Default)]
+pub struct TxTracker {
+    inner: RwLock<TrackerInner>,
+}
+
+impl TxTracker {
+    /// Track a tx sent at `now` by request `request_id`
+    pub fn submitted(&self, signature: Signature, request_id: Option<RequestId>, now: u64) {
+        let mut inner = self.inner.write().expect("lock");
+        if inner.tracked.len() >= MAX_TRACKED_TXS {
+            if let Some(oldest) = inner.tracked.pop_front() {
+                inner.txs.remove(&oldest);
+            }
+        }
+        inner.tracked.push_back(signature);
+        inner.txs.insert(
+            signature,
+            TxStatus {
+                tx: signature.to_string(),
+                state: TxState::Submitted,
+                slot: None,
+                code: None,
+                reason: None,
+                ts: now,
+                updated_ts: now,
+                request_id: request_id.map(|id| id.as_str().to_owned()),
+            },
+        );
+    }
+
+    /// Update a tx from its RPC signature status, returns its state after the update
+    pub fn landed(
+        &self,
+        signature: &Signature,
+        status: &TransactionStatus,
+        now: u64,
+    ) -> Option<TxState> {
+        let mut inner = self.inner.write().expect("lock");
+        let tx = inner.txs.get_mut(signature)?;
+        tx.slot = Some(status.slot);
+        match status.err {
+            Some(ref err) => {
+                tx.code = error_code(err);
+                tx.reason = Some(err.to_string());
+                transition(tx, TxState::Failed, now);
+            }
+            None => match status.confirmation_status() {
+                TransactionConfirmationStatus::Processed => (),
+                TransactionConfirmationStatus::Confirmed => transition(tx, TxState::Confirmed, now),
+                TransactionConfirmationStatus::Finalized => transition(tx, TxState::Finalized, now),
+            },
+        }
+        Some(tx.state)
+    }
+
+    /// Mark a tx expired if it did not land, once it no longer can
+    pub fn expired(&self, signature: &Signature, now: u64) {
+        if let Some(tx) = self.inner.write().expect("lock").txs.get_mut(signature) {
+            if tx.state == TxState::Submitted {
+                transition(tx, TxState::Expired, now);
+            }
+        }
+    }
+
+    pub fn get(&self, signature: &Signature) -> Option<TxStatus> {
+        self.inner.read().expect("lock").txs.get(signature).cloned()
+    }
+}
+
+/// Return the custom program error code of a failed tx, if any
+fn error_code(err: &TransactionError) -> Option<u32> {
+    match err {
+        TransactionError::InstructionError(_, InstructionError::Custom(code)) => Some(*code),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(
+        slot: u64,
+        err: Option<TransactionError>,
+        confirmation: TransactionConfirmationStatus,
+    ) -> TransactionStatus {
+        TransactionStatus {
+            slot,
+            confirmations: None,
+            status: err.clone().map_or(Ok(()), Err),
+            err,
+            confirmation_status: Some(confirmation),
+        }
+    }
+
+    #[test]
+    fn confirmations_advance() {
+        let tracker = TxTracker::default();
+        let sig = Signature::new_unique();
+        tracker.submitted(sig, Some(RequestId::from_client(Some("req-1"))), 100);
+        assert_eq!(tracker.get(&sig).unwrap().state, TxState::Submitted);
+
+        let processed = status(5, None, TransactionConfirmationStatus::Processed);
+        assert_eq!(
+            tracker.landed(&sig, &processed, 101),
+            Some(TxState::Submitted)
+        );
+        let confirmed = status(5, None, TransactionConfirmationStatus::Confirmed);
+        assert_eq!(
+            tracker.landed(&sig, &confirmed, 101),
+            Some(TxState::Confirmed)
+        );
+        // a lagging RPC doesn't downgrade the tx
+        assert_eq!(
+            tracker.landed(&sig, &processed, 102),
+            Some(TxState::Confirmed)
+        );
+        tracker.expired(&sig, 103);
+        let finalized = status(5, None, TransactionConfirmationStatus::Finalized);
+        assert_eq!(
+            tracker.landed(&sig, &finalized, 115),
+            Some(TxState::Finalized)
+        );
+
+        let tx = tracker.get(&sig).unwrap();
+        assert_eq!(tx.slot, Some(5));
+        assert_eq!(tx.request_id.as_deref(), Some("req-1"));
+        assert_eq!((tx.ts, tx.updated_ts), (100, 115));
+        assert!(tracker.get(&Signature::new_unique()).is_none());
+    }
+
+    #[test]
+    fn failed_and_expired() {
+        let tracker = TxTracker::default();
+        let failed = Signature::new_unique();
+        tracker.submitted(failed, None, 100);
+        let err = TransactionError::InstructionError(2, InstructionError::Custom(6003));
+        let landed = status(7, Some(err), TransactionConfirmationStatus::Confirmed);
+        assert_eq!(tracker.landed(&failed, &landed, 101), Some(TxState::Failed));
+        let tx = tracker.get(&failed).unwrap();
+        assert_eq!((tx.slot, tx.code), (Some(7), Some(6003)));
+        assert!(tx.reason.is_some());
+
+        let expired = Signature::new_unique();
+        tracker.submitted(expired, None, 100);
+        tracker.expired(&expired, 104);
+        let tx = tracker.get(&expired).unwrap();
+        assert_eq!(tx.state, TxState::Expired);
+        assert_eq!(tx.slot, None);
+
+        // a late signature status overrides the expiry
+        let confirmed = status(9, None, TransactionConfirmationStatus::Confirmed);
+        assert_eq!(
+            tracker.landed(&expired, &confirmed, 130),
+            Some(TxState::Confirmed)
+        );
+        tracker.expired(&expired, 131);
+        assert_eq!(tracker.get(&expired).unwrap().state, TxState::Confirmed);
+    }
+}
@@ -2,47 +2,68 @@
 //! - gateway request/responses
 //! - wrappers for presenting drift program types with less implementation detail
 //!
+use std::time::Duration;
+
+pub use drift_gateway_types::{
+    Collateral, L2Level, Leverage, MarginInfo, OrderAdjustment, PeggedOrder, PerpPosition,
+    PerpPositionExtended, RepricedOrder, SpotPosition, TxResponse,
+};
 use drift_rs::{
     constants::ProgramData,
-    math::{
-        constants::{BASE_PRECISION, PRICE_PRECISION, QUOTE_PRECISION},
-        liquidation::{CollateralInfo, MarginRequirementInfo},
-    },
+    math::constants::{BASE_PRECISION, PRICE_PRECISION, QUOTE_PRECISION},
     types::{
         self as sdk_types,
         accounts::{PerpMarket, SpotMarket},
-        MarketPrecision, MarketType, ModifyOrderParams, OrderParams, PositionDirection,
-        PostOnlyParam,
+        MarketId, MarketPrecision, MarketStatus, MarketType, ModifyOrderParams, OrderParams,
+        PositionDirection, PostOnlyParam,
     },
-    Wallet,
+    Pubkey, Wallet,
 };
 use rust_decimal::Decimal;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
-
-use crate::websocket::AccountEvent;
+use solana_client::rpc_sender::RpcTransportStats;
+
+use crate::{
+    dca::DcaPlan,
+    events::JournalEntry,
+    execution::MarketExecution,
+    fee_spend::DailyFeeSpend,
+    fills::StoredEvent,
+    history::{EquitySnapshot, Resolution},
+    insurance::SocializedLoss,
+    scheduler::Activation,
+    subscriptions::SubscriptionKind,
+    triggers::{Condition, Trigger, TriggerAction},
+    twap::TwapAlgo,
+    usage::DailyUsage,
+    websocket::{camel_case, AccountEvent, FUNDING_RATE_DECIMALS},
+};
 
 /// decimal places in price values
 pub const PRICE_DECIMALS: u32 = PRICE_PRECISION.ilog10();
 pub const QUOTE_DECIMALS: u32 = QUOTE_PRECISION.ilog10();
 
 #[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct Order {
     #[serde(serialize_with = "order_type_ser", deserialize_with = "order_type_de")]
+    #[cfg_attr(feature = "schema", schemars(with = "drift_gateway_types::OrderType"))]
     order_type: sdk_types::OrderType,
-    market_index: u16,
+    pub(crate) market_index: u16,
     #[serde(
         serialize_with = "ser_market_type",
         deserialize_with = "de_market_type"
     )]
-    market_type: MarketType,
-    amount: Decimal,
-    filled: Decimal,
-    price: Decimal,
+    #[cfg_attr(feature = "schema", schemars(with = "drift_gateway_types::MarketType"))]
+    pub(crate) market_type: MarketType,
+    pub(crate) amount: Decimal,
+    pub(crate) filled: Decimal,
+    pub(crate) price: Decimal,
     post_only: bool,
     reduce_only: bool,
-    user_order_id: u8,
-    order_id: u32,
+    pub(crate) user_order_id: u8,
+    pub(crate) order_id: u32,
     immediate_or_cancel: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     oracle_price_offset: Option<Decimal>,
@@ -108,79 +129,14 @@ where
 }
 
 #[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct SpotPosition {
-    amount: Decimal,
-    #[serde(rename = "type")]
-    balance_type: String, // deposit or borrow
-    market_index: u16,
-}
-
-impl SpotPosition {
-    pub fn from_sdk_type(position: &sdk_types::SpotPosition, spot_market: &SpotMarket) -> Self {
-        // TODO: handle error
-        let token_amount = position.get_token_amount(spot_market).expect("ok");
-        Self {
-            amount: Decimal::from_i128_with_scale(token_amount as i128, spot_market.decimals)
-                .normalize(),
-            market_index: position.market_index,
-            balance_type: if position.balance_type == Default::default() {
-                "deposit".into()
-            } else {
-                "borrow".into()
-            },
-        }
-    }
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct PerpPosition {
-    amount: Decimal,
-    average_entry: Decimal,
-    market_index: u16,
-    #[serde(flatten, skip_serializing_if = "Option::is_none")]
-    extended: Option<PerpPositionExtended>,
-}
-
-impl PerpPosition {
-    pub fn set_extended_info(&mut self, ext: PerpPositionExtended) {
-        self.extended = Some(ext);
-    }
-}
-
-impl From<sdk_types::PerpPosition> for PerpPosition {
-    fn from(value: sdk_types::PerpPosition) -> Self {
-        let amount = Decimal::new(value.base_asset_amount, BASE_PRECISION.ilog10());
-        let average_entry = Decimal::new(value.quote_entry_amount.abs(), PRICE_DECIMALS)
-            .checked_div(amount.abs())
-            .unwrap_or_default();
-
-        Self {
-            amount: amount.normalize(),
-            market_index: value.market_index,
-            average_entry: average_entry.normalize().round_dp(4),
-            extended: None,
-        }
-    }
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct PerpPositionExtended {
-    pub liquidation_price: Decimal,
-    pub unrealized_pnl: Decimal,
-    pub unsettled_pnl: Decimal,
-    pub oracle_price: Decimal,
-}
-
-#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct ModifyOrdersRequest {
     pub orders: Vec<ModifyOrder>,
 }
 
 #[cfg_attr(test, derive(Default))]
 #[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct ModifyOrder {
     #[serde(flatten)]
@@ -195,6 +151,21 @@ pub struct ModifyOrder {
 }
 
 impl ModifyOrder {
+    /// Side of the modified order, if its amount is modified
+    pub fn direction(&self) -> Option<PositionDirection> {
+        self.amount.map(|amount| {
+            if amount.is_sign_negative() {
+                PositionDirection::Short
+            } else {
+                PositionDirection::Long
+            }
+        })
+    }
+
+    pub fn is_reduce_only(&self) -> bool {
+        self.reduce_only.unwrap_or_default()
+    }
+
     pub fn to_order_params(self, base_decimals: u32) -> ModifyOrderParams {
         let target_scale = 10_u32.pow(base_decimals);
 
@@ -233,12 +204,14 @@ impl ModifyOrder {
 }
 
 #[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct PlaceOrdersRequest {
     pub orders: Vec<PlaceOrder>,
 }
 
 #[cfg_attr(test, derive(Default))]
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct PlaceOrder {
     #[serde(flatten)]
@@ -254,6 +227,7 @@ pub struct PlaceOrder {
         deserialize_with = "order_type_de",
         default
     )]
+    #[cfg_attr(feature = "schema", schemars(with = "drift_gateway_types::OrderType"))]
     order_type: sdk_types::OrderType,
     #[serde(default)]
     post_only: bool,
@@ -262,6 +236,226 @@ pub struct PlaceOrder {
     #[serde(default)]
     oracle_price_offset: Option<Decimal>,
     max_ts: Option<i64>,
+    /// max. fill price deviation from the oracle price in bps, market and oracle orders only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_slippage_bps: Option<u32>,
+    /// place as a limit order floating this many bps from the oracle price, negative below
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peg_to_oracle: Option<i32>,
+}
+
+#[derive(Deserialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduleOrdersRequest {
+    /// unix timestamp (seconds) to place the orders at
+    #[serde(default)]
+    pub activate_at: Option<u64>,
+    /// slot to place the orders at
+    #[serde(default)]
+    pub activate_slot: Option<u64>,
+    pub orders: Vec<PlaceOrder>,
+}
+
+#[derive(Serialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduleOrdersResponse {
+    pub id: u64,
+}
+
+#[derive(Serialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduledOrders {
+    pub id: u64,
+    #[serde(flatten)]
+    pub activation: Activation,
+    pub sub_account_id: u16,
+    pub orders: Vec<PlaceOrder>,
+}
+
+#[derive(Serialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduledOrdersResponse {
+    pub scheduled: Vec<ScheduledOrders>,
+}
+
+#[derive(Deserialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct DcaRequest {
+    #[serde(flatten)]
+    pub market: Market,
+    /// base amount per order, negative to sell
+    pub amount: Decimal,
+    /// seconds between orders
+    pub interval: u64,
+    /// total quote notional to trade
+    pub budget: Decimal,
+    /// user order id of placed orders, unused by other orders of the sub-account (default: any free)
+    #[serde(default)]
+    pub user_order_id: Option<u8>,
+    /// unix timestamp of the first order (default: now)
+    #[serde(default)]
+    pub start_at: Option<u64>,
+}
+
+#[derive(Serialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct DcaPlansResponse {
+    pub plans: Vec<DcaPlan>,
+}
+
+#[derive(Deserialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct TwapRequest {
+    #[serde(flatten)]
+    pub market: Market,
+    /// base amount of the parent order, negative to sell
+    pub amount: Decimal,
+    /// seconds to execute over
+    pub duration: u64,
+    /// seconds between child orders
+    pub interval: u64,
+    /// max. share of the market's 24h volume (pro rata per interval) traded per child order,
+    /// in (0, 1], perp markets only
+    #[serde(default)]
+    pub max_participation: Option<Decimal>,
+    /// max. fill price deviation of child orders from the oracle price in bps
+    #[serde(default)]
+    pub max_slippage_bps: Option<u32>,
+    /// user order id of child orders, unused by other orders of the sub-account (default: any free)
+    #[serde(default)]
+    pub user_order_id: Option<u8>,
+    /// unix timestamp of the first child order (default: now)
+    #[serde(default)]
+    pub start_at: Option<u64>,
+}
+
+#[derive(Serialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct TwapAlgosResponse {
+    pub algos: Vec<TwapAlgo>,
+}
+
+#[derive(Deserialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct TriggerRequest {
+    pub condition: Condition,
+    pub action: TriggerAction,
+}
+
+#[derive(Serialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct TriggersResponse {
+    pub triggers: Vec<Trigger>,
+}
+
+#[derive(Serialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookResponse {
+    /// txs decoded for events
+    pub txs: usize,
+}
+
+#[derive(Serialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct MarginSnapshot {
+    pub margin: MarginInfo,
+    /// collateral for initial margin
+    pub collateral: Collateral,
+}
+
+impl MarginSnapshot {
+    /// True if collateral does not cover the initial margin requirement
+    pub fn is_breached(&self) -> bool {
+        self.collateral.total < self.margin.initial
+    }
+}
+
+/// An order of a what-if batch that breaches limits on its own
+#[derive(Serialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct OrderBreach {
+    /// index of the order in the request
+    pub order_index: usize,
+    #[serde(flatten)]
+    pub market: Market,
+    pub reason: String,
+}
+
+#[derive(Serialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct OrdersWhatIfResponse {
+    pub current: MarginSnapshot,
+    /// margin after all orders of the batch are filled
+    pub post_trade: MarginSnapshot,
+    pub breaches: Vec<OrderBreach>,
+    /// some post-only order would cross resting orders of other users
+    pub would_cross: bool,
+    /// indexes of the post-only orders that would cross
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub crossing_orders: Vec<usize>,
+}
+
+/// Transport stats of an RPC client since startup
+#[derive(Serialize, Debug, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct RpcStats {
+    pub requests: usize,
+    /// total time spent in requests
+    pub elapsed_ms: u64,
+    pub avg_latency_ms: Decimal,
+    /// total time spent waiting on RPC rate limits
+    pub rate_limited_ms: u64,
+}
+
+impl From<RpcTransportStats> for RpcStats {
+    fn from(value: RpcTransportStats) -> Self {
+        let elapsed_ms = value.elapsed_time.as_millis() as u64;
+        Self {
+            requests: value.request_count,
+            elapsed_ms,
+            avg_latency_ms: if value.request_count == 0 {
+                Decimal::ZERO
+            } else {
+                (Decimal::from(elapsed_ms) / Decimal::from(value.request_count)).round_dp(2)
+            },
+            rate_limited_ms: value.rate_limited_time.as_millis() as u64,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct ReadinessResponse {
+    pub ready: bool,
+    /// components not yet warm, one of `markets`, `oracles`, `accounts`, `blockhash`
+    pub pending: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct RpcStatsResponse {
+    /// primary RPC, serves queries and subscriptions
+    pub primary: RpcStats,
+    /// RPC used for tx submission (same as primary unless `--tx-rpc` is set)
+    pub tx: RpcStats,
+    pub extra: Vec<RpcStats>,
 }
 
 pub fn ser_market_type<S>(x: &MarketType, s: S) -> Result<S::Ok, S::Error>
@@ -302,6 +496,23 @@ fn scale_decimal_to_i64(x: Decimal, target: u32) -> i64 {
 }
 
 impl PlaceOrder {
+    /// A market order for signed base `amount`
+    pub fn market_order(market: Market, amount: Decimal) -> Self {
+        Self {
+            market,
+            amount,
+            price: Decimal::ZERO,
+            user_order_id: 0,
+            order_type: sdk_types::OrderType::Market,
+            post_only: false,
+            reduce_only: false,
+            oracle_price_offset: None,
+            max_ts: None,
+            max_slippage_bps: None,
+            peg_to_oracle: None,
+        }
+    }
+
     pub fn to_order_params(self, base_decimals: u32) -> OrderParams {
         let target_scale = 10_u32.pow(base_decimals);
         let base_amount = scale_decimal_to_u64(self.amount.abs(), target_scale);
@@ -340,6 +551,7 @@ impl PlaceOrder {
 
 #[cfg_attr(test, derive(Default))]
 #[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct Market {
     /// The market index
@@ -349,6 +561,7 @@ pub struct Market {
         deserialize_with = "de_market_type"
     )]
     /// The market type (Spot or Perp)
+    #[cfg_attr(feature = "schema", schemars(with = "drift_gateway_types::MarketType"))]
     pub market_type: MarketType,
 }
 
@@ -371,9 +584,16 @@ impl Market {
             market_type: MarketType::Perp,
         }
     }
+    pub fn as_market_id(self) -> MarketId {
+        match self.market_type {
+            MarketType::Perp => MarketId::perp(self.market_index),
+            MarketType::Spot => MarketId::spot(self.market_index),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct GetPositionsRequest {
     #[serde(flatten)]
@@ -381,6 +601,7 @@ pub struct GetPositionsRequest {
 }
 
 #[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct GetOrdersRequest {
     #[serde(flatten)]
@@ -388,17 +609,26 @@ pub struct GetOrdersRequest {
 }
 
 #[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct GetOrdersResponse {
     pub orders: Vec<Order>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct GetPositionsResponse {
     pub spot: Vec<SpotPosition>,
     pub perp: Vec<PerpPosition>,
+    #[serde(
+        default,
+        rename = "subAccountName",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub sub_account_name: Option<String>,
 }
 
 #[derive(Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct MarketInfo {
     #[serde(rename = "marketIndex")]
@@ -411,6 +641,8 @@ pub struct MarketInfo {
     initial_margin_ratio: Option<Decimal>,
     #[serde(skip_serializing_if = "Option::is_none")]
     maintenance_margin_ratio: Option<Decimal>,
+    #[serde(flatten)]
+    operations: MarketOperations,
 }
 
 impl From<SpotMarket> for MarketInfo {
@@ -426,6 +658,7 @@ impl From<SpotMarket> for MarketInfo {
             min_order_size: Decimal::new(value.min_order_size() as i64, value.decimals).normalize(),
             initial_margin_ratio: None,
             maintenance_margin_ratio: None,
+            operations: MarketOperations::spot(&value),
         }
     }
 }
@@ -449,24 +682,384 @@ impl From<PerpMarket> for MarketInfo {
             maintenance_margin_ratio: Some(
                 Decimal::new(value.margin_ratio_maintenance as i64, 4).normalize(),
             ),
+            operations: MarketOperations::perp(&value),
         }
     }
 }
 
 #[derive(Debug, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct MarketInfoResponse {
     pub open_interest: u64,
     pub max_open_interest: u64,
+    #[serde(flatten)]
+    pub operations: MarketOperations,
+    pub oracle: OracleInfo,
+}
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct UserHealthResponse {
+    /// 0-100, 0 at the point of liquidation
+    pub health: Decimal,
+    /// maintenance requirement / total collateral
+    pub margin_usage: Decimal,
+    pub total_collateral: Decimal,
+    pub maintenance_requirement: Decimal,
+    pub positions: Vec<PositionHealth>,
+    /// position with the largest maintenance requirement
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub largest_risk: Option<PositionHealth>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct PositionHealth {
+    #[serde(flatten)]
+    pub market: Market,
+    pub amount: Decimal,
+    pub oracle_price: Decimal,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub liquidation_price: Option<Decimal>,
+    /// relative price move until liquidation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub liquidation_distance: Option<Decimal>,
+    /// maintenance margin requirement of the position (excludes size premium)
+    pub maintenance_requirement: Decimal,
+}
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct OracleInfo {
+    /// oracle type e.g. pythPull, switchboardOnDemand
+    pub source: String,
+    pub pubkey: String,
+    pub price: Decimal,
+    /// confidence interval of the price
+    pub confidence: Decimal,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct FillsRequest {
+    /// market symbol e.g. `sol-perp`, all markets if unset
+    pub market: Option<String>,
+    /// unix timestamp, inclusive
+    pub from: Option<u64>,
+    /// unix timestamp, inclusive
+    pub to: Option<u64>,
+    pub limit: Option<usize>,
+    /// return order events instead of fills
+    #[serde(default)]
+    pub orders: bool,
+}
+
+#[derive(Serialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct FillsResponse {
+    /// oldest first
+    pub events: Vec<StoredEvent>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct ExecutionReportRequest {
+    /// market symbol e.g. `sol-perp`, all markets if unset
+    pub market: Option<String>,
+    /// unix timestamp, inclusive (default: 7 days ago)
+    pub from: Option<u64>,
+    /// unix timestamp, inclusive (default: now)
+    pub to: Option<u64>,
+    /// secs after a fill to mark it out against the oracle price (default: 60)
+    pub horizon: Option<u64>,
+}
+
+#[derive(Serialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct ExecutionReportResponse {
+    pub markets: Vec<MarketExecution>,
+}
+
+/// Cached price of a subscribed oracle
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct OraclePrice {
+    #[serde(flatten)]
+    pub market: Market,
+    pub price: Decimal,
+    /// slot of the price update
+    pub slot: u64,
+}
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotResponse {
+    /// slot of the account data, orders, positions, and margin are as of this slot
+    pub slot: u64,
+    pub orders: Vec<Order>,
+    pub spot: Vec<SpotPosition>,
+    pub perp: Vec<PerpPosition>,
+    pub margin: MarginInfo,
+    /// prices of subscribed oracles, each tagged with its own slot
+    pub oracles: Vec<OraclePrice>,
+}
+
+/// decimal places of margin ratios and spot weights
+const MARGIN_DECIMALS: u32 = 4;
+/// decimal places of imf factors and liquidation fees
+const FACTOR_DECIMALS: u32 = 6;
+/// funding rate precision over price precision
+const FUNDING_RATE_BUFFER: i64 = 1_000;
+
+/// Margin configuration of a market, fields are set by market type
+#[derive(Serialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct MarketMarginResponse {
+    #[serde(flatten)]
+    pub market: Market,
+    pub symbol: String,
+    /// perp only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub initial_margin_ratio: Option<Decimal>,
+    /// perp only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub maintenance_margin_ratio: Option<Decimal>,
+    /// perp only, imf factor applied to positive unrealized pnl
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unrealized_pnl_imf_factor: Option<Decimal>,
+    /// perp only, max. pnl imbalance before unrealized pnl is discounted (quote)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unrealized_pnl_max_imbalance: Option<Decimal>,
+    /// spot only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub initial_asset_weight: Option<Decimal>,
+    /// spot only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub maintenance_asset_weight: Option<Decimal>,
+    /// spot only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub initial_liability_weight: Option<Decimal>,
+    /// spot only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub maintenance_liability_weight: Option<Decimal>,
+    /// scales margin requirements up with position size
+    pub imf_factor: Decimal,
+    /// fee paid to liquidators
+    pub liquidator_fee: Decimal,
+    /// fee paid to the insurance fund on liquidation
+    pub if_liquidation_fee: Decimal,
+}
+
+impl MarketMarginResponse {
+    pub fn perp(market: &PerpMarket) -> Self {
+        let margin = |x: u32| Some(Decimal::new(x as i64, MARGIN_DECIMALS).normalize());
+        Self {
+            market: Market::perp(market.market_index),
+            symbol: decode_name(&market.name),
+            initial_margin_ratio: margin(market.margin_ratio_initial),
+            maintenance_margin_ratio: margin(market.margin_ratio_maintenance),
+            unrealized_pnl_imf_factor: Some(factor(market.unrealized_pnl_imf_factor)),
+            unrealized_pnl_max_imbalance: Some(
+                Decimal::new(market.unrealized_pnl_max_imbalance as i64, QUOTE_DECIMALS)
+                    .normalize(),
+            ),
+            initial_asset_weight: None,
+            maintenance_asset_weight: None,
+            initial_liability_weight: None,
+            maintenance_liability_weight: None,
+            imf_factor: factor(market.imf_factor),
+            liquidator_fee: factor(market.liquidator_fee),
+            if_liquidation_fee: factor(market.if_liquidation_fee),
+        }
+    }
+
+    pub fn spot(market: &SpotMarket) -> Self {
+        let weight = |x: u32| Some(Decimal::new(x as i64, MARGIN_DECIMALS).normalize());
+        Self {
+            market: Market::spot(market.market_index),
+            symbol: decode_name(&market.name),
+            initial_margin_ratio: None,
+            maintenance_margin_ratio: None,
+            unrealized_pnl_imf_factor: None,
+            unrealized_pnl_max_imbalance: None,
+            initial_asset_weight: weight(market.initial_asset_weight),
+            maintenance_asset_weight: weight(market.maintenance_asset_weight),
+            initial_liability_weight: weight(market.initial_liability_weight),
+            maintenance_liability_weight: weight(market.maintenance_liability_weight),
+            imf_factor: factor(market.imf_factor),
+            liquidator_fee: factor(market.liquidator_fee),
+            if_liquidation_fee: factor(market.if_liquidation_fee),
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct FundingRateResponse {
+    pub market_index: u16,
+    /// last funding rate, quote per base unit
+    pub last_funding_rate: Decimal,
+    /// next funding rate predicted from the mark/oracle TWAP spread, before clamping by the
+    /// market's contract tier
+    pub predicted_funding_rate: Decimal,
+    /// unix timestamp of the last funding update
+    pub last_funding_ts: i64,
+    /// unix timestamp from which the next funding update is due
+    pub next_funding_ts: i64,
+}
+
+impl FundingRateResponse {
+    pub fn new(market: &PerpMarket) -> Self {
+        Self {
+            market_index: market.market_index,
+            last_funding_rate: Decimal::new(market.amm.last_funding_rate, FUNDING_RATE_DECIMALS)
+                .normalize(),
+            predicted_funding_rate: Decimal::new(
+                predicted_funding_rate(
+                    market.amm.last_mark_price_twap,
+                    market.amm.historical_oracle_data.last_oracle_price_twap,
+                    market.amm.funding_period,
+                ),
+                FUNDING_RATE_DECIMALS,
+            )
+            .normalize(),
+            last_funding_ts: market.amm.last_funding_rate_ts,
+            next_funding_ts: next_funding_ts(
+                market.amm.last_funding_rate_ts,
+                market.amm.funding_period,
+            ),
+        }
+    }
+}
+
+/// Funding rate (quote per base, 1e9 precision) of a mark/oracle TWAP spread (1e6 precision)
+///
+/// the daily spread is paid over `funding_period` secs, as the drift program does
+fn predicted_funding_rate(mark_twap: u64, oracle_twap: i64, funding_period: i64) -> i64 {
+    const ONE_HOUR: i64 = 60 * 60;
+    let period_adjustment = (24 * ONE_HOUR) / funding_period.max(ONE_HOUR);
+    (mark_twap as i64 - oracle_twap) * FUNDING_RATE_BUFFER / period_adjustment
+}
+
+/// Funding updates are due at multiples of `funding_period`
+fn next_funding_ts(last_funding_ts: i64, funding_period: i64) -> i64 {
+    if funding_period <= 0 {
+        return last_funding_ts;
+    }
+    (last_funding_ts / funding_period + 1) * funding_period
+}
+
+fn factor(x: u32) -> Decimal {
+    Decimal::new(x as i64, FACTOR_DECIMALS).normalize()
+}
+
+/// Decode an on-chain account name, names are utf8 padded with spaces
+pub(crate) fn decode_name(name: &[u8; 32]) -> String {
+    String::from_utf8_lossy(name)
+        .trim_end_matches([' ', '\0'])
+        .to_string()
+}
+
+#[derive(Serialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct InsuranceResponse {
+    pub market_index: u16,
+    /// balance of the insurance fund backing the market (quote)
+    pub insurance_fund_balance: Decimal,
+    /// max. insurance the market may draw from the insurance fund (quote)
+    pub max_insurance: Decimal,
+    /// insurance drawn by the market so far (quote)
+    pub settled_insurance: Decimal,
+    /// cumulative losses socialized to the market's users (quote)
+    pub total_social_loss: Decimal,
+    /// recent socialized losses observed by the gateway, newest first
+    pub socialized_losses: Vec<SocializedLoss>,
+}
+
+/// Perp market operations the program may pause as (bit, name)
+const PERP_OPERATIONS: [(u8, &str); 6] = [
+    (1, "updateFunding"),
+    (2, "ammFill"),
+    (4, "fill"),
+    (8, "settlePnl"),
+    (16, "settlePnlWithPosition"),
+    (32, "liquidation"),
+];
+
+/// Spot market operations the program may pause as (bit, name)
+const SPOT_OPERATIONS: [(u8, &str); 5] = [
+    (1, "updateCumulativeInterest"),
+    (2, "fill"),
+    (4, "deposit"),
+    (8, "withdraw"),
+    (16, "liquidation"),
+];
+
+/// Operational state of a market
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct MarketOperations {
+    /// e.g. active, reduceOnly, fillPaused, settlement
+    #[serde(serialize_with = "ser_market_status")]
+    #[cfg_attr(feature = "schema", schemars(with = "String"))]
+    pub status: MarketStatus,
+    /// operations paused by the program e.g. fill, liquidation
+    pub paused_operations: Vec<&'static str>,
+}
+
+impl MarketOperations {
+    pub fn perp(market: &PerpMarket) -> Self {
+        Self::new(market.status, market.paused_operations, &PERP_OPERATIONS)
+    }
+    pub fn spot(market: &SpotMarket) -> Self {
+        Self::new(market.status, market.paused_operations, &SPOT_OPERATIONS)
+    }
+    fn new(status: MarketStatus, paused_operations: u8, operations: &[(u8, &'static str)]) -> Self {
+        Self {
+            status,
+            paused_operations: operations
+                .iter()
+                .filter(|(bit, _)| paused_operations & bit != 0)
+                .map(|(_, name)| *name)
+                .collect(),
+        }
+    }
+    /// True if the program will not fill orders in the market
+    pub fn fills_paused(&self) -> bool {
+        self.paused_operations.contains(&"fill")
+    }
+}
+
+fn ser_market_status<S>(x: &MarketStatus, s: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    s.serialize_str(&camel_case(x))
 }
 
 #[derive(Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct AllMarketsResponse {
     pub spot: Vec<MarketInfo>,
     pub perp: Vec<MarketInfo>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct CancelOrdersRequest {
     /// Market to cancel orders
@@ -480,18 +1073,8 @@ pub struct CancelOrdersRequest {
     pub user_ids: Option<Vec<u8>>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct TxResponse {
-    tx: String,
-}
-
-impl TxResponse {
-    pub fn new(tx_signature: String) -> Self {
-        Self { tx: tx_signature }
-    }
-}
-
 #[derive(Serialize, Deserialize, Debug, Default)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct TxEventsResponse {
     events: Vec<AccountEvent>,
     success: bool,
@@ -510,6 +1093,7 @@ impl TxEventsResponse {
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct CancelAndPlaceRequest {
     pub cancel: CancelOrdersRequest,
     pub modify: ModifyOrdersRequest,
@@ -530,55 +1114,390 @@ pub(crate) fn get_market_decimals(program_data: &ProgramData, market: Market) ->
 }
 
 #[derive(Serialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct SolBalanceResponse {
     pub balance: Decimal,
 }
 
-#[derive(Serialize, Debug)]
-pub struct UserMarginResponse {
-    pub initial: Decimal,
-    pub maintenance: Decimal,
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct BenchRequest {
+    /// number of cancel txs to send, each lands onchain and pays tx fees
+    #[serde(default = "BenchRequest::default_iterations")]
+    pub iterations: u32,
+}
+
+impl BenchRequest {
+    fn default_iterations() -> u32 {
+        5
+    }
 }
 
-impl From<MarginRequirementInfo> for UserMarginResponse {
-    fn from(value: MarginRequirementInfo) -> Self {
+impl Default for BenchRequest {
+    fn default() -> Self {
         Self {
-            initial: Decimal::from_i128_with_scale(value.initial as i128, PRICE_DECIMALS)
-                .normalize(),
-            maintenance: Decimal::from_i128_with_scale(value.maintenance as i128, PRICE_DECIMALS)
-                .normalize(),
+            iterations: Self::default_iterations(),
         }
     }
 }
 
-#[derive(Serialize, Debug)]
-pub struct UserLeverageResponse {
-    pub leverage: Decimal,
+/// Latency percentiles in milliseconds
+#[derive(Serialize, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct LatencyStats {
+    pub p50: Decimal,
+    pub p99: Decimal,
 }
 
-impl From<u128> for UserLeverageResponse {
-    fn from(value: u128) -> Self {
+impl LatencyStats {
+    pub fn from_samples(samples: &mut [Duration]) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+        samples.sort_unstable();
+        let percentile = |p: usize| {
+            let idx = ((samples.len() * p).div_ceil(100)).saturating_sub(1);
+            Decimal::new(samples[idx].as_micros() as i64, 3).normalize()
+        };
         Self {
-            leverage: Decimal::from_i128_with_scale(value as i128, PRICE_DECIMALS).normalize(),
+            p50: percentile(50),
+            p99: percentile(99),
         }
     }
 }
 
 #[derive(Serialize, Debug)]
-pub struct UserCollateralResponse {
-    pub total: Decimal,
-    pub free: Decimal,
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct BenchResponse {
+    pub iterations: u32,
+    /// number of txs confirmed before timing out
+    pub confirmed: u32,
+    pub build: LatencyStats,
+    pub sign: LatencyStats,
+    pub send: LatencyStats,
+    pub confirm: LatencyStats,
+    /// signatures of the cancel txs sent onchain
+    pub signatures: Vec<String>,
 }
 
-impl From<CollateralInfo> for UserCollateralResponse {
-    fn from(value: CollateralInfo) -> Self {
-        Self {
-            total: Decimal::from_i128_with_scale(value.total, QUOTE_DECIMALS).normalize(),
-            free: Decimal::from_i128_with_scale(value.free, QUOTE_DECIMALS).normalize(),
-        }
+#[derive(Deserialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct PollEventsRequest {
+    /// return events with sequence number greater than this
+    #[serde(default)]
+    pub since: u64,
+    /// max. time to wait for new events
+    #[serde(default = "PollEventsRequest::default_timeout_ms")]
+    pub timeout_ms: u64,
+    /// journal epoch `since` refers to, events are returned from the start of the journal if it
+    /// restarted since
+    #[serde(default)]
+    pub epoch: Option<u64>,
+}
+
+impl PollEventsRequest {
+    fn default_timeout_ms() -> u64 {
+        30_000
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PollEventsResponse {
+    pub events: Vec<JournalEntry>,
+    /// sequence number to use as `since` on the next poll
+    pub last_seq: u64,
+    /// journal epoch to pass with `since` on the next poll
+    pub epoch: u64,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct ReconcileRequest {
+    /// orders the client believes are open, compared if set
+    #[serde(default)]
+    pub orders: Option<Vec<ReconcileOrder>>,
+    /// positions the client believes it holds, compared if set
+    #[serde(default)]
+    pub positions: Option<Vec<ReconcilePosition>>,
+}
+
+#[derive(Deserialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct ReconcileOrder {
+    #[serde(flatten)]
+    pub market: Market,
+    /// matched by `orderId` if set, otherwise by `userOrderId`
+    #[serde(default)]
+    pub order_id: Option<u32>,
+    #[serde(default)]
+    pub user_order_id: Option<u8>,
+    /// signed order amount, compared if set
+    #[serde(default)]
+    pub amount: Option<Decimal>,
+    #[serde(default)]
+    pub price: Option<Decimal>,
+    #[serde(default)]
+    pub filled: Option<Decimal>,
+}
+
+#[derive(Deserialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct ReconcilePosition {
+    #[serde(flatten)]
+    pub market: Market,
+    /// signed position size, spot borrows are negative
+    pub amount: Decimal,
+}
+
+/// A difference between client and on-chain state
+#[derive(Serialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Discrepancy {
+    /// order is believed open but is not open on-chain (filled, cancelled, or never placed)
+    #[serde(rename_all = "camelCase")]
+    MissingOrder {
+        #[serde(flatten)]
+        market: Market,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        order_id: Option<u32>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        user_order_id: Option<u8>,
+    },
+    /// order is open on-chain but unknown to the client
+    #[serde(rename_all = "camelCase")]
+    UnknownOrder { order: Order },
+    /// order field differs from on-chain
+    #[serde(rename_all = "camelCase")]
+    OrderMismatch {
+        order_id: u32,
+        field: &'static str,
+        expected: Decimal,
+        actual: Decimal,
+    },
+    /// position size differs from on-chain
+    #[serde(rename_all = "camelCase")]
+    PositionMismatch {
+        #[serde(flatten)]
+        market: Market,
+        expected: Decimal,
+        actual: Decimal,
+    },
+}
+
+#[derive(Serialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct ReconcileResponse {
+    pub in_sync: bool,
+    pub discrepancies: Vec<Discrepancy>,
+}
+
+#[derive(Serialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct SubAccountInfo {
+    pub sub_account_id: u16,
+    pub name: String,
+    pub address: String,
+}
+
+#[derive(Serialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct SubAccountsResponse {
+    pub sub_accounts: Vec<SubAccountInfo>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct CreateSubAccountRequest {
+    /// id of the new sub-account, the next free id if unset
+    pub sub_account_id: Option<u16>,
+    /// on-chain name, up to 32 bytes
+    pub name: Option<String>,
+}
+
+/// Move spot collateral between sub-accounts of the authority
+#[derive(Deserialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct TransferRequest {
+    pub from_sub_account_id: u16,
+    pub to_sub_account_id: u16,
+    /// spot market of the collateral
+    pub market_index: u16,
+    /// token amount e.g. `100.5` (USDC)
+    pub amount: Decimal,
+}
+
+impl TransferRequest {
+    /// Return the amount in token precision of a spot market with `decimals`
+    pub fn token_amount(&self, decimals: u32) -> u64 {
+        scale_decimal_to_u64(self.amount, 10_u32.pow(decimals))
     }
 }
 
+#[derive(Serialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(untagged)]
+pub enum TransferResponse {
+    Sent(TxResponse),
+    /// held until confirmed with the token
+    #[serde(rename_all = "camelCase")]
+    Pending {
+        confirm_token: String,
+        /// secs left to confirm
+        expires_in: u64,
+    },
+}
+
+/// Confirm an operation held for confirmation
+#[derive(Deserialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ConfirmRequest {
+    pub token: String,
+}
+
+#[derive(Serialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct CreateSubAccountResponse {
+    pub sub_account_id: u16,
+    pub address: String,
+    #[serde(flatten)]
+    pub tx: TxResponse,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct EquityHistoryRequest {
+    /// bucket size of returned snapshots e.g. `1m`, `1h`, `1d`
+    #[serde(default)]
+    pub resolution: Resolution,
+}
+
+#[derive(Serialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct EquityHistoryResponse {
+    pub snapshots: Vec<EquitySnapshot>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct OrderbookRequest {
+    /// max. price levels per side
+    pub depth: Option<usize>,
+}
+
+#[derive(Serialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct OrderbookResponse {
+    #[serde(flatten)]
+    pub market: Market,
+    /// oracle price used for oracle offset orders
+    pub oracle_price: Decimal,
+    /// best (highest) first
+    pub bids: Vec<L2Level>,
+    /// best (lowest) first
+    pub asks: Vec<L2Level>,
+}
+
+/// Identifies a subscription by market (market/oracle) or account address
+#[derive(Deserialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct SubscriptionRequest {
+    pub kind: SubscriptionKind,
+    #[serde(flatten)]
+    pub market: Option<Market>,
+    pub account: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct SubscriptionInfo {
+    pub kind: SubscriptionKind,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    pub market: Option<Market>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub account: Option<String>,
+    /// unix timestamp of the (re)subscription
+    pub subscribed_ts: u64,
+    /// latest slot of the subscribed data, None until observed
+    pub last_slot: Option<u64>,
+    /// unix timestamp when `last_slot` last changed
+    pub last_update_ts: Option<u64>,
+    /// seconds since the last update, or since subscribing if never updated
+    pub secs_since_update: u64,
+    /// times resubscribed, manually or after a stall
+    pub resubscribes: u32,
+}
+
+#[derive(Serialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct SubscriptionsResponse {
+    /// stalest first
+    pub subscriptions: Vec<SubscriptionInfo>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct UsageRequest {
+    /// unix timestamp, inclusive (default: 7 days ago)
+    pub from: Option<u64>,
+    /// unix timestamp, inclusive (default: now)
+    pub to: Option<u64>,
+    /// usage of this authority only, all authorities if unset
+    pub authority: Option<String>,
+    /// usage of this API key only, all keys if unset
+    pub api_key: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct UsageResponse {
+    /// daily rollups, oldest first
+    pub usage: Vec<DailyUsage>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct FeeSpendRequest {
+    /// unix timestamp, inclusive (default: 7 days ago)
+    pub from: Option<u64>,
+    /// unix timestamp, inclusive (default: now)
+    pub to: Option<u64>,
+    /// spend of this sub-account address only, all sub-accounts if unset
+    pub sub_account: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct FeeSpendResponse {
+    /// daily rollups, oldest first
+    pub spend: Vec<DailyFeeSpend>,
+}
+
 #[derive(PartialEq)]
 pub enum WalletMode {
     Normal,
@@ -590,15 +1509,47 @@ pub enum WalletMode {
 pub struct GatewayWallet {
     wallet: Wallet,
     mode: WalletMode,
+    /// wallets acting for additional delegator authorities
+    delegators: Vec<Wallet>,
 }
 
 impl GatewayWallet {
     pub fn new(wallet: Wallet, mode: WalletMode) -> Self {
-        Self { wallet, mode }
+        Self {
+            wallet,
+            mode,
+            delegators: Vec::default(),
+        }
+    }
+    /// Act for additional delegator `authorities` with the same delegate key
+    pub fn with_delegators(mut self, authorities: &[Pubkey]) -> Self {
+        self.delegators = authorities
+            .iter()
+            .filter(|a| *a != self.wallet.authority())
+            .map(|a| {
+                let mut wallet = self.wallet.clone();
+                wallet.to_delegated(*a);
+                wallet
+            })
+            .collect();
+        self
     }
     pub fn inner(&self) -> &Wallet {
         &self.wallet
     }
+    /// Return the wallet acting for `authority`, the default wallet if unset
+    pub fn for_authority(&self, authority: Option<&Pubkey>) -> Option<&Wallet> {
+        match authority {
+            Some(authority) => std::iter::once(&self.wallet)
+                .chain(self.delegators.iter())
+                .find(|w| w.authority() == authority),
+            None => Some(&self.wallet),
+        }
+    }
+    /// Additional delegator authorities
+    pub fn delegators(&self) -> impl Iterator<Item = &Pubkey> {
+        self.delegators.iter().map(|w| w.authority())
+    }
     /// True if the wallet is using delegated signing
     pub fn is_delegated(&self) -> bool {
         self.mode == WalletMode::Delegated
@@ -609,6 +1560,38 @@ impl GatewayWallet {
     }
 }
 
+/// Parse the JSON of `value` as `T` and check `T` serializes the same JSON, nulls aside
+///
+/// Round trips gateway types through their `drift_gateway_types` counterpart and back to catch
+/// renamed, missing, or mistyped fields on either side.
+#[cfg(test)]
+pub(crate) fn assert_round_trip<T>(value: &impl Serialize) -> T
+where
+    T: Serialize + serde::de::DeserializeOwned,
+{
+    fn strip_nulls(value: serde_json::Value) -> serde_json::Value {
+        match value {
+            serde_json::Value::Object(map) => map
+                .into_iter()
+                .filter(|(_, v)| !v.is_null())
+                .map(|(k, v)| (k, strip_nulls(v)))
+                .collect(),
+            serde_json::Value::Array(items) => items.into_iter().map(strip_nulls).collect(),
+            other => other,
+        }
+    }
+
+    let json = strip_nulls(serde_json::to_value(value).unwrap());
+    let parsed: T = serde_json::from_value(json.clone()).unwrap_or_else(|err| {
+        panic!(
+            "{json} doesn't parse as {}: {err}",
+            std::any::type_name::<T>()
+        )
+    });
+    assert_eq!(strip_nulls(serde_json::to_value(&parsed).unwrap()), json);
+    parsed
+}
+
 #[cfg(test)]
 mod tests {
     use std::str::FromStr;
@@ -619,7 +1602,58 @@ mod tests {
     };
 
     use super::{Decimal, PlaceOrder};
-    use crate::types::{Market, ModifyOrder, Order};
+    use crate::{
+        events::JournalEntry,
+        types::{
+            assert_round_trip, decode_name, next_funding_ts, predicted_funding_rate,
+            CancelOrdersRequest, Duration, GatewayWallet, GetOrdersResponse, GetPositionsResponse,
+            LatencyStats, MarginInfo, Market, ModifyOrder, OraclePrice, Order, PerpPosition,
+            PerpPositionExtended, PollEventsResponse, Pubkey, RpcStats, SnapshotResponse,
+            SpotPosition, TransferRequest, TxEventsResponse, Wallet, WalletMode,
+        },
+        websocket::{AccountEvent, Channel},
+    };
+
+    #[test]
+    fn decode_name_trims_padding() {
+        let mut name = [b' '; 32];
+        name[..6].copy_from_slice(b"mm-sol");
+        assert_eq!(decode_name(&name), "mm-sol");
+        assert_eq!(decode_name(&[0; 32]), "");
+    }
+
+    #[test]
+    fn rpc_stats_from_transport_stats() {
+        let stats: RpcStats = solana_client::rpc_sender::RpcTransportStats {
+            request_count: 3,
+            elapsed_time: Duration::from_millis(100),
+            rate_limited_time: Duration::from_millis(5),
+        }
+        .into();
+        assert_eq!(stats.requests, 3);
+        assert_eq!(stats.elapsed_ms, 100);
+        assert_eq!(stats.avg_latency_ms, Decimal::from_str("33.33").unwrap());
+        assert_eq!(stats.rate_limited_ms, 5);
+
+        let stats = RpcStats::from(solana_client::rpc_sender::RpcTransportStats::default());
+        assert_eq!(stats.avg_latency_ms, Decimal::ZERO);
+    }
+
+    #[test]
+    fn gateway_wallet_for_authority() {
+        let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut wallet = Wallet::read_only(Pubkey::new_unique());
+        wallet.to_delegated(a);
+        let wallet = GatewayWallet::new(wallet, WalletMode::Delegated).with_delegators(&[a, b]);
+
+        assert_eq!(wallet.delegators().collect::<Vec<_>>(), vec![&b]);
+        assert_eq!(wallet.for_authority(None).unwrap().authority(), &a);
+        assert_eq!(wallet.for_authority(Some(&a)).unwrap().authority(), &a);
+        let delegated = wallet.for_authority(Some(&b)).unwrap();
+        assert_eq!(delegated.authority(), &b);
+        assert_eq!(delegated.signer(), wallet.inner().signer());
+        assert!(wallet.for_authority(Some(&Pubkey::new_unique())).is_none());
+    }
 
     #[test]
     fn place_order_to_order() {
@@ -719,6 +1753,135 @@ mod tests {
         }
     }
 
+    #[test]
+    fn client_types_in_sync() {
+        use drift_gateway_types as client;
+
+        // requests, client to gateway
+        let place = client::PlaceOrder::limit(
+            client::Market::perp(1),
+            Decimal::from_str("-1.5").unwrap(),
+            Decimal::from_str("80.5").unwrap(),
+        )
+        .post_only()
+        .user_order_id(7)
+        .peg_to_oracle(-20);
+        let gateway_place: PlaceOrder = assert_round_trip(&place);
+        assert_eq!(gateway_place.market.market_index, 1);
+        assert_eq!(gateway_place.user_order_id, 7);
+        assert_round_trip::<PlaceOrder>(&client::PlaceOrder::market(
+            client::Market::spot(2),
+            Decimal::from_str("0.1").unwrap(),
+        ));
+        let modify: ModifyOrder = assert_round_trip(&client::ModifyOrder {
+            price: Decimal::from_str("81").ok(),
+            max_ts: Some(1_700_000_000),
+            ..client::ModifyOrder::new(client::Market::spot(2), 5)
+        });
+        assert_eq!(modify.order_id, Some(5));
+        assert_round_trip::<CancelOrdersRequest>(&client::CancelOrders {
+            market: Some(client::Market::perp(0)),
+            ids: Some(vec![1, 2]),
+            user_ids: Some(vec![3]),
+        });
+        assert_round_trip::<CancelOrdersRequest>(&client::CancelOrders::default());
+
+        // responses, gateway to client
+        let order = || {
+            Order::from_sdk_order(
+                drift_rs::types::Order {
+                    base_asset_amount: 1_500_000_000,
+                    price: 80_500_000,
+                    order_id: 3,
+                    direction: PositionDirection::Short,
+                    order_type: OrderType::Limit,
+                    market_type: MarketType::Perp.into(),
+                    oracle_price_offset: -100_000,
+                    ..Default::default()
+                },
+                BASE_PRECISION.ilog10(),
+            )
+        };
+        let client_order: client::Order = assert_round_trip(&order());
+        assert_eq!(client_order.order_id, 3);
+        assert_eq!(client_order.amount, Decimal::from_str("-1.5").unwrap());
+        assert_eq!(client_order.order_type, client::OrderType::Limit);
+        assert_round_trip::<client::OrdersResponse>(&GetOrdersResponse {
+            orders: vec![order()],
+        });
+
+        let spot = SpotPosition {
+            amount: Decimal::from_str("12.5").unwrap(),
+            balance_type: "deposit".into(),
+            market_index: 1,
+        };
+        let mut perp = PerpPosition {
+            amount: Decimal::from_str("-2").unwrap(),
+            average_entry: Decimal::from_str("80.1").unwrap(),
+            market_index: 0,
+            extended: None,
+        };
+        assert_round_trip::<client::PositionsResponse>(&GetPositionsResponse {
+            spot: vec![spot.clone()],
+            perp: vec![perp.clone()],
+            sub_account_name: Some("mm".into()),
+        });
+        perp.set_extended_info(PerpPositionExtended {
+            liquidation_price: Decimal::from_str("120").unwrap(),
+            unrealized_pnl: Decimal::from_str("-1.2").unwrap(),
+            unsettled_pnl: Decimal::ZERO,
+            oracle_price: Decimal::from_str("80.7").unwrap(),
+        });
+        assert_round_trip::<client::PositionsResponse>(&GetPositionsResponse {
+            spot: vec![],
+            perp: vec![perp.clone()],
+            sub_account_name: None,
+        });
+
+        assert_round_trip::<client::Snapshot>(&SnapshotResponse {
+            slot: 300_000_000,
+            orders: vec![order()],
+            spot: vec![spot],
+            perp: vec![perp],
+            margin: MarginInfo {
+                initial: Decimal::from_str("16.2").unwrap(),
+                maintenance: Decimal::from_str("8.1").unwrap(),
+            },
+            oracles: vec![OraclePrice {
+                market: Market::perp(0),
+                price: Decimal::from_str("80.7").unwrap(),
+                slot: 299_999_999,
+            }],
+        });
+
+        let cancel = AccountEvent::OrderCancel {
+            order_id: 3,
+            ts: 1_700_000_000,
+            signature: "sig".into(),
+            tx_idx: 1,
+        };
+        for (success, error) in [
+            (true, None),
+            (false, Some("insufficient collateral".into())),
+        ] {
+            let events: client::TxEventsResponse =
+                assert_round_trip(&TxEventsResponse::new(vec![cancel.clone()], success, error));
+            assert!(matches!(events.events[..], [client::EventData::Known(_)]));
+        }
+
+        let poll: client::PollEventsResponse = assert_round_trip(&PollEventsResponse {
+            events: vec![JournalEntry {
+                seq: 7,
+                sub_account_id: 2,
+                channel: Channel::Orders,
+                data: cancel,
+            }],
+            last_seq: 7,
+            epoch: 1_700_000_000,
+        });
+        assert!(matches!(poll.events[0].data, client::EventData::Known(_)));
+    }
+
     #[test]
     fn modify_order_to_order_params() {
         let m = ModifyOrder {
@@ -747,4 +1910,49 @@ mod tests {
         assert_eq!(order_params.price, Some(1_020_000));
         assert_eq!(order_params.oracle_price_offset, Some(-2_000_000));
     }
+
+    #[test]
+    fn latency_stats_from_samples() {
+        let mut samples: Vec<std::time::Duration> = (1..=100)
+            .rev()
+            .map(std::time::Duration::from_millis)
+            .collect();
+        let stats = LatencyStats::from_samples(&mut samples);
+        assert_eq!(stats.p50, Decimal::from(50));
+        assert_eq!(stats.p99, Decimal::from(99));
+
+        let mut samples = vec![std::time::Duration::from_micros(1_500)];
+        let stats = LatencyStats::from_samples(&mut samples);
+        assert_eq!(stats.p50, Decimal::from_str("1.5").unwrap());
+        assert_eq!(stats.p99, Decimal::from_str("1.5").unwrap());
+
+        assert_eq!(LatencyStats::from_samples(&mut []), LatencyStats::default());
+    }
+
+    #[test]
+    fn funding_rate_prediction() {
+        // mark $1 above oracle, paid over 24 hourly periods
+        assert_eq!(
+            predicted_funding_rate(101_000_000, 100_000_000, 3_600),
+            1_000_000_000 / 24
+        );
+        assert_eq!(
+            predicted_funding_rate(99_000_000, 100_000_000, 3_600),
+            -1_000_000_000 / 24
+        );
+        assert_eq!(next_funding_ts(7_205, 3_600), 10_800);
+        assert_eq!(next_funding_ts(7_200, 3_600), 10_800);
+    }
+
+    #[test]
+    fn transfer_token_amount() {
+        let req = TransferRequest {
+            from_sub_account_id: 0,
+            to_sub_account_id: 1,
+            market_index: 0,
+            amount: Decimal::from_str("100.5").unwrap(),
+        };
+        assert_eq!(req.token_amount(6), 100_500_000);
+        assert_eq!(req.token_amount(9), 100_500_000_000);
+    }
 }
@@ -0,0 +1,243 @@
+//! API usage accounting by authority and API key
+//!
+//! HTTP requests, orders sent, and response bytes are counted per authority, API key, and UTC day.
+//! With `--fills-db` set, daily rollups are written to the embedded db so they survive restarts.
+
+use std::{future::Future, str::FromStr};
+
+use drift_rs::Pubkey;
+use serde::{Deserialize, Serialize};
+use tokio::task::futures::TaskLocalFuture;
+
+use crate::rollups::{day_of, DailyRollups, Rollup};
+
+/// Header of the API key usage is accounted to, it's not verified by the gateway
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// Max. length of an accounted API key, longer keys are accounted as `unknown`
+const MAX_API_KEY_LEN: usize = 64;
+
+tokio::task_local! {
+    static API_KEY: Option<String>;
+}
+
+/// Run `fut` accounting its orders to `api_key`
+pub fn api_key_scope<F: Future>(
+    api_key: Option<String>,
+    fut: F,
+) -> TaskLocalFuture<Option<String>, F> {
+    API_KEY.scope(api_key, fut)
+}
+
+/// Return the API key of the current request, if any
+pub fn current_api_key() -> Option<String> {
+    API_KEY.try_with(Clone::clone).ok().flatten()
+}
+
+/// Return the API key usage of a request is accounted to from its `header`, if any
+///
+/// invalid keys are accounted as `unknown`
+pub fn usage_api_key(header: Option<&[u8]>) -> Option<String> {
+    let key = header?;
+    match std::str::from_utf8(key) {
+        Ok(key) if !key.is_empty() && key.len() <= MAX_API_KEY_LEN => Some(key.to_owned()),
+        _ => Some("unknown".to_owned()),
+    }
+}
+
+/// Usage of an authority over one UTC day
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct DailyUsage {
+    /// start of the day, unix secs
+    pub day: u64,
+    pub authority: String,
+    /// API key of the requests, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+    pub requests: u64,
+    /// orders sent
+    pub orders: u64,
+    /// response body bytes
+    pub egress_bytes: u64,
+}
+
+/// Return the authority usage of a request is accounted to, `default` if unset
+///
+/// invalid authorities are accounted as `unknown`
+pub fn usage_authority(authority: Option<&str>, default: &Pubkey) -> String {
+    match authority {
+        Some(a) if Pubkey::from_str(a).is_ok() => a.to_owned(),
+        Some(_) => "unknown".to_owned(),
+        None => default.to_string(),
+    }
+}
+
+impl Rollup for DailyUsage {
+    type Key = (u64, String, Option<String>);
+    const NAME: &'static str = "usage";
+
+    fn day(&self) -> u64 {
+        self.day
+    }
+
+    fn key(&self) -> Self::Key {
+        (self.day, self.authority.clone(), self.api_key.clone())
+    }
+
+    fn store_key(&self) -> Vec<u8> {
+        let mut key = [&self.day.to_be_bytes()[..], self.authority.as_bytes()].concat();
+        if let Some(ref api_key) = self.api_key {
+            key.push(0);
+            key.extend_from_slice(api_key.as_bytes());
+        }
+        key
+    }
+}
+
+/// Counts API usage per authority and day
+#[derive(Default)]
+pub struct UsageTracker {
+    rollups: DailyRollups<DailyUsage>,
+}
+
+impl UsageTracker {
+    /// Persist rollups to `store`, continuing from those already stored
+    pub fn with_store(store: sled::Tree) -> Self {
+        Self {
+            rollups: DailyRollups::with_store(store),
+        }
+    }
+
+    /// Count a request of `authority` and `api_key` with a response of `egress_bytes`
+    pub fn record_request(
+        &self,
+        authority: &str,
+        api_key: Option<&str>,
+        egress_bytes: u64,
+        now: u64,
+    ) {
+        self.update(authority, api_key, now, |usage| {
+            usage.requests += 1;
+            usage.egress_bytes += egress_bytes;
+        });
+    }
+
+    /// Count orders sent for `authority` and `api_key`
+    pub fn record_orders(&self, authority: &str, api_key: Option<&str>, orders: u64, now: u64) {
+        if orders > 0 {
+            self.update(authority, api_key, now, |usage| usage.orders += orders);
+        }
+    }
+
+    fn update(
+        &self,
+        authority: &str,
+        api_key: Option<&str>,
+        now: u64,
+        f: impl FnOnce(&mut DailyUsage),
+    ) {
+        let day = day_of(now);
+        let key = (day, authority.to_owned(), api_key.map(str::to_owned));
+        let init = || DailyUsage {
+            day,
+            authority: authority.to_owned(),
+            api_key: api_key.map(str::to_owned),
+            ..Default::default()
+        };
+        self.rollups.update(key, init, f);
+    }
+
+    /// Write rollups changed since the last flush to the store, if any
+    pub fn flush(&self) {
+        self.rollups.flush();
+    }
+
+    /// Return daily usage between unix timestamps `from` and `to` (inclusive), oldest first
+    pub fn query(
+        &self,
+        from: u64,
+        to: u64,
+        authority: Option<&str>,
+        api_key: Option<&str>,
+    ) -> Vec<DailyUsage> {
+        self.rollups.query(from, to, |u| {
+            authority.map_or(true, |a| u.authority == a)
+                && api_key.map_or(true, |k| u.api_key.as_deref() == Some(k))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rollups::SECS_PER_DAY;
+
+    #[test]
+    fn usage_rolls_up_daily() {
+        let tree = sled::Config::new()
+            .temporary(true)
+            .open()
+            .unwrap()
+            .open_tree("usage")
+            .unwrap();
+        let tracker = UsageTracker::with_store(tree.clone());
+        let day = 20_000 * SECS_PER_DAY;
+        tracker.record_request("desk-a", None, 100, day + 10);
+        tracker.record_request("desk-a", None, 50, day + 20);
+        tracker.record_orders("desk-a", None, 3, day + 20);
+        tracker.record_request("desk-b", None, 10, day + 30);
+        tracker.record_request("desk-a", None, 5, day + SECS_PER_DAY);
+        // keys sharing an authority are accounted apart
+        tracker.record_request("desk-a", Some("key-1"), 7, day + 30);
+        tracker.record_orders("desk-a", Some("key-1"), 2, day + 30);
+
+        let usage = tracker.query(day + 5, day, Some("desk-a"), None);
+        assert_eq!(
+            usage,
+            vec![
+                DailyUsage {
+                    day,
+                    authority: "desk-a".into(),
+                    api_key: None,
+                    requests: 2,
+                    orders: 3,
+                    egress_bytes: 150,
+                },
+                DailyUsage {
+                    day,
+                    authority: "desk-a".into(),
+                    api_key: Some("key-1".into()),
+                    requests: 1,
+                    orders: 2,
+                    egress_bytes: 7,
+                }
+            ]
+        );
+        assert_eq!(tracker.query(day, day, None, Some("key-1")).len(), 1);
+        assert_eq!(tracker.query(day, day + SECS_PER_DAY, None, None).len(), 4);
+
+        // rollups are restored from the store
+        tracker.flush();
+        let restored = UsageTracker::with_store(tree);
+        assert_eq!(restored.query(day, day + SECS_PER_DAY, None, None).len(), 4);
+        restored.record_request("desk-b", None, 1, day + 40);
+        assert_eq!(
+            restored.query(day, day, Some("desk-b"), None)[0].requests,
+            2
+        );
+        assert_eq!(restored.query(day, day, None, Some("key-1"))[0].orders, 2);
+    }
+
+    #[test]
+    fn api_key_from_header() {
+        assert_eq!(usage_api_key(None), None);
+        assert_eq!(usage_api_key(Some(b"desk-a")).as_deref(), Some("desk-a"));
+        assert_eq!(usage_api_key(Some(b"")).as_deref(), Some("unknown"));
+        assert_eq!(
+            usage_api_key(Some(&[b'k'; MAX_API_KEY_LEN + 1])).as_deref(),
+            Some("unknown")
+        );
+    }
+}
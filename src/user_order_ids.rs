@@ -0,0 +1,88 @@
+//! User order ids reserved gateway-wide
+//!
+//! Orders the gateway places on behalf of sessions and algos (FIX, TWAP, DCA) are identified by
+//! their user order id, so each needs one no other order of the sub-account uses. Ids are reserved
+//! here across all of them, and ids of the sub-account's open orders (e.g. placed by HTTP clients)
+//! are skipped.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+};
+
+use drift_rs::Pubkey;
+
+#[derive(Default)]
+struct Reserved {
+    ids: HashMap<Pubkey, HashSet<u8>>,
+    /// next id to try per sub-account, ids rotate so a released id isn't reused right away
+    next: HashMap<Pubkey, u8>,
+}
+
+/// User order ids reserved per sub-account
+#[derive(Default)]
+pub struct UserOrderIds {
+    reserved: Mutex<Reserved>,
+}
+
+impl UserOrderIds {
+    /// Reserve an id of `sub_account` not reserved and not in `open`, the ids of its open orders
+    ///
+    /// None if all ids are taken
+    pub fn reserve(&self, sub_account: &Pubkey, open: &[u8]) -> Option<u8> {
+        let mut reserved = self.reserved.lock().expect("lock");
+        let start = u16::from(*reserved.next.get(sub_account).unwrap_or(&1));
+        let ids = reserved.ids.entry(*sub_account).or_default();
+        // 0 means unset to the program
+        let id = (0..255_u16)
+            .map(|i| ((start - 1 + i) % 255 + 1) as u8)
+            .find(|id| !ids.contains(id) && !open.contains(id))?;
+        ids.insert(id);
+        reserved.next.insert(*sub_account, id % 255 + 1);
+        Some(id)
+    }
+
+    /// Reserve `id` of `sub_account` again e.g. for restored state, false if already reserved
+    pub fn claim(&self, sub_account: &Pubkey, id: u8) -> bool {
+        id != 0
+            && self
+                .reserved
+                .lock()
+                .expect("lock")
+                .ids
+                .entry(*sub_account)
+                .or_default()
+                .insert(id)
+    }
+
+    pub fn release(&self, sub_account: &Pubkey, id: u8) {
+        if let Some(ids) = self.reserved.lock().expect("lock").ids.get_mut(sub_account) {
+            ids.remove(&id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_unique_per_sub_account() {
+        let ids = UserOrderIds::default();
+        let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        assert_eq!(ids.reserve(&a, &[]), Some(1));
+        // open orders' ids are skipped
+        assert_eq!(ids.reserve(&a, &[2, 3]), Some(4));
+        assert_eq!(ids.reserve(&b, &[]), Some(1));
+        assert!(!ids.claim(&a, 4));
+        assert!(ids.claim(&a, 5));
+
+        // ids rotate, a released id is reused after the others
+        ids.release(&a, 1);
+        let rest: Vec<_> = std::iter::from_fn(|| ids.reserve(&a, &[])).collect();
+        assert_eq!(rest.len(), 255 - 2);
+        assert_eq!(rest[..2], [6, 7]);
+        assert_eq!(rest[250..], [1, 2, 3]);
+        assert_eq!(ids.reserve(&a, &[]), None);
+    }
+}
@@ -0,0 +1,95 @@
+//! Helius webhook ingestion
+//!
+//! With `DRIFT_GATEWAY_HELIUS_AUTH` set, Helius webhooks posted to `/v2/webhooks/helius` are an
+//! additional source of account events. Each webhook must carry the configured auth header.
+//! Raw webhooks include tx logs, which are decoded as is. Enhanced webhooks don't, so their txs are
+//! fetched via RPC. Events are deduplicated with those of the native event stream by the journal.
+//! Failed txs are skipped, their logs include events of the reverted instructions.
+
+use serde_json::Value;
+
+use crate::ws_auth::constant_time_eq;
+
+/// Authenticates webhooks by the auth header configured with Helius
+pub struct HeliusWebhook {
+    auth: String,
+}
+
+impl HeliusWebhook {
+    pub fn new(auth: String) -> Self {
+        Self { auth }
+    }
+
+    /// Return true if `authorization` is the configured auth header
+    pub fn verify(&self, authorization: Option<&str>) -> bool {
+        authorization.is_some_and(|auth| constant_time_eq(auth.as_bytes(), self.auth.as_bytes()))
+    }
+}
+
+/// A tx delivered by webhook
+#[derive(Debug, PartialEq)]
+pub struct WebhookTx {
+    pub signature: String,
+    /// log messages, if delivered
+    pub logs: Option<Vec<String>>,
+}
+
+/// Parse the txs of a raw or enhanced Helius webhook `body`, failed txs are skipped
+pub fn parse_helius(body: &[u8]) -> Result<Vec<WebhookTx>, String> {
+    let txs: Vec<Value> =
+        serde_json::from_slice(body).map_err(|err| format!("invalid webhook: {err}"))?;
+    txs.iter()
+        // the error of raw txs is in their meta, of enhanced txs in `transactionError`
+        .filter(|tx| tx["meta"]["err"].is_null() && tx["transactionError"].is_null())
+        .map(|tx| {
+            // enhanced txs have a signature, raw txs those of the tx
+            let signature = tx["signature"]
+                .as_str()
+                .or_else(|| tx["transaction"]["signatures"][0].as_str())
+                .ok_or("webhook tx without signature")?;
+            let logs = tx["meta"]["logMessages"].as_array().map(|logs| {
+                logs.iter()
+                    .filter_map(|log| log.as_str().map(str::to_string))
+                    .collect()
+            });
+            Ok(WebhookTx {
+                signature: signature.to_string(),
+                logs,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn helius_webhooks() {
+        let enhanced = br#"[{"signature":"sig1","slot":1,"type":"UNKNOWN","events":{}}]"#;
+        assert_eq!(
+            parse_helius(enhanced),
+            Ok(vec![WebhookTx {
+                signature: "sig1".into(),
+                logs: None
+            }])
+        );
+        let raw = br#"[{"slot":1,"meta":{"logMessages":["Program log: a"]},"transaction":{"signatures":["sig2"]}}]"#;
+        assert_eq!(
+            parse_helius(raw),
+            Ok(vec![WebhookTx {
+                signature: "sig2".into(),
+                logs: Some(vec!["Program log: a".into()])
+            }])
+        );
+        let failed = br#"[{"slot":1,"meta":{"err":{"InstructionError":[0,{"Custom":6010}]},"logMessages":["Program log: a"]},"transaction":{"signatures":["sig2"]}},{"signature":"sig1","transactionError":{"error":"0x177a"}}]"#;
+        assert_eq!(parse_helius(failed), Ok(vec![]));
+        assert!(parse_helius(br#"[{"slot":1}]"#).is_err());
+        assert!(parse_helius(br#"{"signature":"sig1"}"#).is_err());
+
+        let webhook = HeliusWebhook::new("secret".into());
+        assert!(webhook.verify(Some("secret")));
+        assert!(!webhook.verify(Some("secre")));
+        assert!(!webhook.verify(None));
+    }
+}
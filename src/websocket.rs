@@ -1,49 +1,145 @@
 //! Websocket server
 
-use std::{collections::HashMap, ops::Neg, sync::Arc};
+use std::{collections::HashMap, fmt::Debug, ops::Neg, sync::Arc, time::Duration};
 
+use anchor_lang::AccountDeserialize;
+pub(crate) use drift_gateway_types::{Channel, Side};
 use drift_rs::{
     constants::ProgramData,
+    drift_idl::types::MarginRequirementType,
     event_subscriber::{DriftEvent, EventSubscriber, PubsubClient},
-    types::{MarketType, Order, OrderType, PositionDirection},
-    Pubkey, Wallet,
+    math::{
+        leverage::get_leverage,
+        liquidation::{calculate_collateral, calculate_margin_requirements},
+    },
+    types::{
+        accounts::{PerpMarket, SpotMarket, User},
+        MarketType, Order, OrderStatus, OrderType, PositionDirection, SdkResult,
+    },
+    DriftClient, Pubkey, Wallet,
 };
 use futures_util::{SinkExt, StreamExt};
 use log::{debug, info, warn};
 use rust_decimal::Decimal;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde_json::json;
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::rpc_config::RpcAccountInfoConfig;
+use solana_sdk::account::Account;
 use tokio::{
     net::{TcpListener, TcpStream},
-    sync::Mutex,
+    sync::{broadcast::error::RecvError, mpsc, Mutex},
     task::JoinHandle,
 };
 use tokio_tungstenite::{accept_async, tungstenite::Message};
 
 use crate::{
-    types::{get_market_decimals, Market, PRICE_DECIMALS},
+    backfill::fill_progress,
+    backoff::Backoff,
+    chain_clock::ChainClock,
+    controller::unix_timestamp,
+    dlob::Dlob,
+    layout::IncompatibleMarkets,
+    net,
+    program_events::ProgramEvents,
+    request_id::RequestId,
+    status::{market_subject, GatewayStatus, StatusCondition, StatusUpdate},
+    sub_accounts::SubAccountNames,
+    subscriptions::{SubscriptionId, Subscriptions},
+    types::{
+        get_market_decimals, Collateral, L2Level, Leverage, MarginInfo, Market, MarketOperations,
+        PerpPosition, SpotPosition, PRICE_DECIMALS,
+    },
+    ws_auth::{self, WsAuth},
+    ws_queue::{OutboundQueue, Policy},
     LOG_TARGET,
 };
 
+/// Interval between checks of subscribed market accounts for changes
+const MARKET_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Interval between reports of interest accrued on spot balances
+const INTEREST_POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Interval between checks of the orderbooks of subscribed markets for changes
+const MARKET_DATA_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Interval between oracle price updates unless requested
+const DEFAULT_ORACLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Min. interval between oracle price updates
+const MIN_ORACLE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Price levels per side of streamed orderbooks unless requested
+const DEFAULT_BOOK_DEPTH: usize = 10;
+
+/// Events per batch in batching mode unless requested
+const DEFAULT_BATCH_EVENTS: usize = 100;
+
+/// Max. messages queued per connection, see `ws_queue`
+const OUTBOUND_QUEUE_CAPACITY: usize = 1_024;
+
+/// Min. delay before resubscribing a finished account event stream
+const MIN_RESUBSCRIBE_DELAY: Duration = Duration::from_millis(500);
+
+/// Max. delay between resubscribes of an account event stream
+const MAX_RESUBSCRIBE_DELAY: Duration = Duration::from_secs(30);
+
+/// Resubscribes of an account event stream before the subscription ends
+const MAX_RESUBSCRIBES: u32 = 10;
+
+/// Event streams up this long are healthy, backoff of the next resubscribe starts over
+const STABLE_STREAM_DURATION: Duration = Duration::from_secs(60);
+
+/// Max. open orders tracked per subscription to resolve the market of order events
+const MAX_ORDER_MARKETS: usize = 1_024;
+
+/// decimal places in funding rate values
+pub(crate) const FUNDING_RATE_DECIMALS: u32 = 9;
+
 /// Start the websocket server
 pub async fn start_ws_server(
-    listen_address: &str,
+    listener: std::net::TcpListener,
+    client: &'static DriftClient,
     ws_client: Arc<PubsubClient>,
     wallet: Wallet,
     program_data: &'static ProgramData,
+    sub_account_names: Arc<SubAccountNames>,
+    subscription_registry: Arc<Subscriptions>,
+    incompatible_markets: Arc<IncompatibleMarkets>,
+    dlob: Arc<Dlob>,
+    chain_clock: Arc<ChainClock>,
+    order_expiry_warning: u64,
+    heartbeat: Option<WsHeartbeat>,
+    auth: Option<Arc<WsAuth>>,
+    status: Arc<GatewayStatus>,
 ) {
     // Create the event loop and TCP listener we'll accept connections on.
-    let listener = TcpListener::bind(&listen_address)
-        .await
-        .expect("failed to bind");
-    info!("Ws server listening at: ws://{}", listen_address);
+    let listener = TcpListener::from_std(listener).expect("Ws listener");
+    info!(
+        "Ws server listening at: ws://{}",
+        listener.local_addr().expect("bound")
+    );
+    // trades subscriptions of all connections share one program event stream
+    let program_events = Arc::new(ProgramEvents::new(Arc::clone(&ws_client)));
     tokio::spawn(async move {
         while let Ok((stream, _)) = listener.accept().await {
             tokio::spawn(accept_connection(
                 stream,
+                client,
                 Arc::clone(&ws_client),
                 wallet.clone(),
                 program_data,
+                Arc::clone(&sub_account_names),
+                Arc::clone(&subscription_registry),
+                Arc::clone(&incompatible_markets),
+                Arc::clone(&dlob),
+                Arc::clone(&program_events),
+                Arc::clone(&chain_clock),
+                order_expiry_warning,
+                heartbeat,
+                auth.clone(),
+                Arc::clone(&status),
             ));
         }
     });
@@ -51,132 +147,841 @@ pub async fn start_ws_server(
 
 async fn accept_connection(
     stream: TcpStream,
+    client: &'static DriftClient,
     ws_client: Arc<PubsubClient>,
     wallet: Wallet,
     program_data: &'static ProgramData,
+    sub_account_names: Arc<SubAccountNames>,
+    subscription_registry: Arc<Subscriptions>,
+    incompatible_markets: Arc<IncompatibleMarkets>,
+    dlob: Arc<Dlob>,
+    program_events: Arc<ProgramEvents>,
+    chain_clock: Arc<ChainClock>,
+    order_expiry_warning: u64,
+    heartbeat: Option<WsHeartbeat>,
+    auth: Option<Arc<WsAuth>>,
+    status: Arc<GatewayStatus>,
 ) {
-    let addr = stream.peer_addr().expect("peer address");
+    let addr = net::peer(stream.peer_addr().expect("peer address"));
     let ws_stream = accept_async(stream).await.expect("Ws handshake");
     info!(target: LOG_TARGET, "accepted Ws connection: {}", addr);
 
     let (mut ws_out, mut ws_in) = ws_stream.split();
-    let (message_tx, mut message_rx) = tokio::sync::mpsc::channel::<Message>(64);
-    let subscriptions = Arc::new(Mutex::new(HashMap::<u8, JoinHandle<()>>::default()));
+    // subscriptions never wait on the connection, see `ws_queue`
+    let message_tx = Arc::new(OutboundQueue::<Outgoing, EventKey>::new(
+        OUTBOUND_QUEUE_CAPACITY,
+    ));
+    let subscriptions = Arc::new(Mutex::new(HashMap::<u16, JoinHandle<()>>::default()));
+    // trades channel subscriptions by market
+    let trade_subscriptions =
+        Arc::new(Mutex::new(HashMap::<(u16, bool), JoinHandle<()>>::default()));
+    let mut status_subscription: Option<JoinHandle<()>> = None;
+    // id of the last subscription, events are numbered per subscription
+    let mut last_stream = 0_u64;
 
-    // writes messages to the connection
+    // writes messages to the connection, batching events if requested
+    let message_rx = Arc::clone(&message_tx);
     tokio::spawn(async move {
-        while let Some(msg) = message_rx.recv().await {
-            if msg.is_close() {
-                let _ = ws_out.send(msg).await;
-                let _ = ws_out.close().await;
-                debug!(target: LOG_TARGET, "closing Ws connection (send half): {}", addr);
-                break;
+        let mut batching: Option<BatchConfig> = None;
+        // events written by subscription
+        let mut seqs = HashMap::<u64, u64>::new();
+        let mut batch = Vec::<String>::new();
+        let mut flush_check = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            let msg = tokio::select! {
+                msg = message_rx.pop() => {
+                    let Some(msg) = msg else {
+                        // the connection ended or fell behind
+                        let _ = ws_out.close().await;
+                        debug!(target: LOG_TARGET, "closing Ws connection (send half): {}", addr);
+                        break;
+                    };
+                    msg
+                }
+                _ = flush_check.tick(), if batching.is_some() => {
+                    if let Some(frame) = batch_frame(&mut batch) {
+                        ws_out.send(frame).await.expect("sent");
+                    }
+                    continue;
+                }
+            };
+            match msg {
+                Outgoing::Event(event) => {
+                    // numbered as written, so events dropped from the queue leave no gaps
+                    let event = event.serialize(&mut seqs);
+                    match batching {
+                        Some(config) => {
+                            batch.push(event);
+                            if batch.len() >= config.max_events {
+                                if let Some(frame) = batch_frame(&mut batch) {
+                                    ws_out.send(frame).await.expect("sent");
+                                }
+                            }
+                        }
+                        None => ws_out.send(Message::text(event)).await.expect("sent"),
+                    }
+                }
+                Outgoing::Batch(config) => {
+                    if let Some(frame) = batch_frame(&mut batch) {
+                        ws_out.send(frame).await.expect("sent");
+                    }
+                    batching = (config.interval_ms > 0).then_some(config);
+                    if let Some(config) = batching {
+                        flush_check =
+                            tokio::time::interval(Duration::from_millis(config.interval_ms));
+                    }
+                }
+                Outgoing::Message(msg) => {
+                    // batched events are sent first, keeping the order of messages
+                    if let Some(frame) = batch_frame(&mut batch) {
+                        ws_out.send(frame).await.expect("sent");
+                    }
+                    if msg.is_close() {
+                        message_rx.close();
+                        let _ = ws_out.send(msg).await;
+                        let _ = ws_out.close().await;
+                        debug!(target: LOG_TARGET, "closing Ws connection (send half): {}", addr);
+                        break;
+                    }
+                    ws_out.send(msg).await.expect("sent");
+                }
             }
-            ws_out.send(msg).await.expect("sent");
         }
     });
 
+    // application-level heartbeats, any message from the client is a response
+    let heartbeat_interval = heartbeat.map_or(Duration::from_secs(1), |h| h.interval);
+    let mut heartbeat_check = tokio::time::interval_at(
+        tokio::time::Instant::now() + heartbeat_interval,
+        heartbeat_interval,
+    );
+    let mut response_deadline: Option<tokio::time::Instant> = None;
+
+    // with auth enabled, requests other than auth and heartbeats are refused until authenticated
+    let challenge = auth.as_ref().map(|_| ws_auth::challenge());
+    let mut authenticated = auth.is_none();
+    let auth_deadline = tokio::time::Instant::now() + ws_auth::AUTH_TIMEOUT;
+    if let Some(ref challenge) = challenge {
+        let _ = message_tx.send(
+            Message::text(json!({"channel": "auth", "challenge": challenge}).to_string()).into(),
+        );
+    }
+
     // watches incoming messages from the connection
-    while let Some(Ok(msg)) = ws_in.next().await {
+    loop {
+        let msg = tokio::select! {
+            msg = ws_in.next() => {
+                let Some(Ok(msg)) = msg else {
+                    break;
+                };
+                msg
+            }
+            _ = heartbeat_check.tick(), if heartbeat.is_some() => {
+                let _ = message_tx.send(
+                    Message::text(
+                        json!({
+                            "channel": "heartbeat",
+                            "ts": unix_timestamp(),
+                        })
+                        .to_string(),
+                    )
+                    .into(),
+                );
+                if let Some(heartbeat) = heartbeat {
+                    response_deadline.get_or_insert(tokio::time::Instant::now() + heartbeat.timeout);
+                }
+                continue;
+            }
+            _ = tokio::time::sleep_until(response_deadline.unwrap_or_else(tokio::time::Instant::now)), if response_deadline.is_some() => {
+                warn!(target: LOG_TARGET, "closing unresponsive Ws connection: {}", addr);
+                break;
+            }
+            _ = tokio::time::sleep_until(auth_deadline), if !authenticated => {
+                warn!(target: LOG_TARGET, "closing unauthenticated Ws connection: {}", addr);
+                let _ = message_tx.send(
+                    Message::text(
+                        json!({"error": "unauthorized", "reason": "auth timed out"}).to_string(),
+                    )
+                    .into(),
+                );
+                let _ = message_tx.send(Message::Close(None).into());
+                break;
+            }
+        };
+        response_deadline = None;
+        let request_id = RequestId::generate();
         match msg {
             Message::Text(ref request) => match serde_json::from_str::<'_, WsRequest>(request) {
                 Ok(request) => {
+                    // requests with a client id are acknowledged
+                    let acked = request.request_id.is_some();
+                    let request_id = RequestId::from_client(request.request_id.as_deref());
+                    if !authenticated && !matches!(request.method, Method::Auth | Method::Heartbeat)
+                    {
+                        let _ = message_tx.send(
+                            Message::text(
+                                json!({
+                                    "error": "unauthorized",
+                                    "reason": "authenticate first",
+                                    "requestId": request_id,
+                                })
+                                .to_string(),
+                            )
+                            .into(),
+                        );
+                        continue;
+                    }
+                    if let Some(batch) = request.batch {
+                        info!(target: LOG_TARGET, "batching events of: {} {batch:?} ({request_id})", addr);
+                        let _ = message_tx.send(Outgoing::Batch(batch));
+                    }
                     match request.method {
+                        Method::Subscribe if request.channel == Some(Channel::Status) => {
+                            if status_subscription
+                                .as_ref()
+                                .is_some_and(|t| !t.is_finished())
+                            {
+                                let _ = message_tx.send(
+                                    Message::text(
+                                        json!({
+                                            "error": "bad request",
+                                            "reason": "subscription already exists",
+                                            "requestId": request_id,
+                                        })
+                                        .to_string(),
+                                    )
+                                    .into(),
+                                );
+                                continue;
+                            }
+                            info!(target: LOG_TARGET, "subscribing to gateway status ({request_id})");
+                            // active conditions first, then changes
+                            let (active, mut updates) = status.subscribe();
+                            let status_tx = message_tx.clone();
+                            last_stream += 1;
+                            let stream = last_stream;
+                            status_subscription = Some(tokio::spawn(request_id.clone().scope(async move {
+                                let send = |update: StatusUpdate| {
+                                    status_tx.send(Outgoing::Event(QueuedEvent {
+                                        stream,
+                                        data: AccountEvent::Status(update),
+                                        channel: Channel::Status,
+                                        sub_account_id: None,
+                                        sub_account_name: None,
+                                        slot: None,
+                                    }))
+                                };
+                                for update in active {
+                                    if send(update).is_err() {
+                                        return;
+                                    }
+                                }
+                                loop {
+                                    match updates.recv().await {
+                                        Ok(update) => {
+                                            if send(update).is_err() {
+                                                warn!(target: LOG_TARGET, "failed sending Ws message: {}", addr);
+                                                break;
+                                            }
+                                        }
+                                        Err(RecvError::Lagged(missed)) => {
+                                            warn!(target: LOG_TARGET, "status subscriber missed {missed} updates: {}", addr);
+                                        }
+                                        Err(RecvError::Closed) => break,
+                                    }
+                                }
+                            })));
+                            if acked {
+                                let _ = message_tx.send(ack("subscribed", &request_id));
+                            }
+                        }
+                        Method::Subscribe if request.channel == Some(Channel::Trades) => {
+                            let Some(market) = request.market else {
+                                let _ = message_tx.send(
+                                    Message::text(
+                                        json!({
+                                            "error": "bad request",
+                                            "reason": "trades channel requires a market",
+                                            "requestId": request_id,
+                                        })
+                                        .to_string(),
+                                    )
+                                    .into(),
+                                );
+                                continue;
+                            };
+                            let key = (market.market_index, market.market_type == MarketType::Perp);
+                            let mut trades_map = trade_subscriptions.lock().await;
+                            if trades_map.contains_key(&key) {
+                                info!(target: LOG_TARGET, "trades subscription already exists for: {market:?} ({request_id})");
+                                let _ = message_tx.send(
+                                    Message::text(
+                                        json!({
+                                            "error": "bad request",
+                                            "reason": "subscription already exists",
+                                            "requestId": request_id,
+                                        })
+                                        .to_string(),
+                                    )
+                                    .into(),
+                                );
+                                continue;
+                            }
+                            info!(target: LOG_TARGET, "subscribing to trades of: {market:?} ({request_id})");
+
+                            // fills of all users are filtered by market
+                            let mut events = program_events.subscribe();
+                            last_stream += 1;
+                            let stream = last_stream;
+                            let join_handle = tokio::spawn(request_id.clone().scope({
+                                let trades_map = Arc::clone(&trade_subscriptions);
+                                let message_tx = message_tx.clone();
+                                async move {
+                                    loop {
+                                        let update = match events.recv().await {
+                                            Ok(update) => update,
+                                            // trades are best-effort
+                                            Err(RecvError::Lagged(missed)) => {
+                                                debug!(target: LOG_TARGET, "trades subscriber missed {missed} events: {}", addr);
+                                                continue;
+                                            }
+                                            Err(RecvError::Closed) => break,
+                                        };
+                                        let Some(data) =
+                                            map_drift_event_for_market(program_data, &update, market)
+                                        else {
+                                            continue;
+                                        };
+                                        if message_tx
+                                            .push(Outgoing::Event(QueuedEvent {
+                                                stream,
+                                                data,
+                                                channel: Channel::Trades,
+                                                sub_account_id: None,
+                                                sub_account_name: None,
+                                                slot: None,
+                                            }), Policy::BestEffort)
+                                            .is_err()
+                                        {
+                                            warn!(target: LOG_TARGET, "failed sending Ws message: {}", addr);
+                                            break;
+                                        }
+                                    }
+                                    warn!(target: LOG_TARGET, "trade stream finished: {market:?}");
+                                    trades_map.lock().await.remove(&key);
+                                }
+                            }));
+                            trades_map.insert(key, join_handle);
+                            if acked {
+                                let _ = message_tx.send(ack("subscribed", &request_id));
+                            }
+                        }
                         Method::Subscribe => {
-                            // TODO: support subscriptions for individual channels and/or markets
                             let mut subscription_map = subscriptions.lock().await;
                             if subscription_map.contains_key(&request.sub_account_id) {
-                                info!(target: LOG_TARGET, "subscription already exists for: {}", request.sub_account_id);
-                                message_tx
-                                    .send(Message::text(
+                                info!(target: LOG_TARGET, "subscription already exists for: {} ({request_id})", request.sub_account_id);
+                                let _ = message_tx.send(
+                                    Message::text(
                                         json!({
                                             "error": "bad request",
                                             "reason": "subscription already exists",
+                                            "requestId": request_id,
+                                        })
+                                        .to_string(),
+                                    )
+                                    .into(),
+                                );
+                                continue;
+                            }
+                            // their accounts can't be decoded by this build
+                            if let Some(market) = request
+                                .markets
+                                .iter()
+                                .find(|m| incompatible_markets.contains(&m.as_market_id()))
+                            {
+                                let _ = message_tx.send(
+                                    Message::text(
+                                        json!({
+                                            "error": "bad request",
+                                            "reason": format!("{} account layout is incompatible with this gateway build", market_subject(*market)),
+                                            "requestId": request_id,
                                         })
                                         .to_string(),
-                                    ))
-                                    .await
-                                    .unwrap();
+                                    )
+                                    .into(),
+                                );
                                 continue;
                             }
-                            info!(target: LOG_TARGET, "subscribing to events for: {}", request.sub_account_id);
+                            info!(target: LOG_TARGET, "subscribing to events for: {} ({request_id})", request.sub_account_id);
 
-                            let sub_account_address =
-                                wallet.sub_account(request.sub_account_id as u16);
+                            let sub_account_address = wallet.sub_account(request.sub_account_id);
                             let mut event_stream = EventSubscriber::subscribe(
                                 Arc::clone(&ws_client),
                                 sub_account_address,
                             )
                             .await
                             .expect("ws connects");
+                            // user account updates for the account channel, closed with the subscription
+                            let mut account_updates = user_account_updates(
+                                client,
+                                Arc::clone(&ws_client),
+                                sub_account_address,
+                            );
+                            // market account updates for the markets channel
+                            let markets = request.markets;
+                            let oracle_interval =
+                                oracle_interval(request.oracle_interval, request.channel);
+                            if !markets.is_empty() {
+                                let market_ids: Vec<_> =
+                                    markets.iter().map(|m| m.as_market_id()).collect();
+                                if let Err(err) = client.subscribe_markets(&market_ids).await {
+                                    warn!(target: LOG_TARGET, "failed subscribing markets: {market_ids:?}, {err:?}");
+                                } else {
+                                    let new_markets: Vec<_> = market_ids
+                                        .iter()
+                                        .map(|m| SubscriptionId::Market(*m))
+                                        .filter(|id| !subscription_registry.contains(id))
+                                        .collect();
+                                    subscription_registry.add(new_markets, unix_timestamp());
+                                }
+                                // oracle prices for the oracle channel, served from the subscription
+                                if oracle_interval.is_some() {
+                                    if let Err(err) = client.subscribe_oracles(&market_ids).await {
+                                        warn!(target: LOG_TARGET, "failed subscribing oracles: {market_ids:?}, {err:?}");
+                                    } else {
+                                        let new_oracles: Vec<_> = market_ids
+                                            .iter()
+                                            .map(|m| SubscriptionId::Oracle(*m))
+                                            .filter(|id| !subscription_registry.contains(id))
+                                            .collect();
+                                        subscription_registry.add(new_oracles, unix_timestamp());
+                                    }
+                                }
+                            }
 
-                            let join_handle = tokio::spawn({
+                            last_stream += 1;
+                            let stream = last_stream;
+                            // stream logs are tagged with the id of the subscribe request
+                            let join_handle = tokio::spawn(request_id.clone().scope({
                                 let subscription_map = Arc::clone(&subscriptions);
                                 let sub_account_id = request.sub_account_id;
                                 let message_tx = message_tx.clone();
+                                let sub_account_name = sub_account_names.name(sub_account_id);
+                                let (channel, market) = (request.channel, request.market);
+                                // orderbooks are streamed on request only, the user subscription backing them is heavy
+                                let book_depth = match (request.depth, channel) {
+                                    (Some(depth), _) => Some(depth),
+                                    (None, Some(Channel::MarketData)) => Some(DEFAULT_BOOK_DEPTH),
+                                    (None, _) => None,
+                                };
+                                let dlob = Arc::clone(&dlob);
+                                let chain_clock = Arc::clone(&chain_clock);
+                                let ws_client = Arc::clone(&ws_client);
 
                                 async move {
+                                    let mut filter = EventFilter::new(channel, market);
+                                    let mut order_expiry =
+                                        OrderExpiryMonitor::new(order_expiry_warning);
+                                    let mut expiry_check =
+                                        tokio::time::interval(Duration::from_secs(1));
+                                    let mut last_user: Option<(u64, User)> = None;
+                                    let mut last_state: Option<AccountState> = None;
+                                    let mut balances = BalanceMonitor::default();
+                                    let mut interest_check =
+                                        tokio::time::interval(INTEREST_POLL_INTERVAL);
+                                    let mut market_check =
+                                        tokio::time::interval(MARKET_POLL_INTERVAL);
+                                    let mut market_states =
+                                        HashMap::<(u16, bool), MarketState>::new();
+                                    let mut book_check =
+                                        tokio::time::interval(MARKET_DATA_POLL_INTERVAL);
+                                    let mut books =
+                                        HashMap::<(u16, bool), (Vec<L2Level>, Vec<L2Level>)>::new();
+                                    let mut oracle_check = tokio::time::interval(
+                                        oracle_interval.unwrap_or(DEFAULT_ORACLE_INTERVAL),
+                                    );
+                                    let mut oracle_prices = HashMap::<(u16, bool), i64>::new();
+                                    let mut resubscribes = Backoff::new(
+                                        MIN_RESUBSCRIBE_DELAY,
+                                        MAX_RESUBSCRIBE_DELAY,
+                                        MAX_RESUBSCRIBES,
+                                    );
+                                    let mut connected_at = tokio::time::Instant::now();
                                     loop {
                                         debug!(target: LOG_TARGET, "event stream connected: {sub_account_id:?}");
-                                        while let Some(ref update) = event_stream.next().await {
-                                            let (channel, data) = map_drift_event_for_account(
-                                                program_data,
-                                                update,
-                                                sub_account_address,
-                                            );
-                                            if data.is_none() {
-                                                continue;
-                                            }
-                                            if message_tx
-                                                .send(Message::text(
-                                                    serde_json::to_string(&WsEvent {
+                                        'stream: loop {
+                                            // events tagged with the slot of the account data they derive from, if known
+                                            let events: Vec<(Channel, AccountEvent, Option<u64>)> = tokio::select! {
+                                                update = event_stream.next() => {
+                                                    let Some(ref update) = update else {
+                                                        break 'stream;
+                                                    };
+                                                    let (channel, data) = map_drift_event_for_account(
+                                                        program_data,
+                                                        update,
+                                                        sub_account_address,
+                                                    );
+                                                    let Some(data) = data else {
+                                                        continue;
+                                                    };
+                                                    order_expiry.on_event(&data);
+                                                    vec![(channel, data, None)]
+                                                }
+                                                _ = expiry_check.tick() => {
+                                                    order_expiry
+                                                        .poll(chain_clock.now())
+                                                        .into_iter()
+                                                        .map(|data| (Channel::Orders, data, None))
+                                                        .collect()
+                                                }
+                                                Some((slot, user)) = account_updates.recv() => {
+                                                    if last_user.as_ref().is_some_and(|(_, last)| *last == user) {
+                                                        continue;
+                                                    }
+                                                    filter.track_open_orders(&user);
+                                                    // fill progress of open orders, incl. fills missed by the event stream
+                                                    let ts = unix_timestamp();
+                                                    let mut events: Vec<_> = match last_user {
+                                                        Some((_, ref last_user)) => fill_progress(last_user, &user)
+                                                            .into_iter()
+                                                            .map(|order| {
+                                                                let decimals = get_market_decimals(
+                                                                    program_data,
+                                                                    Market::new(order.market_index, order.market_type),
+                                                                );
+                                                                let data = AccountEvent::OrderUpdate {
+                                                                    order: OrderWithDecimals::from_order(order, decimals),
+                                                                    ts,
+                                                                };
+                                                                (Channel::Orders, data, Some(slot))
+                                                            })
+                                                            .collect(),
+                                                        None => vec![],
+                                                    };
+                                                    let state = match AccountState::load(client, &user).await {
+                                                        Ok(state) => state,
+                                                        Err(err) => {
+                                                            warn!(target: LOG_TARGET, "failed loading account state: {sub_account_id:?}, {err:?}");
+                                                            continue;
+                                                        }
+                                                    };
+                                                    match SpotBalance::load(client, &user).await {
+                                                        Ok(spot) => events.extend(
+                                                            balances
+                                                                .update(spot, state.collateral.total, false, ts)
+                                                                .into_iter()
+                                                                .map(|data| (Channel::Account, data, Some(slot))),
+                                                        ),
+                                                        Err(err) => {
+                                                            warn!(target: LOG_TARGET, "failed loading spot balances: {sub_account_id:?}, {err:?}");
+                                                        }
+                                                    }
+                                                    last_user = Some((slot, user));
+                                                    if let Some(update) = state.diff(last_state.as_ref(), ts) {
+                                                        events.push((Channel::Account, update, Some(slot)));
+                                                    }
+                                                    last_state = Some(state);
+                                                    events
+                                                }
+                                                // interest accrues and collateral moves with prices without changes to the user account
+                                                _ = interest_check.tick() => {
+                                                    let Some((slot, ref user)) = last_user else {
+                                                        continue;
+                                                    };
+                                                    let (state, spot) = match (AccountState::load(client, user).await, SpotBalance::load(client, user).await) {
+                                                        (Ok(state), Ok(spot)) => (state, spot),
+                                                        (Err(err), _) | (_, Err(err)) => {
+                                                            debug!(target: LOG_TARGET, "failed loading account state: {sub_account_id:?}, {err:?}");
+                                                            continue;
+                                                        }
+                                                    };
+                                                    let ts = unix_timestamp();
+                                                    let mut events: Vec<_> = balances
+                                                        .update(spot, state.collateral.total, true, ts)
+                                                        .into_iter()
+                                                        .map(|data| (Channel::Account, data, Some(slot)))
+                                                        .collect();
+                                                    if let Some(update) = state.diff(last_state.as_ref(), ts) {
+                                                        events.push((Channel::Account, update, Some(slot)));
+                                                    }
+                                                    last_state = Some(state);
+                                                    events
+                                                }
+                                                _ = market_check.tick(), if !markets.is_empty() => {
+                                                    let mut events = Vec::new();
+                                                    for market in &markets {
+                                                        let Some((state, slot)) = MarketState::load(client, *market) else {
+                                                            debug!(target: LOG_TARGET, "market not cached: {market:?}");
+                                                            continue;
+                                                        };
+                                                        let key = (market.market_index, market.market_type == MarketType::Perp);
+                                                        if let Some(update) = state.diff(market_states.get(&key), unix_timestamp()) {
+                                                            events.push((Channel::Markets, update, Some(slot)));
+                                                        }
+                                                        market_states.insert(key, state);
+                                                    }
+                                                    events
+                                                }
+                                                _ = book_check.tick(), if book_depth.is_some() && !markets.is_empty() => {
+                                                    let orders = dlob.orders(client).await;
+                                                    let ts = unix_timestamp();
+                                                    let mut events = Vec::new();
+                                                    for market in &markets {
+                                                        let oracle = match client.get_oracle_price_data_and_slot(market.as_market_id()).await {
+                                                            Ok(oracle) => oracle,
+                                                            Err(err) => {
+                                                                debug!(target: LOG_TARGET, "failed loading oracle: {market:?}, {err:?}");
+                                                                continue;
+                                                            }
+                                                        };
+                                                        let book = dlob.l2_book(
+                                                            &orders,
+                                                            *market,
+                                                            oracle.data.price,
+                                                            chain_clock.now(),
+                                                            get_market_decimals(program_data, *market),
+                                                            book_depth.unwrap_or(DEFAULT_BOOK_DEPTH),
+                                                        );
+                                                        let key = (market.market_index, market.market_type == MarketType::Perp);
+                                                        if books.get(&key) == Some(&book) {
+                                                            continue;
+                                                        }
+                                                        books.insert(key, book.clone());
+                                                        let (bids, asks) = book;
+                                                        let data = AccountEvent::MarketData {
+                                                            market: *market,
+                                                            oracle_price: Decimal::new(oracle.data.price, PRICE_DECIMALS).normalize(),
+                                                            bids,
+                                                            asks,
+                                                            ts,
+                                                        };
+                                                        events.push((Channel::MarketData, data, Some(oracle.slot)));
+                                                    }
+                                                    events
+                                                }
+                                                _ = oracle_check.tick(), if oracle_interval.is_some() && !markets.is_empty() => {
+                                                    let ts = unix_timestamp();
+                                                    let mut events = Vec::new();
+                                                    for market in &markets {
+                                                        let oracle = match client.get_oracle_price_data_and_slot(market.as_market_id()).await {
+                                                            Ok(oracle) => oracle,
+                                                            Err(err) => {
+                                                                debug!(target: LOG_TARGET, "failed loading oracle: {market:?}, {err:?}");
+                                                                continue;
+                                                            }
+                                                        };
+                                                        let key = (market.market_index, market.market_type == MarketType::Perp);
+                                                        if oracle_prices.insert(key, oracle.data.price) == Some(oracle.data.price) {
+                                                            continue;
+                                                        }
+                                                        let data = AccountEvent::OraclePrice {
+                                                            market: *market,
+                                                            price: Decimal::new(oracle.data.price, PRICE_DECIMALS).normalize(),
+                                                            confidence: Decimal::new(oracle.data.confidence as i64, PRICE_DECIMALS).normalize(),
+                                                            ts,
+                                                        };
+                                                        events.push((Channel::Oracle, data, Some(oracle.slot)));
+                                                    }
+                                                    events
+                                                }
+                                            };
+                                            for (channel, data, slot) in events {
+                                                if !filter.accepts(channel, &data) {
+                                                    continue;
+                                                }
+                                                let policy = event_policy(channel, &data);
+                                                if message_tx
+                                                    .push(Outgoing::Event(QueuedEvent {
+                                                        stream,
                                                         data,
                                                         channel,
-                                                        sub_account_id,
-                                                    })
-                                                    .expect("serializes"),
+                                                        sub_account_id: Some(sub_account_id),
+                                                        sub_account_name: sub_account_name.clone(),
+                                                        slot,
+                                                    }), policy)
+                                                    .is_err()
+                                                {
+                                                    warn!(target: LOG_TARGET, "failed sending Ws message: {}", addr);
+                                                    return;
+                                                }
+                                            }
+                                        }
+
+                                        // resubscribe with backoff, the client is told of each attempt
+                                        warn!(target: LOG_TARGET, "event stream finished: {sub_account_id:?}");
+                                        if connected_at.elapsed() >= STABLE_STREAM_DURATION {
+                                            resubscribes.reset();
+                                        }
+                                        loop {
+                                            let Some(delay) = resubscribes.next_delay() else {
+                                                warn!(target: LOG_TARGET, "event stream failed {} resubscribes, giving up: {sub_account_id:?}", resubscribes.attempts());
+                                                let _ = message_tx.send(stream_status(
+                                                    stream,
+                                                    sub_account_id,
+                                                    sub_account_name.clone(),
+                                                    Some(format!(
+                                                        "gave up after {} resubscribes, subscribe again",
+                                                        resubscribes.attempts()
+                                                    )),
+                                                ));
+                                                subscription_map.lock().await.remove(&sub_account_id);
+                                                return;
+                                            };
+                                            if message_tx
+                                                .send(stream_status(
+                                                    stream,
+                                                    sub_account_id,
+                                                    sub_account_name.clone(),
+                                                    Some(format!(
+                                                        "resubscribing in {}ms, attempt {}",
+                                                        delay.as_millis(),
+                                                        resubscribes.attempts()
+                                                    )),
                                                 ))
-                                                .await
                                                 .is_err()
                                             {
-                                                warn!(target: LOG_TARGET, "failed sending Ws message: {}", addr);
-                                                break;
+                                                return;
+                                            }
+                                            tokio::time::sleep(delay).await;
+                                            match EventSubscriber::subscribe(
+                                                Arc::clone(&ws_client),
+                                                sub_account_address,
+                                            )
+                                            .await
+                                            {
+                                                Ok(stream) => {
+                                                    event_stream = stream;
+                                                    break;
+                                                }
+                                                Err(err) => {
+                                                    warn!(target: LOG_TARGET, "resubscribing event stream failed: {sub_account_id:?}, {err:?}");
+                                                }
                                             }
                                         }
-                                        warn!(target: LOG_TARGET, "event stream finished: {sub_account_id:?}, reconnecting...");
-                                        subscription_map.lock().await.remove(&sub_account_id);
+                                        connected_at = tokio::time::Instant::now();
+                                        let _ = message_tx.send(stream_status(
+                                            stream,
+                                            sub_account_id,
+                                            sub_account_name.clone(),
+                                            None,
+                                        ));
                                     }
                                 }
-                            });
+                            }));
 
                             subscription_map.insert(request.sub_account_id, join_handle);
+                            if acked {
+                                let _ = message_tx.send(ack("subscribed", &request_id));
+                            }
+                        }
+                        Method::Unsubscribe if request.channel == Some(Channel::Status) => {
+                            info!(target: LOG_TARGET, "unsubscribing gateway status ({request_id})");
+                            if let Some(task) = status_subscription.take() {
+                                task.abort();
+                            }
+                            if acked {
+                                let _ = message_tx.send(ack("unsubscribed", &request_id));
+                            }
+                        }
+                        Method::Unsubscribe if request.channel == Some(Channel::Trades) => {
+                            let Some(market) = request.market else {
+                                let _ = message_tx.send(
+                                    Message::text(
+                                        json!({
+                                            "error": "bad request",
+                                            "reason": "trades channel requires a market",
+                                            "requestId": request_id,
+                                        })
+                                        .to_string(),
+                                    )
+                                    .into(),
+                                );
+                                continue;
+                            };
+                            info!(target: LOG_TARGET, "unsubscribing trades of: {market:?} ({request_id})");
+                            let key = (market.market_index, market.market_type == MarketType::Perp);
+                            if let Some(task) = trade_subscriptions.lock().await.remove(&key) {
+                                task.abort();
+                            }
+                            if acked {
+                                let _ = message_tx.send(ack("unsubscribed", &request_id));
+                            }
+                        }
+                        // liveness is tracked for any message
+                        Method::Heartbeat => (),
+                        Method::Auth => {
+                            let valid = match (auth.as_ref(), challenge.as_deref()) {
+                                (Some(auth), Some(challenge)) => auth.verify(
+                                    challenge,
+                                    request.api_key.as_deref(),
+                                    request.signature.as_deref(),
+                                ),
+                                _ => true,
+                            };
+                            if !valid {
+                                // no retries, guessing requires a new connection each time
+                                warn!(target: LOG_TARGET, "Ws auth failed: {} ({request_id})", addr);
+                                let _ = message_tx.send(
+                                    Message::text(
+                                        json!({
+                                            "error": "unauthorized",
+                                            "reason": "invalid credentials",
+                                            "requestId": request_id,
+                                        })
+                                        .to_string(),
+                                    )
+                                    .into(),
+                                );
+                                let _ = message_tx.send(Message::Close(None).into());
+                                break;
+                            }
+                            info!(target: LOG_TARGET, "authenticated Ws connection: {} ({request_id})", addr);
+                            authenticated = true;
+                            let _ = message_tx.send(
+                                Message::text(
+                                    json!({"channel": "auth", "authenticated": true}).to_string(),
+                                )
+                                .into(),
+                            );
                         }
                         Method::Unsubscribe => {
-                            info!(target: LOG_TARGET, "unsubscribing events of: {}", request.sub_account_id);
+                            info!(target: LOG_TARGET, "unsubscribing events of: {} ({request_id})", request.sub_account_id);
                             // TODO: support ending by channel, this ends all channels
                             let mut subscription_map = subscriptions.lock().await;
                             if let Some(task) = subscription_map.remove(&request.sub_account_id) {
                                 task.abort();
                             }
+                            if acked {
+                                let _ = message_tx.send(ack("unsubscribed", &request_id));
+                            }
                         }
                     }
                 }
                 Err(err) => {
-                    message_tx
-                        .send(Message::text(
+                    // the client id of a malformed request is echoed if it can be found
+                    let client_id = serde_json::from_str::<serde_json::Value>(request)
+                        .ok()
+                        .and_then(|v| v.get("requestId")?.as_str().map(str::to_owned));
+                    let request_id = match client_id {
+                        Some(ref id) => RequestId::from_client(Some(id)),
+                        None => request_id,
+                    };
+                    let _ = message_tx.send(
+                        Message::text(
                             json!({
                                 "error": "bad request",
                                 "reason": err.to_string(),
+                                "requestId": request_id,
                             })
                             .to_string(),
-                        ))
-                        .await
-                        .unwrap();
+                        )
+                        .into(),
+                    );
                 }
             },
             Message::Close(frame) => {
                 info!(target: LOG_TARGET, "received Ws close: {}", addr);
-                let _ = message_tx.send(Message::Close(frame)).await;
+                let _ = message_tx.send(Message::Close(frame).into());
                 break;
             }
             // tokio-tungstenite handles ping/pong transparently
@@ -184,39 +989,323 @@ async fn accept_connection(
         }
     }
     info!(target: LOG_TARGET, "closing Ws connection: {}", addr);
+    // subscriptions end with their connection
+    for (_, task) in subscriptions.lock().await.drain() {
+        task.abort();
+    }
+    for (_, task) in trade_subscriptions.lock().await.drain() {
+        task.abort();
+    }
+    if let Some(task) = status_subscription {
+        task.abort();
+    }
+    // e.g. the reason of a failed auth is sent before closing
+    message_tx.end();
+}
+
+/// Return a status event of the account event stream of a subscription
+///
+/// * `stream` - the subscription
+/// * `detail` - why the stream is down, none once it's back up
+fn stream_status(
+    stream: u64,
+    sub_account_id: u16,
+    sub_account_name: Option<String>,
+    detail: Option<String>,
+) -> Outgoing {
+    Outgoing::Event(QueuedEvent {
+        stream,
+        data: AccountEvent::Status(StatusUpdate {
+            condition: StatusCondition::EventStream,
+            subject: Some(format!("subAccount-{sub_account_id}")),
+            active: detail.is_some(),
+            detail,
+            ts: unix_timestamp(),
+        }),
+        channel: Channel::Status,
+        sub_account_id: Some(sub_account_id),
+        sub_account_name,
+        slot: None,
+    })
+}
+
+/// Return the acknowledgment of a request with a client id
+fn ack(result: &str, request_id: &RequestId) -> Outgoing {
+    Message::text(json!({"result": result, "requestId": request_id}).to_string()).into()
 }
 
 #[derive(Deserialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "lowercase")]
 enum Method {
     Subscribe,
     Unsubscribe,
+    /// response to a server heartbeat
+    Heartbeat,
+    /// authenticate the connection with `apiKey` or `signature`
+    Auth,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "lowercase")]
-pub(crate) enum Channel {
-    Fills,
-    Orders,
-    Funding,
+/// Application-level heartbeats of Ws connections
+#[derive(Debug, Clone, Copy)]
+pub struct WsHeartbeat {
+    /// time between heartbeats
+    pub interval: Duration,
+    /// time to await a response before closing the connection
+    pub timeout: Duration,
 }
 
 #[derive(Deserialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
-struct WsRequest {
+pub(crate) struct WsRequest {
     method: Method,
-    sub_account_id: u8,
+    /// not required for the trades and status channels
+    #[serde(default)]
+    sub_account_id: u16,
+    /// markets to stream on the markets channel
+    #[serde(default)]
+    markets: Vec<Market>,
+    /// stream only events of this channel, all channels if unset
+    #[serde(default)]
+    channel: Option<Channel>,
+    /// stream only events of this market, if set
+    #[serde(default)]
+    market: Option<Market>,
+    /// stream orderbooks of `markets` on the marketData channel with this many levels per side
+    #[serde(default)]
+    depth: Option<usize>,
+    /// stream oracle prices of `markets` on the oracle channel at most every `oracleInterval` ms
+    #[serde(default)]
+    oracle_interval: Option<u64>,
+    /// batch the events of the connection, applies to all its subscriptions
+    #[serde(default)]
+    batch: Option<BatchConfig>,
+    /// Ws auth key, auth requests only
+    #[serde(default)]
+    api_key: Option<String>,
+    /// hex HMAC-SHA256 of the connection's challenge under the Ws auth key, auth requests only
+    #[serde(default)]
+    signature: Option<String>,
+    /// client id of the request, echoed in its acknowledgment or error
+    #[serde(default)]
+    request_id: Option<String>,
+}
+
+/// Coalesces the events of a connection into JSON arrays
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+struct BatchConfig {
+    /// max. delay of batched events, `0` disables batching
+    interval_ms: u64,
+    /// full batches are sent without delay
+    #[serde(default = "default_batch_events")]
+    max_events: usize,
+}
+
+fn default_batch_events() -> usize {
+    DEFAULT_BATCH_EVENTS
+}
+
+/// Messages to write to a connection
+enum Outgoing {
+    /// event, batched in batching mode
+    Event(QueuedEvent),
+    /// change batching mode
+    Batch(BatchConfig),
+    Message(Message),
+}
+
+/// An event of a subscription queued to a connection
+///
+/// it's numbered once dequeued, so events dropped or superseded in the queue leave no gaps in `seq`
+struct QueuedEvent {
+    /// id of the subscription the event is numbered in
+    stream: u64,
+    data: AccountEvent,
+    channel: Channel,
+    sub_account_id: Option<u16>,
+    sub_account_name: Option<String>,
+    slot: Option<u64>,
+}
+
+impl QueuedEvent {
+    /// Serialize the event as the next event of its subscription
+    ///
+    /// * `seqs` - events written by subscription
+    fn serialize(self, seqs: &mut HashMap<u64, u64>) -> String {
+        let seq = seqs.entry(self.stream).or_default();
+        *seq += 1;
+        serde_json::to_string(&WsEvent {
+            data: self.data,
+            channel: self.channel,
+            sub_account_id: self.sub_account_id,
+            sub_account_name: self.sub_account_name.as_deref(),
+            slot: self.slot,
+            seq: *seq,
+        })
+        .expect("serializes")
+    }
+}
+
+impl From<Message> for Outgoing {
+    fn from(value: Message) -> Self {
+        Self::Message(value)
+    }
+}
+
+/// Return the interval of oracle price updates, none if not streamed
+///
+/// oracle prices are streamed if an interval is requested or the subscription is to the oracle channel only
+fn oracle_interval(interval_ms: Option<u64>, channel: Option<Channel>) -> Option<Duration> {
+    match (interval_ms, channel) {
+        (Some(ms), _) => Some(Duration::from_millis(ms).max(MIN_ORACLE_INTERVAL)),
+        (None, Some(Channel::Oracle)) => Some(DEFAULT_ORACLE_INTERVAL),
+        (None, _) => None,
+    }
+}
+
+/// Queueing key of events superseded by later events of the same market
+type EventKey = (Channel, u16, bool);
+
+/// Return how an event is queued to a slow connection
+///
+/// market snapshots supersede queued ones, public trades are best-effort, account events are never dropped
+fn event_policy(channel: Channel, event: &AccountEvent) -> Policy<EventKey> {
+    match event {
+        AccountEvent::MarketData { market, .. } | AccountEvent::OraclePrice { market, .. } => {
+            Policy::Latest((
+                channel,
+                market.market_index,
+                market.market_type == MarketType::Perp,
+            ))
+        }
+        AccountEvent::Trade { .. } => Policy::BestEffort,
+        _ => Policy::Reliable,
+    }
+}
+
+/// Take the batched events as one JSON array message, none if the batch is empty
+fn batch_frame(batch: &mut Vec<String>) -> Option<Message> {
+    if batch.is_empty() {
+        return None;
+    }
+    let frame = format!("[{}]", batch.join(","));
+    batch.clear();
+    Some(Message::text(frame))
+}
+
+/// Filters the events of a subscription by channel and market
+struct EventFilter {
+    channel: Option<Channel>,
+    market: Option<Market>,
+    /// markets of open orders by order id, for order events identifying the order by id only
+    order_markets: HashMap<u32, Market>,
+}
+
+impl EventFilter {
+    fn new(channel: Option<Channel>, market: Option<Market>) -> Self {
+        Self {
+            channel,
+            market,
+            order_markets: HashMap::default(),
+        }
+    }
+
+    /// Track the markets of `user`'s open orders, e.g. those opened before the subscription
+    fn track_open_orders(&mut self, user: &User) {
+        for order in user.orders.iter().filter(|o| o.status == OrderStatus::Open) {
+            if self.order_markets.len() >= MAX_ORDER_MARKETS
+                && !self.order_markets.contains_key(&order.order_id)
+            {
+                break;
+            }
+            self.order_markets.insert(
+                order.order_id,
+                Market::new(order.market_index, order.market_type),
+            );
+        }
+    }
+
+    /// Return true if the event passes the filter
+    ///
+    /// with a market set, events not specific to a market (e.g. account updates) are filtered
+    fn accepts(&mut self, channel: Channel, event: &AccountEvent) -> bool {
+        let market = self.event_market(event);
+        if self.channel.is_some_and(|c| c != channel) {
+            return false;
+        }
+        match (self.market, market) {
+            (None, _) => true,
+            (Some(filter), Some(market)) => {
+                filter.market_index == market.market_index
+                    && filter.market_type == market.market_type
+            }
+            (Some(_), None) => false,
+        }
+    }
+
+    /// Return the market of an event, tracking the markets of open orders
+    fn event_market(&mut self, event: &AccountEvent) -> Option<Market> {
+        match event {
+            AccountEvent::Fill {
+                market_index,
+                market_type,
+                ..
+            } => Some(Market::new(*market_index, *market_type)),
+            AccountEvent::OrderCreate { order, .. }
+            | AccountEvent::OrderBackfill { order, .. }
+            | AccountEvent::OrderUpdate { order, .. } => {
+                let market = Market::new(order.market_index, order.market_type);
+                if self.order_markets.len() >= MAX_ORDER_MARKETS {
+                    // order ids are sequential, the oldest are dropped first
+                    if let Some(oldest) = self.order_markets.keys().min().copied() {
+                        self.order_markets.remove(&oldest);
+                    }
+                }
+                self.order_markets.insert(order.order_id, market);
+                Some(market)
+            }
+            AccountEvent::OrderExpiring { order_id, .. }
+            | AccountEvent::OrderCancelMissing { order_id, .. } => {
+                self.order_markets.get(order_id).copied()
+            }
+            AccountEvent::OrderCancel { order_id, .. }
+            | AccountEvent::OrderExpire { order_id, .. }
+            | AccountEvent::OrderExpired { order_id, .. }
+            | AccountEvent::OrderClosed { order_id, .. } => self.order_markets.remove(order_id),
+            AccountEvent::FundingPayment { market_index, .. } => Some(Market::perp(*market_index)),
+            AccountEvent::BalanceUpdate { market_index, .. } => Some(Market::spot(*market_index)),
+            AccountEvent::MarketUpdate { market, .. }
+            | AccountEvent::MarketData { market, .. }
+            | AccountEvent::Trade { market, .. }
+            | AccountEvent::OraclePrice { market, .. } => Some(*market),
+            AccountEvent::AccountUpdate { .. } | AccountEvent::Status(_) => None,
+        }
+    }
 }
 
 #[derive(Serialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
-struct WsEvent<T: Serialize> {
+pub(crate) struct WsEvent<'a, T: Serialize> {
     data: T,
     channel: Channel,
-    sub_account_id: u8,
+    /// unset for the trades and status channels
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sub_account_id: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sub_account_name: Option<&'a str>,
+    /// slot of the account data the event derives from, account and markets channels only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    slot: Option<u64>,
+    /// position of the event in its subscription across channels, from 1 without gaps
+    seq: u64,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub(crate) enum AccountEvent {
     #[serde(rename_all = "camelCase")]
@@ -232,6 +1321,7 @@ pub(crate) enum AccountEvent {
             serialize_with = "crate::types::ser_market_type",
             deserialize_with = "crate::types::de_market_type"
         )]
+        #[cfg_attr(feature = "schema", schemars(with = "drift_gateway_types::MarketType"))]
         market_type: MarketType,
         ts: u64,
 
@@ -273,6 +1363,105 @@ pub(crate) enum AccountEvent {
         ts: u64,
         signature: String,
     },
+    /// emitted ahead of an open order reaching its `maxTs`
+    #[serde(rename_all = "camelCase")]
+    OrderExpiring { order_id: u32, max_ts: i64, ts: u64 },
+    /// emitted once an open order reaches its `maxTs` and can no longer be filled
+    #[serde(rename_all = "camelCase")]
+    OrderExpired { order_id: u32, max_ts: i64, ts: u64 },
+    /// current state of an open order created or changed while the event subscription was down
+    #[serde(rename_all = "camelCase")]
+    OrderBackfill { order: OrderWithDecimals, ts: u64 },
+    /// an order filled, cancelled, or expired while the event subscription was down
+    #[serde(rename_all = "camelCase")]
+    OrderClosed { order_id: u32, ts: u64 },
+    /// current state of an open order whose filled amount changed in the user account
+    #[serde(rename_all = "camelCase")]
+    OrderUpdate { order: OrderWithDecimals, ts: u64 },
+    /// spot token balance change outside of fills
+    #[serde(rename_all = "camelCase")]
+    BalanceUpdate {
+        market_index: u16,
+        reason: BalanceChange,
+        /// signed token amount change
+        change: Decimal,
+        /// token balance after the change, negative for borrows
+        balance: Decimal,
+        /// total collateral of the sub-account
+        collateral: Decimal,
+        ts: u64,
+    },
+    /// changed sections of the user account, unchanged sections are omitted
+    #[serde(rename_all = "camelCase")]
+    AccountUpdate {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        spot: Option<Vec<SpotPosition>>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        perp: Option<Vec<PerpPosition>>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        margin: Option<MarginInfo>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        collateral: Option<Collateral>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        leverage: Option<Decimal>,
+        ts: u64,
+    },
+    /// significant market account changes, all fields are current values
+    #[serde(rename_all = "camelCase")]
+    MarketUpdate {
+        #[serde(flatten)]
+        market: Market,
+        status: String,
+        paused_operations: Vec<String>,
+        oracle: String,
+        oracle_source: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        last_funding_rate: Option<Decimal>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        fee_adjustment: Option<i16>,
+        /// names of fields changed since the last update, empty on the first update
+        changed: Vec<String>,
+        ts: u64,
+    },
+    /// L2 orderbook of a market, emitted when its levels change
+    #[serde(rename_all = "camelCase")]
+    MarketData {
+        #[serde(flatten)]
+        market: Market,
+        /// oracle price used for oracle offset orders
+        oracle_price: Decimal,
+        /// best (highest) first
+        bids: Vec<L2Level>,
+        /// best (lowest) first
+        asks: Vec<L2Level>,
+        ts: u64,
+    },
+    /// oracle price of a market, emitted when it changes
+    #[serde(rename_all = "camelCase")]
+    OraclePrice {
+        #[serde(flatten)]
+        market: Market,
+        price: Decimal,
+        /// confidence interval of the price
+        confidence: Decimal,
+        ts: u64,
+    },
+    /// a fill of any user in a market
+    #[serde(rename_all = "camelCase")]
+    Trade {
+        #[serde(flatten)]
+        market: Market,
+        /// side of the taker
+        side: Side,
+        amount: Decimal,
+        price: Decimal,
+        oracle_price: Decimal,
+        maker: Option<String>,
+        taker: Option<String>,
+        ts: u64,
+        signature: String,
+        tx_idx: usize,
+    },
     #[serde(rename_all = "camelCase")]
     FundingPayment {
         amount: Decimal,
@@ -281,6 +1470,8 @@ pub(crate) enum AccountEvent {
         signature: String,
         tx_idx: usize,
     },
+    /// a gateway-level condition raised or cleared
+    Status(StatusUpdate),
 }
 
 impl AccountEvent {
@@ -332,14 +1523,455 @@ impl AccountEvent {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Return the spot market account, from the market subscription if cached, otherwise via RPC
+async fn spot_market_account(client: &DriftClient, market_index: u16) -> SdkResult<SpotMarket> {
+    match client.try_get_spot_market_account_and_slot(market_index) {
+        Some(market) => Ok(market.data),
+        None => client.get_spot_market_account(market_index).await,
+    }
+}
+
+/// Decoded user account state streamed on the account channel
+#[derive(Debug, PartialEq)]
+struct AccountState {
+    spot: Vec<SpotPosition>,
+    perp: Vec<PerpPosition>,
+    margin: MarginInfo,
+    collateral: Collateral,
+    leverage: Decimal,
+}
+
+impl AccountState {
+    async fn load(client: &DriftClient, user: &User) -> SdkResult<Self> {
+        let mut spot = Vec::new();
+        for position in user.spot_positions.iter().filter(|p| !p.is_available()) {
+            let spot_market = spot_market_account(client, position.market_index).await?;
+            spot.push(SpotPosition::from_sdk_type(position, &spot_market));
+        }
+        let leverage: Leverage = get_leverage(client, user)?.into();
+
+        Ok(Self {
+            spot,
+            perp: user
+                .perp_positions
+                .iter()
+                .filter(|p| !p.is_available())
+                .map(|p| (*p).into())
+                .collect(),
+            margin: calculate_margin_requirements(client, user)?.into(),
+            collateral: calculate_collateral(client, user, MarginRequirementType::Maintenance)?
+                .into(),
+            leverage: leverage.leverage,
+        })
+    }
+
+    /// Return an update of the sections changed since `prev`, if any
+    fn diff(&self, prev: Option<&Self>, ts: u64) -> Option<AccountEvent> {
+        fn changed<T: Clone + PartialEq>(prev: Option<&T>, next: &T) -> Option<T> {
+            (prev != Some(next)).then(|| next.clone())
+        }
+        let spot = changed(prev.map(|p| &p.spot), &self.spot);
+        let perp = changed(prev.map(|p| &p.perp), &self.perp);
+        let margin = changed(prev.map(|p| &p.margin), &self.margin);
+        let collateral = changed(prev.map(|p| &p.collateral), &self.collateral);
+        let leverage = changed(prev.map(|p| &p.leverage), &self.leverage);
+        if spot.is_none()
+            && perp.is_none()
+            && margin.is_none()
+            && collateral.is_none()
+            && leverage.is_none()
+        {
+            return None;
+        }
+
+        Some(AccountEvent::AccountUpdate {
+            spot,
+            perp,
+            margin,
+            collateral,
+            leverage,
+            ts,
+        })
+    }
+}
+
+/// Market account state streamed on the markets channel
+#[derive(Debug, PartialEq)]
+struct MarketState {
+    market: Market,
+    operations: MarketOperations,
+    oracle: Pubkey,
+    oracle_source: String,
+    /// perp only
+    last_funding_rate: Option<i64>,
+    /// perp only
+    fee_adjustment: Option<i16>,
+}
+
+impl MarketState {
+    /// Load the market state and slot from the cached market account, None if not cached
+    fn load(client: &DriftClient, market: Market) -> Option<(Self, u64)> {
+        match market.market_type {
+            MarketType::Perp => client
+                .try_get_perp_market_account_and_slot(market.market_index)
+                .map(|m| (Self::from_perp(&m.data), m.slot)),
+            MarketType::Spot => client
+                .try_get_spot_market_account_and_slot(market.market_index)
+                .map(|m| (Self::from_spot(&m.data), m.slot)),
+        }
+    }
+
+    fn from_perp(market: &PerpMarket) -> Self {
+        Self {
+            market: Market::perp(market.market_index),
+            operations: MarketOperations::perp(market),
+            oracle: market.amm.oracle,
+            oracle_source: camel_case(&market.amm.oracle_source),
+            last_funding_rate: Some(market.amm.last_funding_rate),
+            fee_adjustment: Some(market.fee_adjustment),
+        }
+    }
+
+    fn from_spot(market: &SpotMarket) -> Self {
+        Self {
+            market: Market::spot(market.market_index),
+            operations: MarketOperations::spot(market),
+            oracle: market.oracle,
+            oracle_source: camel_case(&market.oracle_source),
+            last_funding_rate: None,
+            fee_adjustment: None,
+        }
+    }
+
+    /// Return an update if any field changed since `prev`, or the initial state if `prev` is None
+    fn diff(&self, prev: Option<&Self>, ts: u64) -> Option<AccountEvent> {
+        let changed: Vec<String> = match prev {
+            Some(prev) => [
+                ("status", prev.operations.status != self.operations.status),
+                (
+                    "pausedOperations",
+                    prev.operations.paused_operations != self.operations.paused_operations,
+                ),
+                ("oracle", prev.oracle != self.oracle),
+                ("oracleSource", prev.oracle_source != self.oracle_source),
+                (
+                    "lastFundingRate",
+                    prev.last_funding_rate != self.last_funding_rate,
+                ),
+                ("feeAdjustment", prev.fee_adjustment != self.fee_adjustment),
+            ]
+            .into_iter()
+            .filter_map(|(field, changed)| changed.then(|| field.to_string()))
+            .collect(),
+            None => vec![],
+        };
+        if prev.is_some() && changed.is_empty() {
+            return None;
+        }
+
+        Some(AccountEvent::MarketUpdate {
+            market: self.market,
+            status: camel_case(&self.operations.status),
+            paused_operations: self
+                .operations
+                .paused_operations
+                .iter()
+                .map(|op| op.to_string())
+                .collect(),
+            oracle: self.oracle.to_string(),
+            oracle_source: self.oracle_source.clone(),
+            last_funding_rate: self
+                .last_funding_rate
+                .map(|r| Decimal::new(r, FUNDING_RATE_DECIMALS).normalize()),
+            fee_adjustment: self.fee_adjustment,
+            changed,
+            ts,
+        })
+    }
+}
+
+/// Format an sdk enum variant name in camelCase e.g. `FundingPaused` => `fundingPaused`
+pub(crate) fn camel_case<T: Debug>(value: &T) -> String {
+    let name = format!("{value:?}");
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_lowercase().chain(chars).collect(),
+        None => name,
+    }
+}
+
+/// Stream updates of the user account `address` from a Ws account subscription of its own
+///
+/// the current account is sent first, updates only follow changes. The subscription is closed
+/// once the receiver is dropped.
+fn user_account_updates(
+    client: &'static DriftClient,
+    ws_client: Arc<PubsubClient>,
+    address: Pubkey,
+) -> mpsc::Receiver<(u64, User)> {
+    let (tx, rx) = mpsc::channel(8);
+    tokio::spawn(async move {
+        let rpc = client.rpc();
+        let config = RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64Zstd),
+            commitment: Some(rpc.commitment()),
+            ..Default::default()
+        };
+        let decode = |data: &[u8]| User::try_deserialize(&mut &data[..]).ok();
+        let mut backoff = Backoff::new(MIN_RESUBSCRIBE_DELAY, MAX_RESUBSCRIBE_DELAY, u32::MAX);
+        while !tx.is_closed() {
+            match ws_client
+                .account_subscribe(&address, Some(config.clone()))
+                .await
+            {
+                Ok((mut updates, unsubscribe)) => {
+                    backoff.reset();
+                    // subscribed first so no change is missed between the fetch and the updates
+                    let current = rpc
+                        .get_account_with_commitment(&address, rpc.commitment())
+                        .await;
+                    if let Ok(res) = current {
+                        let user = res.value.and_then(|account| decode(&account.data));
+                        if let Some(user) = user {
+                            let _ = tx.send((res.context.slot, user)).await;
+                        }
+                    }
+                    loop {
+                        tokio::select! {
+                            _ = tx.closed() => break,
+                            update = updates.next() => {
+                                let Some(update) = update else {
+                                    warn!(target: LOG_TARGET, "user account subscription ended: {address:?}");
+                                    break;
+                                };
+                                let account = update.value.decode::<Account>();
+                                if let Some(user) = account.and_then(|a| decode(&a.data)) {
+                                    let _ = tx.send((update.context.slot, user)).await;
+                                }
+                            }
+                        }
+                    }
+                    unsubscribe().await;
+                }
+                Err(err) => {
+                    warn!(target: LOG_TARGET, "failed subscribing user account: {address:?}, {err:?}");
+                }
+            }
+            if tx.is_closed() {
+                break;
+            }
+            let delay = backoff.next_delay().unwrap_or(MAX_RESUBSCRIBE_DELAY);
+            tokio::time::sleep(delay).await;
+        }
+    });
+    rx
+}
+
+/// Tracks open orders with a `maxTs` to emit events as they approach and reach expiry
+///
+/// only orders created while subscribed are tracked
+struct OrderExpiryMonitor {
+    /// seconds before `maxTs` to emit the expiring event, 0 to disable
+    warning: i64,
+    /// order id => (max ts, unfilled amount, warned)
+    orders: HashMap<u32, (i64, Decimal, bool)>,
+}
+
+impl OrderExpiryMonitor {
+    fn new(warning: u64) -> Self {
+        Self {
+            warning: warning as i64,
+            orders: HashMap::default(),
+        }
+    }
+
+    /// Update tracked orders from an account event
+    fn on_event(&mut self, event: &AccountEvent) {
+        match event {
+            AccountEvent::OrderCreate { order, .. } if order.max_ts > 0 => {
+                self.orders.insert(
+                    order.order_id,
+                    (order.max_ts, order.amount - order.filled, false),
+                );
+            }
+            AccountEvent::OrderCancel { order_id, .. }
+            | AccountEvent::OrderExpire { order_id, .. } => {
+                self.orders.remove(order_id);
+            }
+            AccountEvent::Fill {
+                order_id, amount, ..
+            } => {
+                if let Some((_, unfilled, _)) = self.orders.get_mut(order_id) {
+                    *unfilled -= *amount;
+                    if unfilled.is_sign_negative() || unfilled.is_zero() {
+                        self.orders.remove(order_id);
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Return expiry events due at `now` (unix seconds)
+    fn poll(&mut self, now: i64) -> Vec<AccountEvent> {
+        let mut events = Vec::new();
+        self.orders.retain(|order_id, (max_ts, _, warned)| {
+            if now >= *max_ts {
+                events.push(AccountEvent::OrderExpired {
+                    order_id: *order_id,
+                    max_ts: *max_ts,
+                    ts: now as u64,
+                });
+                return false;
+            }
+            if self.warning > 0 && !*warned && now >= *max_ts - self.warning {
+                *warned = true;
+                events.push(AccountEvent::OrderExpiring {
+                    order_id: *order_id,
+                    max_ts: *max_ts,
+                    ts: now as u64,
+                });
+            }
+            true
+        });
+
+        events
+    }
+}
+
+/// Cause of a spot balance change outside of fills
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
-pub(crate) enum Side {
-    Buy,
-    Sell,
+pub(crate) enum BalanceChange {
+    /// net deposits increased, incl. transfers from other sub-accounts
+    Deposit,
+    /// net deposits decreased, incl. transfers to other sub-accounts
+    Withdrawal,
+    Interest,
+}
+
+/// Spot balance of a sub-account in one market
+#[derive(Debug, Clone, PartialEq)]
+struct SpotBalance {
+    market_index: u16,
+    /// token amount, negative for borrows
+    amount: Decimal,
+    scaled_balance: u64,
+    /// net deposits in token amount
+    cumulative_deposits: Decimal,
+}
+
+impl SpotBalance {
+    async fn load(client: &DriftClient, user: &User) -> SdkResult<Vec<Self>> {
+        // closed positions keep their net deposits until the slot is reused
+        let positions = user
+            .spot_positions
+            .iter()
+            .filter(|p| !p.is_available())
+            .chain(
+                user.spot_positions
+                    .iter()
+                    .filter(|p| p.is_available() && p.cumulative_deposits != 0),
+            );
+        let mut balances: Vec<Self> = Vec::new();
+        for position in positions {
+            if balances
+                .iter()
+                .any(|b| b.market_index == position.market_index)
+            {
+                continue;
+            }
+            let spot_market = spot_market_account(client, position.market_index).await?;
+            let SpotPosition {
+                amount,
+                balance_type,
+                ..
+            } = SpotPosition::from_sdk_type(position, &spot_market);
+            balances.push(Self {
+                market_index: position.market_index,
+                amount: if balance_type == "borrow" {
+                    amount.neg()
+                } else {
+                    amount
+                },
+                scaled_balance: position.scaled_balance,
+                cumulative_deposits: Decimal::new(
+                    position.cumulative_deposits,
+                    spot_market.decimals,
+                )
+                .normalize(),
+            });
+        }
+        Ok(balances)
+    }
+}
+
+/// Tracks spot balances to emit changes outside of fills
+///
+/// changes of net deposits are reported as deposits or withdrawals, changes in the token amount of
+/// an unchanged scaled balance as accrued interest. Other changes (fills, pnl settlement) are not
+/// reported.
+#[derive(Default)]
+struct BalanceMonitor {
+    /// balances by market as of the last update, None before the first update
+    balances: Option<HashMap<u16, SpotBalance>>,
+}
+
+impl BalanceMonitor {
+    /// Update balances, returns balance change events
+    ///
+    /// * `collateral` - current total collateral
+    /// * `interest` - report interest accrued since the last report, otherwise it is carried over
+    fn update(
+        &mut self,
+        balances: Vec<SpotBalance>,
+        collateral: Decimal,
+        interest: bool,
+        ts: u64,
+    ) -> Vec<AccountEvent> {
+        let Some(ref last) = self.balances else {
+            self.balances = Some(balances.into_iter().map(|b| (b.market_index, b)).collect());
+            return vec![];
+        };
+        let mut events = Vec::new();
+        let mut next = HashMap::with_capacity(balances.len());
+        for mut balance in balances {
+            let prev = last.get(&balance.market_index);
+            let deposited =
+                balance.cumulative_deposits - prev.map_or(Decimal::ZERO, |p| p.cumulative_deposits);
+            let event = |reason, change| AccountEvent::BalanceUpdate {
+                market_index: balance.market_index,
+                reason,
+                change,
+                balance: balance.amount,
+                collateral,
+                ts,
+            };
+            if !deposited.is_zero() {
+                let reason = if deposited.is_sign_positive() {
+                    BalanceChange::Deposit
+                } else {
+                    BalanceChange::Withdrawal
+                };
+                events.push(event(reason, deposited));
+            } else if let Some(prev) = prev.filter(|p| p.scaled_balance == balance.scaled_balance) {
+                let accrued = balance.amount - prev.amount;
+                if interest && !accrued.is_zero() {
+                    events.push(event(BalanceChange::Interest, accrued));
+                } else {
+                    balance.amount = prev.amount;
+                }
+            }
+            next.insert(balance.market_index, balance);
+        }
+        self.balances = Some(next);
+        events
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct OrderWithDecimals {
     /// The slot the order was placed
@@ -367,12 +1999,14 @@ pub(crate) struct OrderWithDecimals {
     pub market_index: u16,
     /// The type of order
     #[serde(serialize_with = "ser_order_type", deserialize_with = "de_order_type")]
+    #[cfg_attr(feature = "schema", schemars(with = "String"))]
     pub order_type: OrderType,
     /// Whether market is spot or perp
     #[serde(
         serialize_with = "crate::types::ser_market_type",
         deserialize_with = "crate::types::de_market_type"
     )]
+    #[cfg_attr(feature = "schema", schemars(with = "drift_gateway_types::MarketType"))]
     pub market_type: MarketType,
     /// User generated order id. Can make it easier to place/cancel orders
     pub user_order_id: u8,
@@ -380,6 +2014,7 @@ pub(crate) struct OrderWithDecimals {
         serialize_with = "ser_position_direction",
         deserialize_with = "de_position_direction"
     )]
+    #[cfg_attr(feature = "schema", schemars(with = "drift_gateway_types::Side"))]
     pub direction: PositionDirection,
     /// Whether the order is allowed to only reduce position size
     pub reduce_only: bool,
@@ -392,7 +2027,7 @@ pub(crate) struct OrderWithDecimals {
 }
 
 impl OrderWithDecimals {
-    fn from_order(value: Order, decimals: u32) -> Self {
+    pub(crate) fn from_order(value: Order, decimals: u32) -> Self {
         Self {
             slot: value.slot,
             price: Decimal::new(value.price as i64, PRICE_DECIMALS).normalize(),
@@ -476,6 +2111,73 @@ where
 }
 
 /// Map drift-program events into gateway friendly types for events to the specific UserAccount
+/// Map a fill of any user in `market` to a trade event
+pub(crate) fn map_drift_event_for_market(
+    program_data: &ProgramData,
+    event: &DriftEvent,
+    market: Market,
+) -> Option<AccountEvent> {
+    let DriftEvent::OrderFill {
+        maker,
+        maker_side,
+        taker,
+        taker_side,
+        base_asset_amount_filled,
+        quote_asset_amount_filled,
+        oracle_price,
+        market_index,
+        market_type,
+        signature,
+        tx_idx,
+        ts,
+        ..
+    } = event
+    else {
+        return None;
+    };
+    if *market_index != market.market_index
+        || *market_type != market.market_type
+        || *base_asset_amount_filled == 0
+    {
+        return None;
+    }
+    let amount = Decimal::new(
+        *base_asset_amount_filled as i64,
+        get_market_decimals(program_data, market),
+    );
+    Some(AccountEvent::Trade {
+        market,
+        side: trade_side(*taker_side, *maker_side)?,
+        amount: amount.normalize(),
+        price: (Decimal::new(*quote_asset_amount_filled as i64, PRICE_DECIMALS) / amount)
+            .normalize(),
+        oracle_price: Decimal::new(*oracle_price, PRICE_DECIMALS).normalize(),
+        maker: maker.map(|x| x.to_string()),
+        taker: taker.map(|x| x.to_string()),
+        ts: *ts,
+        signature: signature.to_string(),
+        tx_idx: *tx_idx,
+    })
+}
+
+/// Return the taker side of a fill
+///
+/// fills against the AMM lack either a maker or a taker, the AMM takes the opposite side of the maker
+fn trade_side(
+    taker_side: Option<PositionDirection>,
+    maker_side: Option<PositionDirection>,
+) -> Option<Side> {
+    match (taker_side, maker_side) {
+        (Some(PositionDirection::Long), _) | (None, Some(PositionDirection::Short)) => {
+            Some(Side::Buy)
+        }
+        (Some(PositionDirection::Short), _) | (None, Some(PositionDirection::Long)) => {
+            Some(Side::Sell)
+        }
+        (None, None) => None,
+    }
+}
+
 pub(crate) fn map_drift_event_for_account(
     program_data: &ProgramData,
     event: &DriftEvent,
@@ -641,3 +2343,628 @@ pub(crate) fn map_drift_event_for_account(
         ),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use drift_rs::types::MarketStatus;
+    use futures_util::FutureExt;
+
+    use super::*;
+
+    fn order_create(order_id: u32, max_ts: i64) -> AccountEvent {
+        AccountEvent::OrderCreate {
+            order: OrderWithDecimals::from_order(
+                Order {
+                    order_id,
+                    max_ts,
+                    base_asset_amount: 1_000_000_000,
+                    status: OrderStatus::Open,
+                    ..Default::default()
+                },
+                9,
+            ),
+            ts: 0,
+            signature: String::default(),
+            tx_idx: 0,
+        }
+    }
+
+    #[test]
+    fn ws_request_wide_sub_account_id() {
+        let request: WsRequest =
+            serde_json::from_str(r#"{"method":"subscribe","subAccountId":300}"#).unwrap();
+        assert_eq!(request.sub_account_id, 300);
+        let request: WsRequest =
+            serde_json::from_str(r#"{"method":"unsubscribe","subAccountId":3}"#).unwrap();
+        assert_eq!(request.sub_account_id, 3);
+    }
+
+    #[test]
+    fn client_events_in_sync() {
+        use drift_gateway_types as client;
+
+        use crate::types::assert_round_trip;
+
+        let market = Market::perp(0);
+        let price = Decimal::new(805, 1);
+        let events = vec![
+            AccountEvent::fill(
+                PositionDirection::Short,
+                -1_000,
+                1_500_000_000,
+                120_750_000,
+                80_400_000,
+                3,
+                1_700_000_000,
+                9,
+                &"sig".to_string(),
+                2,
+                0,
+                MarketType::Perp,
+                Some("maker".into()),
+                Some(9),
+                Some(-1_000),
+                None,
+                None,
+                None,
+            ),
+            order_create(3, 1_700_000_060),
+            AccountEvent::OrderCancel {
+                order_id: 3,
+                ts: 1_700_000_000,
+                signature: "sig".into(),
+                tx_idx: 1,
+            },
+            AccountEvent::OrderCancelMissing {
+                user_order_id: 7,
+                order_id: 0,
+                signature: "sig".into(),
+            },
+            AccountEvent::OrderExpire {
+                order_id: 3,
+                fee: Decimal::new(-1, 2),
+                ts: 1_700_000_000,
+                signature: "sig".into(),
+            },
+            AccountEvent::OrderExpiring {
+                order_id: 3,
+                max_ts: 1_700_000_060,
+                ts: 1_700_000_000,
+            },
+            AccountEvent::OrderExpired {
+                order_id: 3,
+                max_ts: 1_700_000_060,
+                ts: 1_700_000_060,
+            },
+            AccountEvent::OrderBackfill {
+                order: OrderWithDecimals::from_order(
+                    Order {
+                        order_type: OrderType::TriggerLimit,
+                        direction: PositionDirection::Short,
+                        trigger_price: 90_000_000,
+                        auction_duration: 10,
+                        ..Default::default()
+                    },
+                    9,
+                ),
+                ts: 1_700_000_000,
+            },
+            AccountEvent::OrderClosed {
+                order_id: 3,
+                ts: 1_700_000_000,
+            },
+            AccountEvent::OrderUpdate {
+                order: OrderWithDecimals::from_order(Order::default(), 6),
+                ts: 1_700_000_000,
+            },
+            AccountEvent::BalanceUpdate {
+                market_index: 1,
+                reason: BalanceChange::Withdrawal,
+                change: Decimal::new(-25, 1),
+                balance: Decimal::new(10, 0),
+                collateral: Decimal::new(1_000, 0),
+                ts: 1_700_000_000,
+            },
+            AccountEvent::AccountUpdate {
+                spot: Some(vec![SpotPosition {
+                    amount: Decimal::new(10, 0),
+                    balance_type: "deposit".into(),
+                    market_index: 1,
+                }]),
+                perp: None,
+                margin: Some(MarginInfo {
+                    initial: Decimal::new(162, 1),
+                    maintenance: Decimal::new(81, 1),
+                }),
+                collateral: Some(Collateral {
+                    total: Decimal::new(1_000, 0),
+                    free: Decimal::new(9838, 1),
+                }),
+                leverage: Some(Decimal::new(12, 2)),
+                ts: 1_700_000_000,
+            },
+            AccountEvent::MarketUpdate {
+                market,
+                status: "active".into(),
+                paused_operations: vec!["fill".into()],
+                oracle: Pubkey::default().to_string(),
+                oracle_source: "pythPull".into(),
+                last_funding_rate: Some(Decimal::new(1, 6)),
+                fee_adjustment: None,
+                changed: vec!["pausedOperations".into()],
+                ts: 1_700_000_000,
+            },
+            AccountEvent::MarketData {
+                market,
+                oracle_price: price,
+                bids: vec![L2Level {
+                    price: Decimal::new(804, 1),
+                    size: Decimal::new(2, 0),
+                }],
+                asks: vec![],
+                ts: 1_700_000_000,
+            },
+            AccountEvent::OraclePrice {
+                market,
+                price,
+                confidence: Decimal::new(1, 2),
+                ts: 1_700_000_000,
+            },
+            AccountEvent::Trade {
+                market,
+                side: Side::Buy,
+                amount: Decimal::new(15, 1),
+                price,
+                oracle_price: price,
+                maker: Some("maker".into()),
+                taker: None,
+                ts: 1_700_000_000,
+                signature: "sig".into(),
+                tx_idx: 0,
+            },
+            AccountEvent::FundingPayment {
+                amount: Decimal::new(-12, 3),
+                market_index: 0,
+                ts: 1_700_000_000,
+                signature: "sig".into(),
+                tx_idx: 3,
+            },
+            AccountEvent::Status(StatusUpdate {
+                condition: StatusCondition::MarketPaused,
+                subject: Some("perp-0".into()),
+                active: true,
+                detail: Some("fills paused".into()),
+                ts: 1_700_000_000,
+            }),
+        ];
+
+        for (seq, data) in events.into_iter().enumerate() {
+            assert_round_trip::<client::AccountEvent>(&data);
+            let event: client::Event = assert_round_trip(&WsEvent {
+                data,
+                channel: Channel::Orders,
+                sub_account_id: Some(2),
+                sub_account_name: Some("mm"),
+                slot: Some(300_000_000),
+                seq: seq as u64 + 1,
+            });
+            assert!(matches!(event.data, client::EventData::Known(_)));
+        }
+
+        let trade: client::Event = serde_json::from_str(
+            &serde_json::to_string(&WsEvent {
+                data: AccountEvent::OrderClosed {
+                    order_id: 3,
+                    ts: 1_700_000_000,
+                },
+                channel: Channel::Trades,
+                sub_account_id: None,
+                sub_account_name: None,
+                slot: None,
+                seq: 1,
+            })
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(trade.sub_account_id, 0);
+        assert!(matches!(trade.data, client::EventData::Known(_)));
+    }
+
+    #[test]
+    fn heartbeat_request() {
+        let request: WsRequest = serde_json::from_str(r#"{"method":"heartbeat"}"#).unwrap();
+        assert!(matches!(request.method, Method::Heartbeat));
+    }
+
+    #[test]
+    fn auth_request() {
+        let request: WsRequest =
+            serde_json::from_str(r#"{"method":"auth","signature":"5bdc"}"#).unwrap();
+        assert!(matches!(request.method, Method::Auth));
+        assert_eq!(request.signature.as_deref(), Some("5bdc"));
+        assert!(request.api_key.is_none());
+    }
+
+    #[test]
+    fn request_acknowledgment() {
+        let request: WsRequest =
+            serde_json::from_str(r#"{"method":"subscribe","subAccountId":0,"requestId":"sub-1"}"#)
+                .unwrap();
+        let request_id = RequestId::from_client(request.request_id.as_deref());
+        let Outgoing::Message(msg) = ack("subscribed", &request_id) else {
+            panic!("not a message");
+        };
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(msg.to_text().unwrap()).unwrap(),
+            json!({"result": "subscribed", "requestId": "sub-1"})
+        );
+    }
+
+    #[test]
+    fn batch_request_and_frame() {
+        let request: WsRequest = serde_json::from_str(
+            r#"{"method":"subscribe","subAccountId":0,"batch":{"intervalMs":50}}"#,
+        )
+        .unwrap();
+        let batch = request.batch.unwrap();
+        assert_eq!(
+            (batch.interval_ms, batch.max_events),
+            (50, DEFAULT_BATCH_EVENTS)
+        );
+
+        let mut batch = vec![];
+        assert!(batch_frame(&mut batch).is_none());
+        batch.push(r#"{"a":1}"#.to_string());
+        batch.push(r#"{"b":2}"#.to_string());
+        assert_eq!(
+            batch_frame(&mut batch),
+            Some(Message::text(r#"[{"a":1},{"b":2}]"#))
+        );
+        assert!(batch.is_empty());
+    }
+
+    #[test]
+    fn oracle_interval_opt_in() {
+        assert_eq!(oracle_interval(None, None), None);
+        assert_eq!(oracle_interval(None, Some(Channel::Fills)), None);
+        assert_eq!(
+            oracle_interval(None, Some(Channel::Oracle)),
+            Some(DEFAULT_ORACLE_INTERVAL)
+        );
+        assert_eq!(
+            oracle_interval(Some(250), None),
+            Some(Duration::from_millis(250))
+        );
+        // throttled to the min. interval
+        assert_eq!(oracle_interval(Some(0), None), Some(MIN_ORACLE_INTERVAL));
+    }
+
+    #[test]
+    fn seq_assigned_at_dequeue() {
+        let event = |stream: u64, ts: u64| {
+            Outgoing::Event(QueuedEvent {
+                stream,
+                data: AccountEvent::Status(StatusUpdate {
+                    condition: StatusCondition::EventStream,
+                    subject: None,
+                    active: false,
+                    detail: None,
+                    ts,
+                }),
+                channel: Channel::Status,
+                sub_account_id: None,
+                sub_account_name: None,
+                slot: None,
+            })
+        };
+        let book = Policy::Latest((Channel::MarketData, 0, true));
+        let queue = OutboundQueue::<Outgoing, EventKey>::new(3);
+        queue.push(event(1, 1), book.clone()).unwrap();
+        queue.push(event(1, 2), Policy::BestEffort).unwrap();
+        // supersedes the queued snapshot
+        queue.push(event(1, 3), book).unwrap();
+        queue.send(event(2, 4)).unwrap();
+        // full, the best-effort event is dropped
+        queue.send(event(1, 5)).unwrap();
+
+        let mut seqs = HashMap::new();
+        let mut written = vec![];
+        while let Some(Some(Outgoing::Event(event))) = queue.pop().now_or_never() {
+            let event: serde_json::Value =
+                serde_json::from_str(&event.serialize(&mut seqs)).unwrap();
+            written.push((
+                event["data"]["status"]["ts"].as_u64(),
+                event["seq"].as_u64(),
+            ));
+        }
+        assert_eq!(
+            written,
+            [(Some(3), Some(1)), (Some(4), Some(1)), (Some(5), Some(2))]
+        );
+    }
+
+    #[test]
+    fn trades_request_and_side() {
+        let request: WsRequest = serde_json::from_str(
+            r#"{"method":"subscribe","channel":"trades","market":{"marketIndex":1,"marketType":"perp"}}"#,
+        )
+        .unwrap();
+        assert_eq!(request.channel, Some(Channel::Trades));
+        assert_eq!(request.market.unwrap().market_index, 1);
+
+        let (long, short) = (PositionDirection::Long, PositionDirection::Short);
+        assert!(matches!(
+            trade_side(Some(long), Some(short)),
+            Some(Side::Buy)
+        ));
+        assert!(matches!(trade_side(Some(short), None), Some(Side::Sell)));
+        // the AMM takes the other side of the maker
+        assert!(matches!(trade_side(None, Some(long)), Some(Side::Sell)));
+        assert!(matches!(trade_side(None, Some(short)), Some(Side::Buy)));
+        assert!(trade_side(None, None).is_none());
+    }
+
+    #[test]
+    fn order_expiry_monitor() {
+        let mut monitor = OrderExpiryMonitor::new(5);
+        monitor.on_event(&order_create(1, 100));
+        monitor.on_event(&order_create(2, 100));
+        // no max ts
+        monitor.on_event(&order_create(3, 0));
+        monitor.on_event(&AccountEvent::OrderCancel {
+            order_id: 2,
+            ts: 0,
+            signature: String::default(),
+            tx_idx: 0,
+        });
+
+        assert!(monitor.poll(94).is_empty());
+        let events = monitor.poll(95);
+        assert_eq!(events.len(), 1);
+        assert!(matches!(
+            events[0],
+            AccountEvent::OrderExpiring {
+                order_id: 1,
+                max_ts: 100,
+                ..
+            }
+        ));
+        // warns once
+        assert!(monitor.poll(96).is_empty());
+        let events = monitor.poll(100);
+        assert_eq!(events.len(), 1);
+        assert!(matches!(
+            events[0],
+            AccountEvent::OrderExpired { order_id: 1, .. }
+        ));
+        assert!(monitor.poll(101).is_empty());
+    }
+
+    #[test]
+    fn event_filter_by_channel_and_market() {
+        let fill = |market_index| AccountEvent::Fill {
+            side: Side::Buy,
+            fee: Decimal::ZERO,
+            amount: Decimal::ONE,
+            price: Decimal::ONE,
+            oracle_price: Decimal::ONE,
+            order_id: 1,
+            market_index,
+            market_type: MarketType::Perp,
+            ts: 1,
+            tx_idx: 0,
+            signature: String::default(),
+            maker: None,
+            maker_order_id: None,
+            maker_fee: None,
+            taker: None,
+            taker_order_id: None,
+            taker_fee: None,
+        };
+        let cancel = |order_id| AccountEvent::OrderCancel {
+            order_id,
+            ts: 1,
+            signature: String::default(),
+            tx_idx: 0,
+        };
+
+        assert_eq!(
+            serde_json::to_string(&Channel::MarketData).unwrap(),
+            r#""marketData""#
+        );
+        let mut all = EventFilter::new(None, None);
+        assert!(all.accepts(Channel::Fills, &fill(0)));
+        assert!(all.accepts(Channel::Orders, &cancel(1)));
+
+        let mut fills = EventFilter::new(Some(Channel::Fills), Some(Market::perp(1)));
+        assert!(fills.accepts(Channel::Fills, &fill(1)));
+        assert!(!fills.accepts(Channel::Fills, &fill(0)));
+        assert!(!fills.accepts(Channel::Orders, &order_create(1, 0)));
+
+        // order events by id resolve the market of the created order
+        let mut create = order_create(7, 0);
+        if let AccountEvent::OrderCreate { ref mut order, .. } = create {
+            order.market_type = MarketType::Perp;
+        }
+        let mut orders = EventFilter::new(None, Some(Market::perp(0)));
+        assert!(orders.accepts(Channel::Orders, &create));
+        assert!(orders.accepts(Channel::Orders, &cancel(7)));
+        assert!(!orders.accepts(Channel::Orders, &cancel(8)));
+        let update = AccountEvent::AccountUpdate {
+            spot: None,
+            perp: None,
+            margin: None,
+            collateral: None,
+            leverage: Some(Decimal::ONE),
+            ts: 1,
+        };
+        assert!(!orders.accepts(Channel::Account, &update));
+    }
+
+    #[test]
+    fn balance_monitor_changes() {
+        let usdc = |amount: i64, scaled_balance: u64, cumulative_deposits: i64| SpotBalance {
+            market_index: 0,
+            amount: Decimal::new(amount, 0),
+            scaled_balance,
+            cumulative_deposits: Decimal::new(cumulative_deposits, 0),
+        };
+        let collateral = Decimal::ONE_HUNDRED;
+        let mut monitor = BalanceMonitor::default();
+        assert!(monitor
+            .update(vec![usdc(100, 100, 100)], collateral, false, 1)
+            .is_empty());
+
+        // deposit
+        let events = monitor.update(vec![usdc(150, 150, 150)], collateral, false, 2);
+        assert!(matches!(
+            events[..],
+            [AccountEvent::BalanceUpdate {
+                reason: BalanceChange::Deposit,
+                change,
+                balance,
+                ..
+            }] if change == Decimal::new(50, 0) && balance == Decimal::new(150, 0)
+        ));
+
+        // interest is carried over until reported
+        assert!(monitor
+            .update(vec![usdc(151, 150, 150)], collateral, false, 3)
+            .is_empty());
+        let events = monitor.update(vec![usdc(152, 150, 150)], collateral, true, 4);
+        assert!(matches!(
+            events[..],
+            [AccountEvent::BalanceUpdate {
+                reason: BalanceChange::Interest,
+                change,
+                ..
+            }] if change == Decimal::TWO
+        ));
+
+        // fills are not reported
+        assert!(monitor
+            .update(vec![usdc(120, 118, 150)], collateral, true, 5)
+            .is_empty());
+
+        // withdrawal of a closed position
+        let events = monitor.update(vec![usdc(0, 0, 30)], collateral, true, 6);
+        assert!(matches!(
+            events[..],
+            [AccountEvent::BalanceUpdate {
+                reason: BalanceChange::Withdrawal,
+                change,
+                ..
+            }] if change == Decimal::new(-120, 0)
+        ));
+    }
+
+    #[test]
+    fn account_state_diff() {
+        let state = AccountState {
+            spot: vec![],
+            perp: vec![drift_rs::types::PerpPosition {
+                market_index: 0,
+                base_asset_amount: 1_000_000_000,
+                ..Default::default()
+            }
+            .into()],
+            margin: MarginInfo {
+                initial: Decimal::ONE,
+                maintenance: Decimal::ONE,
+            },
+            collateral: Collateral {
+                total: Decimal::TEN,
+                free: Decimal::TEN,
+            },
+            leverage: Decimal::ONE,
+        };
+
+        // first update has all sections
+        assert!(matches!(
+            state.diff(None, 1),
+            Some(AccountEvent::AccountUpdate {
+                spot: Some(_),
+                perp: Some(_),
+                margin: Some(_),
+                collateral: Some(_),
+                leverage: Some(_),
+                ..
+            })
+        ));
+
+        let next = AccountState {
+            spot: vec![],
+            perp: vec![],
+            margin: MarginInfo {
+                initial: Decimal::ZERO,
+                maintenance: Decimal::ZERO,
+            },
+            collateral: Collateral {
+                total: Decimal::TEN,
+                free: Decimal::TEN,
+            },
+            leverage: Decimal::ZERO,
+        };
+        assert!(matches!(
+            next.diff(Some(&state), 2),
+            Some(AccountEvent::AccountUpdate {
+                spot: None,
+                perp: Some(_),
+                margin: Some(_),
+                collateral: None,
+                leverage: Some(_),
+                ts: 2,
+            })
+        ));
+        assert!(next.diff(Some(&next), 3).is_none());
+    }
+
+    #[test]
+    fn market_state_diff() {
+        let market_state = || MarketState {
+            market: Market::perp(0),
+            operations: MarketOperations {
+                status: MarketStatus::Active,
+                paused_operations: vec![],
+            },
+            oracle: Pubkey::default(),
+            oracle_source: "pythPull".into(),
+            last_funding_rate: Some(1_000),
+            fee_adjustment: Some(0),
+        };
+        let state = market_state();
+        // initial state
+        assert!(matches!(
+            state.diff(None, 1),
+            Some(AccountEvent::MarketUpdate { ref changed, .. }) if changed.is_empty()
+        ));
+        assert!(state.diff(Some(&state), 2).is_none());
+
+        let paused = MarketState {
+            operations: MarketOperations {
+                status: MarketStatus::FillPaused,
+                paused_operations: vec!["fill"],
+            },
+            ..market_state()
+        };
+        match paused.diff(Some(&state), 3) {
+            Some(AccountEvent::MarketUpdate {
+                status,
+                paused_operations,
+                changed,
+                ..
+            }) => {
+                assert_eq!(status, "fillPaused");
+                assert_eq!(paused_operations, ["fill"]);
+                assert_eq!(changed, ["status", "pausedOperations"])
+            }
+            other => panic!("unexpected: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn camel_case_works() {
+        assert_eq!(camel_case(&MarketType::Perp), "perp");
+        assert_eq!(camel_case(&OrderType::TriggerLimit), "triggerLimit");
+    }
+}
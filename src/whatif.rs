@@ -0,0 +1,97 @@
+//! Post-trade margin simulation of order batches
+
+use drift_rs::{
+    math::constants::BASE_PRECISION,
+    types::{accounts::User, OrderParams, PositionDirection},
+};
+
+/// Fill price of `order` given the current oracle price (PRICE_PRECISION)
+pub fn fill_price(order: &OrderParams, oracle_price: i64) -> i64 {
+    if order.price > 0 {
+        order.price as i64
+    } else {
+        oracle_price + order.oracle_price_offset.unwrap_or_default() as i64
+    }
+}
+
+/// Apply a complete fill of perp `order` at `price` (PRICE_PRECISION) to `user`
+pub fn apply_perp_fill(user: &mut User, order: &OrderParams, price: i64) -> Result<(), String> {
+    let base = match order.direction {
+        PositionDirection::Long => order.base_asset_amount as i64,
+        PositionDirection::Short => -(order.base_asset_amount as i64),
+    };
+    // PRICE_PRECISION == QUOTE_PRECISION
+    let quote = (base as i128 * price as i128 / BASE_PRECISION as i128) as i64;
+
+    let position = match user
+        .perp_positions
+        .iter()
+        .position(|p| p.market_index == order.market_index && !p.is_available())
+    {
+        Some(idx) => &mut user.perp_positions[idx],
+        None => {
+            let position = user
+                .perp_positions
+                .iter_mut()
+                .find(|p| p.is_available())
+                .ok_or_else(|| "no free perp position slots".to_string())?;
+            *position = Default::default();
+            position.market_index = order.market_index;
+            position
+        }
+    };
+    position.base_asset_amount += base;
+    position.quote_asset_amount -= quote;
+    position.quote_entry_amount -= quote;
+    position.quote_break_even_amount -= quote;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use drift_rs::types::{MarketType, PerpPosition};
+
+    use super::*;
+
+    fn order(market_index: u16, direction: PositionDirection, price: u64) -> OrderParams {
+        OrderParams {
+            market_index,
+            market_type: MarketType::Perp,
+            direction,
+            base_asset_amount: 2 * BASE_PRECISION as u64,
+            price,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn apply_perp_fill_works() {
+        let mut user = User::default();
+        user.perp_positions[0] = PerpPosition {
+            market_index: 1,
+            base_asset_amount: BASE_PRECISION as i64,
+            quote_asset_amount: -100_000_000,
+            ..Default::default()
+        };
+
+        // increase existing position
+        let o = order(1, PositionDirection::Long, 0);
+        apply_perp_fill(&mut user, &o, fill_price(&o, 110_000_000)).unwrap();
+        assert_eq!(
+            user.perp_positions[0].base_asset_amount,
+            3 * BASE_PRECISION as i64
+        );
+        assert_eq!(user.perp_positions[0].quote_asset_amount, -320_000_000);
+
+        // open a new position in a free slot
+        let o = order(2, PositionDirection::Short, 50_000_000);
+        apply_perp_fill(&mut user, &o, fill_price(&o, 0)).unwrap();
+        assert_eq!(user.perp_positions[1].market_index, 2);
+        assert_eq!(
+            user.perp_positions[1].base_asset_amount,
+            -2 * BASE_PRECISION as i64
+        );
+        assert_eq!(user.perp_positions[1].quote_asset_amount, 100_000_000);
+    }
+}
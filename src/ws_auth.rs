@@ -0,0 +1,89 @@
+//! Authentication of Ws connections
+//!
+//! With `DRIFT_GATEWAY_WS_AUTH_KEY` set, each connection is sent a random challenge and must
+//! authenticate with the key itself or the HMAC-SHA256 of the challenge under the key before
+//! subscribing. Connections not authenticated within [`AUTH_TIMEOUT`] are closed.
+
+use std::time::Duration;
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use solana_sdk::signature::{Keypair, Signer};
+
+/// Time a connection has to authenticate before it's closed
+pub const AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct WsAuth {
+    key: String,
+}
+
+impl WsAuth {
+    pub fn new(key: String) -> Self {
+        Self { key }
+    }
+
+    /// Return true if either credential is valid for `challenge`
+    ///
+    /// * `api_key` - the auth key
+    /// * `signature` - hex HMAC-SHA256 of `challenge` under the auth key
+    pub fn verify(&self, challenge: &str, api_key: Option<&str>, signature: Option<&str>) -> bool {
+        if let Some(api_key) = api_key {
+            return constant_time_eq(api_key.as_bytes(), self.key.as_bytes());
+        }
+        if let Some(signature) = signature {
+            let expected = to_hex(&hmac_sha256(self.key.as_bytes(), challenge.as_bytes()));
+            return constant_time_eq(signature.to_lowercase().as_bytes(), expected.as_bytes());
+        }
+        false
+    }
+}
+
+/// Return a new random challenge
+pub fn challenge() -> String {
+    // a fresh pubkey is 32 bytes from the OS rng
+    Keypair::new().pubkey().to_string()
+}
+
+pub(crate) fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("any key length");
+    mac.update(message);
+    mac.finalize().into_bytes().into()
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Compare without exiting early, so timing doesn't reveal a matching prefix
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hmac_matches_rfc_4231() {
+        assert_eq!(
+            to_hex(&hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn verify_credentials() {
+        let auth = WsAuth::new("Jefe".into());
+        let challenge = "what do ya want for nothing?";
+        assert!(auth.verify(challenge, Some("Jefe"), None));
+        assert!(!auth.verify(challenge, Some("jefe"), None));
+        assert!(auth.verify(
+            challenge,
+            None,
+            Some("5BDCC146BF60754E6A042426089575C75A003F089D2739839DEC58B964EC3843")
+        ));
+        assert!(!auth.verify("another challenge", None, Some("5bdcc146")));
+        assert!(!auth.verify(challenge, None, None));
+        assert_ne!(super::challenge(), super::challenge());
+    }
+}
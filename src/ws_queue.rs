@@ -0,0 +1,166 @@
+//! Bounded queue of messages to a Ws connection
+//!
+//! Subscriptions enqueue without waiting on the connection so a slow client can't stall event
+//! ingestion. When the queue is full, best-effort messages are dropped oldest first; if only
+//! reliable messages are queued the client is too slow and the queue closes, closing the
+//! connection.
+//!
+//! Dropped and superseded messages are gone without trace, so messages are numbered (Ws `seq`)
+//! once dequeued rather than when queued.
+
+use std::{collections::VecDeque, sync::Mutex};
+
+use tokio::sync::Notify;
+
+/// Handling of a message when the queue is full
+#[derive(Debug, Clone, PartialEq)]
+pub enum Policy<K> {
+    /// never dropped, the queue closes if there's no room
+    Reliable,
+    /// dropped if there's no room
+    BestEffort,
+    /// best-effort, supersedes a queued message with the same key e.g. snapshots of a market
+    Latest(K),
+}
+
+/// The queue was closed
+#[derive(Debug, PartialEq)]
+pub struct QueueClosed;
+
+struct QueueInner<T, K> {
+    items: VecDeque<(T, Policy<K>)>,
+    closed: bool,
+    /// closes once the queued messages are taken
+    ending: bool,
+}
+
+pub struct OutboundQueue<T, K> {
+    inner: Mutex<QueueInner<T, K>>,
+    notify: Notify,
+    capacity: usize,
+}
+
+impl<T, K: PartialEq> OutboundQueue<T, K> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inner: Mutex::new(QueueInner {
+                items: VecDeque::with_capacity(capacity),
+                closed: false,
+                ending: false,
+            }),
+            notify: Notify::new(),
+            capacity,
+        }
+    }
+
+    /// Enqueue a reliable message
+    pub fn send(&self, item: T) -> Result<(), QueueClosed> {
+        self.push(item, Policy::Reliable)
+    }
+
+    /// Enqueue a message, handled by `policy` when the queue is full
+    pub fn push(&self, item: T, policy: Policy<K>) -> Result<(), QueueClosed> {
+        let mut inner = self.inner.lock().expect("lock");
+        if inner.closed || inner.ending {
+            return Err(QueueClosed);
+        }
+        if let Policy::Latest(ref key) = policy {
+            // the new message is queued last, keeping messages in order
+            inner
+                .items
+                .retain(|(_, p)| !matches!(p, Policy::Latest(k) if k == key));
+        }
+        if inner.items.len() >= self.capacity {
+            let droppable = inner.items.iter().position(|(_, p)| *p != Policy::Reliable);
+            match (droppable, &policy) {
+                (Some(idx), _) => {
+                    inner.items.remove(idx);
+                }
+                (None, Policy::Reliable) => {
+                    inner.closed = true;
+                    inner.items.clear();
+                    self.notify.notify_one();
+                    return Err(QueueClosed);
+                }
+                (None, _) => return Ok(()),
+            }
+        }
+        inner.items.push_back((item, policy));
+        self.notify.notify_one();
+        Ok(())
+    }
+
+    /// Return the next message, none once the queue is closed
+    pub async fn pop(&self) -> Option<T> {
+        loop {
+            {
+                let mut inner = self.inner.lock().expect("lock");
+                if inner.closed {
+                    return None;
+                }
+                if let Some((item, _)) = inner.items.pop_front() {
+                    return Some(item);
+                }
+                if inner.ending {
+                    return None;
+                }
+            }
+            self.notify.notified().await;
+        }
+    }
+
+    /// Close the queue once the queued messages are taken
+    pub fn end(&self) {
+        self.inner.lock().expect("lock").ending = true;
+        self.notify.notify_one();
+    }
+
+    /// Close the queue, dropping queued messages
+    pub fn close(&self) {
+        let mut inner = self.inner.lock().expect("lock");
+        inner.closed = true;
+        inner.items.clear();
+        self.notify.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::FutureExt;
+
+    use super::*;
+
+    #[test]
+    fn overflow_policies() {
+        let queue = OutboundQueue::<u32, &str>::new(3);
+        queue.push(1, Policy::Latest("book")).unwrap();
+        queue.send(2).unwrap();
+        queue.push(3, Policy::BestEffort).unwrap();
+        // supersedes the queued snapshot
+        queue.push(4, Policy::Latest("book")).unwrap();
+        // full, the oldest droppable message makes room
+        queue.send(5).unwrap();
+        queue.push(6, Policy::BestEffort).unwrap();
+        assert_eq!(queue.pop().now_or_never(), Some(Some(2)));
+        assert_eq!(queue.pop().now_or_never(), Some(Some(5)));
+        assert_eq!(queue.pop().now_or_never(), Some(Some(6)));
+
+        // full of reliable messages, best-effort messages are dropped
+        for i in 0..3 {
+            queue.send(i).unwrap();
+        }
+        queue.push(7, Policy::BestEffort).unwrap();
+        // a reliable message without room closes the queue
+        assert_eq!(queue.send(9), Err(QueueClosed));
+        assert_eq!(queue.pop().now_or_never(), Some(None));
+        assert_eq!(queue.push(10, Policy::BestEffort), Err(QueueClosed));
+
+        // an ended queue is drained first
+        let queue = OutboundQueue::<u32, &str>::new(3);
+        queue.send(1).unwrap();
+        queue.end();
+        assert_eq!(queue.send(2), Err(QueueClosed));
+        assert_eq!(queue.pop().now_or_never(), Some(Some(1)));
+        assert_eq!(queue.pop().now_or_never(), Some(None));
+    }
+}
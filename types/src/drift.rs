@@ -0,0 +1,76 @@
+//! Conversions from drift program types
+
+use drift_rs::{
+    math::{
+        constants::{BASE_PRECISION, PRICE_PRECISION, QUOTE_PRECISION},
+        liquidation::{CollateralInfo, MarginRequirementInfo},
+    },
+    types::{self as sdk_types, accounts::SpotMarket},
+};
+use rust_decimal::Decimal;
+
+use crate::{Collateral, Leverage, MarginInfo, PerpPosition, SpotPosition};
+
+const PRICE_DECIMALS: u32 = PRICE_PRECISION.ilog10();
+const QUOTE_DECIMALS: u32 = QUOTE_PRECISION.ilog10();
+
+impl SpotPosition {
+    pub fn from_sdk_type(position: &sdk_types::SpotPosition, spot_market: &SpotMarket) -> Self {
+        // TODO: handle error
+        let token_amount = position.get_token_amount(spot_market).expect("ok");
+        Self {
+            amount: Decimal::from_i128_with_scale(token_amount as i128, spot_market.decimals)
+                .normalize(),
+            market_index: position.market_index,
+            balance_type: if position.balance_type == Default::default() {
+                "deposit".into()
+            } else {
+                "borrow".into()
+            },
+        }
+    }
+}
+
+impl From<sdk_types::PerpPosition> for PerpPosition {
+    fn from(value: sdk_types::PerpPosition) -> Self {
+        let amount = Decimal::new(value.base_asset_amount, BASE_PRECISION.ilog10());
+        let average_entry = Decimal::new(value.quote_entry_amount.abs(), PRICE_DECIMALS)
+            .checked_div(amount.abs())
+            .unwrap_or_default();
+
+        Self {
+            amount: amount.normalize(),
+            market_index: value.market_index,
+            average_entry: average_entry.normalize().round_dp(4),
+            extended: None,
+        }
+    }
+}
+
+impl From<MarginRequirementInfo> for MarginInfo {
+    fn from(value: MarginRequirementInfo) -> Self {
+        Self {
+            initial: Decimal::from_i128_with_scale(value.initial as i128, PRICE_DECIMALS)
+                .normalize(),
+            maintenance: Decimal::from_i128_with_scale(value.maintenance as i128, PRICE_DECIMALS)
+                .normalize(),
+        }
+    }
+}
+
+impl From<u128> for Leverage {
+    fn from(value: u128) -> Self {
+        Self {
+            leverage: Decimal::from_i128_with_scale(value as i128, PRICE_DECIMALS).normalize(),
+        }
+    }
+}
+
+impl From<CollateralInfo> for Collateral {
+    fn from(value: CollateralInfo) -> Self {
+        Self {
+            total: Decimal::from_i128_with_scale(value.total, QUOTE_DECIMALS).normalize(),
+            free: Decimal::from_i128_with_scale(value.free, QUOTE_DECIMALS).normalize(),
+        }
+    }
+}
@@ -0,0 +1,899 @@
+//! Request, response, and event types of the drift gateway API
+//!
+//! Shared by the gateway and its client so both ends serialize the same shapes. Decimal values are
+//! in UI units e.g. `0.1` SOL, `100.5` USDC.
+
+use std::borrow::Cow;
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "drift")]
+mod drift;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum MarketType {
+    Perp,
+    Spot,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct Market {
+    pub market_index: u16,
+    pub market_type: MarketType,
+}
+
+impl Market {
+    pub fn perp(market_index: u16) -> Self {
+        Self {
+            market_index,
+            market_type: MarketType::Perp,
+        }
+    }
+    pub fn spot(market_index: u16) -> Self {
+        Self {
+            market_index,
+            market_type: MarketType::Spot,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum OrderType {
+    #[default]
+    Limit,
+    Market,
+    Oracle,
+    TriggerLimit,
+    TriggerMarket,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+/// An open order
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct Order {
+    pub order_type: OrderType,
+    pub market_index: u16,
+    pub market_type: MarketType,
+    /// signed base amount, negative for shorts
+    pub amount: Decimal,
+    pub filled: Decimal,
+    pub price: Decimal,
+    pub post_only: bool,
+    pub reduce_only: bool,
+    pub user_order_id: u8,
+    pub order_id: u32,
+    pub immediate_or_cancel: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub oracle_price_offset: Option<Decimal>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct OrdersResponse {
+    pub orders: Vec<Order>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct PlaceOrder {
+    #[serde(flatten)]
+    pub market: Market,
+    /// signed base amount, negative for shorts
+    pub amount: Decimal,
+    pub price: Decimal,
+    /// 0 if unset
+    pub user_order_id: u8,
+    pub order_type: OrderType,
+    pub post_only: bool,
+    pub reduce_only: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub oracle_price_offset: Option<Decimal>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_ts: Option<i64>,
+    /// max. fill price deviation from the oracle price in bps, market and oracle orders only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_slippage_bps: Option<u32>,
+    /// place as a limit order floating this many bps from the oracle price, negative below
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peg_to_oracle: Option<i32>,
+}
+
+impl PlaceOrder {
+    /// A limit order of signed `amount` at `price`
+    pub fn limit(market: Market, amount: Decimal, price: Decimal) -> Self {
+        Self {
+            market,
+            amount,
+            price,
+            user_order_id: 0,
+            order_type: OrderType::Limit,
+            post_only: false,
+            reduce_only: false,
+            oracle_price_offset: None,
+            max_ts: None,
+            max_slippage_bps: None,
+            peg_to_oracle: None,
+        }
+    }
+
+    /// A market order of signed `amount`
+    pub fn market(market: Market, amount: Decimal) -> Self {
+        Self {
+            order_type: OrderType::Market,
+            ..Self::limit(market, amount, Decimal::ZERO)
+        }
+    }
+
+    pub fn user_order_id(mut self, id: u8) -> Self {
+        self.user_order_id = id;
+        self
+    }
+
+    pub fn post_only(mut self) -> Self {
+        self.post_only = true;
+        self
+    }
+
+    pub fn reduce_only(mut self) -> Self {
+        self.reduce_only = true;
+        self
+    }
+
+    /// Never fill beyond `bps` from the oracle price, market and oracle orders only
+    pub fn max_slippage_bps(mut self, bps: u32) -> Self {
+        self.max_slippage_bps = Some(bps);
+        self
+    }
+
+    /// Float the order `bps` from the oracle price, limit orders only
+    pub fn peg_to_oracle(mut self, bps: i32) -> Self {
+        self.peg_to_oracle = Some(bps);
+        self
+    }
+}
+
+/// Changes to an open order identified by `order_id` or `user_order_id`, unset fields are unchanged
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct ModifyOrder {
+    #[serde(flatten)]
+    pub market: Market,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub order_id: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_order_id: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amount: Option<Decimal>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub price: Option<Decimal>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reduce_only: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub oracle_price_offset: Option<Decimal>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_ts: Option<i64>,
+}
+
+impl ModifyOrder {
+    /// Modify the order `order_id` of `market`
+    pub fn new(market: Market, order_id: u32) -> Self {
+        Self {
+            market,
+            order_id: Some(order_id),
+            user_order_id: None,
+            amount: None,
+            price: None,
+            reduce_only: None,
+            oracle_price_offset: None,
+            max_ts: None,
+        }
+    }
+}
+
+/// Orders to cancel, all open orders if empty
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct CancelOrders {
+    /// cancel all orders of the market
+    #[serde(flatten)]
+    pub market: Option<Market>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ids: Option<Vec<u32>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_ids: Option<Vec<u8>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct TxResponse {
+    /// tx signature
+    pub tx: String,
+    /// changes made to the requested orders before sending
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub adjustments: Vec<OrderAdjustment>,
+    /// base64 serialized tx message, built but not signed in emulation mode
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unsigned_message: Option<String>,
+    /// effective prices of oracle pegged orders at placement
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pegged: Vec<PeggedOrder>,
+    /// post-only orders repriced to not cross resting orders
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub repriced: Vec<RepricedOrder>,
+    /// the send timed out, the tx is rebroadcast and may still land
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pending: bool,
+}
+
+impl TxResponse {
+    pub fn new(tx_signature: String) -> Self {
+        Self {
+            tx: tx_signature,
+            adjustments: Vec::default(),
+            unsigned_message: None,
+            pegged: Vec::default(),
+            repriced: Vec::default(),
+            pending: false,
+        }
+    }
+    /// Response for a tx that was built but not signed or sent
+    pub fn unsigned(message: String) -> Self {
+        Self {
+            tx: String::default(),
+            adjustments: Vec::default(),
+            unsigned_message: Some(message),
+            pegged: Vec::default(),
+            repriced: Vec::default(),
+            pending: false,
+        }
+    }
+    /// Response for a sent tx whose send timed out
+    pub fn pending(mut self) -> Self {
+        self.pending = true;
+        self
+    }
+    pub fn with_adjustments(mut self, adjustments: Vec<OrderAdjustment>) -> Self {
+        self.adjustments = adjustments;
+        self
+    }
+    pub fn with_pegged(mut self, pegged: Vec<PeggedOrder>) -> Self {
+        self.pegged = pegged;
+        self
+    }
+    pub fn with_repriced(mut self, repriced: Vec<RepricedOrder>) -> Self {
+        self.repriced = repriced;
+        self
+    }
+    pub fn signature(&self) -> &str {
+        &self.tx
+    }
+}
+
+/// An order changed by the gateway before sending
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct OrderAdjustment {
+    /// index of the order in the request
+    pub order_index: usize,
+    pub market_index: u16,
+    pub requested_amount: Decimal,
+    pub amount: Decimal,
+    pub reason: Cow<'static, str>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub enum TxState {
+    /// sent, not yet confirmed
+    Submitted,
+    Confirmed,
+    Finalized,
+    /// not confirmed within its TTL
+    Expired,
+    /// landed with an error
+    Failed,
+}
+
+impl TxState {
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, Self::Finalized | Self::Expired | Self::Failed)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct TxStatus {
+    /// tx signature
+    pub tx: String,
+    pub state: TxState,
+    /// slot the tx landed in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slot: Option<u64>,
+    /// program error code of failed txs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<u32>,
+    /// why the tx failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// submission time, unix secs
+    pub ts: u64,
+    /// time of the last state change, unix secs
+    pub updated_ts: u64,
+    /// id of the request that sent the tx
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+/// Aggregated size of resting orders at a price
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct L2Level {
+    pub price: Decimal,
+    pub size: Decimal,
+}
+
+/// An oracle pegged order, priced at placement
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct PeggedOrder {
+    /// index of the order in the request
+    pub order_index: usize,
+    pub oracle_price: Decimal,
+    pub oracle_price_offset: Decimal,
+    /// effective limit price, oracle price + offset
+    pub price: Decimal,
+}
+
+/// A post-only order repriced to rest behind the best opposite price
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct RepricedOrder {
+    /// index of the order in the request
+    pub order_index: usize,
+    pub requested_price: Decimal,
+    pub price: Decimal,
+    /// best resting price on the opposite side the order would have crossed
+    pub crossed_price: Decimal,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct SpotPosition {
+    pub amount: Decimal,
+    /// `deposit` or `borrow`
+    #[serde(rename = "type")]
+    pub balance_type: String,
+    pub market_index: u16,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct PerpPosition {
+    /// signed base amount, negative for shorts
+    pub amount: Decimal,
+    pub average_entry: Decimal,
+    pub market_index: u16,
+    /// position info requests only
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    pub extended: Option<PerpPositionExtended>,
+}
+
+impl PerpPosition {
+    pub fn set_extended_info(&mut self, ext: PerpPositionExtended) {
+        self.extended = Some(ext);
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct PerpPositionExtended {
+    pub liquidation_price: Decimal,
+    pub unrealized_pnl: Decimal,
+    pub unsettled_pnl: Decimal,
+    pub oracle_price: Decimal,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct PositionsResponse {
+    pub spot: Vec<SpotPosition>,
+    pub perp: Vec<PerpPosition>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sub_account_name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct MarginInfo {
+    pub initial: Decimal,
+    pub maintenance: Decimal,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Collateral {
+    pub total: Decimal,
+    pub free: Decimal,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Leverage {
+    pub leverage: Decimal,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct OraclePrice {
+    #[serde(flatten)]
+    pub market: Market,
+    pub price: Decimal,
+    pub slot: u64,
+}
+
+/// Orders, positions, and margin of a sub-account as of one slot
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct Snapshot {
+    pub slot: u64,
+    pub orders: Vec<Order>,
+    pub spot: Vec<SpotPosition>,
+    pub perp: Vec<PerpPosition>,
+    pub margin: MarginInfo,
+    pub oracles: Vec<OraclePrice>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum Channel {
+    Fills,
+    Orders,
+    Funding,
+    Account,
+    Markets,
+    #[serde(rename = "marketData")]
+    MarketData,
+    /// fills of all users in a market
+    Trades,
+    Oracle,
+    /// gateway-level conditions
+    Status,
+}
+
+/// Order state as reported by order events
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct EventOrder {
+    /// slot the order was placed in
+    #[serde(default)]
+    pub slot: u64,
+    pub order_id: u32,
+    pub user_order_id: u8,
+    pub market_index: u16,
+    pub market_type: MarketType,
+    /// camelCase e.g. `triggerLimit`
+    pub order_type: String,
+    pub direction: Side,
+    pub price: Decimal,
+    pub amount: Decimal,
+    pub filled: Decimal,
+    #[serde(default)]
+    pub trigger_price: Decimal,
+    #[serde(default)]
+    pub oracle_price_offset: Decimal,
+    #[serde(default)]
+    pub auction_start_price: Decimal,
+    #[serde(default)]
+    pub auction_end_price: Decimal,
+    #[serde(default)]
+    pub auction_duration: u8,
+    pub reduce_only: bool,
+    pub post_only: bool,
+    #[serde(default)]
+    pub immediate_or_cancel: bool,
+    pub max_ts: i64,
+}
+
+/// Payload of an account event, keyed by event type
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub enum AccountEvent {
+    #[serde(rename_all = "camelCase")]
+    Fill {
+        side: Side,
+        fee: Decimal,
+        amount: Decimal,
+        price: Decimal,
+        oracle_price: Decimal,
+        order_id: u32,
+        market_index: u16,
+        market_type: MarketType,
+        ts: u64,
+        #[serde(default)]
+        tx_idx: usize,
+        signature: String,
+        #[serde(default)]
+        maker: Option<String>,
+        #[serde(default)]
+        maker_order_id: Option<u32>,
+        #[serde(default)]
+        maker_fee: Option<Decimal>,
+        #[serde(default)]
+        taker: Option<String>,
+        #[serde(default)]
+        taker_order_id: Option<u32>,
+        #[serde(default)]
+        taker_fee: Option<Decimal>,
+    },
+    #[serde(rename_all = "camelCase")]
+    OrderCreate {
+        order: EventOrder,
+        ts: u64,
+        signature: String,
+        #[serde(default)]
+        tx_idx: usize,
+    },
+    #[serde(rename_all = "camelCase")]
+    OrderCancel {
+        order_id: u32,
+        ts: u64,
+        signature: String,
+        #[serde(default)]
+        tx_idx: usize,
+    },
+    #[serde(rename_all = "camelCase")]
+    OrderExpire {
+        order_id: u32,
+        fee: Decimal,
+        ts: u64,
+        signature: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    OrderCancelMissing {
+        user_order_id: u8,
+        order_id: u32,
+        signature: String,
+    },
+    /// an open order reaches its `max_ts` soon
+    #[serde(rename_all = "camelCase")]
+    OrderExpiring { order_id: u32, max_ts: i64, ts: u64 },
+    /// an open order reached its `max_ts` and can no longer be filled
+    #[serde(rename_all = "camelCase")]
+    OrderExpired { order_id: u32, max_ts: i64, ts: u64 },
+    #[serde(rename_all = "camelCase")]
+    OrderBackfill { order: EventOrder, ts: u64 },
+    #[serde(rename_all = "camelCase")]
+    OrderClosed { order_id: u32, ts: u64 },
+    /// current state of an open order whose filled amount changed
+    #[serde(rename_all = "camelCase")]
+    OrderUpdate { order: EventOrder, ts: u64 },
+    /// spot token balance change outside of fills
+    #[serde(rename_all = "camelCase")]
+    BalanceUpdate {
+        market_index: u16,
+        /// `deposit`, `withdrawal`, or `interest`
+        reason: String,
+        /// signed token amount change
+        change: Decimal,
+        /// token balance after the change, negative for borrows
+        balance: Decimal,
+        /// total collateral of the sub-account
+        collateral: Decimal,
+        ts: u64,
+    },
+    /// changed sections of the user account, unchanged sections are None
+    #[serde(rename_all = "camelCase")]
+    AccountUpdate {
+        #[serde(default)]
+        spot: Option<Vec<SpotPosition>>,
+        #[serde(default)]
+        perp: Option<Vec<PerpPosition>>,
+        #[serde(default)]
+        margin: Option<MarginInfo>,
+        #[serde(default)]
+        collateral: Option<Collateral>,
+        #[serde(default)]
+        leverage: Option<Decimal>,
+        ts: u64,
+    },
+    /// L2 orderbook of a market, a full snapshot on each change
+    #[serde(rename_all = "camelCase")]
+    MarketData {
+        #[serde(flatten)]
+        market: Market,
+        oracle_price: Decimal,
+        /// best (highest) first
+        bids: Vec<L2Level>,
+        /// best (lowest) first
+        asks: Vec<L2Level>,
+        ts: u64,
+    },
+    /// oracle price of a market, on change
+    #[serde(rename_all = "camelCase")]
+    OraclePrice {
+        #[serde(flatten)]
+        market: Market,
+        price: Decimal,
+        confidence: Decimal,
+        ts: u64,
+    },
+    /// a fill of any user in a market
+    #[serde(rename_all = "camelCase")]
+    Trade {
+        #[serde(flatten)]
+        market: Market,
+        /// side of the taker
+        side: Side,
+        amount: Decimal,
+        price: Decimal,
+        oracle_price: Decimal,
+        #[serde(default)]
+        maker: Option<String>,
+        #[serde(default)]
+        taker: Option<String>,
+        ts: u64,
+        signature: String,
+        #[serde(default)]
+        tx_idx: usize,
+    },
+    #[serde(rename_all = "camelCase")]
+    MarketUpdate {
+        #[serde(flatten)]
+        market: Market,
+        status: String,
+        paused_operations: Vec<String>,
+        oracle: String,
+        oracle_source: String,
+        #[serde(default)]
+        last_funding_rate: Option<Decimal>,
+        #[serde(default)]
+        fee_adjustment: Option<i16>,
+        /// fields changed since the last update, empty on the first update
+        changed: Vec<String>,
+        ts: u64,
+    },
+    #[serde(rename_all = "camelCase")]
+    FundingPayment {
+        amount: Decimal,
+        market_index: u16,
+        ts: u64,
+        signature: String,
+        #[serde(default)]
+        tx_idx: usize,
+    },
+    /// a gateway-level condition raised or cleared
+    #[serde(rename_all = "camelCase")]
+    Status {
+        /// `killSwitch`, `rpcDegraded`, `subscriptionStalled`, `marketPaused`, `incompatibleMarket`,
+        /// `txFailureStreak`, or `eventStream`
+        condition: String,
+        /// the market or subscription affected, unset for gateway-wide conditions
+        #[serde(default)]
+        subject: Option<String>,
+        active: bool,
+        #[serde(default)]
+        detail: Option<String>,
+        ts: u64,
+    },
+}
+
+/// Event payload, `Other` holds events this client version does not know
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(untagged)]
+pub enum EventData {
+    Known(AccountEvent),
+    Other(serde_json::Value),
+}
+
+/// An event of a subscribed sub-account
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct Event {
+    pub channel: Channel,
+    /// `0` on the trades and status channels
+    #[serde(default)]
+    pub sub_account_id: u16,
+    #[serde(default)]
+    pub sub_account_name: Option<String>,
+    /// slot of the account data, account and markets channels only
+    #[serde(default)]
+    pub slot: Option<u64>,
+    /// position of the event in its subscription across channels, from 1 without gaps
+    #[serde(default)]
+    pub seq: u64,
+    pub data: EventData,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct TxEventsResponse {
+    pub events: Vec<EventData>,
+    /// false if the tx failed
+    pub success: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct JournalEntry {
+    pub seq: u64,
+    pub sub_account_id: u16,
+    pub channel: Channel,
+    pub data: EventData,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct PollEventsResponse {
+    pub events: Vec<JournalEntry>,
+    /// pass as `since` on the next poll
+    pub last_seq: u64,
+    /// pass as `epoch` on the next poll, changes when the gateway restarts
+    pub epoch: u64,
+}
+
+/// Error body of a failed request
+#[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorResponse {
+    pub code: u32,
+    pub reason: String,
+    #[serde(default)]
+    pub request_id: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn place_order_serializes() {
+        let order = PlaceOrder::limit(Market::perp(0), Decimal::new(-1, 1), Decimal::from(80))
+            .post_only()
+            .user_order_id(5);
+        assert_eq!(
+            serde_json::to_value(&order).unwrap(),
+            serde_json::json!({
+                "marketIndex": 0,
+                "marketType": "perp",
+                "amount": "-0.1",
+                "price": "80",
+                "userOrderId": 5,
+                "orderType": "limit",
+                "postOnly": true,
+                "reduceOnly": false,
+            })
+        );
+    }
+
+    #[test]
+    fn perp_position_round_trips() {
+        let mut position = PerpPosition {
+            amount: Decimal::new(-15, 1),
+            average_entry: Decimal::from(80),
+            market_index: 0,
+            extended: None,
+        };
+        let json = serde_json::to_value(&position).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({"amount": "-1.5", "averageEntry": "80", "marketIndex": 0})
+        );
+        assert_eq!(
+            serde_json::from_value::<PerpPosition>(json).unwrap(),
+            position
+        );
+
+        position.set_extended_info(PerpPositionExtended {
+            liquidation_price: Decimal::from(120),
+            unrealized_pnl: Decimal::from(-3),
+            unsettled_pnl: Decimal::ZERO,
+            oracle_price: Decimal::from(82),
+        });
+        let json = serde_json::to_value(&position).unwrap();
+        assert_eq!(json["liquidationPrice"], "120");
+        assert_eq!(
+            serde_json::from_value::<PerpPosition>(json).unwrap(),
+            position
+        );
+    }
+
+    #[test]
+    fn event_deserializes() {
+        let fill: Event = serde_json::from_str(
+            r#"{
+                "data": {
+                    "fill": {
+                        "side": "sell",
+                        "fee": "-0.100549",
+                        "amount": "0.0326",
+                        "price": "61687",
+                        "oraclePrice": "61335.477737",
+                        "orderId": 11198929,
+                        "marketIndex": 1,
+                        "marketType": "perp",
+                        "ts": 1709248100,
+                        "txIdx": 12,
+                        "signature": "5xZv",
+                        "maker": null,
+                        "makerOrderId": null,
+                        "makerFee": null,
+                        "taker": "Fii4",
+                        "takerOrderId": 40,
+                        "takerFee": "0.502750"
+                    }
+                },
+                "channel": "fills",
+                "subAccountId": 0
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(fill.channel, Channel::Fills);
+        assert!(matches!(
+            fill.data,
+            EventData::Known(AccountEvent::Fill {
+                side: Side::Sell,
+                order_id: 11198929,
+                ..
+            })
+        ));
+
+        let update: Event = serde_json::from_str(
+            r#"{"data": {"accountUpdate": {"leverage": "1.5", "ts": 1}}, "channel": "account", "subAccountId": 1, "slot": 42}"#,
+        )
+        .unwrap();
+        assert_eq!(update.slot, Some(42));
+        assert!(matches!(
+            update.data,
+            EventData::Known(AccountEvent::AccountUpdate { spot: None, .. })
+        ));
+
+        // unknown events are preserved
+        let unknown: Event = serde_json::from_str(
+            r#"{"data": {"newEvent": {"ts": 1}}, "channel": "orders", "subAccountId": 1}"#,
+        )
+        .unwrap();
+        assert!(matches!(unknown.data, EventData::Other(_)));
+    }
+}